@@ -0,0 +1,142 @@
+//! Generates the `OsCode` key code tables from the Linux input event codes header, so that new
+//! kernel key codes only need an update of `src/keys/input-event-codes.h`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+const HEADER: &str = "src/keys/input-event-codes.h";
+
+/// Largest key code, `KEY_MAX` in the header.
+const KEY_MAX: u16 = 0x2ff;
+
+/// Key codes from this one up are mapped 1:1 to the `KeyCode::K<code>` variants of keyberon.
+const FIRST_NUMBERED_KEYCODE: u16 = 252;
+
+/// Defines that mark the start of a range of buttons. The button defined after them with the same
+/// code is the name used by kanata.
+const RANGE_MARKERS: &[&str] = &[
+    "BTN_MISC",
+    "BTN_MOUSE",
+    "BTN_JOYSTICK",
+    "BTN_GAMEPAD",
+    "BTN_DIGI",
+    "BTN_WHEEL",
+    "BTN_TRIGGER_HAPPY",
+];
+
+/// Codes that are not in the header but have a name in kanata.
+const KANATA_CODES: &[(u16, &str)] = &[
+    (744, "BTN_MAX"),
+    // Rotary input that kanata turns into key taps. These are not key codes of the kernel.
+    (745, "DIAL_CW"),
+    (746, "DIAL_CCW"),
+    (747, "WHEEL_UP"),
+    (748, "WHEEL_DOWN"),
+    (749, "WHEEL_LEFT"),
+    (750, "WHEEL_RIGHT"),
+];
+
+fn main() {
+    println!("cargo:rerun-if-changed={HEADER}");
+    println!("cargo:rerun-if-changed=build.rs");
+    let header = std::fs::read_to_string(HEADER).expect("read input event codes header");
+    let names = key_names(&header);
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "/// Linux key codes, generated by build.rs from `{HEADER}`. Codes that the header does\n\
+         /// not name are `KEY_<code>`.\n\
+         #[repr(u16)]\n\
+         #[allow(non_camel_case_types)]\n\
+         #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]\n\
+         pub enum OsCode {{"
+    )
+    .unwrap();
+    for (code, name) in &names {
+        writeln!(out, "    {name} = {code},").unwrap();
+    }
+    writeln!(out, "}}\n").unwrap();
+
+    writeln!(
+        out,
+        "impl OsCode {{\n    \
+         /// Every key code, indexed by its value.\n    \
+         pub const ALL: [OsCode; {}] = [",
+        names.len()
+    )
+    .unwrap();
+    for name in names.values() {
+        writeln!(out, "        OsCode::{name},").unwrap();
+    }
+    writeln!(out, "    ];\n}}").unwrap();
+    write_file("oscode.rs", &out);
+
+    let numbered = || {
+        names
+            .iter()
+            .filter(|(code, _)| (FIRST_NUMBERED_KEYCODE..KEY_MAX).contains(*code))
+    };
+    let mut out = String::new();
+    writeln!(
+        out,
+        "fn haphazard_osc_to_kc_mappings(osc: OsCode) -> KeyCode {{\n    match osc {{"
+    )
+    .unwrap();
+    for (code, name) in numbered() {
+        writeln!(out, "        OsCode::{name} => KeyCode::K{code},").unwrap();
+    }
+    writeln!(out, "        _ => KeyCode::No,\n    }}\n}}\n").unwrap();
+    writeln!(
+        out,
+        "fn haphazard_kc_to_osc_mappings(kc: KeyCode) -> OsCode {{\n    match kc {{"
+    )
+    .unwrap();
+    for (code, name) in numbered() {
+        writeln!(out, "        KeyCode::K{code} => OsCode::{name},").unwrap();
+    }
+    writeln!(out, "        _ => OsCode::KEY_UNKNOWN,\n    }}\n}}").unwrap();
+    write_file("oscode_mappings.rs", &out);
+}
+
+/// Returns the name of every code from 0 to `KEY_MAX`. The first numeric define of a code is its
+/// name, since later defines of the same code are aliases kept for compatibility.
+fn key_names(header: &str) -> BTreeMap<u16, String> {
+    let mut names = BTreeMap::new();
+    for line in header.lines() {
+        let mut words = line.split_whitespace();
+        if words.next() != Some("#define") {
+            continue;
+        }
+        let (Some(name), Some(value)) = (words.next(), words.next()) else {
+            continue;
+        };
+        if !(name.starts_with("KEY_") || name.starts_with("BTN_")) || RANGE_MARKERS.contains(&name)
+        {
+            continue;
+        }
+        let code = match value.strip_prefix("0x") {
+            Some(hex) => u16::from_str_radix(hex, 16),
+            None => value.parse(),
+        };
+        if let Ok(code) = code {
+            names.entry(code).or_insert_with(|| name.to_string());
+        }
+    }
+    for (code, name) in KANATA_CODES {
+        assert!(
+            names.insert(*code, name.to_string()).is_none(),
+            "{name} has the code of a kernel key"
+        );
+    }
+    for code in 0..=KEY_MAX {
+        names.entry(code).or_insert_with(|| format!("KEY_{code}"));
+    }
+    names
+}
+
+fn write_file(name: &str, contents: &str) {
+    let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+    std::fs::write(Path::new(&out_dir).join(name), contents).expect("write generated file");
+}
@@ -0,0 +1,200 @@
+//! Benchmarks of the paths of the layout that kanata runs for every key event and tick, to catch
+//! performance regressions. Run them in the keyberon directory with `cargo bench`; criterion
+//! compares each run with the previous one.
+//!
+//! The `before` variants of the key state benchmarks repeat how the layout handled its states
+//! before they were updated in place, so that the gain stays visible next to the current code.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use kanata_keyberon::action::{k, l, Action::*, HoldTapAction, HoldTapConfig, SequenceEvent};
+use kanata_keyberon::key_code::KeyCode::*;
+use kanata_keyberon::layout::{Event, Layers, Layout, State};
+
+const KEY: u16 = 0;
+const LAYER_KEY: u16 = 1;
+const HOLD_TAP_KEY: u16 = 2;
+const MACRO_KEY: u16 = 3;
+const CONDITION_KEY: u16 = 4;
+const HOLD_TAP_TIMEOUT: u16 = 200;
+/// The keys held in the key state benchmarks, e.g. modifiers and keys of a chord.
+const HELD_KEYS: u16 = 8;
+const FAKE_KEYS: [kanata_keyberon::key_code::KeyCode; 4] = [LShift, LCtrl, LAlt, LGui];
+
+static MACRO: &[SequenceEvent<core::convert::Infallible>] = &[
+    SequenceEvent::Tap(H),
+    SequenceEvent::Tap(E),
+    SequenceEvent::Tap(L),
+    SequenceEvent::Tap(L),
+    SequenceEvent::Tap(O),
+];
+
+static LAYERS: Layers<5, 1, 3> = [
+    [[
+        k(A),
+        l(1),
+        HoldTap(&HoldTapAction {
+            timeout: HOLD_TAP_TIMEOUT,
+            hold: k(LCtrl),
+            timeout_action: k(LCtrl),
+            tap: k(Escape),
+            config: HoldTapConfig::Default,
+            tap_hold_interval: 0,
+        }),
+        Sequence { events: &MACRO },
+        k(LShift),
+    ]],
+    [[k(B), Trans, Trans, Trans, Trans]],
+    // Merged over the active layer while the condition key is held.
+    [[k(C), Trans, Trans, Trans, Trans]],
+];
+
+type BenchLayout = Layout<'static, 5, 1, 3>;
+
+fn new_layout() -> BenchLayout {
+    let mut layout = Layout::new(&LAYERS);
+    layout.layer_conditions = &[(2, &[CONDITION_KEY])];
+    layout
+}
+
+/// Tick until no key is pressed and no macro is running.
+fn settle(layout: &mut BenchLayout) {
+    loop {
+        black_box(layout.tick());
+        if layout.keycodes().next().is_none() && layout.active_sequences.is_empty() {
+            break;
+        }
+    }
+}
+
+fn tap(layout: &mut BenchLayout, j: u16) {
+    layout.event(Event::Press(0, j));
+    black_box(layout.tick());
+    black_box(layout.keycodes().count());
+    layout.event(Event::Release(0, j));
+    settle(layout);
+}
+
+fn bench_layout(c: &mut Criterion) {
+    c.bench_function("idle tick", |b| {
+        let mut layout = new_layout();
+        b.iter(|| black_box(layout.tick()))
+    });
+    c.bench_function("key tap", |b| {
+        let mut layout = new_layout();
+        b.iter(|| tap(&mut layout, KEY))
+    });
+    c.bench_function("merged lookup", |b| {
+        let mut layout = new_layout();
+        layout.event(Event::Press(0, CONDITION_KEY));
+        black_box(layout.tick());
+        b.iter(|| {
+            layout.event(Event::Press(0, KEY));
+            black_box(layout.tick());
+            black_box(layout.keycodes().count());
+            layout.event(Event::Release(0, KEY));
+            black_box(layout.tick());
+        })
+    });
+    c.bench_function("layer toggle", |b| {
+        let mut layout = new_layout();
+        b.iter(|| {
+            layout.event(Event::Press(0, LAYER_KEY));
+            black_box(layout.tick());
+            black_box(layout.current_layer());
+            layout.event(Event::Release(0, LAYER_KEY));
+            black_box(layout.tick());
+        })
+    });
+    c.bench_function("tap-hold tap", |b| {
+        let mut layout = new_layout();
+        b.iter(|| tap(&mut layout, HOLD_TAP_KEY))
+    });
+    c.bench_function("tap-hold interrupted", |b| {
+        let mut layout = new_layout();
+        b.iter(|| {
+            layout.event(Event::Press(0, HOLD_TAP_KEY));
+            black_box(layout.tick());
+            tap(&mut layout, KEY);
+            layout.event(Event::Release(0, HOLD_TAP_KEY));
+            settle(&mut layout);
+        })
+    });
+    c.bench_function("tap-hold timeout", |b| {
+        let mut layout = new_layout();
+        b.iter(|| {
+            layout.event(Event::Press(0, HOLD_TAP_KEY));
+            for _ in 0..=HOLD_TAP_TIMEOUT {
+                black_box(layout.tick());
+            }
+            layout.event(Event::Release(0, HOLD_TAP_KEY));
+            settle(&mut layout);
+        })
+    });
+    c.bench_function("macro playback", |b| {
+        let mut layout = new_layout();
+        b.iter(|| tap(&mut layout, MACRO_KEY))
+    });
+}
+
+fn hold_keys(layout: &mut BenchLayout) {
+    for j in 0..HELD_KEYS {
+        let _ = layout.states.push(State::NormalKey {
+            keycode: A,
+            coord: (0, 100 + j),
+        });
+    }
+}
+
+/// The fake keys that a macro holds until it completes or is cancelled.
+fn hold_fake_keys(layout: &mut BenchLayout) {
+    for keycode in FAKE_KEYS {
+        let _ = layout.states.push(State::FakeKey { keycode });
+    }
+}
+
+fn bench_states(c: &mut Criterion) {
+    c.bench_function("tick with held keys", |b| {
+        let mut layout = new_layout();
+        hold_keys(&mut layout);
+        b.iter(|| black_box(layout.tick()))
+    });
+    c.bench_function("tick with held keys, before", |b| {
+        let mut layout = new_layout();
+        hold_keys(&mut layout);
+        b.iter(|| {
+            // Every tick collected the states into a new vector.
+            layout.states = layout.states.iter().copied().collect();
+            black_box(layout.tick())
+        })
+    });
+    c.bench_function("clear fake keys", |b| {
+        let mut layout = new_layout();
+        hold_keys(&mut layout);
+        b.iter(|| {
+            hold_fake_keys(&mut layout);
+            layout
+                .states
+                .retain(|s| !matches!(s, State::FakeKey { .. }));
+            black_box(layout.states.len())
+        })
+    });
+    c.bench_function("clear fake keys, before", |b| {
+        let mut layout = new_layout();
+        hold_keys(&mut layout);
+        b.iter(|| {
+            hold_fake_keys(&mut layout);
+            // The states were copied, then filtered once for every fake key.
+            for state in layout.states.clone().iter() {
+                if let State::FakeKey { keycode } = *state {
+                    layout
+                        .states
+                        .retain(|s| !matches!(s, State::FakeKey { keycode: k } if *k == keycode));
+                }
+            }
+            black_box(layout.states.len())
+        })
+    });
+}
+
+criterion_group!(benches, bench_layout, bench_states);
+criterion_main!(benches);
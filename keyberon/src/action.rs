@@ -229,6 +229,9 @@ where
     /// sequence as keys are pressed. Lazy will activate only a single action, decided by the
     /// number of taps in the sequence.
     pub config: TapDanceConfig,
+    /// Determine what a lazy tap dance does when a different key is pressed before it
+    /// activates. Eager tap dances activate on every tap so they are not affected.
+    pub interrupt: TapDanceInterrupt,
 }
 
 /// Determines the behaviour for a `TapDance`.
@@ -238,6 +241,19 @@ pub enum TapDanceConfig {
     Eager,
 }
 
+/// Determines what a lazy `TapDance` does when a different key is pressed before it activates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TapDanceInterrupt {
+    /// Activate the action for the number of taps so far, then handle the other key.
+    #[default]
+    Resolve,
+    /// Activate no action, then handle the other key.
+    Cancel,
+    /// Keep counting taps until the timeout expires or the last action is reached, then handle
+    /// the other keys.
+    Buffer,
+}
+
 /// A group of chords (actions mapped to a combination of multiple physical keys pressed together).
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct ChordsGroup<'a, T = core::convert::Infallible>
@@ -251,6 +267,9 @@ where
     /// Timeout after which a chord will expire and either trigger its action or be discarded if there is no corresponding action.
     /// A chord may trigger its action even before this timeout expires, if a chord key is released, a non-chord key is pressed or the pressed chord is already uniquely identifyable.
     pub timeout: u16,
+    /// If true, the timeout is ignored and a chord is only resolved when one of its keys is released or a non-chord key is pressed, even if it is already uniquely identifiable.
+    /// This is useful for Perkins-style (braille) input where all keys are pressed together and the chord is read on release.
+    pub release_only: bool,
 }
 
 impl<'a, T> ChordsGroup<'a, T> {
@@ -384,6 +403,9 @@ where
     /// Fork action that can activate one of two potential actions depending on what keys are
     /// currently active.
     Fork(&'a ForkConfig<'a, T>),
+    /// Do nothing on press; activate the action when the key is released. The action is pressed
+    /// and then immediately released, i.e. tapped.
+    OnRelease(&'a Action<'a, T>),
 }
 
 impl<'a, T> Action<'a, T> {
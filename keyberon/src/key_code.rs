@@ -809,6 +809,28 @@ pub enum KeyCode {
     K742,
     K743,
     K744,
+    K745,
+    K746,
+    K747,
+    K748,
+    K749,
+    K750,
+    K751,
+    K752,
+    K753,
+    K754,
+    K755,
+    K756,
+    K757,
+    K758,
+    K759,
+    K760,
+    K761,
+    K762,
+    K763,
+    K764,
+    K765,
+    K766,
 }
 
 impl KeyCode {
@@ -68,6 +68,33 @@ where
     pub last_press_tracker: LastPressTracker,
     pub active_sequences: ArrayDeque<[SequenceState<'a, T>; 4], arraydeque::behavior::Wrapping>,
     pub action_queue: ActionQueue<'a, T>,
+    /// The coordinate and outcome of the most recently resolved hold-tap action. This is set by
+    /// `tick` and never cleared by the layout itself; users that want to react to resolutions
+    /// should `take` it after every tick.
+    pub hold_tap_resolution: Option<((u8, u16), HoldTapResolution)>,
+    /// When set, hold-tap and tap-dance keys activate their tap action, or first action,
+    /// immediately on press and one-shot keys act like their action held down. This removes the
+    /// delay and misfires of these actions, e.g. in games.
+    pub game_mode: bool,
+    /// When set, active sequences are not advanced by `tick`. Users can set this to limit the rate
+    /// of the key events output by sequences.
+    pub sequences_paused: bool,
+    /// Layers that only apply while one of the given keys is held. The keys are the `j` of
+    /// coordinates `(0, j)`. While one of them is held, the keys of the layer that are not
+    /// `Trans` take precedence over the active layer. Otherwise keys on the layer act like
+    /// `Trans` if it is active. Conditions of the default layer only add the precedence.
+    pub layer_conditions: &'a [(usize, &'a [u16])],
+    /// The held keys that appear in `layer_conditions`.
+    condition_keys_held: Vec<u16, 16>,
+}
+
+/// The outcome of a resolved hold-tap action.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HoldTapResolution {
+    /// The tap action was activated.
+    Tap,
+    /// The hold action, or the timeout action, was activated.
+    Hold,
 }
 
 /// An event on the key matrix.
@@ -154,7 +181,7 @@ impl<'a, T> CustomEvent<'a, T> {
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, PartialEq)]
 pub enum State<'a, T: 'a> {
     NormalKey {
         keycode: KeyCode,
@@ -177,6 +204,10 @@ pub enum State<'a, T: 'a> {
     },
     SeqCustomPending(&'a T),
     SeqCustomActive(&'a T),
+    OnRelease {
+        action: &'a Action<'a, T>,
+        coord: (u8, u16),
+    },
     Tombstone,
 }
 impl<'a, T> Copy for State<'a, T> {}
@@ -193,15 +224,13 @@ impl<'a, T: 'a> State<'a, T> {
             _ => None,
         }
     }
-    fn tick(&self) -> Option<Self> {
-        Some(*self)
-    }
     /// Returns None if the key has been released and Some otherwise.
     pub fn release(&self, c: (u8, u16), custom: &mut CustomEvent<'a, T>) -> Option<Self> {
         match *self {
             NormalKey { coord, .. }
             | LayerModifier { coord, .. }
             | RepeatingSequence { coord, .. }
+            | OnRelease { coord, .. }
                 if coord == c =>
             {
                 None
@@ -251,6 +280,7 @@ struct TapDanceState<'a, T: 'a> {
     actions: &'a [&'a Action<'a, T>],
     timeout: u16,
     num_taps: u16,
+    interrupt: TapDanceInterrupt,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -298,6 +328,8 @@ pub struct WaitingState<'a, T: 'a + std::fmt::Debug> {
     tap: &'a Action<'a, T>,
     timeout_action: &'a Action<'a, T>,
     config: WaitingConfig<'a, T>,
+    /// Whether the waiting action is the action of a one-shot, which the resolved action is too.
+    is_oneshot: bool,
 }
 
 /// Actions that can be triggered for a key configured for HoldTap.
@@ -324,8 +356,12 @@ impl<'a, T: std::fmt::Debug> WaitingState<'a, T> {
         let (ret, cfg_change) = match self.config {
             WaitingConfig::HoldTap(htc) => (self.handle_hold_tap(htc, queued), None),
             WaitingConfig::TapDance(ref tds) => {
-                let (ret, num_taps) =
-                    self.handle_tap_dance(tds.num_taps, tds.actions.len(), queued);
+                let (ret, num_taps) = self.handle_tap_dance(
+                    tds.num_taps,
+                    tds.actions.len(),
+                    tds.interrupt,
+                    queued,
+                );
                 // Due to ownership issues, handle_tap_dance can't contain all of the necessary
                 // logic.
                 if ret.is_some() {
@@ -376,7 +412,7 @@ impl<'a, T: std::fmt::Debug> WaitingState<'a, T> {
                 }
             }
             HoldTapConfig::Custom(func) => {
-                if let waiting_action @ Some(_) = (func)(QueuedIter(queued.iter())) {
+                if let waiting_action @ Some(_) = (func)(QueuedIter(queued.iter(), self.coord)) {
                     return waiting_action;
                 }
             }
@@ -401,6 +437,7 @@ impl<'a, T: std::fmt::Debug> WaitingState<'a, T> {
         &self,
         num_taps: u16,
         max_taps: usize,
+        interrupt: TapDanceInterrupt,
         queued: &mut Queue,
     ) -> (Option<WaitingAction>, u16) {
         // Evict events with the same coordinates except for the final release. E.g. if 3 taps have
@@ -427,11 +464,12 @@ impl<'a, T: std::fmt::Debug> WaitingState<'a, T> {
             return (Some(WaitingAction::Tap), num_taps);
         }
         // Get the number of sequential taps for this tap-dance key. If a different key was
-        // pressed, activate a tap-dance action.
+        // pressed, activate a tap-dance action or none, unless the other keys are buffered.
         match queued.iter().try_fold(1, |same_tap_count, s| {
             if self.is_corresponding_press(&s.event) {
                 Ok(same_tap_count + 1)
-            } else if matches!(s.event, Event::Press(..)) {
+            } else if matches!(s.event, Event::Press(..)) && interrupt != TapDanceInterrupt::Buffer
+            {
                 Err((same_tap_count, ()))
             } else {
                 Ok(same_tap_count)
@@ -440,7 +478,10 @@ impl<'a, T: std::fmt::Debug> WaitingState<'a, T> {
             Ok(num_taps) => (None, num_taps),
             Err((num_taps, _)) => {
                 evict_same_coord_events(num_taps, queued);
-                (Some(WaitingAction::Tap), num_taps)
+                match interrupt {
+                    TapDanceInterrupt::Cancel => (Some(WaitingAction::NoOp), num_taps),
+                    _ => (Some(WaitingAction::Tap), num_taps),
+                }
             }
         }
     }
@@ -477,7 +518,7 @@ impl<'a, T: std::fmt::Debug> WaitingState<'a, T> {
                 }
             })
             .and_then(|active| {
-                if self.timeout.saturating_sub(self.delay) == 0 {
+                if !config.release_only && self.timeout.saturating_sub(self.delay) == 0 {
                     Err(active) // timeout expired, abort
                 } else {
                     Ok(active)
@@ -485,6 +526,7 @@ impl<'a, T: std::fmt::Debug> WaitingState<'a, T> {
             });
 
         let res = match active {
+            Ok(_) if config.release_only => return None,
             Ok(active) => {
                 // Chording mode still active, only trigger action if it's unambiguous
                 if let Some(action) = config.get_chord_if_unambiguous(active) {
@@ -655,7 +697,7 @@ pub struct OneShotState {
     pub other_pressed_keys: ArrayDeque<OneShotKeys, arraydeque::behavior::Wrapping>,
     /// Timeout (ms) after which all one shot keys expire
     pub timeout: u16,
-    /// Contains the end config of the most recently pressed one shot key
+    /// Contains the end config of the first one shot key of the active stack
     pub end_config: OneShotEndConfig,
     /// Marks if release of the one shot keys should be done on the next tick
     pub release_on_next_tick: bool,
@@ -740,7 +782,14 @@ impl OneShotState {
 ///
 /// Events can be retrieved by iterating over this struct and calling [Queued::event].
 #[derive(Clone)]
-pub struct QueuedIter<'a>(arraydeque::Iter<'a, Queued>);
+pub struct QueuedIter<'a>(arraydeque::Iter<'a, Queued>, (u8, u16));
+
+impl<'a> QueuedIter<'a> {
+    /// Returns the coordinates of the key with the HoldTap action that is being resolved.
+    pub fn hold_tap_coord(&self) -> (u8, u16) {
+        self.1
+    }
+}
 
 impl<'a> Iterator for QueuedIter<'a> {
     type Item = &'a Queued;
@@ -809,6 +858,11 @@ impl<'a, const C: usize, const R: usize, const L: usize, T: 'a + Copy + std::fmt
             last_press_tracker: Default::default(),
             active_sequences: ArrayDeque::new(),
             action_queue: ArrayDeque::new(),
+            hold_tap_resolution: None,
+            game_mode: false,
+            sequences_paused: false,
+            layer_conditions: &[],
+            condition_keys_held: Vec::new(),
         }
     }
     /// Iterates on the key codes of the current state.
@@ -819,15 +873,19 @@ impl<'a, const C: usize, const R: usize, const L: usize, T: 'a + Copy + std::fmt
         if let Some(w) = &self.waiting {
             let hold = w.hold;
             let coord = w.coord;
+            let is_oneshot = w.is_oneshot;
             let delay = match w.config {
                 WaitingConfig::HoldTap(..) | WaitingConfig::Chord(_) => w.delay + w.ticks,
                 WaitingConfig::TapDance(_) => 0,
             };
+            if let WaitingConfig::HoldTap(..) = w.config {
+                self.hold_tap_resolution = Some((coord, HoldTapResolution::Hold));
+            }
             self.waiting = None;
             if coord == self.last_press_tracker.coord {
                 self.last_press_tracker.tap_hold_timeout = 0;
             }
-            self.do_action(hold, coord, delay, false)
+            self.do_action(hold, coord, delay, is_oneshot)
         } else {
             CustomEvent::NoEvent
         }
@@ -836,12 +894,27 @@ impl<'a, const C: usize, const R: usize, const L: usize, T: 'a + Copy + std::fmt
         if let Some(w) = &self.waiting {
             let tap = w.tap;
             let coord = w.coord;
+            let is_oneshot = w.is_oneshot;
             let delay = match w.config {
                 WaitingConfig::HoldTap(..) | WaitingConfig::Chord(_) => w.delay + w.ticks,
                 WaitingConfig::TapDance(_) => 0,
             };
+            // Time since the last tap of a tap dance, which a tap-hold step continues from.
+            let held = match w.config {
+                WaitingConfig::TapDance(tds) => tds.timeout.saturating_sub(w.timeout),
+                _ => 0,
+            };
+            if let WaitingConfig::HoldTap(..) = w.config {
+                self.hold_tap_resolution = Some((coord, HoldTapResolution::Tap));
+            }
             self.waiting = None;
-            self.do_action(tap, coord, delay, false)
+            let custom = self.do_action(tap, coord, delay, is_oneshot);
+            if let Some(w) = self.waiting.as_mut().filter(|w| {
+                w.coord == coord && matches!(w.config, WaitingConfig::HoldTap(..))
+            }) {
+                w.timeout = w.timeout.saturating_sub(held);
+            }
+            custom
         } else {
             CustomEvent::NoEvent
         }
@@ -850,15 +923,19 @@ impl<'a, const C: usize, const R: usize, const L: usize, T: 'a + Copy + std::fmt
         if let Some(w) = &self.waiting {
             let timeout_action = w.timeout_action;
             let coord = w.coord;
+            let is_oneshot = w.is_oneshot;
             let delay = match w.config {
                 WaitingConfig::HoldTap(..) | WaitingConfig::Chord(_) => w.delay + w.ticks,
                 WaitingConfig::TapDance(_) => 0,
             };
+            if let WaitingConfig::HoldTap(..) = w.config {
+                self.hold_tap_resolution = Some((coord, HoldTapResolution::Hold));
+            }
             self.waiting = None;
             if coord == self.last_press_tracker.coord {
                 self.last_press_tracker.tap_hold_timeout = 0;
             }
-            self.do_action(timeout_action, coord, delay, false)
+            self.do_action(timeout_action, coord, delay, is_oneshot)
         } else {
             CustomEvent::NoEvent
         }
@@ -879,7 +956,6 @@ impl<'a, const C: usize, const R: usize, const L: usize, T: 'a + Copy + std::fmt
             // everything. Otherwise an action may never be released.
             return self.do_action(action, coord, 0, false);
         }
-        self.states = self.states.iter().filter_map(State::tick).collect();
         self.queue.iter_mut().for_each(Queued::tick);
         self.last_press_tracker.tick();
         if let Some(ref mut tde) = self.tap_dance_eager {
@@ -888,7 +964,9 @@ impl<'a, const C: usize, const R: usize, const L: usize, T: 'a + Copy + std::fmt
                 self.tap_dance_eager = None;
             }
         }
-        self.process_sequences();
+        if !self.sequences_paused {
+            self.process_sequences();
+        }
 
         let mut custom = CustomEvent::NoEvent;
         if let Some(released_keys) = self.oneshot.tick() {
@@ -941,11 +1019,7 @@ impl<'a, const C: usize, const R: usize, const L: usize, T: 'a + Copy + std::fmt
                     // Process it (SequenceEvent)
                     match seq.cur_event {
                         Some(SequenceEvent::Complete) => {
-                            for fake_key in self.states.clone().iter() {
-                                if let FakeKey { keycode } = *fake_key {
-                                    self.states.retain(|s| s.seq_release(keycode).is_some());
-                                }
-                            }
+                            self.states.retain(|s| !matches!(s, FakeKey { .. }));
                             seq.remaining_events = &[];
                         }
                         Some(SequenceEvent::Press(keycode)) => {
@@ -1029,13 +1103,34 @@ impl<'a, const C: usize, const R: usize, const L: usize, T: 'a + Copy + std::fmt
         }
         current_custom
     }
+    /// Queue the actions of `OnRelease` states of the released coordinate, followed by another
+    /// release of the coordinate so that the queued actions are tapped rather than held.
+    fn queue_on_release_actions(&mut self, coord: (u8, u16)) {
+        let mut queued_any = false;
+        for s in self.states.iter() {
+            if let OnRelease { action, coord: c } = *s {
+                if c == coord {
+                    let _ = self.action_queue.push_back(Some((coord, action)));
+                    queued_any = true;
+                }
+            }
+        }
+        if queued_any {
+            let _ = self.queue.push_front(Queued {
+                event: Event::Release(coord.0, coord.1),
+                since: 0,
+            });
+        }
+    }
     fn dequeue(&mut self, queue: Queued) -> CustomEvent<'a, T> {
         use Event::*;
+        self.update_condition_keys(queue.event);
         match queue.event {
             Release(i, j) => {
                 let mut custom = CustomEvent::NoEvent;
                 let (do_release, overflow_key) = self.oneshot.handle_release((i, j));
                 if do_release {
+                    self.queue_on_release_actions((i, j));
                     self.states
                         .retain(|s| s.release((i, j), &mut custom).is_some());
                 }
@@ -1084,8 +1179,56 @@ impl<'a, const C: usize, const R: usize, const L: usize, T: 'a + Copy + std::fmt
             self.dequeue(queued);
         }
     }
+    fn update_condition_keys(&mut self, event: Event) {
+        let (i, j) = event.coord();
+        if i != 0
+            || !self
+                .layer_conditions
+                .iter()
+                .any(|(_, keys)| keys.contains(&j))
+        {
+            return;
+        }
+        match event {
+            Event::Press(..) => {
+                if !self.condition_keys_held.contains(&j) {
+                    let _ = self.condition_keys_held.push(j);
+                }
+            }
+            Event::Release(..) => self.condition_keys_held.retain(|k| *k != j),
+        }
+    }
+    /// Returns whether the condition of the layer is met, or it has none.
+    fn layer_condition_met(&self, layer: usize) -> bool {
+        self.layer_conditions
+            .iter()
+            .filter(|(l, _)| *l == layer)
+            .all(|(_, keys)| keys.iter().any(|k| self.condition_keys_held.contains(k)))
+    }
     fn press_as_action(&self, coord: (u8, u16), layer: usize) -> &'a Action<'a, T> {
         use crate::action::Action::*;
+        // Layers whose condition is met are merged over the active layer.
+        let overlay = self
+            .layer_conditions
+            .iter()
+            .filter(|(l, _)| self.layer_condition_met(*l))
+            .find_map(|(l, _)| {
+                match self
+                    .layers
+                    .get(*l)
+                    .and_then(|l| l.get(coord.0 as usize))
+                    .and_then(|l| l.get(coord.1 as usize))
+                {
+                    None | Some(Trans) => None,
+                    action => action,
+                }
+            });
+        if let Some(action) = overlay {
+            return action;
+        }
+        if layer != self.default_layer && !self.layer_condition_met(layer) {
+            return self.press_as_action(coord, self.default_layer);
+        }
         let action = self
             .layers
             .get(layer)
@@ -1115,6 +1258,16 @@ impl<'a, const C: usize, const R: usize, const L: usize, T: 'a + Copy + std::fmt
             self.last_press_tracker.tap_hold_timeout = 0;
         }
         use Action::*;
+        if self.game_mode {
+            match action {
+                HoldTap(HoldTapAction { tap, .. }) => {
+                    return self.do_action(tap, coord, delay, is_oneshot);
+                }
+                OneShot(oneshot) => return self.do_action(oneshot.action, coord, delay, is_oneshot),
+                TapDance(td) => return self.do_action(td.actions[0], coord, delay, is_oneshot),
+                _ => {}
+            }
+        }
         match action {
             NoOp | Trans => (),
             HoldTap(HoldTapAction {
@@ -1139,6 +1292,7 @@ impl<'a, const C: usize, const R: usize, const L: usize, T: 'a + Copy + std::fmt
                         tap,
                         timeout_action,
                         config: WaitingConfig::HoldTap(*config),
+                        is_oneshot,
                     };
                     self.waiting = Some(waiting);
                     self.last_press_tracker.tap_hold_timeout = *tap_hold_interval;
@@ -1153,10 +1307,14 @@ impl<'a, const C: usize, const R: usize, const L: usize, T: 'a + Copy + std::fmt
             &OneShot(oneshot) => {
                 self.last_press_tracker.coord = coord;
                 let custom = self.do_action(oneshot.action, coord, delay, true);
+                // Stacked one-shot keys all apply to the same next key press, so they end
+                // together according to the first one-shot key of the stack.
+                if self.oneshot.keys.is_empty() {
+                    self.oneshot.end_config = oneshot.end_config;
+                }
                 self.oneshot
                     .handle_press(OneShotHandlePressKey::OneShotKey(coord));
                 self.oneshot.timeout = oneshot.timeout;
-                self.oneshot.end_config = oneshot.end_config;
                 if let Some(overflow) = self.oneshot.keys.push_back((coord.0, coord.1)) {
                     self.event(Event::Release(overflow.0, overflow.1));
                 }
@@ -1178,7 +1336,9 @@ impl<'a, const C: usize, const R: usize, const L: usize, T: 'a + Copy + std::fmt
                                 actions: td.actions,
                                 timeout: td.timeout,
                                 num_taps: 1,
+                                interrupt: td.interrupt,
                             }),
+                            is_oneshot,
                         });
                     }
                     TapDanceConfig::Eager => {
@@ -1204,7 +1364,7 @@ impl<'a, const C: usize, const R: usize, const L: usize, T: 'a + Copy + std::fmt
                                 }
                             }
                         };
-                        self.do_action(td.actions[0], coord, delay, false);
+                        self.do_action(td.actions[0], coord, delay, is_oneshot);
                     }
                 }
             }
@@ -1219,6 +1379,7 @@ impl<'a, const C: usize, const R: usize, const L: usize, T: 'a + Copy + std::fmt
                     tap: &Action::NoOp,
                     timeout_action: &Action::NoOp,
                     config: WaitingConfig::Chord(chords),
+                    is_oneshot,
                 });
             }
             &KeyCode(keycode) => {
@@ -1270,11 +1431,7 @@ impl<'a, const C: usize, const R: usize, const L: usize, T: 'a + Copy + std::fmt
             CancelSequences => {
                 // Clear any and all running sequences then clean up any leftover FakeKey events
                 self.active_sequences.clear();
-                for fake_key in self.states.clone().iter() {
-                    if let FakeKey { keycode } = *fake_key {
-                        self.states.retain(|s| s.seq_release(keycode).is_some());
-                    }
-                }
+                self.states.retain(|s| !matches!(s, FakeKey { .. }));
             }
             &Layer(value) => {
                 self.last_press_tracker.coord = coord;
@@ -1300,10 +1457,14 @@ impl<'a, const C: usize, const R: usize, const L: usize, T: 'a + Copy + std::fmt
                     }
                     _ => false,
                 }) {
-                    false => self.do_action(&fcfg.left, coord, delay, false),
-                    true => self.do_action(&fcfg.right, coord, delay, false),
+                    false => self.do_action(&fcfg.left, coord, delay, is_oneshot),
+                    true => self.do_action(&fcfg.right, coord, delay, is_oneshot),
                 };
             }
+            OnRelease(action) => {
+                self.last_press_tracker.coord = coord;
+                let _ = self.states.push(State::OnRelease { action, coord });
+            }
         }
         CustomEvent::NoEvent
     }
@@ -1317,12 +1478,35 @@ impl<'a, const C: usize, const R: usize, const L: usize, T: 'a + Copy + std::fmt
             .unwrap_or(self.default_layer)
     }
 
+    /// Obtain the indexes of the active layer stack, starting with the default layer and ending
+    /// with the current layer.
+    pub fn active_layers(&self) -> impl Iterator<Item = usize> + '_ {
+        core::iter::once(self.default_layer).chain(self.states.iter().filter_map(State::get_layer))
+    }
+
     /// Sets the default layer for the layout
     pub fn set_default_layer(&mut self, value: usize) {
         if value < self.layers.len() {
             self.default_layer = value
         }
     }
+
+    /// The ticks until `tick` must be called again, or `None` if nothing changes until the next
+    /// event. Pending events, undecided actions and running sequences are advanced every tick.
+    pub fn ticks_until_due(&self) -> Option<u16> {
+        let every_tick = !self.queue.is_empty()
+            || !self.action_queue.is_empty()
+            || self.waiting.is_some()
+            || self.last_press_tracker.tap_hold_timeout != 0
+            || (self.oneshot.timeout != 0 && !self.oneshot.keys.is_empty())
+            || !self.active_sequences.is_empty()
+            || self.tap_dance_eager.is_some()
+            || self
+                .states
+                .iter()
+                .any(|s| matches!(s, State::SeqCustomPending(_) | State::SeqCustomActive(_)));
+        every_tick.then_some(1)
+    }
 }
 
 #[cfg(test)]
@@ -1343,6 +1527,38 @@ mod test {
         assert_eq!(expected, tested);
     }
 
+    #[test]
+    fn ticks_until_due_while_undecided() {
+        static LAYERS: Layers<1, 1, 1> = [[[HoldTap(&HoldTapAction {
+            timeout: 200,
+            hold: k(LCtrl),
+            timeout_action: k(LCtrl),
+            tap: k(Enter),
+            config: HoldTapConfig::Default,
+            tap_hold_interval: 0,
+        })]]];
+        let mut layout = Layout::new(&LAYERS);
+        assert_eq!(layout.ticks_until_due(), None);
+        layout.event(Press(0, 0));
+        assert_eq!(layout.ticks_until_due(), Some(1));
+        // Every tick until the hold is decided.
+        for _ in 0..1000 {
+            if layout.keycodes().next().is_some() {
+                break;
+            }
+            assert_eq!(layout.ticks_until_due(), Some(1));
+            layout.tick();
+        }
+        // A held key does not change until it is released.
+        assert_keys(&[LCtrl], layout.keycodes());
+        assert_eq!(layout.ticks_until_due(), None);
+        layout.event(Release(0, 0));
+        assert_eq!(layout.ticks_until_due(), Some(1));
+        layout.tick();
+        assert_keys(&[], layout.keycodes());
+        assert_eq!(layout.ticks_until_due(), None);
+    }
+
     #[test]
     fn basic_hold_tap() {
         static LAYERS: Layers<2, 1, 2> = [
@@ -1395,6 +1611,37 @@ mod test {
         assert_keys(&[], layout.keycodes());
     }
 
+    #[test]
+    fn hold_tap_resolution() {
+        static LAYERS: Layers<1, 1, 1> = [[[HoldTap(&HoldTapAction {
+            timeout: 10,
+            hold: k(LCtrl),
+            timeout_action: k(LCtrl),
+            tap: k(Enter),
+            config: HoldTapConfig::Default,
+            tap_hold_interval: 0,
+        })]]];
+        let mut layout = Layout::new(&LAYERS);
+        layout.event(Press(0, 0));
+        layout.event(Release(0, 0));
+        for _ in 0..3 {
+            layout.tick();
+        }
+        assert_eq!(
+            layout.hold_tap_resolution.take(),
+            Some(((0, 0), HoldTapResolution::Tap))
+        );
+        layout.event(Press(0, 0));
+        for _ in 0..15 {
+            layout.tick();
+        }
+        assert_keys(&[LCtrl], layout.keycodes());
+        assert_eq!(
+            layout.hold_tap_resolution.take(),
+            Some(((0, 0), HoldTapResolution::Hold))
+        );
+    }
+
     #[test]
     fn basic_hold_tap_timeout() {
         static LAYERS: Layers<2, 1, 2> = [
@@ -2547,6 +2794,52 @@ mod test {
         assert_keys(&[], layout.keycodes());
     }
 
+    #[test]
+    fn one_shot_stack_ends_like_first() {
+        static LAYERS: Layers<3, 1, 2> = [
+            [[
+                OneShot(&crate::action::OneShot {
+                    timeout: 100,
+                    action: &k(LShift),
+                    end_config: OneShotEndConfig::EndOnFirstRelease,
+                }),
+                OneShot(&crate::action::OneShot {
+                    timeout: 100,
+                    action: &Layer(1),
+                    end_config: OneShotEndConfig::EndOnFirstPress,
+                }),
+                NoOp,
+            ]],
+            [[NoOp, NoOp, k(Kb1)]],
+        ];
+        let mut layout = Layout::new(&LAYERS);
+
+        layout.event(Press(0, 0));
+        layout.event(Release(0, 0));
+        layout.event(Press(0, 1));
+        layout.event(Release(0, 1));
+        for _ in 0..4 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+        }
+        for _ in 0..10 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert_keys(&[LShift], layout.keycodes());
+            assert_eq!(layout.current_layer(), 1);
+        }
+        // Both one-shots apply to the symbol and end on its release, like the first one-shot.
+        layout.event(Press(0, 2));
+        for _ in 0..10 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert_keys(&[LShift, Kb1], layout.keycodes());
+            assert_eq!(layout.current_layer(), 1);
+        }
+        layout.event(Release(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+        assert_eq!(layout.current_layer(), 0);
+    }
+
     #[test]
     fn one_shot_tap_hold() {
         static LAYERS: Layers<3, 1, 2> = [
@@ -2607,6 +2900,141 @@ mod test {
         assert_keys(&[], layout.keycodes());
     }
 
+    #[test]
+    fn game_mode() {
+        static LAYERS: Layers<3, 1, 1> = [[[
+            OneShot(&crate::action::OneShot {
+                timeout: 200,
+                action: &k(LShift),
+                end_config: OneShotEndConfig::EndOnFirstPress,
+            }),
+            HoldTap(&HoldTapAction {
+                timeout: 100,
+                hold: k(LAlt),
+                timeout_action: k(LAlt),
+                tap: k(Space),
+                config: HoldTapConfig::Default,
+                tap_hold_interval: 0,
+            }),
+            TapDance(&crate::action::TapDance {
+                timeout: 100,
+                actions: &[&k(A), &k(B)],
+                config: TapDanceConfig::Lazy,
+                interrupt: TapDanceInterrupt::Resolve,
+            }),
+        ]]];
+        let mut layout = Layout::new(&LAYERS);
+        layout.game_mode = true;
+
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[Space], layout.keycodes());
+        for _ in 0..200 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert_keys(&[Space], layout.keycodes());
+        }
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LShift], layout.keycodes());
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+
+        layout.event(Press(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[A], layout.keycodes());
+        layout.event(Release(0, 2));
+        layout.event(Press(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[A], layout.keycodes());
+
+        layout.game_mode = false;
+        layout.event(Release(0, 2));
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+    }
+
+    #[test]
+    fn sequences_paused() {
+        static EVENTS: &[SequenceEvent<core::convert::Infallible>] =
+            &[SequenceEvent::Tap(A), SequenceEvent::Tap(B)];
+        static LAYERS: Layers<1, 1, 1> = [[[Sequence { events: &EVENTS }]]];
+        let mut layout = Layout::new(&LAYERS);
+        layout.event(Press(0, 0));
+        layout.event(Release(0, 0));
+        layout.sequences_paused = true;
+        for _ in 0..10 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert_keys(&[], layout.keycodes());
+        }
+
+        layout.sequences_paused = false;
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[A], layout.keycodes());
+        // The tapped key stays pressed while paused.
+        layout.sequences_paused = true;
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[A], layout.keycodes());
+        layout.sequences_paused = false;
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[B], layout.keycodes());
+    }
+
+    #[test]
+    fn layer_conditions() {
+        static LAYERS: Layers<3, 1, 2> = [
+            [[k(LShift), l(1), k(A)]],
+            [[k(LShift), Trans, k(B)]],
+        ];
+        let mut layout = Layout::new(&LAYERS);
+        layout.layer_conditions = &[(1, &[0])];
+
+        // The layer is active but shift is not held.
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        layout.event(Press(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(1, layout.current_layer());
+        assert_keys(&[A], layout.keycodes());
+        layout.event(Release(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+
+        layout.event(Press(0, 0));
+        layout.event(Press(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LShift, B], layout.keycodes());
+        layout.event(Release(0, 2));
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+
+        layout.event(Press(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[A], layout.keycodes());
+        layout.event(Release(0, 2));
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+
+        // The layer is merged over the active layer while shift is held.
+        assert_eq!(0, layout.current_layer());
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        layout.event(Press(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LShift, B], layout.keycodes());
+    }
+
     #[test]
     fn tap_dance() {
         static LAYERS: Layers<2, 2, 1> = [[
@@ -2630,6 +3058,7 @@ mod test {
                         }),
                     ],
                     config: TapDanceConfig::Lazy,
+                    interrupt: TapDanceInterrupt::Resolve,
                 }),
                 k(A),
             ],
@@ -2736,7 +3165,8 @@ mod test {
             assert_keys(&[], layout.keycodes());
         }
         layout.event(Press(0, 0));
-        for _ in 0..101 {
+        // The hold activates after the tap-hold timeout from the last tap, like a lone tap-hold.
+        for _ in 0..100 {
             assert_eq!(CustomEvent::NoEvent, layout.tick());
             assert_keys(&[], layout.keycodes());
         }
@@ -2749,6 +3179,145 @@ mod test {
         assert_keys(&[], layout.keycodes());
     }
 
+    #[test]
+    fn tap_dance_hold_tap_step_times_from_last_tap() {
+        static LAYERS: Layers<2, 1, 1> = [[[
+            TapDance(&crate::action::TapDance {
+                timeout: 100,
+                actions: &[
+                    &k(A),
+                    &HoldTap(&HoldTapAction {
+                        timeout: 100,
+                        hold: k(LCtrl),
+                        timeout_action: k(LCtrl),
+                        tap: k(Space),
+                        config: HoldTapConfig::Default,
+                        tap_hold_interval: 0,
+                    }),
+                ],
+                config: TapDanceConfig::Lazy,
+                interrupt: TapDanceInterrupt::Resolve,
+            }),
+            HoldTap(&HoldTapAction {
+                timeout: 100,
+                hold: k(LCtrl),
+                timeout_action: k(LCtrl),
+                tap: k(Space),
+                config: HoldTapConfig::Default,
+                tap_hold_interval: 0,
+            }),
+        ]]];
+        let mut layout = Layout::new(&LAYERS);
+        let ticks_until_hold = |layout: &mut Layout<2, 1, 1>| {
+            (1..1000)
+                .find(|_| {
+                    layout.tick();
+                    layout.keycodes().next().is_some()
+                })
+                .unwrap()
+        };
+
+        // Test: a lone tap-hold
+        layout.event(Press(0, 1));
+        let lone = ticks_until_hold(&mut layout);
+        assert_eq!(lone, 101);
+        assert_keys(&[LCtrl], layout.keycodes());
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+
+        // Test: the tap-hold step of a tap-dance, held after a tap. It holds after the tap-hold
+        // timeout from the last tap, like the lone tap-hold, and not after the tap-dance
+        // resolved plus one tick.
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        layout.event(Release(0, 0));
+        for _ in 0..50 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert_keys(&[], layout.keycodes());
+        }
+        layout.event(Press(0, 0));
+        assert_eq!(ticks_until_hold(&mut layout), lone);
+        assert_keys(&[LCtrl], layout.keycodes());
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+    }
+
+    #[test]
+    fn tap_dance_interrupt() {
+        static LAYERS: Layers<3, 1, 1> = [[[
+            TapDance(&crate::action::TapDance {
+                timeout: 100,
+                actions: &[&k(Kb1), &k(Kb2)],
+                config: TapDanceConfig::Lazy,
+                interrupt: TapDanceInterrupt::Cancel,
+            }),
+            TapDance(&crate::action::TapDance {
+                timeout: 100,
+                actions: &[&k(Kb1), &k(Kb2)],
+                config: TapDanceConfig::Lazy,
+                interrupt: TapDanceInterrupt::Buffer,
+            }),
+            k(A),
+        ]]];
+        let mut layout = Layout::new(&LAYERS);
+
+        // Test: cancel, the tap dance does nothing and the other key is handled
+        layout.event(Press(0, 0));
+        layout.event(Release(0, 0));
+        layout.event(Press(0, 2));
+        for _ in 0..3 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert_keys(&[], layout.keycodes());
+        }
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[A], layout.keycodes());
+        layout.event(Release(0, 2));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+
+        // Test: buffer, taps after the other key still count and the other key comes after
+        layout.event(Press(0, 1));
+        layout.event(Release(0, 1));
+        layout.event(Press(0, 2));
+        layout.event(Release(0, 2));
+        for _ in 0..50 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert_keys(&[], layout.keycodes());
+        }
+        layout.event(Press(0, 1));
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[Kb2], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[Kb2, A], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[Kb2], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+
+        // Test: buffer, the timeout activates the taps so far before the other key
+        layout.event(Press(0, 1));
+        layout.event(Release(0, 1));
+        layout.event(Press(0, 2));
+        layout.event(Release(0, 2));
+        for _ in 0..100 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert_keys(&[], layout.keycodes());
+        }
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[Kb1], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[A], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+    }
+
     #[test]
     fn tap_dance_eager() {
         static LAYERS: Layers<2, 2, 1> = [[
@@ -2757,6 +3326,7 @@ mod test {
                     timeout: 100,
                     actions: &[&k(Kb1), &k(Kb2), &k(Kb3)],
                     config: TapDanceConfig::Eager,
+                    interrupt: TapDanceInterrupt::Resolve,
                 }),
                 k(A),
             ],
@@ -2883,6 +3453,82 @@ mod test {
         assert_keys(&[], layout.keycodes());
     }
 
+    #[test]
+    fn release_after_layer_change() {
+        static LAYERS: Layers<2, 1, 2> = [
+            [[Layer(1), KeyCode(A)]],
+            [[Trans, KeyCode(B)]],
+        ];
+        let mut layout = Layout::new(&LAYERS);
+
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[B], layout.keycodes());
+        // The layer is deactivated while B is held. B must stay held and be released with the
+        // key that pressed it, rather than changing to A.
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(0, layout.current_layer());
+        assert_keys(&[B], layout.keycodes());
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+    }
+
+    #[test]
+    fn active_layers() {
+        static LAYERS: Layers<2, 1, 3> = [
+            [[Layer(1), Layer(2)]],
+            [[Trans, Layer(2)]],
+            [[Trans, Trans]],
+        ];
+        let mut layout = Layout::new(&LAYERS);
+
+        assert!(layout.active_layers().eq([0]));
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(layout.active_layers().eq([0, 1, 2]));
+        assert_eq!(2, layout.current_layer());
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert!(layout.active_layers().eq([0, 2]));
+    }
+
+    #[test]
+    fn on_release() {
+        static LAYERS: Layers<2, 1, 1> = [[[
+            OnRelease(&KeyCode(A)),
+            MultipleActions(&(&[KeyCode(B), OnRelease(&KeyCode(C))] as _)),
+        ]]];
+        let mut layout = Layout::new(&LAYERS);
+
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[A], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[B], layout.keycodes());
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[C], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+    }
+
     #[test]
     fn test_chord() {
         const GROUP: ChordsGroup<core::convert::Infallible> = ChordsGroup {
@@ -2896,6 +3542,7 @@ mod test {
                 (11, &KeyCode(Kb6)),
             ],
             timeout: 100,
+            release_only: false,
         };
         static LAYERS: Layers<6, 1, 1> = [[[
             NoOp,
@@ -3004,6 +3651,46 @@ mod test {
         assert_keys(&[], layout.keycodes());
     }
 
+    #[test]
+    fn test_chord_release_only() {
+        const GROUP: ChordsGroup<core::convert::Infallible> = ChordsGroup {
+            coords: &[((0, 0), 1), ((0, 1), 2), ((0, 2), 4)],
+            chords: &[(1, &KeyCode(A)), (3, &KeyCode(B)), (7, &KeyCode(C))],
+            timeout: 10,
+            release_only: true,
+        };
+        static LAYERS: Layers<3, 1, 1> = [[[Chords(&GROUP), Chords(&GROUP), Chords(&GROUP)]]];
+
+        let mut layout = Layout::new(&LAYERS);
+        // Neither the timeout nor an unambiguous chord resolves the chord.
+        layout.event(Press(0, 0));
+        layout.event(Press(0, 1));
+        layout.event(Press(0, 2));
+        for _ in 0..50 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert_keys(&[], layout.keycodes());
+        }
+        // Releasing any chord key resolves the chord of all keys pressed.
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[C], layout.keycodes());
+        layout.event(Release(0, 0));
+        layout.event(Release(0, 2));
+        for _ in 0..3 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+        }
+        assert_keys(&[], layout.keycodes());
+
+        layout.event(Press(0, 0));
+        for _ in 0..50 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert_keys(&[], layout.keycodes());
+        }
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[A], layout.keycodes());
+    }
+
     #[test]
     fn test_fork() {
         static LAYERS: Layers<2, 1, 1> = [[[
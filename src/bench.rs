@@ -0,0 +1,118 @@
+//! `kanata --bench-self`: timings of the stages of key processing with the configuration on this
+//! machine, so that bug reports about lag can say where the time goes.
+//!
+//! The configuration is parsed once per stage and its layout is then simulated without input or
+//! output devices: every key of defsrc is pressed and released in turn, with time to settle in
+//! between. The OS is left out; on Linux, `--measure-latency` measures the latency of real key
+//! events. The timer stage measures how long a sleep of one tick really takes, since kanata
+//! processes the layout once per tick and a coarse OS timer delays every output.
+
+use crate::cfg::{CfgBuilder, Simulation};
+
+use anyhow::{anyhow, Result};
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// The times that every key of defsrc is pressed and released.
+const ROUNDS: usize = 10;
+/// Milliseconds that the simulation runs after each key, so that timeouts, e.g. of `tap-hold`,
+/// expire before the next key.
+const SETTLE_MS: u32 = 1000;
+const IDLE_TICKS: usize = 10_000;
+const TIMER_SAMPLES: usize = 200;
+
+struct StageTimings {
+    name: &'static str,
+    samples: Vec<Duration>,
+}
+
+impl StageTimings {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            samples: vec![],
+        }
+    }
+
+    fn measure<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.samples.push(start.elapsed());
+        result
+    }
+}
+
+impl std::fmt::Display for StageTimings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut samples = self.samples.clone();
+        samples.sort_unstable();
+        let Some(max) = samples.last() else {
+            return write!(f, "{:<14} no samples", self.name);
+        };
+        let percentile = |p: usize| samples[(samples.len() - 1) * p / 100].as_micros();
+        let mean = samples.iter().sum::<Duration>() / samples.len() as u32;
+        write!(
+            f,
+            "{:<14} n {:>7}  mean {:>8}us  p50 {:>8}us  p99 {:>8}us  max {:>8}us",
+            self.name,
+            samples.len(),
+            mean.as_micros(),
+            percentile(50),
+            percentile(99),
+            max.as_micros()
+        )
+    }
+}
+
+/// Print the timings of the stages for the configuration file.
+pub fn run(path: &Path) -> Result<()> {
+    let cfg_err = |e: miette::Report| anyhow!("{e:?}");
+    let mut parse = StageTimings::new("parse");
+    let mut resolve = StageTimings::new("resolve");
+    let mut validate = StageTimings::new("validate");
+    let parsed = parse.measure(|| CfgBuilder::from_file(path).and_then(|b| b.parse()));
+    let resolved = resolve.measure(|| parsed.and_then(|p| p.resolve()).map_err(cfg_err))?;
+    let cfg = validate.measure(|| resolved.validate().freeze());
+
+    let mut idle = StageTimings::new("idle tick");
+    let mut press = StageTimings::new("key press");
+    let mut release = StageTimings::new("key release");
+    let mut sim = Simulation::new(&cfg);
+    for _ in 0..IDLE_TICKS {
+        idle.measure(|| sim.tick());
+    }
+    let mut keys = cfg.mapped_keys.iter().copied().collect::<Vec<_>>();
+    keys.sort_unstable_by_key(|k| *k as u16);
+    for _ in 0..ROUNDS {
+        for &key in keys.iter() {
+            press.measure(|| {
+                sim.press(key);
+                sim.tick();
+            });
+            release.measure(|| {
+                sim.release(key);
+                sim.tick();
+            });
+            sim.wait(SETTLE_MS);
+            sim.set_default_layer(0);
+        }
+    }
+
+    let mut timer = StageTimings::new("1ms sleep");
+    for _ in 0..TIMER_SAMPLES {
+        timer.measure(|| std::thread::sleep(Duration::from_millis(1)));
+    }
+
+    println!(
+        "kanata v{} on {} {}, {} keys in defsrc",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        keys.len()
+    );
+    for stage in [parse, resolve, validate, idle, press, release, timer] {
+        println!("{stage}");
+    }
+    Ok(())
+}
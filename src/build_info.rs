@@ -0,0 +1,25 @@
+//! `kanata build-info`: the version and build of a running kanata instance and the hash of its
+//! configuration, to paste into a bug report.
+
+use crate::tcp_client::KanataClient;
+use crate::tcp_server::{ClientMessage, ServerMessage};
+
+use anyhow::{anyhow, Result};
+
+/// Print the build info of the kanata instance on the port as JSON.
+pub fn run(port: u16) -> Result<()> {
+    let info = KanataClient::connect(port)?.request(
+        &ClientMessage::RequestBuildInfo,
+        "build info",
+        |msg| match msg {
+            ServerMessage::BuildInfo { info } => Some(info),
+            _ => None,
+        },
+    )?;
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&info)
+            .map_err(|e| anyhow!("failed to serialize the build info: {e}"))?
+    );
+    Ok(())
+}
@@ -0,0 +1,197 @@
+//! Staged construction of a [`Cfg`].
+//!
+//! Reading a configuration goes through the stages below. Errors of the first two stages are
+//! reported with the [`CfgStage`] that failed.
+//!
+//! 1. parse: merge the included files, decrypt the `defsecrets` file, read the s-expressions,
+//!    expand the items that generate other items, and read `defcfg` and `deflocalkeys`.
+//! 2. resolve: resolve variables, aliases and actions into the keyberon layers.
+//! 3. validate: run the lints over the resolved layers.
+//! 4. freeze: create the keyberon layout and the key outputs used for key repeat.
+//!
+//! [`new_from_file`] runs every stage. The builder is useful to stop early or to inspect the
+//! intermediate results, e.g. the lint warnings reported by `kanata --check`.
+
+use super::sexpr::Span;
+use super::*;
+
+use std::fmt;
+
+/// A stage of building a configuration that can fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CfgStage {
+    Parse,
+    Resolve,
+}
+
+impl fmt::Display for CfgStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CfgStage::Parse => "parsing",
+            CfgStage::Resolve => "resolving",
+        })
+    }
+}
+
+fn stage_error(mut e: CfgError, stage: CfgStage, s: &ParsedState) -> miette::Error {
+    e.stage = Some(stage);
+    error_with_source(e, s)
+}
+
+/// The configuration text, before any stage has run.
+pub struct CfgBuilder {
+    s: ParsedState,
+}
+
+impl CfgBuilder {
+    /// Read the configuration text from a file.
+    pub fn from_file(p: &std::path::Path) -> MResult<Self> {
+        let text = std::fs::read_to_string(p)
+            .map_err(|e| miette::miette!("Failed to read {}: {e}", p.display()))?;
+        Ok(Self::from_text(p.to_string_lossy().to_string(), text))
+    }
+
+    /// Use configuration text that does not come from a file. `name` is shown in errors.
+    pub fn from_text(name: String, text: String) -> Self {
+        let s = ParsedState {
+            cfg_filename: name,
+            cfg_text: text,
+            ..Default::default()
+        };
+        Self { s }
+    }
+
+    /// Merges the included files, decrypts the secrets and reads the s-expressions.
+    pub fn parse(mut self) -> MResult<ParsedCfg> {
+        let (text, includes) = expand_includes(&self.s.cfg_filename, &self.s.cfg_text)
+            .map_err(|e| stage_error(e, CfgStage::Parse, &self.s))?;
+        self.s.cfg_text = text;
+        self.s.includes = includes;
+        let secrets = load_secrets(&self.s.cfg_filename, &self.s.cfg_text)
+            .map_err(|e| stage_error(e, CfgStage::Parse, &self.s))?;
+        match parse_cfg_exprs(&self.s.cfg_text, &self.s.includes, &secrets) {
+            Ok((items, exprs)) => Ok(ParsedCfg {
+                s: self.s,
+                items,
+                exprs,
+            }),
+            Err(e) => Err(stage_error(e, CfgStage::Parse, &self.s)),
+        }
+    }
+}
+
+/// The configuration after the parse stage.
+pub struct ParsedCfg {
+    s: ParsedState,
+    items: HashMap<String, String>,
+    exprs: SpannedRootExprs,
+}
+
+impl ParsedCfg {
+    pub fn resolve(mut self) -> MResult<ResolvedCfg> {
+        let text = self.s.cfg_text.clone();
+        match resolve_cfg_exprs(&text, self.items, self.exprs, &mut self.s) {
+            Ok((items, mapped_keys, layer_info, layers, sequences, overrides, hooks)) => {
+                Ok(ResolvedCfg {
+                    s: self.s,
+                    items,
+                    mapped_keys,
+                    layer_info,
+                    layers,
+                    sequences,
+                    overrides,
+                    hooks,
+                })
+            }
+            Err(e) => Err(stage_error(e, CfgStage::Resolve, &self.s)),
+        }
+    }
+}
+
+/// The configuration after the resolve stage.
+pub struct ResolvedCfg {
+    s: ParsedState,
+    items: HashMap<String, String>,
+    mapped_keys: MappedKeys,
+    layer_info: Vec<LayerInfo>,
+    layers: Box<KanataLayers>,
+    sequences: KeySeqsToFKeys,
+    overrides: Overrides,
+    hooks: Hooks,
+}
+
+impl ResolvedCfg {
+    pub fn validate(self) -> ValidatedCfg {
+        let mut warnings = lint(&self.layers, &self.layer_info, &self.mapped_keys);
+        for warning in warnings.iter_mut() {
+            warning.location = lint_span(warning, &self.s).map(|span| {
+                let (name, text, start) = self.s.source_at(span.start());
+                SourceLocation::new(name, text, span.start() - start)
+            });
+        }
+        ValidatedCfg {
+            resolved: self,
+            warnings,
+        }
+    }
+}
+
+/// Returns the span of the layer item a warning is about, or of the layer name if the warning is
+/// about the whole layer.
+fn lint_span(warning: &LintWarning, s: &ParsedState) -> Option<Span> {
+    let layer = s.layer_exprs.get(*s.layer_idxs.get(&warning.layer)?)?;
+    let item = match warning.key {
+        Some(key) => {
+            let i = s
+                .mapping_order
+                .iter()
+                .position(|k| *k == usize::from(key))?;
+            layer.get(i + 2)?
+        }
+        None => layer.get(1)?,
+    };
+    Some(item.span())
+}
+
+/// The configuration after the validate stage.
+pub struct ValidatedCfg {
+    resolved: ResolvedCfg,
+    warnings: Vec<LintWarning>,
+}
+
+impl ValidatedCfg {
+    /// Warnings about suspicious patterns, see [`lint`].
+    pub fn warnings(&self) -> &[LintWarning] {
+        &self.warnings
+    }
+
+    pub fn freeze(self) -> Cfg {
+        let r = self.resolved;
+        let fake_keys =
+            r.s.fake_keys
+                .iter()
+                .map(|(name, (idx, _))| (name.clone(), *idx))
+                .collect();
+        Cfg {
+            items: r.items,
+            mapped_keys: r.mapped_keys,
+            layer_info: r.layer_info,
+            key_outputs: create_key_outputs(&r.layers, &r.overrides),
+            layout: create_layout(r.layers, r.s.layer_conditions, r.s.a),
+            mouse_accel_layers: r.s.mouse_accel_layers,
+            device_tags: r.s.device_tags,
+            mod_translations: r.s.mod_translations,
+            helpers: r.s.helpers,
+            modal_layers: r.s.modal_layers,
+            sequences: r.sequences,
+            overrides: r.overrides,
+            hooks: r.hooks,
+            fake_keys,
+            tests: r.s.tests,
+            launcher: r.s.launcher,
+            mod_chords: r.s.mod_chords,
+            shortcodes: r.s.shortcodes,
+            snippets: r.s.snippets,
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! Capabilities that a configuration needs: the optional features kanata was compiled with and the
+//! platform it runs on.
+//!
+//! `(defrequires cmd sound)` makes kanata refuse the configuration with an error that names the
+//! missing capabilities. `(defoptional (cmd) <items>...)` wraps configuration items that are only
+//! used if kanata has all the listed capabilities; otherwise they are skipped with a warning, so
+//! that the rest of the configuration still works, e.g. a layer of `cmd` actions in a
+//! configuration that is shared with a build without `cmd`. `defoptional` items may hold any
+//! other configuration item, including `defcfg`.
+
+use super::*;
+
+const DEFREQUIRES: &str = "defrequires";
+const DEFOPTIONAL: &str = "defoptional";
+
+/// The capabilities that configurations may name and whether this build of kanata has them.
+const CAPABILITIES: &[(&str, bool)] = &[
+    ("cmd", cfg!(feature = "cmd")),
+    ("clipboard", cfg!(feature = "clipboard")),
+    ("sound", cfg!(feature = "sound")),
+    ("wayland", cfg!(feature = "wayland")),
+    ("xtest", cfg!(feature = "xtest")),
+    ("ble_hid", cfg!(feature = "ble_hid")),
+    ("kvm", cfg!(feature = "kvm")),
+    ("interception_driver", cfg!(feature = "interception_driver")),
+    ("linux", cfg!(target_os = "linux")),
+    ("windows", cfg!(target_os = "windows")),
+];
+
+/// Check the `defrequires` items and replace the `defoptional` items with the items they hold if
+/// kanata has the capabilities, or else with nothing.
+pub(super) fn expand_capabilities(exprs: SpannedRootExprs) -> Result<SpannedRootExprs> {
+    let mut expanded = vec![];
+    for expr in exprs {
+        match expr.t.first().and_then(|e| e.atom(None)) {
+            Some(DEFREQUIRES) => {
+                let missing = missing_capabilities(&expr.t[1..])?;
+                if !missing.is_empty() {
+                    bail_span!(
+                        &expr,
+                        "This configuration requires {}, which this build of kanata does not \
+                         have. Use a build with the feature or skip the items that need it \
+                         with {DEFOPTIONAL}.",
+                        missing.join(", ")
+                    )
+                }
+            }
+            Some(DEFOPTIONAL) => {
+                const ERR_MSG: &str =
+                    "defoptional expects a list of capabilities followed by configuration items";
+                let Some(capabilities) = expr.t.get(1).and_then(|e| e.list(None)) else {
+                    bail_span!(&expr, "{ERR_MSG}")
+                };
+                let mut items = vec![];
+                for item in &expr.t[2..] {
+                    match item {
+                        SExpr::List(list) => items.push(list.clone()),
+                        SExpr::Atom(_) => bail_expr!(item, "{ERR_MSG}"),
+                    }
+                }
+                let missing = missing_capabilities(capabilities)?;
+                if missing.is_empty() {
+                    expanded.extend(expand_capabilities(items)?);
+                } else {
+                    log::warn!(
+                        "skipping {} item(s) of {DEFOPTIONAL}: this build of kanata does not \
+                         have {}",
+                        items.len(),
+                        missing.join(", ")
+                    );
+                }
+            }
+            _ => expanded.push(expr),
+        }
+    }
+    Ok(expanded)
+}
+
+/// The named capabilities that this build of kanata does not have.
+fn missing_capabilities(names: &[SExpr]) -> Result<Vec<&str>> {
+    let mut missing = vec![];
+    for expr in names {
+        let Some(name) = expr.atom(None) else {
+            bail_expr!(expr, "Expected the name of a capability")
+        };
+        match CAPABILITIES.iter().find(|(c, _)| *c == name) {
+            Some((_, true)) => {}
+            Some((_, false)) => missing.push(name),
+            None => bail_expr!(
+                expr,
+                "Unknown capability. Known capabilities are: {}",
+                CAPABILITIES
+                    .iter()
+                    .map(|(c, _)| *c)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+    Ok(missing)
+}
@@ -30,3 +30,66 @@ pub(crate) fn custom_tap_hold_release(
         None
     })
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Hand {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Finger {
+    Thumb,
+    Index,
+    Middle,
+    Ring,
+    Pinky,
+}
+
+/// Returns a closure that can be used in `HoldTapConfig::Custom`, which will return early with a
+/// Tap action in the case that a key on the same hand as the HoldTap key is pressed, according to
+/// `hands` which is indexed by `OsCode`. For other keys, it behaves as
+/// `HoldTapConfig::PermissiveHold` would.
+pub(crate) fn custom_tap_hold_opposite_hand(
+    hands: &'static [Option<Hand>],
+    a: &Allocations,
+) -> &'static (dyn Fn(QueuedIter) -> Option<WaitingAction> + Send + Sync) {
+    custom_tap_hold_other_group(hands, a)
+}
+
+/// Like [`custom_tap_hold_opposite_hand`], but only a key typed by the same finger as the HoldTap
+/// key results in a Tap, so that rolls between the fingers of one hand can still result in a Hold.
+pub(crate) fn custom_tap_hold_other_finger(
+    fingers: &'static [Option<(Hand, Finger)>],
+    a: &Allocations,
+) -> &'static (dyn Fn(QueuedIter) -> Option<WaitingAction> + Send + Sync) {
+    custom_tap_hold_other_group(fingers, a)
+}
+
+/// Tap if a key in the same group as the HoldTap key is pressed, otherwise PermissiveHold.
+fn custom_tap_hold_other_group<G: Copy + PartialEq + Send + Sync + 'static>(
+    groups: &'static [Option<G>],
+    a: &Allocations,
+) -> &'static (dyn Fn(QueuedIter) -> Option<WaitingAction> + Send + Sync) {
+    // Fake keys are not on the physical key row and have no group.
+    let group_of = move |(i, j): (u8, u16)| match i {
+        0 => groups.get(usize::from(j)).copied().flatten(),
+        _ => None,
+    };
+    a.sref(move |mut queued: QueuedIter| -> Option<WaitingAction> {
+        let hold_tap_group = group_of(queued.hold_tap_coord());
+        while let Some(q) = queued.next() {
+            if q.event().is_press() {
+                let (i, j) = q.event().coord();
+                if hold_tap_group.is_some() && group_of((i, j)) == hold_tap_group {
+                    return Some(WaitingAction::Tap);
+                }
+                let target = Event::Release(i, j);
+                if queued.clone().copied().any(|q| q.event() == target) {
+                    return Some(WaitingAction::Hold);
+                }
+            }
+        }
+        None
+    })
+}
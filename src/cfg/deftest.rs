@@ -0,0 +1,152 @@
+//! Tests of a configuration declared in the configuration itself with `deftest`, run by
+//! `kanata --check` against a [`Simulation`].
+//!
+//! ```text
+//! (deftest caps-is-esc-when-tapped
+//!   (tap caps)
+//!   (expect-within 200 esc))
+//! ```
+
+use super::*;
+
+/// Milliseconds that `expect` waits for outputs without an explicit time limit.
+const DEFAULT_EXPECT_MS: u16 = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CfgTest {
+    pub name: String,
+    steps: Vec<TestStep>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TestStep {
+    Press(OsCode),
+    Release(OsCode),
+    Tap(OsCode),
+    Wait(u16),
+    /// Set the default layer by its keyberon index.
+    Layer(usize),
+    /// The keys that must be pressed, in order, within the milliseconds after the last input.
+    Expect(Vec<KeyCode>, u16),
+}
+
+/// Parse `(deftest <name> <step>...)`.
+pub(super) fn parse_deftests(
+    exprs: &[&Spanned<Vec<SExpr>>],
+    s: &ParsedState,
+) -> Result<Vec<CfgTest>> {
+    const STEPS_MSG: &str = "Valid test steps: (press <key>), (release <key>), (tap <key>), \
+        (wait <ms>), (layer <layer>), (expect <key>...), (expect-within <ms> <key>...)";
+    let mut tests: Vec<CfgTest> = vec![];
+    for expr in exprs {
+        let name = match expr.t.get(1).and_then(|e| e.atom(s.vars())) {
+            Some(name) => name.to_owned(),
+            None => bail_span!(expr, "deftest expects a name followed by test steps"),
+        };
+        if tests.iter().any(|t| t.name == name) {
+            bail_span!(expr, "Duplicate test name: {name}");
+        }
+        let mut steps = vec![];
+        for step_expr in &expr.t[2..] {
+            let step = match step_expr.list(s.vars()) {
+                Some(step) => step,
+                None => bail_expr!(step_expr, "{STEPS_MSG}"),
+            };
+            let kind = step
+                .first()
+                .and_then(|k| k.atom(s.vars()))
+                .ok_or_else(|| anyhow_expr!(step_expr, "{STEPS_MSG}"))?;
+            let key = |e: &SExpr| {
+                e.atom(s.vars())
+                    .and_then(str_to_oscode)
+                    .ok_or_else(|| anyhow_expr!(e, "Unknown key in test step"))
+            };
+            let step = match (kind, &step[1..]) {
+                ("press", [k]) => TestStep::Press(key(k)?),
+                ("release", [k]) => TestStep::Release(key(k)?),
+                ("tap", [k]) => TestStep::Tap(key(k)?),
+                ("wait", [ms]) => TestStep::Wait(parse_u16(ms, s, "wait")?),
+                ("layer", [layer]) => {
+                    let name = layer.atom(s.vars()).unwrap_or_default();
+                    match s.layer_idxs.get(name) {
+                        Some(idx) => TestStep::Layer(idx * 2),
+                        None => bail_expr!(layer, "Unknown layer name: {name}"),
+                    }
+                }
+                ("expect", keys) if !keys.is_empty() => TestStep::Expect(
+                    keys.iter()
+                        .map(|k| key(k).map(KeyCode::from))
+                        .collect::<Result<_>>()?,
+                    DEFAULT_EXPECT_MS,
+                ),
+                ("expect-within", [ms, keys @ ..]) if !keys.is_empty() => TestStep::Expect(
+                    keys.iter()
+                        .map(|k| key(k).map(KeyCode::from))
+                        .collect::<Result<_>>()?,
+                    parse_u16(ms, s, "expect-within")?,
+                ),
+                _ => bail_expr!(step_expr, "{STEPS_MSG}"),
+            };
+            steps.push(step);
+        }
+        tests.push(CfgTest { name, steps });
+    }
+    Ok(tests)
+}
+
+impl CfgTest {
+    /// Run the test against a fresh simulation of the configuration. Returns a description of
+    /// the first step that failed.
+    pub fn run(&self, cfg: &Cfg) -> std::result::Result<(), String> {
+        self.run_in(&mut Simulation::new(cfg))
+    }
+
+    /// Run the test in the simulation, which is left in the state after the last step that ran.
+    pub fn run_in(&self, sim: &mut Simulation) -> std::result::Result<(), String> {
+        let mut last_input = sim.time();
+        let mut checked = sim.outputs().len();
+        for (i, step) in self.steps.iter().enumerate() {
+            match step {
+                TestStep::Press(k) => {
+                    sim.press(*k);
+                    last_input = sim.time();
+                }
+                TestStep::Release(k) => {
+                    sim.release(*k);
+                    last_input = sim.time();
+                }
+                TestStep::Tap(k) => {
+                    sim.press(*k);
+                    sim.tick();
+                    sim.release(*k);
+                    last_input = sim.time();
+                }
+                TestStep::Wait(ms) => sim.wait(u32::from(*ms)),
+                TestStep::Layer(layer) => sim.set_default_layer(*layer),
+                TestStep::Expect(keys, within) => {
+                    let pressed = |sim: &Simulation| {
+                        sim.outputs()[checked..]
+                            .iter()
+                            .filter(|o| o.pressed)
+                            .map(|o| o.key)
+                            .collect::<Vec<_>>()
+                    };
+                    while pressed(sim).len() < keys.len()
+                        && sim.time() - last_input < u32::from(*within)
+                    {
+                        sim.tick();
+                    }
+                    let got = pressed(sim);
+                    if &got != keys {
+                        return Err(format!(
+                            "step {}: expected {keys:?} within {within}ms, got {got:?}",
+                            i + 1
+                        ));
+                    }
+                    checked = sim.outputs().len();
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,261 @@
+//! A lossless, editable view of a configuration file.
+//!
+//! Unlike [`super::new_from_file`], which turns the configuration into a keyberon layout and
+//! throws the source away, [`CfgDocument`] keeps the original text around. Top-level items can be
+//! looked up, replaced, inserted and removed; everything not touched by an edit (comments,
+//! whitespace, item ordering) is written back byte-for-byte. This is intended as the backend for
+//! GUI/TUI configuration editors.
+//!
+//! Edits splice the text directly and then re-parse it, so the spans of every item are always
+//! valid after an edit and a malformed edit is rejected without modifying the document.
+
+// This is a library API for external editors; the kanata binary itself does not use all of it.
+#![allow(dead_code)]
+
+use super::sexpr::{self, SExpr, Span, Spanned};
+
+use anyhow::{anyhow, bail, Result};
+
+use std::path::Path;
+
+/// A configuration file that can be edited and written back while preserving comments and
+/// ordering.
+#[derive(Debug, Clone)]
+pub struct CfgDocument {
+    text: String,
+    items: Vec<Spanned<Vec<SExpr>>>,
+}
+
+/// A reference to a top-level item of a [`CfgDocument`], e.g. `(deflayer base ...)`.
+#[derive(Debug, Clone, Copy)]
+pub struct CfgItem<'a> {
+    text: &'a str,
+    item: &'a Spanned<Vec<SExpr>>,
+}
+
+impl<'a> CfgItem<'a> {
+    /// The first atom of the item, e.g. `deflayer`. Returns an empty string if the item is empty
+    /// or begins with a list.
+    pub fn kind(&self) -> &'a str {
+        match self.item.t.first() {
+            Some(SExpr::Atom(a)) => &a.t,
+            _ => "",
+        }
+    }
+
+    /// The second atom of the item, e.g. the layer name for `deflayer`, if it exists.
+    pub fn name(&self) -> Option<&'a str> {
+        match self.item.t.get(1) {
+            Some(SExpr::Atom(a)) => Some(&a.t),
+            _ => None,
+        }
+    }
+
+    /// The parsed expressions of the item, including the leading atom.
+    pub fn exprs(&self) -> &'a [SExpr] {
+        &self.item.t
+    }
+
+    /// The source text of the item, including the enclosing parentheses.
+    pub fn text(&self) -> &'a str {
+        &self.text[self.item.span]
+    }
+
+    pub fn span(&self) -> Span {
+        self.item.span
+    }
+}
+
+impl CfgDocument {
+    /// Parse a configuration from text. Only the syntax is checked; the semantic validity of the
+    /// configuration is not.
+    pub fn parse(text: impl Into<String>) -> Result<Self> {
+        let text = text.into();
+        let items = parse_items(&text)?;
+        Ok(Self { text, items })
+    }
+
+    pub fn from_file(p: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(p)
+            .map_err(|e| anyhow!("failed to read {}: {e}", p.display()))?;
+        Self::parse(text)
+    }
+
+    pub fn write_to_file(&self, p: &Path) -> Result<()> {
+        std::fs::write(p, &self.text).map_err(|e| anyhow!("failed to write {}: {e}", p.display()))
+    }
+
+    /// The full text of the document, including all edits.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn item(&self, idx: usize) -> Option<CfgItem<'_>> {
+        self.items.get(idx).map(|item| CfgItem {
+            text: &self.text,
+            item,
+        })
+    }
+
+    /// Iterate over the top-level items in file order.
+    pub fn items(&self) -> impl Iterator<Item = CfgItem<'_>> + '_ {
+        self.items.iter().map(|item| CfgItem {
+            text: &self.text,
+            item,
+        })
+    }
+
+    /// Find the index of the first item with the given kind and, optionally, name. For example
+    /// `find("deflayer", Some("base"))` or `find("defsrc", None)`.
+    pub fn find(&self, kind: &str, name: Option<&str>) -> Option<usize> {
+        self.items()
+            .position(|item| item.kind() == kind && (name.is_none() || item.name() == name))
+    }
+
+    /// Replace the item at `idx` with `new_item`, which must be a single top-level list.
+    pub fn replace_item(&mut self, idx: usize, new_item: &str) -> Result<()> {
+        let span = self.span_of(idx)?;
+        check_single_item(new_item)?;
+        self.splice(span.start..span.end, new_item)
+    }
+
+    /// Insert `new_item` before the item at `idx`. If `idx` is equal to the number of items, the
+    /// item is appended to the end of the document.
+    pub fn insert_item(&mut self, idx: usize, new_item: &str) -> Result<()> {
+        if idx == self.items.len() {
+            return self.push_item(new_item);
+        }
+        let span = self.span_of(idx)?;
+        check_single_item(new_item)?;
+        self.splice(span.start..span.start, &format!("{new_item}\n\n"))
+    }
+
+    /// Append `new_item` to the end of the document.
+    pub fn push_item(&mut self, new_item: &str) -> Result<()> {
+        check_single_item(new_item)?;
+        let mut insertion = String::new();
+        if !self.text.is_empty() {
+            if !self.text.ends_with('\n') {
+                insertion.push('\n');
+            }
+            insertion.push('\n');
+        }
+        insertion.push_str(new_item);
+        insertion.push('\n');
+        let end = self.text.len();
+        self.splice(end..end, &insertion)
+    }
+
+    /// Remove the item at `idx`. The rest of the line it was on is removed as well if it only
+    /// contains whitespace.
+    pub fn remove_item(&mut self, idx: usize) -> Result<()> {
+        let span = self.span_of(idx)?;
+        let rest = &self.text[span.end..];
+        let end = match rest.find('\n') {
+            Some(nl) if rest[..nl].trim().is_empty() => span.end + nl + 1,
+            None if rest.trim().is_empty() => self.text.len(),
+            _ => span.end,
+        };
+        self.splice(span.start..end, "")
+    }
+
+    fn span_of(&self, idx: usize) -> Result<Span> {
+        match self.items.get(idx) {
+            Some(item) => Ok(item.span),
+            None => bail!(
+                "item index {idx} is out of range; the document has {} items",
+                self.items.len()
+            ),
+        }
+    }
+
+    /// Apply the edit and re-parse. The document is left unchanged if the result fails to parse.
+    fn splice(&mut self, range: std::ops::Range<usize>, replacement: &str) -> Result<()> {
+        let mut new_text = self.text.clone();
+        new_text.replace_range(range, replacement);
+        self.items = parse_items(&new_text)?;
+        self.text = new_text;
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for CfgDocument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+fn parse_items(text: &str) -> Result<Vec<Spanned<Vec<SExpr>>>> {
+    sexpr::parse(text).map_err(|(msg, start, _)| anyhow!("{msg} at byte offset {start}"))
+}
+
+fn check_single_item(item: &str) -> Result<()> {
+    match parse_items(item)?.len() {
+        1 => Ok(()),
+        n => bail!("expected exactly one top-level item, found {n}"),
+    }
+}
+
+#[test]
+fn document_round_trip_preserves_comments() {
+    let text = r"
+;; leading comment
+(defsrc a b) ;; trailing comment
+#| multi
+   line |#
+(deflayer base
+  ;; inside
+  c d)
+";
+    let doc = CfgDocument::parse(text).unwrap();
+    assert_eq!(doc.to_string(), text);
+    assert_eq!(doc.len(), 2);
+    assert_eq!(doc.item(1).unwrap().name(), Some("base"));
+    assert_eq!(doc.find("defsrc", None), Some(0));
+    assert_eq!(doc.find("deflayer", Some("base")), Some(1));
+    assert_eq!(doc.find("deflayer", Some("nope")), None);
+}
+
+#[test]
+fn document_edits_preserve_untouched_text() {
+    let text = ";; keep me\n(defsrc a b) ;; and me\n(deflayer base c d)\n";
+    let mut doc = CfgDocument::parse(text).unwrap();
+
+    doc.replace_item(1, "(deflayer base e f)").unwrap();
+    assert_eq!(
+        doc.text(),
+        ";; keep me\n(defsrc a b) ;; and me\n(deflayer base e f)\n"
+    );
+
+    doc.push_item("(deflayer other _ _)").unwrap();
+    assert_eq!(doc.find("deflayer", Some("other")), Some(2));
+    assert!(doc
+        .text()
+        .ends_with("(deflayer base e f)\n\n(deflayer other _ _)\n"));
+
+    doc.insert_item(0, "(defcfg)").unwrap();
+    assert!(doc
+        .text()
+        .starts_with(";; keep me\n(defcfg)\n\n(defsrc a b) ;; and me\n"));
+
+    doc.remove_item(3).unwrap();
+    assert_eq!(
+        doc.text(),
+        ";; keep me\n(defcfg)\n\n(defsrc a b) ;; and me\n(deflayer base e f)\n\n"
+    );
+
+    // Invalid edits are rejected and leave the document as-is.
+    let before = doc.text().to_string();
+    assert!(doc.replace_item(0, "(defcfg").is_err());
+    assert!(doc.replace_item(0, "(a) (b)").is_err());
+    assert!(doc.remove_item(10).is_err());
+    assert_eq!(doc.text(), before);
+}
@@ -7,7 +7,7 @@ pub type MResult<T> = miette::Result<T>;
 pub type Result<T> = std::result::Result<T, CfgError>;
 
 #[derive(Error, Debug, Diagnostic)]
-#[error("Error in configuration file")]
+#[error("Error in configuration file{}", .stage.map(|s| format!(" while {s}")).unwrap_or_default())]
 #[diagnostic()]
 pub struct CfgError {
     // Snippets and highlights can be included in the diagnostic!
@@ -15,6 +15,8 @@ pub struct CfgError {
     pub err_span: Option<SourceSpan>,
     #[help]
     pub help_msg: String,
+    /// The stage of building the configuration that failed, if known.
+    pub stage: Option<CfgStage>,
 }
 
 pub(super) fn help(err_msg: impl AsRef<str>) -> String {
@@ -32,6 +34,7 @@ pub(super) fn error_expr(expr: &sexpr::SExpr, err_msg: impl AsRef<str>) -> CfgEr
     CfgError {
         err_span: Some(expr_err_span(expr)),
         help_msg: help(err_msg),
+        stage: None,
     }
 }
 
@@ -39,6 +42,7 @@ pub(super) fn error_spanned<T>(expr: &Spanned<T>, err_msg: impl AsRef<str>) -> C
     CfgError {
         err_span: Some(spanned_err_span(expr)),
         help_msg: help(err_msg),
+        stage: None,
     }
 }
 
@@ -56,11 +60,14 @@ pub(super) fn spanned_err_span<T>(spanned: &Spanned<T>) -> SourceSpan {
     SourceSpan::new(span.start.into(), (span.end - span.start).into())
 }
 
-pub(super) fn error_with_source(e: miette::Error, ps: &ParsedState) -> miette::Error {
-    e.with_source_code(NamedSource::new(
-        ps.cfg_filename.clone(),
-        ps.cfg_text.clone(),
-    ))
+/// Attaches the text of the file the error is in, which is not the merged text if the
+/// configuration includes other files.
+pub(super) fn error_with_source(mut e: CfgError, ps: &ParsedState) -> miette::Error {
+    let (name, text, start) = ps.source_at(e.err_span.map_or(0, |span| span.offset()));
+    e.err_span = e
+        .err_span
+        .map(|span| span_start_len(span.offset() - start, span.len()));
+    miette::Error::from(e).with_source_code(NamedSource::new(name, text.to_owned()))
 }
 
 impl From<anyhow::Error> for CfgError {
@@ -68,6 +75,7 @@ impl From<anyhow::Error> for CfgError {
         Self {
             err_span: None,
             help_msg: help(value.to_string()),
+            stage: None,
         }
     }
 }
@@ -0,0 +1,256 @@
+//! Lints for suspicious patterns in a parsed configuration, reported by `kanata --check`.
+//!
+//! The lints work on the keyberon layers rather than the configuration text, so aliases and
+//! templates have already been expanded. Each config layer is duplicated in the keyberon layout;
+//! the second copy, at an odd index, is inspected since it keeps transparent keys transparent
+//! instead of delegating them to defsrc.
+
+use super::*;
+
+use std::fmt;
+
+/// Hold timeouts above this delay a modifier noticeably when the hold only activates on timeout.
+const SLOW_MODIFIER_HOLD_MS: u16 = 300;
+
+type LintAction<'a> = Action<'a, &'a &'a [&'a CustomAction]>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintKind {
+    /// A layer that no action activates.
+    UnreachableLayer,
+    /// A binding at the position of the key that holds its layer, which cannot be pressed while
+    /// the layer is active.
+    ShadowedBinding,
+    /// A tap-hold whose modifier hold activates only after a long timeout.
+    SlowModifierHold,
+    /// An output key that several remapped keys of a layer are bound to.
+    DuplicateKey,
+}
+
+impl fmt::Display for LintKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LintKind::UnreachableLayer => "unreachable-layer",
+            LintKind::ShadowedBinding => "shadowed-binding",
+            LintKind::SlowModifierHold => "slow-modifier-hold",
+            LintKind::DuplicateKey => "duplicate-key",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintWarning {
+    pub kind: LintKind,
+    pub layer: String,
+    /// The defsrc key whose binding the warning is about, if it is not about the whole layer.
+    pub key: Option<OsCode>,
+    pub message: String,
+    /// Where in the configuration the warning points to. The lints only see the layers, so this
+    /// is filled in afterwards by [`ResolvedCfg::validate`].
+    pub location: Option<SourceLocation>,
+}
+
+impl fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(location) = &self.location {
+            write!(f, "{location}: ")?;
+        }
+        write!(
+            f,
+            "warning[{}]: layer {}: {}",
+            self.kind, self.layer, self.message
+        )
+    }
+}
+
+/// A position in a configuration file, displayed as `file:line:column`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+    /// Starts at 1.
+    pub line: usize,
+    /// Starts at 1 and counts characters rather than bytes.
+    pub column: usize,
+}
+
+impl SourceLocation {
+    pub fn new(file: &str, text: &str, offset: usize) -> Self {
+        let before = &text[..offset];
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        Self {
+            file: file.to_owned(),
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// Run all lints over the layers. `mapped_keys` are the keys whose bindings are inspected.
+pub fn lint<const L: usize>(
+    layers: &[[[LintAction; KEYS_IN_ROW]; LAYER_COLUMNS]; L],
+    layer_info: &[LayerInfo],
+    mapped_keys: &MappedKeys,
+) -> Vec<LintWarning> {
+    let mut keys = mapped_keys.iter().copied().collect::<Vec<_>>();
+    keys.sort_by_key(|k| *k as u16);
+    let cfg_layers = (0..layer_info.len()).step_by(2).collect::<Vec<_>>();
+    let warn = |kind, layer: usize, key, message| LintWarning {
+        kind,
+        layer: layer_info[layer].name.clone(),
+        key,
+        message,
+        location: None,
+    };
+    let mut warnings = vec![];
+
+    let mut referenced = HashSet::default();
+    for &layer in &cfg_layers {
+        for row in layers[layer + 1].iter() {
+            for action in row.iter() {
+                visit(action, &mut |a| match a {
+                    Action::Layer(l) | Action::DefaultLayer(l) => {
+                        referenced.insert(l / 2 * 2);
+                    }
+                    _ => {}
+                });
+            }
+        }
+    }
+    for &layer in cfg_layers.iter().skip(1) {
+        if !referenced.contains(&layer) {
+            warnings.push(warn(
+                LintKind::UnreachableLayer,
+                layer,
+                None,
+                "no action activates this layer".into(),
+            ));
+        }
+    }
+
+    for &layer in &cfg_layers {
+        for &key in &keys {
+            let mut held_layers = vec![];
+            visit(&layers[layer + 1][0][usize::from(key)], &mut |a| {
+                if let Action::Layer(l) = a {
+                    held_layers.push(l / 2 * 2);
+                }
+            });
+            held_layers.dedup();
+            for held in held_layers.into_iter().filter(|l| *l != layer) {
+                let shadowed = &layers[held + 1][0][usize::from(key)];
+                if !matches!(shadowed, Action::Trans | Action::NoOp | Action::Layer(_)) {
+                    warnings.push(warn(
+                        LintKind::ShadowedBinding,
+                        held,
+                        Some(key),
+                        format!(
+                            "the binding of {} cannot be used because {} holds this layer in layer {}",
+                            key_name(key),
+                            key_name(key),
+                            layer_info[layer].name
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+
+    for &layer in &cfg_layers {
+        for &key in &keys {
+            visit(&layers[layer + 1][0][usize::from(key)], &mut |a| {
+                let ht = match a {
+                    Action::HoldTap(ht) => ht,
+                    _ => return,
+                };
+                let hold_is_mod = match ht.hold {
+                    Action::KeyCode(k) => k.is_modifier(),
+                    Action::MultipleKeyCodes(ks) => ks.iter().all(|k| k.is_modifier()),
+                    _ => false,
+                };
+                if hold_is_mod
+                    && matches!(ht.config, HoldTapConfig::Default)
+                    && ht.timeout > SLOW_MODIFIER_HOLD_MS
+                {
+                    warnings.push(warn(
+                        LintKind::SlowModifierHold,
+                        layer,
+                        Some(key),
+                        format!(
+                            "the modifier held by {} only activates after {}ms, \
+                             consider a shorter timeout or tap-hold-press",
+                            key_name(key),
+                            ht.timeout
+                        ),
+                    ));
+                }
+            });
+        }
+    }
+
+    for &layer in &cfg_layers {
+        let mut outputs: Vec<(OsCode, Vec<OsCode>)> = vec![];
+        for &key in &keys {
+            let out = match layers[layer + 1][0][usize::from(key)] {
+                Action::KeyCode(k) => OsCode::from(k),
+                _ => continue,
+            };
+            if out == key {
+                continue;
+            }
+            match outputs.iter_mut().find(|(o, _)| *o == out) {
+                Some((_, srcs)) => srcs.push(key),
+                None => outputs.push((out, vec![key])),
+            }
+        }
+        for (out, srcs) in outputs.into_iter().filter(|(_, srcs)| srcs.len() > 1) {
+            warnings.push(warn(
+                LintKind::DuplicateKey,
+                layer,
+                Some(srcs[1]),
+                format!(
+                    "{} is bound to several keys: {}",
+                    key_name(out),
+                    srcs.iter()
+                        .map(|k| key_name(*k))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            ));
+        }
+    }
+
+    warnings
+}
+
+/// Call `f` on the action and on every action nested in it.
+fn visit<'a>(action: &LintAction<'a>, f: &mut impl FnMut(LintAction<'a>)) {
+    f(*action);
+    match action {
+        Action::MultipleActions(actions) => actions.iter().for_each(|a| visit(a, f)),
+        Action::HoldTap(ht) => {
+            visit(&ht.hold, f);
+            visit(&ht.tap, f);
+            visit(&ht.timeout_action, f);
+        }
+        Action::OneShot(os) => visit(os.action, f),
+        Action::TapDance(td) => td.actions.iter().for_each(|a| visit(a, f)),
+        Action::Chords(group) => group.chords.iter().for_each(|(_, a)| visit(a, f)),
+        Action::Fork(fork) => {
+            visit(&fork.left, f);
+            visit(&fork.right, f);
+        }
+        Action::OnRelease(a) => visit(a, f),
+        _ => {}
+    }
+}
+
+fn key_name(key: OsCode) -> String {
+    let name = format!("{key:?}");
+    name.strip_prefix("KEY_").unwrap_or(&name).to_lowercase()
+}
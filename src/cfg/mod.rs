@@ -39,6 +39,8 @@
 //! The specific values in example above applies to Linux, but the same logic applies to Windows.
 pub mod sexpr;
 
+pub mod document;
+
 mod alloc;
 use alloc::*;
 
@@ -48,6 +50,15 @@ pub use key_override::*;
 mod custom_tap_hold;
 use custom_tap_hold::*;
 
+mod lint;
+pub use lint::*;
+
+mod builder;
+pub use builder::*;
+
+mod suggest;
+use suggest::*;
+
 use crate::custom_action::*;
 use crate::keys::*;
 use crate::layers::*;
@@ -120,6 +131,23 @@ macro_rules! anyhow_span {
     };
 }
 
+// Declared after the macros above so that they can be used.
+mod deftest;
+pub use deftest::*;
+
+mod sim;
+pub use sim::*;
+
+mod sources;
+pub use sources::{default_cfg_paths, first_layer_name};
+use sources::*;
+
+mod secrets;
+use secrets::*;
+
+mod capabilities;
+use capabilities::*;
+
 pub type KanataAction = Action<'static, &'static &'static [&'static CustomAction]>;
 type KLayout =
     Layout<'static, KEYS_IN_ROW, 2, ACTUAL_NUM_LAYERS, &'static &'static [&'static CustomAction]>;
@@ -128,6 +156,35 @@ pub type BorrowedKLayout<'a> =
     Layout<'a, KEYS_IN_ROW, 2, ACTUAL_NUM_LAYERS, &'a &'a [&'a CustomAction]>;
 pub type KeySeqsToFKeys = Trie<Vec<u16>, (u8, u16)>;
 
+/// Coordinates of the fake keys that are tapped on events, configured by `defhooks`.
+#[derive(Debug, Default, Clone)]
+pub struct Hooks {
+    /// Tapped when a tap-hold action activates its hold action.
+    pub tap_hold_hold: Option<(u8, u16)>,
+    /// Tapped when a tap-hold action activates its tap action.
+    pub tap_hold_tap: Option<(u8, u16)>,
+    /// Tapped when the layer with the given index in the configuration becomes active.
+    pub layer_enter: HashMap<usize, (u8, u16)>,
+    /// Tapped when the layer with the given index in the configuration stops being active.
+    pub layer_exit: HashMap<usize, (u8, u16)>,
+}
+
+/// A `defsnippets` entry: typed text that is replaced by the expansion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snippet {
+    pub trigger: String,
+    pub expansion: Vec<SnippetPart>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnippetPart {
+    Text(String),
+    /// Where the cursor is left after typing the expansion.
+    Cursor,
+    Date,
+    Time,
+}
+
 pub struct KanataLayout {
     layout: KLayout,
     _allocations: Arc<Allocations>,
@@ -172,61 +229,162 @@ pub struct Cfg {
     pub sequences: KeySeqsToFKeys,
     /// Overrides defined in `defoverrides`.
     pub overrides: Overrides,
+    /// Hooks defined in `defhooks`.
+    pub hooks: Hooks,
+    /// Fake key names and their indexes in the fake key row, used to act on fake keys by name.
+    pub fake_keys: HashMap<String, usize>,
+    /// Tests defined in `deftest`, run by `kanata --check`.
+    pub tests: Vec<CfgTest>,
+    /// The names of the `deflauncher` entries and the coordinates of their fake keys.
+    pub launcher: Vec<(String, (u8, u16))>,
+    /// The modifiers of the `defmodchords` entries and the coordinates of their fake keys.
+    pub mod_chords: Vec<ModChord>,
+    /// The short codes and their replacements, from `defshortcodes`.
+    pub shortcodes: HashMap<String, String>,
+    pub snippets: Vec<Snippet>,
+    /// The mouse acceleration of keyberon layers, from `defmouseaccel`.
+    pub mouse_accel_layers: Vec<(usize, MouseAccel)>,
+    /// The layer tags that follow the presence of input devices, from `defdevicetags`.
+    pub device_tags: Vec<DeviceTags>,
+    /// The output modifiers of keyberon layers and the keys that replace them, from
+    /// `defmodtranslation`.
+    pub mod_translations: Vec<(usize, Vec<(OsCode, OsCode)>)>,
+    /// The helper processes that kanata runs alongside itself, from `defhelpers`.
+    pub helpers: Vec<HelperCmd>,
+    /// The operators of the keyberon layers of modal editing, from `defmodal`.
+    pub modal_layers: Vec<(usize, Vec<ModalOperator>)>,
 }
 
-/// Parse a new configuration from a file.
+/// Parse a new configuration from a file, running every stage of [`CfgBuilder`].
 pub fn new_from_file(p: &std::path::Path) -> MResult<Cfg> {
-    let (items, mapped_keys, layer_info, key_outputs, layout, sequences, overrides) = parse_cfg(p)?;
+    let cfg = CfgBuilder::from_file(p)?
+        .parse()?
+        .resolve()?
+        .validate()
+        .freeze();
     log::info!("config parsed");
-    Ok(Cfg {
-        items,
-        mapped_keys,
-        layer_info,
-        key_outputs,
-        layout,
-        sequences,
-        overrides,
-    })
+    Ok(cfg)
+}
+
+/// Parse a new configuration from a file with the items of an overlay file appended, so that the
+/// layers of the overlay come after the layers of the file. See [`append_overlay`].
+pub fn new_from_file_with_overlay(p: &std::path::Path, overlay: &std::path::Path) -> MResult<Cfg> {
+    let read = |p: &std::path::Path| {
+        std::fs::read_to_string(p)
+            .map_err(|e| miette::miette!("Failed to read {}: {e}", p.display()))
+    };
+    let text = append_overlay(&read(p)?, &read(overlay)?);
+    let cfg = CfgBuilder::from_text(p.to_string_lossy().to_string(), text)
+        .parse()?
+        .resolve()?
+        .validate()
+        .freeze();
+    log::info!("config parsed with overlay {}", overlay.display());
+    Ok(cfg)
 }
 
 pub type MappedKeys = HashSet<OsCode>;
-// Note: this uses a Vec inside the HashMap instead of a HashSet because ordering matters, e.g. for
-// chords like `S-b`, we want to ensure that `b` is checked first because key repeat for `b` is
-// useful while it is not useful for shift. The outputs should be iterated over in reverse order.
-pub type KeyOutputs = Vec<HashMap<OsCode, Vec<OsCode>>>;
+
+/// The modifiers of a `defmodchords` entry and the coordinates of its fake key.
+pub type ModChord = (Vec<OsCode>, (u8, u16));
+
+/// A `defdevicetags` entry: the name of an input device and the layer tags it turns on or off,
+/// where `true` turns the tag on while the device is present and `false` while it is absent.
+pub type DeviceTags = (String, Vec<(String, bool)>);
+
+/// A `defhelpers` entry: the name of a helper process and its program and arguments.
+pub type HelperCmd = (String, Vec<String>);
+
+/// An operator of a `defmodal` layer: the key that starts it, the chord that is tapped once its
+/// motion selected the text, and the keyberon layer to switch to afterwards, if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModalOperator {
+    pub key: OsCode,
+    pub chord: Vec<OsCode>,
+    pub then_layer: Option<usize>,
+}
+// Note: this uses a Vec for the outputs of a key instead of a HashSet because ordering matters,
+// e.g. for chords like `S-b`, we want to ensure that `b` is checked first because key repeat for
+// `b` is useful while it is not useful for shift. The outputs should be iterated over in reverse
+// order.
+pub type KeyOutputs = Vec<LayerKeyOutputs>;
+
+/// The outputs of the keys of one layer, sorted by key. Only the keys with outputs are stored, so
+/// a sorted Vec is smaller than a map and iterates in a deterministic order.
+#[derive(Debug, Default)]
+pub struct LayerKeyOutputs {
+    outputs: Vec<(OsCode, Vec<OsCode>)>,
+}
+
+impl LayerKeyOutputs {
+    pub fn get(&self, key: &OsCode) -> Option<&Vec<OsCode>> {
+        self.outputs
+            .binary_search_by_key(&(*key as u16), |(k, _)| *k as u16)
+            .ok()
+            .map(|i| &self.outputs[i].1)
+    }
+
+    /// Iterates on the keys and their outputs, sorted by key.
+    pub fn iter(&self) -> impl Iterator<Item = (OsCode, &[OsCode])> {
+        self.outputs.iter().map(|(k, outs)| (*k, outs.as_slice()))
+    }
+}
+
+impl From<HashMap<OsCode, Vec<OsCode>>> for LayerKeyOutputs {
+    fn from(outputs: HashMap<OsCode, Vec<OsCode>>) -> Self {
+        let mut outputs = outputs
+            .into_iter()
+            .map(|(k, mut outs)| {
+                outs.shrink_to_fit();
+                (k, outs)
+            })
+            .collect::<Vec<_>>();
+        outputs.sort_unstable_by_key(|(k, _)| *k as u16);
+        Self { outputs }
+    }
+}
 
 #[derive(Debug)]
 pub struct LayerInfo {
     pub name: String,
     pub cfg_text: String,
+    /// Sounds played while this layer is active, configured by `defsounds`.
+    pub sounds: Option<SoundProfile>,
+    /// Tags for turning groups of layers off and on, configured by `deflayertags`.
+    pub tags: Vec<String>,
+    /// Fields sent to TCP clients when this layer is activated or deactivated, configured by
+    /// `deflayernotify`.
+    pub notify: Vec<(String, String)>,
+    /// Descriptions of the layer and of its keys, configured by `deflayerdoc`.
+    pub doc: LayerDoc,
 }
 
-#[allow(clippy::type_complexity)] // return type is not pub
-fn parse_cfg(
-    p: &std::path::Path,
-) -> MResult<(
-    HashMap<String, String>,
-    MappedKeys,
-    Vec<LayerInfo>,
-    KeyOutputs,
-    KanataLayout,
-    KeySeqsToFKeys,
-    Overrides,
-)> {
-    let mut s = ParsedState::default();
-    let (cfg, src, layer_info, klayers, seqs, overrides) = match parse_cfg_raw(p, &mut s) {
-        Ok(v) => v,
-        Err(e) => return Err(error_with_source(e.into(), &s)),
-    };
-    Ok((
-        cfg,
-        src,
-        layer_info,
-        create_key_outputs(&klayers, &overrides),
-        create_layout(klayers, s.a),
-        seqs,
-        overrides,
-    ))
+/// Descriptions of a layer and of the actions of its keys, configured by `deflayerdoc`.
+#[derive(Debug, Clone, Default)]
+pub struct LayerDoc {
+    pub description: Option<String>,
+    /// The descriptions of the keys by their defsrc key.
+    pub keys: Vec<(OsCode, String)>,
+}
+
+impl LayerDoc {
+    pub fn key(&self, key: OsCode) -> Option<&str> {
+        self.keys
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, description)| description.as_str())
+    }
+}
+
+/// Sound files to play for events, configured by `defsounds`.
+#[derive(Debug, Clone, Default)]
+pub struct SoundProfile {
+    pub press: Option<String>,
+    pub release: Option<String>,
+    /// Played when this layer becomes the active layer.
+    pub layer_change: Option<String>,
+    pub tap: Option<String>,
+    pub hold: Option<String>,
 }
 
 #[cfg(all(not(feature = "interception_driver"), target_os = "windows"))]
@@ -235,7 +393,10 @@ const DEF_LOCAL_KEYS: &str = "deflocalkeys-win";
 const DEF_LOCAL_KEYS: &str = "deflocalkeys-wintercept";
 #[cfg(target_os = "linux")]
 const DEF_LOCAL_KEYS: &str = "deflocalkeys-linux";
+const DEF_EXTRA_KEYS: &str = "defextrakeys";
+const DEF_MATRIX: &str = "defmatrix";
 
+#[cfg(test)]
 #[allow(clippy::type_complexity)] // return type is not pub
 fn parse_cfg_raw(
     p: &std::path::Path,
@@ -247,6 +408,7 @@ fn parse_cfg_raw(
     Box<KanataLayers>,
     KeySeqsToFKeys,
     Overrides,
+    Hooks,
 )> {
     let text = std::fs::read_to_string(p).map_err(|e| anyhow!("{e}"))?;
     s.cfg_filename = p.to_string_lossy().to_string();
@@ -254,6 +416,7 @@ fn parse_cfg_raw(
     parse_cfg_raw_string(text, s)
 }
 
+#[cfg(test)]
 #[allow(clippy::type_complexity)] // return type is not pub
 fn parse_cfg_raw_string(
     text: String,
@@ -265,16 +428,40 @@ fn parse_cfg_raw_string(
     Box<KanataLayers>,
     KeySeqsToFKeys,
     Overrides,
+    Hooks,
 )> {
-    let spanned_root_exprs = sexpr::parse(&text).map_err(|(help_msg, start, len)| CfgError {
+    let (cfg, spanned_root_exprs) = parse_cfg_exprs(&text, &[], &HashMap::default())?;
+    resolve_cfg_exprs(&text, cfg, spanned_root_exprs, s)
+}
+
+type SpannedRootExprs = Vec<Spanned<Vec<SExpr>>>;
+
+/// The parse stage of reading a configuration. This reads the s-expressions, checks the
+/// capabilities that it needs, expands the items that generate other items, and parses the items
+/// that affect how the rest is read: `defcfg` and `deflocalkeys`. `includes` are the files merged
+/// into `text`, see [`expand_includes`], and `secrets` are the values of `${secret:name}`, see
+/// [`load_secrets`].
+fn parse_cfg_exprs(
+    text: &str,
+    includes: &[IncludedSource],
+    secrets: &HashMap<String, String>,
+) -> Result<(HashMap<String, String>, SpannedRootExprs)> {
+    let spanned_root_exprs = sexpr::parse(text).map_err(|(help_msg, start, len)| CfgError {
         err_span: Some(span_start_len(start, len)),
         help_msg,
+        stage: None,
     })?;
-
-    let root_exprs: Vec<_> = spanned_root_exprs.iter().map(|t| t.t.clone()).collect();
+    let mut spanned_root_exprs = expand_capabilities(spanned_root_exprs)?;
+    namespace_included_aliases(&mut spanned_root_exprs, includes);
 
     error_on_unknown_top_level_atoms(&spanned_root_exprs)?;
 
+    let spanned_root_exprs = interpolate_env_vars(spanned_root_exprs, secrets)?;
+
+    let spanned_root_exprs = expand_mirror_layers(spanned_root_exprs)?;
+    let spanned_root_exprs = expand_extended_layers(spanned_root_exprs)?;
+    let root_exprs: Vec<_> = spanned_root_exprs.iter().map(|t| t.t.clone()).collect();
+
     let cfg = root_exprs
         .iter()
         .find(gen_first_atom_filter("defcfg"))
@@ -292,24 +479,64 @@ fn parse_cfg_raw_string(
         )
     }
 
-    if let Some(result) = root_exprs
+    let local_keys = root_exprs
         .iter()
-        .find(gen_first_atom_filter(DEF_LOCAL_KEYS))
-        .map(|custom_keys| parse_deflocalkeys(custom_keys))
-    {
-        result?;
-    }
-    if let Some(spanned) = spanned_root_exprs
+        .find(gen_first_atom_filter(DEF_LOCAL_KEYS));
+    let extra_keys = root_exprs
         .iter()
-        .filter(gen_first_atom_filter_spanned(DEF_LOCAL_KEYS))
-        .nth(1)
-    {
-        bail_span!(
-            spanned,
-            "Only one {DEF_LOCAL_KEYS} is allowed, found more. Delete the extras."
-        )
+        .find(gen_first_atom_filter(DEF_EXTRA_KEYS));
+    let matrix = root_exprs.iter().find(gen_first_atom_filter(DEF_MATRIX));
+    if local_keys.is_some() || extra_keys.is_some() || matrix.is_some() {
+        clear_custom_str_oscode_mapping();
+        let mut custom_keys = HashMap::default();
+        if let Some(expr) = local_keys {
+            parse_deflocalkeys(expr, &mut custom_keys)?;
+        }
+        if let Some(expr) = extra_keys {
+            parse_defextrakeys(expr, &mut custom_keys)?;
+        }
+        replace_custom_str_oscode_mapping(&custom_keys);
+        // The keys of the matrix can be the names defined above.
+        if let Some(expr) = matrix {
+            parse_defmatrix(expr, &mut custom_keys)?;
+            replace_custom_str_oscode_mapping(&custom_keys);
+        }
+    }
+    for item in [DEF_LOCAL_KEYS, DEF_EXTRA_KEYS, DEF_MATRIX] {
+        if let Some(spanned) = spanned_root_exprs
+            .iter()
+            .filter(gen_first_atom_filter_spanned(item))
+            .nth(1)
+        {
+            bail_span!(
+                spanned,
+                "Only one {item} is allowed, found more. Delete the extras."
+            )
+        }
     }
 
+    Ok((cfg, spanned_root_exprs))
+}
+
+/// The resolve stage of reading a configuration. This resolves variables, aliases and all other
+/// items into the keyberon layers and the associated metadata.
+#[allow(clippy::type_complexity)] // return type is not pub
+fn resolve_cfg_exprs(
+    text: &str,
+    cfg: HashMap<String, String>,
+    spanned_root_exprs: SpannedRootExprs,
+    s: &mut ParsedState,
+) -> Result<(
+    HashMap<String, String>,
+    MappedKeys,
+    Vec<LayerInfo>,
+    Box<KanataLayers>,
+    KeySeqsToFKeys,
+    Overrides,
+    Hooks,
+)> {
+    let root_exprs: Vec<_> = spanned_root_exprs.iter().map(|t| t.t.clone()).collect();
+
     let src_expr = root_exprs
         .iter()
         .find(gen_first_atom_filter("defsrc"))
@@ -324,6 +551,15 @@ fn parse_cfg_raw_string(
             "Exactly one defsrc is allowed, found more. Delete the extras."
         )
     }
+    let alt_exprs = spanned_root_exprs
+        .iter()
+        .filter(gen_first_atom_filter_spanned("defsrcalt"))
+        .collect::<Vec<_>>();
+    let src_layout = parse_src_layouts(src_expr, &alt_exprs, &cfg)?;
+    let src_expr = match &src_layout {
+        Some((src_expr, _)) => src_expr,
+        None => src_expr,
+    };
     let (src, mapping_order) = parse_defsrc(src_expr, &cfg)?;
 
     let deflayer_filter = gen_first_atom_filter("deflayer");
@@ -348,7 +584,13 @@ fn parse_cfg_raw_string(
         )
     }
 
-    let layer_idxs = parse_layer_indexes(&layer_exprs, mapping_order.len())?;
+    let layer_idxs = parse_layer_indexes(
+        &layer_exprs,
+        match &src_layout {
+            Some((_, positions)) => positions.len(),
+            None => mapping_order.len(),
+        },
+    )?;
     let mut sorted_idxs: Vec<(&String, &usize)> =
         layer_idxs.iter().map(|tuple| (tuple.0, tuple.1)).collect();
 
@@ -378,19 +620,69 @@ fn parse_cfg_raw_string(
         })
         .collect::<Vec<_>>();
 
-    let layer_info: Vec<LayerInfo> = layer_names
+    let mut layer_info: Vec<LayerInfo> = layer_names
         .into_iter()
         .zip(layer_strings)
-        .map(|(name, cfg_text)| LayerInfo { name, cfg_text })
+        .map(|(name, cfg_text)| LayerInfo {
+            name,
+            cfg_text,
+            sounds: None,
+            tags: vec![],
+            notify: vec![],
+            doc: LayerDoc::default(),
+        })
         .collect();
 
+    let sound_exprs = spanned_root_exprs
+        .iter()
+        .filter(gen_first_atom_filter_spanned("defsounds"))
+        .collect::<Vec<_>>();
+    parse_sound_profiles(&sound_exprs, &mut layer_info)?;
+
+    let layer_tag_exprs = spanned_root_exprs
+        .iter()
+        .filter(gen_first_atom_filter_spanned("deflayertags"))
+        .collect::<Vec<_>>();
+    parse_layer_tags(&layer_tag_exprs, &mut layer_info)?;
+
+    let layer_notify_exprs = spanned_root_exprs
+        .iter()
+        .filter(gen_first_atom_filter_spanned("deflayernotify"))
+        .collect::<Vec<_>>();
+    parse_layer_notify(&layer_notify_exprs, &mut layer_info)?;
+
+    let layer_doc_exprs = spanned_root_exprs
+        .iter()
+        .filter(gen_first_atom_filter_spanned("deflayerdoc"))
+        .collect::<Vec<_>>();
+    parse_layer_docs(&layer_doc_exprs, &mapping_order, &mut layer_info)?;
+
+    let device_tag_exprs = spanned_root_exprs
+        .iter()
+        .filter(gen_first_atom_filter_spanned("defdevicetags"))
+        .collect::<Vec<_>>();
+    let device_tags = parse_device_tags(&device_tag_exprs, &layer_info)?;
+
     let defsrc_layer = parse_defsrc_layer(src_expr, &mapping_order, s);
 
-    let layer_exprs = root_exprs
+    let mut layer_exprs = root_exprs
         .iter()
         .filter(&deflayer_filter)
         .cloned()
         .collect::<Vec<_>>();
+    if let Some((_, positions)) = &src_layout {
+        // Drop the layer items of the positions that the physical layout does not have, so that
+        // the layers line up with its keys.
+        for layer in layer_exprs.iter_mut() {
+            let items = layer.split_off(2);
+            layer.extend(
+                items
+                    .into_iter()
+                    .zip(positions)
+                    .filter_map(|(item, present)| present.then_some(item)),
+            );
+        }
+    }
 
     *s = ParsedState {
         a: s.a.clone(),
@@ -400,6 +692,8 @@ fn parse_cfg_raw_string(
         defsrc_layer,
         cfg_filename: s.cfg_filename.clone(),
         cfg_text: s.cfg_text.clone(),
+        includes: s.includes.clone(),
+        device_tags,
         is_cmd_enabled: {
             #[cfg(feature = "cmd")]
             {
@@ -433,12 +727,68 @@ fn parse_cfg_raw_string(
         .collect::<Vec<_>>();
     parse_chord_groups(&chords_exprs, s)?;
 
+    let dead_keys_exprs = root_exprs
+        .iter()
+        .filter(gen_first_atom_filter("defdeadkeys"))
+        .collect::<Vec<_>>();
+    parse_dead_keys(&dead_keys_exprs, s)?;
+
+    let hands_exprs = spanned_root_exprs
+        .iter()
+        .filter(gen_first_atom_filter_spanned("defhands"))
+        .collect::<Vec<_>>();
+    parse_hands(&hands_exprs, s)?;
+
     let fake_keys_exprs = root_exprs
         .iter()
         .filter(gen_first_atom_filter("deffakekeys"))
         .collect::<Vec<_>>();
     parse_fake_keys(&fake_keys_exprs, s)?;
 
+    let hook_exprs = spanned_root_exprs
+        .iter()
+        .filter(gen_first_atom_filter_spanned("defhooks"))
+        .collect::<Vec<_>>();
+    let hooks = parse_hooks(&hook_exprs, s)?;
+
+    let launcher_exprs = spanned_root_exprs
+        .iter()
+        .filter(gen_first_atom_filter_spanned("deflauncher"))
+        .collect::<Vec<_>>();
+    s.launcher = parse_launcher(&launcher_exprs, s)?;
+
+    let mod_chord_exprs = spanned_root_exprs
+        .iter()
+        .filter(gen_first_atom_filter_spanned("defmodchords"))
+        .collect::<Vec<_>>();
+    s.mod_chords = parse_mod_chords(&mod_chord_exprs, s)?;
+
+    let shortcode_exprs = spanned_root_exprs
+        .iter()
+        .filter(gen_first_atom_filter_spanned("defshortcodes"))
+        .collect::<Vec<_>>();
+    s.shortcodes = parse_shortcodes(&shortcode_exprs, s)?;
+
+    let snippet_exprs = spanned_root_exprs
+        .iter()
+        .filter(gen_first_atom_filter_spanned("defsnippets"))
+        .collect::<Vec<_>>();
+    s.snippets = parse_snippets(&snippet_exprs, s)?;
+    let output_history = cfg
+        .get("output-history")
+        .map(|s| matches!(s.to_lowercase().as_str(), "yes" | "true"))
+        .unwrap_or_default();
+    if let (false, Some(expr)) = (
+        output_history,
+        shortcode_exprs.first().or(snippet_exprs.first()),
+    ) {
+        bail_span!(
+            expr,
+            "{} needs the output history, which is only recorded with `output-history yes` in defcfg",
+            expr.t[0].atom(None).unwrap_or_default()
+        )
+    }
+
     let sequence_exprs = root_exprs
         .iter()
         .filter(gen_first_atom_filter("defseq"))
@@ -453,6 +803,42 @@ fn parse_cfg_raw_string(
 
     let mut klayers = parse_layers(s)?;
 
+    let test_exprs = spanned_root_exprs
+        .iter()
+        .filter(gen_first_atom_filter_spanned("deftest"))
+        .collect::<Vec<_>>();
+    s.tests = parse_deftests(&test_exprs, s)?;
+
+    let condition_exprs = spanned_root_exprs
+        .iter()
+        .filter(gen_first_atom_filter_spanned("deflayercondition"))
+        .collect::<Vec<_>>();
+    s.layer_conditions = parse_layer_conditions(&condition_exprs, s)?;
+
+    let mouse_accel_exprs = spanned_root_exprs
+        .iter()
+        .filter(gen_first_atom_filter_spanned("defmouseaccel"))
+        .collect::<Vec<_>>();
+    s.mouse_accel_layers = parse_mouse_accel_layers(&mouse_accel_exprs, s)?;
+
+    let mod_translation_exprs = spanned_root_exprs
+        .iter()
+        .filter(gen_first_atom_filter_spanned("defmodtranslation"))
+        .collect::<Vec<_>>();
+    s.mod_translations = parse_mod_translations(&mod_translation_exprs, s)?;
+
+    let helper_exprs = spanned_root_exprs
+        .iter()
+        .filter(gen_first_atom_filter_spanned("defhelpers"))
+        .collect::<Vec<_>>();
+    s.helpers = parse_helpers(&helper_exprs, s)?;
+
+    let modal_exprs = spanned_root_exprs
+        .iter()
+        .filter(gen_first_atom_filter_spanned("defmodal"))
+        .collect::<Vec<_>>();
+    s.modal_layers = parse_modal_layers(&modal_exprs, s)?;
+
     resolve_chord_groups(&mut klayers, s)?;
 
     let override_exprs = root_exprs
@@ -475,7 +861,7 @@ fn parse_cfg_raw_string(
         }
     };
 
-    Ok((cfg, src, layer_info, klayers, sequences, overrides))
+    Ok((cfg, src, layer_info, klayers, sequences, overrides, hooks))
 }
 
 fn error_on_unknown_top_level_atoms(exprs: &[Spanned<Vec<SExpr>>]) -> Result<()> {
@@ -499,10 +885,35 @@ fn error_on_unknown_top_level_atoms(exprs: &[Spanned<Vec<SExpr>>]) -> Result<()>
                 | "deflocalkeys-linux"
                 | "deflocalkeys-win"
                 | "deflocalkeys-wintercept"
+                | "defextrakeys"
+                | "defmatrix"
+                | "include"
                 | "deffakekeys"
                 | "defchords"
                 | "defvar"
-                | "defseq" => Ok(()),
+                | "defseq"
+                | "defdeadkeys"
+                | "defmirror"
+                | "deflayerextends"
+                | "defsounds"
+                | "deflayertags"
+                | "deflayernotify"
+                | "deflayerdoc"
+                | "defdevicetags"
+                | "defhooks"
+                | "defhands"
+                | "defsrcalt"
+                | "deflayercondition"
+                | "defmouseaccel"
+                | "defmodtranslation"
+                | "deflauncher"
+                | "defmodchords"
+                | "defshortcodes"
+                | "defsnippets"
+                | "defsecrets"
+                | "defhelpers"
+                | "defmodal"
+                | "deftest" => Ok(()),
                 _ => bail_span!(expr, "Found unknown configuration item"),
             })
             .ok_or_else(|| {
@@ -584,15 +995,111 @@ fn check_first_expr<'a>(
 }
 
 /// Parse configuration entries from an expression starting with defcfg.
+/// The items that are read from defcfg. Options of other operating systems are included, since
+/// they are ignored rather than unknown.
+const DEFCFG_ITEMS: &[&str] = &[
+    "strict-cfg",
+    "process-unmapped-keys",
+    "src-layout",
+    "danger-enable-cmd",
+    "sequence-timeout",
+    "sequence-input-mode",
+    "log-layer-changes",
+    "log-layer-stack",
+    "log-filter",
+    "crash-dump-dir",
+    "exit-combo",
+    "exit-combo-hold",
+    "defer-layer-changes",
+    "persist-state-file",
+    "usage-stats-file",
+    "key-counts-file",
+    "openrgb-server",
+    "openrgb-layer-colors",
+    "layer-display-device",
+    "layer-display-format",
+    "layer-display-sync",
+    "serial-input-device",
+    "serial-input-keys",
+    "kvm-targets",
+    "kvm-listen",
+    "kvm-key",
+    "kvm-tls-cert",
+    "kvm-tls-key",
+    "output-layer-targets",
+    "pointer-regions",
+    "pointer-region-layers",
+    "pointer-region-poll",
+    "game-mode-layers",
+    "language-layers",
+    "dwell-click-time",
+    "slow-keys-delay",
+    "bounce-keys-delay",
+    "key-repeat",
+    "event-stage-priority",
+    "key-transform-input",
+    "key-transform-output",
+    "suppress-modifier-taps",
+    "modifier-tap-mask",
+    "guard-chords",
+    "guard-layers",
+    "guard-confirm",
+    "kiosk-allow",
+    "kiosk-layers",
+    "count-layers",
+    "multi-press-timeout",
+    "startup-layers",
+    "output-history",
+    "output-rate-limit",
+    "output-rate-burst",
+    "output-queue-rate",
+    "output-interactive-yield",
+    "check-for-updates",
+    "linux-dev",
+    "linux-continue-if-no-devs-found",
+    "linux-compose-on-top",
+    "linux-unicode-u-code",
+    "linux-unicode-termination",
+    "linux-output-device-name",
+    "linux-output-device-vendor-id",
+    "linux-output-device-product-id",
+    "linux-output-device-capabilities",
+    "linux-output-split-mouse",
+    "linux-output-absolute-pointer",
+    "linux-output-backend",
+    "linux-output-wayland-xkb-layout",
+    "linux-output-ble-adapter",
+    "linux-output-secondary-device-name",
+    "linux-strip-events",
+    "linux-ime-detect",
+    "linux-ime-passthrough-layers",
+    "linux-led-layers",
+    "linux-pause-layers-on-lock",
+    "linux-pointer-layer-devices",
+    "linux-pointer-layer",
+    "linux-pointer-layer-linger",
+    "linux-scancode-keys",
+    "linux-session-aware",
+    "linux-session-id",
+    "linux-xkb-layout",
+    "windows-altgr",
+    "windows-interception-mouse-hwid",
+];
+
 fn parse_defcfg(expr: &[SExpr]) -> Result<HashMap<String, String>> {
     let mut cfg = HashMap::default();
+    let mut key_exprs = vec![];
     let mut exprs = check_first_expr(expr.iter(), "defcfg")?;
     // Read k-v pairs from the configuration
     loop {
         let key = match exprs.next() {
             Some(k) => k,
-            None => return Ok(cfg),
+            None => {
+                check_defcfg_items(&cfg, &key_exprs)?;
+                return Ok(cfg);
+            }
         };
+        key_exprs.push(key);
         let val = match exprs.next() {
             Some(v) => v,
             None => bail_expr!(key, "Found a defcfg key missing a value"),
@@ -619,12 +1126,33 @@ fn parse_defcfg(expr: &[SExpr]) -> Result<HashMap<String, String>> {
     }
 }
 
-/// Parse custom keys from an expression starting with deflocalkeys. Statefully updates the `keys`
-/// module using the custom keys parsed.
-fn parse_deflocalkeys(expr: &[SExpr]) -> Result<()> {
-    let mut cfg = HashMap::default();
+/// Report unknown defcfg items, which are most likely typos. They are errors if `strict-cfg` is
+/// enabled and warnings otherwise.
+fn check_defcfg_items(cfg: &HashMap<String, String>, key_exprs: &[&SExpr]) -> Result<()> {
+    let strict = cfg
+        .get("strict-cfg")
+        .map(|s| matches!(s.to_lowercase().as_str(), "yes" | "true"))
+        .unwrap_or_default();
+    for key_expr in key_exprs {
+        let key = key_expr.atom(None).expect("atom").trim_matches('"');
+        if DEFCFG_ITEMS.contains(&key) {
+            continue;
+        }
+        let help = match did_you_mean(key, DEFCFG_ITEMS.iter().copied()) {
+            Some(item) => format!("\nDid you mean: {item}"),
+            None => String::new(),
+        };
+        if strict {
+            bail_expr!(key_expr, "Unknown defcfg item {key}{help}");
+        }
+        log::warn!("Unknown defcfg item {key} is ignored{help}");
+    }
+    Ok(())
+}
+
+/// Parse custom keys from an expression starting with deflocalkeys into `cfg`.
+fn parse_deflocalkeys(expr: &[SExpr], cfg: &mut HashMap<String, OsCode>) -> Result<()> {
     let mut exprs = check_first_expr(expr.iter(), DEF_LOCAL_KEYS)?;
-    clear_custom_str_oscode_mapping();
     // Read k-v pairs from the configuration
     while let Some(key_expr) = exprs.next() {
         let key = key_expr
@@ -655,7 +1183,69 @@ fn parse_deflocalkeys(expr: &[SExpr]) -> Result<()> {
         log::debug!("custom mapping: {key} {}", osc.as_u16());
         cfg.insert(key.to_owned(), osc);
     }
-    replace_custom_str_oscode_mapping(&cfg);
+    Ok(())
+}
+
+/// Parse the key names of defextrakeys into `keys`, giving each name the next code of
+/// `EXTRA_KEY_CODES` that `deflocalkeys` did not take.
+fn parse_defextrakeys(expr: &[SExpr], keys: &mut HashMap<String, OsCode>) -> Result<()> {
+    let exprs = check_first_expr(expr.iter(), DEF_EXTRA_KEYS)?;
+    let mut codes = EXTRA_KEY_CODES
+        .filter(|code| !keys.values().any(|osc| osc.as_u16() == *code))
+        .collect::<Vec<_>>()
+        .into_iter();
+    for name_expr in exprs {
+        let name = name_expr
+            .atom(None)
+            .ok_or_else(|| anyhow_expr!(name_expr, "No lists are allowed in {DEF_EXTRA_KEYS}"))?;
+        if str_to_oscode(name).is_some() {
+            bail_expr!(
+                name_expr,
+                "Cannot use {name} in {DEF_EXTRA_KEYS} because it is a default key name"
+            );
+        } else if keys.contains_key(name) {
+            bail_expr!(
+                name_expr,
+                "Duplicate {name} found in {DEF_EXTRA_KEYS} or {DEF_LOCAL_KEYS}"
+            );
+        }
+        let Some(code) = codes.next() else {
+            bail_expr!(
+                name_expr,
+                "Too many keys in {DEF_EXTRA_KEYS}, at most {} are available",
+                EXTRA_KEY_CODES.count()
+            );
+        };
+        let osc = OsCode::from_u16(code).expect("extra key codes are valid");
+        log::debug!("extra key: {name} {code}");
+        keys.insert(name.to_owned(), osc);
+    }
+    Ok(())
+}
+
+/// Parse the rows of defmatrix into `keys`, naming the key at each position `r<row>c<column>`,
+/// counted from 0 like in keyboard firmware. `_` is a position without a key.
+fn parse_defmatrix(expr: &[SExpr], keys: &mut HashMap<String, OsCode>) -> Result<()> {
+    let exprs = check_first_expr(expr.iter(), DEF_MATRIX)?;
+    for (row, row_expr) in exprs.enumerate() {
+        let cols = row_expr.list(None).ok_or_else(|| {
+            anyhow_expr!(
+                row_expr,
+                "Each row of {DEF_MATRIX} must be a list of key names"
+            )
+        })?;
+        for (col, key_expr) in cols.iter().enumerate() {
+            let key = key_expr.atom(None).ok_or_else(|| {
+                anyhow_expr!(key_expr, "No lists are allowed in a {DEF_MATRIX} row")
+            })?;
+            if key == "_" {
+                continue;
+            }
+            let osc = str_to_oscode(key)
+                .ok_or_else(|| anyhow_expr!(key_expr, "Unknown key in {DEF_MATRIX}: {key}"))?;
+            keys.insert(format!("r{row}c{col}"), osc);
+        }
+    }
     Ok(())
 }
 
@@ -674,8 +1264,9 @@ fn parse_defsrc(
             SExpr::Atom(a) => &a.t,
             _ => bail_expr!(expr, "No lists allowed in defsrc"),
         };
-        let oscode = str_to_oscode(s)
-            .ok_or_else(|| anyhow_expr!(expr, "Unknown key in defsrc: \"{}\"", s))?;
+        let oscode = str_to_oscode(s).ok_or_else(|| {
+            anyhow_expr!(expr, "Unknown key in defsrc: \"{}\"{}", s, key_name_help(s))
+        })?;
         if mkeys.contains(&oscode) {
             bail_expr!(expr, "Repeat declaration of key in defsrc: \"{}\"", s)
         }
@@ -700,9 +1291,734 @@ fn parse_defsrc(
             }
         }
     }
-
-    mkeys.shrink_to_fit();
-    Ok((mkeys, ordered_codes))
+
+    mkeys.shrink_to_fit();
+    Ok((mkeys, ordered_codes))
+}
+
+/// Parse the `(defsrcalt <name> <key>...)` items, alternative physical layouts for the positions
+/// of defsrc, and return the one selected by the defcfg item `src-layout`. A `_` marks a position
+/// that the physical layout does not have.
+///
+/// The selected layout is returned as a defsrc expression with its keys, along with whether each
+/// position of defsrc is present.
+fn parse_src_layouts(
+    src_expr: &[SExpr],
+    alt_exprs: &[&Spanned<Vec<SExpr>>],
+    defcfg: &HashMap<String, String>,
+) -> Result<Option<(Vec<SExpr>, Vec<bool>)>> {
+    if alt_exprs.is_empty() && !defcfg.contains_key("src-layout") {
+        return Ok(None);
+    }
+    // The keys of defsrc still name the positions, so they must be valid as well.
+    parse_defsrc(src_expr, defcfg)?;
+    let src_len = src_expr.len() - 1;
+    let mut names = HashSet::default();
+    let mut selected = None;
+    for expr in alt_exprs {
+        let name = match expr.t.get(1).and_then(|e| e.atom(None)) {
+            Some(name) => name,
+            None => bail_span!(
+                expr,
+                "defsrcalt expects a name followed by the keys of defsrc"
+            ),
+        };
+        if !names.insert(name) {
+            bail_span!(expr, "Duplicate defsrcalt name: {name}");
+        }
+        let keys = &expr.t[2..];
+        if keys.len() != src_len {
+            bail_span!(
+                expr,
+                "defsrcalt {name} has {} item(s), but requires {src_len} to match defsrc",
+                keys.len()
+            );
+        }
+        let positions = keys
+            .iter()
+            .map(|key| key.atom(None) != Some("_"))
+            .collect::<Vec<_>>();
+        let mut alt_src = vec![src_expr[0].clone()];
+        alt_src.extend(
+            keys.iter()
+                .filter(|key| key.atom(None) != Some("_"))
+                .cloned(),
+        );
+        // Check the keys of every layout, not only the selected one.
+        parse_defsrc(&alt_src, defcfg)?;
+        if defcfg.get("src-layout").map(String::as_str) == Some(name) {
+            selected = Some((alt_src, positions));
+        }
+    }
+    match defcfg.get("src-layout") {
+        Some(name) if selected.is_none() => bail!("src-layout {name} has no defsrcalt"),
+        _ => Ok(selected),
+    }
+}
+
+/// Key pairs that are swapped by `defmirror`, mirroring the left and right halves of a QWERTY
+/// keyboard around the line between `g` and `h`.
+const MIRROR_PAIRS: &[(&str, &str)] = &[
+    ("1", "0"),
+    ("2", "9"),
+    ("3", "8"),
+    ("4", "7"),
+    ("5", "6"),
+    ("q", "p"),
+    ("w", "o"),
+    ("e", "i"),
+    ("r", "u"),
+    ("t", "y"),
+    ("a", "scln"),
+    ("s", "l"),
+    ("d", "k"),
+    ("f", "j"),
+    ("g", "h"),
+    ("z", "/"),
+    ("x", "."),
+    ("c", "comm"),
+    ("v", "m"),
+    ("b", "n"),
+    ("tab", "bspc"),
+    ("caps", "ret"),
+];
+
+/// Replace every `${NAME}` inside of atoms with the value of the environment variable `NAME`, so
+/// that device paths, commands and other strings can differ between machines sharing a
+/// configuration. `$${` is kept as a literal `${`. `${secret:NAME}` is replaced with the secret
+/// `NAME` from the `defsecrets` file instead.
+fn interpolate_env_vars(
+    exprs: Vec<Spanned<Vec<SExpr>>>,
+    secrets: &HashMap<String, String>,
+) -> Result<Vec<Spanned<Vec<SExpr>>>> {
+    let lookup = |name: &str| match name.strip_prefix(SECRET_PREFIX) {
+        Some(name) => secrets.get(name).cloned(),
+        None => std::env::var(name).ok(),
+    };
+    fn interpolate_expr(expr: SExpr, lookup: &impl Fn(&str) -> Option<String>) -> Result<SExpr> {
+        Ok(match expr {
+            SExpr::Atom(a) if a.t.contains("${") => {
+                let t = interpolate_env_str(&a.t, lookup).map_err(|e| error_spanned(&a, e))?;
+                SExpr::Atom(Spanned::new(t, a.span))
+            }
+            SExpr::Atom(a) => SExpr::Atom(a),
+            SExpr::List(l) => SExpr::List(Spanned::new(
+                l.t.into_iter()
+                    .map(|expr| interpolate_expr(expr, lookup))
+                    .collect::<Result<_>>()?,
+                l.span,
+            )),
+        })
+    }
+    exprs
+        .into_iter()
+        .map(|expr| {
+            Ok(Spanned::new(
+                expr.t
+                    .into_iter()
+                    .map(|expr| interpolate_expr(expr, &lookup))
+                    .collect::<Result<_>>()?,
+                expr.span,
+            ))
+        })
+        .collect()
+}
+
+fn interpolate_env_str(
+    s: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> std::result::Result<String, String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find("${") {
+        if rest[..i].ends_with('$') {
+            out.push_str(&rest[..i - 1]);
+            out.push_str("${");
+            rest = &rest[i + 2..];
+            continue;
+        }
+        out.push_str(&rest[..i]);
+        let end = rest[i..]
+            .find('}')
+            .ok_or_else(|| format!("missing closing brace in environment variable: {rest}"))?;
+        let name = &rest[i + 2..i + end];
+        if name.is_empty() {
+            return Err("environment variable name in ${} must not be empty".into());
+        }
+        let value = lookup(name).ok_or_else(|| match name.strip_prefix(SECRET_PREFIX) {
+            Some(secret) => format!(
+                "secret {secret} is not in the defsecrets file
+                 Add a {secret}=value line to the file, or use $${{ to write a literal ${{"
+            ),
+            None => format!(
+                "environment variable {name} is not set
+                 Set it before starting kanata, or use $${{ to write a literal ${{"
+            ),
+        })?;
+        out.push_str(&value);
+        rest = &rest[i + end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Replace every `(defmirror name [(key1 key2)...])` with an equivalent deflayer. In the generated
+/// layer, every defsrc key that has a mirror counterpart outputs that counterpart and all other
+/// keys are transparent. Extra pairs given in the defmirror replace the default pairs of those
+/// keys.
+fn expand_mirror_layers(exprs: Vec<Spanned<Vec<SExpr>>>) -> Result<Vec<Spanned<Vec<SExpr>>>> {
+    const ERR_MSG: &str = "defmirror expects a layer name followed by optional (key key) pairs";
+    let defsrc = match exprs.iter().find(gen_first_atom_filter_spanned("defsrc")) {
+        Some(defsrc) => defsrc.t.clone(),
+        // Error on missing defsrc is reported later.
+        None => return Ok(exprs),
+    };
+    exprs
+        .into_iter()
+        .map(|expr| {
+            if !gen_first_atom_filter_spanned("defmirror")(&&expr) {
+                return Ok(expr);
+            }
+            let name = match expr.t.get(1).and_then(|e| e.atom(None)) {
+                Some(name) => name.to_owned(),
+                None => bail_span!(&expr, "{ERR_MSG}"),
+            };
+            let pairs = parse_mirror_pairs(&expr.t[2..], ERR_MSG)?;
+            let span = expr.span;
+            let mut layer = vec![
+                SExpr::Atom(Spanned::new("deflayer".into(), span)),
+                SExpr::Atom(Spanned::new(name, span)),
+            ];
+            for src_key in defsrc.iter().skip(1) {
+                let mirrored = src_key
+                    .atom(None)
+                    .and_then(str_to_oscode)
+                    .and_then(|osc| pairs.iter().find(|(o, _)| *o == osc))
+                    .map(|(_, mirrored)| mirrored.clone())
+                    .unwrap_or_else(|| "_".into());
+                layer.push(SExpr::Atom(Spanned::new(mirrored, span)));
+            }
+            Ok(Spanned::new(layer, span))
+        })
+        .collect()
+}
+
+/// Returns the default mirror pairs, with the pairs in `pair_exprs` replacing the defaults for
+/// their keys. Each pair is returned in both directions along with the name of the key it is
+/// mirrored to.
+fn parse_mirror_pairs(pair_exprs: &[SExpr], err_msg: &str) -> Result<Vec<(OsCode, String)>> {
+    let mut pairs: Vec<(OsCode, String)> = vec![];
+    let mut add_pair = |a: &str, b: &str| -> Option<()> {
+        let (osc_a, osc_b) = (str_to_oscode(a)?, str_to_oscode(b)?);
+        pairs.retain(|(osc, _)| *osc != osc_a && *osc != osc_b);
+        pairs.push((osc_a, b.to_owned()));
+        pairs.push((osc_b, a.to_owned()));
+        Some(())
+    };
+    for (a, b) in MIRROR_PAIRS {
+        add_pair(a, b).expect("valid default mirror keys");
+    }
+    for pair_expr in pair_exprs {
+        let pair = match pair_expr.list(None) {
+            Some(pair @ [SExpr::Atom(_), SExpr::Atom(_)]) => pair,
+            _ => bail_expr!(pair_expr, "{err_msg}"),
+        };
+        let (a, b) = (
+            pair[0].atom(None).expect("atom"),
+            pair[1].atom(None).expect("atom"),
+        );
+        if add_pair(a, b).is_none() {
+            bail_expr!(pair_expr, "Unknown key in pair");
+        }
+    }
+    Ok(pairs)
+}
+
+/// Replace every `(deflayerextends name parent [key action]...)` with a deflayer that has the
+/// items of the parent layer, except for the listed defsrc keys which get the given actions
+/// instead, or become transparent for `unbind`. The parent can be a deflayer or another
+/// deflayerextends.
+fn expand_extended_layers(exprs: Vec<Spanned<Vec<SExpr>>>) -> Result<Vec<Spanned<Vec<SExpr>>>> {
+    const ERR_MSG: &str = "deflayerextends expects a layer name, the name of the layer it \
+        extends, then pairs of a defsrc key and its action";
+    let defsrc = match exprs.iter().find(gen_first_atom_filter_spanned("defsrc")) {
+        Some(defsrc) => defsrc.t.clone(),
+        // Error on missing defsrc is reported later.
+        None => return Ok(exprs),
+    };
+    let src_keys = defsrc
+        .iter()
+        .skip(1)
+        .map(|k| k.atom(None).and_then(str_to_oscode))
+        .collect::<Vec<_>>();
+    let mut layers: HashMap<String, Vec<SExpr>> = exprs
+        .iter()
+        .filter(gen_first_atom_filter_spanned("deflayer"))
+        .filter_map(|expr| Some((expr.t.get(1)?.atom(None)?.to_owned(), expr.t[2..].to_vec())))
+        .collect();
+
+    // Resolve the layers whose parent is known until none are left, since a layer may extend a
+    // layer that is defined further down.
+    let mut pending = exprs
+        .iter()
+        .filter(gen_first_atom_filter_spanned("deflayerextends"))
+        .collect::<Vec<_>>();
+    while !pending.is_empty() {
+        let mut unresolved = vec![];
+        for expr in pending.iter().copied() {
+            let (name, parent) = match (
+                expr.t.get(1).and_then(|e| e.atom(None)),
+                expr.t.get(2).and_then(|e| e.atom(None)),
+            ) {
+                (Some(name), Some(parent)) => (name, parent),
+                _ => bail_span!(expr, "{ERR_MSG}"),
+            };
+            let mut items = match layers.get(parent) {
+                Some(items) => items.clone(),
+                None => {
+                    unresolved.push(expr);
+                    continue;
+                }
+            };
+            let pairs = &expr.t[3..];
+            if pairs.len() % 2 != 0 {
+                bail_expr!(pairs.last().expect("odd length"), "{ERR_MSG}");
+            }
+            for pair in pairs.chunks(2) {
+                let key = pair[0]
+                    .atom(None)
+                    .ok_or_else(|| anyhow_expr!(&pair[0], "{ERR_MSG}"))?;
+                let osc = str_to_oscode(key).ok_or_else(|| {
+                    anyhow_expr!(&pair[0], "Unknown key: {key}{}", key_name_help(key))
+                })?;
+                let i = src_keys
+                    .iter()
+                    .position(|k| *k == Some(osc))
+                    .ok_or_else(|| anyhow_expr!(&pair[0], "{key} is not in defsrc"))?;
+                // A parent with the wrong number of items is reported later.
+                if let Some(item) = items.get_mut(i) {
+                    *item = match pair[1].atom(None) {
+                        // Removing the binding of the parent makes the key transparent.
+                        Some("unbind") => SExpr::Atom(Spanned::new("_".into(), pair[1].span())),
+                        _ => pair[1].clone(),
+                    };
+                }
+            }
+            layers.insert(name.to_owned(), items);
+        }
+        if unresolved.len() == pending.len() {
+            let expr = unresolved[0];
+            bail_span!(
+                expr,
+                "The layer to extend does not exist or extends this layer: {}",
+                expr.t[2].atom(None).expect("checked above")
+            );
+        }
+        pending = unresolved;
+    }
+
+    exprs
+        .into_iter()
+        .map(|expr| {
+            if !gen_first_atom_filter_spanned("deflayerextends")(&&expr) {
+                return Ok(expr);
+            }
+            let name = expr.t[1].clone();
+            let items = layers[name.atom(None).expect("checked above")].clone();
+            let mut layer = vec![
+                SExpr::Atom(Spanned::new("deflayer".into(), expr.span)),
+                name,
+            ];
+            layer.extend(items);
+            Ok(Spanned::new(layer, expr.span))
+        })
+        .collect()
+}
+
+/// Parse `(defsounds <layer-name> <event> <sound-file>...)` items into the sound profiles of the
+/// layers. The layer name `default` sets the profile of all layers without their own profile.
+fn parse_sound_profiles(
+    exprs: &[&Spanned<Vec<SExpr>>],
+    layer_info: &mut [LayerInfo],
+) -> Result<()> {
+    const ERR_MSG: &str =
+        "defsounds expects a layer name or default, followed by pairs of <event> <sound-file>";
+    #[cfg(not(feature = "sound"))]
+    if !exprs.is_empty() {
+        log::warn!("kanata was compiled without the sound feature; defsounds will not play sounds");
+    }
+    let mut default = None;
+    for expr in exprs {
+        let name = match expr.t.get(1).and_then(|e| e.atom(None)) {
+            Some(name) => name,
+            None => bail_span!(expr, "{ERR_MSG}"),
+        };
+        let mut profile = SoundProfile::default();
+        let mut params = expr.t[2..].chunks_exact(2);
+        for pair in params.by_ref() {
+            let (event, file) = match (pair[0].atom(None), pair[1].atom(None)) {
+                (Some(event), Some(file)) => (event, file),
+                _ => bail_expr!(&pair[0], "{ERR_MSG}"),
+            };
+            let sound = match event {
+                "press" => &mut profile.press,
+                "release" => &mut profile.release,
+                "layer-change" => &mut profile.layer_change,
+                "tap" => &mut profile.tap,
+                "hold" => &mut profile.hold,
+                _ => bail_expr!(
+                    &pair[0],
+                    "Unknown sound event. Valid events: press, release, layer-change, tap, hold"
+                ),
+            };
+            *sound = Some(file.trim_matches('"').to_owned());
+        }
+        if let [event] = params.remainder() {
+            bail_expr!(event, "This event is missing a sound file");
+        }
+        if name == "default" {
+            default = Some(profile);
+            continue;
+        }
+        let mut found = false;
+        for layer in layer_info.iter_mut().filter(|l| l.name == name) {
+            layer.sounds = Some(profile.clone());
+            found = true;
+        }
+        if !found {
+            bail_expr!(&expr.t[1], "Unknown layer name in defsounds");
+        }
+    }
+    if let Some(default) = default {
+        for layer in layer_info.iter_mut().filter(|l| l.sounds.is_none()) {
+            layer.sounds = Some(default.clone());
+        }
+    }
+    Ok(())
+}
+
+/// Parse `(deflayertags <layer-name> <tag>...)` items into the tags of the layers.
+fn parse_layer_tags(exprs: &[&Spanned<Vec<SExpr>>], layer_info: &mut [LayerInfo]) -> Result<()> {
+    const ERR_MSG: &str = "deflayertags expects a layer name followed by one or more tags";
+    for expr in exprs {
+        let name = match expr.t.get(1).and_then(|e| e.atom(None)) {
+            Some(name) => name,
+            None => bail_span!(expr, "{ERR_MSG}"),
+        };
+        if expr.t.len() < 3 {
+            bail_span!(expr, "{ERR_MSG}");
+        }
+        let mut tags = vec![];
+        for tag_expr in &expr.t[2..] {
+            match tag_expr.atom(None) {
+                Some(tag) => tags.push(tag.to_owned()),
+                None => bail_expr!(tag_expr, "A tag must be a name, not a list"),
+            }
+        }
+        let mut found = false;
+        for layer in layer_info.iter_mut().filter(|l| l.name == name) {
+            for tag in tags.iter() {
+                if !layer.tags.contains(tag) {
+                    layer.tags.push(tag.clone());
+                }
+            }
+            found = true;
+        }
+        if !found {
+            bail_expr!(&expr.t[1], "Unknown layer name in deflayertags");
+        }
+    }
+    Ok(())
+}
+
+/// Parse `(deflayernotify <layer-name> <field> <value>...)` items into the notification fields of
+/// the layers.
+fn parse_layer_notify(exprs: &[&Spanned<Vec<SExpr>>], layer_info: &mut [LayerInfo]) -> Result<()> {
+    const ERR_MSG: &str = "deflayernotify expects a layer name followed by pairs of <field> <value>";
+    for expr in exprs {
+        let name = match expr.t.get(1).and_then(|e| e.atom(None)) {
+            Some(name) => name,
+            None => bail_span!(expr, "{ERR_MSG}"),
+        };
+        if expr.t.len() < 4 {
+            bail_span!(expr, "{ERR_MSG}");
+        }
+        let mut fields: Vec<(String, String)> = vec![];
+        let mut params = expr.t[2..].chunks_exact(2);
+        for pair in params.by_ref() {
+            let (field, value) = match (pair[0].atom(None), pair[1].atom(None)) {
+                (Some(field), Some(value)) => (field, value.trim_matches('"')),
+                _ => bail_expr!(&pair[0], "{ERR_MSG}"),
+            };
+            if fields.iter().any(|(f, _)| f == field) {
+                bail_expr!(&pair[0], "This field is already set for the layer");
+            }
+            fields.push((field.to_owned(), value.to_owned()));
+        }
+        if let [field] = params.remainder() {
+            bail_expr!(field, "This field is missing a value");
+        }
+        let mut found = false;
+        for layer in layer_info.iter_mut().filter(|l| l.name == name) {
+            if !layer.notify.is_empty() {
+                bail_expr!(&expr.t[1], "Only one deflayernotify is allowed per layer");
+            }
+            layer.notify = fields.clone();
+            found = true;
+        }
+        if !found {
+            bail_expr!(&expr.t[1], "Unknown layer name in deflayernotify");
+        }
+    }
+    Ok(())
+}
+
+/// Parse `(deflayerdoc <layer-name> <description> <key> <description>...)` items into the
+/// descriptions of the layers and of their keys. The keys are keys of defsrc.
+fn parse_layer_docs(
+    exprs: &[&Spanned<Vec<SExpr>>],
+    mapping_order: &[usize],
+    layer_info: &mut [LayerInfo],
+) -> Result<()> {
+    const ERR_MSG: &str =
+        "deflayerdoc expects a layer name, its description and pairs of <key> <description>";
+    let description = |expr: &SExpr| -> Result<String> {
+        match expr.atom(None) {
+            Some(s) => Ok(s.trim_matches('"').to_owned()),
+            None => bail_expr!(expr, "A description must be a string, not a list"),
+        }
+    };
+    for expr in exprs {
+        let name = match expr.t.get(1).and_then(|e| e.atom(None)) {
+            Some(name) => name,
+            None => bail_span!(expr, "{ERR_MSG}"),
+        };
+        let doc_expr = match expr.t.get(2) {
+            Some(doc_expr) => doc_expr,
+            None => bail_span!(expr, "{ERR_MSG}"),
+        };
+        let mut doc = LayerDoc {
+            description: Some(description(doc_expr)?),
+            keys: vec![],
+        };
+        let mut params = expr.t[3..].chunks_exact(2);
+        for pair in params.by_ref() {
+            let key = match pair[0].atom(None).and_then(str_to_oscode) {
+                Some(key) => key,
+                None => bail_expr!(&pair[0], "Unknown key in deflayerdoc"),
+            };
+            if !mapping_order.contains(&usize::from(key)) {
+                bail_expr!(&pair[0], "This key is not in defsrc");
+            }
+            if doc.key(key).is_some() {
+                bail_expr!(&pair[0], "This key is already described for the layer");
+            }
+            doc.keys.push((key, description(&pair[1])?));
+        }
+        if let [key] = params.remainder() {
+            bail_expr!(key, "This key is missing a description");
+        }
+        let mut found = false;
+        for layer in layer_info.iter_mut().filter(|l| l.name == name) {
+            if layer.doc.description.is_some() {
+                bail_expr!(&expr.t[1], "Only one deflayerdoc is allowed per layer");
+            }
+            layer.doc = doc.clone();
+            found = true;
+        }
+        if !found {
+            bail_expr!(&expr.t[1], "Unknown layer name in deflayerdoc");
+        }
+    }
+    Ok(())
+}
+
+/// Parse `(defdevicetags <device-name> <tag>...)` items, the layer tags that are turned on while
+/// the device is present. A tag prefixed with `!` is turned on while the device is absent instead.
+fn parse_device_tags(
+    exprs: &[&Spanned<Vec<SExpr>>],
+    layer_info: &[LayerInfo],
+) -> Result<Vec<DeviceTags>> {
+    const ERR_MSG: &str = "defdevicetags expects a device name followed by one or more tags";
+    let mut entries: Vec<DeviceTags> = vec![];
+    for expr in exprs {
+        let (name_expr, tag_exprs) = match &expr.t[1..] {
+            [name, tags @ ..] if !tags.is_empty() => (name, tags),
+            _ => bail_span!(expr, "{ERR_MSG}"),
+        };
+        let device = match name_expr.atom(None) {
+            Some(name) => name.trim_matches('"').to_owned(),
+            None => bail_expr!(name_expr, "{ERR_MSG}"),
+        };
+        if entries.iter().any(|(d, _)| *d == device) {
+            bail_expr!(name_expr, "This device already has a defdevicetags");
+        }
+        let mut tags = vec![];
+        for tag_expr in tag_exprs {
+            let tag = match tag_expr.atom(None) {
+                Some(tag) => tag,
+                None => bail_expr!(tag_expr, "A tag must be a name, not a list"),
+            };
+            let (tag, when_present) = match tag.strip_prefix('!') {
+                Some(tag) => (tag, false),
+                None => (tag, true),
+            };
+            if !layer_info.iter().any(|l| l.tags.iter().any(|t| t == tag)) {
+                bail_expr!(tag_expr, "No layer has this tag in deflayertags");
+            }
+            tags.push((tag.to_owned(), when_present));
+        }
+        entries.push((device, tags));
+    }
+    Ok(entries)
+}
+
+/// Parse `(defhelpers <name> (<program> <arg>...)...)` items, the helper processes that kanata
+/// runs alongside itself. They run commands, so they need `danger-enable-cmd`.
+fn parse_helpers(exprs: &[&Spanned<Vec<SExpr>>], s: &ParsedState) -> Result<Vec<HelperCmd>> {
+    const ERR_MSG: &str =
+        "defhelpers expects pairs of a name and a list of a program and its arguments";
+    let mut helpers: Vec<HelperCmd> = vec![];
+    for expr in exprs {
+        if !s.is_cmd_enabled {
+            bail_span!(
+                expr,
+                "defhelpers runs commands, but cmd is not enabled. Set danger-enable-cmd to yes."
+            );
+        }
+        let mut pairs = expr.t[1..].chunks_exact(2);
+        for pair in pairs.by_ref() {
+            let Some(name) = pair[0].atom(s.vars()) else {
+                bail_expr!(&pair[0], "{ERR_MSG}")
+            };
+            if helpers.iter().any(|(n, _)| n == name) {
+                bail_expr!(&pair[0], "Duplicate helper name");
+            }
+            let cmd = match pair[1].list(s.vars()) {
+                Some(cmd) if !cmd.is_empty() => cmd,
+                _ => bail_expr!(&pair[1], "{ERR_MSG}"),
+            };
+            let cmd = cmd
+                .iter()
+                .map(|arg| {
+                    arg.atom(s.vars())
+                        .map(|a| a.trim_matches('"').to_owned())
+                        .ok_or_else(|| anyhow_expr!(arg, "Arguments must be strings, not lists"))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            helpers.push((name.to_owned(), cmd));
+        }
+        if let [name] = pairs.remainder() {
+            bail_expr!(name, "This helper is missing its command");
+        }
+    }
+    Ok(helpers)
+}
+
+const MODIFIERS: [OsCode; 8] = [
+    OsCode::KEY_LEFTSHIFT,
+    OsCode::KEY_RIGHTSHIFT,
+    OsCode::KEY_LEFTCTRL,
+    OsCode::KEY_RIGHTCTRL,
+    OsCode::KEY_LEFTALT,
+    OsCode::KEY_RIGHTALT,
+    OsCode::KEY_LEFTMETA,
+    OsCode::KEY_RIGHTMETA,
+];
+
+/// Parse `(deflayercondition <layer-name> <modifier>...)` items into the modifiers that must be
+/// held for each keyberon layer to apply.
+fn parse_layer_conditions(
+    exprs: &[&Spanned<Vec<SExpr>>],
+    s: &ParsedState,
+) -> Result<Vec<(usize, Vec<u16>)>> {
+    const ERR_MSG: &str = "deflayercondition expects a layer name followed by one or more \
+        modifiers: lsft rsft lctl rctl lalt ralt lmet rmet";
+    let mut conditions = vec![];
+    for expr in exprs {
+        let (name_expr, mod_exprs) = match &expr.t[1..] {
+            [name, mods @ ..] if !mods.is_empty() => (name, mods),
+            _ => bail_span!(expr, "{ERR_MSG}"),
+        };
+        let name = name_expr.atom(s.vars()).unwrap_or_default();
+        let layer = match s.layer_idxs.get(name) {
+            Some(idx) => *idx,
+            None => bail_expr!(name_expr, "Unknown layer name in deflayercondition"),
+        };
+        if conditions.iter().any(|(l, _)| *l == layer * 2) {
+            bail_expr!(name_expr, "This layer already has a deflayercondition");
+        }
+        let mut mods = vec![];
+        for mod_expr in mod_exprs {
+            let osc = match mod_expr.atom(s.vars()).and_then(str_to_oscode) {
+                Some(osc) if MODIFIERS.contains(&osc) => osc,
+                _ => bail_expr!(mod_expr, "{ERR_MSG}"),
+            };
+            if !s.mapping_order.contains(&usize::from(osc)) {
+                bail_expr!(mod_expr, "Modifiers in deflayercondition must be in defsrc");
+            }
+            mods.push(u16::from(osc));
+        }
+        // Both keyberon versions of the layer have the condition.
+        conditions.push((layer * 2, mods.clone()));
+        conditions.push((layer * 2 + 1, mods));
+    }
+    Ok(conditions)
+}
+
+/// Parse `(defhands (left <keys>...) (right <keys>...))` into the hand of each key. A hand may be
+/// followed by a finger, e.g. `(left-index <keys>...)`, to assign the keys to that finger as well.
+fn parse_hands(exprs: &[&Spanned<Vec<SExpr>>], s: &mut ParsedState) -> Result<()> {
+    const ERR_MSG: &str = "defhands expects lists of a hand (left or right), optionally followed \
+        by -thumb, -index, -middle, -ring or -pinky, and then keys";
+    let expr = match exprs {
+        [] => return Ok(()),
+        [expr] => expr,
+        [_, expr, ..] => bail_span!(expr, "Only one defhands is allowed, found more."),
+    };
+    let mut hands = vec![None; KEYS_IN_ROW];
+    let mut fingers = vec![None; KEYS_IN_ROW];
+    let mut has_fingers = false;
+    for hand_expr in &expr.t[1..] {
+        let (hand_name_expr, keys) = match hand_expr.list(s.vars()) {
+            Some([hand, keys @ ..]) => (hand, keys),
+            _ => bail_expr!(hand_expr, "{ERR_MSG}"),
+        };
+        let hand_name = hand_name_expr.atom(s.vars()).unwrap_or_default();
+        let (hand, finger) = hand_name.split_once('-').unwrap_or((hand_name, ""));
+        let hand = match hand {
+            "left" => Hand::Left,
+            "right" => Hand::Right,
+            _ => bail_expr!(hand_name_expr, "{ERR_MSG}"),
+        };
+        let finger = match finger {
+            "" => None,
+            "thumb" => Some(Finger::Thumb),
+            "index" => Some(Finger::Index),
+            "middle" => Some(Finger::Middle),
+            "ring" => Some(Finger::Ring),
+            "pinky" => Some(Finger::Pinky),
+            _ => bail_expr!(hand_name_expr, "{ERR_MSG}"),
+        };
+        has_fingers |= finger.is_some();
+        for key in keys {
+            let osc = key.atom(s.vars()).and_then(str_to_oscode).ok_or_else(|| {
+                let help = key.atom(s.vars()).map(key_name_help).unwrap_or_default();
+                anyhow_expr!(key, "Unknown key in defhands{help}")
+            })?;
+            let hand_of_key = &mut hands[usize::from(osc)];
+            if hand_of_key.is_some() {
+                bail_expr!(key, "This key is already assigned to a hand");
+            }
+            *hand_of_key = Some(hand);
+            fingers[usize::from(osc)] = finger.map(|finger| (hand, finger));
+        }
+    }
+    s.hands = Some(s.a.sref_vec(hands));
+    if has_fingers {
+        s.fingers = Some(s.a.sref_vec(fingers));
+    }
+    Ok(())
 }
 
 type LayerIndexes = HashMap<String, usize>;
@@ -749,7 +2065,34 @@ struct ParsedState {
     is_cmd_enabled: bool,
     cfg_filename: String,
     cfg_text: String,
+    /// The files merged into `cfg_text` by `include`.
+    includes: Vec<IncludedSource>,
     vars: HashMap<String, SExpr>,
+    dead_keys: HashMap<String, &'static KanataAction>,
+    /// The hand of each key, indexed by `OsCode`, if `defhands` exists.
+    hands: Option<&'static [Option<Hand>]>,
+    /// The hand and finger of each key, indexed by `OsCode`, if `defhands` assigns fingers.
+    fingers: Option<&'static [Option<(Hand, Finger)>]>,
+    tests: Vec<CfgTest>,
+    /// The names of the `deflauncher` entries and the coordinates of their fake keys.
+    launcher: Vec<(String, (u8, u16))>,
+    /// The modifiers of the `defmodchords` entries and the coordinates of their fake keys.
+    mod_chords: Vec<ModChord>,
+    /// The short codes and their replacements, from `defshortcodes`.
+    shortcodes: HashMap<String, String>,
+    snippets: Vec<Snippet>,
+    /// The modifier keys required by keyberon layers, from `deflayercondition`.
+    layer_conditions: Vec<(usize, Vec<u16>)>,
+    /// The mouse acceleration of keyberon layers, from `defmouseaccel`.
+    mouse_accel_layers: Vec<(usize, MouseAccel)>,
+    /// The layer tags that follow the presence of input devices, from `defdevicetags`.
+    device_tags: Vec<DeviceTags>,
+    /// The translated output modifiers of keyberon layers, from `defmodtranslation`.
+    mod_translations: Vec<(usize, Vec<(OsCode, OsCode)>)>,
+    /// The helper processes, from `defhelpers`.
+    helpers: Vec<HelperCmd>,
+    /// The operators of the modal editing layers, from `defmodal`.
+    modal_layers: Vec<(usize, Vec<ModalOperator>)>,
     a: Arc<Allocations>,
 }
 
@@ -772,7 +2115,22 @@ impl Default for ParsedState {
             is_cmd_enabled: false,
             cfg_filename: Default::default(),
             cfg_text: Default::default(),
+            includes: vec![],
             vars: Default::default(),
+            dead_keys: Default::default(),
+            hands: None,
+            fingers: None,
+            tests: vec![],
+            layer_conditions: vec![],
+            mouse_accel_layers: vec![],
+            device_tags: vec![],
+            mod_translations: vec![],
+            helpers: vec![],
+            modal_layers: vec![],
+            launcher: vec![],
+            mod_chords: vec![],
+            shortcodes: Default::default(),
+            snippets: vec![],
             a: unsafe { Allocations::new() },
         }
     }
@@ -786,6 +2144,7 @@ struct ChordGroup {
     coords: Vec<((u8, u16), ChordKeys)>,
     chords: HashMap<u32, SExpr>,
     timeout: u16,
+    release_only: bool,
 }
 
 fn parse_vars(exprs: &[&Vec<SExpr>], s: &mut ParsedState) -> Result<()> {
@@ -804,7 +2163,16 @@ fn parse_vars(exprs: &[&Vec<SExpr>], s: &mut ParsedState) -> Result<()> {
                     "variable key name has no action - you should add an action."
                 ),
             };
-            if s.vars.insert(var_name.into(), var_expr.clone()).is_some() {
+            // Arithmetic is evaluated here so that variables can be derived from each other and
+            // then used anywhere a number is expected.
+            let var_expr = match is_arithmetic_expr(var_expr) {
+                true => match eval_number(var_expr, Some(&s.vars))? {
+                    Some(n) => SExpr::Atom(Spanned::new(n.to_string(), var_expr.span())),
+                    None => var_expr.clone(),
+                },
+                false => var_expr.clone(),
+            };
+            if s.vars.insert(var_name.into(), var_expr).is_some() {
                 bail_expr!(var_name_expr, "duplicate variable name: {}", var_name);
             }
         }
@@ -942,6 +2310,36 @@ fn parse_action_atom(ac: &Spanned<String>, s: &ParsedState) -> Result<&'static K
                 s.a.sref(s.a.sref_slice(CustomAction::SequenceLeader)),
             )))
         }
+        "launcher" => {
+            return Ok(s.a.sref(Action::Custom(
+                s.a.sref(s.a.sref_slice(CustomAction::Launcher)),
+            )))
+        }
+        "mouse-grid" => {
+            return Ok(s.a.sref(Action::Custom(
+                s.a.sref(s.a.sref_slice(CustomAction::MouseGrid)),
+            )))
+        }
+        "mouse-drop" => {
+            return Ok(s.a.sref(Action::Custom(
+                s.a.sref(s.a.sref_slice(CustomAction::MouseDrop)),
+            )))
+        }
+        "layer-lock" => {
+            return Ok(s.a.sref(Action::Custom(
+                s.a.sref(s.a.sref_slice(CustomAction::LayerLock)),
+            )))
+        }
+        "passthrough" => {
+            return Ok(s.a.sref(Action::Custom(
+                s.a.sref(s.a.sref_slice(CustomAction::Passthrough)),
+            )))
+        }
+        "resync-modifiers" => {
+            return Ok(s.a.sref(Action::Custom(
+                s.a.sref(s.a.sref_slice(CustomAction::ResyncModifiers)),
+            )))
+        }
         "mlft" | "mouseleft" => {
             return Ok(s.a.sref(Action::Custom(
                 s.a.sref(s.a.sref_slice(CustomAction::Mouse(Btn::Left))),
@@ -1011,8 +2409,12 @@ fn parse_action_atom(ac: &Spanned<String>, s: &ParsedState) -> Result<&'static K
         return match s.aliases.get(alias) {
             Some(ac) => Ok(*ac),
             None => bail!(
-                "Referenced unknown alias {}. Note that order of declarations matter.",
-                alias
+                "Referenced unknown alias {}. Note that order of declarations matter.{}",
+                alias,
+                match did_you_mean(alias, s.aliases.keys().map(|a| a.as_str())) {
+                    Some(a) => format!("\nDid you mean: @{a}"),
+                    None => String::new(),
+                }
             ),
         };
     }
@@ -1021,7 +2423,12 @@ fn parse_action_atom(ac: &Spanned<String>, s: &ParsedState) -> Result<&'static K
     let (mut keys, unparsed_str) = parse_mod_prefix(ac)?;
     keys.push(
         str_to_oscode(unparsed_str)
-            .ok_or_else(|| anyhow!("Unknown key/action/variable: {ac:?}"))?
+            .ok_or_else(|| {
+                anyhow!(
+                    "Unknown key/action/variable: {ac:?}{}",
+                    key_name_help(unparsed_str)
+                )
+            })?
             .into(),
     );
     Ok(s.a.sref(Action::MultipleKeyCodes(s.a.sref(s.a.sref_vec(keys)))))
@@ -1034,11 +2441,13 @@ fn parse_action_list(ac: &[SExpr], s: &ParsedState) -> Result<&'static KanataAct
     }
     let ac_type = match &ac[0] {
         SExpr::Atom(a) => &a.t,
-        _ => bail!("Action list must start with an atom"),
+        // A list of actions, e.g. `((layer-switch nav) a)`, is shorthand for multi.
+        SExpr::List(_) => return parse_multi(ac, s),
     };
     match ac_type.as_str() {
         "layer-switch" => parse_layer_base(&ac[1..], s),
         "layer-toggle" | "layer-while-held" => parse_layer_toggle(&ac[1..], s),
+        "temp-layer" => parse_temp_layer(&ac[1..], s),
         "tap-hold" => parse_tap_hold(&ac[1..], s, HoldTapConfig::Default),
         "tap-hold-press" => parse_tap_hold(&ac[1..], s, HoldTapConfig::HoldOnOtherKeyPress),
         "tap-hold-release" => parse_tap_hold(&ac[1..], s, HoldTapConfig::PermissiveHold),
@@ -1049,12 +2458,15 @@ fn parse_action_list(ac: &[SExpr], s: &ParsedState) -> Result<&'static KanataAct
             parse_tap_hold_timeout(&ac[1..], s, HoldTapConfig::PermissiveHold)
         }
         "tap-hold-release-keys" => parse_tap_hold_release_keys(&ac[1..], s),
+        "tap-hold-opposite-hand" => parse_tap_hold_opposite_hand(&ac[1..], s),
+        "tap-hold-other-finger" => parse_tap_hold_other_finger(&ac[1..], s),
         "multi" => parse_multi(&ac[1..], s),
         "macro" => parse_macro(&ac[1..], s, RepeatMacro::No),
         "macro-repeat" => parse_macro(&ac[1..], s, RepeatMacro::Yes),
         "macro-release-cancel" => parse_macro_release_cancel(&ac[1..], s, RepeatMacro::No),
         "macro-repeat-release-cancel" => parse_macro_release_cancel(&ac[1..], s, RepeatMacro::Yes),
         "unicode" => parse_unicode(&ac[1..], s),
+        "dead-key" => parse_dead_key(&ac[1..], s),
         "one-shot" | "one-shot-press" => {
             parse_one_shot(&ac[1..], s, OneShotEndConfig::EndOnFirstPress)
         }
@@ -1069,6 +2481,7 @@ fn parse_action_list(ac: &[SExpr], s: &ParsedState) -> Result<&'static KanataAct
         "tap-dance-eager" => parse_tap_dance(&ac[1..], s, TapDanceConfig::Eager),
         "chord" => parse_chord(&ac[1..], s),
         "release-key" => parse_release_key(&ac[1..], s),
+        "toggle-key" => parse_toggle_key(&ac[1..], s),
         "release-layer" => parse_release_layer(&ac[1..], s),
         "on-press-fakekey" => parse_fake_key_op(&ac[1..], s),
         "on-release-fakekey" => parse_on_release_fake_key_op(&ac[1..], s),
@@ -1087,14 +2500,29 @@ fn parse_action_list(ac: &[SExpr], s: &ParsedState) -> Result<&'static KanataAct
         "movemouse-accel-left" => parse_move_mouse_accel(&ac[1..], MoveDirection::Left, s),
         "movemouse-accel-right" => parse_move_mouse_accel(&ac[1..], MoveDirection::Right, s),
         "setmouse" => parse_set_mouse(&ac[1..], s),
+        "mouse-warp" => parse_mouse_warp(&ac[1..], s),
+        "mouse-drag" => parse_mouse_drag(&ac[1..], s),
+        "dwell-click" => parse_dwell_click(&ac[1..], s),
+        "kvm-target" => parse_kvm_target(&ac[1..], s),
+        "route-output" => parse_route_output(&ac[1..], s),
         "dynamic-macro-record" => parse_dynamic_macro_record(&ac[1..], s),
         "dynamic-macro-play" => parse_dynamic_macro_play(&ac[1..], s),
         "arbitrary-code" => parse_arbitrary_code(&ac[1..], s),
         "cmd" => parse_cmd(&ac[1..], s, CmdType::Standard),
         "cmd-output-keys" => parse_cmd(&ac[1..], s, CmdType::OutputKeys),
+        "clipboard-paste" => parse_clipboard_paste(&ac[1..], s),
         "fork" => parse_fork(&ac[1..], s),
+        "on-release" => parse_on_release(&ac[1..], s),
+        "press-release" => parse_press_release(&ac[1..], s),
         "caps-word" => parse_caps_word(&ac[1..], s),
         "caps-word-custom" => parse_caps_word_custom(&ac[1..], s),
+        "morse" => parse_morse(&ac[1..], s),
+        "unicode-input" => parse_unicode_input(&ac[1..], s),
+        "swap-hands" => parse_swap_hands(&ac[1..], s),
+        "set-var" => parse_set_var(&ac[1..], s),
+        "switch-var" => parse_switch_var(&ac[1..], s),
+        "cooldown" => parse_cooldown(&ac[1..], s),
+        "counted" => parse_counted(&ac[1..], s),
         "dynamic-macro-record-stop-truncate" => parse_macro_record_stop_truncate(&ac[1..], s),
         _ => bail_expr!(&ac[0], "Unknown action type: {ac_type}"),
     }
@@ -1110,6 +2538,18 @@ fn parse_layer_toggle(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static K
     Ok(s.a.sref(Action::Layer(layer_idx(ac_params, &s.layer_idxs)? * 2 + 1)))
 }
 
+fn parse_temp_layer(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAction> {
+    const ERR_MSG: &str = "temp-layer expects 2 parameters: <layer-name> <seconds>";
+    let [layer, seconds] = ac_params else {
+        bail!("{ERR_MSG}, found {}", ac_params.len());
+    };
+    let layer = layer_idx(std::slice::from_ref(layer), &s.layer_idxs)? * 2 + 1;
+    let seconds = parse_non_zero_u16(seconds, s, "seconds")?;
+    Ok(s.a.sref(Action::Custom(s.a.sref(
+        s.a.sref_slice(CustomAction::TempLayer { layer, seconds }),
+    ))))
+}
+
 fn layer_idx(ac_params: &[SExpr], layers: &LayerIndexes) -> Result<usize> {
     if ac_params.len() != 1 {
         bail!(
@@ -1221,23 +2661,119 @@ Params in order:
     }))))
 }
 
+fn parse_tap_hold_opposite_hand(
+    ac_params: &[SExpr],
+    s: &ParsedState,
+) -> Result<&'static KanataAction> {
+    if ac_params.len() != 4 {
+        bail!(
+            r"tap-hold-opposite-hand expects 4 items after it, got {}.
+Params in order:
+<tap-timeout> <hold-timeout> <tap-action> <hold-action>",
+            ac_params.len(),
+        )
+    }
+    let hands = match s.hands {
+        Some(hands) => hands,
+        None => bail!("tap-hold-opposite-hand requires defhands to exist"),
+    };
+    let tap_timeout = parse_non_zero_u16(&ac_params[0], s, "tap timeout")?;
+    let hold_timeout = parse_non_zero_u16(&ac_params[1], s, "hold timeout")?;
+    let tap_action = parse_action(&ac_params[2], s)?;
+    let hold_action = parse_action(&ac_params[3], s)?;
+    if matches!(tap_action, Action::HoldTap { .. }) {
+        bail!("tap-hold does not work in the tap-action of tap-hold")
+    }
+    Ok(s.a.sref(Action::HoldTap(s.a.sref(HoldTapAction {
+        config: HoldTapConfig::Custom(custom_tap_hold_opposite_hand(hands, &s.a)),
+        tap_hold_interval: tap_timeout,
+        timeout: hold_timeout,
+        tap: *tap_action,
+        hold: *hold_action,
+        timeout_action: *hold_action,
+    }))))
+}
+
+fn parse_tap_hold_other_finger(
+    ac_params: &[SExpr],
+    s: &ParsedState,
+) -> Result<&'static KanataAction> {
+    let fingers = match s.fingers {
+        Some(fingers) => fingers,
+        None => bail!("tap-hold-other-finger requires defhands to assign keys to fingers"),
+    };
+    parse_tap_hold(
+        ac_params,
+        s,
+        HoldTapConfig::Custom(custom_tap_hold_other_finger(fingers, &s.a)),
+    )
+}
+
 fn parse_u16(expr: &SExpr, s: &ParsedState, label: &str) -> Result<u16> {
-    expr.atom(s.vars())
-        .map(str::parse::<u16>)
-        .and_then(|u| u.ok())
+    eval_number(expr, s.vars())?
+        .and_then(|n| u16::try_from(n).ok())
         .ok_or_else(|| anyhow_expr!(expr, "{label} must be 0-65535"))
 }
 
 fn parse_non_zero_u16(expr: &SExpr, s: &ParsedState, label: &str) -> Result<u16> {
-    expr.atom(s.vars())
-        .map(str::parse::<u16>)
-        .and_then(|u| match u {
+    eval_number(expr, s.vars())?
+        .and_then(|n| match u16::try_from(n) {
             Ok(u @ 1..) => Some(u),
             _ => None,
         })
         .ok_or_else(|| anyhow_expr!(expr, "{label} must be 1-65535"))
 }
 
+const ARITHMETIC_OPERATORS: &[&str] = &["+", "-", "*", "/"];
+
+/// Returns true if the expression is an arithmetic expression like `(+ $base-timeout 30)`.
+fn is_arithmetic_expr(expr: &SExpr) -> bool {
+    matches!(
+        expr,
+        SExpr::List(l) if matches!(l.t.first(), Some(SExpr::Atom(op)) if ARITHMETIC_OPERATORS.contains(&op.t.as_str()))
+    )
+}
+
+/// Evaluate a number or an arithmetic expression whose operands are numbers, variables or nested
+/// expressions. Returns `Ok(None)` if the expression is not a number at all so that callers can
+/// report their own error message, and an error if an arithmetic expression is malformed.
+fn eval_number(expr: &SExpr, vars: Option<&HashMap<String, SExpr>>) -> Result<Option<i64>> {
+    if let Some(a) = expr.atom(vars) {
+        return Ok(a.parse().ok());
+    }
+    let list = match expr.list(vars) {
+        Some(l) => l,
+        None => return Ok(None),
+    };
+    let op = match list.first() {
+        Some(SExpr::Atom(op)) if ARITHMETIC_OPERATORS.contains(&op.t.as_str()) => &op.t,
+        _ => return Ok(None),
+    };
+    if list.len() < 3 {
+        bail_expr!(
+            expr,
+            "{op} expects two or more numbers, e.g. ({op} $var 10)"
+        );
+    }
+    let mut result: Option<i64> = None;
+    for operand in &list[1..] {
+        let n = eval_number(operand, vars)?
+            .ok_or_else(|| anyhow_expr!(operand, "operand of {op} must be a number"))?;
+        result = Some(match result {
+            None => n,
+            Some(acc) => match op.as_str() {
+                "+" => acc.checked_add(n),
+                "-" => acc.checked_sub(n),
+                "*" => acc.checked_mul(n),
+                _ if n == 0 => bail_expr!(operand, "division by zero"),
+                _ => acc.checked_div(n),
+            }
+            .ok_or_else(|| anyhow_expr!(expr, "the result of {op} is too large"))?,
+        });
+    }
+    Ok(result)
+}
+
 fn parse_key_list(expr: &SExpr, s: &ParsedState, label: &str) -> Result<Vec<OsCode>> {
     expr.list(s.vars())
         .map(|keys| {
@@ -1527,6 +3063,60 @@ fn parse_unicode(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static Kanata
         .ok_or_else(|| anyhow_expr!(&ac_params[0], "{ERR_STR}"))?
 }
 
+/// Parse the character->dead key chain mappings from exprs starting with defdeadkeys. The chains
+/// depend on the OS keyboard layout so there is no built-in table; the user describes how their
+/// layout produces each character.
+fn parse_dead_keys(exprs: &[&Vec<SExpr>], s: &mut ParsedState) -> Result<()> {
+    for expr in exprs {
+        let mut subexprs = check_first_expr(expr.iter(), "defdeadkeys")?;
+        while let Some(char_expr) = subexprs.next() {
+            let chr = char_expr
+                .atom(s.vars())
+                .ok_or_else(|| anyhow_expr!(char_expr, "Dead key character must not be a list."))?
+                .to_owned();
+            let chain_expr = match subexprs.next() {
+                Some(v) => v,
+                None => bail_expr!(
+                    char_expr,
+                    "Dead key character has no key chain - you should add a list of keys."
+                ),
+            };
+            let chain = chain_expr.list(s.vars()).ok_or_else(|| {
+                anyhow_expr!(
+                    chain_expr,
+                    "Dead key chain must be a list of keys and chords, e.g. (RA-' e)"
+                )
+            })?;
+            let action = parse_macro(chain, s, RepeatMacro::No).map_err(|mut e| {
+                if e.err_span.is_none() {
+                    e.err_span = Some(expr_err_span(chain_expr))
+                }
+                e
+            })?;
+            if s.dead_keys.insert(chr.clone(), action).is_some() {
+                bail_expr!(char_expr, "Duplicate dead key character: {}", chr);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn parse_dead_key(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAction> {
+    const ERR_STR: &str = "dead-key expects one character that is defined in defdeadkeys";
+    if ac_params.len() != 1 {
+        bail!(ERR_STR)
+    }
+    let chr = ac_params[0]
+        .atom(s.vars())
+        .ok_or_else(|| anyhow_expr!(&ac_params[0], "{ERR_STR}"))?;
+    s.dead_keys.get(chr).copied().ok_or_else(|| {
+        anyhow_expr!(
+            &ac_params[0],
+            "{ERR_STR}. {chr} is not defined. Note that order of declarations matter."
+        )
+    })
+}
+
 enum CmdType {
     Standard,
     OutputKeys,
@@ -1559,6 +3149,27 @@ fn parse_cmd(
         })))))
 }
 
+fn parse_clipboard_paste(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAction> {
+    const ERR_STR: &str =
+        "clipboard-paste expects zero parameters or a maximum number of characters";
+    /// Limit on the pasted characters so that copying something large by accident does not
+    /// type for minutes.
+    const DEFAULT_MAX_CHARS: u16 = 1000;
+    if cfg!(not(feature = "clipboard")) {
+        bail!("clipboard-paste is specified but kanata was compiled without the clipboard feature");
+    }
+    let max_chars = match ac_params {
+        [] => DEFAULT_MAX_CHARS,
+        [max] => parse_non_zero_u16(max, s, "clipboard-paste maximum characters")?,
+        _ => bail!(ERR_STR),
+    };
+    Ok(s.a.sref(Action::Custom(s.a.sref(s.a.sref_slice(
+        CustomAction::ClipboardPaste {
+            max_chars: max_chars.into(),
+        },
+    )))))
+}
+
 fn parse_one_shot(
     ac_params: &[SExpr],
     s: &ParsedState,
@@ -1571,11 +3182,8 @@ fn parse_one_shot(
 
     let timeout = parse_non_zero_u16(&ac_params[0], s, "timeout")?;
     let action = parse_action(&ac_params[1], s)?;
-    if !matches!(
-        action,
-        Action::Layer(..) | Action::KeyCode(..) | Action::MultipleKeyCodes(..)
-    ) {
-        bail!("one-shot is only allowed to contain layer-toggle, a keycode, or a chord");
+    if matches!(action, Action::OneShot(..)) {
+        bail_expr!(&ac_params[1], "one-shot cannot contain another one-shot");
     }
 
     Ok(s.a.sref(Action::OneShot(s.a.sref(OneShot {
@@ -1591,9 +3199,15 @@ fn parse_tap_dance(
     config: TapDanceConfig,
 ) -> Result<&'static KanataAction> {
     const ERR_MSG: &str = "tap-dance expects a timeout (number) followed by a list of actions";
-    if ac_params.len() != 2 {
-        bail!(ERR_MSG);
-    }
+    let interrupt = match (ac_params.len(), config) {
+        (2, _) => TapDanceInterrupt::Resolve,
+        (3, TapDanceConfig::Lazy) => parse_tap_dance_interrupt(&ac_params[2], s)?,
+        (3, TapDanceConfig::Eager) => bail_expr!(
+            &ac_params[2],
+            "tap-dance-eager activates on every tap, so it cannot be interrupted"
+        ),
+        _ => bail!("{ERR_MSG}, optionally followed by resolve, cancel or buffer"),
+    };
 
     let timeout = parse_non_zero_u16(&ac_params[0], s, "timeout")?;
     let actions = ac_params[1]
@@ -1612,9 +3226,22 @@ fn parse_tap_dance(
         timeout,
         actions: s.a.sref_vec(actions),
         config,
+        interrupt,
     }))))
 }
 
+fn parse_tap_dance_interrupt(expr: &SExpr, s: &ParsedState) -> Result<TapDanceInterrupt> {
+    match expr.atom(s.vars()) {
+        Some("resolve") => Ok(TapDanceInterrupt::Resolve),
+        Some("cancel") => Ok(TapDanceInterrupt::Cancel),
+        Some("buffer") => Ok(TapDanceInterrupt::Buffer),
+        _ => bail_expr!(
+            expr,
+            "The interruption behaviour of tap-dance must be resolve, cancel or buffer"
+        ),
+    }
+}
+
 fn parse_chord(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAction> {
     const ERR_MSG: &str = "Action chord expects a chords group name followed by an identifier";
     if ac_params.len() != 2 {
@@ -1649,6 +3276,7 @@ fn parse_chord(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAc
         timeout: group.timeout,
         coords: s.a.sref_vec(vec![((0, group.id), chord_keys)]),
         chords: s.a.sref_vec(vec![]),
+        release_only: group.release_only,
     }))))
 }
 
@@ -1666,6 +3294,20 @@ fn parse_release_key(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static Ka
     }
 }
 
+fn parse_toggle_key(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAction> {
+    const ERR_MSG: &str = "toggle-key expects exactly one keycode (e.g. lsft)";
+    if ac_params.len() != 1 {
+        bail!("{ERR_MSG}: found {} items", ac_params.len());
+    }
+    let ac = parse_action(&ac_params[0], s)?;
+    match ac {
+        Action::KeyCode(kc) => Ok(s.a.sref(Action::Custom(
+            s.a.sref(s.a.sref_slice(CustomAction::ToggleKey(*kc))),
+        ))),
+        _ => bail_expr!(&ac_params[0], "{}", ERR_MSG),
+    }
+}
+
 fn parse_release_layer(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAction> {
     Ok(s.a.sref(Action::ReleaseState(ReleasableState::Layer(
         layer_idx(ac_params, &s.layer_idxs)? * 2 + 1,
@@ -1696,8 +3338,10 @@ fn parse_chord_groups(exprs: &[&Spanned<Vec<SExpr>>], s: &mut ParsedState) -> Re
             .and_then(|e| e.atom(s.vars()))
             .ok_or_else(|| anyhow_span!(expr, "{MSG}"))?
             .to_owned();
-        let timeout = match subexprs.next() {
-            Some(e) => parse_non_zero_u16(e, s, "timeout")?,
+        // A timeout of `release` means chords are only resolved on release, e.g. for braille input.
+        let (timeout, release_only) = match subexprs.next() {
+            Some(e) if e.atom(s.vars()) == Some("release") => (u16::MAX, true),
+            Some(e) => (parse_non_zero_u16(e, s, "timeout")?, false),
             None => bail_span!(expr, "{MSG}"),
         };
         let id = match s.chord_groups.len().try_into() {
@@ -1711,6 +3355,7 @@ fn parse_chord_groups(exprs: &[&Spanned<Vec<SExpr>>], s: &mut ParsedState) -> Re
             coords: Vec::new(),
             chords: HashMap::default(),
             timeout,
+            release_only,
         };
         // Read k-v pairs from the configuration
         while let Some(keys_expr) = subexprs.next() {
@@ -1790,6 +3435,7 @@ fn resolve_chord_groups(layers: &mut KanataLayers, s: &ParsedState) -> Result<()
             coords: s.a.sref_vec(group.coords),
             chords: s.a.sref_vec(chords),
             timeout: group.timeout,
+            release_only: group.release_only,
         }))
     }).collect::<Result<Vec<_>>>()?;
 
@@ -1846,6 +3492,9 @@ fn find_chords_coords(chord_groups: &mut [ChordGroup], coord: (u8, u16), action:
             find_chords_coords(chord_groups, coord, left);
             find_chords_coords(chord_groups, coord, right);
         }
+        Action::OnRelease(ac) => {
+            find_chords_coords(chord_groups, coord, ac);
+        }
     }
 }
 
@@ -1909,76 +3558,359 @@ fn fill_chords(
             } else {
                 None
             }
-        }
-        Action::TapDance(&td @ TapDance { actions, .. }) => {
-            let new_actions = actions
-                .iter()
-                .map(|ac| fill_chords(chord_groups, ac, s))
-                .collect::<Vec<_>>();
-            if new_actions.iter().any(|it| it.is_some()) {
-                let new_actions = new_actions
-                    .iter()
-                    .zip(actions)
-                    .map(|(new_ac, ac)| new_ac.map(|v| s.a.sref(v)).unwrap_or(*ac))
-                    .collect::<Vec<_>>();
-                Some(Action::TapDance(s.a.sref(TapDance {
-                    actions: s.a.sref_vec(new_actions),
-                    ..td
-                })))
-            } else {
-                None
+        }
+        Action::TapDance(&td @ TapDance { actions, .. }) => {
+            let new_actions = actions
+                .iter()
+                .map(|ac| fill_chords(chord_groups, ac, s))
+                .collect::<Vec<_>>();
+            if new_actions.iter().any(|it| it.is_some()) {
+                let new_actions = new_actions
+                    .iter()
+                    .zip(actions)
+                    .map(|(new_ac, ac)| new_ac.map(|v| s.a.sref(v)).unwrap_or(*ac))
+                    .collect::<Vec<_>>();
+                Some(Action::TapDance(s.a.sref(TapDance {
+                    actions: s.a.sref_vec(new_actions),
+                    ..td
+                })))
+            } else {
+                None
+            }
+        }
+        Action::Fork(&fcfg @ ForkConfig { left, right, .. }) => {
+            let new_left = fill_chords(chord_groups, &left, s);
+            let new_right = fill_chords(chord_groups, &right, s);
+            if new_left.is_some() || new_right.is_some() {
+                Some(Action::Fork(s.a.sref(ForkConfig {
+                    left: new_left.unwrap_or(left),
+                    right: new_right.unwrap_or(right),
+                    ..fcfg
+                })))
+            } else {
+                None
+            }
+        }
+        Action::OnRelease(ac) => {
+            fill_chords(chord_groups, ac, s).map(|ac| Action::OnRelease(s.a.sref(ac)))
+        }
+    }
+}
+
+fn parse_fake_keys(exprs: &[&Vec<SExpr>], s: &mut ParsedState) -> Result<()> {
+    for expr in exprs {
+        let mut subexprs = check_first_expr(expr.iter(), "deffakekeys")?;
+        // Read k-v pairs from the configuration
+        while let Some(key_name_expr) = subexprs.next() {
+            let key_name = key_name_expr
+                .atom(s.vars())
+                .ok_or_else(|| anyhow_expr!(key_name_expr, "Fake key name must not be a list."))?
+                .to_owned();
+            let action = match subexprs.next() {
+                Some(v) => v,
+                None => bail_expr!(
+                    key_name_expr,
+                    "Fake key name has no action - you should add an action."
+                ),
+            };
+            let action = parse_action(action, s)?;
+            let idx = s.fake_keys.len();
+            log::trace!("inserting {key_name}->{idx}:{action:?}");
+            if s.fake_keys
+                .insert(key_name.clone(), (idx, action))
+                .is_some()
+            {
+                bail_expr!(key_name_expr, "Duplicate fake key: {}", key_name);
+            }
+        }
+    }
+    if s.fake_keys.len() > KEYS_IN_ROW {
+        bail!(
+            "Maximum number of fake keys is {KEYS_IN_ROW}, found {}",
+            s.fake_keys.len()
+        );
+    }
+    Ok(())
+}
+
+/// Parse `(defhooks <event> <action>...)`. The actions are added as fake keys whose names cannot
+/// clash with user-defined fake keys, since they contain a space.
+fn parse_hooks(exprs: &[&Spanned<Vec<SExpr>>], s: &mut ParsedState) -> Result<Hooks> {
+    const EVENTS_MSG: &str = "Unknown hook event. Valid events: tap-hold-hold, tap-hold-tap, \
+        (layer-enter <layer>), (layer-exit <layer>)";
+    let mut hooks = Hooks::default();
+    for expr in exprs {
+        let mut params = expr.t[1..].chunks_exact(2);
+        for pair in params.by_ref() {
+            let (event, hook) = match &pair[0] {
+                SExpr::Atom(a) => match a.t.as_str() {
+                    "tap-hold-hold" => ("tap-hold-hold".to_owned(), &mut hooks.tap_hold_hold),
+                    "tap-hold-tap" => ("tap-hold-tap".to_owned(), &mut hooks.tap_hold_tap),
+                    _ => bail_expr!(&pair[0], "{EVENTS_MSG}"),
+                },
+                SExpr::List(l) => {
+                    let (kind, layer) = match &l.t[..] {
+                        [kind, layer] => match (kind.atom(s.vars()), layer.atom(s.vars())) {
+                            (Some(kind), Some(layer)) => (kind, layer),
+                            _ => bail_expr!(&pair[0], "{EVENTS_MSG}"),
+                        },
+                        _ => bail_expr!(&pair[0], "{EVENTS_MSG}"),
+                    };
+                    let layer_hooks = match kind {
+                        "layer-enter" => &mut hooks.layer_enter,
+                        "layer-exit" => &mut hooks.layer_exit,
+                        _ => bail_expr!(&pair[0], "{EVENTS_MSG}"),
+                    };
+                    let layer_idx = match s.layer_idxs.get(layer) {
+                        Some(idx) => *idx,
+                        None => bail_expr!(&pair[0], "Unknown layer name: {layer}"),
+                    };
+                    if layer_hooks.contains_key(&layer_idx) {
+                        bail_expr!(&pair[0], "Duplicate hook event: {kind} {layer}");
+                    }
+                    let action = parse_action(&pair[1], s)?;
+                    let idx = s.fake_keys.len();
+                    s.fake_keys
+                        .insert(format!("{kind} {layer} hook"), (idx, action));
+                    layer_hooks.insert(layer_idx, get_fake_key_coords(idx));
+                    continue;
+                }
+            };
+            if hook.is_some() {
+                bail_expr!(&pair[0], "Duplicate hook event: {event}");
+            }
+            let action = parse_action(&pair[1], s)?;
+            let idx = s.fake_keys.len();
+            s.fake_keys.insert(format!("{event} hook"), (idx, action));
+            *hook = Some(get_fake_key_coords(idx));
+        }
+        if let [event] = params.remainder() {
+            bail_expr!(
+                event,
+                "This hook event has no action - you should add an action."
+            );
+        }
+    }
+    if s.fake_keys.len() > KEYS_IN_ROW {
+        bail!(
+            "Maximum number of fake keys and hooks is {KEYS_IN_ROW}, found {}",
+            s.fake_keys.len()
+        );
+    }
+    Ok(hooks)
+}
+
+/// Parse `(defshortcodes <code> <text>...)`.
+fn parse_shortcodes(
+    exprs: &[&Spanned<Vec<SExpr>>],
+    s: &ParsedState,
+) -> Result<HashMap<String, String>> {
+    const ERR_MSG: &str = "defshortcodes expects pairs of a short code and its replacement text";
+    let mut shortcodes = HashMap::default();
+    for expr in exprs {
+        let mut params = expr.t[1..].chunks_exact(2);
+        for pair in params.by_ref() {
+            let (code, text) = match (pair[0].atom(s.vars()), pair[1].atom(s.vars())) {
+                (Some(code), Some(text)) => (code, text.trim_matches('"')),
+                _ => bail_expr!(&pair[0], "{ERR_MSG}"),
+            };
+            if code.is_empty()
+                || !code
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "_+-".contains(c))
+            {
+                bail_expr!(
+                    &pair[0],
+                    "Short codes can only contain a-z, 0-9, _, + and -, without the colons"
+                );
+            }
+            if shortcodes
+                .insert(code.to_owned(), text.to_owned())
+                .is_some()
+            {
+                bail_expr!(&pair[0], "Duplicate short code: {code}");
+            }
+        }
+        if let [code] = params.remainder() {
+            bail_expr!(code, "{ERR_MSG}");
+        }
+    }
+    Ok(shortcodes)
+}
+
+/// Parse `(defsnippets <trigger> <expansion>...)`. Expansions can contain the placeholders
+/// `{cursor}`, `{date}`, `{time}` and `{enter}`, and `{{` for a literal `{`.
+fn parse_snippets(exprs: &[&Spanned<Vec<SExpr>>], s: &ParsedState) -> Result<Vec<Snippet>> {
+    const ERR_MSG: &str = "defsnippets expects pairs of a trigger and its expansion";
+    let mut snippets: Vec<Snippet> = vec![];
+    for expr in exprs {
+        let mut params = expr.t[1..].chunks_exact(2);
+        for pair in params.by_ref() {
+            let (trigger, expansion) = match (pair[0].atom(s.vars()), pair[1].atom(s.vars())) {
+                (Some(trigger), Some(expansion)) => {
+                    (trigger.trim_matches('"'), expansion.trim_matches('"'))
+                }
+                _ => bail_expr!(&pair[0], "{ERR_MSG}"),
+            };
+            if trigger.is_empty() || !trigger.chars().all(|c| c.is_ascii_graphic()) {
+                bail_expr!(
+                    &pair[0],
+                    "Snippet triggers can only contain ASCII characters other than spaces"
+                );
+            }
+            if snippets.iter().any(|s| s.trigger == trigger) {
+                bail_expr!(&pair[0], "Duplicate snippet trigger: {trigger}");
             }
+            let expansion =
+                parse_snippet_expansion(expansion).map_err(|e| anyhow_expr!(&pair[1], "{e}"))?;
+            snippets.push(Snippet {
+                trigger: trigger.to_owned(),
+                expansion,
+            });
         }
-        Action::Fork(&fcfg @ ForkConfig { left, right, .. }) => {
-            let new_left = fill_chords(chord_groups, &left, s);
-            let new_right = fill_chords(chord_groups, &right, s);
-            if new_left.is_some() || new_right.is_some() {
-                Some(Action::Fork(s.a.sref(ForkConfig {
-                    left: new_left.unwrap_or(left),
-                    right: new_right.unwrap_or(right),
-                    ..fcfg
-                })))
-            } else {
-                None
+        if let [trigger] = params.remainder() {
+            bail_expr!(trigger, "{ERR_MSG}");
+        }
+    }
+    Ok(snippets)
+}
+
+fn parse_snippet_expansion(expansion: &str) -> std::result::Result<Vec<SnippetPart>, String> {
+    let mut parts = vec![];
+    let mut text = String::new();
+    let mut rest = expansion;
+    while let Some(start) = rest.find('{') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix("{{") {
+            text.push('{');
+            rest = after;
+            continue;
+        }
+        let end = rest
+            .find('}')
+            .ok_or("Unclosed placeholder in snippet expansion, use {{ for a literal {")?;
+        let part = match &rest[1..end] {
+            "enter" => {
+                text.push('\n');
+                rest = &rest[end + 1..];
+                continue;
+            }
+            "cursor" if parts.contains(&SnippetPart::Cursor) => {
+                return Err("Snippet expansions can only contain one {cursor}".into())
+            }
+            "cursor" => SnippetPart::Cursor,
+            "date" => SnippetPart::Date,
+            "time" => SnippetPart::Time,
+            p => {
+                return Err(format!(
+                    "Unknown placeholder {{{p}}}. Valid placeholders: \
+                    {{cursor}} {{date}} {{time}} {{enter}}"
+                ))
             }
+        };
+        if !text.is_empty() {
+            parts.push(SnippetPart::Text(std::mem::take(&mut text)));
         }
+        parts.push(part);
+        rest = &rest[end + 1..];
+    }
+    text.push_str(rest);
+    if !text.is_empty() {
+        parts.push(SnippetPart::Text(text));
     }
+    Ok(parts)
 }
 
-fn parse_fake_keys(exprs: &[&Vec<SExpr>], s: &mut ParsedState) -> Result<()> {
+/// Parse `(deflauncher <name> <action>...)`. Like hooks, the actions are added as fake keys whose
+/// names contain a space.
+fn parse_launcher(
+    exprs: &[&Spanned<Vec<SExpr>>],
+    s: &mut ParsedState,
+) -> Result<Vec<(String, (u8, u16))>> {
+    let mut entries: Vec<(String, (u8, u16))> = vec![];
     for expr in exprs {
-        let mut subexprs = check_first_expr(expr.iter(), "deffakekeys")?;
-        // Read k-v pairs from the configuration
-        while let Some(key_name_expr) = subexprs.next() {
-            let key_name = key_name_expr
-                .atom(s.vars())
-                .ok_or_else(|| anyhow_expr!(key_name_expr, "Fake key name must not be a list."))?
-                .to_owned();
-            let action = match subexprs.next() {
-                Some(v) => v,
-                None => bail_expr!(
-                    key_name_expr,
-                    "Fake key name has no action - you should add an action."
-                ),
+        let mut params = expr.t[1..].chunks_exact(2);
+        for pair in params.by_ref() {
+            let name = match pair[0].atom(s.vars()) {
+                Some(name) => name.to_owned(),
+                None => bail_expr!(&pair[0], "Launcher entry names must be strings"),
             };
-            let action = parse_action(action, s)?;
+            if entries.iter().any(|(n, _)| *n == name) {
+                bail_expr!(&pair[0], "Duplicate launcher entry: {name}");
+            }
+            let action = parse_action(&pair[1], s)?;
             let idx = s.fake_keys.len();
-            log::trace!("inserting {key_name}->{idx}:{action:?}");
-            if s.fake_keys
-                .insert(key_name.clone(), (idx, action))
-                .is_some()
-            {
-                bail_expr!(key_name_expr, "Duplicate fake key: {}", key_name);
+            s.fake_keys
+                .insert(format!("{name} launcher"), (idx, action));
+            entries.push((name, get_fake_key_coords(idx)));
+        }
+        if let [name] = params.remainder() {
+            bail_expr!(
+                name,
+                "This launcher entry has no action - you should add an action."
+            );
+        }
+    }
+    if s.fake_keys.len() > KEYS_IN_ROW {
+        bail!(
+            "Maximum number of fake keys, hooks and launcher entries is {KEYS_IN_ROW}, found {}",
+            s.fake_keys.len()
+        );
+    }
+    Ok(entries)
+}
+
+/// Parse `(defmodchords (<modifier>...) <action>...)`. The actions are added as fake keys, like
+/// the launcher entries, and are tapped when all modifiers of the chord are pressed together.
+fn parse_mod_chords(exprs: &[&Spanned<Vec<SExpr>>], s: &mut ParsedState) -> Result<Vec<ModChord>> {
+    const ERR_MSG: &str = "Modifier chords must be a list of two or more modifiers: \
+        lsft rsft lctl rctl lalt ralt lmet rmet";
+    let mut chords: Vec<ModChord> = vec![];
+    for expr in exprs {
+        let mut params = expr.t[1..].chunks_exact(2);
+        for pair in params.by_ref() {
+            let mod_exprs = match pair[0].list(s.vars()) {
+                Some(mods) if mods.len() >= 2 => mods,
+                _ => bail_expr!(&pair[0], "{ERR_MSG}"),
+            };
+            let mut mods = vec![];
+            for mod_expr in mod_exprs {
+                match mod_expr.atom(s.vars()).and_then(str_to_oscode) {
+                    Some(osc) if MODIFIERS.contains(&osc) && !mods.contains(&osc) => mods.push(osc),
+                    _ => bail_expr!(mod_expr, "{ERR_MSG}"),
+                }
+            }
+            mods.sort_by_key(|osc| u16::from(*osc));
+            if chords.iter().any(|(m, _)| *m == mods) {
+                bail_expr!(&pair[0], "Duplicate modifier chord");
             }
+            let action = parse_action(&pair[1], s)?;
+            let idx = s.fake_keys.len();
+            let name = mod_exprs
+                .iter()
+                .filter_map(|m| m.atom(s.vars()))
+                .collect::<Vec<_>>()
+                .join("+");
+            s.fake_keys
+                .insert(format!("{name} modchord"), (idx, action));
+            chords.push((mods, get_fake_key_coords(idx)));
+        }
+        if let [chord] = params.remainder() {
+            bail_expr!(
+                chord,
+                "This modifier chord has no action - you should add an action."
+            );
         }
     }
     if s.fake_keys.len() > KEYS_IN_ROW {
         bail!(
-            "Maximum number of fake keys is {KEYS_IN_ROW}, found {}",
+            "Maximum number of fake keys, hooks, launcher entries and modifier chords is \
+            {KEYS_IN_ROW}, found {}",
             s.fake_keys.len()
         );
     }
-    Ok(())
+    Ok(chords)
 }
 
 fn parse_fake_key_op(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAction> {
@@ -2037,7 +3969,7 @@ fn parse_fake_key_op_coord_action(
     Ok((Coord { x, y }, action))
 }
 
-fn get_fake_key_coords<T: Into<usize>>(y: T) -> (u8, u16) {
+pub fn get_fake_key_coords<T: Into<usize>>(y: T) -> (u8, u16) {
     let y: usize = y.into();
     (1, y as u16)
 }
@@ -2059,11 +3991,9 @@ fn parse_delay(
     s: &ParsedState,
 ) -> Result<&'static KanataAction> {
     const ERR_MSG: &str = "fakekey-delay expects a single number (ms, 0-65535)";
-    let delay = ac_params[0]
-        .atom(s.vars())
-        .map(str::parse::<u16>)
-        .ok_or_else(|| anyhow!("{ERR_MSG}"))?
-        .map_err(|e| anyhow!("{ERR_MSG}: {e}"))?;
+    let delay = eval_number(&ac_params[0], s.vars())?
+        .and_then(|n| u16::try_from(n).ok())
+        .ok_or_else(|| anyhow!("{ERR_MSG}"))?;
     Ok(s.a
         .sref(Action::Custom(s.a.sref(s.a.sref_slice(match is_release {
             false => CustomAction::Delay(delay),
@@ -2072,9 +4002,8 @@ fn parse_delay(
 }
 
 fn parse_distance(expr: &SExpr, s: &ParsedState, label: &str) -> Result<u16> {
-    expr.atom(s.vars())
-        .map(str::parse::<u16>)
-        .and_then(|d| match d {
+    eval_number(expr, s.vars())?
+        .and_then(|d| match u16::try_from(d) {
             Ok(dist @ 1..=30000) => Some(dist),
             _ => None,
         })
@@ -2126,27 +4055,212 @@ fn parse_move_mouse_accel(
     direction: MoveDirection,
     s: &ParsedState,
 ) -> Result<&'static KanataAction> {
-    if ac_params.len() != 4 {
-        bail!("movemouse-accel expects four parameters, found {}\n<interval (ms)> <acceleration time (ms)> <min_distance> <max_distance>", ac_params.len());
+    if !matches!(ac_params.len(), 4 | 5) {
+        bail!("movemouse-accel expects four or five parameters, found {}\n<interval (ms)> <acceleration time (ms)> <min_distance> <max_distance> [linear|quadratic]", ac_params.len());
     }
     let interval = parse_non_zero_u16(&ac_params[0], s, "interval")?;
-    let accel_time = parse_non_zero_u16(&ac_params[1], s, "acceleration time")?;
-    let min_distance = parse_distance(&ac_params[2], s, "min distance")?;
-    let max_distance = parse_distance(&ac_params[3], s, "max distance")?;
-    if min_distance > max_distance {
-        bail!("min distance should be less than max distance")
-    }
+    let curve = match ac_params.get(4) {
+        Some(curve) => parse_accel_curve(curve, s)?,
+        None => AccelCurve::Linear,
+    };
+    let accel = parse_mouse_accel(curve, &ac_params[1..4], s)?;
     Ok(s.a.sref(Action::Custom(s.a.sref(s.a.sref_slice(
         CustomAction::MoveMouseAccel {
             direction,
             interval,
-            accel_time,
-            min_distance,
-            max_distance,
+            accel,
         },
     )))))
 }
 
+fn parse_accel_curve(expr: &SExpr, s: &ParsedState) -> Result<AccelCurve> {
+    match expr.atom(s.vars()) {
+        Some("linear") => Ok(AccelCurve::Linear),
+        Some("quadratic") => Ok(AccelCurve::Quadratic),
+        _ => bail_expr!(expr, "Acceleration curve must be linear or quadratic"),
+    }
+}
+
+/// Parse `<acceleration time (ms)> <min_distance> <max_distance>`.
+fn parse_mouse_accel(curve: AccelCurve, params: &[SExpr], s: &ParsedState) -> Result<MouseAccel> {
+    let accel_time = parse_non_zero_u16(&params[0], s, "acceleration time")?;
+    let min_distance = parse_distance(&params[1], s, "min distance")?;
+    let max_distance = parse_distance(&params[2], s, "max distance")?;
+    if min_distance > max_distance {
+        bail!("min distance should be less than max distance")
+    }
+    Ok(MouseAccel {
+        curve,
+        accel_time,
+        min_distance,
+        max_distance,
+    })
+}
+
+/// Parse `(defmouseaccel <layer> <linear|quadratic> <acceleration time (ms)> <min_distance>
+/// <max_distance>)`, the acceleration of mouse movements while the layer is active.
+fn parse_mouse_accel_layers(
+    exprs: &[&Spanned<Vec<SExpr>>],
+    s: &ParsedState,
+) -> Result<Vec<(usize, MouseAccel)>> {
+    const ERR_MSG: &str = "defmouseaccel expects five parameters: <layer> <linear|quadratic> \
+        <acceleration time (ms)> <min_distance> <max_distance>";
+    let mut layers = vec![];
+    for expr in exprs {
+        let [name_expr, curve, params @ ..] = &expr.t[1..] else {
+            bail_span!(expr, "{ERR_MSG}");
+        };
+        if params.len() != 3 {
+            bail_span!(expr, "{ERR_MSG}");
+        }
+        let name = name_expr.atom(s.vars()).unwrap_or_default();
+        let layer = match s.layer_idxs.get(name) {
+            Some(idx) => *idx,
+            None => bail_expr!(name_expr, "Unknown layer name in defmouseaccel"),
+        };
+        if layers.iter().any(|(l, _)| *l == layer * 2) {
+            bail_expr!(name_expr, "This layer already has a defmouseaccel");
+        }
+        let accel = parse_mouse_accel(parse_accel_curve(curve, s)?, params, s)?;
+        // Both keyberon versions of the layer have the acceleration.
+        layers.push((layer * 2, accel));
+        layers.push((layer * 2 + 1, accel));
+    }
+    Ok(layers)
+}
+
+/// Parse `(defmodtranslation <layer> <modifier> <key> ...)`, the keys that are output instead of
+/// the modifiers while the layer is active, e.g. the language switch keys of a keyboard layout.
+fn parse_mod_translations(
+    exprs: &[&Spanned<Vec<SExpr>>],
+    s: &ParsedState,
+) -> Result<Vec<(usize, Vec<(OsCode, OsCode)>)>> {
+    const ERR_MSG: &str = "defmodtranslation expects a layer name followed by pairs of a \
+        modifier (lsft rsft lctl rctl lalt ralt lmet rmet) and the key to output instead";
+    let mut layers = vec![];
+    for expr in exprs {
+        let (name_expr, pairs) = match &expr.t[1..] {
+            [name, pairs @ ..] if !pairs.is_empty() && pairs.len() % 2 == 0 => (name, pairs),
+            _ => bail_span!(expr, "{ERR_MSG}"),
+        };
+        let name = name_expr.atom(s.vars()).unwrap_or_default();
+        let layer = match s.layer_idxs.get(name) {
+            Some(idx) => *idx,
+            None => bail_expr!(name_expr, "Unknown layer name in defmodtranslation"),
+        };
+        if layers.iter().any(|(l, _)| *l == layer * 2) {
+            bail_expr!(name_expr, "This layer already has a defmodtranslation");
+        }
+        let mut translations: Vec<(OsCode, OsCode)> = vec![];
+        for pair in pairs.chunks(2) {
+            let from = match pair[0].atom(s.vars()).and_then(str_to_oscode) {
+                Some(osc) if MODIFIERS.contains(&osc) => osc,
+                _ => bail_expr!(&pair[0], "{ERR_MSG}"),
+            };
+            if translations.iter().any(|(f, _)| *f == from) {
+                bail_expr!(&pair[0], "This modifier is already translated on this layer");
+            }
+            let to = match pair[1].atom(s.vars()).and_then(str_to_oscode) {
+                Some(osc) => osc,
+                None => bail_expr!(&pair[1], "Unknown key name in defmodtranslation"),
+            };
+            translations.push((from, to));
+        }
+        // Both keyberon versions of the layer have the translations.
+        layers.push((layer * 2, translations.clone()));
+        layers.push((layer * 2 + 1, translations));
+    }
+    Ok(layers)
+}
+
+/// Parse `(defmodal <layer-name> (<key> <chord> <layer-name>?)...)` items into the operators of
+/// the modal editing layers. A count is typed with the digits that the layer outputs, so neither
+/// digits nor modifiers can be operators.
+fn parse_modal_layers(
+    exprs: &[&Spanned<Vec<SExpr>>],
+    s: &ParsedState,
+) -> Result<Vec<(usize, Vec<ModalOperator>)>> {
+    const ERR_MSG: &str = "defmodal expects a layer name followed by operators of the form \
+        (<key> <chord>) or (<key> <chord> <layer-name>)";
+    let layer_idx = |expr: &SExpr| -> Result<usize> {
+        let name = expr.atom(s.vars()).unwrap_or_default();
+        match s.layer_idxs.get(name) {
+            Some(idx) => Ok(*idx),
+            None => bail_expr!(expr, "Unknown layer name in defmodal"),
+        }
+    };
+    let mut layers = vec![];
+    for expr in exprs {
+        let (name_expr, operator_exprs) = match &expr.t[1..] {
+            [name, operators @ ..] => (name, operators),
+            _ => bail_span!(expr, "{ERR_MSG}"),
+        };
+        let layer = layer_idx(name_expr)?;
+        if layers.iter().any(|(l, _)| *l == layer * 2) {
+            bail_expr!(name_expr, "This layer already has a defmodal");
+        }
+        let mut operators: Vec<ModalOperator> = vec![];
+        for operator_expr in operator_exprs {
+            let (key_expr, chord_expr, then_expr) = match operator_expr.list(s.vars()) {
+                Some([key, chord]) => (key, chord, None),
+                Some([key, chord, then]) => (key, chord, Some(then)),
+                _ => bail_expr!(operator_expr, "{ERR_MSG}"),
+            };
+            let key = match key_expr.atom(s.vars()).and_then(str_to_oscode) {
+                Some(osc) if MODIFIERS.contains(&osc) || count_digit(osc).is_some() => {
+                    bail_expr!(key_expr, "An operator cannot be a modifier or a digit")
+                }
+                Some(osc) => osc,
+                None => bail_expr!(key_expr, "Unknown key name in defmodal"),
+            };
+            if operators.iter().any(|op| op.key == key) {
+                bail_expr!(key_expr, "This key is already an operator on this layer");
+            }
+            let invalid_chord = || error_expr(chord_expr, "Invalid chord, e.g. C-x, in defmodal");
+            let chord = chord_expr.atom(s.vars()).ok_or_else(invalid_chord)?;
+            let (mods, chord_key) = parse_mod_prefix(chord).map_err(|_| invalid_chord())?;
+            let chord_key = str_to_oscode(chord_key).ok_or_else(invalid_chord)?;
+            let chord = mods
+                .into_iter()
+                .map(OsCode::from)
+                .chain(std::iter::once(chord_key))
+                .collect();
+            let then_layer = match then_expr {
+                Some(then) => Some(layer_idx(then)? * 2),
+                None => None,
+            };
+            operators.push(ModalOperator {
+                key,
+                chord,
+                then_layer,
+            });
+        }
+        // Both keyberon versions of the layer are modal.
+        layers.push((layer * 2, operators.clone()));
+        layers.push((layer * 2 + 1, operators));
+    }
+    Ok(layers)
+}
+
+/// The digit of a key of the number row or numpad, which `count-layers` and `defmodal` layers
+/// capture as a count.
+pub fn count_digit(osc: OsCode) -> Option<u32> {
+    use OsCode::*;
+    match osc {
+        KEY_0 | KEY_KP0 => Some(0),
+        KEY_1 | KEY_KP1 => Some(1),
+        KEY_2 | KEY_KP2 => Some(2),
+        KEY_3 | KEY_KP3 => Some(3),
+        KEY_4 | KEY_KP4 => Some(4),
+        KEY_5 | KEY_KP5 => Some(5),
+        KEY_6 | KEY_KP6 => Some(6),
+        KEY_7 | KEY_KP7 => Some(7),
+        KEY_8 | KEY_KP8 => Some(8),
+        KEY_9 | KEY_KP9 => Some(9),
+        _ => None,
+    }
+}
+
 fn parse_set_mouse(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAction> {
     if ac_params.len() != 2 {
         bail!(
@@ -2161,6 +4275,124 @@ fn parse_set_mouse(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static Kana
     )))
 }
 
+/// The regions of `mouse-warp`, the cells of a 3x3 grid over the screen in reading order.
+const MOUSE_WARP_REGIONS: [&str; 9] = [
+    "top-left",
+    "top",
+    "top-right",
+    "left",
+    "center",
+    "right",
+    "bottom-left",
+    "bottom",
+    "bottom-right",
+];
+
+/// Parse `(mouse-warp <region>)` or `(mouse-warp <x%> <y%>)` into a `setmouse` action.
+fn parse_mouse_warp(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAction> {
+    const ERR_MSG: &str = "mouse-warp expects a region or two percentages: <x%> <y%>";
+    let to_coord = |numerator: u32, denominator: u32| -> u16 {
+        (u32::from(u16::MAX) * numerator / denominator) as u16
+    };
+    let (x, y) = match ac_params {
+        [region] => {
+            let name = region
+                .atom(s.vars())
+                .ok_or_else(|| anyhow_expr!(region, "{ERR_MSG}"))?;
+            let i = MOUSE_WARP_REGIONS
+                .iter()
+                .position(|&r| r == name)
+                .ok_or_else(|| {
+                    anyhow_expr!(
+                        region,
+                        "Unknown mouse-warp region {name}. Valid regions: {}",
+                        MOUSE_WARP_REGIONS.join(" ")
+                    )
+                })? as u32;
+            (to_coord(2 * (i % 3) + 1, 6), to_coord(2 * (i / 3) + 1, 6))
+        }
+        [x, y] => {
+            let x = parse_u16(x, s, "x%")?;
+            let y = parse_u16(y, s, "y%")?;
+            if x > 100 || y > 100 {
+                bail!("mouse-warp percentages must be from 0 to 100, found {x} {y}");
+            }
+            (to_coord(x.into(), 100), to_coord(y.into(), 100))
+        }
+        _ => bail!("{ERR_MSG}, found {} parameters", ac_params.len()),
+    };
+    Ok(s.a.sref(Action::Custom(
+        s.a.sref(s.a.sref_slice(CustomAction::SetMouse { x, y })),
+    )))
+}
+
+/// Parse `(mouse-drag <button>)`, where the button is named like the mouse button actions.
+fn parse_mouse_drag(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAction> {
+    const ERR_MSG: &str = "mouse-drag expects one parameter: <mlft|mrgt|mmid|mfwd|mbck>";
+    let [btn] = ac_params else {
+        bail!("{ERR_MSG}, found {}", ac_params.len());
+    };
+    let btn = match btn.atom(s.vars()) {
+        Some("mlft" | "mouseleft") => Btn::Left,
+        Some("mrgt" | "mouseright") => Btn::Right,
+        Some("mmid" | "mousemid") => Btn::Mid,
+        Some("mfwd" | "mouseforward") => Btn::Forward,
+        Some("mbck" | "mousebackward") => Btn::Backward,
+        _ => bail_expr!(btn, "{ERR_MSG}"),
+    };
+    Ok(s.a.sref(Action::Custom(
+        s.a.sref(s.a.sref_slice(CustomAction::MouseDrag(btn))),
+    )))
+}
+
+/// Parse `(dwell-click on|off|toggle)`.
+fn parse_dwell_click(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAction> {
+    const ERR_MSG: &str = "dwell-click expects one parameter: <on|off|toggle>";
+    let [param] = ac_params else {
+        bail!("{ERR_MSG}, found {}", ac_params.len());
+    };
+    let action = match param.atom(s.vars()) {
+        Some("on") => DwellClickAction::On,
+        Some("off") => DwellClickAction::Off,
+        Some("toggle") => DwellClickAction::Toggle,
+        _ => bail_expr!(param, "{ERR_MSG}"),
+    };
+    Ok(s.a.sref(Action::Custom(
+        s.a.sref(s.a.sref_slice(CustomAction::DwellClick(action))),
+    )))
+}
+
+fn parse_kvm_target(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAction> {
+    const ERR_MSG: &str = "kvm-target expects one parameter: <local|next|target name>";
+    let [param] = ac_params else {
+        bail!("{ERR_MSG}, found {}", ac_params.len());
+    };
+    let switch = match param.atom(s.vars()) {
+        Some("local") => KvmSwitch::Local,
+        Some("next") => KvmSwitch::Next,
+        Some(name) => KvmSwitch::Target(name.to_owned()),
+        None => bail_expr!(param, "{ERR_MSG}"),
+    };
+    Ok(s.a.sref(Action::Custom(
+        s.a.sref(s.a.sref_slice(CustomAction::KvmTarget(switch))),
+    )))
+}
+
+fn parse_route_output(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAction> {
+    const ERR_MSG: &str = "route-output expects one parameter: <target name|auto>";
+    let [param] = ac_params else {
+        bail!("{ERR_MSG}, found {}", ac_params.len());
+    };
+    let target = match param.atom(s.vars()) {
+        Some("auto") => None,
+        Some(target) => Some(target.to_owned()),
+        None => bail_expr!(param, "{ERR_MSG}"),
+    };
+    Ok(s.a.sref(Action::Custom(
+        s.a.sref(s.a.sref_slice(CustomAction::RouteOutput(target))),
+    )))
+}
+
 fn parse_dynamic_macro_record(
     ac_params: &[SExpr],
     s: &ParsedState,
@@ -2372,6 +4604,27 @@ fn parse_fork(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAct
     }))))
 }
 
+fn parse_on_release(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAction> {
+    const ERR_STR: &str = "on-release expects 1 param: <action>";
+    if ac_params.len() != 1 {
+        bail!("{ERR_STR}\nFound {} params instead of 1", ac_params.len());
+    }
+    let action = parse_action(&ac_params[0], s)?;
+    Ok(s.a.sref(Action::OnRelease(action)))
+}
+
+fn parse_press_release(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAction> {
+    const ERR_STR: &str = "press-release expects 2 params: <press-action> <release-action>";
+    if ac_params.len() != 2 {
+        bail!("{ERR_STR}\nFound {} params instead of 2", ac_params.len());
+    }
+    let press = *parse_action(&ac_params[0], s)?;
+    let release = Action::OnRelease(parse_action(&ac_params[1], s)?);
+    Ok(s.a.sref(Action::MultipleActions(
+        s.a.sref(s.a.sref_vec(vec![press, release])),
+    )))
+}
+
 fn parse_caps_word(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAction> {
     const ERR_STR: &str = "caps-word expects 1 param: <timeout>";
     if ac_params.len() != 1 {
@@ -2469,6 +4722,128 @@ fn parse_caps_word_custom(ac_params: &[SExpr], s: &ParsedState) -> Result<&'stat
     )))
 }
 
+fn parse_morse(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAction> {
+    const ERR_MSG: &str = "morse expects 3 parameters: <dash-threshold> <letter-gap> <word-gap>";
+    if ac_params.len() != 3 {
+        bail!("{ERR_MSG}, found {}", ac_params.len());
+    }
+    let dash_threshold = parse_non_zero_u16(&ac_params[0], s, "dash-threshold")?;
+    let letter_gap = parse_non_zero_u16(&ac_params[1], s, "letter-gap")?;
+    let word_gap = parse_non_zero_u16(&ac_params[2], s, "word-gap")?;
+    if word_gap <= letter_gap {
+        bail_expr!(&ac_params[2], "word-gap must be larger than letter-gap");
+    }
+    Ok(s.a.sref(Action::Custom(s.a.sref(s.a.sref_slice(
+        CustomAction::Morse(MorseCfg {
+            dash_threshold,
+            letter_gap,
+            word_gap,
+        }),
+    )))))
+}
+
+fn parse_unicode_input(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAction> {
+    const ERR_MSG: &str = "unicode-input expects zero parameters or one of: hex dec";
+    let radix = match ac_params {
+        [] => 16,
+        [radix] => match radix.atom(s.vars()) {
+            Some("hex") => 16,
+            Some("dec") => 10,
+            _ => bail_expr!(radix, "{ERR_MSG}"),
+        },
+        _ => bail!(ERR_MSG),
+    };
+    Ok(s.a.sref(Action::Custom(
+        s.a.sref(s.a.sref_slice(CustomAction::UnicodeInput(radix))),
+    )))
+}
+
+fn parse_swap_hands(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAction> {
+    const ERR_MSG: &str = "swap-hands expects zero or more (key key) pairs";
+    let pairs = parse_mirror_pairs(ac_params, ERR_MSG)?
+        .into_iter()
+        .map(|(osc, mirrored)| (osc, str_to_oscode(&mirrored).expect("valid mirror key")))
+        .collect::<Vec<_>>();
+    Ok(s.a.sref(Action::Custom(
+        s.a.sref(s.a.sref_slice(CustomAction::SwapHands(s.a.sref_vec(pairs)))),
+    )))
+}
+
+fn parse_set_var(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAction> {
+    const ERR_MSG: &str = "set-var expects 2 parameters: <variable-name> <value>";
+    let (name, value) = match ac_params {
+        [name, value] => match (name.atom(s.vars()), value.atom(s.vars())) {
+            (Some(name), Some(value)) => (name.to_owned(), value.to_owned()),
+            _ => bail!("{ERR_MSG}"),
+        },
+        _ => bail!("{ERR_MSG}, found {}", ac_params.len()),
+    };
+    Ok(s.a.sref(Action::Custom(
+        s.a.sref(s.a.sref_slice(CustomAction::SetVar { name, value })),
+    )))
+}
+
+fn parse_switch_var(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAction> {
+    const ERR_MSG: &str =
+        "switch-var expects a variable name followed by pairs of <value> <action>";
+    let name = match ac_params.first().and_then(|name| name.atom(s.vars())) {
+        Some(name) => name.to_owned(),
+        None => bail!("{ERR_MSG}"),
+    };
+    let mut params = ac_params[1..].chunks_exact(2);
+    let mut cases = vec![];
+    for pair in params.by_ref() {
+        let value = match pair[0].atom(s.vars()) {
+            Some("_") => None,
+            Some(value) => Some(value.to_owned()),
+            None => bail_expr!(&pair[0], "{ERR_MSG}"),
+        };
+        let action = parse_action(&pair[1], s)?;
+        cases.push(SwitchVarCase {
+            value,
+            action: ActionRef(action),
+        });
+    }
+    if let [value] = params.remainder() {
+        bail_expr!(
+            value,
+            "This value has no action - you should add an action."
+        );
+    }
+    if cases.is_empty() {
+        bail!("{ERR_MSG}");
+    }
+    Ok(s.a.sref(Action::Custom(s.a.sref(s.a.sref_slice(
+        CustomAction::SwitchVar {
+            name,
+            cases: s.a.sref_vec(cases),
+        },
+    )))))
+}
+
+fn parse_cooldown(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAction> {
+    const ERR_MSG: &str = "cooldown expects 2 parameters: <ms> <action>";
+    let [ms, action] = ac_params else {
+        bail!("{ERR_MSG}, found {}", ac_params.len());
+    };
+    let ms = parse_non_zero_u16(ms, s, "cooldown")?;
+    let action = ActionRef(parse_action(action, s)?);
+    Ok(s.a.sref(Action::Custom(
+        s.a.sref(s.a.sref_slice(CustomAction::Cooldown { ms, action })),
+    )))
+}
+
+fn parse_counted(ac_params: &[SExpr], s: &ParsedState) -> Result<&'static KanataAction> {
+    const ERR_MSG: &str = "counted expects 1 parameter: <action>";
+    let [action] = ac_params else {
+        bail!("{ERR_MSG}, found {}", ac_params.len());
+    };
+    let action = ActionRef(parse_action(action, s)?);
+    Ok(s.a.sref(Action::Custom(
+        s.a.sref(s.a.sref_slice(CustomAction::Counted(action))),
+    )))
+}
+
 fn parse_macro_record_stop_truncate(
     ac_params: &[SExpr],
     s: &ParsedState,
@@ -2496,13 +4871,7 @@ fn create_key_outputs(layers: &KanataLayers, overrides: &Overrides) -> KeyOutput
             };
             add_key_output_from_action_to_key_pos(osc_slot, action, &mut layer_outputs, overrides);
         }
-        outs.push(layer_outputs);
-    }
-    for layer_outs in outs.iter_mut() {
-        for keys_out in layer_outs.values_mut() {
-            keys_out.shrink_to_fit();
-        }
-        layer_outs.shrink_to_fit();
+        outs.push(layer_outputs.into());
     }
     outs.shrink_to_fit();
     outs
@@ -2550,6 +4919,9 @@ fn add_key_output_from_action_to_key_pos(
             add_key_output_from_action_to_key_pos(osc_slot, left, outputs, overrides);
             add_key_output_from_action_to_key_pos(osc_slot, right, outputs, overrides);
         }
+        Action::OnRelease(ac) => {
+            add_key_output_from_action_to_key_pos(osc_slot, ac, outputs, overrides);
+        }
         Action::Chords(ChordsGroup { chords, .. }) => {
             for (_, ac) in chords.iter() {
                 add_key_output_from_action_to_key_pos(osc_slot, ac, outputs, overrides);
@@ -2592,6 +4964,17 @@ fn add_kc_output(
 }
 
 /// Create a layout from `layers::LAYERS`.
-fn create_layout(layers: Box<KanataLayers>, a: Arc<Allocations>) -> KanataLayout {
-    KanataLayout::new(Layout::new(a.bref(layers)), a)
+fn create_layout(
+    layers: Box<KanataLayers>,
+    layer_conditions: Vec<(usize, Vec<u16>)>,
+    a: Arc<Allocations>,
+) -> KanataLayout {
+    let mut layout = Layout::new(a.bref(layers));
+    layout.layer_conditions = a.sref_vec(
+        layer_conditions
+            .into_iter()
+            .map(|(layer, mods)| (layer, a.sref_vec(mods)))
+            .collect(),
+    );
+    KanataLayout::new(layout, a)
 }
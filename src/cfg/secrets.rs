@@ -0,0 +1,135 @@
+//! Secrets read from an encrypted file named by `defsecrets`, so that commands and snippet
+//! expansions with private text can be kept in a public configuration.
+//!
+//! The file is decrypted with the `age` tool and holds `name=value` lines. The configuration
+//! refers to a secret with `${secret:name}`, which is replaced like an environment variable. The
+//! decrypted text is only kept in memory. It is decrypted once per change of the file, so live
+//! reload does not ask for a passphrase again.
+
+use super::*;
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+const DEFSECRETS: &str = "defsecrets";
+
+/// The prefix of a secret's name in `${secret:name}`.
+pub(super) const SECRET_PREFIX: &str = "secret:";
+
+type DecryptedFile = (PathBuf, Option<SystemTime>, HashMap<String, String>);
+
+static DECRYPTED: Mutex<Vec<DecryptedFile>> = Mutex::new(Vec::new());
+
+/// Decrypts the file of the `defsecrets` item in `text`, if there is one, and returns its secrets.
+/// Paths are relative to the directory of `name`.
+pub(super) fn load_secrets(name: &str, text: &str) -> Result<HashMap<String, String>> {
+    let Ok(root_exprs) = sexpr::parse(text) else {
+        return Ok(HashMap::default());
+    };
+    let mut items = root_exprs
+        .iter()
+        .filter(gen_first_atom_filter_spanned(DEFSECRETS));
+    let Some(expr) = items.next() else {
+        return Ok(HashMap::default());
+    };
+    if let Some(extra) = items.next() {
+        bail_span!(
+            extra,
+            "Only one {DEFSECRETS} is allowed, found more. Delete the extras."
+        )
+    }
+
+    const ERR_MSG: &str = "defsecrets expects a file path, optionally followed by: identity <key file>";
+    let path = |expr: &SExpr| -> Result<PathBuf> {
+        let path = expr
+            .atom(None)
+            .ok_or_else(|| anyhow_expr!(expr, "{ERR_MSG}"))?
+            .trim_matches('"');
+        let path = interpolate_env_str(path, |name| std::env::var(name).ok())
+            .map_err(|e| anyhow_expr!(expr, "{e}"))?;
+        let path = match path.strip_prefix("~/") {
+            Some(rest) => std::env::var_os("HOME")
+                .map(|home| PathBuf::from(home).join(rest))
+                .unwrap_or_else(|| PathBuf::from(&path)),
+            None => PathBuf::from(&path),
+        };
+        Ok(Path::new(name).parent().unwrap_or(Path::new("")).join(path))
+    };
+    let (file, identity) = match &expr.t[1..] {
+        [file] => (path(file)?, None),
+        [file, SExpr::Atom(opt), identity] if opt.t == "identity" => {
+            (path(file)?, Some(path(identity)?))
+        }
+        _ => bail_span!(expr, "{ERR_MSG}"),
+    };
+
+    let modified = std::fs::metadata(&file)
+        .map_err(|e| anyhow_span!(expr, "Failed to read {}: {e}", file.display()))?
+        .modified()
+        .ok();
+    let mut decrypted = DECRYPTED.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((_, _, secrets)) = decrypted
+        .iter()
+        .find(|(path, time, _)| *path == file && *time == modified)
+    {
+        return Ok(secrets.clone());
+    }
+    let plain_text = decrypt(&file, identity.as_deref()).map_err(|e| anyhow_span!(expr, "{e}"))?;
+    let secrets = parse_secrets(&plain_text).map_err(|e| {
+        anyhow_span!(
+            expr,
+            "{} does not hold name=value lines: {e}",
+            file.display()
+        )
+    })?;
+    decrypted.retain(|(path, _, _)| *path != file);
+    decrypted.push((file, modified, secrets.clone()));
+    Ok(secrets)
+}
+
+/// Runs `age --decrypt`. Without an identity, age asks for the passphrase of the file on the
+/// terminal.
+fn decrypt(file: &Path, identity: Option<&Path>) -> std::result::Result<String, String> {
+    let mut cmd = Command::new("age");
+    cmd.arg("--decrypt");
+    if let Some(identity) = identity {
+        cmd.arg("--identity").arg(identity);
+    }
+    let output = cmd
+        .arg(file)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("Failed to run age to decrypt {}: {e}", file.display()))?;
+    if !output.status.success() {
+        return Err(format!(
+            "age could not decrypt {}: {}",
+            file.display(),
+            output.status
+        ));
+    }
+    String::from_utf8(output.stdout)
+        .map_err(|_| format!("The decrypted {} is not UTF-8 text", file.display()))
+}
+
+/// Parses the decrypted text. Blank lines and lines starting with `#` are skipped.
+pub(super) fn parse_secrets(text: &str) -> std::result::Result<HashMap<String, String>, String> {
+    let mut secrets = HashMap::default();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim_start();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {} has no =", i + 1))?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(format!("line {} has no name before =", i + 1));
+        }
+        secrets.insert(name.to_owned(), value.to_owned());
+    }
+    Ok(secrets)
+}
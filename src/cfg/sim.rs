@@ -0,0 +1,130 @@
+//! Simulation of a configuration without input or output devices.
+//!
+//! The simulation feeds key events to a fresh keyberon layout of the configuration and ticks it
+//! once per simulated millisecond, recording the inputs and the key outputs that change. Only the
+//! layout is simulated: custom actions such as macros, unicode and commands produce no output.
+
+use super::*;
+
+/// A key output press or release at a simulated time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputEvent {
+    /// Milliseconds since the start of the simulation.
+    pub time: u32,
+    pub key: KeyCode,
+    pub pressed: bool,
+}
+
+pub struct Simulation<'a> {
+    layout: BorrowedKLayout<'a>,
+    time: u32,
+    held: Vec<KeyCode>,
+    inputs: Vec<(u32, OsCode, bool)>,
+    outputs: Vec<OutputEvent>,
+}
+
+impl<'a> Simulation<'a> {
+    pub fn new(cfg: &'a Cfg) -> Self {
+        let mut layout = Layout::new(cfg.layout.b().layers);
+        layout.layer_conditions = cfg.layout.b().layer_conditions;
+        Self {
+            layout,
+            time: 0,
+            held: vec![],
+            inputs: vec![],
+            outputs: vec![],
+        }
+    }
+
+    pub fn press(&mut self, key: OsCode) {
+        self.inputs.push((self.time, key, true));
+        self.layout.event(Event::Press(0, key.into()));
+    }
+
+    pub fn release(&mut self, key: OsCode) {
+        self.inputs.push((self.time, key, false));
+        self.layout.event(Event::Release(0, key.into()));
+    }
+
+    /// Set the default layer by its keyberon index.
+    pub fn set_default_layer(&mut self, layer: usize) {
+        self.layout.set_default_layer(layer);
+    }
+
+    /// Advance the simulation by one millisecond.
+    pub fn tick(&mut self) {
+        self.layout.tick();
+        self.time += 1;
+        let cur = self.layout.keycodes().collect::<Vec<_>>();
+        for &key in self.held.iter().filter(|k| !cur.contains(k)) {
+            self.outputs.push(OutputEvent {
+                time: self.time,
+                key,
+                pressed: false,
+            });
+        }
+        for &key in cur.iter().filter(|k| !self.held.contains(k)) {
+            self.outputs.push(OutputEvent {
+                time: self.time,
+                key,
+                pressed: true,
+            });
+        }
+        self.held = cur;
+    }
+
+    pub fn wait(&mut self, ms: u32) {
+        for _ in 0..ms {
+            self.tick();
+        }
+    }
+
+    /// Milliseconds since the start of the simulation.
+    pub fn time(&self) -> u32 {
+        self.time
+    }
+
+    /// The output events so far, in order.
+    pub fn outputs(&self) -> &[OutputEvent] {
+        &self.outputs
+    }
+
+    /// The inputs and outputs so far, one per line with their time. An input comes before the
+    /// outputs of the same millisecond, since outputs are only produced by the following tick.
+    #[cfg(test)]
+    pub fn transcript(&self) -> String {
+        let mut inputs = self.inputs.iter().peekable();
+        let mut out = String::new();
+        for o in self.outputs.iter() {
+            while let Some((time, key, pressed)) = inputs.next_if(|(t, _, _)| *t < o.time) {
+                out.push_str(&transcript_line(
+                    *time,
+                    "in ",
+                    *pressed,
+                    &format!("{key:?}"),
+                ));
+            }
+            out.push_str(&transcript_line(
+                o.time,
+                "out",
+                o.pressed,
+                &format!("{:?}", o.key),
+            ));
+        }
+        for (time, key, pressed) in inputs {
+            out.push_str(&transcript_line(
+                *time,
+                "in ",
+                *pressed,
+                &format!("{key:?}"),
+            ));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+fn transcript_line(time: u32, direction: &str, pressed: bool, key: &str) -> String {
+    let action = if pressed { "press  " } else { "release" };
+    format!("{time:>6}ms {direction} {action} {key}\n")
+}
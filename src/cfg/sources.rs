@@ -0,0 +1,209 @@
+//! Where the configuration text comes from: finding the configuration file when none is given,
+//! merging the files named by `include` items into one text, and appending an overlay file.
+//!
+//! Included files are placed before the including file in the merged text, so that their items
+//! come first, e.g. the first `deflayer` of a base configuration stays the default layer. Every
+//! span of the merged text belongs to one file; [`ParsedState::source_at`] maps it back.
+
+use super::sexpr::Span;
+use super::*;
+
+use std::path::{Path, PathBuf};
+
+const INCLUDE: &str = "include";
+
+/// The files searched, in order, for a configuration when none is given:
+///
+/// 1. `kanata.kbd` in the working directory
+/// 2. `$XDG_CONFIG_HOME/kanata/kanata.kbd`, or `~/.config/kanata/kanata.kbd`
+/// 3. `kanata/kanata.kbd` in each of `$XDG_CONFIG_DIRS`, then `/etc/kanata/kanata.kbd`
+///
+/// On Windows, 2 is `%APPDATA%\kanata\kanata.kbd` and there is no 3.
+pub fn default_cfg_paths() -> Vec<PathBuf> {
+    let in_dir = |dir: PathBuf| dir.join("kanata").join("kanata.kbd");
+    let mut paths = vec![PathBuf::from("kanata.kbd")];
+    #[cfg(not(target_os = "windows"))]
+    {
+        let config_home = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+        paths.extend(config_home.map(in_dir));
+        if let Some(dirs) = std::env::var_os("XDG_CONFIG_DIRS") {
+            paths.extend(
+                std::env::split_paths(&dirs)
+                    .filter(|dir| !dir.as_os_str().is_empty())
+                    .map(in_dir),
+            );
+        }
+        paths.push(PathBuf::from("/etc/kanata/kanata.kbd"));
+    }
+    #[cfg(target_os = "windows")]
+    paths.extend(std::env::var_os("APPDATA").map(|dir| in_dir(PathBuf::from(dir))));
+    paths
+}
+
+/// A file merged into the configuration text by `include`.
+#[derive(Debug, Clone)]
+pub(super) struct IncludedSource {
+    /// The path of the file, shown in errors.
+    pub name: String,
+    /// The file stem, which prefixes the aliases defined in the file.
+    pub namespace: String,
+    /// Where the text of the file is in the merged text.
+    pub span: Span,
+}
+
+impl ParsedState {
+    /// Returns the name and text of the file that `offset` of the merged text is in, and the
+    /// offset of that text in the merged text.
+    pub(super) fn source_at(&self, offset: usize) -> (&str, &str, usize) {
+        match self
+            .includes
+            .iter()
+            .find(|inc| inc.span.start() <= offset && offset <= inc.span.end())
+        {
+            Some(inc) => (&inc.name, &self.cfg_text[inc.span], inc.span.start()),
+            None => {
+                let start = self.main_text_start();
+                (&self.cfg_filename, &self.cfg_text[start..], start)
+            }
+        }
+    }
+
+    /// The offset of the including file's text in the merged text.
+    fn main_text_start(&self) -> usize {
+        self.includes.last().map_or(0, |inc| inc.span.end() + 1)
+    }
+}
+
+/// Merges the files named by the `include` items of `text` with it. Returns the merged text and
+/// where each included file is in it. Paths are relative to the directory of `name`.
+///
+/// Syntax errors are left to the parse stage, which reports them against the merged text.
+pub(super) fn expand_includes(name: &str, text: &str) -> Result<(String, Vec<IncludedSource>)> {
+    let Ok(root_exprs) = sexpr::parse(text) else {
+        return Ok((text.to_owned(), vec![]));
+    };
+    let dir = Path::new(name).parent().unwrap_or(Path::new(""));
+    let mut merged = String::new();
+    let mut includes: Vec<IncludedSource> = vec![];
+    for expr in root_exprs
+        .iter()
+        .filter(gen_first_atom_filter_spanned(INCLUDE))
+    {
+        let path = match &expr.t[1..] {
+            [SExpr::Atom(path)] => path.t.trim_matches('"'),
+            _ => bail_span!(expr, "{INCLUDE} expects one file path"),
+        };
+        let path = dir.join(path);
+        let included = std::fs::read_to_string(&path).map_err(|e| {
+            anyhow_span!(
+                expr,
+                "Failed to read the included file {}: {e}",
+                path.display()
+            )
+        })?;
+        if sexpr::parse(&included)
+            .map(|exprs| exprs.iter().any(|expr| first_atom(expr) == Some(INCLUDE)))
+            .unwrap_or(false)
+        {
+            bail_span!(
+                expr,
+                "{} includes other files, which is only allowed in the main configuration file",
+                path.display()
+            );
+        }
+        let namespace = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if includes.iter().any(|inc| inc.namespace == namespace) {
+            bail_span!(
+                expr,
+                "Another included file is also named {namespace}. Included files need different names because their aliases are prefixed with the name."
+            );
+        }
+        let start = merged.len();
+        merged.push_str(&included);
+        includes.push(IncludedSource {
+            name: path.to_string_lossy().to_string(),
+            namespace,
+            span: Span {
+                start,
+                end: merged.len(),
+            },
+        });
+        merged.push('\n');
+    }
+    merged.push_str(text);
+    Ok((merged, includes))
+}
+
+/// Appends the text of an overlay file, which defines layers and aliases for trying out on top of
+/// a configuration. It must not repeat the `defsrc` or `defcfg` of the configuration. Errors in
+/// the overlay are shown against the name of the configuration file.
+pub(super) fn append_overlay(text: &str, overlay: &str) -> String {
+    format!("{text}\n{overlay}")
+}
+
+/// The name of the first layer defined in the text, e.g. the layer of an overlay file that is
+/// activated on top of the configuration.
+pub fn first_layer_name(text: &str) -> Option<String> {
+    sexpr::parse(text)
+        .ok()?
+        .iter()
+        .find(|expr| matches!(first_atom(expr), Some("deflayer" | "defmirror")))
+        .and_then(|expr| expr.t.get(1))
+        .and_then(|name| name.atom(None))
+        .map(str::to_owned)
+}
+
+/// Prefixes the aliases defined by `defalias` in each included file with the file's namespace,
+/// e.g. `base/nav`, along with the references to them in the same file. Other files refer to
+/// them by the prefixed name and can define their own alias of the same name.
+pub(super) fn namespace_included_aliases(
+    exprs: &mut [Spanned<Vec<SExpr>>],
+    includes: &[IncludedSource],
+) {
+    for inc in includes {
+        let in_file = |expr: &Spanned<Vec<SExpr>>| {
+            inc.span.start() <= expr.span.start() && expr.span.end() <= inc.span.end()
+        };
+        let names: HashSet<String> = exprs
+            .iter()
+            .filter(|expr| in_file(expr) && first_atom(expr) == Some("defalias"))
+            .flat_map(|expr| expr.t.iter().skip(1).step_by(2))
+            .filter_map(|name| name.atom(None).map(str::to_owned))
+            .collect();
+        for expr in exprs.iter_mut().filter(|expr| in_file(expr)) {
+            if first_atom(expr) == Some("defalias") {
+                for name in expr.t.iter_mut().skip(1).step_by(2) {
+                    if let SExpr::Atom(name) = name {
+                        name.t = format!("{}/{}", inc.namespace, name.t);
+                    }
+                }
+            }
+            prefix_alias_refs(&mut expr.t, &names, &inc.namespace);
+        }
+    }
+}
+
+fn first_atom(expr: &Spanned<Vec<SExpr>>) -> Option<&str> {
+    expr.t.first().and_then(|a| a.atom(None))
+}
+
+fn prefix_alias_refs(exprs: &mut [SExpr], names: &HashSet<String>, namespace: &str) {
+    for expr in exprs {
+        match expr {
+            SExpr::Atom(a) => {
+                if let Some(name) = a.t.strip_prefix('@') {
+                    if names.contains(name) {
+                        a.t = format!("@{namespace}/{name}");
+                    }
+                }
+            }
+            SExpr::List(l) => prefix_alias_refs(&mut l.t, names, namespace),
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! "Did you mean" suggestions for misspelled names in the configuration.
+
+use crate::keys::{str_to_oscode, KEY_NAMES};
+
+/// Returns the candidate that is closest to `input`, if it is close enough to likely be what was
+/// meant.
+pub fn did_you_mean<'a>(
+    input: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let max_distance = std::cmp::max(1, input.chars().count() / 3);
+    candidates
+        .into_iter()
+        .map(|c| (edit_distance(input, c), c))
+        .filter(|(d, _)| *d <= max_distance)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, c)| c)
+}
+
+/// Returns a key name that is similar to the unknown key name. Names of the Linux input event
+/// codes, e.g. `KEY_LEFTCTRL`, are matched against the keys they name.
+pub fn suggest_key_name(input: &str) -> Option<&'static str> {
+    let valid_names = || {
+        KEY_NAMES
+            .iter()
+            .copied()
+            .filter(|n| str_to_oscode(n).is_some())
+    };
+    if input.starts_with("KEY_") || input.starts_with("BTN_") {
+        let code_names = valid_names()
+            .map(|n| (format!("{:?}", str_to_oscode(n).expect("valid")), n))
+            .collect::<Vec<_>>();
+        let code = did_you_mean(input, code_names.iter().map(|(code, _)| code.as_str()))?;
+        return code_names
+            .iter()
+            .find(|(c, _)| c == code)
+            .map(|(_, name)| *name);
+    }
+    did_you_mean(input, valid_names())
+}
+
+/// Returns a help sentence to append to an unknown key error, or an empty string.
+pub fn key_name_help(input: &str) -> String {
+    match suggest_key_name(input) {
+        Some(name) => format!("\nDid you mean: {name}"),
+        None => String::new(),
+    }
+}
+
+/// The Levenshtein distance between the strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != *cb);
+            cur[j + 1] = substitution.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+#[test]
+fn suggestions_for_misspelled_names() {
+    assert_eq!(edit_distance("lctrl", "lctl"), 1);
+    assert_eq!(edit_distance("", "abc"), 3);
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+    assert_eq!(suggest_key_name("lctrll"), Some("lctrl"));
+    assert_eq!(suggest_key_name("bspcc"), Some("bspc"));
+    assert_eq!(suggest_key_name("KEY_LFTCTRL"), Some("lctrl"));
+    assert_eq!(suggest_key_name("KEY_ESC"), Some("esc"));
+    assert_eq!(suggest_key_name("completely-different"), None);
+    assert_eq!(
+        did_you_mean("linux-dve", ["linux-dev", "linux-led-layers"]),
+        Some("linux-dev")
+    );
+}
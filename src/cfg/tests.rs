@@ -154,7 +154,7 @@ fn parse_transparent_default() {
         Err(poisoned) => poisoned.into_inner(),
     };
     let mut s = ParsedState::default();
-    let (_, _, layer_strings, layers, _, _) = parse_cfg_raw(
+    let (_, _, layer_strings, layers, _, _, _) = parse_cfg_raw(
         &std::path::PathBuf::from("./cfg_samples/transparent_default.kbd"),
         &mut s,
     )
@@ -317,3 +317,2112 @@ fn recursive_multi_is_flattened() {
         panic!("multi did not parse into multi");
     }
 }
+
+#[test]
+fn parse_dead_keys() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defdeadkeys
+  é (RA-' e)
+  ñ (S-grv n)
+)
+(defalias é (dead-key é))
+(defsrc a b)
+(deflayer base @é (dead-key ñ))
+"#;
+    let (_, _, _, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    assert_eq!(
+        layers[0][0][usize::from(OsCode::KEY_A)],
+        Action::Sequence {
+            events: &&[
+                SequenceEvent::Press(KeyCode::RAlt),
+                SequenceEvent::Press(KeyCode::Quote),
+                SequenceEvent::Release(KeyCode::Quote),
+                SequenceEvent::Release(KeyCode::RAlt),
+                SequenceEvent::Press(KeyCode::E),
+                SequenceEvent::Release(KeyCode::E),
+                SequenceEvent::Complete,
+            ][..]
+        }
+    );
+
+    let mut s = ParsedState::default();
+    let source = r#"
+(defdeadkeys é (' e))
+(defsrc a)
+(deflayer base (dead-key ü))
+"#;
+    let e = parse_cfg_raw_string(source.into(), &mut s)
+        .map(|_| ())
+        .unwrap_err();
+    assert!(e.help_msg.contains("ü is not defined"));
+}
+
+#[test]
+fn parse_release_only_chords() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc f d)
+(deflayer braille (chord braille 1) (chord braille 2))
+(defchords braille release (1) a (1 2) b)
+"#;
+    let (_, _, _, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    match layers[0][0][usize::from(OsCode::KEY_F)] {
+        Action::Chords(group) => assert!(group.release_only),
+        _ => panic!("expected chord"),
+    }
+}
+
+#[test]
+fn parse_mirror_layer() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc f a spc lsft)
+(deflayer base @spc _ _ _)
+(defmirror mirror (lsft a))
+(defalias spc (layer-while-held mirror))
+"#;
+    let (_, _, layer_info, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    assert_eq!(layer_info[2].name, "mirror");
+    assert_eq!(
+        layers[2][0][usize::from(OsCode::KEY_F)],
+        Action::KeyCode(KeyCode::J)
+    );
+    assert_eq!(
+        layers[2][0][usize::from(OsCode::KEY_A)],
+        Action::KeyCode(KeyCode::LShift)
+    );
+    assert_eq!(
+        layers[2][0][usize::from(OsCode::KEY_LEFTSHIFT)],
+        Action::KeyCode(KeyCode::A)
+    );
+    assert_eq!(
+        layers[2][0][usize::from(OsCode::KEY_SPACE)],
+        Action::KeyCode(KeyCode::Space)
+    );
+}
+
+#[test]
+fn parse_capabilities() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let lacking = if cfg!(target_os = "linux") {
+        "windows"
+    } else {
+        "linux"
+    };
+    let mut s = ParsedState::default();
+    let source = format!(
+        r#"
+(defsrc a b)
+(deflayer base a b)
+(defoptional () (deflayer extra b a))
+(defoptional ({lacking}) (deflayer broken (no-such-action) b))
+"#
+    );
+    let (_, _, layer_info, _, _, _, _) = parse_cfg_raw_string(source, &mut s).unwrap();
+    let names = layer_info
+        .iter()
+        .map(|l| l.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["base", "base", "extra", "extra"]);
+
+    let requires = format!("(defrequires {lacking})");
+    let missing = format!("requires {lacking}");
+    for (item, msg) in [
+        (requires.as_str(), missing.as_str()),
+        ("(defrequires nope)", "Unknown capability"),
+        ("(defoptional cmd)", "defoptional expects"),
+    ] {
+        let mut s = ParsedState::default();
+        let source = format!("(defsrc a) (deflayer base a) {item}");
+        let e = parse_cfg_raw_string(source, &mut s).unwrap_err();
+        assert!(e.help_msg.contains(msg), "{item}: {e:?}");
+    }
+}
+
+#[test]
+fn parse_swap_hands() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a f)
+(deflayer base (swap-hands (a lsft)) _)
+"#;
+    let (_, _, _, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    let pairs = match layers[0][0][usize::from(OsCode::KEY_A)] {
+        Action::Custom(&[CustomAction::SwapHands(pairs)]) => pairs,
+        _ => panic!("expected swap-hands"),
+    };
+    assert!(pairs.contains(&(OsCode::KEY_F, OsCode::KEY_J)));
+    assert!(pairs.contains(&(OsCode::KEY_LEFTSHIFT, OsCode::KEY_A)));
+    assert!(!pairs.contains(&(OsCode::KEY_A, OsCode::KEY_SEMICOLON)));
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a)
+(deflayer base (swap-hands (a)))
+"#;
+    parse_cfg_raw_string(source.into(), &mut s).expect_err("invalid pair");
+}
+
+#[test]
+fn parse_sound_profiles() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a)
+(deflayer base a)
+(deflayer nav b)
+(defsounds default press click.wav hold "thock.wav")
+(defsounds nav press tick.wav)
+"#;
+    let (_, _, layer_info, _, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    let base = layer_info[0].sounds.as_ref().unwrap();
+    assert_eq!(base.press.as_deref(), Some("click.wav"));
+    assert_eq!(base.hold.as_deref(), Some("thock.wav"));
+    let nav = layer_info[2].sounds.as_ref().unwrap();
+    assert_eq!(nav.press.as_deref(), Some("tick.wav"));
+    assert_eq!(nav.hold, None);
+
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a)
+(deflayer base a)
+(defsounds base press)
+"#;
+    parse_cfg_raw_string(source.into(), &mut s).expect_err("missing sound file");
+}
+
+#[test]
+fn parse_hooks() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a)
+(deflayer base (tap-hold 200 200 a lctl))
+(deffakekeys fk b)
+(defhooks tap-hold-hold c)
+"#;
+    let (_, _, _, layers, _, _, hooks) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    assert_eq!(hooks.tap_hold_tap, None);
+    let (x, y) = hooks.tap_hold_hold.unwrap();
+    assert_eq!(
+        layers[0][x as usize][y as usize],
+        Action::KeyCode(KeyCode::C)
+    );
+
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a)
+(deflayer base a)
+(defhooks tap-hold-hold c tap-hold-hold d)
+"#;
+    parse_cfg_raw_string(source.into(), &mut s).expect_err("duplicate hook");
+}
+
+#[test]
+fn parse_layer_hooks() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a)
+(deflayer base a)
+(deflayer nav left)
+(defhooks (layer-exit nav) esc (layer-enter nav) c)
+"#;
+    let (_, _, _, layers, _, _, hooks) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    assert!(!hooks.layer_enter.contains_key(&0));
+    let (x, y) = hooks.layer_exit[&1];
+    assert_eq!(
+        layers[0][x as usize][y as usize],
+        Action::KeyCode(KeyCode::Escape)
+    );
+    let (x, y) = hooks.layer_enter[&1];
+    assert_eq!(
+        layers[0][x as usize][y as usize],
+        Action::KeyCode(KeyCode::C)
+    );
+
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a)
+(deflayer base a)
+(defhooks (layer-enter nope) c)
+"#;
+    parse_cfg_raw_string(source.into(), &mut s).expect_err("unknown layer");
+}
+
+#[test]
+fn parse_switch_var() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a b)
+(deflayer base (switch-var mode vim left _ a) (set-var mode vim))
+"#;
+    let (_, _, _, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    let cases = match layers[0][0][usize::from(OsCode::KEY_A)] {
+        Action::Custom(&[CustomAction::SwitchVar { name, cases }]) => {
+            assert_eq!(name, "mode");
+            cases
+        }
+        _ => panic!("expected switch-var"),
+    };
+    assert_eq!(cases.len(), 2);
+    assert_eq!(cases[0].value.as_deref(), Some("vim"));
+    assert_eq!(*cases[0].action.0, Action::KeyCode(KeyCode::Left));
+    assert_eq!(cases[1].value, None);
+    assert!(matches!(
+        layers[0][0][usize::from(OsCode::KEY_B)],
+        Action::Custom(&[CustomAction::SetVar { .. }])
+    ));
+
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a)
+(deflayer base (switch-var mode vim))
+"#;
+    parse_cfg_raw_string(source.into(), &mut s).expect_err("value without action");
+}
+
+#[test]
+fn parse_cooldown() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a)
+(deflayer base (cooldown 500 (macro C-s ret)))
+"#;
+    let (_, _, _, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    match layers[0][0][usize::from(OsCode::KEY_A)] {
+        Action::Custom(&[CustomAction::Cooldown { ms, action }]) => {
+            assert_eq!(*ms, 500);
+            assert!(matches!(action.0, Action::Sequence { .. }));
+        }
+        _ => panic!("expected cooldown"),
+    }
+
+    for (action, msg) in [
+        ("(cooldown 500)", "no action"),
+        ("(cooldown 0 a)", "zero cooldown"),
+        ("(cooldown a 500)", "action before the cooldown"),
+    ] {
+        let mut s = ParsedState::default();
+        let source = format!("(defsrc a)\n(deflayer base {action})");
+        parse_cfg_raw_string(source, &mut s).expect_err(msg);
+    }
+}
+
+#[test]
+fn parse_counted() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defcfg count-layers nav)
+(defsrc a b)
+(deflayer base (counted mlft) (layer-while-held nav))
+(deflayer nav _ _)
+"#;
+    let (cfg, _, _, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    assert_eq!(cfg.get("count-layers").map(String::as_str), Some("nav"));
+    match layers[0][0][usize::from(OsCode::KEY_A)] {
+        Action::Custom(&[CustomAction::Counted(action)]) => {
+            assert!(matches!(action.0, Action::Custom(_)));
+        }
+        _ => panic!("expected counted"),
+    }
+
+    for (action, msg) in [("(counted)", "no action"), ("(counted a b)", "two actions")] {
+        let mut s = ParsedState::default();
+        let source = format!("(defsrc a)\n(deflayer base {action})");
+        parse_cfg_raw_string(source, &mut s).expect_err(msg);
+    }
+}
+
+#[test]
+fn tap_hold_opposite_hand() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc f d j)
+(deflayer base (tap-hold-opposite-hand 200 200 f lctl) d j)
+(defhands (left f d) (right j))
+"#;
+    let (_, _, _, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    let mut layout = create_layout(layers, vec![], s.a);
+    let layout = layout.bm();
+    let f = u16::from(OsCode::KEY_F);
+    let d = u16::from(OsCode::KEY_D);
+    let j = u16::from(OsCode::KEY_J);
+    let tick_until_key = |layout: &mut BorrowedKLayout| {
+        for _ in 0..10 {
+            layout.tick();
+            if layout.keycodes().next().is_some() {
+                break;
+            }
+        }
+        layout.keycodes().next()
+    };
+
+    // Same hand: resolves to tap as soon as the other key is pressed.
+    layout.event(Event::Press(0, f));
+    layout.event(Event::Press(0, d));
+    assert_eq!(tick_until_key(layout), Some(KeyCode::F));
+    layout.event(Event::Release(0, d));
+    layout.event(Event::Release(0, f));
+    for _ in 0..10 {
+        layout.tick();
+    }
+
+    // Opposite hand: resolves to hold once the other key is tapped.
+    layout.event(Event::Press(0, f));
+    layout.event(Event::Press(0, j));
+    layout.event(Event::Release(0, j));
+    assert_eq!(tick_until_key(layout), Some(KeyCode::LCtrl));
+}
+
+#[test]
+fn layer_lock_keeps_the_held_layer() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a b)
+(deflayer base (layer-while-held nav) b)
+(deflayer nav _ layer-lock)
+"#;
+    let (_, _, _, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    let mut layout = create_layout(layers, vec![], s.a);
+    let layout = layout.bm();
+    let a = u16::from(OsCode::KEY_A);
+
+    // Keyberon layer 3 is nav as activated by layer-while-held.
+    layout.event(Event::Press(0, a));
+    layout.tick();
+    assert_eq!(layout.current_layer(), 3);
+    assert_eq!(crate::kanata::toggle_layer_lock(layout), Some((3, true)));
+    layout.event(Event::Release(0, a));
+    layout.tick();
+    assert_eq!(layout.current_layer(), 3);
+
+    assert_eq!(crate::kanata::toggle_layer_lock(layout), Some((3, false)));
+    layout.tick();
+    assert_eq!(layout.current_layer(), 0);
+    assert_eq!(crate::kanata::toggle_layer_lock(layout), None);
+
+    // A layer held by a layer owner instead of a key is not locked when no key is held.
+    let _ = layout.states.push(State::LayerModifier {
+        value: 3,
+        coord: crate::kanata::TEMP_LAYER_COORD,
+    });
+    layout.tick();
+    assert_eq!(layout.current_layer(), 3);
+    assert_eq!(crate::kanata::toggle_layer_lock(layout), None);
+    layout.tick();
+    assert_eq!(layout.current_layer(), 3);
+}
+
+#[test]
+fn temp_layer_activates_the_layer() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a b)
+(deflayer base (temp-layer nav 2700) b)
+(deflayer nav _ left)
+"#;
+    let (_, _, _, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    let a = u16::from(OsCode::KEY_A);
+    match layers[0][0][usize::from(a)] {
+        Action::Custom(&[CustomAction::TempLayer { layer, seconds }]) => {
+            assert_eq!((*layer, *seconds), (3, 2700))
+        }
+        ref ac => panic!("expected temp-layer, found {ac:?}"),
+    }
+    let mut layout = create_layout(layers, vec![], s.a);
+    let layout = layout.bm();
+    let mut temp_layer = None;
+    let until = std::time::Instant::now();
+    crate::kanata::start_temp_layer(layout, &mut temp_layer, 3, "nav".into(), until);
+    layout.tick();
+    assert_eq!(layout.current_layer(), 3);
+    assert!(temp_layer.is_some());
+
+    for source in [
+        "(defsrc a) (deflayer base (temp-layer base))",
+        "(defsrc a) (deflayer base (temp-layer nope 10))",
+        "(defsrc a) (deflayer base (temp-layer base 0))",
+    ] {
+        let mut s = ParsedState::default();
+        parse_cfg_raw_string(source.into(), &mut s).expect_err(source);
+    }
+}
+
+#[test]
+fn tap_hold_other_finger() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc f r d)
+(deflayer base (tap-hold-other-finger 200 200 f lctl) r d)
+(defhands (left-index f r) (left-middle d))
+"#;
+    let (_, _, _, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    let mut layout = create_layout(layers, vec![], s.a);
+    let layout = layout.bm();
+    let f = u16::from(OsCode::KEY_F);
+    let r = u16::from(OsCode::KEY_R);
+    let d = u16::from(OsCode::KEY_D);
+    let tick_until_key = |layout: &mut BorrowedKLayout| {
+        for _ in 0..10 {
+            layout.tick();
+            if layout.keycodes().next().is_some() {
+                break;
+            }
+        }
+        layout.keycodes().next()
+    };
+
+    // Same finger: resolves to tap as soon as the other key is pressed.
+    layout.event(Event::Press(0, f));
+    layout.event(Event::Press(0, r));
+    assert_eq!(tick_until_key(layout), Some(KeyCode::F));
+    layout.event(Event::Release(0, r));
+    layout.event(Event::Release(0, f));
+    for _ in 0..10 {
+        layout.tick();
+    }
+
+    // Another finger of the same hand: resolves to hold once the other key is tapped.
+    layout.event(Event::Press(0, f));
+    layout.event(Event::Press(0, d));
+    layout.event(Event::Release(0, d));
+    assert_eq!(tick_until_key(layout), Some(KeyCode::LCtrl));
+}
+
+#[test]
+fn parse_hands_errors() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    for source in [
+        "(defsrc f) (deflayer base (tap-hold-opposite-hand 200 200 f lctl))",
+        "(defsrc f) (deflayer base f) (defhands (left f) (right f))",
+        "(defsrc f) (deflayer base f) (defhands (middle f))",
+        "(defsrc f) (deflayer base f) (defhands (left-toe f))",
+        "(defsrc f) (deflayer base (tap-hold-other-finger 200 200 f lctl)) (defhands (left f))",
+    ] {
+        let mut s = ParsedState::default();
+        parse_cfg_raw_string(source.into(), &mut s).expect_err(source);
+    }
+}
+
+#[test]
+fn parse_on_release() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a b)
+(deflayer base (on-release c) (press-release d e))
+"#;
+    let (_, _, _, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    assert_eq!(
+        layers[0][0][usize::from(OsCode::KEY_A)],
+        Action::OnRelease(&Action::KeyCode(KeyCode::C))
+    );
+    assert_eq!(
+        layers[0][0][usize::from(OsCode::KEY_B)],
+        Action::MultipleActions(
+            &(&[
+                Action::KeyCode(KeyCode::D),
+                Action::OnRelease(&Action::KeyCode(KeyCode::E)),
+            ] as _)
+        )
+    );
+
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a)
+(deflayer base (press-release d))
+"#;
+    parse_cfg_raw_string(source.into(), &mut s).expect_err("missing release action");
+}
+
+#[test]
+fn parse_toggle_key() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a)
+(deflayer base (toggle-key lsft))
+"#;
+    let (_, _, _, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    assert_eq!(
+        layers[0][0][usize::from(OsCode::KEY_A)],
+        Action::Custom(&(&[&CustomAction::ToggleKey(KeyCode::LShift)] as _))
+    );
+
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a)
+(deflayer base (toggle-key (layer-switch base)))
+"#;
+    parse_cfg_raw_string(source.into(), &mut s).expect_err("not a key");
+}
+
+#[test]
+fn parse_action_list_as_multi() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a)
+(deflayer base ((layer-switch base) b (multi c ((release-key lsft) d))))
+"#;
+    let (_, _, _, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    assert_eq!(
+        layers[0][0][usize::from(OsCode::KEY_A)],
+        Action::MultipleActions(
+            &(&[
+                Action::DefaultLayer(0),
+                Action::KeyCode(KeyCode::B),
+                Action::KeyCode(KeyCode::C),
+                Action::ReleaseState(ReleasableState::KeyCode(KeyCode::LShift)),
+                Action::KeyCode(KeyCode::D),
+            ] as _)
+        )
+    );
+}
+
+#[test]
+fn parse_media_key_tap_hold() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc next)
+(deflayer base (tap-hold 200 300 next ffwd))
+"#;
+    let (_, _, _, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    match layers[0][0][usize::from(OsCode::KEY_NEXTSONG)] {
+        Action::HoldTap(HoldTapAction { tap, hold, .. }) => {
+            assert_eq!(*tap, Action::KeyCode(KeyCode::MediaNextSong));
+            let ffwd = match hold {
+                Action::KeyCode(kc) => *kc,
+                _ => panic!("expected a key"),
+            };
+            assert_eq!(OsCode::from(ffwd), OsCode::KEY_FASTFORWARD);
+        }
+        _ => panic!("expected tap-hold"),
+    }
+}
+
+#[test]
+fn lint_suspicious_patterns() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc caps a s d)
+(deflayer base (tap-hold 200 500 esc lctl) (layer-while-held nav) b b)
+(deflayer nav _ left _ _)
+(deflayer unused _ _ _ _)
+"#;
+    let (_, mapped_keys, layer_info, layers, _, _, _) =
+        parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    let warnings = lint(&layers, &layer_info, &mapped_keys)
+        .into_iter()
+        .map(|w| (w.kind, w.layer))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        warnings,
+        [
+            (LintKind::UnreachableLayer, "unused".to_string()),
+            (LintKind::ShadowedBinding, "nav".to_string()),
+            (LintKind::SlowModifierHold, "base".to_string()),
+            (LintKind::DuplicateKey, "base".to_string()),
+        ]
+    );
+
+    let source = r#"
+(defsrc caps a)
+(deflayer base (tap-hold-press 200 500 esc lctl) (layer-while-held nav))
+(deflayer nav _ _)
+"#;
+    let (_, mapped_keys, layer_info, layers, _, _, _) =
+        parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    assert!(lint(&layers, &layer_info, &mapped_keys).is_empty());
+}
+
+#[test]
+fn parse_strict_cfg_and_suggestions() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = "(defcfg linux-dve /dev/input/event0) (defsrc a) (deflayer base a)";
+    parse_cfg_raw_string(source.into(), &mut s).unwrap();
+
+    let source = "(defcfg strict-cfg yes linux-dve /dev/input/event0) (defsrc a) (deflayer base a)";
+    let e = parse_cfg_raw_string(source.into(), &mut s)
+        .map(|_| ())
+        .unwrap_err();
+    assert!(e.help_msg.contains("Unknown defcfg item linux-dve"));
+    assert!(e.help_msg.contains("Did you mean: linux-dev"));
+
+    let source = "(defcfg strict-cfg yes) (defsrc KEY_LFTCTRL) (deflayer base a)";
+    let e = parse_cfg_raw_string(source.into(), &mut s)
+        .map(|_| ())
+        .unwrap_err();
+    assert!(e.help_msg.contains("Did you mean: lctrl"));
+
+    let source = "(defsrc a) (deflayer base bspcc)";
+    let e = parse_cfg_raw_string(source.into(), &mut s)
+        .map(|_| ())
+        .unwrap_err();
+    assert!(e.help_msg.contains("Did you mean: bspc"));
+}
+
+#[test]
+fn parse_arithmetic_in_timing_values() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defvar
+  base 200
+  hold (+ $base 30)
+  slow (* (- $hold 30) 2)
+)
+(defsrc a s)
+(deflayer base (tap-hold $base $hold a lctl) (tap-hold (/ $slow 4) (+ $base $hold 1) s lalt))
+"#;
+    let (_, _, _, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    let timeouts = [OsCode::KEY_A, OsCode::KEY_S].map(|k| match layers[1][0][usize::from(k)] {
+        Action::HoldTap(ht) => (ht.tap_hold_interval, ht.timeout),
+        _ => panic!("expected tap-hold"),
+    });
+    assert_eq!(timeouts, [(200, 230), (100, 431)]);
+
+    for (source, err) in [
+        ("(defsrc a) (deflayer base (tap-hold (- 100 200) 200 a b))", "must be 1-65535"),
+        ("(defsrc a) (deflayer base (tap-hold (/ 100 0) 200 a b))", "division by zero"),
+        ("(defsrc a) (deflayer base (tap-hold (+ 100 a) 200 a b))", "must be a number"),
+        (
+            "(defvar x (* 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21)) (defsrc a) (deflayer base a)",
+            "too large",
+        ),
+    ] {
+        let e = parse_cfg_raw_string(source.into(), &mut s)
+            .map(|_| ())
+            .unwrap_err();
+        assert!(e.help_msg.contains(err), "{}", e.help_msg);
+    }
+}
+
+#[test]
+fn interpolate_environment_variables() {
+    let lookup = |name: &str| match name {
+        "HOME" => Some("/home/user".to_string()),
+        "DEV" => Some("event3".to_string()),
+        _ => None,
+    };
+    assert_eq!(
+        interpolate_env_str("${HOME}/dev/${DEV}", lookup).unwrap(),
+        "/home/user/dev/event3"
+    );
+    assert_eq!(
+        interpolate_env_str("$${HOME} $HOME", lookup).unwrap(),
+        "${HOME} $HOME"
+    );
+    let e = interpolate_env_str("${KANATA_UNSET}", lookup).unwrap_err();
+    assert!(e.contains("environment variable KANATA_UNSET is not set"));
+    assert!(interpolate_env_str("${HOME", lookup).is_err());
+    assert!(interpolate_env_str("${}", lookup).is_err());
+
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    std::env::set_var("KANATA_TEST_INTERPOLATION_DEV", "/dev/input/event7");
+    let source = "(defcfg linux-dev ${KANATA_TEST_INTERPOLATION_DEV}) (defsrc a) (deflayer base a)";
+    let (cfg, ..) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    assert_eq!(cfg["linux-dev"], "/dev/input/event7");
+    let source = "(defsrc a) (deflayer base (cmd ls ${KANATA_TEST_INTERPOLATION_UNSET}))";
+    let e = parse_cfg_raw_string(source.into(), &mut s)
+        .map(|_| ())
+        .unwrap_err();
+    assert!(e
+        .help_msg
+        .contains("KANATA_TEST_INTERPOLATION_UNSET is not set"));
+}
+
+#[test]
+fn interpolate_secrets() {
+    let secrets = parse_secrets("# tokens\n\ntoken=abc=def\n  user=me\n").unwrap();
+    assert_eq!(secrets["token"], "abc=def");
+    assert_eq!(secrets["user"], "me");
+    assert!(parse_secrets("token").is_err());
+    assert!(parse_secrets("=value").is_err());
+
+    let source = "(defsrc a) (deflayer base (cmd curl -u ${secret:user}:${secret:token}))";
+    let (_, exprs) = parse_cfg_exprs(source, &[], &secrets).unwrap();
+    assert!(matches!(
+        &exprs[1].t[2],
+        SExpr::List(l) if l.t[3].atom(None) == Some("me:abc=def"),
+    ));
+    let source = "(defsrc a) (deflayer base (cmd ls ${secret:missing}))";
+    let e = parse_cfg_exprs(source, &[], &secrets).map(|_| ()).unwrap_err();
+    assert!(e.help_msg.contains("secret missing is not in the defsecrets file"));
+}
+
+#[test]
+fn parse_layer_extends() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a s d f)
+(deflayerextends media nav  s vold  f volu)
+(deflayer base a s d f)
+(deflayer nav left down up rght)
+(deflayerextends media-fast media  f (multi volu volu)  a unbind)
+"#;
+    let (_, _, layer_info, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    let names = layer_info
+        .iter()
+        .step_by(2)
+        .map(|l| l.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["media", "base", "nav", "media-fast"]);
+    let keys = [OsCode::KEY_A, OsCode::KEY_S, OsCode::KEY_D, OsCode::KEY_F];
+    let layer_keys = |layer: usize| keys.map(|k| layers[layer * 2 + 1][0][usize::from(k)]);
+    assert_eq!(
+        layer_keys(0),
+        [KeyCode::Left, KeyCode::VolDown, KeyCode::Up, KeyCode::VolUp].map(Action::KeyCode)
+    );
+    assert_eq!(layer_keys(3)[0], Action::Trans);
+    assert_eq!(layer_keys(3)[1..3], layer_keys(0)[1..3]);
+    assert!(matches!(layer_keys(3)[3], Action::MultipleActions(_)));
+
+    for (source, err) in [
+        (
+            "(defsrc a) (deflayer base a) (deflayerextends x y a b)",
+            "does not exist or extends this layer: y",
+        ),
+        (
+            "(defsrc a) (deflayerextends x y a b) (deflayerextends y x a c)",
+            "does not exist or extends this layer",
+        ),
+        (
+            "(defsrc a) (deflayer base a) (deflayerextends x base s b)",
+            "s is not in defsrc",
+        ),
+        (
+            "(defsrc a) (deflayer base a) (deflayerextends x base a)",
+            "deflayerextends expects",
+        ),
+    ] {
+        let e = parse_cfg_raw_string(source.into(), &mut s)
+            .map(|_| ())
+            .unwrap_err();
+        assert!(e.help_msg.contains(err), "{}", e.help_msg);
+    }
+}
+
+#[test]
+fn build_cfg_in_stages() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let build = |text: &str| {
+        CfgBuilder::from_text("test.kbd".into(), text.into())
+            .parse()
+            .and_then(|p| p.resolve())
+    };
+
+    let e = build("(defsrc a) (deflayer base a")
+        .map(|_| ())
+        .unwrap_err();
+    assert_eq!(e.to_string(), "Error in configuration file while parsing");
+    let e = build("(defcfg) (defcfg) (defsrc a) (deflayer base a)")
+        .map(|_| ())
+        .unwrap_err();
+    assert_eq!(e.to_string(), "Error in configuration file while parsing");
+    let e = build("(defsrc a) (deflayer base @missing)")
+        .map(|_| ())
+        .unwrap_err();
+    assert_eq!(e.to_string(), "Error in configuration file while resolving");
+
+    let cfg = build("(defsrc a b)\n(deflayer base\n  c c)\n(deflayer unused a b)")
+        .unwrap()
+        .validate();
+    let warnings = cfg
+        .warnings()
+        .iter()
+        .map(|w| (w.kind, w.location.clone().unwrap().to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        warnings,
+        [
+            (LintKind::UnreachableLayer, "test.kbd:4:11".to_string()),
+            (LintKind::DuplicateKey, "test.kbd:3:5".to_string()),
+        ]
+    );
+    let cfg = cfg.freeze();
+    assert_eq!(cfg.layer_info[0].name, "base");
+    assert!(cfg.mapped_keys.contains(&OsCode::KEY_B));
+}
+
+#[test]
+fn key_outputs_are_sorted_by_key() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let cfg = CfgBuilder::from_text(
+        "test.kbd".into(),
+        "(defsrc z a m) (deflayer base S-b c (tap-hold 200 200 x y))".into(),
+    )
+    .parse()
+    .and_then(|p| p.resolve())
+    .unwrap()
+    .validate()
+    .freeze();
+    let outputs = &cfg.key_outputs[0];
+    assert!(outputs
+        .outputs
+        .windows(2)
+        .all(|w| (w[0].0 as u16) < (w[1].0 as u16)));
+    assert_eq!(
+        outputs.get(&OsCode::KEY_Z),
+        Some(&vec![OsCode::KEY_LEFTSHIFT, OsCode::KEY_B])
+    );
+    assert_eq!(
+        outputs.get(&OsCode::KEY_M),
+        Some(&vec![OsCode::KEY_X, OsCode::KEY_Y])
+    );
+    assert_eq!(outputs.get(&OsCode::KEY_Q), Some(&vec![OsCode::KEY_Q]));
+}
+
+#[test]
+fn deftest_runs_against_the_simulation() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let cfg = CfgBuilder::from_text(
+        "test.kbd".into(),
+        "
+(defsrc caps h)
+(deflayer base (tap-hold 200 200 esc lctl) h)
+(deflayer nav _ left)
+(deftest caps-tap (tap caps) (expect-within 200 esc))
+(deftest caps-hold (press caps) (wait 250) (expect lctl) (release caps))
+(deftest nav-h (layer nav) (tap h) (expect left))
+(deftest wrong (press caps) (expect-within 100 esc))
+"
+        .into(),
+    )
+    .parse()
+    .and_then(|p| p.resolve())
+    .unwrap()
+    .validate()
+    .freeze();
+    let results = cfg
+        .tests
+        .iter()
+        .map(|t| (t.name.as_str(), t.run(&cfg)))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        results,
+        [
+            ("caps-tap", Ok(())),
+            ("caps-hold", Ok(())),
+            ("nav-h", Ok(())),
+            (
+                "wrong",
+                Err("step 2: expected [Escape] within 100ms, got []".to_string())
+            ),
+        ]
+    );
+
+    for (text, msg) in [
+        ("(deftest)", "deftest expects a name"),
+        ("(deftest t (tap nope))", "Unknown key in test step"),
+        ("(deftest t (layer nope))", "Unknown layer name: nope"),
+        ("(deftest t (expect))", "Valid test steps"),
+        ("(deftest t) (deftest t)", "Duplicate test name: t"),
+    ] {
+        let err = CfgBuilder::from_text(
+            "test.kbd".into(),
+            format!("(defsrc a) (deflayer base a) {text}"),
+        )
+        .parse()
+        .and_then(|p| p.resolve())
+        .err()
+        .expect("invalid test is an error");
+        assert!(format!("{err:?}").contains(msg), "{text}: {err:?}");
+    }
+}
+
+#[test]
+fn parse_layer_conditions() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let cfg = CfgBuilder::from_text(
+        "test.kbd".into(),
+        "
+(defsrc lsft rsft 1 2)
+(deflayer base lsft rsft 1 2)
+(deflayer sym _ _ f1 _)
+(deflayercondition sym lsft rsft)
+(deftest unshifted (tap 1) (expect 1))
+(deftest shifted (press rsft) (tap 1) (tap 2) (expect rsft f1 2) (release rsft))
+"
+        .into(),
+    )
+    .parse()
+    .and_then(|p| p.resolve())
+    .unwrap()
+    .validate()
+    .freeze();
+    for test in cfg.tests.iter() {
+        assert_eq!(test.run(&cfg), Ok(()), "{}", test.name);
+    }
+
+    for (text, msg) in [
+        ("(deflayercondition base)", "expects a layer name"),
+        ("(deflayercondition nope lsft)", "Unknown layer name"),
+        ("(deflayercondition base a)", "expects a layer name"),
+        ("(deflayercondition base rsft)", "must be in defsrc"),
+        (
+            "(deflayercondition base lsft) (deflayercondition base lsft)",
+            "already has a deflayercondition",
+        ),
+    ] {
+        let err = CfgBuilder::from_text(
+            "test.kbd".into(),
+            format!("(defsrc a lsft) (deflayer base a lsft) {text}"),
+        )
+        .parse()
+        .and_then(|p| p.resolve())
+        .err()
+        .expect("invalid condition is an error");
+        assert!(format!("{err:?}").contains(msg), "{text}: {err:?}");
+    }
+}
+
+#[test]
+fn parse_launcher_entries() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let cfg = CfgBuilder::from_text(
+        "test.kbd".into(),
+        "
+(defsrc a)
+(deflayer base launcher)
+(deffakekeys x a)
+(deflauncher firefox b terminal (macro c d))
+"
+        .into(),
+    )
+    .parse()
+    .and_then(|p| p.resolve())
+    .unwrap()
+    .validate()
+    .freeze();
+    assert_eq!(
+        cfg.launcher,
+        [
+            ("firefox".to_string(), (1, 1)),
+            ("terminal".to_string(), (1, 2))
+        ]
+    );
+    assert_eq!(cfg.fake_keys.get("terminal launcher"), Some(&2));
+
+    for (text, msg) in [
+        ("(deflauncher firefox)", "has no action"),
+        ("(deflauncher (a) b)", "must be strings"),
+        ("(deflauncher a b a c)", "Duplicate launcher entry: a"),
+    ] {
+        let err = CfgBuilder::from_text(
+            "test.kbd".into(),
+            format!("(defsrc a) (deflayer base a) {text}"),
+        )
+        .parse()
+        .and_then(|p| p.resolve())
+        .err()
+        .expect("invalid launcher is an error");
+        assert!(format!("{err:?}").contains(msg), "{text}: {err:?}");
+    }
+}
+
+#[test]
+fn parse_shortcodes() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let cfg = CfgBuilder::from_text(
+        "test.kbd".into(),
+        "(defcfg output-history yes) (defsrc a) (deflayer base a) (defshortcodes shrug 🤷 +1 \"👍\")"
+            .into(),
+    )
+    .parse()
+    .and_then(|p| p.resolve())
+    .unwrap()
+    .validate()
+    .freeze();
+    assert_eq!(cfg.shortcodes.get("shrug").map(String::as_str), Some("🤷"));
+    assert_eq!(cfg.shortcodes.get("+1").map(String::as_str), Some("👍"));
+
+    for (text, msg) in [
+        ("(defshortcodes shrug)", "expects pairs"),
+        ("(defshortcodes :shrug: 🤷)", "Short codes can only contain"),
+        ("(defshortcodes Shrug 🤷)", "Short codes can only contain"),
+        ("(defshortcodes a b a c)", "Duplicate short code: a"),
+    ] {
+        let err = CfgBuilder::from_text(
+            "test.kbd".into(),
+            format!("(defcfg output-history yes) (defsrc a) (deflayer base a) {text}"),
+        )
+        .parse()
+        .and_then(|p| p.resolve())
+        .err()
+        .expect("invalid short codes are an error");
+        assert!(format!("{err:?}").contains(msg), "{text}: {err:?}");
+    }
+}
+
+#[test]
+fn parse_snippets() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let cfg = CfgBuilder::from_text(
+        "test.kbd".into(),
+        "
+(defcfg output-history yes)
+(defsrc a)
+(deflayer base a)
+(defsnippets
+  ;sig \"Regards,{enter}Sam\"
+  ;fn \"fn {cursor}() {{}\"
+  ;now \"{date} {time}\"
+)
+"
+        .into(),
+    )
+    .parse()
+    .and_then(|p| p.resolve())
+    .unwrap()
+    .validate()
+    .freeze();
+    use SnippetPart::*;
+    assert_eq!(
+        cfg.snippets,
+        [
+            Snippet {
+                trigger: ";sig".into(),
+                expansion: vec![Text("Regards,\nSam".into())],
+            },
+            Snippet {
+                trigger: ";fn".into(),
+                expansion: vec![Text("fn ".into()), Cursor, Text("() {}".into())],
+            },
+            Snippet {
+                trigger: ";now".into(),
+                expansion: vec![Date, Text(" ".into()), Time],
+            },
+        ]
+    );
+
+    for (text, msg) in [
+        ("(defsnippets ;sig)", "expects pairs"),
+        (
+            "(defsnippets \"a b\" c)",
+            "Snippet triggers can only contain",
+        ),
+        ("(defsnippets a b a c)", "Duplicate snippet trigger: a"),
+        (
+            "(defsnippets a {cursor}{cursor})",
+            "only contain one {cursor}",
+        ),
+        ("(defsnippets a {now})", "Unknown placeholder {now}"),
+        ("(defsnippets a {date)", "Unclosed placeholder"),
+    ] {
+        let err = CfgBuilder::from_text(
+            "test.kbd".into(),
+            format!("(defcfg output-history yes) (defsrc a) (deflayer base a) {text}"),
+        )
+        .parse()
+        .and_then(|p| p.resolve())
+        .err()
+        .expect("invalid snippets are an error");
+        assert!(format!("{err:?}").contains(msg), "{text}: {err:?}");
+    }
+
+    for text in ["(defsnippets ;sig Sam)", "(defshortcodes shrug 🤷)"] {
+        let err = CfgBuilder::from_text(
+            "test.kbd".into(),
+            format!("(defsrc a) (deflayer base a) {text}"),
+        )
+        .parse()
+        .and_then(|p| p.resolve())
+        .err()
+        .expect("the output history is opt-in");
+        assert!(
+            format!("{err:?}").contains("`output-history yes`"),
+            "{text}: {err:?}"
+        );
+    }
+}
+
+#[test]
+fn parse_mouse_warp() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a b c d)
+(deflayer base (mouse-warp top-left) (mouse-warp center) (mouse-warp 100 25) mouse-grid)
+"#;
+    let (_, _, _, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    for (osc, x, y) in [
+        (OsCode::KEY_A, 10922, 10922),
+        (OsCode::KEY_B, 32767, 32767),
+        (OsCode::KEY_C, 65535, 16383),
+    ] {
+        match layers[0][0][usize::from(osc)] {
+            Action::Custom(&[&CustomAction::SetMouse { x: ax, y: ay }]) => {
+                assert_eq!((ax, ay), (x, y), "{osc:?}")
+            }
+            _ => panic!("expected setmouse"),
+        }
+    }
+    assert!(matches!(
+        layers[0][0][usize::from(OsCode::KEY_D)],
+        Action::Custom(&[&CustomAction::MouseGrid])
+    ));
+
+    for (action, msg) in [
+        ("(mouse-warp middle)", "Unknown mouse-warp region middle"),
+        ("(mouse-warp 50 101)", "from 0 to 100"),
+        ("(mouse-warp 1 2 3)", "expects a region or two percentages"),
+    ] {
+        let mut s = ParsedState::default();
+        let err = parse_cfg_raw_string(format!("(defsrc a) (deflayer base {action})"), &mut s)
+            .expect_err("invalid mouse-warp is an error");
+        assert!(format!("{err:?}").contains(msg), "{action}: {err:?}");
+    }
+}
+
+#[test]
+fn parse_mouse_drag() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a b)
+(deflayer base (mouse-drag mrgt) mouse-drop)
+"#;
+    let (_, _, _, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    assert!(matches!(
+        layers[0][0][usize::from(OsCode::KEY_A)],
+        Action::Custom(&[&CustomAction::MouseDrag(Btn::Right)])
+    ));
+    assert!(matches!(
+        layers[0][0][usize::from(OsCode::KEY_B)],
+        Action::Custom(&[&CustomAction::MouseDrop])
+    ));
+
+    for action in [
+        "(mouse-drag)",
+        "(mouse-drag left)",
+        "(mouse-drag mlft mrgt)",
+    ] {
+        let mut s = ParsedState::default();
+        let err = parse_cfg_raw_string(format!("(defsrc a) (deflayer base {action})"), &mut s)
+            .expect_err("invalid mouse-drag is an error");
+        assert!(
+            format!("{err:?}").contains("mouse-drag expects one parameter"),
+            "{action}: {err:?}"
+        );
+    }
+}
+
+#[test]
+fn parse_dwell_click() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defcfg dwell-click-time 800)
+(defsrc a b)
+(deflayer base (dwell-click toggle) (dwell-click off))
+"#;
+    let (_, _, _, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    assert!(matches!(
+        layers[0][0][usize::from(OsCode::KEY_A)],
+        Action::Custom(&[&CustomAction::DwellClick(DwellClickAction::Toggle)])
+    ));
+    assert!(matches!(
+        layers[0][0][usize::from(OsCode::KEY_B)],
+        Action::Custom(&[&CustomAction::DwellClick(DwellClickAction::Off)])
+    ));
+
+    for action in ["(dwell-click)", "(dwell-click yes)"] {
+        let mut s = ParsedState::default();
+        let err = parse_cfg_raw_string(format!("(defsrc a) (deflayer base {action})"), &mut s)
+            .expect_err("invalid dwell-click is an error");
+        assert!(
+            format!("{err:?}").contains("dwell-click expects one parameter"),
+            "{action}: {err:?}"
+        );
+    }
+}
+
+#[test]
+fn parse_kvm_target() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a b c)
+(deflayer base (kvm-target laptop) (kvm-target next) (kvm-target local))
+"#;
+    let (_, _, _, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    assert!(matches!(
+        layers[0][0][usize::from(OsCode::KEY_A)],
+        Action::Custom(&[&CustomAction::KvmTarget(KvmSwitch::Target(ref name))]) if name == "laptop"
+    ));
+    assert!(matches!(
+        layers[0][0][usize::from(OsCode::KEY_B)],
+        Action::Custom(&[&CustomAction::KvmTarget(KvmSwitch::Next)])
+    ));
+    assert!(matches!(
+        layers[0][0][usize::from(OsCode::KEY_C)],
+        Action::Custom(&[&CustomAction::KvmTarget(KvmSwitch::Local)])
+    ));
+
+    let mut s = ParsedState::default();
+    let err = parse_cfg_raw_string("(defsrc a) (deflayer base (kvm-target))".into(), &mut s)
+        .expect_err("kvm-target without a target is an error");
+    assert!(format!("{err:?}").contains("kvm-target expects one parameter"));
+}
+
+#[test]
+fn parse_route_output() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a b)
+(deflayer base (route-output secondary) (route-output auto))
+"#;
+    let (_, _, _, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    assert!(matches!(
+        layers[0][0][usize::from(OsCode::KEY_A)],
+        Action::Custom(&[&CustomAction::RouteOutput(Some(ref target))]) if target == "secondary"
+    ));
+    assert!(matches!(
+        layers[0][0][usize::from(OsCode::KEY_B)],
+        Action::Custom(&[&CustomAction::RouteOutput(None)])
+    ));
+
+    let mut s = ParsedState::default();
+    let err = parse_cfg_raw_string(
+        "(defsrc a) (deflayer base (route-output a b))".into(),
+        &mut s,
+    )
+    .expect_err("route-output with two targets is an error");
+    assert!(format!("{err:?}").contains("route-output expects one parameter"));
+}
+
+#[test]
+fn parse_mouse_accel_profiles() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a b)
+(deflayer base (movemouse-accel-right 1 1000 1 5 quadratic) (layer-while-held fine))
+(deflayer fine _ _)
+(defmouseaccel fine linear 500 1 2)
+"#;
+    let (_, _, _, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    let accel = match layers[0][0][usize::from(OsCode::KEY_A)] {
+        Action::Custom(
+            &[&CustomAction::MoveMouseAccel {
+                direction: MoveDirection::Right,
+                interval: 1,
+                accel,
+            }],
+        ) => accel,
+        _ => panic!("expected movemouse-accel"),
+    };
+    assert_eq!(accel.curve, AccelCurve::Quadratic);
+    assert_eq!(accel.distance(0), 1);
+    assert_eq!(accel.distance(500), 2);
+    assert_eq!(accel.distance(1000), 5);
+    assert_eq!(accel.distance(5000), 5);
+
+    let fine = MouseAccel {
+        curve: AccelCurve::Linear,
+        accel_time: 500,
+        min_distance: 1,
+        max_distance: 2,
+    };
+    assert_eq!(s.mouse_accel_layers, vec![(2, fine), (3, fine)]);
+
+    for (item, msg) in [
+        ("(defmouseaccel nope linear 500 1 2)", "Unknown layer name"),
+        ("(defmouseaccel base cubic 500 1 2)", "linear or quadratic"),
+        (
+            "(defmouseaccel base linear 500 1)",
+            "defmouseaccel expects five parameters",
+        ),
+        ("(defmouseaccel base linear 500 3 2)", "min distance"),
+    ] {
+        let mut s = ParsedState::default();
+        let err = parse_cfg_raw_string(format!("(defsrc a) (deflayer base a) {item}"), &mut s)
+            .expect_err("invalid defmouseaccel is an error");
+        assert!(format!("{err:?}").contains(msg), "{item}: {err:?}");
+    }
+}
+
+#[test]
+fn parse_mod_translations() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a ralt)
+(deflayer base a ralt)
+(deflayer korean _ _)
+(defmodtranslation korean ralt hngl rctl hnja)
+"#;
+    parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    let translations = vec![
+        (OsCode::KEY_RIGHTALT, OsCode::KEY_HANGEUL),
+        (OsCode::KEY_RIGHTCTRL, OsCode::KEY_HANJA),
+    ];
+    assert_eq!(
+        s.mod_translations,
+        vec![(2, translations.clone()), (3, translations)]
+    );
+
+    for (item, msg) in [
+        ("(defmodtranslation nope ralt hngl)", "Unknown layer name"),
+        ("(defmodtranslation base a hngl)", "pairs of a modifier"),
+        ("(defmodtranslation base ralt)", "pairs of a modifier"),
+        ("(defmodtranslation base ralt nope)", "Unknown key name"),
+        (
+            "(defmodtranslation base ralt hngl ralt hnja)",
+            "already translated",
+        ),
+        (
+            "(defmodtranslation base ralt hngl) (defmodtranslation base rctl hnja)",
+            "already has a defmodtranslation",
+        ),
+    ] {
+        let mut s = ParsedState::default();
+        let err = parse_cfg_raw_string(format!("(defsrc a) (deflayer base a) {item}"), &mut s)
+            .expect_err("invalid defmodtranslation is an error");
+        assert!(format!("{err:?}").contains(msg), "{item}: {err:?}");
+    }
+}
+
+#[test]
+fn parse_src_layouts() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let source = r#"
+(defsrc a b c)
+(defsrcalt swapped b a _)
+(deflayer base x y z)
+(deflayer other _ (layer-switch base) _)
+"#;
+    let mut s = ParsedState::default();
+    let (_, mapped_keys, _, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    assert_eq!(
+        layers[0][0][usize::from(OsCode::KEY_A)],
+        Action::KeyCode(KeyCode::X)
+    );
+    assert_eq!(
+        layers[0][0][usize::from(OsCode::KEY_C)],
+        Action::KeyCode(KeyCode::Z)
+    );
+
+    let mut s = ParsedState::default();
+    let (_, mapped_keys_alt, _, layers, _, _, _) =
+        parse_cfg_raw_string(format!("(defcfg src-layout swapped) {source}"), &mut s).unwrap();
+    assert_eq!(
+        layers[0][0][usize::from(OsCode::KEY_B)],
+        Action::KeyCode(KeyCode::X)
+    );
+    assert_eq!(
+        layers[0][0][usize::from(OsCode::KEY_A)],
+        Action::KeyCode(KeyCode::Y)
+    );
+    // Transparent keys fall through to the physical key.
+    assert_eq!(
+        layers[2][0][usize::from(OsCode::KEY_B)],
+        Action::KeyCode(KeyCode::B)
+    );
+    assert!(mapped_keys.contains(&OsCode::KEY_C));
+    assert!(!mapped_keys_alt.contains(&OsCode::KEY_C));
+
+    for (cfg, msg) in [
+        ("(defcfg src-layout iso)", "src-layout iso has no defsrcalt"),
+        ("(defsrcalt swapped a b c)", "Duplicate defsrcalt name"),
+        ("(defsrcalt short a b)", "requires 3 to match defsrc"),
+        ("(defsrcalt bad a b nokey)", "Unknown key in defsrc"),
+    ] {
+        let mut s = ParsedState::default();
+        let err = parse_cfg_raw_string(format!("{cfg} {source}"), &mut s)
+            .expect_err("invalid defsrcalt is an error");
+        assert!(format!("{err:?}").contains(msg), "{cfg}: {err:?}");
+    }
+}
+
+#[test]
+fn parse_mod_chords() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a)
+(deflayer base a)
+(defmodchords (rsft lsft) (caps-word 2000) (lctl rctl) caps)
+"#;
+    parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    assert_eq!(
+        s.mod_chords,
+        vec![
+            (
+                vec![OsCode::KEY_LEFTSHIFT, OsCode::KEY_RIGHTSHIFT],
+                get_fake_key_coords(0usize)
+            ),
+            (
+                vec![OsCode::KEY_LEFTCTRL, OsCode::KEY_RIGHTCTRL],
+                get_fake_key_coords(1usize)
+            ),
+        ]
+    );
+    assert!(s.fake_keys.contains_key("rsft+lsft modchord"));
+
+    for (item, msg) in [
+        ("(defmodchords (lsft) caps)", "two or more modifiers"),
+        ("(defmodchords (lsft a) caps)", "two or more modifiers"),
+        ("(defmodchords (lsft lsft) caps)", "two or more modifiers"),
+        (
+            "(defmodchords (lsft rsft) caps (rsft lsft) a)",
+            "Duplicate modifier chord",
+        ),
+        ("(defmodchords (lsft rsft))", "has no action"),
+    ] {
+        let mut s = ParsedState::default();
+        let err = parse_cfg_raw_string(format!("(defsrc a) (deflayer base a) {item}"), &mut s)
+            .expect_err("invalid defmodchords is an error");
+        assert!(format!("{err:?}").contains(msg), "{item}: {err:?}");
+    }
+}
+
+#[test]
+fn parse_layer_tags() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a)
+(deflayer base a)
+(deflayer nav b)
+(deflayer num c)
+(deflayertags nav nav left-hand)
+(deflayertags num experimental)
+(deflayertags nav experimental left-hand)
+"#;
+    let (_, _, layer_info, _, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    assert!(layer_info[0].tags.is_empty());
+    assert_eq!(layer_info[2].tags, ["nav", "left-hand", "experimental"]);
+    assert_eq!(layer_info[3].tags, layer_info[2].tags);
+    assert_eq!(layer_info[4].tags, ["experimental"]);
+
+    for source in [
+        "(defsrc a) (deflayer base a) (deflayertags base)",
+        "(defsrc a) (deflayer base a) (deflayertags sym experimental)",
+        "(defsrc a) (deflayer base a) (deflayertags base (experimental))",
+    ] {
+        let mut s = ParsedState::default();
+        parse_cfg_raw_string(source.into(), &mut s).expect_err(source);
+    }
+}
+
+#[test]
+fn parse_layer_notify() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a)
+(deflayer base a)
+(deflayer nav b)
+(deflayernotify nav label "Navigation mode" icon compass)
+"#;
+    let (_, _, layer_info, _, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    assert!(layer_info[0].notify.is_empty());
+    let fields = [("label", "Navigation mode"), ("icon", "compass")]
+        .map(|(f, v)| (f.to_owned(), v.to_owned()));
+    assert_eq!(layer_info[2].notify, fields);
+    assert_eq!(layer_info[3].notify, fields);
+
+    for source in [
+        "(defsrc a) (deflayer base a) (deflayernotify base)",
+        "(defsrc a) (deflayer base a) (deflayernotify base label)",
+        "(defsrc a) (deflayer base a) (deflayernotify sym label Symbols)",
+        "(defsrc a) (deflayer base a) (deflayernotify base label (Base))",
+        "(defsrc a) (deflayer base a) (deflayernotify base label a label b)",
+        "(defsrc a) (deflayer base a) (deflayernotify base label a) (deflayernotify base icon b)",
+    ] {
+        let mut s = ParsedState::default();
+        parse_cfg_raw_string(source.into(), &mut s).expect_err(source);
+    }
+}
+
+#[test]
+fn parse_layer_docs() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a s)
+(deflayer base a s)
+(deflayer nav left right)
+(deflayerdoc nav "Arrow keys" a "Move left" s "Move right")
+"#;
+    let (_, _, layer_info, _, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    assert!(layer_info[0].doc.description.is_none());
+    assert_eq!(layer_info[3].doc.description.as_deref(), Some("Arrow keys"));
+    assert_eq!(layer_info[3].doc.key(OsCode::KEY_A), Some("Move left"));
+    assert_eq!(layer_info[3].doc.key(OsCode::KEY_S), Some("Move right"));
+    assert_eq!(layer_info[3].doc.key(OsCode::KEY_D), None);
+
+    let prefix = "(defsrc a) (deflayer base a)";
+    for (item, msg) in [
+        ("(deflayerdoc base)", "pairs of"),
+        ("(deflayerdoc sym Symbols)", "Unknown layer"),
+        ("(deflayerdoc base (Base))", "must be a string"),
+        ("(deflayerdoc base Base a)", "missing a description"),
+        ("(deflayerdoc base Base s Save)", "not in defsrc"),
+        ("(deflayerdoc base Base a b a c)", "already described"),
+        ("(deflayerdoc base a) (deflayerdoc base b)", "Only one"),
+    ] {
+        let mut s = ParsedState::default();
+        let err = parse_cfg_raw_string(format!("{prefix} {item}"), &mut s)
+            .expect_err("invalid deflayerdoc is an error");
+        assert!(format!("{err:?}").contains(msg), "{item}: {err:?}");
+    }
+}
+
+#[test]
+fn parse_modal_layers() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a s d)
+(deflayer base a s d)
+(deflayer normal left d c)
+(defmodal normal (d C-x) (c C-x base))
+"#;
+    parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    let cut = vec![OsCode::KEY_LEFTCTRL, OsCode::KEY_X];
+    let operators = vec![
+        ModalOperator {
+            key: OsCode::KEY_D,
+            chord: cut.clone(),
+            then_layer: None,
+        },
+        ModalOperator {
+            key: OsCode::KEY_C,
+            chord: cut,
+            then_layer: Some(0),
+        },
+    ];
+    assert_eq!(s.modal_layers, [(2, operators.clone()), (3, operators)]);
+
+    let prefix = "(defsrc a) (deflayer base a) (deflayer normal a)";
+    for (item, msg) in [
+        ("(defmodal)", "expects a layer name"),
+        ("(defmodal visual)", "Unknown layer name"),
+        ("(defmodal normal d)", "expects a layer name"),
+        ("(defmodal normal (d))", "expects a layer name"),
+        ("(defmodal normal (d C-x visual))", "Unknown layer name"),
+        ("(defmodal normal (5 C-x))", "modifier or a digit"),
+        ("(defmodal normal (lsft C-x))", "modifier or a digit"),
+        ("(defmodal normal (nokey C-x))", "Unknown key name"),
+        ("(defmodal normal (d C-nokey))", "Invalid chord"),
+        ("(defmodal normal (d C-x) (d C-c))", "already an operator"),
+        ("(defmodal normal) (defmodal normal)", "already has a"),
+    ] {
+        let mut s = ParsedState::default();
+        let err = parse_cfg_raw_string(format!("{prefix} {item}"), &mut s)
+            .expect_err("invalid defmodal is an error");
+        assert!(format!("{err:?}").contains(msg), "{item}: {err:?}");
+    }
+}
+
+#[test]
+fn parse_device_tags() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a)
+(deflayer base a)
+(deflayer numpad 1)
+(deflayer tkl kp1)
+(deflayertags numpad numpad)
+(deflayertags tkl tkl-compensation)
+(defdevicetags "Keychron K8" numpad !tkl-compensation)
+"#;
+    parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    assert_eq!(
+        s.device_tags,
+        vec![(
+            "Keychron K8".to_owned(),
+            vec![
+                ("numpad".to_owned(), true),
+                ("tkl-compensation".to_owned(), false),
+            ],
+        )]
+    );
+
+    let prefix = "(defsrc a) (deflayer base a) (deflayertags base numpad)";
+    for (item, msg) in [
+        ("(defdevicetags kbd)", "followed by one or more tags"),
+        ("(defdevicetags (kbd) numpad)", "followed by one or more tags"),
+        ("(defdevicetags kbd nope)", "No layer has this tag"),
+        ("(defdevicetags kbd !nope)", "No layer has this tag"),
+        ("(defdevicetags kbd (numpad))", "must be a name"),
+        (
+            "(defdevicetags kbd numpad) (defdevicetags kbd !numpad)",
+            "already has a defdevicetags",
+        ),
+    ] {
+        let mut s = ParsedState::default();
+        let err = parse_cfg_raw_string(format!("{prefix} {item}"), &mut s)
+            .expect_err("invalid defdevicetags is an error");
+        assert!(format!("{err:?}").contains(msg), "{item}: {err:?}");
+    }
+}
+
+#[test]
+fn parse_helpers() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let source = r#"(defsrc a) (deflayer base a) (defhelpers tray (kanata-tray "-p" 5829))"#;
+    let mut s = ParsedState::default();
+    let err = parse_cfg_raw_string(source.into(), &mut s)
+        .expect_err("defhelpers without danger-enable-cmd is an error");
+    assert!(format!("{err:?}").contains("danger-enable-cmd"), "{err:?}");
+
+    #[cfg(feature = "cmd")]
+    {
+        let mut s = ParsedState::default();
+        parse_cfg_raw_string(format!("(defcfg danger-enable-cmd yes) {source}"), &mut s).unwrap();
+        assert_eq!(
+            s.helpers,
+            vec![(
+                "tray".to_owned(),
+                vec!["kanata-tray".to_owned(), "-p".to_owned(), "5829".to_owned()],
+            )]
+        );
+
+        let prefix = "(defcfg danger-enable-cmd yes) (defsrc a) (deflayer base a)";
+        for (item, msg) in [
+            ("(defhelpers tray)", "missing its command"),
+            ("(defhelpers tray ())", "a name and a list"),
+            ("(defhelpers tray kanata-tray)", "a name and a list"),
+            ("(defhelpers (tray) (kanata-tray))", "a name and a list"),
+            ("(defhelpers tray (kanata-tray (-p)))", "not lists"),
+            ("(defhelpers tray (a) tray (b))", "Duplicate helper name"),
+        ] {
+            let mut s = ParsedState::default();
+            let err = parse_cfg_raw_string(format!("{prefix} {item}"), &mut s)
+                .expect_err("invalid defhelpers is an error");
+            assert!(format!("{err:?}").contains(msg), "{item}: {err:?}");
+        }
+    }
+}
+
+#[test]
+fn parse_tap_dance_interrupt() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a b c)
+(deflayer base (tap-dance 200 (a b)) (tap-dance 200 (a b) cancel) (tap-dance 200 (a b) buffer))
+"#;
+    let (_, _, _, layers, _, _, _) = parse_cfg_raw_string(source.into(), &mut s).unwrap();
+    for (osc, interrupt) in [
+        (OsCode::KEY_A, TapDanceInterrupt::Resolve),
+        (OsCode::KEY_B, TapDanceInterrupt::Cancel),
+        (OsCode::KEY_C, TapDanceInterrupt::Buffer),
+    ] {
+        match layers[0][0][usize::from(osc)] {
+            Action::TapDance(td) => assert_eq!(td.interrupt, interrupt),
+            ref ac => panic!("expected a tap-dance, found {ac:?}"),
+        }
+    }
+
+    for source in [
+        "(defsrc a) (deflayer base (tap-dance 200 (a b) later))",
+        "(defsrc a) (deflayer base (tap-dance-eager 200 (a b) cancel))",
+        "(defsrc a) (deflayer base (tap-dance 200 (a b) cancel buffer))",
+    ] {
+        let mut s = ParsedState::default();
+        parse_cfg_raw_string(source.into(), &mut s).expect_err(source);
+    }
+}
+
+#[test]
+fn parse_one_shot_of_any_action() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defsrc a b c)
+(deflayer base
+  (one-shot 500 (tap-hold 200 200 a lalt))
+  (one-shot 500 (tap-dance 200 (lsft lctl)))
+  (one-shot 500 (multi lsft (layer-while-held base))))
+"#;
+    parse_cfg_raw_string(source.into(), &mut s).unwrap();
+
+    let mut s = ParsedState::default();
+    let err = parse_cfg_raw_string(
+        "(defsrc a) (deflayer base (one-shot 500 (one-shot 500 lsft)))".into(),
+        &mut s,
+    )
+    .expect_err("nested one-shot is an error");
+    assert!(format!("{err:?}").contains("another one-shot"), "{err:?}");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn parse_extra_keys() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(deflocalkeys-linux mykey 751)
+(defextrakeys copy paste)
+(defsrc a b c)
+(deflayer base (macro copy a) (chord g x) (chord g y))
+(deffakekeys pasted (macro C-v))
+(defchords g 50 (x) copy (x y) paste)
+(defseq pasted (paste copy))
+"#;
+    let result = parse_cfg_raw_string(source.into(), &mut s).map(|_| ());
+    let codes = ["mykey", "copy", "paste"].map(|name| str_to_oscode(name).map(OsCode::as_u16));
+    for (item, msg) in [
+        ("(defextrakeys lsft)", "default key name"),
+        ("(defextrakeys copy copy)", "Duplicate copy"),
+        (
+            "(deflocalkeys-linux copy 751) (defextrakeys copy)",
+            "Duplicate copy",
+        ),
+        ("(defextrakeys (copy))", "No lists"),
+        (
+            "(defextrakeys a1 a2 a3 a4 a5 a6 a7 a8 a9 a10 a11 a12 a13 a14 a15 a16 a17 a18 a19)",
+            "Too many keys",
+        ),
+        (
+            "(defextrakeys copy) (defextrakeys paste)",
+            "Only one defextrakeys",
+        ),
+    ] {
+        let mut s = ParsedState::default();
+        let err = parse_cfg_raw_string(format!("{item} (defsrc a) (deflayer base a)"), &mut s)
+            .expect_err("invalid defextrakeys is an error");
+        assert!(format!("{err:?}").contains(msg), "{item}: {err:?}");
+    }
+    replace_custom_str_oscode_mapping(&HashMap::default());
+
+    result.unwrap();
+    assert_eq!(codes, [Some(751), Some(752), Some(753)]);
+}
+
+#[test]
+fn parse_matrix() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut s = ParsedState::default();
+    let source = r#"
+(defextrakeys copy)
+(defmatrix
+  (esc 1 2 _)
+  (tab q w copy))
+(defsrc r0c0 r0c1 r1c0)
+(deflayer base r1c3 r1c2 r1c1)
+"#;
+    let result = parse_cfg_raw_string(source.into(), &mut s).map(|_| ());
+    let codes = ["r0c0", "r0c3", "r1c1", "r1c3", "copy"].map(str_to_oscode);
+    for (item, msg) in [
+        ("(defmatrix esc)", "must be a list"),
+        ("(defmatrix (esc (1)))", "No lists"),
+        ("(defmatrix (esc nosuchkey))", "Unknown key"),
+        ("(defmatrix (esc)) (defmatrix (tab))", "Only one defmatrix"),
+    ] {
+        let mut s = ParsedState::default();
+        let err = parse_cfg_raw_string(format!("{item} (defsrc a) (deflayer base a)"), &mut s)
+            .expect_err("invalid defmatrix is an error");
+        assert!(format!("{err:?}").contains(msg), "{item}: {err:?}");
+    }
+    replace_custom_str_oscode_mapping(&HashMap::default());
+
+    result.unwrap();
+    assert_eq!(
+        codes[..3],
+        [Some(OsCode::KEY_ESC), None, Some(OsCode::KEY_Q)]
+    );
+    // Matrix positions can be keys of defextrakeys.
+    assert!(codes[3].is_some());
+    assert_eq!(codes[3], codes[4]);
+}
+
+#[test]
+fn parse_include() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let dir = std::env::temp_dir().join(format!("kanata-include-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("base.kbd"),
+        "(defsrc a b) (defalias x b) (deflayer base @x b)",
+    )
+    .unwrap();
+    std::fs::write(dir.join("broken.kbd"), "(defsrc a)\n(deflayer base nope)").unwrap();
+    std::fs::write(dir.join("nested.kbd"), "(include base.kbd)").unwrap();
+    let build = |text: &str| {
+        CfgBuilder::from_text(
+            dir.join("kanata.kbd").to_string_lossy().to_string(),
+            text.into(),
+        )
+        .parse()
+        .and_then(|p| p.resolve())
+    };
+
+    let cfg = build(
+        "
+(include base.kbd)
+(defalias x z)
+(deflayer extra @base/x @x)
+(deftest base-a (tap a) (expect b))
+(deftest extra-a (layer extra) (tap a) (expect b))
+(deftest extra-b (layer extra) (tap b) (expect z))
+",
+    )
+    .unwrap()
+    .validate()
+    .freeze();
+    for test in cfg.tests.iter() {
+        assert_eq!(test.run(&cfg), Ok(()), "{}", test.name);
+    }
+    assert_eq!(cfg.tests.len(), 3);
+
+    // Errors show the text of the file they are in.
+    let err = build("(include broken.kbd)")
+        .err()
+        .expect("unknown key is an error");
+    let label = err.labels().unwrap().next().unwrap();
+    let contents = err
+        .source_code()
+        .unwrap()
+        .read_span(label.inner(), 0, 0)
+        .unwrap();
+    assert_eq!(std::str::from_utf8(contents.data()).unwrap(), "nope");
+    assert_eq!(
+        contents.name(),
+        Some(dir.join("broken.kbd").to_string_lossy().as_ref())
+    );
+    assert_eq!(contents.line(), 1);
+
+    for (text, msg) in [
+        ("(include missing.kbd)", "Failed to read the included file"),
+        ("(include)", "include expects one file path"),
+        (
+            "(include nested.kbd)",
+            "only allowed in the main configuration file",
+        ),
+        (
+            "(include base.kbd) (include ./base.kbd)",
+            "Another included file is also named base",
+        ),
+    ] {
+        let err = build(text).err().expect("invalid include is an error");
+        assert!(format!("{err:?}").contains(msg), "{text}: {err:?}");
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Compares the transcripts of the scenarios in `tests/golden/*.kbd` with the `.golden` files next
+/// to them. Each scenario is a `deftest` item; its expectations must pass as well. Run with
+/// `KANATA_UPDATE_GOLDEN=1` to re-record the golden files after an intended change.
+#[test]
+fn golden_transcripts() {
+    let _lk = match CFG_PARSE_LOCK.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let update = std::env::var_os("KANATA_UPDATE_GOLDEN").is_some();
+    let mut paths = std::fs::read_dir("./tests/golden")
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "kbd"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(!paths.is_empty());
+    let mut mismatches = vec![];
+    for path in paths {
+        let cfg = new_from_file(&path).unwrap();
+        let mut transcript = String::new();
+        for test in cfg.tests.iter() {
+            let mut sim = Simulation::new(&cfg);
+            if let Err(e) = test.run_in(&mut sim) {
+                panic!("{}: {}: {e}", path.display(), test.name);
+            }
+            transcript.push_str(&format!("== {}\n{}", test.name, sim.transcript()));
+        }
+        let golden_path = path.with_extension("golden");
+        if update {
+            std::fs::write(&golden_path, &transcript).unwrap();
+        } else if std::fs::read_to_string(&golden_path).ok().as_deref() != Some(&transcript) {
+            mismatches.push(format!(
+                "{} differs, the new transcript is:\n{transcript}",
+                golden_path.display()
+            ));
+        }
+    }
+    assert!(
+        mismatches.is_empty(),
+        "{}\nRun with KANATA_UPDATE_GOLDEN=1 to re-record the golden files if the change is \
+         intended.",
+        mismatches.join("\n")
+    );
+}
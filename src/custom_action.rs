@@ -1,3 +1,5 @@
+use crate::cfg::KanataAction;
+use crate::keys::OsCode;
 use kanata_keyberon::key_code::KeyCode;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -5,6 +7,12 @@ pub enum CustomAction {
     Cmd(Vec<String>),
     CmdOutputKeys(Vec<String>),
     Unicode(char),
+    /// Start typing a character by its code point in this radix.
+    UnicodeInput(u32),
+    /// Type the clipboard contents if they have at most this many characters.
+    ClipboardPaste {
+        max_chars: usize,
+    },
     Mouse(Btn),
     MouseTap(Btn),
     FakeKey {
@@ -30,11 +38,10 @@ pub enum CustomAction {
     MoveMouseAccel {
         direction: MoveDirection,
         interval: u16,
-        accel_time: u16,
-        min_distance: u16,
-        max_distance: u16,
+        accel: MouseAccel,
     },
     SequenceLeader,
+    Launcher,
     LiveReload,
     LiveReloadNext,
     LiveReloadPrev,
@@ -49,6 +56,92 @@ pub enum CustomAction {
         x: u16,
         y: u16,
     },
+    MouseGrid,
+    /// Press the button until the next `MouseDrag` or `MouseDrop`.
+    MouseDrag(Btn),
+    MouseDrop,
+    DwellClick(DwellClickAction),
+    Morse(MorseCfg),
+    /// Key pairs, in both directions, whose positions are swapped while the key is held.
+    SwapHands(&'static [(OsCode, OsCode)]),
+    /// Latch the key down until the action is activated again.
+    ToggleKey(KeyCode),
+    /// Keep the held layer active after its key is released, or release it if it is locked.
+    LayerLock,
+    /// Activate the keyberon layer for the seconds.
+    TempLayer {
+        layer: usize,
+        seconds: u16,
+    },
+    /// Output every key unchanged until a TCP client or a live reload turns it off.
+    Passthrough,
+    /// Release every modifier on the output, for when the OS has modifiers stuck.
+    ResyncModifiers,
+    /// Send the output to another machine of `kvm-targets`, or back to this one.
+    KvmTarget(KvmSwitch),
+    /// Send the output to a target of the output router, or to the targets of the layers again
+    /// with `None`.
+    RouteOutput(Option<String>),
+    SetVar {
+        name: String,
+        value: String,
+    },
+    SwitchVar {
+        name: String,
+        cases: &'static [SwitchVarCase],
+    },
+    /// Activate the action unless it was activated less than the milliseconds ago.
+    Cooldown {
+        ms: u16,
+        action: ActionRef,
+    },
+    /// Activate the action as many times as the typed count says, see `count-layers`.
+    Counted(ActionRef),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AccelCurve {
+    Linear,
+    Quadratic,
+}
+
+/// How the distance of mouse movements grows while a movement key is held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MouseAccel {
+    pub curve: AccelCurve,
+    /// Milliseconds until `max_distance` is reached.
+    pub accel_time: u16,
+    pub min_distance: u16,
+    pub max_distance: u16,
+}
+
+impl MouseAccel {
+    /// The distance of a movement this many milliseconds after the key was pressed.
+    pub fn distance(&self, elapsed: u16) -> u16 {
+        let t = (f64::from(elapsed) / f64::from(self.accel_time)).min(1.0);
+        let t = match self.curve {
+            AccelCurve::Linear => t,
+            AccelCurve::Quadratic => t * t,
+        };
+        let (min, max) = (f64::from(self.min_distance), f64::from(self.max_distance));
+        (min + (max - min) * t) as u16
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum KvmSwitch {
+    /// This machine.
+    Local,
+    /// The target after the active one, or this machine after the last target.
+    Next,
+    Target(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DwellClickAction {
+    On,
+    Off,
+    Toggle,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -95,3 +188,39 @@ pub struct CapsWordCfg {
     pub keys_nonterminal: &'static [KeyCode],
     pub timeout: u16,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MorseCfg {
+    /// Presses held for at least this many ms are dashes; shorter presses are dots.
+    pub dash_threshold: u16,
+    /// Idle ms after which the collected dots and dashes are typed as a character.
+    pub letter_gap: u16,
+    /// Idle ms after which a space is typed and Morse input ends.
+    pub word_gap: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SwitchVarCase {
+    /// The value the variable must have for this case to be chosen. `None` matches any value.
+    pub value: Option<String>,
+    pub action: ActionRef,
+}
+
+/// A reference to an action that is compared and hashed by its address, since actions implement
+/// neither `Eq` nor `Hash`.
+#[derive(Debug, Clone, Copy)]
+pub struct ActionRef(pub &'static KanataAction);
+
+impl PartialEq for ActionRef {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.0, other.0)
+    }
+}
+
+impl Eq for ActionRef {}
+
+impl std::hash::Hash for ActionRef {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::ptr::hash(self.0, state)
+    }
+}
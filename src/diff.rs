@@ -0,0 +1,151 @@
+//! `kanata diff`: the changes in behaviour between two versions of a configuration.
+//!
+//! Both configurations are parsed, so aliases, templates and variables are resolved, and the
+//! action of every key is compared per layer, by layer name. Changes that do not change what a key
+//! does, e.g. reordered or renamed aliases, are not listed. Transparent keys are compared as
+//! transparent rather than by the action of the layer below them.
+
+use crate::cfg::{self, Cfg};
+use crate::explain::describe_action;
+use crate::keys::OsCode;
+
+use anyhow::{anyhow, Result};
+
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// Print the changed actions between the configurations.
+pub fn run(old_path: &Path, new_path: &Path) -> Result<()> {
+    let old = cfg::new_from_file(old_path).map_err(|e| anyhow!("{e:?}"))?;
+    let new = cfg::new_from_file(new_path).map_err(|e| anyhow!("{e:?}"))?;
+    let diff = diff(&old, &new);
+    if diff.is_empty() {
+        println!("no key does anything different");
+    } else {
+        print!("{diff}");
+    }
+    Ok(())
+}
+
+/// The layers that were added or removed and, for the layers in both configurations, the keys
+/// whose action changed with the old action prefixed by `-` and the new one by `+`.
+fn diff(old: &Cfg, new: &Cfg) -> String {
+    let mut text = String::new();
+    let mut keys = old
+        .mapped_keys
+        .union(&new.mapped_keys)
+        .copied()
+        .collect::<Vec<_>>();
+    keys.sort_by_key(|k| *k as u16);
+    for info in old.layer_info.iter().step_by(2) {
+        if layer_index(new, &info.name).is_none() {
+            let _ = writeln!(text, "layer {}: removed", info.name);
+        }
+    }
+    for (i, info) in new.layer_info.iter().enumerate().step_by(2) {
+        let Some(old_idx) = layer_index(old, &info.name) else {
+            let _ = writeln!(text, "layer {}: added", info.name);
+            continue;
+        };
+        let mut changes = String::new();
+        for key in keys.iter().copied() {
+            let before = describe_key(old, old_idx, key);
+            let after = describe_key(new, i, key);
+            if before == after {
+                continue;
+            }
+            let _ = writeln!(changes, "  {}:", key_name(key));
+            for line in before.lines() {
+                let _ = writeln!(changes, "    - {line}");
+            }
+            for line in after.lines() {
+                let _ = writeln!(changes, "    + {line}");
+            }
+        }
+        if !changes.is_empty() {
+            let _ = writeln!(text, "layer {}:\n{changes}", info.name);
+        }
+    }
+    text
+}
+
+/// The index of the first keyberon copy of the named layer.
+fn layer_index(cfg: &Cfg, name: &str) -> Option<usize> {
+    cfg.layer_info
+        .iter()
+        .enumerate()
+        .step_by(2)
+        .find_map(|(i, info)| (info.name == name).then_some(i))
+}
+
+/// Describe the action of the key on the layer, where `layer` is the index of the first keyberon
+/// copy of the layer.
+fn describe_key(cfg: &Cfg, layer: usize, key: OsCode) -> String {
+    if !cfg.mapped_keys.contains(&key) {
+        return "not in defsrc, passed through\n".into();
+    }
+    // keyberon has two layers for every layer in the configuration. The first copy of the first
+    // layer is the default layer; the second copy of the others keeps `_` transparent.
+    let idx = if layer == 0 { 0 } else { layer + 1 };
+    let action = &cfg.layout.b().layers[idx][0][usize::from(key as u16)];
+    describe_action(action, &cfg.layer_info)
+}
+
+fn key_name(key: OsCode) -> String {
+    format!("{key:?}").trim_start_matches("KEY_").to_lowercase()
+}
+
+#[test]
+fn diff_lists_changed_actions_per_layer() {
+    let parse = |name: &str, text: &str| {
+        let path =
+            std::env::temp_dir().join(format!("kanata-diff-{name}-{}.kbd", std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        let cfg = cfg::new_from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        cfg.unwrap()
+    };
+    let old = parse(
+        "old",
+        "
+(defsrc a s d)
+(defalias nav (layer-while-held nav))
+(deflayer base a @nav d)
+(deflayer nav left _ right)
+(deflayer num 1 2 3)
+",
+    );
+    let new = parse(
+        "new",
+        "
+(defsrc a s d f)
+(defalias n (layer-while-held nav))
+(deflayer base a @n C-d f)
+(deflayer nav home _ right _)
+(deflayer sym 1 2 3 4)
+",
+    );
+    assert_eq!(
+        diff(&old, &new),
+        "layer num: removed
+layer base:
+  d:
+    - key KEY_D
+    + keys KEY_LEFTCTRL + KEY_D
+  f:
+    - not in defsrc, passed through
+    + key KEY_F
+
+layer nav:
+  a:
+    - key KEY_LEFT
+    + key KEY_HOME
+  f:
+    - not in defsrc, passed through
+    + transparent
+
+layer sym: added
+"
+    );
+    assert_eq!(diff(&new, &new), "");
+}
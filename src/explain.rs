@@ -0,0 +1,306 @@
+//! `kanata explain`: show how a key is resolved through the layer stack.
+//!
+//! The explanation lists the layers from the top of the stack down to the layer whose action
+//! wins, the action with the actions it expands to, and the state machines that the action
+//! involves, such as the tap-hold waiting state, with the descriptions of the layer and the key
+//! from `deflayerdoc`. It is computed either for the active layers of a running kanata instance,
+//! through its TCP server, or for a hypothetical stack of layers of a configuration file.
+
+use crate::cfg::{self, BorrowedKLayout, LayerInfo};
+use crate::keys::{str_to_oscode, OsCode};
+use crate::tcp_client::KanataClient;
+use crate::tcp_server::{ClientMessage, ServerMessage};
+
+use anyhow::{anyhow, Result};
+use kanata_keyberon::action::Action;
+
+use std::fmt::{Debug, Write as _};
+use std::path::Path;
+
+/// Print the explanation for the key, from the running kanata instance on the port if given, or
+/// else from the configuration file with the given layers active, bottom first. Without layers,
+/// only the first layer is active.
+pub fn run(key: &str, cfg_path: &Path, layers: &[String], port: Option<u16>) -> Result<()> {
+    if let Some(port) = port {
+        println!("{}", explain_remote(key, port)?);
+        return Ok(());
+    }
+    let osc = str_to_oscode(key).ok_or_else(|| anyhow!("unknown key {key}"))?;
+    let cfg = cfg::new_from_file(cfg_path).map_err(|e| anyhow!("{e:?}"))?;
+    let stack = layer_stack(layers, &cfg.layer_info)?;
+    println!(
+        "{}",
+        explain_key(cfg.layout.b(), &cfg.layer_info, &stack, osc)
+    );
+    Ok(())
+}
+
+fn explain_remote(key: &str, port: u16) -> Result<String> {
+    KanataClient::connect(port)?.request(
+        &ClientMessage::Explain { key: key.into() },
+        "explanation",
+        |msg| match msg {
+            ServerMessage::Explanation { text } => Some(text),
+            _ => None,
+        },
+    )
+}
+
+/// Returns the keyberon layer indexes of the named layers. The first layer is the default layer
+/// and the others are active as if by `layer-while-held`.
+fn layer_stack(names: &[String], layer_info: &[LayerInfo]) -> Result<Vec<usize>> {
+    if names.is_empty() {
+        return Ok(vec![0]);
+    }
+    names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let idx = layer_info
+                .iter()
+                .position(|info| &info.name == name)
+                .ok_or_else(|| anyhow!("unknown layer {name}"))?;
+            // keyberon has two layers for every layer in the configuration; the odd one is used
+            // for layer-while-held.
+            Ok(if i == 0 { idx } else { idx + 1 })
+        })
+        .collect()
+}
+
+/// Explain the resolution of the key with the given keyberon layers active, bottom first.
+pub fn explain_key(
+    layout: &BorrowedKLayout,
+    layer_info: &[LayerInfo],
+    stack: &[usize],
+    key: OsCode,
+) -> String {
+    let mut text = format!("{key:?}\n");
+    let Some((winner, action)) = stack.iter().rev().find_map(|&idx| {
+        let action = &layout.layers[idx][0][usize::from(key as u16)];
+        if matches!(action, Action::Trans) {
+            let _ = writeln!(text, "  layer {}: transparent", layer_info[idx].name);
+            None
+        } else {
+            Some((&layer_info[idx], action))
+        }
+    }) else {
+        text += "  no layer maps the key; it is passed through\n";
+        return text;
+    };
+    match &winner.doc.description {
+        Some(description) => {
+            let _ = writeln!(text, "  layer {}: wins ({description})", winner.name);
+        }
+        None => {
+            let _ = writeln!(text, "  layer {}: wins", winner.name);
+        }
+    }
+    if let Some(description) = winner.doc.key(key) {
+        let _ = writeln!(text, "description: {description}");
+    }
+    text += "action:\n";
+    let mut machines = vec![];
+    describe(action, layer_info, 1, &mut text, &mut machines);
+    if machines.is_empty() {
+        text += "state machines: none";
+    } else {
+        machines.dedup();
+        let _ = write!(text, "state machines: {}", machines.join(", "));
+    }
+    text
+}
+
+/// Describe the action and the actions it expands to, one per line with nested actions indented.
+pub fn describe_action<T: Debug + PartialEq>(
+    action: &Action<T>,
+    layer_info: &[LayerInfo],
+) -> String {
+    let mut text = String::new();
+    describe(action, layer_info, 0, &mut text, &mut vec![]);
+    text
+}
+
+fn describe<T: Debug + PartialEq>(
+    action: &Action<T>,
+    layer_info: &[LayerInfo],
+    depth: usize,
+    text: &mut String,
+    machines: &mut Vec<&'static str>,
+) {
+    let indent = "  ".repeat(depth);
+    match action {
+        Action::NoOp => {
+            let _ = writeln!(text, "{indent}nothing (XX)");
+        }
+        Action::Trans => {
+            let _ = writeln!(text, "{indent}transparent");
+        }
+        Action::KeyCode(k) => {
+            let _ = writeln!(text, "{indent}key {:?}", OsCode::from(*k));
+        }
+        Action::MultipleKeyCodes(ks) => {
+            let keys = ks.iter().map(|k| format!("{:?}", OsCode::from(*k)));
+            let _ = writeln!(
+                text,
+                "{indent}keys {}",
+                keys.collect::<Vec<_>>().join(" + ")
+            );
+        }
+        Action::MultipleActions(actions) => {
+            for (i, action) in actions.iter().enumerate() {
+                describe_nested(
+                    &format!("action {}", i + 1),
+                    action,
+                    layer_info,
+                    depth,
+                    text,
+                    machines,
+                );
+            }
+        }
+        Action::Layer(idx) => {
+            let _ = writeln!(text, "{indent}layer-while-held {}", layer_info[*idx].name);
+        }
+        Action::DefaultLayer(idx) => {
+            let _ = writeln!(text, "{indent}layer-switch {}", layer_info[*idx].name);
+        }
+        Action::Sequence { events } | Action::RepeatableSequence { events } => {
+            machines.push("macro");
+            let _ = writeln!(text, "{indent}macro of {} events", events.len());
+        }
+        Action::CancelSequences => {
+            let _ = writeln!(text, "{indent}cancel macros");
+        }
+        Action::ReleaseState(state) => {
+            let _ = writeln!(text, "{indent}release {state:?}");
+        }
+        Action::HoldTap(ht) => {
+            machines.push("tap-hold waiting state");
+            let _ = writeln!(
+                text,
+                "{indent}tap-hold, timeout {}ms, {:?}",
+                ht.timeout, ht.config
+            );
+            describe_nested("tap", &ht.tap, layer_info, depth, text, machines);
+            describe_nested("hold", &ht.hold, layer_info, depth, text, machines);
+            if ht.timeout_action != ht.hold {
+                describe_nested(
+                    "on timeout",
+                    &ht.timeout_action,
+                    layer_info,
+                    depth,
+                    text,
+                    machines,
+                );
+            }
+        }
+        Action::Custom(custom) => {
+            machines.push("custom action");
+            let _ = writeln!(text, "{indent}custom {custom:?}");
+        }
+        Action::OneShot(os) => {
+            machines.push("one-shot");
+            let _ = writeln!(
+                text,
+                "{indent}one-shot, timeout {}ms, {:?}",
+                os.timeout, os.end_config
+            );
+            describe_nested("action", os.action, layer_info, depth, text, machines);
+        }
+        Action::TapDance(td) => {
+            machines.push("tap-dance");
+            let _ = writeln!(
+                text,
+                "{indent}tap-dance, timeout {}ms, {:?} when interrupted",
+                td.timeout, td.interrupt
+            );
+            for (i, action) in td.actions.iter().enumerate() {
+                describe_nested(
+                    &format!("tap {}", i + 1),
+                    action,
+                    layer_info,
+                    depth,
+                    text,
+                    machines,
+                );
+            }
+        }
+        Action::Chords(group) => {
+            machines.push("chords");
+            let _ = writeln!(
+                text,
+                "{indent}chord group of {} chords, timeout {}ms",
+                group.chords.len(),
+                group.timeout
+            );
+        }
+        Action::Fork(fork) => {
+            let triggers = fork.right_triggers.iter().map(|k| OsCode::from(*k));
+            let _ = writeln!(
+                text,
+                "{indent}fork, right if {:?} is held",
+                triggers.collect::<Vec<_>>()
+            );
+            describe_nested("left", &fork.left, layer_info, depth, text, machines);
+            describe_nested("right", &fork.right, layer_info, depth, text, machines);
+        }
+        Action::OnRelease(action) => {
+            describe_nested("on release", action, layer_info, depth, text, machines);
+        }
+    }
+}
+
+fn describe_nested<T: Debug + PartialEq>(
+    label: &str,
+    action: &Action<T>,
+    layer_info: &[LayerInfo],
+    depth: usize,
+    text: &mut String,
+    machines: &mut Vec<&'static str>,
+) {
+    let _ = writeln!(text, "{}{label}:", "  ".repeat(depth + 1));
+    describe(action, layer_info, depth + 2, text, machines);
+}
+
+#[test]
+fn explain_key_through_layers() {
+    let path = std::env::temp_dir().join(format!("kanata-explain-{}.kbd", std::process::id()));
+    std::fs::write(
+        &path,
+        "
+(defsrc a s d)
+(deflayer base (tap-hold 200 200 esc lctl) (layer-while-held nav) C-d)
+(deflayer nav _ _ left)
+(deflayerdoc nav \"Arrows\" d \"Move left\")
+",
+    )
+    .unwrap();
+    let cfg = cfg::new_from_file(&path);
+    std::fs::remove_file(&path).unwrap();
+    let cfg = cfg.unwrap();
+    let stack = layer_stack(&["base".into(), "nav".into()], &cfg.layer_info).unwrap();
+    assert_eq!(stack, [0, 3]);
+    assert_eq!(
+        explain_key(cfg.layout.b(), &cfg.layer_info, &stack, OsCode::KEY_A),
+        "KEY_A
+  layer nav: transparent
+  layer base: wins
+action:
+  tap-hold, timeout 200ms, Default
+    tap:
+      key KEY_ESC
+    hold:
+      key KEY_LEFTCTRL
+state machines: tap-hold waiting state"
+    );
+    assert_eq!(
+        explain_key(cfg.layout.b(), &cfg.layer_info, &stack, OsCode::KEY_D),
+        "KEY_D
+  layer nav: wins (Arrows)
+description: Move left
+action:
+  key KEY_LEFT
+state machines: none"
+    );
+    assert!(layer_stack(&["sym".into()], &cfg.layer_info).is_err());
+}
@@ -0,0 +1,196 @@
+//! The version and build of the running kanata, for bug reports and support tooling, and the
+//! opt-in check for a newer release.
+//!
+//! `ClientMessage::RequestBuildInfo` and `kanata build-info` report the version, the enabled cargo
+//! features, the input and output backend and a hash of the active configuration file, so that a
+//! bug report names the exact build and configuration that ran.
+//!
+//! With `check-for-updates yes`, kanata asks GitHub for the latest release once, with `curl` in a
+//! background thread, and logs if it is newer. The check is off by default since it contacts a
+//! server; without it, kanata never uses the network for this.
+
+use super::*;
+
+use sha2::{Digest, Sha256};
+
+use crate::tcp_server::BuildInfo;
+
+pub const CHECK_FOR_UPDATES_CFG_NAME: &str = "check-for-updates";
+
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/jtroo/kanata/releases/latest";
+
+/// The cargo features that this kanata was built with.
+pub fn enabled_features() -> Vec<String> {
+    [
+        ("cmd", cfg!(feature = "cmd")),
+        ("clipboard", cfg!(feature = "clipboard")),
+        ("sound", cfg!(feature = "sound")),
+        ("perf_logging", cfg!(feature = "perf_logging")),
+        ("interception_driver", cfg!(feature = "interception_driver")),
+        ("wayland", cfg!(feature = "wayland")),
+        ("xtest", cfg!(feature = "xtest")),
+        ("ble_hid", cfg!(feature = "ble_hid")),
+        ("kvm", cfg!(feature = "kvm")),
+    ]
+    .iter()
+    .filter(|(_, enabled)| *enabled)
+    .map(|(name, _)| (*name).to_owned())
+    .collect()
+}
+
+/// The SHA-256 of the configuration file in hex, or an empty string if it cannot be read. Files
+/// that it includes are not part of the hash.
+pub fn config_hash(path: &std::path::Path) -> String {
+    match std::fs::read(path) {
+        Ok(bytes) => hex(&Sha256::digest(bytes)),
+        Err(e) => {
+            log::warn!("cannot hash the configuration file {}: {e}", path.display());
+            String::new()
+        }
+    }
+}
+
+/// The bytes in lowercase hex.
+pub(super) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Whether the version `latest`, e.g. `v1.4.0`, is newer than `current`. A prerelease is older
+/// than the release of the same version.
+fn is_newer(latest: &str, current: &str) -> bool {
+    let parse = |version: &str| {
+        let version = version.trim_start_matches('v');
+        let (numbers, prerelease) = match version.split_once('-') {
+            Some((numbers, _)) => (numbers, true),
+            None => (version, false),
+        };
+        let numbers = numbers
+            .split('.')
+            .map(|n| n.parse::<u64>().unwrap_or(0))
+            .collect::<Vec<_>>();
+        (numbers, !prerelease)
+    };
+    parse(latest) > parse(current)
+}
+
+/// The latest release of kanata, checked once if `check-for-updates` is enabled.
+#[derive(Debug, Default)]
+pub struct UpdateCheck {
+    /// The tag of the latest release once the check finished, or `None` if it is disabled.
+    latest: Option<Arc<Mutex<Option<String>>>>,
+}
+
+impl UpdateCheck {
+    /// Start the check if it was enabled. A live reload does not check again.
+    pub fn update_from_cfg(&mut self, items: &HashMap<String, String>) {
+        let enabled = items
+            .get(CHECK_FOR_UPDATES_CFG_NAME)
+            .is_some_and(|s| matches!(s.to_lowercase().as_str(), "yes" | "true"));
+        if !enabled {
+            self.latest = None;
+            return;
+        }
+        if self.latest.is_some() {
+            return;
+        }
+        let latest = Arc::new(Mutex::new(None));
+        self.latest = Some(latest.clone());
+        std::thread::spawn(move || match latest_release() {
+            Ok(tag) => {
+                let current = env!("CARGO_PKG_VERSION");
+                if is_newer(&tag, current) {
+                    log::info!("kanata {tag} is available, this is {current}");
+                } else {
+                    log::debug!("kanata is up to date, the latest release is {tag}");
+                }
+                *latest.lock() = Some(tag);
+            }
+            Err(e) => log::warn!("could not check for a newer kanata: {e}"),
+        });
+    }
+
+    pub fn latest(&self) -> Option<String> {
+        self.latest
+            .as_ref()
+            .and_then(|latest| latest.lock().clone())
+    }
+}
+
+fn latest_release() -> Result<String> {
+    let output = std::process::Command::new("curl")
+        .args(["-fsSL", "--max-time", "10", LATEST_RELEASE_URL])
+        .output()
+        .map_err(|e| anyhow!("failed to run curl: {e}"))?;
+    if !output.status.success() {
+        bail!(
+            "curl failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let release: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .map_err(|e| anyhow!("invalid reply from GitHub: {e}"))?;
+    release["tag_name"]
+        .as_str()
+        .map(str::to_owned)
+        .ok_or_else(|| anyhow!("the reply from GitHub has no tag_name"))
+}
+
+impl Kanata {
+    /// The version and build of kanata, for `ClientMessage::RequestBuildInfo`.
+    pub fn build_info(&self) -> BuildInfo {
+        BuildInfo {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            target: format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS),
+            features: enabled_features(),
+            backend: self.backend_name(),
+            config: self.cfg_paths[self.loaded_cfg_idx].display().to_string(),
+            config_hash: self.config_hash.clone(),
+            latest_release: self.update_check.latest(),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn backend_name(&self) -> String {
+        let input = match self.filter_mode {
+            true => "stdin",
+            false => "evdev",
+        };
+        let output = match &self.output_device_cfg.backend {
+            OutputBackend::Uinput => "uinput",
+            #[cfg(feature = "wayland")]
+            OutputBackend::Wayland { .. } => "wayland",
+            #[cfg(feature = "xtest")]
+            OutputBackend::Xtest => "xtest",
+            #[cfg(feature = "ble_hid")]
+            OutputBackend::Ble { .. } => "ble",
+            OutputBackend::Stdout => "stdout",
+        };
+        format!("linux {input} input, {output} output")
+    }
+
+    #[cfg(target_os = "windows")]
+    fn backend_name(&self) -> String {
+        match cfg!(feature = "interception_driver") {
+            true => "windows interception driver".to_owned(),
+            false => "windows low-level hooks".to_owned(),
+        }
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+    fn backend_name(&self) -> String {
+        std::env::consts::OS.to_owned()
+    }
+}
+
+#[test]
+fn newer_releases_are_detected() {
+    assert!(is_newer("v1.4.0", "1.4.0-prerelease-1"));
+    assert!(is_newer("v1.10.0", "1.9.2"));
+    assert!(!is_newer("v1.4.0", "1.4.0"));
+    assert!(!is_newer("v1.3.0", "1.4.0-prerelease-1"));
+    assert!(!is_newer("v1.4.0-prerelease-2", "1.4.0"));
+    assert_eq!(
+        config_hash(std::path::Path::new("/nonexistent/kanata.kbd")),
+        ""
+    );
+}
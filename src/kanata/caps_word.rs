@@ -39,6 +39,11 @@ impl CapsWordState {
         }
     }
 
+    /// Caps-word counts down its timeout and checks the active keys every tick.
+    pub(crate) fn ticks_until_due(&self) -> Option<u16> {
+        Some(1)
+    }
+
     pub(crate) fn maybe_add_lsft(&mut self, active_keys: &mut Vec<KeyCode>) -> CapsWordNextState {
         if self.timeout_ticks == 0 {
             return End;
@@ -0,0 +1,178 @@
+//! `clipboard-paste`: type the contents of the clipboard.
+//!
+//! This is for places where the paste shortcut does not work, e.g. VNC consoles. The text is
+//! typed like other text that kanata types, see [`text`](super::text).
+//!
+//! The clipboard is read by running `wl-paste` or `xclip`, which can take a while or hang, e.g.
+//! if the clipboard owner does not respond. So it is read in a thread and the processing loop only
+//! picks up the text on a later tick, like the IME poller in [`ime`](super::ime).
+
+use super::*;
+
+#[cfg(target_os = "linux")]
+use std::io::Read;
+#[cfg(target_os = "linux")]
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+
+/// How long the clipboard tool may run before it is killed.
+#[cfg(target_os = "linux")]
+const CLIPBOARD_TIMEOUT: time::Duration = time::Duration::from_secs(2);
+
+/// Read the clipboard with the tool of the running display server.
+#[cfg(target_os = "linux")]
+fn read_clipboard() -> Result<String> {
+    let cmd = if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        let mut cmd = Command::new("wl-paste");
+        cmd.arg("--no-newline");
+        cmd
+    } else {
+        let mut cmd = Command::new("xclip");
+        cmd.args(["-selection", "clipboard", "-o"]);
+        cmd
+    };
+    run_with_timeout(cmd, CLIPBOARD_TIMEOUT)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_clipboard() -> Result<String> {
+    bail!("clipboard-paste is only supported on Linux")
+}
+
+/// Run the command and return its output, or kill it if it runs longer than `timeout`.
+#[cfg(target_os = "linux")]
+fn run_with_timeout(mut cmd: Command, timeout: time::Duration) -> Result<String> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow!("failed to run {:?}: {e}", cmd.get_program()))?;
+    // Read the output while waiting so that the command does not block on a full pipe.
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let stdout = std::thread::spawn(move || {
+        let mut text = vec![];
+        stdout.read_to_end(&mut text).map(|_| text)
+    });
+    let deadline = time::Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if time::Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!(
+                "{:?} did not finish within {} ms",
+                cmd.get_program(),
+                timeout.as_millis()
+            );
+        }
+        std::thread::sleep(time::Duration::from_millis(10));
+    };
+    if !status.success() {
+        let mut stderr = String::new();
+        if let Some(mut pipe) = child.stderr.take() {
+            let _ = pipe.read_to_string(&mut stderr);
+        }
+        bail!("{:?} failed: {}", cmd.get_program(), stderr.trim());
+    }
+    let stdout = stdout
+        .join()
+        .map_err(|_| anyhow!("reading the output of {:?} panicked", cmd.get_program()))?
+        .map_err(|e| anyhow!("failed to read the output of {:?}: {e}", cmd.get_program()))?;
+    Ok(String::from_utf8_lossy(&stdout).into_owned())
+}
+
+/// Returns an error if the text is longer than `max_chars`. Nothing is typed in that case since
+/// part of e.g. a password is of no use.
+fn check_len(text: &str, max_chars: usize) -> Result<()> {
+    let len = text.chars().count();
+    if len > max_chars {
+        bail!("clipboard has {len} characters, more than the limit of {max_chars}");
+    }
+    Ok(())
+}
+
+/// A clipboard read that is in progress.
+#[derive(Debug, Default)]
+pub struct ClipboardPaste {
+    /// Receives the text to type once the clipboard was read.
+    pending: Option<Receiver<Result<String>>>,
+}
+
+impl ClipboardPaste {
+    /// Start reading the clipboard in a thread. Failures are logged since they are not problems
+    /// with kanata's output.
+    pub(super) fn start(&mut self, max_chars: usize) {
+        if self.pending.is_some() {
+            log::warn!("clipboard-paste: the clipboard is still being read");
+            return;
+        }
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let text = read_clipboard().and_then(|text| check_len(&text, max_chars).map(|_| text));
+            let _ = tx.send(text);
+        });
+        self.pending = Some(rx);
+    }
+
+    /// The text to type if the clipboard was read since the last call.
+    fn poll(&mut self) -> Option<String> {
+        let result = match self.pending.as_ref()?.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => return None,
+            Err(mpsc::TryRecvError::Disconnected) => Err(anyhow!("the clipboard reader stopped")),
+        };
+        self.pending = None;
+        match result {
+            Ok(text) => Some(text),
+            Err(e) => {
+                log::warn!("clipboard-paste: {e}");
+                None
+            }
+        }
+    }
+
+    /// Poll every tick while the clipboard is read.
+    pub(super) fn ticks_until_due(&self) -> Option<u16> {
+        self.pending.as_ref().map(|_| 1)
+    }
+}
+
+impl Kanata {
+    /// Queue the clipboard contents to be typed as macro output once they were read.
+    pub(super) fn tick_clipboard_paste(&mut self) {
+        if let Some(text) = self.clipboard_paste.poll() {
+            log::debug!(
+                "clipboard-paste: typing {} character(s)",
+                text.chars().count()
+            );
+            self.output_queue.push_text(OutputPriority::Macro, &text);
+        }
+    }
+}
+
+#[test]
+fn clipboard_text_over_the_limit_is_not_typed() {
+    assert!(check_len("hunter2", 7).is_ok());
+    assert!(check_len("hunter2", 6).is_err());
+    assert!(check_len("é", 1).is_ok());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn hanging_clipboard_tools_are_killed() {
+    let mut cmd = Command::new("sh");
+    cmd.args(["-c", "printf hi"]);
+    assert_eq!(
+        run_with_timeout(cmd, time::Duration::from_secs(5)).unwrap(),
+        "hi"
+    );
+    let mut cmd = Command::new("sleep");
+    cmd.arg("10");
+    let start = time::Instant::now();
+    let e = run_with_timeout(cmd, time::Duration::from_millis(50)).unwrap_err();
+    assert!(e.to_string().contains("did not finish"), "{e}");
+    assert!(start.elapsed() < time::Duration::from_secs(5));
+}
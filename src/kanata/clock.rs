@@ -0,0 +1,54 @@
+//! The time that the processing loop ticks by.
+//!
+//! Keyberon and the timers of kanata, e.g. tap-hold, tap-dance, one-shot, chords and slow keys,
+//! count ticks instead of reading the time. The processing loop runs one tick per millisecond of
+//! this clock. With a virtual clock, time only passes when it is advanced, so every timer behaves
+//! the same on every run regardless of how long processing takes.
+
+use super::*;
+
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Default)]
+pub enum Clock {
+    /// The monotonic time of the OS.
+    #[default]
+    System,
+    /// Time that only passes through [`Clock::advance`]. Clones share the same time.
+    Virtual(Arc<Mutex<Instant>>),
+}
+
+#[allow(dead_code)] // virtual clocks are used by tests, kanata itself runs on the system clock
+impl Clock {
+    pub fn new_virtual() -> Self {
+        Clock::Virtual(Arc::new(Mutex::new(Instant::now())))
+    }
+
+    pub fn now(&self) -> Instant {
+        match self {
+            Clock::System => Instant::now(),
+            Clock::Virtual(now) => *now.lock(),
+        }
+    }
+
+    /// Moves a virtual clock forward. The system clock cannot be moved.
+    pub fn advance(&self, by: Duration) {
+        match self {
+            Clock::System => log::warn!("cannot advance the system clock"),
+            Clock::Virtual(now) => *now.lock() += by,
+        }
+    }
+}
+
+#[test]
+fn virtual_clock_only_moves_when_advanced() {
+    let clock = Clock::new_virtual();
+    let start = clock.now();
+    std::thread::sleep(Duration::from_millis(5));
+    assert_eq!(clock.now(), start);
+    let shared = clock.clone();
+    shared.advance(Duration::from_millis(2));
+    assert_eq!(clock.now() - start, Duration::from_millis(2));
+    clock.advance(Duration::from_millis(198));
+    assert_eq!(shared.now() - start, Duration::from_millis(200));
+}
@@ -2,19 +2,59 @@ use crate::cfg::parse_mod_prefix;
 use crate::cfg::sexpr::*;
 use crate::keys::*;
 
+use super::ActiveWindow;
+
 // local log prefix
 const LP: &str = "cmd-out:";
 
-pub(super) fn run_cmd_in_thread(cmd_and_args: Vec<String>) -> std::thread::JoinHandle<()> {
-    std::thread::spawn(move || {
-        let mut args = cmd_and_args.iter();
-        let mut cmd = std::process::Command::new(
-            args.next()
-                .expect("parsing should have forbidden empty cmd"),
-        );
-        for arg in args {
-            cmd.arg(arg);
+/// What commands are told about the state of kanata in environment variables.
+#[derive(Debug, Clone, Default)]
+pub struct CmdEnv {
+    pub window: Option<ActiveWindow>,
+    /// The presses in a row of the last pressed key, in milliseconds since the first one.
+    pub presses: Vec<u64>,
+}
+
+/// Build the command. The active window reported by TCP clients is passed in the environment
+/// variables `KANATA_WINDOW_CLASS` and `KANATA_WINDOW_TITLE`, which are unset if it is unknown.
+/// The presses in a row are passed as their count in `KANATA_PRESS_COUNT` and their times in
+/// `KANATA_PRESS_TIMES`, separated by spaces.
+fn command(cmd_and_args: &[String], env: &CmdEnv) -> std::process::Command {
+    let mut args = cmd_and_args.iter();
+    let mut cmd = std::process::Command::new(
+        args.next()
+            .expect("parsing should have forbidden empty cmd"),
+    );
+    for arg in args {
+        cmd.arg(arg);
+    }
+    match &env.window {
+        Some(window) => {
+            cmd.env("KANATA_WINDOW_CLASS", &window.class);
+            cmd.env("KANATA_WINDOW_TITLE", &window.title);
         }
+        None => {
+            cmd.env_remove("KANATA_WINDOW_CLASS");
+            cmd.env_remove("KANATA_WINDOW_TITLE");
+        }
+    }
+    if env.presses.is_empty() {
+        cmd.env_remove("KANATA_PRESS_COUNT");
+        cmd.env_remove("KANATA_PRESS_TIMES");
+    } else {
+        let times = env.presses.iter().map(u64::to_string);
+        cmd.env("KANATA_PRESS_COUNT", env.presses.len().to_string());
+        cmd.env("KANATA_PRESS_TIMES", times.collect::<Vec<_>>().join(" "));
+    }
+    cmd
+}
+
+pub(super) fn run_cmd_in_thread(
+    cmd_and_args: Vec<String>,
+    env: CmdEnv,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut cmd = command(&cmd_and_args, &env);
         match cmd.output() {
             Ok(output) => {
                 log::info!(
@@ -152,16 +192,11 @@ fn try_parse_chorded_list<'a>(
     }
 }
 
-pub(super) fn keys_for_cmd_output(cmd_and_args: &[String]) -> impl Iterator<Item = Item> {
-    let mut args = cmd_and_args.iter();
-    let mut cmd = std::process::Command::new(
-        args.next()
-            .expect("parsing should have forbidden empty cmd"),
-    );
-    for arg in args {
-        cmd.arg(arg);
-    }
-    let output = match cmd.output() {
+pub(super) fn keys_for_cmd_output(
+    cmd_and_args: &[String],
+    env: &CmdEnv,
+) -> impl Iterator<Item = Item> {
+    let output = match command(cmd_and_args, env).output() {
         Ok(o) => o,
         Err(e) => {
             log::error!("Failed to execute cmd: {e}");
@@ -193,3 +228,47 @@ pub(super) fn keys_for_cmd_output(cmd_and_args: &[String]) -> impl Iterator<Item
         }
     }
 }
+
+#[test]
+fn active_window_is_passed_to_commands() {
+    use std::ffi::OsStr;
+    let cmd_and_args = ["notify-send".to_owned(), "hi".to_owned()];
+    let window = ActiveWindow {
+        class: "firefox".into(),
+        title: "Kanata - Mozilla Firefox".into(),
+    };
+    let env = CmdEnv {
+        window: Some(window),
+        presses: vec![],
+    };
+    let cmd = command(&cmd_and_args, &env);
+    assert_eq!(cmd.get_args().collect::<Vec<_>>(), ["hi"]);
+    let envs = cmd.get_envs().collect::<Vec<_>>();
+    assert!(envs.contains(&(
+        OsStr::new("KANATA_WINDOW_CLASS"),
+        Some(OsStr::new("firefox"))
+    )));
+    assert!(envs.contains(&(
+        OsStr::new("KANATA_WINDOW_TITLE"),
+        Some(OsStr::new("Kanata - Mozilla Firefox"))
+    )));
+    let cmd = command(&cmd_and_args, &CmdEnv::default());
+    assert!(cmd.get_envs().all(|(_, value)| value.is_none()));
+}
+
+#[test]
+fn presses_in_a_row_are_passed_to_commands() {
+    use std::ffi::OsStr;
+    let cmd_and_args = ["notify-send".to_owned(), "hi".to_owned()];
+    let env = CmdEnv {
+        window: None,
+        presses: vec![0, 180, 350],
+    };
+    let cmd = command(&cmd_and_args, &env);
+    let envs = cmd.get_envs().collect::<Vec<_>>();
+    assert!(envs.contains(&(OsStr::new("KANATA_PRESS_COUNT"), Some(OsStr::new("3")))));
+    assert!(envs.contains(&(
+        OsStr::new("KANATA_PRESS_TIMES"),
+        Some(OsStr::new("0 180 350"))
+    )));
+}
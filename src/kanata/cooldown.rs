@@ -0,0 +1,51 @@
+//! Actions that cannot be activated again within a cooldown.
+//!
+//! `(cooldown <ms> <action>)` activates the action at most once per cooldown, so that key chatter
+//! or a nervous double tap cannot run a command or submit a form twice. Presses within the
+//! cooldown do nothing and do not restart it. An alias is one action, so the cooldown is shared by
+//! every key that uses the alias.
+
+use super::*;
+
+#[derive(Debug, Default)]
+pub struct Cooldowns {
+    /// When each action with a cooldown was last activated.
+    activated: HashMap<ActionRef, time::Instant>,
+}
+
+impl Cooldowns {
+    /// Returns whether the action may be activated now, and if so, starts its cooldown.
+    pub fn activate(&mut self, action: ActionRef, cooldown_ms: u16, now: time::Instant) -> bool {
+        let cooldown = time::Duration::from_millis(cooldown_ms.into());
+        match self.activated.get(&action) {
+            Some(at) if now.duration_since(*at) < cooldown => false,
+            _ => {
+                self.activated.insert(action, now);
+                true
+            }
+        }
+    }
+
+    /// Forget the activations, e.g. on live reload, which replaces the actions.
+    pub fn clear(&mut self) {
+        self.activated.clear();
+    }
+}
+
+#[test]
+fn cooldown_ignores_activations_until_it_is_over() {
+    static ACTION: KanataAction = Action::KeyCode(KeyCode::Enter);
+    static OTHER: KanataAction = Action::KeyCode(KeyCode::Enter);
+    let start = time::Instant::now();
+    let ms = |ms| start + time::Duration::from_millis(ms);
+    let mut cooldowns = Cooldowns::default();
+    assert!(cooldowns.activate(ActionRef(&ACTION), 500, start));
+    assert!(!cooldowns.activate(ActionRef(&ACTION), 500, ms(30)));
+    // Other actions have their own cooldown.
+    assert!(cooldowns.activate(ActionRef(&OTHER), 500, ms(30)));
+    // Ignored presses do not restart the cooldown.
+    assert!(!cooldowns.activate(ActionRef(&ACTION), 500, ms(499)));
+    assert!(cooldowns.activate(ActionRef(&ACTION), 500, ms(500)));
+    cooldowns.clear();
+    assert!(cooldowns.activate(ActionRef(&ACTION), 500, ms(501)));
+}
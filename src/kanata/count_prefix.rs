@@ -0,0 +1,220 @@
+//! Count prefixes: digits typed before an effect repeat it, e.g. `5` then `down` taps `down` five
+//! times.
+//!
+//! `count-layers` lists the layers whose digits are captured as a count instead of being typed,
+//! while one of them is the current layer. `defmodal` layers capture digits as well. The count is
+//! kept when the layer is left, so a count typed on a held layer applies to the key pressed after
+//! releasing it.
+//!
+//! The next key that the layout outputs, other than a modifier, is tapped as often as the count
+//! says. `(counted <action>)` repeats any other action instead, e.g. a macro or a mouse click: the
+//! action is activated once on press, and the key is tapped again by kanata after it is released
+//! until the action was activated the counted times. Both use up the count.
+
+use super::*;
+
+pub const COUNT_LAYERS_CFG_NAME: &str = "count-layers";
+
+/// The largest count, so that a mistyped count cannot flood the output.
+pub const MAX_COUNT: u32 = 999;
+
+/// A count typed with digits, or none.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Count(u32);
+
+impl Count {
+    /// Add the digit of the key to the count, if it is a digit. `0` only continues a count, so
+    /// that it can be mapped to something else.
+    pub fn digit(&mut self, key: OsCode) -> bool {
+        match count_digit(key) {
+            Some(digit) if digit > 0 || self.0 > 0 => {
+                self.0 = (self.0 * 10 + digit).min(MAX_COUNT);
+                log::debug!("count {}", self.0);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.0 > 0
+    }
+
+    /// Use up the count, returning how many times to repeat: 1 if none was typed.
+    pub fn take(&mut self) -> u32 {
+        std::mem::take(&mut self.0).max(1)
+    }
+
+    pub fn clear(&mut self) {
+        self.0 = 0;
+    }
+}
+
+/// The remaining taps of the physical key of a `counted` action.
+#[derive(Debug, Clone, Copy)]
+struct CountedRepeat {
+    key: u16,
+    remaining: u32,
+    /// Whether the key was released by the user, after which the taps start.
+    released: bool,
+    /// Whether the key is pressed by a tap.
+    pressed: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct CountPrefix {
+    pub count: Count,
+    /// The keyberon layers whose digits are captured.
+    layers: Vec<usize>,
+    repeat: Option<CountedRepeat>,
+}
+
+impl CountPrefix {
+    pub fn from_cfg(items: &HashMap<String, String>, layer_info: &[LayerInfo]) -> Result<Self> {
+        let mut prefix = Self::default();
+        for name in items
+            .get(COUNT_LAYERS_CFG_NAME)
+            .map(String::as_str)
+            .unwrap_or_default()
+            .split_whitespace()
+        {
+            let len = prefix.layers.len();
+            prefix.layers.extend(
+                layer_info
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, l)| l.name == name)
+                    .map(|(i, _)| i),
+            );
+            if prefix.layers.len() == len {
+                bail!("{COUNT_LAYERS_CFG_NAME} contains unknown layer: {name}");
+            }
+        }
+        Ok(prefix)
+    }
+
+    /// Capture the key as a digit of the count if the layer is one of `count-layers`.
+    pub fn capture(&mut self, key: OsCode, layer: usize) -> bool {
+        self.layers.contains(&layer) && self.count.digit(key)
+    }
+
+    /// How many times to tap a key that the layout outputs. Modifiers do not use up the count.
+    pub fn take_for_key(&mut self, key: OsCode) -> u32 {
+        if KeyCode::from(key).is_modifier() {
+            1
+        } else {
+            self.count.take()
+        }
+    }
+
+    /// Handle the press of a `counted` action on the physical key, or on a fake key if `None`.
+    /// Presses by the taps of kanata do not use up the count.
+    pub fn counted_press(&mut self, key: Option<u16>) {
+        if self.repeat.is_some() {
+            return;
+        }
+        let times = self.count.take();
+        match key {
+            Some(key) if times > 1 => {
+                self.repeat = Some(CountedRepeat {
+                    key,
+                    remaining: times - 1,
+                    released: false,
+                    pressed: false,
+                });
+            }
+            None if times > 1 => log::warn!("counted actions of fake keys are not repeated"),
+            _ => {}
+        }
+    }
+
+    pub fn counted_release(&mut self) {
+        if let Some(repeat) = &mut self.repeat {
+            repeat.released = true;
+        }
+    }
+
+    /// Tick every millisecond while the key of a `counted` action is tapped after its release.
+    pub fn ticks_until_due(&self) -> Option<u16> {
+        self.repeat.filter(|r| r.released).map(|_| 1)
+    }
+
+    /// The next event that taps the key of a `counted` action, at most one per tick.
+    pub fn next_repeat_event(&mut self) -> Option<Event> {
+        let repeat = self.repeat.as_mut().filter(|r| r.released)?;
+        if repeat.pressed {
+            repeat.pressed = false;
+            return Some(Event::Release(0, repeat.key));
+        }
+        if repeat.remaining == 0 {
+            self.repeat = None;
+            return None;
+        }
+        repeat.remaining -= 1;
+        repeat.pressed = true;
+        Some(Event::Press(0, repeat.key))
+    }
+}
+
+#[test]
+fn count_prefix_captures_digits_and_repeats() {
+    let layer_info = ["base", "base", "count", "count"]
+        .iter()
+        .map(|name| LayerInfo {
+            name: name.to_string(),
+            cfg_text: String::new(),
+            sounds: None,
+            tags: vec![],
+            notify: vec![],
+            doc: LayerDoc::default(),
+        })
+        .collect::<Vec<_>>();
+    let mut items = HashMap::default();
+    items.insert(COUNT_LAYERS_CFG_NAME.to_owned(), "count".to_owned());
+    let mut prefix = CountPrefix::from_cfg(&items, &layer_info).unwrap();
+    assert!(!prefix.capture(OsCode::KEY_1, 0));
+    assert!(!prefix.capture(OsCode::KEY_0, 2));
+    assert!(prefix.capture(OsCode::KEY_1, 2));
+    assert!(prefix.capture(OsCode::KEY_KP2, 3));
+    assert!(!prefix.capture(OsCode::KEY_A, 2));
+    assert_eq!(prefix.take_for_key(OsCode::KEY_LEFTCTRL), 1);
+    assert_eq!(prefix.take_for_key(OsCode::KEY_Z), 12);
+    assert_eq!(prefix.take_for_key(OsCode::KEY_Z), 1);
+    for _ in 0..4 {
+        assert!(prefix.capture(OsCode::KEY_9, 2));
+    }
+    assert_eq!(prefix.count.take(), MAX_COUNT);
+
+    assert!(prefix.capture(OsCode::KEY_3, 2));
+    prefix.counted_press(Some(30));
+    assert_eq!(prefix.next_repeat_event(), None);
+    assert_eq!(prefix.ticks_until_due(), None, "taps start on release");
+    prefix.counted_release();
+    // The taps activate the action again, which must not use up a count typed meanwhile.
+    prefix.count.digit(OsCode::KEY_5);
+    let mut events = vec![];
+    while let Some(event) = prefix.next_repeat_event() {
+        // The processing loop must not block on input while taps are pending.
+        assert_eq!(prefix.ticks_until_due(), Some(1));
+        if matches!(event, Event::Press(..)) {
+            prefix.counted_press(Some(30));
+        } else {
+            prefix.counted_release();
+        }
+        events.push(event);
+    }
+    assert_eq!(
+        events,
+        [
+            Event::Press(0, 30),
+            Event::Release(0, 30),
+            Event::Press(0, 30),
+            Event::Release(0, 30),
+        ]
+    );
+    assert_eq!(prefix.ticks_until_due(), None);
+    assert_eq!(prefix.count.take(), 5);
+
+    items.insert(COUNT_LAYERS_CFG_NAME.to_owned(), "numbers".to_owned());
+    assert!(CountPrefix::from_cfg(&items, &layer_info).is_err());
+}
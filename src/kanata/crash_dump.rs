@@ -0,0 +1,325 @@
+//! Crash dumps, so that intermittent crashes can be debugged from user reports.
+//!
+//! Dumps are only written if `crash-dump-dir` is set. The panic hook cannot lock `Kanata` since
+//! the processing loop usually holds the lock when it panics. Instead, the recent input events and
+//! a summary of the state are kept in `CRASH_DUMP`, which the hook formats and writes to a new
+//! file in the directory together with the panic message. Only key codes are recorded, not the
+//! text that was typed through them.
+//!
+//! Recording happens for every key event, so it only stores numbers in atomics: no locking, no
+//! allocation and no formatting, see the hot path in `docs/design.md`.
+
+use super::*;
+
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicUsize, Ordering::Relaxed};
+
+pub const CRASH_DUMP_DIR_CFG_NAME: &str = "crash-dump-dir";
+const MAX_EVENTS: usize = 50;
+/// The most layers, held keys and latched keys that are recorded.
+const MAX_LIST_LEN: usize = 16;
+
+/// The actions in progress, by their bit in `CrashDump::in_progress`.
+const IN_PROGRESS: [&str; 13] = [
+    "tap-hold waiting",
+    "queued events",
+    "one-shot",
+    "macros",
+    "tap-dance",
+    "sequence",
+    "launcher",
+    "mouse-grid",
+    "dynamic macro replay",
+    "dynamic macro record",
+    "caps-word",
+    "morse",
+    "unicode-input",
+];
+
+pub static CRASH_DUMP: Lazy<CrashDump> = Lazy::new(CrashDump::new);
+
+#[derive(Debug)]
+struct CrashDumpCfg {
+    dir: PathBuf,
+    layer_names: Vec<String>,
+}
+
+/// Numbers written by the processing loop and read by the panic hook without locking.
+#[derive(Debug)]
+struct AtomicList {
+    items: [AtomicU16; MAX_LIST_LEN],
+    len: AtomicUsize,
+}
+
+impl AtomicList {
+    fn new() -> Self {
+        Self {
+            items: std::array::from_fn(|_| AtomicU16::new(0)),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    /// Store the first `MAX_LIST_LEN` items.
+    fn set(&self, items: impl Iterator<Item = u16>) {
+        let mut len = 0;
+        for (slot, item) in self.items.iter().zip(items) {
+            slot.store(item, Relaxed);
+            len += 1;
+        }
+        self.len.store(len, Relaxed);
+    }
+
+    fn get(&self) -> impl Iterator<Item = u16> + '_ {
+        self.items[..self.len.load(Relaxed).min(MAX_LIST_LEN)]
+            .iter()
+            .map(|item| item.load(Relaxed))
+    }
+}
+
+#[derive(Debug)]
+pub struct CrashDump {
+    /// Where to write dumps, or `None` if they are disabled. Only locked when the configuration
+    /// is loaded and by the panic hook.
+    cfg: Mutex<Option<CrashDumpCfg>>,
+    enabled: AtomicBool,
+    /// A ring of the recent input events, each stored as `code << 8 | value + 1` so that empty
+    /// slots are 0.
+    events: [AtomicU32; MAX_EVENTS],
+    /// The number of events recorded so far, which points at the oldest event in the ring.
+    recorded: AtomicUsize,
+    /// The keyberon indexes of the active layers, bottom first.
+    layers: AtomicList,
+    held_keys: AtomicList,
+    latched_keys: AtomicList,
+    /// The bits of the `IN_PROGRESS` actions that are in progress.
+    in_progress: AtomicU32,
+}
+
+impl CrashDump {
+    fn new() -> Self {
+        Self {
+            cfg: Mutex::new(None),
+            enabled: AtomicBool::new(false),
+            events: std::array::from_fn(|_| AtomicU32::new(0)),
+            recorded: AtomicUsize::new(0),
+            layers: AtomicList::new(),
+            held_keys: AtomicList::new(),
+            latched_keys: AtomicList::new(),
+            in_progress: AtomicU32::new(0),
+        }
+    }
+
+    pub fn update_from_cfg(&self, items: &HashMap<String, String>, layer_info: &[LayerInfo]) {
+        let cfg = items.get(CRASH_DUMP_DIR_CFG_NAME).map(|dir| CrashDumpCfg {
+            dir: PathBuf::from(dir),
+            layer_names: layer_info.iter().map(|l| l.name.clone()).collect(),
+        });
+        self.enabled.store(cfg.is_some(), Relaxed);
+        *self.cfg.lock() = cfg;
+    }
+
+    pub fn record_event(&self, event: &KeyEvent) {
+        if !self.enabled.load(Relaxed) {
+            return;
+        }
+        let i = self.recorded.fetch_add(1, Relaxed) % MAX_EVENTS;
+        let value = u32::from(u16::from(event.code)) << 8 | (event.value as u32 + 1);
+        self.events[i].store(value, Relaxed);
+    }
+
+    fn report(&self, panic: &str, layer_names: &[String]) -> String {
+        let layers = self
+            .layers
+            .get()
+            .map(|idx| match layer_names.get(usize::from(idx)) {
+                Some(name) => name.clone(),
+                None => idx.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(" > ");
+        let key_names = |list: &AtomicList| {
+            list.get()
+                .map(|code| match OsCode::from_u16(code) {
+                    Some(osc) => format!("{osc:?}"),
+                    None => code.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let in_progress = self.in_progress.load(Relaxed);
+        let in_progress = IN_PROGRESS
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| in_progress & (1 << bit) != 0)
+            .map(|(_, name)| *name)
+            .collect::<Vec<_>>();
+        let recorded = self.recorded.load(Relaxed);
+        let events = (0..MAX_EVENTS)
+            .map(|i| self.events[(recorded + i) % MAX_EVENTS].load(Relaxed))
+            .filter(|event| *event != 0)
+            .collect::<Vec<_>>();
+        let mut report = format!(
+            "kanata v{} crashed: {panic}\n\nstate:\n  layers: {layers}\n  held keys: {}\n  \
+            latched keys: {}\n  in progress: {}\n\nlast {} input events, oldest first:\n",
+            env!("CARGO_PKG_VERSION"),
+            key_names(&self.held_keys),
+            key_names(&self.latched_keys),
+            in_progress.join(", "),
+            events.len(),
+        );
+        for event in events {
+            let code = (event >> 8) as u16;
+            let value = match event & 0xff {
+                1 => "Release",
+                2 => "Press",
+                _ => "Repeat",
+            };
+            match OsCode::from_u16(code) {
+                Some(osc) => report += &format!("  {osc:?} {value}\n"),
+                None => report += &format!("  {code} {value}\n"),
+            }
+        }
+        report
+    }
+}
+
+/// Write the report to a new file in the directory, which is created if needed. On Linux, the
+/// directory must belong to this user and not be writable by others, and the file is only
+/// readable by this user.
+fn write_dump(dir: &std::path::Path, report: &str) -> std::io::Result<PathBuf> {
+    let mut dir_builder = std::fs::DirBuilder::new();
+    dir_builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut dir_builder, 0o700);
+    dir_builder.create(dir)?;
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let meta = std::fs::metadata(dir)?;
+        // SAFETY: geteuid has no preconditions and cannot fail.
+        if meta.uid() != unsafe { libc::geteuid() } || meta.mode() & 0o022 != 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "the directory belongs to another user or others may write to it",
+            ));
+        }
+    }
+    let secs = time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let path = dir.join(format!("kanata-crash-{secs}-{}.txt", std::process::id()));
+    let mut options = std::fs::OpenOptions::new();
+    // Never overwrite an existing file or follow a symlink that someone put in its place.
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    #[cfg(target_os = "linux")]
+    std::os::unix::fs::OpenOptionsExt::custom_flags(&mut options, libc::O_NOFOLLOW);
+    options.open(&path)?.write_all(report.as_bytes())?;
+    Ok(path)
+}
+
+/// Write a crash dump when kanata panics if `crash-dump-dir` is set, then run the default panic
+/// hook.
+pub fn install_crash_dump_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        // The panic may have happened while the configuration was loaded.
+        if let Some(cfg) = CRASH_DUMP.cfg.try_lock() {
+            if let Some(cfg) = cfg.as_ref() {
+                let report = format!(
+                    "{}\nbacktrace:\n{}\n",
+                    CRASH_DUMP.report(&info.to_string(), &cfg.layer_names),
+                    std::backtrace::Backtrace::force_capture()
+                );
+                match write_dump(&cfg.dir, &report) {
+                    Ok(path) => eprintln!("wrote a crash dump to {}", path.display()),
+                    Err(e) => {
+                        eprintln!("could not write a crash dump to {}: {e}", cfg.dir.display())
+                    }
+                }
+            }
+        }
+        default_hook(info);
+    }));
+}
+
+impl Kanata {
+    /// Save a summary of the state for a crash dump.
+    pub(super) fn update_crash_dump(&self) {
+        if !CRASH_DUMP.enabled.load(Relaxed) {
+            return;
+        }
+        let layout = self.layout.b();
+        CRASH_DUMP
+            .layers
+            .set(layout.active_layers().map(|idx| idx as u16));
+        let codes = |keys: &[KeyCode]| keys.iter().map(|k| u16::from(OsCode::from(*k)));
+        CRASH_DUMP.held_keys.set(codes(&self.prev_keys));
+        CRASH_DUMP.latched_keys.set(codes(&self.latched_keys));
+        let in_progress = [
+            layout.waiting.is_some(),
+            !layout.queue.is_empty(),
+            !layout.oneshot.keys.is_empty(),
+            !layout.active_sequences.is_empty(),
+            layout.tap_dance_eager.is_some(),
+            self.sequence_state.is_some(),
+            self.launcher_state.is_some(),
+            self.mouse_grid.is_some(),
+            self.dynamic_macro_replay_state.is_some(),
+            self.dynamic_macro_record_state.is_some(),
+            self.caps_word.is_some(),
+            self.morse.is_some(),
+            self.unicode_input.is_some(),
+        ];
+        let bits = in_progress
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (bit, active)| bits | u32::from(*active) << bit);
+        CRASH_DUMP.in_progress.store(bits, Relaxed);
+    }
+}
+
+#[test]
+fn crash_dump_keeps_last_events() {
+    let dump = CrashDump::new();
+    dump.record_event(&KeyEvent::new(OsCode::KEY_C, KeyValue::Press));
+    assert_eq!(dump.recorded.load(Relaxed), 0, "recorded while disabled");
+    dump.enabled.store(true, Relaxed);
+    for _ in 0..MAX_EVENTS {
+        dump.record_event(&KeyEvent::new(OsCode::KEY_A, KeyValue::Press));
+    }
+    dump.record_event(&KeyEvent::new(OsCode::KEY_B, KeyValue::Release));
+    dump.layers.set([0, 3].into_iter());
+    dump.held_keys
+        .set([u16::from(OsCode::KEY_LEFTSHIFT)].into_iter());
+    dump.latched_keys
+        .set((0..100).map(|_| u16::from(OsCode::KEY_A)));
+    assert_eq!(dump.latched_keys.get().count(), MAX_LIST_LEN);
+    dump.in_progress.store(0b1001, Relaxed);
+    let report = dump.report(
+        "oops",
+        &["base".into(), "x".into(), "y".into(), "nav".into()],
+    );
+    assert!(report
+        .contains("crashed: oops\n\nstate:\n  layers: base > nav\n  held keys: KEY_LEFTSHIFT\n"));
+    assert!(report.contains("  in progress: tap-hold waiting, macros\n"));
+    assert!(report.contains(&format!("last {MAX_EVENTS} input events")));
+    assert!(report.ends_with("  KEY_A Press\n  KEY_B Release\n"));
+}
+
+#[test]
+fn crash_dumps_are_new_private_files() {
+    let dir = std::env::temp_dir().join(format!("kanata-crash-test-{}", std::process::id()));
+    let path = write_dump(&dir, "report").unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "report");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+    // A dump of the same second is not written over the first one.
+    assert!(write_dump(&dir, "other").is_err() || std::fs::read_dir(&dir).unwrap().count() == 2);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "report");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,102 @@
+//! Layer tags that follow the presence of input devices.
+//!
+//! `(defdevicetags <device-name> <tag>...)` turns the layers with the tags on while the device is
+//! one of the input devices of kanata and off while it is not; tags prefixed with `!` the other
+//! way around, e.g. the layers that make up for the keys of an external keyboard while it is
+//! unplugged. A tag is only changed when its device is plugged in or unplugged and on live
+//! reloads, so TCP clients can still change it in between.
+
+use super::*;
+
+#[derive(Debug, Default)]
+pub struct DeviceTagRules {
+    rules: Vec<DeviceTags>,
+    /// The names of the input devices, `None` until the event loop reported them.
+    present: Option<Vec<String>>,
+}
+
+impl DeviceTagRules {
+    pub fn new(rules: Vec<DeviceTags>) -> Self {
+        Self {
+            rules,
+            present: None,
+        }
+    }
+
+    /// Replace the rules with those of a new configuration. Returns the tag changes of every
+    /// rule for the present devices.
+    pub fn update_from_cfg(&mut self, rules: Vec<DeviceTags>) -> Vec<(String, bool)> {
+        self.rules = rules;
+        self.changes(|_, _| true)
+    }
+
+    /// Returns the tag changes of the devices that were plugged in or unplugged.
+    pub fn devices_changed(&mut self, names: Vec<String>) -> Vec<(String, bool)> {
+        let previous = self.present.replace(names);
+        self.changes(|device, present| {
+            previous
+                .as_ref()
+                .map_or(true, |p| p.contains(device) != present)
+        })
+    }
+
+    /// The tag changes of the rules whose device and presence `changed` accepts.
+    fn changes(&self, changed: impl Fn(&String, bool) -> bool) -> Vec<(String, bool)> {
+        let Some(names) = &self.present else {
+            return vec![];
+        };
+        self.rules
+            .iter()
+            .filter_map(|(device, tags)| {
+                let present = names.contains(device);
+                changed(device, present).then(|| {
+                    tags.iter()
+                        .map(move |(tag, when_present)| (tag.clone(), *when_present == present))
+                })
+            })
+            .flatten()
+            .collect()
+    }
+}
+
+impl Kanata {
+    /// Apply the rules of `defdevicetags` to the names of the input devices, sent by the event
+    /// loop whenever a device is plugged in or unplugged.
+    pub(super) fn devices_changed(&mut self, names: Vec<String>) {
+        log::debug!("input devices: {names:?}");
+        let changes = self.device_tags.devices_changed(names);
+        if !changes.is_empty() {
+            self.set_layer_tags(&changes);
+        }
+    }
+
+    /// Apply the rules of a new configuration to the present devices.
+    pub(super) fn reload_device_tags(&mut self, rules: Vec<DeviceTags>) {
+        let changes = self.device_tags.update_from_cfg(rules);
+        if !changes.is_empty() {
+            self.set_layer_tags(&changes);
+        }
+    }
+}
+
+#[test]
+fn device_tags_follow_plugged_devices() {
+    let mut rules = DeviceTagRules::new(vec![(
+        "Keychron K8".into(),
+        vec![("numpad".into(), true), ("tkl".into(), false)],
+    )]);
+    let changes = |on: bool| vec![("numpad".to_owned(), on), ("tkl".to_owned(), !on)];
+    let laptop = vec!["AT Translated Set 2 keyboard".to_owned()];
+    let docked = [laptop.clone(), vec!["Keychron K8".to_owned()]].concat();
+
+    assert_eq!(rules.devices_changed(laptop.clone()), changes(false));
+    // Other devices do not change the tags, so that TCP clients can.
+    assert!(rules.devices_changed(laptop.clone()).is_empty());
+    assert_eq!(rules.devices_changed(docked.clone()), changes(true));
+    assert!(rules.devices_changed(docked).is_empty());
+    assert_eq!(rules.devices_changed(laptop), changes(false));
+    assert_eq!(
+        rules.update_from_cfg(vec![("Keychron K8".into(), vec![("tkl".into(), false)])]),
+        [("tkl".to_owned(), true)]
+    );
+}
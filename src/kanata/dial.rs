@@ -0,0 +1,133 @@
+//! Rotary encoders and scroll wheels as bindable keys.
+//!
+//! Knobs report their rotation as relative events rather than keys: `REL_DIAL` for dials,
+//! `REL_WHEEL` and `REL_WHEEL_HI_RES` for knobs and wheels that scroll vertically, and
+//! `REL_HWHEEL` and `REL_HWHEEL_HI_RES` for wheels that scroll horizontally. When the keys
+//! `dialcw`, `dialccw`, `wheelup`, `wheeldown`, `wheelleft` or `wheelright` are in defsrc, every
+//! detent of the rotation in that direction is turned into a tap of the key, so each layer can map
+//! it to any action.
+
+use super::*;
+
+use evdev::{EventType, InputEvent, RelativeAxisType};
+
+/// `REL_WHEEL_HI_RES` reports this value for one detent of a regular wheel.
+const HI_RES_PER_DETENT: i32 = 120;
+
+#[derive(Default)]
+pub struct DialInput {
+    wheel: WheelAxis,
+    hwheel: WheelAxis,
+}
+
+/// A wheel that may report high resolution events along with the detents.
+#[derive(Default)]
+struct WheelAxis {
+    /// High resolution wheel movement that does not add up to a detent yet.
+    hi_res: i32,
+    /// Devices that report high resolution events also report every detent as a regular event,
+    /// which must not be counted twice.
+    seen_hi_res: bool,
+}
+
+impl WheelAxis {
+    fn hi_res_detents(&mut self, value: i32) -> u32 {
+        self.seen_hi_res = true;
+        if self.hi_res.signum() == -value.signum() {
+            // Partial movement in the other direction does not count.
+            self.hi_res = 0;
+        }
+        self.hi_res += value;
+        let detents = self.hi_res / HI_RES_PER_DETENT;
+        self.hi_res -= detents * HI_RES_PER_DETENT;
+        detents.unsigned_abs()
+    }
+
+    fn detents(&self, value: i32) -> u32 {
+        match self.seen_hi_res {
+            true => 0,
+            false => value.unsigned_abs(),
+        }
+    }
+}
+
+impl DialInput {
+    /// Returns the key that a relative event is turned into and the number of taps of it. The
+    /// number of taps is 0 if the event is a duplicate of a high resolution event or does not yet
+    /// add up to a detent. Returns `None` for other events.
+    pub fn key_taps(&mut self, event: &InputEvent) -> Option<(OsCode, u32)> {
+        if event.event_type() != EventType::RELATIVE {
+            return None;
+        }
+        let value = event.value();
+        let direction = |positive, negative| if value > 0 { positive } else { negative };
+        let wheel_key = direction(OsCode::WHEEL_UP, OsCode::WHEEL_DOWN);
+        let hwheel_key = direction(OsCode::WHEEL_RIGHT, OsCode::WHEEL_LEFT);
+        match RelativeAxisType(event.code()) {
+            RelativeAxisType::REL_DIAL => Some((
+                direction(OsCode::DIAL_CW, OsCode::DIAL_CCW),
+                value.unsigned_abs(),
+            )),
+            RelativeAxisType::REL_WHEEL_HI_RES => {
+                Some((wheel_key, self.wheel.hi_res_detents(value)))
+            }
+            RelativeAxisType::REL_WHEEL => Some((wheel_key, self.wheel.detents(value))),
+            RelativeAxisType::REL_HWHEEL_HI_RES => {
+                Some((hwheel_key, self.hwheel.hi_res_detents(value)))
+            }
+            RelativeAxisType::REL_HWHEEL => Some((hwheel_key, self.hwheel.detents(value))),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn dial_input_counts_detents() {
+    let rel = |axis: RelativeAxisType, value| InputEvent::new(EventType::RELATIVE, axis.0, value);
+    let mut dial = DialInput::default();
+    assert_eq!(
+        dial.key_taps(&rel(RelativeAxisType::REL_DIAL, 2)),
+        Some((OsCode::DIAL_CW, 2))
+    );
+    assert_eq!(
+        dial.key_taps(&rel(RelativeAxisType::REL_DIAL, -1)),
+        Some((OsCode::DIAL_CCW, 1))
+    );
+    assert_eq!(
+        dial.key_taps(&rel(RelativeAxisType::REL_WHEEL, -1)),
+        Some((OsCode::WHEEL_DOWN, 1))
+    );
+    assert_eq!(dial.key_taps(&rel(RelativeAxisType::REL_X, 5)), None);
+
+    // Once high resolution events are seen, the wheel detents come from those.
+    assert_eq!(
+        dial.key_taps(&rel(RelativeAxisType::REL_WHEEL_HI_RES, 60)),
+        Some((OsCode::WHEEL_UP, 0))
+    );
+    assert_eq!(
+        dial.key_taps(&rel(RelativeAxisType::REL_WHEEL_HI_RES, 90)),
+        Some((OsCode::WHEEL_UP, 1))
+    );
+    assert_eq!(
+        dial.key_taps(&rel(RelativeAxisType::REL_WHEEL, 1)),
+        Some((OsCode::WHEEL_UP, 0))
+    );
+    assert_eq!(
+        dial.key_taps(&rel(RelativeAxisType::REL_WHEEL_HI_RES, -120)),
+        Some((OsCode::WHEEL_DOWN, 1))
+    );
+
+    // The horizontal wheel is counted separately.
+    assert_eq!(
+        dial.key_taps(&rel(RelativeAxisType::REL_HWHEEL, -2)),
+        Some((OsCode::WHEEL_LEFT, 2))
+    );
+    assert_eq!(
+        dial.key_taps(&rel(RelativeAxisType::REL_HWHEEL_HI_RES, 120)),
+        Some((OsCode::WHEEL_RIGHT, 1))
+    );
+    assert_eq!(
+        dial.key_taps(&rel(RelativeAxisType::REL_HWHEEL, 1)),
+        Some((OsCode::WHEEL_RIGHT, 0))
+    );
+}
@@ -0,0 +1,117 @@
+//! Dwell clicking, for people who find it hard to press keys for clicks.
+//!
+//! While dwell clicking is on, the left mouse button is clicked once the pointer stopped moving for
+//! `dwell-click-time` milliseconds. Kanata does not see the pointer, so only movement by its own
+//! mouse actions counts. The pointer must move again before the next click.
+
+use super::*;
+
+pub const DWELL_CLICK_TIME_CFG_NAME: &str = "dwell-click-time";
+const DEFAULT_DWELL_CLICK_TIME: u16 = 600;
+
+#[derive(Debug)]
+pub struct DwellClick {
+    pub enabled: bool,
+    time: u16,
+    ticks_until_click: Option<u16>,
+}
+
+impl Default for DwellClick {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            time: DEFAULT_DWELL_CLICK_TIME,
+            ticks_until_click: None,
+        }
+    }
+}
+
+impl DwellClick {
+    /// Read the dwell time from defcfg, keeping `enabled`.
+    pub fn update_from_cfg(&mut self, items: &HashMap<String, String>) -> Result<()> {
+        self.time = match items.get(DWELL_CLICK_TIME_CFG_NAME) {
+            Some(s) => match s.parse::<u16>() {
+                Ok(time @ 1..) => time,
+                _ => bail!("{DWELL_CLICK_TIME_CFG_NAME} must be 1-65535, found {s}"),
+            },
+            None => DEFAULT_DWELL_CLICK_TIME,
+        };
+        Ok(())
+    }
+
+    pub fn set(&mut self, action: DwellClickAction) {
+        self.enabled = match action {
+            DwellClickAction::On => true,
+            DwellClickAction::Off => false,
+            DwellClickAction::Toggle => !self.enabled,
+        };
+        log::info!(
+            "dwell click {}",
+            if self.enabled { "enabled" } else { "disabled" }
+        );
+        self.ticks_until_click = None;
+    }
+
+    /// The pointer moved: click once it stays still for the dwell time.
+    pub fn moved(&mut self) {
+        if self.enabled {
+            self.ticks_until_click = Some(self.time);
+        }
+    }
+
+    /// Forget a pending click, e.g. because a button was clicked with a key.
+    pub fn cancel(&mut self) {
+        self.ticks_until_click = None;
+    }
+
+    /// Returns whether to click now.
+    pub fn tick(&mut self) -> bool {
+        match &mut self.ticks_until_click {
+            Some(1) => {
+                self.ticks_until_click = None;
+                true
+            }
+            Some(ticks) => {
+                *ticks -= 1;
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// The ticks until the next click, if one is pending.
+    pub fn ticks_until_due(&self) -> Option<u16> {
+        self.ticks_until_click
+    }
+}
+
+#[test]
+fn dwell_click_clicks_once_after_the_pointer_stops() {
+    let mut items = HashMap::default();
+    items.insert(DWELL_CLICK_TIME_CFG_NAME.into(), "3".into());
+    let mut dwell = DwellClick::default();
+    dwell.update_from_cfg(&items).unwrap();
+
+    // Nothing happens while dwell clicking is off.
+    dwell.moved();
+    assert_eq!(dwell.ticks_until_due(), None);
+
+    dwell.set(DwellClickAction::Toggle);
+    dwell.moved();
+    assert!(!dwell.tick());
+    dwell.moved();
+    assert_eq!(dwell.ticks_until_due(), Some(3));
+    assert_eq!(
+        [dwell.tick(), dwell.tick(), dwell.tick()],
+        [false, false, true]
+    );
+    assert_eq!(dwell.ticks_until_due(), None);
+    assert!(!dwell.tick());
+
+    dwell.moved();
+    dwell.cancel();
+    assert!(!dwell.tick());
+
+    items.insert(DWELL_CLICK_TIME_CFG_NAME.into(), "0".into());
+    assert!(dwell.update_from_cfg(&items).is_err());
+}
@@ -0,0 +1,182 @@
+//! The stages that an input key event goes through before it reaches the layout.
+//!
+//! Every stage either passes the event on, possibly changed, or consumes it:
+//! - `transform`: replaces or drops the key of the event, see `key-transform-input`.
+//! - `pass-through`: while pass-through is active, outputs the event as is.
+//! - `key-filter`: slow keys and bounce keys, which hold back or drop presses.
+//! - `ime`: on Linux, outputs the event as is while an IME is composing.
+//! - `swap-hands`: swaps the key of the event with its mirror.
+//!
+//! The stages run in the order of their priorities, lowest first. `event-stage-priority` changes
+//! the priorities, e.g. `event-stage-priority "swap-hands:5"` swaps keys before the key filters
+//! see them. The layout is always the last stage; combos are resolved by it, and the output rate
+//! limit applies to what it outputs.
+
+use super::*;
+
+pub const EVENT_STAGE_PRIORITY_CFG_NAME: &str = "event-stage-priority";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventStage {
+    Transform,
+    PassThrough,
+    KeyFilter,
+    Ime,
+    SwapHands,
+}
+
+impl EventStage {
+    const ALL: [Self; 5] = [
+        Self::Transform,
+        Self::PassThrough,
+        Self::KeyFilter,
+        Self::Ime,
+        Self::SwapHands,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Transform => "transform",
+            Self::PassThrough => "pass-through",
+            Self::KeyFilter => "key-filter",
+            Self::Ime => "ime",
+            Self::SwapHands => "swap-hands",
+        }
+    }
+
+    fn default_priority(self) -> i16 {
+        match self {
+            Self::Transform => 5,
+            Self::PassThrough => 10,
+            Self::KeyFilter => 20,
+            Self::Ime => 30,
+            Self::SwapHands => 40,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct EventStages {
+    /// The stages in the order they run.
+    stages: Vec<EventStage>,
+}
+
+impl Default for EventStages {
+    fn default() -> Self {
+        Self {
+            stages: EventStage::ALL.to_vec(),
+        }
+    }
+}
+
+impl EventStages {
+    /// Read the priorities from defcfg, e.g. `event-stage-priority "swap-hands:5 ime:15"`. Stages
+    /// that are not listed keep their default priority, and stages of the same priority keep
+    /// their default order.
+    pub fn update_from_cfg(&mut self, items: &HashMap<String, String>) -> Result<()> {
+        let mut priorities = EventStage::ALL.map(|stage| (stage, stage.default_priority()));
+        if let Some(value) = items.get(EVENT_STAGE_PRIORITY_CFG_NAME) {
+            for item in value.split_whitespace() {
+                let Some((name, priority)) = item.split_once(':') else {
+                    bail!(
+                        "{EVENT_STAGE_PRIORITY_CFG_NAME} expects items like swap-hands:5, \
+                         found {item}"
+                    );
+                };
+                let Some((_, p)) = priorities.iter_mut().find(|(s, _)| s.name() == name) else {
+                    bail!(
+                        "{EVENT_STAGE_PRIORITY_CFG_NAME}: unknown stage {name}, \
+                         expected transform, pass-through, key-filter, ime or swap-hands"
+                    );
+                };
+                *p = priority.parse().map_err(|_| {
+                    anyhow!("{EVENT_STAGE_PRIORITY_CFG_NAME}: {priority} is not a priority")
+                })?;
+            }
+        }
+        priorities.sort_by_key(|(_, p)| *p);
+        self.stages = priorities.iter().map(|(stage, _)| *stage).collect();
+        Ok(())
+    }
+
+    /// The index of the stage after the given one.
+    fn after(&self, stage: EventStage) -> usize {
+        self.stages.iter().position(|s| *s == stage).unwrap_or(0) + 1
+    }
+}
+
+impl Kanata {
+    /// Run the event through the stages, then the layout.
+    pub(super) fn process_key_event(&mut self, event: KeyEvent) -> Result<()> {
+        self.output_queue.interactive_input();
+        self.process_key_event_from(0, event)
+    }
+
+    /// Run a press that slow keys held back through the stages after the key filter.
+    pub(super) fn process_slow_key_press(&mut self, code: OsCode) -> Result<()> {
+        let next = self.event_stages.after(EventStage::KeyFilter);
+        self.process_key_event_from(next, KeyEvent::new(code, KeyValue::Press))
+    }
+
+    fn process_key_event_from(&mut self, first: usize, mut event: KeyEvent) -> Result<()> {
+        for i in first..self.event_stages.stages.len() {
+            match self.run_event_stage(self.event_stages.stages[i], event)? {
+                Some(next) => event = next,
+                None => return Ok(()),
+            }
+        }
+        self.handle_layout_event(&event)
+    }
+
+    /// Returns the event for the next stage, or `None` if the stage consumed it.
+    fn run_event_stage(&mut self, stage: EventStage, event: KeyEvent) -> Result<Option<KeyEvent>> {
+        match stage {
+            EventStage::Transform => Ok(self.key_transforms.input(&event)),
+            EventStage::PassThrough => Ok((!self.pass_through(&event)?).then_some(event)),
+            EventStage::KeyFilter => Ok(self.key_filter.accepts(&event).then_some(event)),
+            EventStage::Ime => {
+                #[cfg(target_os = "linux")]
+                if let Some(ime) = &mut self.ime_passthrough {
+                    if ime.should_pass_through(&event, self.layout.b().current_layer()) {
+                        log::debug!("IME active: passing through {event:?}");
+                        self.kbd_out.write_key(event.code, event.value)?;
+                        return Ok(None);
+                    }
+                }
+                Ok(Some(event))
+            }
+            EventStage::SwapHands => Ok(Some(self.swap_hands.transform(&event))),
+        }
+    }
+}
+
+#[test]
+fn event_stages_run_in_the_order_of_their_priorities() {
+    let mut stages = EventStages::default();
+    let mut items = HashMap::default();
+    stages.update_from_cfg(&items).unwrap();
+    assert_eq!(stages.stages, EventStage::ALL);
+    assert_eq!(stages.after(EventStage::KeyFilter), 3);
+
+    items.insert(
+        EVENT_STAGE_PRIORITY_CFG_NAME.to_owned(),
+        "swap-hands:5 ime:20".to_owned(),
+    );
+    stages.update_from_cfg(&items).unwrap();
+    assert_eq!(
+        stages.stages,
+        [
+            EventStage::Transform,
+            EventStage::SwapHands,
+            EventStage::PassThrough,
+            EventStage::KeyFilter,
+            EventStage::Ime,
+        ]
+    );
+    assert_eq!(stages.after(EventStage::KeyFilter), 4);
+
+    for bad in ["swap-hands", "combos:5", "ime:high"] {
+        items.insert(EVENT_STAGE_PRIORITY_CFG_NAME.to_owned(), bad.to_owned());
+        assert!(stages.update_from_cfg(&items).is_err(), "{bad}");
+    }
+}
@@ -0,0 +1,165 @@
+//! The key combination that exits kanata, so that a broken configuration can always be escaped.
+//!
+//! The input event loops check every key event that they read against the combination before
+//! anything else, whether the keys are mapped or not. The combination is Lctl+Spc+Esc by default.
+//! `exit-combo` replaces it, e.g. `exit-combo "lctl rctl esc"`, and with `exit-combo-hold` the
+//! keys must be held together for that many milliseconds, so that the combination is not pressed
+//! by accident. On Linux kanata then shuts down gracefully as on SIGTERM, releasing the devices.
+
+use super::*;
+
+pub const EXIT_COMBO_CFG_NAME: &str = "exit-combo";
+pub const EXIT_COMBO_HOLD_CFG_NAME: &str = "exit-combo-hold";
+
+pub static EXIT_COMBO: Lazy<Mutex<ExitCombo>> = Lazy::new(|| Mutex::new(ExitCombo::default()));
+
+#[derive(Debug)]
+pub struct ExitCombo {
+    keys: Vec<OsCode>,
+    hold: time::Duration,
+    /// The keys of the combination that are held.
+    held: Vec<OsCode>,
+    /// When the last key of the combination was pressed, while all of them are held.
+    complete_since: Option<time::Instant>,
+}
+
+impl Default for ExitCombo {
+    fn default() -> Self {
+        Self {
+            keys: vec![OsCode::KEY_LEFTCTRL, OsCode::KEY_SPACE, OsCode::KEY_ESC],
+            hold: time::Duration::ZERO,
+            held: vec![],
+            complete_since: None,
+        }
+    }
+}
+
+impl ExitCombo {
+    /// Read the combination from defcfg, keeping the state of held keys.
+    pub fn update_from_cfg(&mut self, items: &HashMap<String, String>) -> Result<()> {
+        let default = Self::default();
+        let keys = match items.get(EXIT_COMBO_CFG_NAME) {
+            Some(s) => s
+                .split_whitespace()
+                .map(|key| {
+                    str_to_oscode(key)
+                        .ok_or_else(|| anyhow!("{EXIT_COMBO_CFG_NAME}: unknown key {key}"))
+                })
+                .collect::<Result<Vec<_>>>()?,
+            None => default.keys,
+        };
+        if keys.is_empty() {
+            bail!("{EXIT_COMBO_CFG_NAME} needs at least one key");
+        }
+        let hold_ms = match items.get(EXIT_COMBO_HOLD_CFG_NAME) {
+            Some(s) => s
+                .parse::<u16>()
+                .map_err(|_| anyhow!("{EXIT_COMBO_HOLD_CFG_NAME} must be 0-65535, found {s}"))?,
+            None => 0,
+        };
+        self.keys = keys;
+        self.hold = time::Duration::from_millis(hold_ms.into());
+        self.held.retain(|k| self.keys.contains(k));
+        self.complete_since = None;
+        Ok(())
+    }
+
+    /// Track the keys of the combination. Returns the time from which the combination counts as
+    /// held if the event completed it.
+    pub fn event(&mut self, event: &KeyEvent, now: time::Instant) -> Option<time::Instant> {
+        if !self.keys.contains(&event.code) {
+            return None;
+        }
+        match event.value {
+            KeyValue::Press => {
+                if !self.held.contains(&event.code) {
+                    self.held.push(event.code);
+                }
+            }
+            KeyValue::Release => {
+                self.held.retain(|k| *k != event.code);
+                self.complete_since = None;
+                return None;
+            }
+            KeyValue::Repeat => return None,
+        }
+        if self.complete_since.is_some() || self.held.len() < self.keys.len() {
+            return None;
+        }
+        self.complete_since = Some(now);
+        self.complete_since
+    }
+
+    /// Whether the combination is still held since the given time.
+    fn held_since(&self, since: time::Instant) -> bool {
+        self.complete_since == Some(since)
+    }
+}
+
+/// Checks if kanata should exit because of the key event, immediately or once the exit combo
+/// was held long enough.
+pub(super) fn check_for_exit(event: &KeyEvent) {
+    let mut combo = EXIT_COMBO.lock();
+    let Some(since) = combo.event(event, time::Instant::now()) else {
+        return;
+    };
+    let hold = combo.hold;
+    drop(combo);
+    if hold.is_zero() {
+        exit();
+        return;
+    }
+    std::thread::spawn(move || {
+        std::thread::sleep(hold);
+        if EXIT_COMBO.lock().held_since(since) {
+            exit();
+        }
+    });
+}
+
+fn exit() {
+    const EXIT_MSG: &str = "pressed the exit combo, exiting";
+    #[cfg(not(target_os = "linux"))]
+    {
+        log::info!("{EXIT_MSG}");
+        panic!("{EXIT_MSG}");
+    }
+    #[cfg(target_os = "linux")]
+    {
+        log::info!("{EXIT_MSG}");
+        signal_hook::low_level::raise(signal_hook::consts::SIGTERM).expect("raise signal");
+    }
+}
+
+#[test]
+fn exit_combo_is_complete_while_all_keys_are_held() {
+    let press = |code| KeyEvent::new(code, KeyValue::Press);
+    let release = |code| KeyEvent::new(code, KeyValue::Release);
+    let now = time::Instant::now();
+    let mut combo = ExitCombo::default();
+    assert_eq!(combo.event(&press(OsCode::KEY_LEFTCTRL), now), None);
+    assert_eq!(combo.event(&press(OsCode::KEY_SPACE), now), None);
+    assert_eq!(combo.event(&press(OsCode::KEY_ESC), now), Some(now));
+    assert!(combo.held_since(now));
+    // Repeats do not restart the hold.
+    assert_eq!(combo.event(&press(OsCode::KEY_ESC), now), None);
+    assert_eq!(combo.event(&release(OsCode::KEY_SPACE), now), None);
+    assert!(!combo.held_since(now));
+
+    let items = [
+        (EXIT_COMBO_CFG_NAME.to_owned(), "lctl rctl".to_owned()),
+        (EXIT_COMBO_HOLD_CFG_NAME.to_owned(), "2000".to_owned()),
+    ]
+    .into_iter()
+    .collect();
+    combo.update_from_cfg(&items).unwrap();
+    assert_eq!(combo.hold, time::Duration::from_secs(2));
+    // Left control is still held from before.
+    assert_eq!(combo.event(&press(OsCode::KEY_ESC), now), None);
+    assert_eq!(combo.event(&press(OsCode::KEY_RIGHTCTRL), now), Some(now));
+
+    let items = [(EXIT_COMBO_CFG_NAME.to_owned(), "lctl nokey".to_owned())]
+        .into_iter()
+        .collect();
+    assert!(combo.update_from_cfg(&items).is_err());
+}
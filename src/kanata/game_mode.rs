@@ -0,0 +1,79 @@
+//! Game mode, in which keys with timing-dependent actions act immediately.
+//!
+//! While game mode is active, hold-tap and tap-dance keys activate their tap action or first
+//! action on press and one-shot keys act like their action held down. Simple remaps are kept.
+//! Game mode is active while one of the `game-mode-layers` is active, or while it is enabled by a
+//! TCP client, e.g. a script that watches the focused window.
+
+use super::*;
+
+pub const GAME_MODE_LAYERS_CFG_NAME: &str = "game-mode-layers";
+
+#[derive(Debug, Default)]
+pub struct GameMode {
+    /// The keyberon layer indexes during which game mode is active.
+    layers: Vec<usize>,
+    /// Set by TCP clients.
+    pub enabled: bool,
+}
+
+impl GameMode {
+    /// Read the game mode layers from defcfg, keeping `enabled`.
+    pub fn update_from_cfg(
+        &mut self,
+        items: &HashMap<String, String>,
+        layer_info: &[LayerInfo],
+    ) -> Result<()> {
+        let mut layers = vec![];
+        for layer in items
+            .get(GAME_MODE_LAYERS_CFG_NAME)
+            .map(|s| s.split_whitespace())
+            .into_iter()
+            .flatten()
+        {
+            let len = layers.len();
+            layers.extend(
+                layer_info
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, l)| l.name == layer)
+                    .map(|(i, _)| i),
+            );
+            if layers.len() == len {
+                bail!("{GAME_MODE_LAYERS_CFG_NAME} contains unknown layer: {layer}");
+            }
+        }
+        self.layers = layers;
+        Ok(())
+    }
+
+    pub fn is_active(&self, layer: usize) -> bool {
+        self.enabled || self.layers.contains(&layer)
+    }
+}
+
+#[test]
+fn game_mode_is_active_on_layers_or_when_enabled() {
+    let layer_info = ["base", "base", "fps", "fps"]
+        .iter()
+        .map(|name| LayerInfo {
+            name: name.to_string(),
+            cfg_text: String::new(),
+            sounds: None,
+            tags: vec![],
+            notify: vec![],
+            doc: LayerDoc::default(),
+        })
+        .collect::<Vec<_>>();
+    let mut items = HashMap::default();
+    items.insert(GAME_MODE_LAYERS_CFG_NAME.into(), "fps".into());
+    let mut game_mode = GameMode::default();
+    game_mode.update_from_cfg(&items, &layer_info).unwrap();
+    assert!(!game_mode.is_active(0));
+    assert!(game_mode.is_active(3));
+    game_mode.enabled = true;
+    assert!(game_mode.is_active(0));
+
+    items.insert(GAME_MODE_LAYERS_CFG_NAME.into(), "mmo".into());
+    assert!(game_mode.update_from_cfg(&items, &layer_info).is_err());
+}
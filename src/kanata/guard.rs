@@ -0,0 +1,180 @@
+//! A guard against dangerous key chords, for kiosks and other machines where some shortcuts of the
+//! OS must not be reachable, e.g. `C-A-f1` to switch to a text console or `A-sys` for the magic
+//! SysRq keys of Linux.
+//!
+//! `guard-chords` lists chords in the prefix syntax of the configuration, e.g. `"C-A-f1 A-sys"`.
+//! Modifiers match either side. When the layout outputs the key of a chord while its modifiers are
+//! held on the output, the key is not output. With `guard-layers`, the guard only applies while
+//! one of the layers is the current layer. With `guard-confirm`, the key is output if the same
+//! chord is pressed again within that many milliseconds, so that it needs a deliberate double
+//! press instead of being blocked.
+//!
+//! The guard applies to the keys that the layout outputs, not to keys injected by TCP clients or
+//! the IME and pass-through stages.
+
+use super::*;
+
+use crate::cfg::parse_mod_prefix;
+
+use std::time::Instant;
+
+pub const GUARD_CHORDS_CFG_NAME: &str = "guard-chords";
+pub const GUARD_LAYERS_CFG_NAME: &str = "guard-layers";
+pub const GUARD_CONFIRM_CFG_NAME: &str = "guard-confirm";
+
+#[derive(Debug, Default)]
+pub struct ChordGuard {
+    /// The chords as written, their modifiers and their keys, on the left side.
+    chords: Vec<(String, Vec<OsCode>, OsCode)>,
+    /// The keyberon layers where the guard applies, or all layers if empty.
+    layers: Vec<usize>,
+    confirm: Option<time::Duration>,
+    /// The chord that was blocked last in confirm mode and when.
+    pending: Option<(usize, Instant)>,
+}
+
+impl ChordGuard {
+    pub fn from_cfg(items: &HashMap<String, String>, layer_info: &[LayerInfo]) -> Result<Self> {
+        let mut guard = Self::default();
+        for item in items
+            .get(GUARD_CHORDS_CFG_NAME)
+            .map(String::as_str)
+            .unwrap_or_default()
+            .split_whitespace()
+        {
+            let invalid = || anyhow!("{GUARD_CHORDS_CFG_NAME}: invalid chord {item}");
+            let (mods, key) = parse_mod_prefix(item).map_err(|_| invalid())?;
+            let key = str_to_oscode(key).ok_or_else(invalid)?;
+            let mods = mods.into_iter().map(|kc| left_side(kc.into())).collect();
+            guard.chords.push((item.to_owned(), mods, left_side(key)));
+        }
+        for name in items
+            .get(GUARD_LAYERS_CFG_NAME)
+            .map(String::as_str)
+            .unwrap_or_default()
+            .split_whitespace()
+        {
+            let len = guard.layers.len();
+            guard.layers.extend(
+                layer_info
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, l)| l.name == name)
+                    .map(|(i, _)| i),
+            );
+            if guard.layers.len() == len {
+                bail!("{GUARD_LAYERS_CFG_NAME} contains unknown layer: {name}");
+            }
+        }
+        if let Some(s) = items.get(GUARD_CONFIRM_CFG_NAME) {
+            match s.parse::<u16>() {
+                Ok(ms @ 1..) => guard.confirm = Some(time::Duration::from_millis(ms.into())),
+                _ => bail!("{GUARD_CONFIRM_CFG_NAME} must be 1-65535, found {s}"),
+            }
+        }
+        Ok(guard)
+    }
+
+    /// Whether the key may be pressed on the output with the other output keys held.
+    pub fn allows(&mut self, key: OsCode, held: &[KeyCode], layer: usize, now: Instant) -> bool {
+        if self.chords.is_empty() || (!self.layers.is_empty() && !self.layers.contains(&layer)) {
+            return true;
+        }
+        let held = held
+            .iter()
+            .map(|kc| left_side(OsCode::from(kc)))
+            .collect::<Vec<_>>();
+        let key = left_side(key);
+        let Some(i) = self
+            .chords
+            .iter()
+            .position(|(_, mods, k)| *k == key && mods.iter().all(|m| held.contains(m)))
+        else {
+            return true;
+        };
+        let name = &self.chords[i].0;
+        let Some(window) = self.confirm else {
+            log::warn!("guard: blocked {name}");
+            return false;
+        };
+        match self.pending.take() {
+            Some((pending, at)) if pending == i && now.duration_since(at) <= window => {
+                log::info!("guard: {name} confirmed");
+                true
+            }
+            _ => {
+                log::warn!(
+                    "guard: blocked {name}, press it again within {}ms to confirm",
+                    window.as_millis()
+                );
+                self.pending = Some((i, now));
+                false
+            }
+        }
+    }
+}
+
+/// The modifier on the left side for a right modifier, so that chords match either side.
+fn left_side(osc: OsCode) -> OsCode {
+    match osc {
+        OsCode::KEY_RIGHTCTRL => OsCode::KEY_LEFTCTRL,
+        OsCode::KEY_RIGHTSHIFT => OsCode::KEY_LEFTSHIFT,
+        OsCode::KEY_RIGHTALT => OsCode::KEY_LEFTALT,
+        OsCode::KEY_RIGHTMETA => OsCode::KEY_LEFTMETA,
+        osc => osc,
+    }
+}
+
+#[test]
+fn guard_blocks_or_confirms_chords() {
+    let layer_info = ["base", "base", "kiosk", "kiosk"]
+        .iter()
+        .map(|name| LayerInfo {
+            name: name.to_string(),
+            cfg_text: String::new(),
+            sounds: None,
+            tags: vec![],
+            notify: vec![],
+            doc: LayerDoc::default(),
+        })
+        .collect::<Vec<_>>();
+    let mut items = HashMap::default();
+    items.insert(GUARD_CHORDS_CFG_NAME.to_owned(), "C-A-f1 A-sys".to_owned());
+    items.insert(GUARD_LAYERS_CFG_NAME.to_owned(), "kiosk".to_owned());
+    let mut guard = ChordGuard::from_cfg(&items, &layer_info).unwrap();
+    let now = Instant::now();
+    let ctl_alt = [KeyCode::RCtrl, KeyCode::LAlt, KeyCode::F1];
+    assert!(guard.allows(OsCode::KEY_F1, &ctl_alt, 0, now));
+    assert!(!guard.allows(OsCode::KEY_F1, &ctl_alt, 2, now));
+    assert!(!guard.allows(OsCode::KEY_SYSRQ, &[KeyCode::RAlt], 3, now));
+    assert!(guard.allows(OsCode::KEY_F1, &[KeyCode::LAlt], 2, now));
+    assert!(guard.allows(OsCode::KEY_F2, &ctl_alt, 2, now));
+
+    items.insert(GUARD_CONFIRM_CFG_NAME.to_owned(), "500".to_owned());
+    let mut guard = ChordGuard::from_cfg(&items, &layer_info).unwrap();
+    assert!(!guard.allows(OsCode::KEY_F1, &ctl_alt, 2, now));
+    assert!(guard.allows(
+        OsCode::KEY_F1,
+        &ctl_alt,
+        2,
+        now + time::Duration::from_millis(400)
+    ));
+    assert!(!guard.allows(OsCode::KEY_F1, &ctl_alt, 2, now));
+    assert!(!guard.allows(
+        OsCode::KEY_F1,
+        &ctl_alt,
+        2,
+        now + time::Duration::from_millis(600)
+    ));
+
+    for (name, bad) in [
+        (GUARD_CHORDS_CFG_NAME, "C-C-f1"),
+        (GUARD_CHORDS_CFG_NAME, "C-nokey"),
+        (GUARD_LAYERS_CFG_NAME, "nav"),
+        (GUARD_CONFIRM_CFG_NAME, "0"),
+    ] {
+        let mut items = items.clone();
+        items.insert(name.to_owned(), bad.to_owned());
+        assert!(ChordGuard::from_cfg(&items, &layer_info).is_err(), "{bad}");
+    }
+}
@@ -0,0 +1,212 @@
+//! Helper processes that kanata runs alongside itself, e.g. an overlay, a tray icon or a window
+//! tracker that reports the active window over TCP.
+//!
+//! `(defhelpers <name> (<program> <arg>...)...)` starts each helper once kanata runs and starts it
+//! again whenever it exits, after a delay that doubles while it keeps exiting soon after it
+//! started. The helpers are killed when kanata shuts down. On a live reload, only the helpers that
+//! were added, removed or whose command changed are started or killed.
+
+use super::*;
+
+use std::process::{Child, Command};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const MIN_RESTART_DELAY: time::Duration = time::Duration::from_secs(1);
+const MAX_RESTART_DELAY: time::Duration = time::Duration::from_secs(60);
+/// A helper that ran this long is started again after the minimum delay.
+const STABLE_RUN_TIME: time::Duration = time::Duration::from_secs(30);
+const EXIT_POLL_INTERVAL: time::Duration = time::Duration::from_millis(200);
+
+#[derive(Debug)]
+struct RunningHelper {
+    cmd: HelperCmd,
+    stop: Arc<AtomicBool>,
+    child: Arc<Mutex<Option<Child>>>,
+}
+
+impl RunningHelper {
+    fn start(cmd: HelperCmd) -> Self {
+        let helper = Self {
+            cmd,
+            stop: Arc::new(AtomicBool::new(false)),
+            child: Arc::new(Mutex::new(None)),
+        };
+        let (cmd, stop, child) = (
+            helper.cmd.clone(),
+            helper.stop.clone(),
+            helper.child.clone(),
+        );
+        std::thread::spawn(move || supervise(&cmd, &stop, &child));
+        helper
+    }
+
+    fn kill(&self) {
+        // The supervisor checks the flag while holding the lock before it starts the helper, so
+        // it cannot start another one after this.
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(mut child) = self.child.lock().take() {
+            log::info!("stopping helper {}", self.cmd.0);
+            if let Err(e) = child.kill() {
+                log::warn!("failed to kill helper {}: {e}", self.cmd.0);
+            }
+            let _ = child.wait();
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Helpers {
+    cmds: Vec<HelperCmd>,
+    running: Vec<RunningHelper>,
+    started: bool,
+}
+
+impl Helpers {
+    pub fn new(cmds: Vec<HelperCmd>) -> Self {
+        Self {
+            cmds,
+            ..Default::default()
+        }
+    }
+
+    /// Start the helpers, once kanata runs.
+    pub fn start(&mut self) {
+        if std::mem::replace(&mut self.started, true) {
+            return;
+        }
+        self.running = self.cmds.drain(..).map(RunningHelper::start).collect();
+    }
+
+    /// Replace the helpers with those of a new configuration, keeping the ones that are the same.
+    pub fn update_from_cfg(&mut self, cmds: Vec<HelperCmd>) {
+        if !self.started {
+            self.cmds = cmds;
+            return;
+        }
+        let (mut kept, removed) = std::mem::take(&mut self.running)
+            .into_iter()
+            .partition::<Vec<_>, _>(|helper| cmds.contains(&helper.cmd));
+        for helper in removed {
+            helper.kill();
+        }
+        self.running = cmds
+            .into_iter()
+            .map(|cmd| {
+                let kept_at = kept.iter().position(|helper| helper.cmd == cmd);
+                match kept_at {
+                    Some(i) => kept.swap_remove(i),
+                    None => RunningHelper::start(cmd),
+                }
+            })
+            .collect();
+    }
+
+    /// Kill the helpers, before kanata exits.
+    pub fn kill_all(&mut self) {
+        for helper in self.running.drain(..) {
+            helper.kill();
+        }
+    }
+}
+
+/// Run the helper until `stop` is set, starting it again whenever it exits.
+fn supervise(cmd: &HelperCmd, stop: &AtomicBool, child: &Mutex<Option<Child>>) {
+    let (name, args) = (&cmd.0, &cmd.1);
+    let mut delay = MIN_RESTART_DELAY;
+    loop {
+        let started_at = time::Instant::now();
+        {
+            let mut child = child.lock();
+            if stop.load(Ordering::Relaxed) {
+                return;
+            }
+            match command(args).spawn() {
+                Ok(c) => {
+                    log::info!("started helper {name}, pid {}", c.id());
+                    *child = Some(c);
+                }
+                Err(e) => log::error!("failed to start helper {name}: {e}"),
+            }
+        }
+        // Poll instead of waiting, so that the helper can be killed while it runs.
+        let status = loop {
+            let mut child = child.lock();
+            let Some(c) = child.as_mut() else {
+                break None;
+            };
+            match c.try_wait() {
+                Ok(None) => {}
+                Ok(Some(status)) => {
+                    *child = None;
+                    break Some(status);
+                }
+                Err(e) => {
+                    log::error!("failed to wait for helper {name}: {e}");
+                    *child = None;
+                    break None;
+                }
+            }
+            drop(child);
+            std::thread::sleep(EXIT_POLL_INTERVAL);
+        };
+        if stop.load(Ordering::Relaxed) {
+            return;
+        }
+        if started_at.elapsed() >= STABLE_RUN_TIME {
+            delay = MIN_RESTART_DELAY;
+        }
+        match status {
+            Some(status) => {
+                log::warn!("helper {name} exited with {status}, restarting in {delay:?}")
+            }
+            None => log::warn!("restarting helper {name} in {delay:?}"),
+        }
+        std::thread::sleep(delay);
+        delay = (delay * 2).min(MAX_RESTART_DELAY);
+    }
+}
+
+fn command(args: &[String]) -> Command {
+    let mut cmd = Command::new(&args[0]);
+    cmd.args(&args[1..]);
+    // Kill the helper if kanata dies without shutting down, e.g. when it crashes. The signal is
+    // sent when the thread that started the helper exits, which is its supervisor.
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::process::CommandExt;
+        // SAFETY: prctl is async-signal-safe and the closure does not allocate.
+        unsafe {
+            cmd.pre_exec(|| {
+                libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGTERM);
+                Ok(())
+            });
+        }
+    }
+    cmd
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn helpers_are_kept_across_reloads_unless_changed() {
+    let helper = |name: &str, secs: &str| -> HelperCmd {
+        (name.to_owned(), vec!["sleep".to_owned(), secs.to_owned()])
+    };
+    let mut helpers = Helpers::new(vec![helper("a", "30"), helper("b", "30")]);
+    helpers.update_from_cfg(vec![helper("a", "30")]);
+    assert!(helpers.running.is_empty());
+    helpers.start();
+    assert_eq!(helpers.running.len(), 1);
+    let stop_a = helpers.running[0].stop.clone();
+
+    helpers.update_from_cfg(vec![helper("b", "20"), helper("a", "30")]);
+    assert_eq!(helpers.running.len(), 2);
+    assert!(Arc::ptr_eq(&helpers.running[1].stop, &stop_a));
+    helpers.update_from_cfg(vec![helper("a", "20")]);
+    assert!(stop_a.load(Ordering::Relaxed));
+    assert_eq!(helpers.running.len(), 1);
+
+    let stop = helpers.running[0].stop.clone();
+    helpers.kill_all();
+    assert!(stop.load(Ordering::Relaxed));
+    assert!(helpers.running.is_empty());
+}
@@ -0,0 +1,157 @@
+//! Pass-through of key events while an input method editor (IME) is active.
+//!
+//! Layers that inject modifiers or macros can corrupt an in-progress IME composition, e.g. for CJK
+//! input. When configured, kanata polls the IME framework and, while it reports an active input
+//! method, key presses made on one of the configured layers are sent to the OS unmodified.
+//!
+//! The state is queried with the framework's own command line tools (`fcitx5-remote` or
+//! `ibus engine`) so that no D-Bus dependency is needed.
+
+use super::*;
+
+use std::process::Command;
+use std::sync::atomic::AtomicBool;
+
+pub const IME_DETECT_CFG_NAME: &str = "linux-ime-detect";
+pub const IME_LAYERS_CFG_NAME: &str = "linux-ime-passthrough-layers";
+const IME_POLL_INTERVAL: time::Duration = time::Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImeFramework {
+    Fcitx,
+    Ibus,
+}
+
+impl ImeFramework {
+    fn try_from_str(s: &str) -> Result<Self> {
+        match s {
+            "fcitx" => Ok(ImeFramework::Fcitx),
+            "ibus" => Ok(ImeFramework::Ibus),
+            _ => bail!("{IME_DETECT_CFG_NAME} must be one of: fcitx, ibus"),
+        }
+    }
+
+    /// Returns whether an input method (as opposed to a plain keyboard layout) is active. Errors,
+    /// e.g. the tool not being installed, are treated as inactive.
+    fn query_active(self) -> bool {
+        match self {
+            ImeFramework::Fcitx => Command::new("fcitx5-remote")
+                .output()
+                // 0 = closed, 1 = inactive, 2 = active
+                .map(|o| String::from_utf8_lossy(&o.stdout).trim() == "2")
+                .unwrap_or(false),
+            ImeFramework::Ibus => Command::new("ibus")
+                .arg("engine")
+                .output()
+                .map(|o| {
+                    let engine = String::from_utf8_lossy(&o.stdout);
+                    let engine = engine.trim();
+                    o.status.success() && !engine.is_empty() && !engine.starts_with("xkb:")
+                })
+                .unwrap_or(false),
+        }
+    }
+}
+
+pub struct ImePassthrough {
+    /// keyberon layer indexes on which key presses are passed through.
+    layers: Vec<usize>,
+    active: Arc<AtomicBool>,
+    /// Keys whose presses were passed through, so that their repeats and releases are too.
+    passed_through_keys: Vec<OsCode>,
+}
+
+impl ImePassthrough {
+    /// Read the IME configuration from defcfg. Returns `None` if IME detection is not configured.
+    /// Starts a thread that polls the IME state for as long as the returned value is alive.
+    pub fn from_cfg(
+        items: &HashMap<String, String>,
+        layer_info: &[LayerInfo],
+    ) -> Result<Option<Self>> {
+        let framework = match items.get(IME_DETECT_CFG_NAME) {
+            Some(s) => ImeFramework::try_from_str(s)?,
+            None => return Ok(None),
+        };
+        let layer_names = items
+            .get(IME_LAYERS_CFG_NAME)
+            .ok_or_else(|| anyhow!("{IME_DETECT_CFG_NAME} requires {IME_LAYERS_CFG_NAME}"))?;
+        let mut layers = vec![];
+        for name in layer_names.split_whitespace() {
+            let len_before = layers.len();
+            layers.extend(
+                layer_info
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, l)| l.name == name)
+                    .map(|(i, _)| i),
+            );
+            if layers.len() == len_before {
+                bail!("{IME_LAYERS_CFG_NAME} contains unknown layer: {name}");
+            }
+        }
+
+        let active = Arc::new(AtomicBool::new(false));
+        let poll_active = active.clone();
+        std::thread::spawn(move || {
+            // Stop polling once the owning ImePassthrough is dropped, e.g. on live reload.
+            while Arc::strong_count(&poll_active) > 1 {
+                let is_active = framework.query_active();
+                if poll_active.swap(is_active, SeqCst) != is_active {
+                    log::info!("IME active: {is_active}");
+                }
+                std::thread::sleep(IME_POLL_INTERVAL);
+            }
+        });
+
+        Ok(Some(Self {
+            layers,
+            active,
+            passed_through_keys: vec![],
+        }))
+    }
+
+    /// Returns true if the event should be sent directly to the OS instead of being processed by
+    /// the layout.
+    pub fn should_pass_through(&mut self, event: &KeyEvent, cur_layer: usize) -> bool {
+        match event.value {
+            KeyValue::Press => {
+                if self.active.load(SeqCst) && self.layers.contains(&cur_layer) {
+                    self.passed_through_keys.push(event.code);
+                    true
+                } else {
+                    false
+                }
+            }
+            KeyValue::Repeat => self.passed_through_keys.contains(&event.code),
+            KeyValue::Release => {
+                let len_before = self.passed_through_keys.len();
+                self.passed_through_keys.retain(|k| *k != event.code);
+                self.passed_through_keys.len() != len_before
+            }
+        }
+    }
+}
+
+#[test]
+fn ime_passthrough_tracks_passed_through_keys() {
+    let mut ime = ImePassthrough {
+        layers: vec![2, 3],
+        active: Arc::new(AtomicBool::new(true)),
+        passed_through_keys: vec![],
+    };
+    let press = |code| KeyEvent {
+        code,
+        value: KeyValue::Press,
+    };
+    let release = |code| KeyEvent {
+        code,
+        value: KeyValue::Release,
+    };
+    assert!(!ime.should_pass_through(&press(OsCode::KEY_A), 0));
+    assert!(ime.should_pass_through(&press(OsCode::KEY_B), 2));
+    ime.active.store(false, SeqCst);
+    // The release must follow the press even if the IME state changed in between.
+    assert!(ime.should_pass_through(&release(OsCode::KEY_B), 2));
+    assert!(!ime.should_pass_through(&release(OsCode::KEY_A), 2));
+    assert!(!ime.should_pass_through(&press(OsCode::KEY_C), 2));
+}
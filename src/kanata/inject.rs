@@ -0,0 +1,53 @@
+//! Key events injected by TCP clients, so that scripts can type or trigger layers through kanata
+//! instead of a separate tool such as xdotool.
+//!
+//! `InjectKeys` takes a list of keys separated by spaces: a key name taps the key, `+name` presses
+//! it and `-name` releases it. The events go through the same processing as the events of the
+//! input devices, so they trigger the actions of the active layers, unless `output` is set, in
+//! which case they are sent to the output as they are. `InjectText` types text on the output. Keys
+//! sent to the output and text are queued as background output, see
+//! [`output_queue`](super::output_queue).
+
+use super::*;
+
+/// Parse the keys of `InjectKeys` into the events to inject, in order.
+pub fn parse_injected_keys(keys: &str) -> Result<Vec<KeyEvent>> {
+    let mut events = vec![];
+    for item in keys.split_whitespace() {
+        // `-` alone is the minus key, not a release without a key.
+        let prefixed = |prefix: char| item.strip_prefix(prefix).filter(|name| !name.is_empty());
+        let (name, values): (&str, &[KeyValue]) = match (prefixed('+'), prefixed('-')) {
+            (Some(name), _) => (name, &[KeyValue::Press]),
+            (_, Some(name)) => (name, &[KeyValue::Release]),
+            _ => (item, &[KeyValue::Press, KeyValue::Release]),
+        };
+        let code = str_to_oscode(name).ok_or_else(|| anyhow!("unknown key {name}"))?;
+        events.extend(values.iter().map(|&value| KeyEvent { code, value }));
+    }
+    if events.is_empty() {
+        bail!("no keys to inject");
+    }
+    Ok(events)
+}
+
+#[test]
+fn injected_keys_are_taps_presses_and_releases() {
+    let events = parse_injected_keys("+lsft a -lsft -").unwrap();
+    let events = events
+        .iter()
+        .map(|ev| (ev.code, ev.value))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        events,
+        [
+            (OsCode::KEY_LEFTSHIFT, KeyValue::Press),
+            (OsCode::KEY_A, KeyValue::Press),
+            (OsCode::KEY_A, KeyValue::Release),
+            (OsCode::KEY_LEFTSHIFT, KeyValue::Release),
+            (OsCode::KEY_MINUS, KeyValue::Press),
+            (OsCode::KEY_MINUS, KeyValue::Release),
+        ]
+    );
+    assert!(parse_injected_keys("a nosuchkey").is_err());
+    assert!(parse_injected_keys(" ").is_err());
+}
@@ -0,0 +1,243 @@
+//! Sources of key events other than the keyboards of the OS, e.g. DIY macro pads.
+//!
+//! An [`InputSource`] is read in a thread of its own, which sends the key events to the
+//! processing loop like the keyboards do, so they go through the same event stages and layers.
+//! The thread is started once the processing loop exists, see [`Kanata::set_processing_tx`].
+//!
+//! The serial source reads a macro pad without HID firmware, e.g. an ESP32 or an Arduino, from
+//! the tty of `serial-input-device`, e.g. `/dev/ttyACM0` or `\\.\COM3`. Every line that the pad
+//! writes is `+` for a press or `-` for a release, followed by the id of the key, e.g. `+0` and
+//! `-0`. The id is the index of the key in `serial-input-keys`, e.g. `"f13 f14 f15"`, and these
+//! keys must be in defsrc. The tty is read as it is, so the baud rate must be set up beforehand,
+//! e.g. with `stty`, unless the pad is a USB CDC device for which it does not matter. The held
+//! keys are released if the pad disconnects, and the device is opened again until it is back.
+
+use super::*;
+
+use std::io::{BufRead, BufReader};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub const SERIAL_INPUT_DEVICE_CFG_NAME: &str = "serial-input-device";
+pub const SERIAL_INPUT_KEYS_CFG_NAME: &str = "serial-input-keys";
+
+/// How long to wait before reading a source again after it failed, e.g. while it is unplugged.
+const RETRY_DELAY: time::Duration = time::Duration::from_secs(1);
+
+pub trait InputSource: Send {
+    /// The source in logs.
+    fn name(&self) -> &str;
+
+    /// Wait for the next key events of the source. After an error, the source is read again
+    /// after a delay.
+    fn read_events(&mut self) -> Result<Vec<KeyEvent>>;
+}
+
+/// The configured input sources, which are read until this is dropped.
+pub struct InputSources {
+    serial: Option<SerialPad>,
+    started: bool,
+    stop: Arc<AtomicBool>,
+}
+
+impl Drop for InputSources {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+impl InputSources {
+    pub fn from_cfg(items: &HashMap<String, String>, mapped_keys: &MappedKeys) -> Result<Self> {
+        Ok(Self {
+            serial: SerialPad::from_cfg(items, mapped_keys)?,
+            started: false,
+            stop: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Whether the new sources read the same as these, so that these can be kept on a live
+    /// reload instead of reading the devices twice.
+    pub fn same_as(&self, other: &Self) -> bool {
+        self.serial_cfg() == other.serial_cfg()
+    }
+
+    fn serial_cfg(&self) -> Option<(&str, &[OsCode])> {
+        self.serial
+            .as_ref()
+            .map(|pad| (pad.device.as_str(), pad.keys.as_slice()))
+    }
+
+    /// Start a thread for every source that sends its key events to the processing loop.
+    pub fn start(&mut self, tx: &Sender<ProcessingEvent>) {
+        if std::mem::replace(&mut self.started, true) {
+            return;
+        }
+        if let Some(pad) = &self.serial {
+            let source = SerialPad::new(pad.device.clone(), pad.keys.clone());
+            let (tx, stop) = (tx.clone(), self.stop.clone());
+            std::thread::spawn(move || run_input_source(source, tx, &stop));
+        }
+    }
+}
+
+fn run_input_source(mut source: impl InputSource, tx: Sender<ProcessingEvent>, stop: &AtomicBool) {
+    let mut warned = false;
+    while !stop.load(Ordering::Relaxed) {
+        let events = match source.read_events() {
+            Ok(events) => {
+                warned = false;
+                events
+            }
+            Err(e) => {
+                if !warned {
+                    log::warn!("could not read the input source {}: {e}", source.name());
+                    warned = true;
+                }
+                std::thread::sleep(RETRY_DELAY);
+                continue;
+            }
+        };
+        if stop.load(Ordering::Relaxed) {
+            return;
+        }
+        for event in events {
+            log::debug!("input source {}: {event:?}", source.name());
+            check_for_exit(&event);
+            if tx.send(ProcessingEvent::Key(event)).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// A macro pad that writes its key presses to a serial port.
+pub struct SerialPad {
+    device: String,
+    /// The keys by id.
+    keys: Vec<OsCode>,
+    reader: Option<BufReader<std::fs::File>>,
+    held: Vec<OsCode>,
+}
+
+impl SerialPad {
+    fn new(device: String, keys: Vec<OsCode>) -> Self {
+        Self {
+            device,
+            keys,
+            reader: None,
+            held: vec![],
+        }
+    }
+
+    fn from_cfg(items: &HashMap<String, String>, mapped_keys: &MappedKeys) -> Result<Option<Self>> {
+        let Some(device) = items.get(SERIAL_INPUT_DEVICE_CFG_NAME) else {
+            return Ok(None);
+        };
+        let Some(keys) = items.get(SERIAL_INPUT_KEYS_CFG_NAME) else {
+            bail!("{SERIAL_INPUT_DEVICE_CFG_NAME} requires {SERIAL_INPUT_KEYS_CFG_NAME}");
+        };
+        let keys = keys
+            .split_whitespace()
+            .map(|key| match str_to_oscode(key) {
+                Some(code) if mapped_keys.contains(&code) => Ok(code),
+                Some(_) => Err(anyhow!(
+                    "{SERIAL_INPUT_KEYS_CFG_NAME}: {key} is not in defsrc"
+                )),
+                None => Err(anyhow!("{SERIAL_INPUT_KEYS_CFG_NAME}: unknown key {key}")),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Some(Self::new(device.clone(), keys)))
+    }
+
+    /// The key event of a line that the pad wrote.
+    fn parse_line(&self, line: &str) -> Option<KeyEvent> {
+        let line = line.trim();
+        let (value, id) = if let Some(id) = line.strip_prefix('+') {
+            (KeyValue::Press, id)
+        } else {
+            (KeyValue::Release, line.strip_prefix('-')?)
+        };
+        let code = *self.keys.get(id.parse::<usize>().ok()?)?;
+        Some(KeyEvent::new(code, value))
+    }
+}
+
+impl InputSource for SerialPad {
+    fn name(&self) -> &str {
+        &self.device
+    }
+
+    fn read_events(&mut self) -> Result<Vec<KeyEvent>> {
+        let mut reader = match self.reader.take() {
+            Some(reader) => reader,
+            None => {
+                let file = std::fs::File::open(&self.device)?;
+                log::info!("reading the serial input device {}", self.device);
+                BufReader::new(file)
+            }
+        };
+        let mut line = String::new();
+        let failure = match reader.read_line(&mut line) {
+            Ok(0) => Some(anyhow!("the device was closed")),
+            Ok(_) => None,
+            Err(e) => Some(e.into()),
+        };
+        // The device is opened again on the next read if it failed.
+        if let Some(e) = failure {
+            if self.held.is_empty() {
+                return Err(e);
+            }
+            log::warn!(
+                "serial input device {} failed, releasing its keys: {e}",
+                self.device
+            );
+            return Ok(self
+                .held
+                .drain(..)
+                .map(|code| KeyEvent::new(code, KeyValue::Release))
+                .collect());
+        }
+        self.reader = Some(reader);
+        let Some(event) = self.parse_line(&line) else {
+            log::warn!("serial input device {} sent {:?}", self.device, line.trim());
+            return Ok(vec![]);
+        };
+        let event = match event.value {
+            // A press of a held key repeats it.
+            KeyValue::Press if self.held.contains(&event.code) => {
+                KeyEvent::new(event.code, KeyValue::Repeat)
+            }
+            KeyValue::Press => {
+                self.held.push(event.code);
+                event
+            }
+            _ => {
+                self.held.retain(|k| *k != event.code);
+                event
+            }
+        };
+        Ok(vec![event])
+    }
+}
+
+#[test]
+fn serial_pad_lines_are_key_events() {
+    let mut items = HashMap::default();
+    let mapped_keys = [OsCode::KEY_F13, OsCode::KEY_F14].into_iter().collect();
+    assert!(SerialPad::from_cfg(&items, &mapped_keys).unwrap().is_none());
+    items.insert(
+        SERIAL_INPUT_DEVICE_CFG_NAME.to_owned(),
+        "/dev/ttyACM0".to_owned(),
+    );
+    assert!(SerialPad::from_cfg(&items, &mapped_keys).is_err());
+    items.insert(SERIAL_INPUT_KEYS_CFG_NAME.to_owned(), "f13 f15".to_owned());
+    assert!(SerialPad::from_cfg(&items, &mapped_keys).is_err());
+    items.insert(SERIAL_INPUT_KEYS_CFG_NAME.to_owned(), "f13 f14".to_owned());
+    let pad = SerialPad::from_cfg(&items, &mapped_keys).unwrap().unwrap();
+
+    let parse = |line| pad.parse_line(line).map(|e| (e.code, e.value));
+    assert_eq!(parse("+0\r\n"), Some((OsCode::KEY_F13, KeyValue::Press)));
+    assert_eq!(parse("-1\n"), Some((OsCode::KEY_F14, KeyValue::Release)));
+    assert_eq!(parse("+2\n"), None);
+    assert_eq!(parse("0\n"), None);
+    assert_eq!(parse("\n"), None);
+}
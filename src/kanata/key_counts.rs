@@ -0,0 +1,165 @@
+//! Lifetime press counts of the physical keys, for spotting worn out switches.
+//!
+//! Every press of an input key is counted before slow keys and bounce keys filter it. TCP clients
+//! read the counts with `RequestKeyCounts`, together with the presses that bounce keys ignored: a
+//! switch that chatters shows up with more presses than the keys around it and many ignored
+//! presses. The counts are kept in memory since kanata started, or across restarts in the file of
+//! `key-counts-file`, which is written at most once a minute and on shutdown.
+
+use super::*;
+
+use crate::tcp_server::KeyCount;
+use std::collections::BTreeMap;
+
+pub const KEY_COUNTS_FILE_CFG_NAME: &str = "key-counts-file";
+const SAVE_INTERVAL: time::Duration = time::Duration::from_secs(60);
+
+#[derive(Debug)]
+pub struct KeyCounts {
+    path: Option<PathBuf>,
+    /// Presses by key name.
+    presses: BTreeMap<String, u64>,
+    changed: bool,
+    saved_at: time::Instant,
+}
+
+impl KeyCounts {
+    pub fn from_cfg(items: &HashMap<String, String>, now: time::Instant) -> Self {
+        let mut counts = Self {
+            path: None,
+            presses: BTreeMap::new(),
+            changed: false,
+            saved_at: now,
+        };
+        counts.update_from_cfg(items, now);
+        counts
+    }
+
+    /// Switch to the file of the configuration if it changed. The counts of the file are added to
+    /// the counts in memory, so that nothing counted before is lost.
+    pub fn update_from_cfg(&mut self, items: &HashMap<String, String>, now: time::Instant) {
+        let path = items.get(KEY_COUNTS_FILE_CFG_NAME).map(PathBuf::from);
+        if path == self.path {
+            return;
+        }
+        self.save(now);
+        self.path = path;
+        let Some(path) = &self.path else {
+            return;
+        };
+        let saved = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| {
+                serde_json::from_str::<BTreeMap<String, u64>>(&text).map_err(|e| e.to_string())
+            });
+        match saved {
+            Ok(saved) => {
+                for (key, presses) in saved {
+                    *self.presses.entry(key).or_default() += presses;
+                }
+            }
+            Err(e) if path.exists() => {
+                log::warn!("starting new key counts, {} is unreadable: {e}", path.display())
+            }
+            Err(_) => {}
+        }
+        self.changed = true;
+    }
+
+    pub fn press(&mut self, key: OsCode) {
+        *self.presses.entry(format!("{key:?}")).or_default() += 1;
+        self.changed = true;
+    }
+
+    /// The counts of the keys, with the presses that bounce keys ignored.
+    pub fn report(&self, bounces: &HashMap<OsCode, u64>) -> Vec<KeyCount> {
+        let mut bounces = bounces
+            .iter()
+            .map(|(key, count)| (format!("{key:?}"), *count))
+            .collect::<HashMap<_, _>>();
+        self.presses
+            .iter()
+            .map(|(key, presses)| KeyCount {
+                key: key.clone(),
+                presses: *presses,
+                bounces: bounces.remove(key).unwrap_or(0),
+            })
+            .collect()
+    }
+
+    /// Write the file if the counts changed and it was last written long enough ago.
+    pub fn save_if_due(&mut self, now: time::Instant) {
+        if now.duration_since(self.saved_at) >= SAVE_INTERVAL {
+            self.save(now);
+        }
+    }
+
+    /// Write the file if there is one and the counts changed. Written to a temporary file first
+    /// so that the file is never left half written.
+    pub fn save(&mut self, now: time::Instant) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if !self.changed {
+            return;
+        }
+        self.changed = false;
+        self.saved_at = now;
+        let result = serde_json::to_string(&self.presses)
+            .map_err(|e| std::io::Error::other(e.to_string()))
+            .and_then(|text| {
+                let mut tmp = path.clone().into_os_string();
+                tmp.push(".tmp");
+                std::fs::write(&tmp, text)?;
+                std::fs::rename(&tmp, path)
+            });
+        if let Err(e) = result {
+            log::warn!("failed to write key counts {}: {e}", path.display());
+        }
+    }
+}
+
+impl Kanata {
+    /// The press counts of the physical keys, for `ClientMessage::RequestKeyCounts`.
+    pub fn key_count_report(&self) -> Vec<KeyCount> {
+        self.key_counts.report(self.key_filter.bounces())
+    }
+}
+
+#[test]
+fn key_counts_are_kept_in_the_file() {
+    let path = std::env::temp_dir().join(format!("kanata-key-counts-{}.json", std::process::id()));
+    let start = time::Instant::now();
+    let mut counts = KeyCounts::from_cfg(&HashMap::default(), start);
+    counts.press(OsCode::KEY_A);
+    counts.save(start);
+    assert!(!path.exists());
+
+    // Counted before the file is configured, then kept in it.
+    let mut items = HashMap::default();
+    items.insert(
+        KEY_COUNTS_FILE_CFG_NAME.to_owned(),
+        path.to_string_lossy().to_string(),
+    );
+    counts.update_from_cfg(&items, start);
+    counts.press(OsCode::KEY_A);
+    counts.press(OsCode::KEY_B);
+    counts.save_if_due(start + time::Duration::from_secs(1));
+    assert!(!path.exists());
+    counts.save_if_due(start + SAVE_INTERVAL);
+
+    let mut counts = KeyCounts::from_cfg(&items, start);
+    counts.press(OsCode::KEY_B);
+    let mut bounces = HashMap::default();
+    bounces.insert(OsCode::KEY_B, 1);
+    let report = counts
+        .report(&bounces)
+        .into_iter()
+        .map(|c| (c.key, c.presses, c.bounces))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        report,
+        vec![("KEY_A".to_owned(), 2, 0), ("KEY_B".to_owned(), 2, 1)]
+    );
+    std::fs::remove_file(&path).unwrap();
+}
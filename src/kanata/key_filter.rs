@@ -0,0 +1,153 @@
+//! Slow keys and bounce keys, the key filters of X11 AccessX, for people with tremors.
+//!
+//! With slow keys, a press only counts once the key has been held for `slow-keys-delay`
+//! milliseconds; keys released earlier are ignored. With bounce keys, a press of a key within
+//! `bounce-keys-delay` milliseconds after its release is ignored. The release and repeats of an
+//! ignored press are ignored too. Both filters are the `key-filter` event stage, which by default
+//! runs before the layers, IME and swap-hands see the input.
+//!
+//! The presses that bounce keys ignored are counted per key, see `RequestKeyCounts`.
+
+use super::*;
+
+pub const SLOW_KEYS_DELAY_CFG_NAME: &str = "slow-keys-delay";
+pub const BOUNCE_KEYS_DELAY_CFG_NAME: &str = "bounce-keys-delay";
+
+#[derive(Debug, Default)]
+pub struct KeyFilter {
+    /// 0 disables slow keys.
+    slow_keys_delay: u16,
+    /// 0 disables bounce keys.
+    bounce_keys_delay: u16,
+    /// Keys held for less than the slow keys delay and the ticks until their press counts.
+    pending: Vec<(OsCode, u16)>,
+    /// Recently released keys and the ticks until they can be pressed again.
+    released: Vec<(OsCode, u16)>,
+    /// Keys whose press was ignored, so that their release is ignored too.
+    ignored: Vec<OsCode>,
+    /// The presses that bounce keys ignored since kanata started.
+    bounces: HashMap<OsCode, u64>,
+}
+
+impl KeyFilter {
+    /// Read the delays from defcfg, keeping the state of held keys.
+    pub fn update_from_cfg(&mut self, items: &HashMap<String, String>) -> Result<()> {
+        let delay = |name: &str| -> Result<u16> {
+            match items.get(name) {
+                Some(s) => s
+                    .parse::<u16>()
+                    .map_err(|_| anyhow!("{name} must be 0-65535, found {s}")),
+                None => Ok(0),
+            }
+        };
+        self.slow_keys_delay = delay(SLOW_KEYS_DELAY_CFG_NAME)?;
+        self.bounce_keys_delay = delay(BOUNCE_KEYS_DELAY_CFG_NAME)?;
+        Ok(())
+    }
+
+    /// Returns whether the event should be processed now.
+    pub fn accepts(&mut self, event: &KeyEvent) -> bool {
+        let code = event.code;
+        match event.value {
+            KeyValue::Press => {
+                if self.released.iter().any(|(k, _)| *k == code) {
+                    log::debug!("bounce keys: ignoring {code:?}");
+                    self.ignored.push(code);
+                    *self.bounces.entry(code).or_default() += 1;
+                    return false;
+                }
+                if self.slow_keys_delay > 0 {
+                    self.pending.push((code, self.slow_keys_delay));
+                    return false;
+                }
+                true
+            }
+            KeyValue::Release => {
+                if let Some(i) = self.ignored.iter().position(|k| *k == code) {
+                    self.ignored.swap_remove(i);
+                    return false;
+                }
+                if let Some(i) = self.pending.iter().position(|(k, _)| *k == code) {
+                    log::debug!("slow keys: {code:?} released too early");
+                    self.pending.swap_remove(i);
+                    return false;
+                }
+                if self.bounce_keys_delay > 0 {
+                    self.released.push((code, self.bounce_keys_delay));
+                }
+                true
+            }
+            KeyValue::Repeat => {
+                !self.ignored.contains(&code) && !self.pending.iter().any(|(k, _)| *k == code)
+            }
+        }
+    }
+
+    /// Advance the delays by a millisecond. Returns a key that was held for the slow keys delay,
+    /// whose press should be processed now. Other keys that are ready are returned by the next
+    /// ticks.
+    pub fn tick(&mut self) -> Option<OsCode> {
+        self.released.retain_mut(|(_, ticks)| {
+            *ticks -= 1;
+            *ticks > 0
+        });
+        for (_, ticks) in self.pending.iter_mut() {
+            *ticks = ticks.saturating_sub(1);
+        }
+        let i = self.pending.iter().position(|(_, ticks)| *ticks == 0)?;
+        Some(self.pending.remove(i).0)
+    }
+
+    pub fn bounces(&self) -> &HashMap<OsCode, u64> {
+        &self.bounces
+    }
+
+    /// The ticks until the first pending press counts or a released key can be pressed again.
+    pub fn ticks_until_due(&self) -> Option<u16> {
+        self.pending
+            .iter()
+            .chain(self.released.iter())
+            .map(|(_, ticks)| (*ticks).max(1))
+            .min()
+    }
+}
+
+#[test]
+fn slow_keys_and_bounce_keys_filter_presses() {
+    let press = |code| KeyEvent::new(code, KeyValue::Press);
+    let release = |code| KeyEvent::new(code, KeyValue::Release);
+    let mut items = HashMap::default();
+    items.insert(SLOW_KEYS_DELAY_CFG_NAME.into(), "3".into());
+    let mut filter = KeyFilter::default();
+    filter.update_from_cfg(&items).unwrap();
+
+    // A key released before the slow keys delay is ignored.
+    assert!(!filter.accepts(&press(OsCode::KEY_A)));
+    assert_eq!(filter.tick(), None);
+    assert!(!filter.accepts(&release(OsCode::KEY_A)));
+    assert_eq!(filter.ticks_until_due(), None);
+
+    // A key held for the delay is pressed.
+    assert!(!filter.accepts(&press(OsCode::KEY_A)));
+    assert!(!filter.accepts(&KeyEvent::new(OsCode::KEY_A, KeyValue::Repeat)));
+    assert_eq!([filter.tick(), filter.tick()], [None, None]);
+    assert_eq!(filter.tick(), Some(OsCode::KEY_A));
+    assert!(filter.accepts(&release(OsCode::KEY_A)));
+
+    items.insert(SLOW_KEYS_DELAY_CFG_NAME.into(), "0".into());
+    items.insert(BOUNCE_KEYS_DELAY_CFG_NAME.into(), "2".into());
+    filter.update_from_cfg(&items).unwrap();
+    assert!(filter.accepts(&press(OsCode::KEY_B)));
+    assert!(filter.accepts(&release(OsCode::KEY_B)));
+    assert_eq!(filter.ticks_until_due(), Some(2));
+
+    // The bounce and its release are ignored, other keys are not.
+    assert!(!filter.accepts(&press(OsCode::KEY_B)));
+    assert!(filter.accepts(&press(OsCode::KEY_C)));
+    assert!(!filter.accepts(&release(OsCode::KEY_B)));
+    assert_eq!([filter.tick(), filter.tick()], [None, None]);
+    assert!(filter.accepts(&press(OsCode::KEY_B)));
+
+    items.insert(BOUNCE_KEYS_DELAY_CFG_NAME.into(), "-1".into());
+    assert!(filter.update_from_cfg(&items).is_err());
+}
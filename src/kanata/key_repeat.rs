@@ -0,0 +1,168 @@
+//! What kanata does with the autorepeat events of held input keys.
+//!
+//! Keyboards repeat a held key and the OS reads the repeats as events of their own. By default
+//! kanata forwards a repeat as a repeat of the key that the held key outputs, if it outputs one.
+//! `key-repeat` sets this per kind of action of the held key:
+//! - `forward`: the default.
+//! - `regenerate`: release and press the key again, so that its action runs again, e.g. a macro is
+//!   typed once per repeat. Also for programs that ignore repeat events.
+//! - `suppress`: ignore the repeats, e.g. so that the hold action of a tap-hold key is not
+//!   repeated.
+
+use super::*;
+
+pub const KEY_REPEAT_CFG_NAME: &str = "key-repeat";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RepeatPolicy {
+    #[default]
+    Forward,
+    Regenerate,
+    Suppress,
+}
+
+/// The kinds of actions that `key-repeat` sets a policy for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepeatedAction {
+    /// Keys and chords of keys.
+    Key,
+    TapHold,
+    Macro,
+    Other,
+}
+
+impl RepeatedAction {
+    pub fn of(action: &KanataAction) -> Self {
+        match action {
+            Action::KeyCode(_) | Action::MultipleKeyCodes(_) => Self::Key,
+            Action::HoldTap(_) => Self::TapHold,
+            Action::Sequence { .. } | Action::RepeatableSequence { .. } => Self::Macro,
+            // macro-release-cancel and friends add custom actions next to the macro.
+            Action::MultipleActions(actions)
+                if actions.iter().any(|a| Self::of(a) == Self::Macro) =>
+            {
+                Self::Macro
+            }
+            _ => Self::Other,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct KeyRepeat {
+    key: RepeatPolicy,
+    tap_hold: RepeatPolicy,
+    macro_: RepeatPolicy,
+    other: RepeatPolicy,
+}
+
+impl KeyRepeat {
+    /// Read the policies from defcfg, e.g. `key-repeat "tap-hold:suppress macro:regenerate"`.
+    /// Kinds of actions that are not listed are forwarded.
+    pub fn update_from_cfg(&mut self, items: &HashMap<String, String>) -> Result<()> {
+        *self = Self::default();
+        let Some(value) = items.get(KEY_REPEAT_CFG_NAME) else {
+            return Ok(());
+        };
+        for item in value.split_whitespace() {
+            let Some((kind, policy)) = item.split_once(':') else {
+                bail!("{KEY_REPEAT_CFG_NAME} expects items like tap-hold:suppress, found {item}");
+            };
+            let policy = match policy {
+                "forward" => RepeatPolicy::Forward,
+                "regenerate" => RepeatPolicy::Regenerate,
+                "suppress" => RepeatPolicy::Suppress,
+                _ => bail!(
+                    "{KEY_REPEAT_CFG_NAME}: unknown policy {policy}, \
+                     expected forward, regenerate or suppress"
+                ),
+            };
+            match kind {
+                "key" => self.key = policy,
+                "tap-hold" => self.tap_hold = policy,
+                "macro" => self.macro_ = policy,
+                "other" => self.other = policy,
+                _ => bail!(
+                    "{KEY_REPEAT_CFG_NAME}: unknown kind of action {kind}, \
+                     expected key, tap-hold, macro or other"
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn policy(&self, action: RepeatedAction) -> RepeatPolicy {
+        match action {
+            RepeatedAction::Key => self.key,
+            RepeatedAction::TapHold => self.tap_hold,
+            RepeatedAction::Macro => self.macro_,
+            RepeatedAction::Other => self.other,
+        }
+    }
+}
+
+impl Kanata {
+    /// Handle a repeat of the held input key according to `key-repeat`. The action of the key is
+    /// looked up on the current layer, or the default layer if it is transparent there.
+    pub(super) fn handle_repeat_policy(&mut self, event: &KeyEvent) -> Result<()> {
+        let layout = self.layout.b();
+        let key = usize::from(event.code as u16);
+        let mut action = &layout.layers[layout.current_layer()][0][key];
+        if matches!(action, Action::Trans) {
+            action = &layout.layers[layout.default_layer][0][key];
+        }
+        match self.key_repeat.policy(RepeatedAction::of(action)) {
+            RepeatPolicy::Forward => self.handle_repeat(event),
+            RepeatPolicy::Regenerate => {
+                // keyberon processes one event per tick, so the key is pressed again on the tick
+                // after its release.
+                let evc = u16::from(event.code);
+                self.layout.bm().event(Event::Release(0, evc));
+                self.layout.bm().event(Event::Press(0, evc));
+                Ok(())
+            }
+            RepeatPolicy::Suppress => {
+                log::debug!("key-repeat: suppressing repeat of {:?}", event.code);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[test]
+fn key_repeat_policies_are_read_per_kind_of_action() {
+    let mut items = HashMap::default();
+    items.insert(
+        KEY_REPEAT_CFG_NAME.to_owned(),
+        "tap-hold:suppress  macro:regenerate".to_owned(),
+    );
+    let mut key_repeat = KeyRepeat::default();
+    key_repeat.update_from_cfg(&items).unwrap();
+    assert_eq!(
+        key_repeat.policy(RepeatedAction::of(&KanataAction::KeyCode(KeyCode::A))),
+        RepeatPolicy::Forward
+    );
+    assert_eq!(
+        key_repeat.policy(RepeatedAction::TapHold),
+        RepeatPolicy::Suppress
+    );
+    assert_eq!(
+        key_repeat.policy(RepeatedAction::Macro),
+        RepeatPolicy::Regenerate
+    );
+    assert_eq!(
+        key_repeat.policy(RepeatedAction::of(&KanataAction::NoOp)),
+        RepeatPolicy::Forward
+    );
+
+    for bad in ["tap-hold", "tap-hold:drop", "chord:suppress"] {
+        items.insert(KEY_REPEAT_CFG_NAME.to_owned(), bad.to_owned());
+        assert!(key_repeat.update_from_cfg(&items).is_err(), "{bad}");
+    }
+    items.remove(KEY_REPEAT_CFG_NAME);
+    key_repeat.update_from_cfg(&items).unwrap();
+    assert_eq!(
+        key_repeat.policy(RepeatedAction::TapHold),
+        RepeatPolicy::Forward
+    );
+}
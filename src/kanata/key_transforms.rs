@@ -0,0 +1,155 @@
+//! Simple transforms of key events before the layout looks them up and of the keys it outputs,
+//! for normalizations that would otherwise need a layer of their own.
+//!
+//! `key-transform-input` replaces keys before the layout, e.g. `"kprt=ret"` makes the keypad
+//! enter act like enter on every layer. It runs as the `transform` event stage. Both keys must be
+//! in defsrc. `key-transform-output` replaces keys that the layout outputs, after everything
+//! else, e.g. `"rmet=lmet"`. A key replaced by `XX` is dropped: an input event does nothing, and
+//! an output key is never output, e.g. `"rmet=XX"` to strip a modifier.
+
+use super::*;
+
+pub const KEY_TRANSFORM_INPUT_CFG_NAME: &str = "key-transform-input";
+pub const KEY_TRANSFORM_OUTPUT_CFG_NAME: &str = "key-transform-output";
+
+#[derive(Debug, Default)]
+pub struct KeyTransforms {
+    /// Input keys and their replacements, `None` to drop the event.
+    input: Vec<(OsCode, Option<OsCode>)>,
+    /// Output keys and their replacements, `None` to strip the key.
+    output: Vec<(KeyCode, Option<KeyCode>)>,
+}
+
+impl KeyTransforms {
+    pub fn from_cfg(items: &HashMap<String, String>, mapped_keys: &MappedKeys) -> Result<Self> {
+        let input = parse_transforms(items, KEY_TRANSFORM_INPUT_CFG_NAME)?;
+        for key in input
+            .iter()
+            .flat_map(|(from, to)| std::iter::once(*from).chain(*to))
+        {
+            if !mapped_keys.contains(&key) {
+                bail!("{KEY_TRANSFORM_INPUT_CFG_NAME}: {key:?} is not in defsrc");
+            }
+        }
+        let output = parse_transforms(items, KEY_TRANSFORM_OUTPUT_CFG_NAME)?
+            .into_iter()
+            .map(|(from, to)| (KeyCode::from(from), to.map(KeyCode::from)))
+            .collect();
+        Ok(Self { input, output })
+    }
+
+    /// The event for the layout, or `None` if it is dropped.
+    pub fn input(&self, event: &KeyEvent) -> Option<KeyEvent> {
+        match self.input.iter().find(|(from, _)| *from == event.code) {
+            Some((_, to)) => to.map(|code| KeyEvent::new(code, event.value)),
+            None => Some(*event),
+        }
+    }
+
+    /// The key to output instead of the key, or `None` if it is stripped.
+    pub fn output_key(&self, key: KeyCode) -> Option<KeyCode> {
+        match self.output.iter().find(|(from, _)| *from == key) {
+            Some((_, to)) => *to,
+            None => Some(key),
+        }
+    }
+
+    /// Replace and strip the keys that the layout outputs.
+    pub fn transform_output(&self, keys: &mut Vec<KeyCode>) {
+        if self.output.is_empty() {
+            return;
+        }
+        keys.retain_mut(|key| match self.output_key(*key) {
+            Some(to) => {
+                *key = to;
+                true
+            }
+            None => false,
+        });
+        // Two keys may have become the same key, which is output once.
+        let mut i = 0;
+        while i < keys.len() {
+            if keys[..i].contains(&keys[i]) {
+                keys.remove(i);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+/// Parse `from=to` items, where `to` is `XX` to drop the key.
+fn parse_transforms(
+    items: &HashMap<String, String>,
+    cfg_name: &str,
+) -> Result<Vec<(OsCode, Option<OsCode>)>> {
+    let Some(value) = items.get(cfg_name) else {
+        return Ok(vec![]);
+    };
+    let key =
+        |name: &str| str_to_oscode(name).ok_or_else(|| anyhow!("{cfg_name}: unknown key {name}"));
+    let mut transforms = vec![];
+    for item in value.split_whitespace() {
+        let Some((from, to)) = item.split_once('=') else {
+            bail!("{cfg_name} expects items like kprt=ret, found {item}");
+        };
+        let from = key(from)?;
+        if transforms.iter().any(|(f, _)| *f == from) {
+            bail!("{cfg_name}: {from:?} is transformed twice");
+        }
+        let to = match to {
+            "XX" => None,
+            to => Some(key(to)?),
+        };
+        transforms.push((from, to));
+    }
+    Ok(transforms)
+}
+
+#[test]
+fn key_transforms_replace_and_drop_keys() {
+    let mapped_keys = [OsCode::KEY_KPENTER, OsCode::KEY_ENTER, OsCode::KEY_A]
+        .into_iter()
+        .collect();
+    let mut items = HashMap::default();
+    items.insert(
+        KEY_TRANSFORM_INPUT_CFG_NAME.to_owned(),
+        "kprt=ret a=XX".to_owned(),
+    );
+    items.insert(
+        KEY_TRANSFORM_OUTPUT_CFG_NAME.to_owned(),
+        "rmet=XX rsft=lsft".to_owned(),
+    );
+    let transforms = KeyTransforms::from_cfg(&items, &mapped_keys).unwrap();
+
+    let input = |code| {
+        transforms
+            .input(&KeyEvent::new(code, KeyValue::Release))
+            .map(|e| (e.code, e.value))
+    };
+    assert_eq!(
+        input(OsCode::KEY_KPENTER),
+        Some((OsCode::KEY_ENTER, KeyValue::Release))
+    );
+    assert_eq!(input(OsCode::KEY_A), None);
+    assert_eq!(
+        input(OsCode::KEY_ENTER),
+        Some((OsCode::KEY_ENTER, KeyValue::Release))
+    );
+
+    let mut keys = vec![KeyCode::RGui, KeyCode::LShift, KeyCode::RShift, KeyCode::B];
+    transforms.transform_output(&mut keys);
+    assert_eq!(keys, [KeyCode::LShift, KeyCode::B]);
+    assert_eq!(
+        transforms.output_key(KeyCode::RShift),
+        Some(KeyCode::LShift)
+    );
+
+    for bad in ["kprt", "kprt=nokey", "kprt=ret kprt=a", "kprt=b"] {
+        items.insert(KEY_TRANSFORM_INPUT_CFG_NAME.to_owned(), bad.to_owned());
+        assert!(
+            KeyTransforms::from_cfg(&items, &mapped_keys).is_err(),
+            "{bad}"
+        );
+    }
+}
@@ -0,0 +1,186 @@
+//! Kiosk mode: only the keys and effects of a whitelist are output, so that kanata can restrict
+//! the input of shared or public machines.
+//!
+//! `kiosk-allow` lists the output keys that may be pressed, e.g. `"a b c spc bspc ret"`, and the
+//! effects that may be activated, by the names of `KIOSK_EFFECTS`. Everything else is dropped and
+//! logged. With `kiosk-layers`, kiosk mode only applies while one of the layers is the current
+//! layer, so that one configuration can hold a restricted profile and an unrestricted one.
+//!
+//! Kiosk mode applies to the output of the layout. Keys that are not in defsrc bypass the layout
+//! unless `process-unmapped-keys` is enabled, and text of snippets and shortcodes is typed as
+//! configured.
+
+use super::*;
+
+pub const KIOSK_ALLOW_CFG_NAME: &str = "kiosk-allow";
+pub const KIOSK_LAYERS_CFG_NAME: &str = "kiosk-layers";
+
+/// The effects that `kiosk-allow` may list.
+pub const KIOSK_EFFECTS: &[&str] = &[
+    "mouse",
+    "unicode",
+    "cmd",
+    "clipboard",
+    "arbitrary-code",
+    "live-reload",
+    "passthrough",
+    "kvm",
+];
+
+#[derive(Debug, Default)]
+pub struct Kiosk {
+    /// Whether `kiosk-allow` is configured; an empty whitelist drops all output.
+    enabled: bool,
+    keys: Vec<OsCode>,
+    effects: Vec<&'static str>,
+    /// The keyberon layers where kiosk mode applies, or all layers if empty.
+    layers: Vec<usize>,
+}
+
+impl Kiosk {
+    pub fn from_cfg(items: &HashMap<String, String>, layer_info: &[LayerInfo]) -> Result<Self> {
+        let mut kiosk = Self::default();
+        let Some(allow) = items.get(KIOSK_ALLOW_CFG_NAME) else {
+            if items.contains_key(KIOSK_LAYERS_CFG_NAME) {
+                bail!("{KIOSK_LAYERS_CFG_NAME} requires {KIOSK_ALLOW_CFG_NAME}");
+            }
+            return Ok(kiosk);
+        };
+        kiosk.enabled = true;
+        for name in allow.split_whitespace() {
+            if let Some(effect) = KIOSK_EFFECTS.iter().find(|e| **e == name) {
+                kiosk.effects.push(*effect);
+            } else if let Some(osc) = str_to_oscode(name) {
+                kiosk.keys.push(osc);
+            } else {
+                bail!(
+                    "{KIOSK_ALLOW_CFG_NAME}: {name} is neither a key nor an effect. \
+                     Effects are: {}",
+                    KIOSK_EFFECTS.join(", ")
+                );
+            }
+        }
+        for name in items
+            .get(KIOSK_LAYERS_CFG_NAME)
+            .map(String::as_str)
+            .unwrap_or_default()
+            .split_whitespace()
+        {
+            let len = kiosk.layers.len();
+            kiosk.layers.extend(
+                layer_info
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, l)| l.name == name)
+                    .map(|(i, _)| i),
+            );
+            if kiosk.layers.len() == len {
+                bail!("{KIOSK_LAYERS_CFG_NAME} contains unknown layer: {name}");
+            }
+        }
+        let process_unmapped_keys = items
+            .get("process-unmapped-keys")
+            .is_some_and(|s| matches!(s.to_lowercase().as_str(), "true" | "yes"));
+        if !process_unmapped_keys {
+            log::warn!(
+                "{KIOSK_ALLOW_CFG_NAME}: keys that are not in defsrc are output unchanged \
+                 unless process-unmapped-keys is enabled"
+            );
+        }
+        Ok(kiosk)
+    }
+
+    fn applies(&self, layer: usize) -> bool {
+        self.enabled && (self.layers.is_empty() || self.layers.contains(&layer))
+    }
+
+    /// Whether the key may be pressed on the output.
+    pub fn allows_key(&self, key: OsCode, layer: usize) -> bool {
+        if !self.applies(layer) || self.keys.contains(&key) {
+            return true;
+        }
+        log::warn!("kiosk: dropped {key:?}");
+        false
+    }
+
+    /// Whether the action may be activated. Actions that only change the state of kanata, e.g.
+    /// layer changes, are always allowed.
+    pub fn allows_action(&self, action: &CustomAction, layer: usize) -> bool {
+        match effect(action) {
+            Some(effect) if self.applies(layer) && !self.effects.contains(&effect) => {
+                log::warn!("kiosk: dropped {effect} action {action:?}");
+                false
+            }
+            _ => true,
+        }
+    }
+}
+
+/// The name in `KIOSK_EFFECTS` of the effect that the action has outside of kanata.
+fn effect(action: &CustomAction) -> Option<&'static str> {
+    match action {
+        CustomAction::Mouse(_)
+        | CustomAction::MouseTap(_)
+        | CustomAction::MWheel { .. }
+        | CustomAction::MoveMouse { .. }
+        | CustomAction::MoveMouseAccel { .. }
+        | CustomAction::SetMouse { .. }
+        | CustomAction::MouseGrid
+        | CustomAction::MouseDrag(_)
+        | CustomAction::MouseDrop
+        | CustomAction::DwellClick(_) => Some("mouse"),
+        CustomAction::Unicode(_) | CustomAction::UnicodeInput(_) => Some("unicode"),
+        CustomAction::Cmd(_) | CustomAction::CmdOutputKeys(_) => Some("cmd"),
+        CustomAction::ClipboardPaste { .. } => Some("clipboard"),
+        CustomAction::SendArbitraryCode(_) => Some("arbitrary-code"),
+        CustomAction::LiveReload | CustomAction::LiveReloadNext | CustomAction::LiveReloadPrev => {
+            Some("live-reload")
+        }
+        CustomAction::Passthrough => Some("passthrough"),
+        CustomAction::KvmTarget(_) | CustomAction::RouteOutput(_) => Some("kvm"),
+        _ => None,
+    }
+}
+
+#[test]
+fn kiosk_drops_keys_and_effects_outside_the_whitelist() {
+    let layer_info = ["base", "base", "public", "public"]
+        .iter()
+        .map(|name| LayerInfo {
+            name: name.to_string(),
+            cfg_text: String::new(),
+            sounds: None,
+            tags: vec![],
+            notify: vec![],
+            doc: LayerDoc::default(),
+        })
+        .collect::<Vec<_>>();
+    let mut items = HashMap::default();
+    assert!(Kiosk::from_cfg(&items, &layer_info)
+        .unwrap()
+        .allows_key(OsCode::KEY_F1, 0));
+
+    items.insert(KIOSK_ALLOW_CFG_NAME.to_owned(), "a spc mouse".to_owned());
+    items.insert(KIOSK_LAYERS_CFG_NAME.to_owned(), "public".to_owned());
+    let kiosk = Kiosk::from_cfg(&items, &layer_info).unwrap();
+    assert!(kiosk.allows_key(OsCode::KEY_A, 2));
+    assert!(kiosk.allows_key(OsCode::KEY_SPACE, 3));
+    assert!(!kiosk.allows_key(OsCode::KEY_LEFTMETA, 2));
+    assert!(kiosk.allows_key(OsCode::KEY_LEFTMETA, 0));
+    assert!(kiosk.allows_action(&CustomAction::MouseDrop, 2));
+    assert!(kiosk.allows_action(&CustomAction::LayerLock, 2));
+    assert!(!kiosk.allows_action(&CustomAction::Cmd(vec![]), 2));
+    assert!(!kiosk.allows_action(&CustomAction::LiveReload, 3));
+    assert!(kiosk.allows_action(&CustomAction::LiveReload, 1));
+
+    for (name, bad) in [
+        (KIOSK_ALLOW_CFG_NAME, "a nokey"),
+        (KIOSK_LAYERS_CFG_NAME, "admin"),
+    ] {
+        let mut items = items.clone();
+        items.insert(name.to_owned(), bad.to_owned());
+        assert!(Kiosk::from_cfg(&items, &layer_info).is_err(), "{bad}");
+    }
+    items.remove(KIOSK_ALLOW_CFG_NAME);
+    assert!(Kiosk::from_cfg(&items, &layer_info).is_err());
+}
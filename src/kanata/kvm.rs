@@ -0,0 +1,298 @@
+//! Network KVM: the output of kanata typed on other machines that run kanata.
+//!
+//! The machine with the keyboard lists the other machines in `kvm-targets`, e.g.
+//! `kvm-targets "laptop=192.168.1.20:7070 desk=desk.lan:7070"`. `(kvm-target laptop)` sends the
+//! output to one of them, `(kvm-target next)` to the next one in the list and `(kvm-target local)`
+//! back to this machine. The layers of this machine stay in effect whichever machine is the
+//! target, since only the keys that they output are sent. The targets are also destinations of
+//! the output router, so layers can send their output to them by default, see
+//! `output-layer-targets`. Sending the output to another machine is only supported on Linux.
+//!
+//! The other machines listen with `kvm-listen`, e.g. `kvm-listen 0.0.0.0:7070`, and output the
+//! keys that they receive as they are, like the `OutputKeys` of TCP clients. When the connection
+//! is lost, they release the keys that are still held.
+//!
+//! Both sides need the same `kvm-key`, which is best kept in the `defsecrets` file, and the same
+//! certificate in `kvm-tls-cert`, whose private key `kvm-tls-key` is only needed by listeners. The
+//! connection is TLS, and the sender only accepts a listener that presents this certificate. Then
+//! the listener sends a random challenge that the sender answers with its HMAC-SHA256 under the
+//! key, so the key itself is never sent. The network code is in `kvm_net` and needs the `kvm`
+//! cargo feature. Only keys are sent; mouse events are dropped.
+
+use super::*;
+
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub const KVM_TARGETS_CFG_NAME: &str = "kvm-targets";
+pub const KVM_LISTEN_CFG_NAME: &str = "kvm-listen";
+pub const KVM_KEY_CFG_NAME: &str = "kvm-key";
+pub const KVM_TLS_CERT_CFG_NAME: &str = "kvm-tls-cert";
+pub const KVM_TLS_KEY_CFG_NAME: &str = "kvm-tls-key";
+
+/// The machines to send the output to and the listener for the output of other machines.
+pub struct Kvm {
+    targets: Vec<KvmTarget>,
+    listen: Option<SocketAddr>,
+    key: Arc<str>,
+    /// The certificate and private key files, compared on a live reload.
+    tls_files: (Option<String>, Option<String>),
+    #[cfg(feature = "kvm")]
+    tls: Option<Arc<KvmTls>>,
+    started: bool,
+    stop: Arc<AtomicBool>,
+}
+
+struct KvmTarget {
+    name: String,
+    addr: SocketAddr,
+    /// Sends key events to the thread that is connected to the target, once it was started.
+    #[cfg(all(target_os = "linux", feature = "kvm"))]
+    tx: Option<Sender<Vec<KeyEvent>>>,
+}
+
+impl Drop for Kvm {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Kvm {
+    pub fn from_cfg(items: &HashMap<String, String>) -> Result<Self> {
+        let mut targets = vec![];
+        for item in items
+            .get(KVM_TARGETS_CFG_NAME)
+            .map(String::as_str)
+            .unwrap_or_default()
+            .split_whitespace()
+        {
+            let Some((name, addr)) = item.split_once('=') else {
+                bail!(
+                    "{KVM_TARGETS_CFG_NAME} expects items like laptop=192.168.1.20:7070, \
+                     found {item}"
+                );
+            };
+            if RESERVED_TARGET_NAMES.contains(&name)
+                || name == "next"
+                || targets.iter().any(|t: &KvmTarget| t.name == name)
+            {
+                bail!("{KVM_TARGETS_CFG_NAME}: the name {name} is reserved or used twice");
+            }
+            targets.push(KvmTarget {
+                name: name.to_owned(),
+                addr: socket_addr(KVM_TARGETS_CFG_NAME, addr)?,
+                #[cfg(all(target_os = "linux", feature = "kvm"))]
+                tx: None,
+            });
+        }
+        #[cfg(not(target_os = "linux"))]
+        if !targets.is_empty() {
+            bail!("{KVM_TARGETS_CFG_NAME} is only supported on Linux");
+        }
+        let listen = match items.get(KVM_LISTEN_CFG_NAME) {
+            Some(addr) => Some(socket_addr(KVM_LISTEN_CFG_NAME, addr)?),
+            None => None,
+        };
+        let key = items
+            .get(KVM_KEY_CFG_NAME)
+            .map(String::as_str)
+            .unwrap_or_default();
+        let networked = !targets.is_empty() || listen.is_some();
+        #[cfg(not(feature = "kvm"))]
+        if networked {
+            bail!(
+                "{KVM_TARGETS_CFG_NAME} and {KVM_LISTEN_CFG_NAME} are specified but kanata was \
+                 compiled without the kvm feature"
+            );
+        }
+        if networked && key.len() < 16 {
+            bail!(
+                "{KVM_TARGETS_CFG_NAME} and {KVM_LISTEN_CFG_NAME} require a {KVM_KEY_CFG_NAME} \
+                 of at least 16 characters"
+            );
+        }
+        let tls_files = (
+            items.get(KVM_TLS_CERT_CFG_NAME).cloned(),
+            items.get(KVM_TLS_KEY_CFG_NAME).cloned(),
+        );
+        #[cfg(feature = "kvm")]
+        let tls = match networked {
+            true => Some(Arc::new(KvmTls::from_files(
+                tls_files.0.as_deref(),
+                tls_files.1.as_deref().filter(|_| listen.is_some()),
+            )?)),
+            false => None,
+        };
+        Ok(Self {
+            targets,
+            listen,
+            key: key.into(),
+            tls_files,
+            #[cfg(feature = "kvm")]
+            tls,
+            started: false,
+            stop: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Whether the new configuration sends to and listens on the same addresses with the same
+    /// key and certificate, so that this can be kept on a live reload along with its connections.
+    pub fn same_as(&self, other: &Self) -> bool {
+        let targets = |kvm: &Self| {
+            kvm.targets
+                .iter()
+                .map(|t| (t.name.clone(), t.addr))
+                .collect::<Vec<_>>()
+        };
+        targets(self) == targets(other)
+            && self.listen == other.listen
+            && self.key == other.key
+            && self.tls_files == other.tls_files
+    }
+
+    /// Start listening for other machines, if `kvm-listen` is configured. The keys that they
+    /// send are output through the processing loop.
+    pub fn start(&mut self, _tx: &Sender<ProcessingEvent>) {
+        if std::mem::replace(&mut self.started, true) {
+            return;
+        }
+        #[cfg(feature = "kvm")]
+        if let (Some(addr), Some(tls)) = (self.listen, &self.tls) {
+            let (tx, key, tls, stop) = (
+                _tx.clone(),
+                self.key.clone(),
+                tls.clone(),
+                self.stop.clone(),
+            );
+            std::thread::spawn(move || {
+                if let Err(e) = listen(addr, &tx, &key, &tls, &stop) {
+                    log::error!("kvm: could not listen on {addr}: {e}");
+                }
+            });
+        }
+    }
+
+    pub fn has_target(&self, name: &str) -> bool {
+        self.targets.iter().any(|t| t.name == name)
+    }
+
+    /// The target after the given one, or this machine after the last target.
+    pub fn next_target(&self, name: &str) -> &str {
+        let next = match self.targets.iter().position(|t| t.name == name) {
+            Some(i) => i + 1,
+            None => 0,
+        };
+        self.targets.get(next).map_or("local", |t| &t.name)
+    }
+
+    /// The output for the target, which is connected to when keys are first sent to it.
+    #[cfg(all(target_os = "linux", feature = "kvm"))]
+    pub fn sink(&mut self, name: &str) -> Option<Box<dyn OutputSink>> {
+        let target = self.targets.iter_mut().find(|t| t.name == name)?;
+        let tls = self.tls.clone()?;
+        let tx = target.tx.get_or_insert_with(|| {
+            let (tx, rx) = std::sync::mpsc::channel();
+            let (name, addr, key) = (target.name.clone(), target.addr, self.key.clone());
+            std::thread::spawn(move || send_to_target(&name, addr, &key, &tls, rx));
+            tx
+        });
+        Some(Box::new(KvmOutput { tx: tx.clone() }))
+    }
+
+    /// There are no targets without the `kvm` feature.
+    #[cfg(all(target_os = "linux", not(feature = "kvm")))]
+    pub fn sink(&mut self, _name: &str) -> Option<Box<dyn OutputSink>> {
+        None
+    }
+}
+
+impl Kanata {
+    /// Send the output to another machine, or back to this one.
+    #[cfg(target_os = "linux")]
+    pub(super) fn switch_kvm_target(&mut self, switch: &KvmSwitch) {
+        let target = match switch {
+            KvmSwitch::Local => "local",
+            KvmSwitch::Next => self.kvm.next_target(self.output_router.active()),
+            KvmSwitch::Target(name) => name,
+        };
+        if target != "local" && !self.kvm.has_target(target) {
+            log::warn!("kvm-target: {target} is not in {KVM_TARGETS_CFG_NAME}");
+            return;
+        }
+        let target = target.to_owned();
+        if let Err(e) = self.output_router.choose(Some(target), &self.kvm) {
+            log::warn!("kvm-target: {e}");
+        }
+    }
+
+    /// Replace the KVM configuration on a live reload. Unless the configuration is the same, the
+    /// held keys are released and the output is routed again, to the new connections.
+    pub(super) fn replace_kvm(&mut self, kvm: Kvm) {
+        if kvm.same_as(&self.kvm) {
+            return;
+        }
+        #[cfg(target_os = "linux")]
+        {
+            self.release_routed_keys();
+            self.kbd_out.set_route(OutputRoute::Device);
+        }
+        self.output_router.reroute();
+        self.kvm = kvm;
+        if let Some(tx) = &self.processing_tx {
+            self.kvm.start(tx);
+        }
+    }
+}
+
+fn socket_addr(cfg_name: &str, addr: &str) -> Result<SocketAddr> {
+    addr.to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| anyhow!("{cfg_name}: invalid address {addr}"))
+}
+
+#[test]
+fn kvm_cfg_targets_and_listener() {
+    let mut items = HashMap::default();
+    let kvm = Kvm::from_cfg(&items).unwrap();
+    assert_eq!(kvm.next_target("local"), "local");
+    items.insert(KVM_LISTEN_CFG_NAME.to_owned(), "127.0.0.1:7070".to_owned());
+    assert!(Kvm::from_cfg(&items).is_err());
+    items.insert(KVM_KEY_CFG_NAME.to_owned(), "0123456789abcdef".to_owned());
+    // The network needs the feature, and the certificate with it.
+    assert!(Kvm::from_cfg(&items).is_err());
+    #[cfg(feature = "kvm")]
+    {
+        items.insert(
+            KVM_TLS_CERT_CFG_NAME.to_owned(),
+            "./test_cfgs/kvm-test.crt".to_owned(),
+        );
+        items.insert(
+            KVM_TLS_KEY_CFG_NAME.to_owned(),
+            "./test_cfgs/kvm-test.key".to_owned(),
+        );
+        let kvm = Kvm::from_cfg(&items).unwrap();
+        assert!(kvm.same_as(&Kvm::from_cfg(&items).unwrap()));
+        #[cfg(target_os = "linux")]
+        {
+            items.insert(
+                KVM_TARGETS_CFG_NAME.to_owned(),
+                "laptop=127.0.0.1:7071".to_owned(),
+            );
+            let with_target = Kvm::from_cfg(&items).unwrap();
+            assert!(!kvm.same_as(&with_target));
+            assert!(with_target.has_target("laptop"));
+            assert_eq!(with_target.next_target("local"), "laptop");
+            assert_eq!(with_target.next_target("laptop"), "local");
+            for bad in [
+                "laptop",
+                "next=127.0.0.1:7071",
+                "secondary=127.0.0.1:7071",
+                "a=127.0.0.1:1 a=127.0.0.1:2",
+            ] {
+                items.insert(KVM_TARGETS_CFG_NAME.to_owned(), bad.to_owned());
+                assert!(Kvm::from_cfg(&items).is_err(), "{bad}");
+            }
+        }
+    }
+}
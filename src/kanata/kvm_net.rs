@@ -0,0 +1,487 @@
+//! The connections of the network KVM, see [`kvm`](super::kvm), with the `kvm` cargo feature.
+//!
+//! The connection is TLS 1.3 with `rustls`. All machines share one certificate: listeners present
+//! it with its private key and senders only accept a listener that presents exactly this
+//! certificate, so no certificate authority is involved. Inside the connection, the listener
+//! sends a challenge from the random source of the OS and the sender answers with the
+//! HMAC-SHA256 of the challenge under `kvm-key`, which the listener checks in constant time. Then
+//! the sender writes one line per key event.
+
+use super::*;
+
+use hmac::{Hmac, Mac};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{
+    ClientConfig, ClientConnection, DigitallySignedStruct, ServerConfig, ServerConnection,
+    SignatureScheme, StreamOwned,
+};
+use sha2::Sha256;
+
+use std::io::{BufRead, BufReader};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const GREETING: &str = "kanata-kvm 2";
+/// The name that senders ask for. Listeners are recognized by their certificate instead.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+const SERVER_NAME: &str = "kanata-kvm";
+const CONNECT_TIMEOUT: time::Duration = time::Duration::from_secs(1);
+/// How long a listener waits between checks for new connections.
+const ACCEPT_INTERVAL: time::Duration = time::Duration::from_millis(100);
+
+/// The TLS configurations of both sides.
+#[derive(Debug)]
+pub struct KvmTls {
+    /// Targets are only supported on Linux.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    client: Arc<ClientConfig>,
+    /// Only listeners have the private key.
+    server: Option<Arc<ServerConfig>>,
+}
+
+impl KvmTls {
+    /// Load the certificate, and the private key if this machine listens.
+    pub fn from_files(cert: Option<&str>, key: Option<&str>) -> Result<Self> {
+        let open = |name: &str, path: &str| {
+            std::fs::File::open(path)
+                .map(BufReader::new)
+                .map_err(|e| anyhow!("{name}: cannot open {path}: {e}"))
+        };
+        let cert_path = cert.ok_or_else(|| {
+            anyhow!(
+                "{KVM_TARGETS_CFG_NAME} and {KVM_LISTEN_CFG_NAME} require a \
+                 {KVM_TLS_CERT_CFG_NAME}"
+            )
+        })?;
+        let cert = rustls_pemfile::certs(&mut open(KVM_TLS_CERT_CFG_NAME, cert_path)?)
+            .next()
+            .ok_or_else(|| anyhow!("{KVM_TLS_CERT_CFG_NAME}: {cert_path} has no certificate"))?
+            .map_err(|e| anyhow!("{KVM_TLS_CERT_CFG_NAME}: cannot read {cert_path}: {e}"))?;
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let client = ClientConfig::builder_with_provider(provider.clone())
+            .with_protocol_versions(&[&rustls::version::TLS13])?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(PinnedCert {
+                cert: cert.clone(),
+                provider: provider.clone(),
+            }))
+            .with_no_client_auth();
+        let server = match key {
+            Some(key_path) => {
+                let key = rustls_pemfile::private_key(&mut open(KVM_TLS_KEY_CFG_NAME, key_path)?)
+                    .map_err(|e| anyhow!("{KVM_TLS_KEY_CFG_NAME}: cannot read {key_path}: {e}"))?
+                    .ok_or_else(|| {
+                        anyhow!("{KVM_TLS_KEY_CFG_NAME}: {key_path} has no private key")
+                    })?;
+                let server = ServerConfig::builder_with_provider(provider)
+                    .with_protocol_versions(&[&rustls::version::TLS13])?
+                    .with_no_client_auth()
+                    .with_single_cert(vec![cert], key)
+                    .map_err(|e| {
+                        anyhow!(
+                            "{KVM_TLS_KEY_CFG_NAME} does not match {KVM_TLS_CERT_CFG_NAME}: {e}"
+                        )
+                    })?;
+                Some(Arc::new(server))
+            }
+            None => None,
+        };
+        Ok(Self {
+            client: Arc::new(client),
+            server,
+        })
+    }
+}
+
+/// Accepts only the listener that presents the configured certificate.
+#[derive(Debug)]
+struct PinnedCert {
+    cert: CertificateDer<'static>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedCert {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        match end_entity.as_ref() == self.cert.as_ref() {
+            true => Ok(ServerCertVerified::assertion()),
+            false => Err(rustls::Error::InvalidCertificate(
+                rustls::CertificateError::UnknownIssuer,
+            )),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// The output of kanata while another machine is the target. Only key events are sent.
+#[cfg(target_os = "linux")]
+pub(super) struct KvmOutput {
+    pub(super) tx: Sender<Vec<KeyEvent>>,
+}
+
+#[cfg(target_os = "linux")]
+impl OutputSink for KvmOutput {
+    fn emit(&mut self, events: &[evdev::InputEvent]) -> Result<(), std::io::Error> {
+        // The other machine repeats held keys itself.
+        let events = events
+            .iter()
+            .filter_map(|ev| KeyEvent::try_from(*ev).ok())
+            .filter(|ev| ev.value != KeyValue::Repeat)
+            .collect::<Vec<_>>();
+        if !events.is_empty() {
+            // The thread only stops when this is dropped.
+            let _ = self.tx.send(events);
+        }
+        Ok(())
+    }
+
+    fn capabilities(&self) -> OutputCapabilities {
+        OutputCapabilities::default()
+    }
+}
+
+/// Send the key events to the target until the senders are dropped. The connection is made when
+/// there are events to send; events that cannot be sent are dropped.
+#[cfg(target_os = "linux")]
+pub(super) fn send_to_target(
+    name: &str,
+    addr: SocketAddr,
+    key: &str,
+    tls: &KvmTls,
+    rx: Receiver<Vec<KeyEvent>>,
+) {
+    let mut stream = None;
+    let mut warned = false;
+    for events in rx {
+        if stream.is_none() {
+            match connect(addr, key, tls) {
+                Ok(s) => {
+                    log::info!("kvm: connected to {name} at {addr}");
+                    stream = Some(s);
+                    warned = false;
+                }
+                Err(e) => {
+                    if !warned {
+                        log::warn!(
+                            "kvm: could not connect to {name} at {addr}, dropping its keys: {e}"
+                        );
+                        warned = true;
+                    }
+                    continue;
+                }
+            }
+        }
+        let lines = events.iter().map(event_line).collect::<String>();
+        if let Some(s) = &mut stream {
+            if let Err(e) = s.write_all(lines.as_bytes()).and_then(|_| s.flush()) {
+                log::warn!("kvm: lost the connection to {name}: {e}");
+                stream = None;
+            }
+        }
+    }
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn connect(
+    addr: SocketAddr,
+    key: &str,
+    tls: &KvmTls,
+) -> Result<StreamOwned<ClientConnection, TcpStream>> {
+    let sock = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+    sock.set_nodelay(true)?;
+    sock.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+    let conn = ClientConnection::new(tls.client.clone(), ServerName::try_from(SERVER_NAME)?)?;
+    let mut reader = BufReader::new(StreamOwned::new(conn, sock));
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(|e| {
+        anyhow!("{e}, check that {KVM_TLS_CERT_CFG_NAME} is the same on both machines")
+    })?;
+    let Some(challenge) = line.trim_end().strip_prefix(GREETING).map(str::trim) else {
+        bail!("{addr} is not a kanata kvm-listen address of this version");
+    };
+    let stream = reader.get_mut();
+    writeln!(stream, "{}", response(key, challenge))?;
+    stream.flush()?;
+    line.clear();
+    // A listener that does not accept the key closes the connection.
+    if reader.read_line(&mut line).is_err() || line.trim_end() != "ok" {
+        bail!(
+            "the key was not accepted, check that {KVM_KEY_CFG_NAME} is the same on both machines"
+        );
+    }
+    reader.get_ref().sock.set_read_timeout(None)?;
+    Ok(reader.into_inner())
+}
+
+pub(super) fn listen(
+    addr: SocketAddr,
+    tx: &Sender<ProcessingEvent>,
+    key: &Arc<str>,
+    tls: &Arc<KvmTls>,
+    stop: &Arc<AtomicBool>,
+) -> Result<()> {
+    // The listener of the previous configuration may hold the address for a moment after a live
+    // reload.
+    let mut attempts = 0;
+    let listener = loop {
+        match TcpListener::bind(addr) {
+            Ok(listener) => break listener,
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse && attempts < 10 => {
+                attempts += 1;
+                std::thread::sleep(ACCEPT_INTERVAL);
+            }
+            Err(e) => return Err(e.into()),
+        }
+    };
+    // Accepting without blocking lets the listener stop when the configuration changes.
+    listener.set_nonblocking(true)?;
+    log::info!("kvm: listening on {addr}");
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, peer)) => {
+                if let Err(e) = stream.set_nonblocking(false) {
+                    log::warn!("kvm: failed to set up the connection of {peer}: {e}");
+                    continue;
+                }
+                let (tx, key, tls, stop) = (tx.clone(), key.clone(), tls.clone(), stop.clone());
+                std::thread::spawn(move || match receive(stream, &tx, &key, &tls, &stop) {
+                    Ok(()) => log::info!("kvm: {peer} disconnected"),
+                    Err(e) => log::warn!("kvm: {peer} disconnected: {e}"),
+                });
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(ACCEPT_INTERVAL)
+            }
+            Err(e) => log::warn!("kvm: failed to accept a connection: {e}"),
+        }
+    }
+    Ok(())
+}
+
+/// Authenticate the sender, then output its keys until it disconnects.
+fn receive(
+    sock: TcpStream,
+    tx: &Sender<ProcessingEvent>,
+    key: &str,
+    tls: &KvmTls,
+    stop: &AtomicBool,
+) -> Result<()> {
+    let Some(server) = tls.server.clone() else {
+        bail!("{KVM_LISTEN_CFG_NAME} requires a {KVM_TLS_KEY_CFG_NAME}");
+    };
+    sock.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+    let peer = sock.peer_addr()?;
+    let mut reader = BufReader::new(StreamOwned::new(ServerConnection::new(server)?, sock));
+    let challenge = nonce()?;
+    let stream = reader.get_mut();
+    writeln!(stream, "{GREETING} {challenge}")?;
+    stream.flush()?;
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !verify(key, &challenge, line.trim_end()) {
+        bail!("the key was not accepted");
+    }
+    let stream = reader.get_mut();
+    writeln!(stream, "ok")?;
+    stream.flush()?;
+    reader.get_ref().sock.set_read_timeout(None)?;
+    log::info!("kvm: receiving keys from {peer}");
+
+    let mut held: Vec<OsCode> = vec![];
+    let result = loop {
+        line.clear();
+        match reader.read_line(&mut line) {
+            Ok(0) => break Ok(()),
+            Ok(_) => {}
+            Err(e) => break Err(e.into()),
+        }
+        if stop.load(Ordering::Relaxed) {
+            break Ok(());
+        }
+        let Some(event) = parse_event_line(&line) else {
+            break Err(anyhow!("invalid line {:?}", line.trim_end()));
+        };
+        match event.value {
+            KeyValue::Release => held.retain(|k| *k != event.code),
+            _ if !held.contains(&event.code) => held.push(event.code),
+            _ => {}
+        }
+        let events = vec![event];
+        if tx
+            .send(ProcessingEvent::Command(KanataCommand::OutputKeys {
+                events,
+            }))
+            .is_err()
+        {
+            return Ok(());
+        }
+    };
+    if !held.is_empty() {
+        let events = held
+            .into_iter()
+            .map(|code| KeyEvent::new(code, KeyValue::Release))
+            .collect();
+        let _ = tx.send(ProcessingEvent::Command(KanataCommand::OutputKeys {
+            events,
+        }));
+    }
+    result
+}
+
+/// A key event on the wire: `+` for a press or `-` for a release, followed by the key code.
+#[cfg(target_os = "linux")]
+fn event_line(event: &KeyEvent) -> String {
+    let sign = match event.value {
+        KeyValue::Release => '-',
+        _ => '+',
+    };
+    format!("{sign}{}\n", event.code as u16)
+}
+
+fn parse_event_line(line: &str) -> Option<KeyEvent> {
+    let line = line.trim_end();
+    let (value, code) = if let Some(code) = line.strip_prefix('+') {
+        (KeyValue::Press, code)
+    } else {
+        (KeyValue::Release, line.strip_prefix('-')?)
+    };
+    // The codes are the same on every platform.
+    let code = *OsCode::ALL.get(code.parse::<usize>().ok()?)?;
+    Some(KeyEvent::new(code, value))
+}
+
+/// A random challenge from the random source of the OS.
+fn nonce() -> Result<String> {
+    let mut bytes = [0; 16];
+    getrandom::getrandom(&mut bytes).map_err(|e| anyhow!("no random challenge: {e}"))?;
+    Ok(hex(&bytes))
+}
+
+fn challenge_mac(key: &str, challenge: &str) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(challenge.as_bytes());
+    mac
+}
+
+/// The answer of the sender to the challenge.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn response(key: &str, challenge: &str) -> String {
+    hex(&challenge_mac(key, challenge).finalize().into_bytes())
+}
+
+/// Whether the answer to the challenge was made with the key.
+fn verify(key: &str, challenge: &str, response: &str) -> bool {
+    let bytes = (0..response.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(response.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>();
+    bytes.is_some_and(|bytes| challenge_mac(key, challenge).verify_slice(&bytes).is_ok())
+}
+
+#[test]
+fn kvm_key_lines_over_authenticated_tls() {
+    #[cfg(target_os = "linux")]
+    assert_eq!(
+        event_line(&KeyEvent::new(OsCode::KEY_A, KeyValue::Press)),
+        "+30\n"
+    );
+    let parsed = parse_event_line("-30\n").unwrap();
+    assert_eq!(
+        (parsed.code, parsed.value),
+        (OsCode::KEY_A, KeyValue::Release)
+    );
+    assert!(parse_event_line("30\n").is_none());
+    assert!(parse_event_line("+99999\n").is_none());
+
+    // RFC 4231, test case 2.
+    assert_eq!(
+        response("Jefe", "what do ya want for nothing?"),
+        "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+    assert!(verify("Jefe", "abc", &response("Jefe", "abc")));
+    assert!(!verify("Jefe", "abc", &response("jefe", "abc")));
+    assert!(!verify("Jefe", "abc", "5bdc"));
+    assert_ne!(nonce().unwrap(), nonce().unwrap());
+
+    let cert = "./test_cfgs/kvm-test.crt";
+    assert!(KvmTls::from_files(None, None).is_err());
+    assert!(KvmTls::from_files(Some("./test_cfgs/kvm-test.key"), None).is_err());
+    let tls = KvmTls::from_files(Some(cert), Some("./test_cfgs/kvm-test.key")).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let key = "0123456789abcdef";
+    let (tx, rx) = std::sync::mpsc::channel();
+    let receiver = std::thread::spawn(move || {
+        let stop = AtomicBool::new(false);
+        for _ in 0..2 {
+            let (sock, _) = listener.accept().unwrap();
+            let _ = receive(sock, &tx, key, &tls, &stop);
+        }
+    });
+    let sender = KvmTls::from_files(Some(cert), None).unwrap();
+    let e = connect(addr, "fedcba9876543210", &sender).unwrap_err();
+    assert!(e.to_string().contains("not accepted"), "{e}");
+    let mut stream = connect(addr, key, &sender).unwrap();
+    stream.write_all(b"+30\n").unwrap();
+    stream.flush().unwrap();
+    drop(stream);
+    receiver.join().unwrap();
+    let events = rx
+        .iter()
+        .flat_map(|event| match event {
+            ProcessingEvent::Command(KanataCommand::OutputKeys { events }) => events,
+            _ => panic!("unexpected event"),
+        })
+        .map(|event| (event.code, event.value))
+        .collect::<Vec<_>>();
+    // The key that is still held when the sender disconnects is released.
+    assert_eq!(
+        events,
+        [
+            (OsCode::KEY_A, KeyValue::Press),
+            (OsCode::KEY_A, KeyValue::Release)
+        ]
+    );
+}
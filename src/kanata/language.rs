@@ -0,0 +1,190 @@
+//! Language switch keys for input methods, e.g. for Korean or Japanese.
+//!
+//! Keyboards of these languages have keys that switch the input method, and layouts differ in
+//! which of them exist: a Korean keyboard has `hngl` and `hnja` where others have right alt and
+//! right control. `defmodtranslation` makes a layer output such keys instead of modifiers, so
+//! that actions, including macros, output the key that the active layout expects.
+//!
+//! With `language-layers`, the last language switch key that kanata output activates a layer, as
+//! if a key held it with `layer-while-held`, e.g. to use symbols that suit the active input
+//! method. Kanata only knows the keys that it output, not the state of the input method.
+
+use super::*;
+
+pub const LANGUAGE_LAYERS_CFG_NAME: &str = "language-layers";
+
+/// The coordinate of the layer activated by the last language switch key. No key is at this
+/// coordinate, so only another language switch key or a force unlock releases the layer.
+pub const LANGUAGE_LAYER_COORD: (u8, u16) = (u8::MAX, u16::MAX - 3);
+
+/// The keys that switch the input method. Outputting one of them releases the layer of the
+/// previous one.
+const LANGUAGE_KEYS: &[OsCode] = &[
+    OsCode::KEY_HANGEUL,
+    OsCode::KEY_HANJA,
+    OsCode::KEY_HENKAN,
+    OsCode::KEY_MUHENKAN,
+    OsCode::KEY_KATAKANAHIRAGANA,
+    OsCode::KEY_KATAKANA,
+    OsCode::KEY_HIRAGANA,
+    OsCode::KEY_ZENKAKUHANKAKU,
+];
+
+#[derive(Debug, Default)]
+pub struct LanguageKeys {
+    /// The output modifiers of keyberon layers and the keys that replace them.
+    translations: Vec<(usize, Vec<(KeyCode, KeyCode)>)>,
+    /// The held keys that were translated when they were pressed. They stay translated until
+    /// they are released, even if the layer changes.
+    held: Vec<(KeyCode, KeyCode)>,
+    /// The language switch keys and the keyberon layers that they activate.
+    layers: Vec<(OsCode, usize)>,
+}
+
+impl LanguageKeys {
+    pub fn new(
+        translations: &[(usize, Vec<(OsCode, OsCode)>)],
+        items: &HashMap<String, String>,
+        layer_info: &[LayerInfo],
+    ) -> Result<Self> {
+        let translations = translations
+            .iter()
+            .map(|(layer, pairs)| {
+                let pairs = pairs
+                    .iter()
+                    .map(|(from, to)| (KeyCode::from(*from), KeyCode::from(*to)))
+                    .collect();
+                (*layer, pairs)
+            })
+            .collect();
+        let mut layers = vec![];
+        let pairs = items
+            .get(LANGUAGE_LAYERS_CFG_NAME)
+            .map(|s| s.split_whitespace().collect::<Vec<_>>())
+            .unwrap_or_default();
+        if pairs.len() % 2 != 0 {
+            bail!(
+                "{LANGUAGE_LAYERS_CFG_NAME} expects pairs of a language switch key and a layer"
+            );
+        }
+        for pair in pairs.chunks(2) {
+            let key = match str_to_oscode(pair[0]) {
+                Some(key) if LANGUAGE_KEYS.contains(&key) => key,
+                _ => bail!(
+                    "{LANGUAGE_LAYERS_CFG_NAME}: {} is not a language switch key, expected one \
+                     of: hngl hnja henk mhnk kana katakana hiragana zkhk",
+                    pair[0]
+                ),
+            };
+            // The second version of each layer is the one activated by layer-while-held.
+            let layer = layer_info
+                .iter()
+                .enumerate()
+                .position(|(i, l)| i % 2 == 1 && l.name == pair[1])
+                .ok_or_else(|| {
+                    anyhow!("{LANGUAGE_LAYERS_CFG_NAME} is an unknown layer: {}", pair[1])
+                })?;
+            layers.push((key, layer));
+        }
+        Ok(Self {
+            translations,
+            held: vec![],
+            layers,
+        })
+    }
+
+    /// Replace the output modifiers that the layer translates. Keys that are newly pressed are
+    /// translated by the layer; held keys keep the translation they were pressed with.
+    pub fn translate(&mut self, keys: &mut [KeyCode], layer: usize) {
+        self.held.retain(|(from, _)| keys.contains(from));
+        let layer_translations = self
+            .translations
+            .iter()
+            .find(|(l, _)| *l == layer)
+            .map(|(_, pairs)| pairs.as_slice())
+            .unwrap_or_default();
+        for key in keys.iter_mut() {
+            if let Some((_, to)) = self.held.iter().find(|(from, _)| from == key) {
+                *key = *to;
+            } else if let Some((from, to)) = layer_translations.iter().find(|(f, _)| f == key) {
+                self.held.push((*from, *to));
+                *key = *to;
+            }
+        }
+    }
+
+    /// A key was output. Returns the layer to activate if it is a language switch key, which is
+    /// `Some(None)` if it has no layer.
+    pub fn output(&self, key: OsCode) -> Option<Option<usize>> {
+        if !LANGUAGE_KEYS.contains(&key) || self.layers.is_empty() {
+            return None;
+        }
+        Some(
+            self.layers
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, layer)| *layer),
+        )
+    }
+}
+
+/// Activate the layer of the language switch key that was output, releasing the layer of the
+/// previous one.
+pub fn set_language_layer(layout: &mut BorrowedKLayout, layer: Option<usize>) {
+    layout.states.retain(
+        |s| !matches!(s, State::LayerModifier { coord, .. } if *coord == LANGUAGE_LAYER_COORD),
+    );
+    if let Some(layer) = layer {
+        log::debug!("language switch key output, activating layer {layer}");
+        let _ = layout.states.push(State::LayerModifier {
+            value: layer,
+            coord: LANGUAGE_LAYER_COORD,
+        });
+    }
+}
+
+#[test]
+fn language_keys_translate_and_activate_layers() {
+    let layer_info = ["base", "base", "korean", "korean", "hanja", "hanja"]
+        .iter()
+        .map(|name| LayerInfo {
+            name: name.to_string(),
+            cfg_text: String::new(),
+            sounds: None,
+            tags: vec![],
+            notify: vec![],
+            doc: LayerDoc::default(),
+        })
+        .collect::<Vec<_>>();
+    let translations = [
+        (2, vec![(OsCode::KEY_RIGHTALT, OsCode::KEY_HANGEUL)]),
+        (3, vec![(OsCode::KEY_RIGHTALT, OsCode::KEY_HANGEUL)]),
+    ];
+    let mut items = HashMap::default();
+    items.insert(LANGUAGE_LAYERS_CFG_NAME.into(), "hnja hanja".into());
+    let mut lang = LanguageKeys::new(&translations, &items, &layer_info).unwrap();
+
+    let mut keys = [KeyCode::RAlt, KeyCode::A];
+    lang.translate(&mut keys, 0);
+    assert_eq!(keys, [KeyCode::RAlt, KeyCode::A]);
+    let mut keys = [KeyCode::RAlt];
+    lang.translate(&mut keys, 3);
+    assert_eq!(keys, [KeyCode::Lang1]);
+    // The held key stays translated after the layer changes.
+    let mut keys = [KeyCode::RAlt];
+    lang.translate(&mut keys, 0);
+    assert_eq!(keys, [KeyCode::Lang1]);
+    lang.translate(&mut [], 0);
+    let mut keys = [KeyCode::RAlt];
+    lang.translate(&mut keys, 0);
+    assert_eq!(keys, [KeyCode::RAlt]);
+
+    assert_eq!(lang.output(OsCode::KEY_HANJA), Some(Some(5)));
+    assert_eq!(lang.output(OsCode::KEY_HANGEUL), Some(None));
+    assert_eq!(lang.output(OsCode::KEY_A), None);
+
+    items.insert(LANGUAGE_LAYERS_CFG_NAME.into(), "a korean".into());
+    assert!(LanguageKeys::new(&translations, &items, &layer_info).is_err());
+    items.insert(LANGUAGE_LAYERS_CFG_NAME.into(), "hngl".into());
+    assert!(LanguageKeys::new(&translations, &items, &layer_info).is_err());
+}
@@ -0,0 +1,157 @@
+//! Measurement of the latency that kanata adds to key events, enabled with `--measure-latency`.
+//!
+//! The latency of a key event is the time from when kanata read it from the input device until
+//! its output was written: immediately for keys that are not mapped, and at the end of the tick
+//! that processed it for mapped keys. Outputs that are delayed on purpose, e.g. by the timeout of
+//! a `tap-hold`, are not part of the latency. The percentiles over the whole run are logged
+//! periodically.
+
+use super::*;
+
+use std::time::{Duration, Instant};
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+pub struct LatencyMeter {
+    /// Read times of the key events sent to the processing loop, in the order they were sent.
+    pending: VecDeque<Instant>,
+    /// Read time of the key event that the processing loop is handling.
+    processing: Option<Instant>,
+    /// Latencies in microseconds.
+    samples: Vec<u32>,
+    last_report: Instant,
+    samples_at_last_report: usize,
+}
+
+impl Default for LatencyMeter {
+    fn default() -> Self {
+        Self {
+            pending: VecDeque::new(),
+            processing: None,
+            samples: vec![],
+            last_report: Instant::now(),
+            samples_at_last_report: 0,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct LatencySummary {
+    pub count: usize,
+    pub p50: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl std::fmt::Display for LatencySummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "added latency over {} key events: p50 {}us, p99 {}us, max {}us",
+            self.count,
+            self.p50.as_micros(),
+            self.p99.as_micros(),
+            self.max.as_micros()
+        )
+    }
+}
+
+impl LatencyMeter {
+    /// A key event that was read at `read_at` was sent to the processing loop.
+    pub fn event_sent(&mut self, read_at: Instant) {
+        self.pending.push_back(read_at);
+    }
+
+    /// The processing loop received the next key event.
+    pub fn event_received(&mut self) {
+        self.processing = self.pending.pop_front();
+    }
+
+    /// The processing loop finished the tick that handled the received key event.
+    pub fn event_processed(&mut self) {
+        if let Some(read_at) = self.processing.take() {
+            self.record(read_at);
+        }
+    }
+
+    /// The output of a key event that was read at `read_at` was written.
+    pub fn record(&mut self, read_at: Instant) {
+        self.add_sample(read_at.elapsed());
+        if self.last_report.elapsed() >= REPORT_INTERVAL
+            && self.samples.len() > self.samples_at_last_report
+        {
+            self.last_report = Instant::now();
+            self.samples_at_last_report = self.samples.len();
+            if let Some(summary) = self.summary() {
+                log::info!("{summary}");
+            }
+        }
+    }
+
+    fn add_sample(&mut self, latency: Duration) {
+        self.samples
+            .push(u32::try_from(latency.as_micros()).unwrap_or(u32::MAX));
+    }
+
+    pub fn summary(&self) -> Option<LatencySummary> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted = self.samples.clone();
+        sorted.sort_unstable();
+        let percentile = |p: usize| {
+            let i = (sorted.len() * p).div_ceil(100).saturating_sub(1);
+            Duration::from_micros(u64::from(sorted[i]))
+        };
+        Some(LatencySummary {
+            count: sorted.len(),
+            p50: percentile(50),
+            p99: percentile(99),
+            max: percentile(100),
+        })
+    }
+}
+
+impl Kanata {
+    pub fn latency_event_received(&mut self) {
+        if let Some(meter) = &self.latency_meter {
+            meter.lock().event_received();
+        }
+    }
+
+    pub fn latency_event_processed(&mut self) {
+        if let Some(meter) = &self.latency_meter {
+            meter.lock().event_processed();
+        }
+    }
+}
+
+#[test]
+fn latency_summary_percentiles() {
+    let mut meter = LatencyMeter::default();
+    assert_eq!(meter.summary(), None);
+    for us in (1..=100).rev() {
+        meter.add_sample(Duration::from_micros(us));
+    }
+    assert_eq!(
+        meter.summary(),
+        Some(LatencySummary {
+            count: 100,
+            p50: Duration::from_micros(50),
+            p99: Duration::from_micros(99),
+            max: Duration::from_micros(100),
+        })
+    );
+
+    // Key events are matched to their read times in the order they were sent.
+    let mut meter = LatencyMeter::default();
+    let read_at = Instant::now();
+    meter.event_sent(read_at);
+    meter.event_sent(read_at + Duration::from_secs(1));
+    meter.event_received();
+    assert_eq!(meter.processing, Some(read_at));
+    meter.event_processed();
+    meter.event_processed();
+    assert_eq!(meter.samples.len(), 1);
+    assert_eq!(meter.pending.len(), 1);
+}
@@ -0,0 +1,159 @@
+//! The launcher, a variant of sequence mode that runs entries by name.
+//!
+//! After the `launcher` action, the keys output by the layout are captured and typed letters,
+//! digits and dashes are collected. The collected input is fuzzy matched against the names of the
+//! `deflauncher` entries and enter runs the best match by tapping its fake key. Backspace removes
+//! a character, while escape or the sequence timeout cancel the launcher.
+
+use crate::tcp_server::ServerMessage;
+use kanata_keyberon::key_code::KeyCode;
+
+#[derive(Debug)]
+pub struct LauncherState {
+    pub input: String,
+    pub ticks_until_timeout: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LauncherNext {
+    Active,
+    /// Run the entry with this index.
+    Run(usize),
+    Cancel,
+}
+
+impl LauncherState {
+    pub fn new(timeout: u16) -> Self {
+        Self {
+            input: String::new(),
+            ticks_until_timeout: timeout,
+        }
+    }
+
+    /// The launcher counts down its timeout every tick.
+    pub fn ticks_until_due(&self) -> Option<u16> {
+        Some(1)
+    }
+
+    /// The notification of the current input and its best match.
+    pub fn message<'a>(&self, names: impl Iterator<Item = &'a str> + Clone) -> ServerMessage {
+        ServerMessage::Launcher {
+            input: self.input.clone(),
+            best_match: best_match(&self.input, names.clone())
+                .and_then(|idx| names.clone().nth(idx))
+                .map(str::to_owned),
+            active: true,
+        }
+    }
+
+    /// Handle a key pressed while the launcher is active.
+    pub fn key<'a>(&mut self, key: KeyCode, names: impl Iterator<Item = &'a str>) -> LauncherNext {
+        use KeyCode::*;
+        match key {
+            Enter | KpEnter => {
+                return match best_match(&self.input, names) {
+                    Some(idx) => LauncherNext::Run(idx),
+                    None => LauncherNext::Cancel,
+                }
+            }
+            Escape => return LauncherNext::Cancel,
+            BSpace => {
+                self.input.pop();
+            }
+            _ => {
+                if let Some(c) = key_char(key) {
+                    self.input.push(c);
+                }
+            }
+        }
+        LauncherNext::Active
+    }
+}
+
+fn key_char(key: KeyCode) -> Option<char> {
+    use KeyCode::*;
+    let c = match key {
+        Minus | KpMinus => '-',
+        Kb0 | Kp0 => '0',
+        Kb1 | Kp1 => '1',
+        Kb2 | Kp2 => '2',
+        Kb3 | Kp3 => '3',
+        Kb4 | Kp4 => '4',
+        Kb5 | Kp5 => '5',
+        Kb6 | Kp6 => '6',
+        Kb7 | Kp7 => '7',
+        Kb8 | Kp8 => '8',
+        Kb9 | Kp9 => '9',
+        _ => {
+            let name = format!("{key:?}");
+            match name.as_bytes() {
+                [c] if c.is_ascii_uppercase() => c.to_ascii_lowercase() as char,
+                _ => return None,
+            }
+        }
+    };
+    Some(c)
+}
+
+/// Returns the index of the name that matches the input best, preferring earlier names on ties.
+pub fn best_match<'a>(input: &str, names: impl Iterator<Item = &'a str>) -> Option<usize> {
+    if input.is_empty() {
+        return None;
+    }
+    names
+        .enumerate()
+        .filter_map(|(i, name)| fuzzy_score(input, name).map(|score| (i, score)))
+        .min_by_key(|&(i, score)| (std::cmp::Reverse(score), i))
+        .map(|(i, _)| i)
+}
+
+/// Score how well the name matches the input, if the input characters appear in the name in
+/// order. Consecutive characters and characters at the start of words score higher, and longer
+/// names score a little lower.
+fn fuzzy_score(input: &str, name: &str) -> Option<i32> {
+    let name = name.to_lowercase();
+    let name = name.as_bytes();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut prev_match = None;
+    for c in input.bytes() {
+        let found = pos + name[pos..].iter().position(|&n| n == c)?;
+        score += 1;
+        if prev_match.is_some_and(|p| p + 1 == found) {
+            score += 3;
+        }
+        if found == 0 || matches!(name[found - 1], b'-' | b'_' | b' ') {
+            score += 2;
+        }
+        prev_match = Some(found);
+        pos = found + 1;
+    }
+    Some(score * 8 - name.len() as i32)
+}
+
+#[test]
+fn launcher_fuzzy_matches_names() {
+    let names = ["firefox", "files", "terminal", "fire-alarm"];
+    let best = |input| best_match(input, names.iter().copied());
+    assert_eq!(best("fi"), Some(1));
+    assert_eq!(best("ff"), Some(0));
+    assert_eq!(best("fa"), Some(3));
+    assert_eq!(best("trm"), Some(2));
+    assert_eq!(best("xyz"), None);
+    assert_eq!(best(""), None);
+
+    use KeyCode::*;
+    let mut state = LauncherState::new(1000);
+    for key in [T, LShift, E, Kb9, BSpace, R] {
+        assert_eq!(state.key(key, names.iter().copied()), LauncherNext::Active);
+    }
+    assert_eq!(state.input, "ter");
+    assert_eq!(
+        state.key(Enter, names.iter().copied()),
+        LauncherNext::Run(2)
+    );
+    assert_eq!(
+        state.key(Escape, names.iter().copied()),
+        LauncherNext::Cancel
+    );
+}
@@ -0,0 +1,255 @@
+//! Layer changes written to a keyboard with a display, e.g. an OLED on a QMK keyboard.
+//!
+//! When `layer-display-device` is configured, kanata writes a frame with the active layer to the
+//! device whenever the layer changes, so that firmware can show the host-side layer. The device
+//! is a serial port, e.g. `/dev/ttyACM0` or `\\.\COM3`, or a raw HID device such as `/dev/hidraw3`
+//! for QMK's raw HID feature. It is written from a separate thread so that a slow or missing
+//! device never delays key processing; it is opened again on the next layer change if writing
+//! fails, e.g. because the keyboard was unplugged.
+//!
+//! A frame is the bytes `K` `L`, the keyberon layer index, the length of the layer name and the
+//! name, cut to [`MAX_NAME_LEN`] bytes. For raw HID, the frame is padded with zeros to a
+//! [`RAW_HID_REPORT_LEN`] byte report and prefixed with report ID 0.
+//!
+//! With `layer-display-sync`, the sync goes both ways for raw HID: the firmware sends a report
+//! with the same frame when its layer changes, and kanata changes its default layer like the
+//! `ChangeLayer` TCP message does. The layer is found by name, or by index if the name is empty.
+//! The reports are read in another thread, which is started once the processing loop exists, see
+//! [`Kanata::set_processing_tx`]. Neither side sends anything if the layer does not change, so
+//! the two do not echo each other's changes forever.
+
+use super::*;
+
+use std::fs::OpenOptions;
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub const LAYER_DISPLAY_DEVICE_CFG_NAME: &str = "layer-display-device";
+pub const LAYER_DISPLAY_FORMAT_CFG_NAME: &str = "layer-display-format";
+pub const LAYER_DISPLAY_SYNC_CFG_NAME: &str = "layer-display-sync";
+
+const FRAME_MAGIC: [u8; 2] = *b"KL";
+/// The size of the reports of QMK's raw HID feature.
+const RAW_HID_REPORT_LEN: usize = 32;
+/// The longest name that fits into a raw HID report after the frame header.
+const MAX_NAME_LEN: usize = RAW_HID_REPORT_LEN - 4;
+/// How long to wait before opening the device for reading again, e.g. while it is unplugged.
+const REOPEN_DELAY: time::Duration = time::Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameFormat {
+    Serial,
+    RawHid,
+}
+
+pub struct LayerDisplay {
+    tx: Sender<Vec<u8>>,
+    format: FrameFormat,
+    device: String,
+    /// Whether layer changes of the firmware are read from the device.
+    sync: bool,
+    /// Stops the thread that reads from the device, when this is dropped.
+    stop: Arc<AtomicBool>,
+}
+
+impl Drop for LayerDisplay {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+impl LayerDisplay {
+    /// Read the device from defcfg and start the thread that writes to it. Returns `None` if no
+    /// device is configured.
+    pub fn from_cfg(items: &HashMap<String, String>) -> Result<Option<Self>> {
+        let device = match items.get(LAYER_DISPLAY_DEVICE_CFG_NAME) {
+            Some(device) => device.clone(),
+            None => return Ok(None),
+        };
+        let format = match items.get(LAYER_DISPLAY_FORMAT_CFG_NAME).map(String::as_str) {
+            None | Some("serial") => FrameFormat::Serial,
+            Some("raw-hid") => FrameFormat::RawHid,
+            Some(other) => {
+                bail!("{LAYER_DISPLAY_FORMAT_CFG_NAME} must be serial or raw-hid, found {other}")
+            }
+        };
+        let sync = items
+            .get(LAYER_DISPLAY_SYNC_CFG_NAME)
+            .is_some_and(|s| matches!(s.to_lowercase().as_str(), "yes" | "true"));
+        if sync && format != FrameFormat::RawHid {
+            bail!("{LAYER_DISPLAY_SYNC_CFG_NAME} requires {LAYER_DISPLAY_FORMAT_CFG_NAME} raw-hid");
+        }
+        let (tx, rx) = std::sync::mpsc::channel();
+        let writer_device = device.clone();
+        std::thread::spawn(move || run_writer(&writer_device, rx));
+        Ok(Some(Self {
+            tx,
+            format,
+            device,
+            sync,
+            stop: Arc::new(AtomicBool::new(false)),
+        }))
+    }
+
+    /// Start reading the layer changes of the firmware if `layer-display-sync` is enabled. They
+    /// are sent to the processing loop through `tx`.
+    pub fn start_sync(&self, tx: Sender<ProcessingEvent>, layer_info: &[LayerInfo]) {
+        if !self.sync {
+            return;
+        }
+        let device = self.device.clone();
+        let layers = layer_info.iter().map(|l| l.name.clone()).collect();
+        let stop = self.stop.clone();
+        std::thread::spawn(move || run_reader(&device, layers, tx, &stop));
+    }
+
+    /// Write the frame of the layer to the device.
+    pub fn layer_changed(&self, layer: usize, layer_info: &[LayerInfo]) {
+        let name = layer_info.get(layer).map_or("", |l| l.name.as_str());
+        // The thread only stops if this is dropped.
+        let _ = self.tx.send(frame(self.format, layer, name));
+    }
+}
+
+fn run_writer(device: &str, rx: Receiver<Vec<u8>>) {
+    let mut file: Option<std::fs::File> = None;
+    while let Ok(frame) = rx.recv() {
+        // Only the most recent layer matters if several changed in the meantime.
+        let frame = rx.try_iter().last().unwrap_or(frame);
+        if file.is_none() {
+            match OpenOptions::new().write(true).open(device) {
+                Ok(f) => file = Some(f),
+                Err(e) => {
+                    log::warn!("could not open the layer display device {device}: {e}");
+                    continue;
+                }
+            }
+        }
+        if let Some(Err(e)) = file.as_mut().map(|f| f.write_all(&frame)) {
+            log::warn!("failed to write to the layer display device {device}: {e}");
+            file = None;
+        }
+    }
+}
+
+fn run_reader(device: &str, layers: Vec<String>, tx: Sender<ProcessingEvent>, stop: &AtomicBool) {
+    let mut warned = false;
+    let mut report = [0; RAW_HID_REPORT_LEN];
+    while !stop.load(Ordering::Relaxed) {
+        let mut file = match std::fs::File::open(device) {
+            Ok(file) => {
+                warned = false;
+                file
+            }
+            Err(e) => {
+                if !warned {
+                    log::warn!("could not open the layer display device {device} to sync: {e}");
+                    warned = true;
+                }
+                std::thread::sleep(REOPEN_DELAY);
+                continue;
+            }
+        };
+        loop {
+            let len = match file.read(&mut report) {
+                Ok(0) => break,
+                Ok(len) => len,
+                Err(e) => {
+                    log::warn!("failed to read from the layer display device {device}: {e}");
+                    break;
+                }
+            };
+            if stop.load(Ordering::Relaxed) {
+                return;
+            }
+            let Some(name) = parse_frame(&report[..len], &layers) else {
+                continue;
+            };
+            log::debug!("layer display device changed the layer to {name}");
+            if tx
+                .send(ProcessingEvent::Command(KanataCommand::ChangeLayer {
+                    name,
+                }))
+                .is_err()
+            {
+                return;
+            }
+        }
+        std::thread::sleep(REOPEN_DELAY);
+    }
+}
+
+/// Returns the layer of a frame sent by the firmware: the layer with the name in the frame, or
+/// with the index if the name is empty.
+fn parse_frame(report: &[u8], layers: &[String]) -> Option<String> {
+    let [b'K', b'L', index, len, rest @ ..] = report else {
+        return None;
+    };
+    let name = rest.get(..usize::from(*len))?;
+    if name.is_empty() {
+        return layers.get(usize::from(*index)).cloned();
+    }
+    let name = std::str::from_utf8(name).ok()?;
+    match layers.iter().find(|l| l.as_str() == name) {
+        Some(layer) => Some(layer.clone()),
+        None => {
+            log::warn!("layer display device sent an unknown layer: {name}");
+            None
+        }
+    }
+}
+
+fn frame(format: FrameFormat, layer: usize, name: &str) -> Vec<u8> {
+    let mut len = name.len().min(MAX_NAME_LEN);
+    while !name.is_char_boundary(len) {
+        len -= 1;
+    }
+    let mut frame = vec![];
+    if format == FrameFormat::RawHid {
+        frame.push(0); // report ID
+    }
+    frame.extend(FRAME_MAGIC);
+    frame.push(u8::try_from(layer).unwrap_or(u8::MAX));
+    frame.push(len as u8);
+    frame.extend(&name.as_bytes()[..len]);
+    if format == FrameFormat::RawHid {
+        frame.resize(1 + RAW_HID_REPORT_LEN, 0);
+    }
+    frame
+}
+
+#[test]
+fn layer_display_frames() {
+    assert_eq!(frame(FrameFormat::Serial, 2, "nav"), b"KL\x02\x03nav");
+    let report = frame(FrameFormat::RawHid, 1, "base");
+    assert_eq!(report.len(), 33);
+    assert_eq!(&report[..9], b"\0KL\x01\x04base");
+    assert!(report[9..].iter().all(|b| *b == 0));
+    // Long names are cut at a character boundary to fit into a report.
+    let name = format!("{}é", "a".repeat(27));
+    let report = frame(FrameFormat::RawHid, 0, &name);
+    assert_eq!(report.len(), 33);
+    assert_eq!(report[4], 27);
+    assert_eq!(&report[5..32], "a".repeat(27).as_bytes());
+}
+
+#[test]
+fn layer_display_sync_frames() {
+    let layers = ["base", "nav"].map(String::from);
+    // Reports read from hidraw have no report ID.
+    let report = frame(FrameFormat::RawHid, 1, "nav");
+    assert_eq!(parse_frame(&report[1..], &layers), Some("nav".into()));
+    assert_eq!(parse_frame(b"KL\x01\x00", &layers), Some("nav".into()));
+    assert_eq!(parse_frame(b"KL\x00\x04base", &layers), Some("base".into()));
+    // The name takes precedence over the index.
+    assert_eq!(parse_frame(b"KL\x07\x03nav", &layers), Some("nav".into()));
+    for report in [
+        &b"KL\x05\x00"[..],
+        b"KL\x00\x03sym",
+        b"KL\x00\x09nav",
+        b"XL\x00\x00",
+        b"KL",
+    ] {
+        assert_eq!(parse_frame(report, &layers), None, "{report:?}");
+    }
+}
@@ -0,0 +1,110 @@
+//! Logging of the active layer stack, for debugging how layers and profiles interact.
+//!
+//! With `log-layer-stack yes`, every change of the layer stack is logged as a single line such as
+//! `layer stack: base(58) > nav(12) > num(20)`, where the number is the count of keys the layer
+//! overrides, i.e. maps to something other than `_`. Changes are throttled so that rapidly
+//! toggling layers does not flood the log; only the latest stack is logged.
+
+use super::*;
+
+use kanata_keyberon::action::Action;
+
+use crate::layers::KEYS_IN_ROW;
+
+pub const LOG_LAYER_STACK_CFG_NAME: &str = "log-layer-stack";
+const THROTTLE_MS: u16 = 250;
+
+#[derive(Debug, Default)]
+pub struct LayerStackLog {
+    enabled: bool,
+    /// The keyberon layer indexes of the last logged stack.
+    logged: Vec<usize>,
+    ms_since_log: u16,
+}
+
+impl LayerStackLog {
+    pub fn update_from_cfg(&mut self, items: &HashMap<String, String>) {
+        self.enabled = items
+            .get(LOG_LAYER_STACK_CFG_NAME)
+            .is_some_and(|s| matches!(s.to_lowercase().as_str(), "yes" | "true"));
+        self.logged.clear();
+        self.ms_since_log = THROTTLE_MS;
+    }
+
+    /// Log the layer stack if it changed and the last log was long enough ago.
+    pub fn tick(&mut self, layout: &BorrowedKLayout, layer_info: &[LayerInfo]) {
+        if !self.enabled {
+            return;
+        }
+        self.ms_since_log = self.ms_since_log.saturating_add(1);
+        if self.ms_since_log < THROTTLE_MS || layout.active_layers().eq(self.logged.iter().copied())
+        {
+            return;
+        }
+        self.logged.clear();
+        self.logged.extend(layout.active_layers());
+        self.ms_since_log = 0;
+        log::info!(
+            "layer stack: {}",
+            render_layer_stack(&self.logged, layer_info, layout.layers)
+        );
+    }
+
+    /// Whether there is no pending change of the layer stack to log.
+    pub fn is_idle(&self, layout: &BorrowedKLayout) -> bool {
+        !self.enabled || layout.active_layers().eq(self.logged.iter().copied())
+    }
+
+    /// The ticks until a pending change of the layer stack is logged.
+    pub fn ticks_until_due(&self, layout: &BorrowedKLayout) -> Option<u16> {
+        match self.is_idle(layout) {
+            true => None,
+            false => Some(THROTTLE_MS.saturating_sub(self.ms_since_log).max(1)),
+        }
+    }
+}
+
+fn render_layer_stack<T>(
+    stack: &[usize],
+    layer_info: &[LayerInfo],
+    layers: &[[[Action<T>; KEYS_IN_ROW]; 2]],
+) -> String {
+    stack
+        .iter()
+        .map(|&idx| {
+            // The odd keyberon layer keeps `_` as transparent; the even one has it replaced with
+            // the defsrc actions.
+            let overridden = layers[idx | 1][0]
+                .iter()
+                .filter(|action| !matches!(action, Action::Trans))
+                .count();
+            format!("{}({overridden})", layer_info[idx].name)
+        })
+        .collect::<Vec<_>>()
+        .join(" > ")
+}
+
+#[test]
+fn render_layer_stack_counts_overridden_keys() {
+    let mut layers = crate::layers::new_layers();
+    for (i, action) in layers[1][0].iter_mut().take(5).enumerate() {
+        *action = KanataAction::KeyCode(KeyCode::from(OsCode::from_u16(i as u16).unwrap()));
+    }
+    layers[3][0][30] = KanataAction::NoOp;
+    let layer_info = ["base", "base", "nav", "nav"]
+        .iter()
+        .map(|name| LayerInfo {
+            name: name.to_string(),
+            cfg_text: String::new(),
+            sounds: None,
+            tags: vec![],
+            notify: vec![],
+            doc: LayerDoc::default(),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        render_layer_stack(&[0, 3], &layer_info, &layers[..]),
+        "base(5) > nav(1)"
+    );
+    assert_eq!(render_layer_stack(&[2], &layer_info, &layers[..]), "nav(1)");
+}
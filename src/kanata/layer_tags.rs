@@ -0,0 +1,155 @@
+//! Turning off groups of layers by their `deflayertags` tags.
+//!
+//! While a tag is turned off, the layers with the tag cannot be activated: `layer-while-held` and
+//! similar actions for them are undone after the tick that activated them, and `layer-switch` to
+//! them reverts to the previous default layer. Tags are turned off and on by TCP clients and stay
+//! off across live reloads.
+
+use super::*;
+
+#[derive(Debug, Default)]
+pub struct LayerTags {
+    /// The tags that are turned off.
+    disabled_tags: HashSet<String>,
+    /// Whether each keyberon layer is turned off by one of its tags.
+    disabled_layers: Vec<bool>,
+    /// The most recent default layer that is not turned off.
+    last_default_layer: usize,
+}
+
+impl LayerTags {
+    /// Apply the turned off tags to the layers of a new configuration.
+    pub fn update_from_cfg(&mut self, layer_info: &[LayerInfo]) {
+        self.disabled_layers = layer_info
+            .iter()
+            .map(|l| l.tags.iter().any(|t| self.disabled_tags.contains(t)))
+            .collect();
+        self.last_default_layer = 0;
+    }
+
+    /// Turn the layers with the tags off or on at once. Returns the changes that undo these, or
+    /// an error without changing anything if no layer has one of the tags.
+    pub fn set_enabled(
+        &mut self,
+        changes: &[(String, bool)],
+        layer_info: &[LayerInfo],
+    ) -> Result<Vec<(String, bool)>> {
+        for (tag, _) in changes {
+            if !layer_info.iter().any(|l| l.tags.contains(tag)) {
+                bail!("no layer has the tag {tag}");
+            }
+        }
+        let mut undo = changes
+            .iter()
+            .map(|(tag, enabled)| {
+                let was_enabled = if *enabled {
+                    !self.disabled_tags.remove(tag)
+                } else {
+                    self.disabled_tags.insert(tag.clone())
+                };
+                (tag.clone(), was_enabled)
+            })
+            .collect::<Vec<_>>();
+        // Undoing in reverse restores the first state of a tag that is changed several times.
+        undo.reverse();
+        self.update_layers(layer_info);
+        Ok(undo)
+    }
+
+    fn update_layers(&mut self, layer_info: &[LayerInfo]) {
+        let last_default_layer = self.last_default_layer;
+        self.update_from_cfg(layer_info);
+        self.last_default_layer = last_default_layer;
+    }
+
+    pub fn is_disabled(&self, layer: usize) -> bool {
+        self.disabled_layers.get(layer).copied().unwrap_or(false)
+    }
+
+    /// Undo the activation of layers that are turned off.
+    pub fn enforce(&mut self, layout: &mut BorrowedKLayout) {
+        if self.disabled_tags.is_empty() {
+            self.last_default_layer = layout.default_layer;
+            return;
+        }
+        let disabled = &self.disabled_layers;
+        layout.states.retain(|s| match s {
+            State::LayerModifier { value, .. } => !disabled.get(*value).copied().unwrap_or(false),
+            _ => true,
+        });
+        if !self.is_disabled(layout.default_layer) {
+            self.last_default_layer = layout.default_layer;
+            return;
+        }
+        let fallback = if self.is_disabled(self.last_default_layer) {
+            // The layer was turned off while it was the default layer.
+            (0..self.disabled_layers.len())
+                .step_by(2)
+                .find(|&l| !self.is_disabled(l))
+        } else {
+            Some(self.last_default_layer)
+        };
+        if let Some(layer) = fallback {
+            log::info!("layer {layer} is turned off by its tags, switching back");
+            layout.set_default_layer(layer);
+            self.last_default_layer = layer;
+        }
+    }
+}
+
+#[test]
+fn layer_tags_turn_off_layers() {
+    let layer_info = [
+        ("base", &[][..]),
+        ("nav", &["nav"][..]),
+        ("exp", &["experimental", "nav"][..]),
+    ]
+    .iter()
+    .flat_map(|(name, tags)| {
+        std::iter::repeat_with(move || LayerInfo {
+            name: name.to_string(),
+            cfg_text: String::new(),
+            sounds: None,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            notify: vec![],
+            doc: LayerDoc::default(),
+        })
+        .take(2)
+    })
+    .collect::<Vec<_>>();
+    let mut tags = LayerTags::default();
+    tags.update_from_cfg(&layer_info);
+    assert!(!tags.is_disabled(4));
+    let off = |tag: &str| vec![(tag.to_string(), false)];
+    let on = |tag: &str| vec![(tag.to_string(), true)];
+    assert_eq!(
+        tags.set_enabled(&off("experimental"), &layer_info).unwrap(),
+        on("experimental")
+    );
+    assert_eq!(
+        tags.set_enabled(&off("experimental"), &layer_info).unwrap(),
+        off("experimental")
+    );
+    assert!(!tags.is_disabled(3));
+    assert!(tags.is_disabled(4) && tags.is_disabled(5));
+
+    // Turned off tags stay off across live reloads.
+    tags.update_from_cfg(&layer_info);
+    assert!(tags.is_disabled(5));
+    assert_eq!(
+        tags.set_enabled(&on("experimental"), &layer_info).unwrap(),
+        off("experimental")
+    );
+    assert!(!tags.is_disabled(5));
+
+    // Several changes apply at once, or not at all if a tag is unknown.
+    let changes = [off("nav"), on("nav"), off("experimental")].concat();
+    assert_eq!(
+        tags.set_enabled(&changes, &layer_info).unwrap(),
+        [on("experimental"), off("nav"), on("nav")].concat()
+    );
+    assert!(!tags.is_disabled(3) && tags.is_disabled(5));
+    let changes = [on("experimental"), off("left-hand")].concat();
+    assert!(tags.set_enabled(&changes, &layer_info).is_err());
+    assert!(tags.is_disabled(5));
+}
@@ -0,0 +1,145 @@
+//! Repurposing of the keyboard lock LEDs as layer indicators.
+//!
+//! When configured, the CapsLock, NumLock and ScrollLock LEDs of the grabbed keyboards show
+//! whether certain layers are active instead of the lock states. The LEDs are written to the
+//! input devices directly with `EV_LED` events. The OS still sets the LEDs to the actual lock
+//! states when a lock key is pressed, so kanata writes the layer state again after it outputs the
+//! release of a lock key.
+
+use super::*;
+
+use evdev::{Device, EventType, InputEvent, LedType};
+
+pub const LED_LAYERS_CFG_NAME: &str = "linux-led-layers";
+
+pub struct LedIndicator {
+    /// LEDs and the keyberon layer indexes during which they are lit.
+    leds: Vec<(LedType, Vec<usize>)>,
+    /// Whether the LEDs must be written even if the layer has not changed.
+    dirty: bool,
+}
+
+impl LedIndicator {
+    /// Read the LED configuration from defcfg. Returns `None` if LED indicators are not
+    /// configured.
+    pub fn from_cfg(
+        items: &HashMap<String, String>,
+        layer_info: &[LayerInfo],
+    ) -> Result<Option<Self>> {
+        let cfg = match items.get(LED_LAYERS_CFG_NAME) {
+            Some(cfg) => cfg,
+            None => return Ok(None),
+        };
+        let mut leds: Vec<(LedType, Vec<usize>)> = vec![];
+        for pair in cfg.split_whitespace() {
+            let (led, layer) = pair.split_once(':').ok_or_else(|| {
+                anyhow!("{LED_LAYERS_CFG_NAME} expects <led>:<layer> pairs, found: {pair}")
+            })?;
+            let led = match led {
+                "caps" => LedType::LED_CAPSL,
+                "num" => LedType::LED_NUML,
+                "scroll" => LedType::LED_SCROLLL,
+                _ => bail!("{LED_LAYERS_CFG_NAME}: unknown LED {led}, expected: caps, num, scroll"),
+            };
+            let layers = layer_info
+                .iter()
+                .enumerate()
+                .filter(|(_, l)| l.name == layer)
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+            if layers.is_empty() {
+                bail!("{LED_LAYERS_CFG_NAME} contains unknown layer: {layer}");
+            }
+            match leds.iter_mut().find(|(l, _)| *l == led) {
+                Some((_, led_layers)) => led_layers.extend(layers),
+                None => leds.push((led, layers)),
+            }
+        }
+        Ok(Some(Self { leds, dirty: true }))
+    }
+
+    /// Request that the LEDs are written on the next update, e.g. because the OS may have
+    /// overwritten them.
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Write the LEDs for the current layer if the layer changed or the LEDs are dirty.
+    pub fn update(&mut self, devices: &mut [Device], cur_layer: usize, layer_changed: bool) {
+        if !layer_changed && !self.dirty {
+            return;
+        }
+        self.dirty = false;
+        let events = self
+            .led_states(cur_layer)
+            .map(|(led, on)| InputEvent::new(EventType::LED, led.0, i32::from(on)))
+            .collect::<Vec<_>>();
+        for device in devices.iter_mut() {
+            if let Err(e) = device.send_events(&events) {
+                log::warn!("failed to write LEDs: {e}");
+            }
+        }
+    }
+
+    fn led_states(&self, cur_layer: usize) -> impl Iterator<Item = (LedType, bool)> + '_ {
+        self.leds
+            .iter()
+            .map(move |(led, layers)| (*led, layers.contains(&cur_layer)))
+    }
+}
+
+/// Open the devices at the given paths that have LEDs. These are opened separately from the
+/// grabbed devices that are read from, since those are owned by the event loop.
+pub fn open_led_devices(paths: &[String]) -> Vec<Device> {
+    paths
+        .iter()
+        .filter_map(|path| match Device::open(path) {
+            Ok(device) => Some(device),
+            Err(e) => {
+                log::warn!("failed to open {path} for LEDs: {e}");
+                None
+            }
+        })
+        .filter(|device| device.supported_leds().is_some())
+        .collect()
+}
+
+#[test]
+fn led_indicator_parses_led_layer_pairs() {
+    let layer_info = ["base", "base", "nav", "nav", "sym", "sym"]
+        .iter()
+        .map(|name| LayerInfo {
+            name: name.to_string(),
+            cfg_text: String::new(),
+            sounds: None,
+            tags: vec![],
+            notify: vec![],
+            doc: LayerDoc::default(),
+        })
+        .collect::<Vec<_>>();
+    let mut items = HashMap::default();
+    items.insert(
+        LED_LAYERS_CFG_NAME.into(),
+        "caps:nav num:sym caps:sym".into(),
+    );
+    let leds = LedIndicator::from_cfg(&items, &layer_info)
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        leds.led_states(2).collect::<Vec<_>>(),
+        [(LedType::LED_CAPSL, true), (LedType::LED_NUML, false)]
+    );
+    assert_eq!(
+        leds.led_states(5).collect::<Vec<_>>(),
+        [(LedType::LED_CAPSL, true), (LedType::LED_NUML, true)]
+    );
+    assert_eq!(
+        leds.led_states(0).collect::<Vec<_>>(),
+        [(LedType::LED_CAPSL, false), (LedType::LED_NUML, false)]
+    );
+
+    items.insert(LED_LAYERS_CFG_NAME.into(), "kana:nav".into());
+    assert!(LedIndicator::from_cfg(&items, &layer_info).is_err());
+    items.insert(LED_LAYERS_CFG_NAME.into(), "caps:nope".into());
+    assert!(LedIndicator::from_cfg(&items, &layer_info).is_err());
+}
@@ -1,67 +1,269 @@
 use anyhow::{anyhow, bail, Result};
 use log::info;
 use parking_lot::Mutex;
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    iterator::Signals,
+};
 use std::convert::TryFrom;
 use std::sync::mpsc::Sender;
 use std::sync::Arc;
 
 use super::*;
 
+/// How often the use of pointing devices is reported to the processing loop at most.
+const POINTER_ACTIVITY_INTERVAL: time::Duration = time::Duration::from_millis(10);
+
 impl Kanata {
+    /// Shut down through the processing loop on SIGINT and SIGTERM, so that held keys are
+    /// released and the state is saved. A second signal exits immediately in case the processing
+    /// loop is stuck.
+    pub fn start_signal_handler(tx: Sender<ProcessingEvent>) -> Result<()> {
+        let mut signals = Signals::new([SIGINT, SIGTERM])?;
+        std::thread::spawn(move || {
+            let mut shutting_down = false;
+            for signal in &mut signals {
+                if shutting_down
+                    || tx
+                        .send(ProcessingEvent::Command(KanataCommand::Shutdown))
+                        .is_err()
+                {
+                    signal_hook::low_level::emulate_default_handler(signal)
+                        .expect("run original sighandlers");
+                    unreachable!();
+                }
+                shutting_down = true;
+            }
+        });
+        Ok(())
+    }
+
     /// Enter an infinite loop that listens for OS key events and sends them to the processing
     /// thread.
-    pub fn event_loop(kanata: Arc<Mutex<Self>>, tx: Sender<KeyEvent>) -> Result<()> {
+    pub fn event_loop(kanata: Arc<Mutex<Self>>, tx: Sender<ProcessingEvent>) -> Result<()> {
         info!("entering the event loop");
 
-        let k = kanata.lock();
-        let mut kbd_in = match KbdIn::new(&k.kbd_in_paths, k.continue_if_no_devices) {
+        let mut k = kanata.lock();
+        let mut input = InputState {
+            scancode_map: k.scancode_map.take(),
+            dial: DialInput::default(),
+            latency: k.latency_meter.clone(),
+            compose_on_top: k.compose_on_top,
+        };
+        if k.filter_mode {
+            drop(k);
+            let mut stdin_in = StdinIn::default();
+            let mut events = vec![];
+            loop {
+                stdin_in
+                    .read(&mut events)
+                    .map_err(|e| anyhow!("failed read: {}", e))?;
+                if events.is_empty() {
+                    info!("stdin was closed, exiting");
+                    return Ok(());
+                }
+                handle_input_events(&kanata, &tx, &events, &mut input)?;
+            }
+        }
+
+        // Media keys are often on a separate device, which is only grabbed if they are mapped.
+        let include_media_devices = {
+            let mapped_keys = MAPPED_KEYS.lock();
+            MEDIA_KEYS.iter().any(|k| mapped_keys.contains(k))
+        };
+        let mut kbd_in = match KbdIn::new(
+            &k.kbd_in_paths,
+            &k.device_fds,
+            k.continue_if_no_devices,
+            include_media_devices,
+            k.force,
+            !k.compose_on_top,
+        ) {
             Ok(kbd_in) => kbd_in,
             Err(e) => {
                 bail!("failed to open keyboard device(s): {}", e)
             }
         };
+        if let Some(session_id) = &k.session_aware {
+            let session = SessionWatcher::new(session_id.as_deref())
+                .map_err(|e| anyhow!("failed to watch the logind session: {e}"))?;
+            kbd_in.watch_session(session)?;
+        }
+        k.led_devices = open_led_devices(&kbd_in.device_paths());
+        k.device_grab_control = Some(kbd_in.grab_control());
+        // Changes of the devices take effect on restart, like linux-dev.
+        kbd_in.watch_activity(k.pointer_layer.devices.clone());
         drop(k);
 
+        let mut events = vec![];
+        let mut activity_sent_at: Option<time::Instant> = None;
         loop {
-            let events = kbd_in.read().map_err(|e| anyhow!("failed read: {}", e))?;
-            log::trace!("{events:?}");
-
-            for in_event in events.into_iter() {
-                let key_event = match KeyEvent::try_from(in_event) {
-                    Ok(ev) => ev,
-                    _ => {
-                        // Pass-through non-key events
-                        let mut kanata = kanata.lock();
-                        kanata
-                            .kbd_out
-                            .write_raw(in_event)
-                            .map_err(|e| anyhow!("failed write: {}", e))?;
-                        continue;
-                    }
-                };
-
-                check_for_exit(&key_event);
-
-                // Check if this keycode is mapped in the configuration. If it hasn't been mapped, send
-                // it immediately.
-                if !MAPPED_KEYS.lock().contains(&key_event.code) {
-                    let mut kanata = kanata.lock();
-                    kanata
-                        .kbd_out
-                        .write_key(key_event.code, key_event.value)
-                        .map_err(|e| anyhow!("failed write key: {}", e))?;
-                    continue;
+            kbd_in
+                .read(&mut events)
+                .map_err(|e| anyhow!("failed read: {}", e))?;
+            // Pointing devices send events every few milliseconds while they are used.
+            if kbd_in.take_activity()
+                && activity_sent_at.map_or(true, |t| t.elapsed() >= POINTER_ACTIVITY_INTERVAL)
+            {
+                activity_sent_at = Some(time::Instant::now());
+                if let Err(e) = tx.send(ProcessingEvent::Command(KanataCommand::PointerActivity)) {
+                    bail!("failed to send on channel: {}", e)
                 }
-
-                // Send key events to the processing loop
-                if let Err(e) = tx.send(key_event) {
+            }
+            if kbd_in.take_devices_changed() {
+                let names = kbd_in.device_names();
+                if let Err(e) = tx.send(ProcessingEvent::Command(KanataCommand::DevicesChanged {
+                    names,
+                })) {
                     bail!("failed to send on channel: {}", e)
                 }
             }
+            handle_input_events(&kanata, &tx, &events, &mut input)?;
         }
     }
 
     pub fn check_release_non_physical_shift(&mut self) -> Result<()> {
         Ok(())
     }
+
+    /// Release every key that is held in the layout if the system was suspended since the last
+    /// check. Releases are often lost around a suspend, e.g. when the lid is closed while a layer
+    /// key is held, which would leave keys or layers stuck after resuming.
+    pub fn release_held_keys_after_resume(&mut self) {
+        if !self.resume_detector.check() {
+            return;
+        }
+        let coords = self
+            .layout
+            .b()
+            .states
+            .iter()
+            .filter_map(|state| match state {
+                State::NormalKey { coord, .. }
+                | State::LayerModifier { coord, .. }
+                | State::Custom { coord, .. }
+                | State::RepeatingSequence { coord, .. }
+                | State::OnRelease { coord, .. } => Some(*coord),
+                _ => None,
+            })
+            .filter(|(row, _)| *row == 0)
+            .collect::<Vec<_>>();
+        log::info!(
+            "system resumed from suspend, releasing held keys: {}",
+            coords
+                .iter()
+                .map(|&(_, col)| match OsCode::try_from(usize::from(col)) {
+                    Ok(osc) => format!("{osc:?}"),
+                    Err(_) => col.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+        for (row, col) in coords {
+            self.layout.bm().event(Event::Release(row, col));
+        }
+    }
+}
+
+/// State of the event loop that applies to the events of all devices.
+struct InputState {
+    scancode_map: Option<ScancodeMap>,
+    dial: DialInput,
+    latency: Option<Arc<Mutex<LatencyMeter>>>,
+    /// The devices are not grabbed, so their events reach the system without being written.
+    compose_on_top: bool,
+}
+
+impl InputState {
+    fn send(
+        &self,
+        tx: &Sender<ProcessingEvent>,
+        event: KeyEvent,
+        read_at: time::Instant,
+    ) -> Result<()> {
+        if let Some(latency) = &self.latency {
+            latency.lock().event_sent(read_at);
+        }
+        if let Err(e) = tx.send(event.into()) {
+            bail!("failed to send on channel: {}", e)
+        }
+        Ok(())
+    }
+
+    fn written(&self, read_at: time::Instant) {
+        if let Some(latency) = &self.latency {
+            latency.lock().record(read_at);
+        }
+    }
+}
+
+/// Pass through unmapped and non-key events and send the mapped key events to the processing
+/// loop.
+fn handle_input_events(
+    kanata: &Mutex<Kanata>,
+    tx: &Sender<ProcessingEvent>,
+    events: &[evdev::InputEvent],
+    input: &mut InputState,
+) -> Result<()> {
+    log::trace!("{events:?}");
+    let read_at = time::Instant::now();
+
+    for mut in_event in events.iter().copied() {
+        if let Some(scancode_map) = &mut input.scancode_map {
+            in_event = scancode_map.remap(in_event);
+        }
+
+        // Rotation of knobs is sent as taps of the dial and wheel keys if these are mapped.
+        if let Some((key, taps)) = input.dial.key_taps(&in_event) {
+            if MAPPED_KEYS.lock().contains(&key) {
+                for _ in 0..taps {
+                    for value in [KeyValue::Press, KeyValue::Release] {
+                        input.send(tx, KeyEvent::new(key, value), read_at)?;
+                    }
+                }
+                continue;
+            }
+        }
+        let key_event = match KeyEvent::try_from(in_event) {
+            Ok(ev) => ev,
+            _ if input.compose_on_top => continue,
+            _ => {
+                // Pass-through non-key events
+                let mut kanata = kanata.lock();
+                kanata
+                    .kbd_out
+                    .write_raw(in_event)
+                    .map_err(|e| anyhow!("failed write: {}", e))?;
+                continue;
+            }
+        };
+
+        check_for_exit(&key_event);
+
+        if input.compose_on_top {
+            kanata
+                .lock()
+                .kbd_out
+                .input_key(key_event.code, key_event.value);
+        }
+
+        // Check if this keycode is mapped in the configuration. If it hasn't been mapped, send
+        // it immediately.
+        if !MAPPED_KEYS.lock().contains(&key_event.code) {
+            if input.compose_on_top {
+                continue;
+            }
+            let mut kanata = kanata.lock();
+            kanata
+                .kbd_out
+                .write_key(key_event.code, key_event.value)
+                .map_err(|e| anyhow!("failed write key: {}", e))?;
+            input.written(read_at);
+            continue;
+        }
+
+        // Send key events to the processing loop
+        input.send(tx, key_event, read_at)?;
+    }
+    Ok(())
 }
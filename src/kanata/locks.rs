@@ -0,0 +1,271 @@
+//! State that keeps keys or layers active until something releases it, and recovery from it.
+//!
+//! Keys latched by `toggle-key` are recorded with the key that latched them and when, so that TCP
+//! clients can find out why a key is stuck with `RequestLocks`. `ForceUnlock` releases everything
+//! that can get stuck without restarting kanata: latched keys, held keys and layers, and the
+//! modes that capture keys, such as sequences and the launcher.
+//!
+//! `resync-modifiers` and `ResyncModifiers` release every modifier on the output, for when the
+//! modifier state of the OS no longer matches the modifiers that kanata pressed, see
+//! [`OutputModifiers`]. `RequestLocks` lists the modifiers that kanata believes are held.
+//!
+//! `layer-lock` keeps a layer held by `layer-while-held` active after its key is released, by
+//! moving the held layer to a coordinate that no key has.
+
+use super::*;
+
+use crate::tcp_server::LockInfo;
+
+#[derive(Debug)]
+struct LatchRecord {
+    key: KeyCode,
+    /// The name of the physical or fake key that latched it.
+    by: String,
+    since: time::Instant,
+}
+
+/// Who latched the keys of `toggle-key`.
+#[derive(Debug, Default)]
+pub struct LatchAudit {
+    records: Vec<LatchRecord>,
+}
+
+impl LatchAudit {
+    pub fn latched(&mut self, key: KeyCode, by: String, now: time::Instant) {
+        self.unlatched(key);
+        self.records.push(LatchRecord {
+            key,
+            by,
+            since: now,
+        });
+    }
+
+    pub fn unlatched(&mut self, key: KeyCode) {
+        self.records.retain(|r| r.key != key);
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    /// Describe the latched keys. Keys without a record were restored from `persist-state-file`.
+    fn report(&self, latched_keys: &[KeyCode], now: time::Instant) -> Vec<LockInfo> {
+        latched_keys
+            .iter()
+            .map(|key| {
+                let record = self.records.iter().find(|r| r.key == *key);
+                LockInfo {
+                    lock: format!("toggle-key {:?}", OsCode::from(*key)),
+                    taken_by: record.map(|r| r.by.clone()),
+                    held_ms: record.map(|r| now.duration_since(r.since).as_millis() as u64),
+                }
+            })
+            .collect()
+    }
+}
+
+/// The coordinate of a layer locked by `layer-lock`. No key is at this coordinate, so only
+/// `layer-lock` or a force unlock releases the layer.
+pub const LAYER_LOCK_COORD: (u8, u16) = (u8::MAX, u16::MAX);
+
+/// Lock the most recently held layer, or unlock it if it is already locked. Returns the layer and
+/// whether it is now locked, or `None` if no layer is held. Layers held by the other owners in
+/// [`LAYER_OWNERS`], such as `temp-layer`, are not keys and are left to their owners.
+pub fn toggle_layer_lock(layout: &mut BorrowedKLayout) -> Option<(usize, bool)> {
+    let i = layout.states.iter().rposition(|state| {
+        matches!(state, State::LayerModifier { coord, .. }
+            if *coord == LAYER_LOCK_COORD || !LAYER_OWNERS.iter().any(|(c, _)| c == coord))
+    })?;
+    match &mut layout.states[i] {
+        State::LayerModifier { value, coord } if *coord == LAYER_LOCK_COORD => {
+            let layer = *value;
+            layout.states.remove(i);
+            Some((layer, false))
+        }
+        State::LayerModifier { value, coord } => {
+            *coord = LAYER_LOCK_COORD;
+            Some((*value, true))
+        }
+        _ => unreachable!("found a layer modifier"),
+    }
+}
+
+/// The owners of the layers that no key holds, by the coordinate that they hold their layers at.
+/// Something that holds layers this way takes a coordinate of its own and names itself here, so
+/// that `RequestLocks` and force unlock tell what holds the layer.
+const LAYER_OWNERS: &[((u8, u16), &str)] = &[
+    (LAYER_LOCK_COORD, "layer-lock"),
+    #[cfg(target_os = "linux")]
+    (POINTER_LAYER_COORD, "pointer"),
+    (OVERLAY_LAYER_COORD, "overlay"),
+    (LANGUAGE_LAYER_COORD, "language"),
+    (TEMP_LAYER_COORD, "temp-layer"),
+    (STARTUP_LAYER_COORD, "startup-layers"),
+    (POINTER_REGION_LAYER_COORD, "pointer-regions"),
+];
+
+/// The name of the physical or fake key at the coordinate, or of the owner of a layer that no
+/// key holds.
+pub fn coord_name(fake_key_names: &[String], (row, col): (u8, u16)) -> String {
+    if let Some((_, owner)) = LAYER_OWNERS.iter().find(|(c, _)| *c == (row, col)) {
+        return (*owner).to_owned();
+    }
+    match row {
+        0 => match OsCode::try_from(usize::from(col)) {
+            Ok(osc) => format!("{osc:?}"),
+            Err(_) => col.to_string(),
+        },
+        _ => fake_key_name(fake_key_names, col).to_owned(),
+    }
+}
+
+impl Kanata {
+    /// Describe the latched keys, the held layers and the modes that capture keys.
+    pub fn lock_report(&self) -> Vec<LockInfo> {
+        let mut locks = self
+            .latch_audit
+            .report(&self.latched_keys, self.clock.now());
+        locks.extend(self.kbd_out.modifiers.held().iter().map(|osc| LockInfo {
+            lock: format!("output modifier {osc:?}"),
+            taken_by: None,
+            held_ms: None,
+        }));
+        for state in self.layout.b().states.iter() {
+            if let State::LayerModifier { value, coord } = state {
+                locks.push(LockInfo {
+                    lock: format!("layer {}", self.layer_info[*value].name),
+                    taken_by: Some(coord_name(&self.fake_key_names, *coord)),
+                    held_ms: None,
+                });
+            }
+        }
+        let modes = [
+            ("passthrough", self.passthrough.is_some()),
+            ("sequence", self.sequence_state.is_some()),
+            ("launcher", self.launcher_state.is_some()),
+            ("mouse-grid", self.mouse_grid.is_some()),
+            ("caps-word", self.caps_word.is_some()),
+            ("morse", self.morse.is_some()),
+            ("unicode-input", self.unicode_input.is_some()),
+            (
+                "dynamic macro record",
+                self.dynamic_macro_record_state.is_some(),
+            ),
+            (
+                "deferred layer changes",
+                !self.deferred_layer_commands.is_empty(),
+            ),
+        ];
+        locks.extend(
+            modes
+                .iter()
+                .filter(|(_, active)| *active)
+                .map(|(name, _)| LockInfo {
+                    lock: name.to_string(),
+                    taken_by: None,
+                    held_ms: None,
+                }),
+        );
+        locks
+    }
+
+    /// Release everything that can keep keys or layers active, for recovery when something is
+    /// stuck. The outputs are released on the next tick.
+    pub(super) fn force_unlock(&mut self) {
+        log::warn!(
+            "force unlock: releasing {}",
+            match self.lock_report().as_slice() {
+                [] => "nothing".to_owned(),
+                locks => locks
+                    .iter()
+                    .map(|l| match &l.taken_by {
+                        Some(by) => format!("{} (taken by {by})", l.lock),
+                        None => l.lock.clone(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            }
+        );
+        self.latched_keys.clear();
+        self.latch_audit.clear();
+        self.deferred_layer_commands.clear();
+        self.sequence_state = None;
+        self.launcher_state = None;
+        self.mouse_grid = None;
+        self.caps_word = None;
+        self.morse = None;
+        self.unicode_input = None;
+        self.dynamic_macro_record_state = None;
+        self.dynamic_macro_replay_state = None;
+        for osc in self.output_queue.clear() {
+            if let Err(e) = self.kbd_out.release_key(osc) {
+                log::warn!("failed to release {osc:?}: {e:?}");
+            }
+        }
+        let layout = self.layout.bm();
+        let coords = layout
+            .states
+            .iter()
+            .filter_map(|state| match state {
+                State::NormalKey { coord, .. }
+                | State::LayerModifier { coord, .. }
+                | State::Custom { coord, .. }
+                | State::RepeatingSequence { coord, .. }
+                | State::OnRelease { coord, .. } => Some(*coord),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        for (row, col) in coords {
+            layout.event(Event::Release(row, col));
+        }
+        layout.active_sequences.clear();
+    }
+
+    /// Release every modifier on the output. The modifiers that are still held in the layout are
+    /// pressed again on the next tick.
+    pub(super) fn resync_modifiers(&mut self) {
+        log::warn!(
+            "resyncing modifiers, kanata held {:?} on the output",
+            self.kbd_out.modifiers.held()
+        );
+        for osc in OUTPUT_MODIFIERS {
+            if let Err(e) = self.kbd_out.release_key(osc) {
+                log::warn!("failed to release {osc:?}: {e:?}");
+            }
+        }
+        self.prev_keys
+            .retain(|k| !OUTPUT_MODIFIERS.contains(&OsCode::from(*k)));
+    }
+}
+
+#[test]
+fn layer_owners_have_their_own_coordinates() {
+    for (i, (coord, owner)) in LAYER_OWNERS.iter().enumerate() {
+        assert_eq!(coord.0, u8::MAX, "{owner} is in the row of the fake keys");
+        for (other_coord, other) in &LAYER_OWNERS[i + 1..] {
+            assert_ne!(coord, other_coord, "{owner} and {other} share a coordinate");
+        }
+    }
+    assert_eq!(coord_name(&[], TEMP_LAYER_COORD), "temp-layer");
+    assert_eq!(coord_name(&[], (0, OsCode::KEY_A.into())), "KEY_A");
+}
+
+#[test]
+fn latch_audit_reports_who_latched() {
+    let mut audit = LatchAudit::default();
+    let start = time::Instant::now();
+    audit.latched(KeyCode::LShift, "KEY_CAPSLOCK".into(), start);
+    audit.latched(KeyCode::LCtrl, "KEY_A".into(), start);
+    audit.unlatched(KeyCode::LCtrl);
+    let locks = audit.report(
+        &[KeyCode::LShift, KeyCode::RAlt],
+        start + time::Duration::from_millis(1500),
+    );
+    assert_eq!(locks.len(), 2);
+    assert_eq!(locks[0].lock, "toggle-key KEY_LEFTSHIFT");
+    assert_eq!(locks[0].taken_by.as_deref(), Some("KEY_CAPSLOCK"));
+    assert_eq!(locks[0].held_ms, Some(1500));
+    // Restored from the state file.
+    assert_eq!(locks[1].lock, "toggle-key KEY_RIGHTALT");
+    assert_eq!(locks[1].taken_by, None);
+}
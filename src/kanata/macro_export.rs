@@ -0,0 +1,136 @@
+//! Export and import of the recorded dynamic macros and the snippets of `defsnippets` in a
+//! portable JSON format, so that they can be moved to another machine or into the configuration.
+//!
+//! Keys are written by key name in the syntax of `InjectKeys`, e.g. `+lsft a -lsft`, instead of
+//! by key code, so the format does not depend on the platform. Snippet expansions are written as
+//! in `defsnippets`, with placeholders such as `{date}`. `kanata export-macros` and
+//! `kanata import-macros` read and write the format.
+
+use super::*;
+
+use crate::tcp_server::{ExportedMacro, ExportedSnippet, MacroExport};
+
+/// The version of the export format, which changes when an older kanata could no longer import
+/// an export correctly.
+pub const MACRO_EXPORT_VERSION: u32 = 1;
+
+/// The keys of a dynamic macro in the syntax of `InjectKeys`. A press directly followed by the
+/// release of the same key is written as a tap.
+pub fn macro_keys(items: &[DynamicMacroItem]) -> String {
+    let mut keys = vec![];
+    let mut items = items.iter().peekable();
+    while let Some(item) = items.next() {
+        match item {
+            DynamicMacroItem::Press(osc) => {
+                let name = crate::palette::key_name(*osc);
+                if items.peek() == Some(&&DynamicMacroItem::Release(*osc)) {
+                    items.next();
+                    keys.push(name);
+                } else {
+                    keys.push(format!("+{name}"));
+                }
+            }
+            DynamicMacroItem::Release(osc) => {
+                keys.push(format!("-{}", crate::palette::key_name(*osc)))
+            }
+            // Only inserted while a macro is replayed, never recorded.
+            DynamicMacroItem::EndMacro(_) => {}
+        }
+    }
+    keys.join(" ")
+}
+
+/// Parse the keys of an exported dynamic macro.
+pub fn parse_macro_keys(keys: &str) -> Result<Vec<DynamicMacroItem>> {
+    if keys.trim().is_empty() {
+        return Ok(vec![]);
+    }
+    Ok(parse_injected_keys(keys)?
+        .into_iter()
+        .map(|ev| match ev.value {
+            KeyValue::Release => DynamicMacroItem::Release(ev.code),
+            _ => DynamicMacroItem::Press(ev.code),
+        })
+        .collect())
+}
+
+/// The expansion of a snippet as written in `defsnippets`.
+pub fn snippet_expansion_text(expansion: &[SnippetPart]) -> String {
+    expansion
+        .iter()
+        .map(|part| match part {
+            SnippetPart::Text(text) => text.replace('{', "{{").replace('\n', "{enter}"),
+            SnippetPart::Cursor => "{cursor}".to_owned(),
+            SnippetPart::Date => "{date}".to_owned(),
+            SnippetPart::Time => "{time}".to_owned(),
+        })
+        .collect()
+}
+
+impl Kanata {
+    /// The recorded dynamic macros and the snippets of the configuration, for
+    /// `ClientMessage::ExportMacros`.
+    pub fn macro_export(&self) -> MacroExport {
+        let mut dynamic_macros = self
+            .dynamic_macros
+            .iter()
+            .map(|(id, items)| ExportedMacro {
+                id: *id,
+                keys: macro_keys(items),
+            })
+            .collect::<Vec<_>>();
+        dynamic_macros.sort_by_key(|m| m.id);
+        MacroExport {
+            version: MACRO_EXPORT_VERSION,
+            dynamic_macros,
+            snippets: self
+                .snippets
+                .iter()
+                .map(|snippet| ExportedSnippet {
+                    trigger: snippet.trigger.clone(),
+                    expansion: snippet_expansion_text(&snippet.expansion),
+                })
+                .collect(),
+        }
+    }
+
+    /// Replace the dynamic macros with the same IDs as the imported ones. Other macros are kept.
+    pub fn import_macros(&mut self, macros: Vec<(u16, Vec<DynamicMacroItem>)>) {
+        for (id, items) in macros {
+            log::info!("importing dynamic macro {id}");
+            self.dynamic_macros.insert(id, items);
+        }
+        if let Some(p) = &mut self.state_persistence {
+            p.mark_macros_changed();
+        }
+    }
+}
+
+#[test]
+fn macro_keys_round_trip() {
+    let items = vec![
+        DynamicMacroItem::Press(OsCode::KEY_LEFTSHIFT),
+        DynamicMacroItem::Press(OsCode::KEY_A),
+        DynamicMacroItem::Release(OsCode::KEY_A),
+        DynamicMacroItem::Release(OsCode::KEY_LEFTSHIFT),
+        DynamicMacroItem::Press(OsCode::KEY_B),
+        DynamicMacroItem::Press(OsCode::KEY_C),
+        DynamicMacroItem::Release(OsCode::KEY_C),
+        DynamicMacroItem::Release(OsCode::KEY_B),
+    ];
+    let keys = macro_keys(&items);
+    assert_eq!(keys, "+lshift a -lshift +b c -b");
+    assert_eq!(parse_macro_keys(&keys).unwrap(), items);
+    assert!(parse_macro_keys(" ").unwrap().is_empty());
+    assert!(parse_macro_keys("+nosuchkey").is_err());
+
+    let expansion = [
+        SnippetPart::Text("fn {\n".into()),
+        SnippetPart::Cursor,
+        SnippetPart::Date,
+    ];
+    assert_eq!(
+        snippet_expansion_text(&expansion),
+        "fn {{{enter}{cursor}{date}"
+    );
+}
@@ -3,16 +3,15 @@
 use anyhow::{anyhow, bail, Result};
 use log::{error, info};
 use parking_lot::Mutex;
-use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, TryRecvError};
 
 use kanata_keyberon::key_code::*;
 use kanata_keyberon::layout::*;
 
 use std::collections::VecDeque;
 use std::io::Write;
-use std::net::TcpStream;
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, AtomicU32, Ordering::SeqCst};
+use std::sync::atomic::{AtomicU32, Ordering::SeqCst};
 use std::sync::Arc;
 use std::time;
 
@@ -20,13 +19,22 @@ use crate::cfg::*;
 use crate::custom_action::*;
 use crate::keys::*;
 use crate::oskbd::*;
-use crate::tcp_server::ServerMessage;
+use crate::tcp_server::{relay_notifications, NotificationSink, ServerMessage};
 use crate::{cfg, ValidatedArgs};
 
 #[cfg(feature = "cmd")]
 mod cmd;
 #[cfg(feature = "cmd")]
 use cmd::*;
+#[cfg(feature = "cmd")]
+mod multi_press;
+#[cfg(feature = "cmd")]
+pub use multi_press::*;
+
+#[cfg(feature = "clipboard")]
+mod clipboard;
+#[cfg(feature = "clipboard")]
+use clipboard::*;
 
 #[cfg(target_os = "windows")]
 mod windows;
@@ -41,9 +49,321 @@ pub use linux::*;
 mod caps_word;
 pub use caps_word::*;
 
+mod morse;
+pub use morse::*;
+
+mod unicode_input;
+pub use unicode_input::*;
+
+mod launcher;
+pub use launcher::*;
+
+mod mouse_grid;
+pub use mouse_grid::*;
+
+mod dwell_click;
+pub use dwell_click::*;
+
+mod mod_chords;
+pub use mod_chords::*;
+
+mod key_filter;
+pub use key_filter::*;
+
+mod key_counts;
+pub use key_counts::*;
+
+mod key_repeat;
+pub use key_repeat::*;
+mod event_stages;
+pub use event_stages::*;
+
+mod cooldown;
+pub use cooldown::*;
+
+mod startup_layers;
+pub use startup_layers::*;
+
+mod transaction;
+pub use transaction::*;
+
+mod clock;
+pub use clock::*;
+
+mod rate_limit;
+pub use rate_limit::*;
+mod output_queue;
+pub use output_queue::*;
+
+mod layer_stack;
+pub use layer_stack::*;
+
+mod crash_dump;
+pub use crash_dump::*;
+mod exit_combo;
+pub use exit_combo::*;
+
+const LOG_FILTER_CFG_NAME: &str = "log-filter";
+const DEFER_LAYER_CHANGES_CFG_NAME: &str = "defer-layer-changes";
+
+mod text;
+pub use text::*;
+
+mod output_history;
+pub use output_history::*;
+
+mod shortcodes;
+pub use shortcodes::*;
+
+mod snippets;
+pub use snippets::*;
+
+mod macro_export;
+pub use macro_export::*;
+
+mod build_info;
+pub use build_info::*;
+
+mod swap_hands;
+pub use swap_hands::*;
+mod key_transforms;
+pub use key_transforms::*;
+mod modifier_taps;
+pub use modifier_taps::*;
+mod guard;
+pub use guard::*;
+mod kiosk;
+pub use kiosk::*;
+mod helpers;
+pub use helpers::*;
+mod count_prefix;
+pub use count_prefix::*;
+mod modal;
+pub use modal::*;
+
+mod sound;
+pub use sound::*;
+
+mod persist;
+pub use persist::*;
+
+mod openrgb;
+pub use openrgb::*;
+
+mod layer_display;
+pub use layer_display::*;
+mod input_source;
+pub use input_source::*;
+mod kvm;
+pub use kvm::*;
+#[cfg(feature = "kvm")]
+mod kvm_net;
+#[cfg(feature = "kvm")]
+use kvm_net::*;
+mod output_router;
+pub use output_router::*;
+mod pointer_regions;
+pub use pointer_regions::*;
+
+mod game_mode;
+pub use game_mode::*;
+
+mod layer_tags;
+pub use layer_tags::*;
+
+mod locks;
+pub use locks::*;
+
+mod usage_stats;
+pub use usage_stats::*;
+
+mod reload;
+pub use reload::*;
+
+mod overlay;
+pub use overlay::*;
+
+mod inject;
+pub use inject::*;
+
+mod passthrough;
+pub use passthrough::*;
+
+mod temp_layer;
+pub use temp_layer::*;
+
+mod language;
+pub use language::*;
+
+mod undo;
+pub use undo::*;
+
+#[cfg(target_os = "linux")]
+mod ime;
+#[cfg(target_os = "linux")]
+pub use ime::*;
+
+#[cfg(target_os = "linux")]
+mod led;
+#[cfg(target_os = "linux")]
+mod pointer_layer;
+#[cfg(target_os = "linux")]
+pub use pointer_layer::*;
+#[cfg(target_os = "linux")]
+pub use led::*;
+
+#[cfg(target_os = "linux")]
+mod dial;
+#[cfg(target_os = "linux")]
+pub use dial::*;
+
+#[cfg(target_os = "linux")]
+mod latency;
+#[cfg(target_os = "linux")]
+pub use latency::*;
+
+#[cfg(target_os = "linux")]
+mod scancode;
+#[cfg(target_os = "linux")]
+pub use scancode::*;
+
+#[cfg(target_os = "linux")]
+mod screen_lock;
+#[cfg(target_os = "linux")]
+pub use screen_lock::*;
+
+#[cfg(target_os = "linux")]
+mod device_tags;
+#[cfg(target_os = "linux")]
+pub use device_tags::*;
+
+/// How long the shutdown waits for TCP clients to be notified.
+const SHUTDOWN_NOTIFICATION_TIMEOUT: time::Duration = time::Duration::from_secs(1);
+
 type HashSet<T> = rustc_hash::FxHashSet<T>;
 type HashMap<K, V> = rustc_hash::FxHashMap<K, V>;
 
+/// Input of the processing loop. Key events come from the event loop and commands from TCP
+/// clients, so that only the processing loop changes the layout.
+#[derive(Debug)]
+pub enum ProcessingEvent {
+    Key(KeyEvent),
+    Command(KanataCommand),
+}
+
+impl From<KeyEvent> for ProcessingEvent {
+    fn from(kev: KeyEvent) -> Self {
+        Self::Key(kev)
+    }
+}
+
+#[derive(Debug)]
+pub enum KanataCommand {
+    ChangeLayer {
+        name: String,
+    },
+    ActOnFakeKey {
+        name: String,
+        action: FakeKeyAction,
+    },
+    SetVar {
+        name: String,
+        value: String,
+    },
+    /// Sent by TCP clients and on SIGINT or SIGTERM.
+    Shutdown,
+    ChangeConfig {
+        index: usize,
+    },
+    SetGameMode {
+        enabled: bool,
+    },
+    /// Turn the layers with `deflayertags` tags off or on in one update.
+    SetLayerTags {
+        changes: Vec<(String, bool)>,
+    },
+    /// Revert the most recent state change made by the commands above.
+    Undo,
+    /// Release latched keys, held keys and layers, and modes that capture keys.
+    ForceUnlock,
+    /// Sent by TCP clients, e.g. when the focused window changes.
+    ClearOutputHistory,
+    ActiveWindowChanged {
+        class: String,
+        title: String,
+    },
+    /// Sent by the event loop when a device of `linux-pointer-layer-devices` is used.
+    PointerActivity,
+    /// The position of the pointer on the screen, see `pointer-regions`.
+    PointerMoved {
+        x: i32,
+        y: i32,
+    },
+    /// Live reload with the overlay file on top of the active configuration file.
+    LoadOverlay {
+        path: PathBuf,
+    },
+    DropOverlay,
+    /// Sent when the screen of the logind session is locked or unlocked, see
+    /// `linux-pause-layers-on-lock`.
+    ScreenLocked {
+        locked: bool,
+    },
+    /// Sent by the event loop with the names of the input devices when one is plugged in or
+    /// unplugged, see `defdevicetags`.
+    DevicesChanged {
+        names: Vec<String>,
+    },
+    /// Output the keys unchanged instead of processing them, see `passthrough`.
+    SetPassthrough {
+        enabled: bool,
+    },
+    /// Release every modifier on the output, see `resync-modifiers`.
+    ResyncModifiers,
+    /// Key events injected by a TCP client that are sent to the output without processing.
+    OutputKeys {
+        events: Vec<KeyEvent>,
+    },
+    TypeText {
+        text: String,
+    },
+    /// Activate the layer for the time, see `temp-layer`.
+    ActivateTempLayer {
+        name: String,
+        duration: time::Duration,
+    },
+    ExtendTempLayer {
+        by: time::Duration,
+    },
+    CancelTempLayer,
+    /// Dynamic macros imported by a TCP client, see `import-macros`.
+    ImportMacros {
+        macros: Vec<(u16, Vec<DynamicMacroItem>)>,
+    },
+    /// State changes of a TCP client that are applied together or not at all.
+    Transaction {
+        commands: Vec<KanataCommand>,
+    },
+}
+
+impl KanataCommand {
+    /// Whether the command changes layers, so that `defer-layer-changes` applies to it.
+    fn changes_layers(&self) -> bool {
+        match self {
+            KanataCommand::ChangeLayer { .. } | KanataCommand::SetLayerTags { .. } => true,
+            KanataCommand::Transaction { commands } => commands.iter().any(Self::changes_layers),
+            _ => false,
+        }
+    }
+}
+
+/// The focused window, as reported by a TCP client that watches it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveWindow {
+    pub class: String,
+    pub title: String,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DynamicMacroItem {
     Press(OsCode),
@@ -56,18 +376,68 @@ pub struct Kanata {
     pub kbd_out: KbdOut,
     pub cfg_paths: Vec<PathBuf>,
     pub cur_cfg_idx: usize,
+    /// The index of the configuration file that the layout was built from, which differs from
+    /// `cur_cfg_idx` while a live reload of another file is pending.
+    loaded_cfg_idx: usize,
+    /// The hash of the configuration file that the layout was built from, for bug reports.
+    config_hash: String,
+    /// The latest release of kanata, if `check-for-updates` is enabled.
+    update_check: UpdateCheck,
+    /// The file and error of the last live reload if it failed.
+    reload_error: Option<(String, String)>,
     pub key_outputs: cfg::KeyOutputs,
     pub layout: cfg::KanataLayout,
     pub cur_keys: Vec<KeyCode>,
     pub prev_keys: Vec<KeyCode>,
     pub layer_info: Vec<LayerInfo>,
+    /// The defcfg items, compared with the new ones on live reload.
+    cfg_items: HashMap<String, String>,
     pub prev_layer: usize,
     pub scroll_state: Option<ScrollState>,
     pub hscroll_state: Option<ScrollState>,
     pub move_mouse_state_vertical: Option<MoveMouseState>,
     pub move_mouse_state_horizontal: Option<MoveMouseState>,
+    /// The mouse acceleration of keyberon layers, from `defmouseaccel`.
+    pub mouse_accel_layers: Vec<(usize, MouseAccel)>,
+    /// Translated output modifiers and the layers of language switch keys.
+    language_keys: LanguageKeys,
     pub sequence_timeout: u16,
     pub sequence_state: Option<SequenceState>,
+    /// The names of the `deflauncher` entries and the coordinates of their fake keys.
+    pub launcher_entries: Vec<(String, (u8, u16))>,
+    pub launcher_state: Option<LauncherState>,
+    /// The launcher state that TCP clients have not been notified of yet.
+    launcher_message: Option<ServerMessage>,
+    mod_chords: ModChords,
+    /// Keyboard-driven mouse warping started by `mouse-grid`.
+    pub mouse_grid: Option<MouseGridState>,
+    /// The mouse button held down by `mouse-drag`.
+    pub dragged_btn: Option<Btn>,
+    /// Clicks after the pointer stops, configured by `dwell-click-time`.
+    pub dwell_click: DwellClick,
+    /// Slow keys and bounce keys.
+    key_filter: KeyFilter,
+    /// What to do with autorepeat events, configured by `key-repeat`.
+    key_repeat: KeyRepeat,
+    event_stages: EventStages,
+    key_transforms: KeyTransforms,
+    modifier_taps: ModifierTaps,
+    /// Blocks the dangerous chords of `guard-chords`.
+    guard: ChordGuard,
+    /// Drops the output that `kiosk-allow` does not list.
+    kiosk: Kiosk,
+    /// The count typed on `count-layers` and `defmodal` layers, and the repeats of `counted`.
+    count_prefix: CountPrefix,
+    /// The operators of the modal editing layers of `defmodal`.
+    modal: Modal,
+    rate_limit: OutputRateLimit,
+    /// Macro and background output, written after the interactive output.
+    output_queue: OutputQueue,
+    layer_stack_log: LayerStackLog,
+    /// Text recently output by kanata, for short codes and snippets.
+    pub output_history: OutputHistory,
+    pub shortcodes: Shortcodes,
+    pub snippets: Vec<Snippet>,
     pub sequences: cfg::KeySeqsToFKeys,
     pub sequence_input_mode: SequenceInputMode,
     pub dynamic_macros: HashMap<u16, Vec<DynamicMacroItem>>,
@@ -75,14 +445,129 @@ pub struct Kanata {
     pub dynamic_macro_record_state: Option<DynamicMacroRecordState>,
     pub overrides: Overrides,
     pub override_states: OverrideStates,
+    pub hooks: Hooks,
+    pub fake_keys: HashMap<String, usize>,
+    /// Fake key names by column, so that logs can name fake keys and hooks.
+    fake_key_names: Vec<String>,
+    /// Variables set by `set-var` or TCP clients and read by `switch-var`.
+    pub runtime_vars: HashMap<String, String>,
+    /// When the actions of `cooldown` were last activated.
+    cooldowns: Cooldowns,
+    /// The focused window reported by TCP clients, which is passed to `cmd` actions.
+    pub active_window: Option<ActiveWindow>,
+    /// The presses in a row that commands are told about.
+    #[cfg(feature = "cmd")]
+    multi_press: MultiPress,
+    /// The clipboard read of a `clipboard-paste` action that is in progress.
+    #[cfg(feature = "clipboard")]
+    clipboard_paste: ClipboardPaste,
+    /// State changed by TCP clients, for `Undo`.
+    undo_history: UndoHistory,
+    /// Keys latched down by `toggle-key`. These are added to the output state every tick.
+    pub latched_keys: Vec<KeyCode>,
+    /// The keys that latched `latched_keys`, for `ClientMessage::RequestLocks`.
+    latch_audit: LatchAudit,
+    /// Saving and restoring of runtime state, configured by `persist-state-file`.
+    state_persistence: Option<StatePersistence>,
+    /// Counts of used bindings and layers, configured by `usage-stats-file`.
+    usage_stats: Option<UsageRecorder>,
+    /// Lifetime press counts of the input keys, kept in `key-counts-file`.
+    key_counts: KeyCounts,
+    /// The time that ticks are counted by.
+    pub clock: Clock,
     last_tick: time::Instant,
     live_reload_requested: bool,
+    /// The keys held while passthrough is on, `None` while it is off.
+    passthrough: Option<Vec<OsCode>>,
+    passthrough_requested: bool,
+    resync_modifiers_requested: bool,
+    /// The `kvm-target` activated during the tick, applied once the held keys are known.
+    kvm_switch_requested: Option<KvmSwitch>,
+    /// The configuration file loaded on top of the active one by `LoadOverlay`.
+    overlay: Overlay,
+    /// The layer activated for a fixed time by `temp-layer` or `ActivateTempLayer`.
+    temp_layer: Option<TempLayer>,
     #[cfg(target_os = "linux")]
     continue_if_no_devices: bool,
+    /// Whether devices with a touch surface are grabbed.
+    #[cfg(target_os = "linux")]
+    force: bool,
+    /// File descriptors of input devices that were opened before kanata started.
+    #[cfg(target_os = "linux")]
+    device_fds: Vec<i32>,
+    /// Whether devices are released while the logind session is inactive, and the session to
+    /// watch if it is not the one kanata runs in.
+    #[cfg(target_os = "linux")]
+    session_aware: Option<Option<String>>,
+    #[cfg(target_os = "linux")]
+    resume_detector: ResumeDetector,
+    /// Whether events are read from stdin instead of the input devices.
+    #[cfg(target_os = "linux")]
+    filter_mode: bool,
+    /// Whether the input devices are left ungrabbed and kanata only adds events to theirs.
+    #[cfg(target_os = "linux")]
+    compose_on_top: bool,
+    /// The settings the output was created with, to replace it when a live reload changes them.
+    #[cfg(target_os = "linux")]
+    output_device_cfg: OutputDeviceCfg,
+    #[cfg(target_os = "linux")]
+    symlink_path: Option<String>,
     #[cfg(all(feature = "interception_driver", target_os = "windows"))]
     intercept_mouse_hwid: Option<Vec<u8>>,
     log_layer_changes: bool,
     pub caps_word: Option<CapsWordState>,
+    pub morse: Option<MorseState>,
+    /// Code point input started by `unicode-input`.
+    pub unicode_input: Option<UnicodeInputState>,
+    pub swap_hands: SwapHandsState,
+    pub game_mode: GameMode,
+    /// Layers turned off by their tags.
+    layer_tags: LayerTags,
+    /// Whether layer changes from TCP clients wait until no keys are held, configured by
+    /// `defer-layer-changes`.
+    defer_layer_changes: bool,
+    /// Layer changes from TCP clients that wait for the held keys to be released.
+    deferred_layer_commands: Vec<KanataCommand>,
+    #[cfg(target_os = "linux")]
+    ime_passthrough: Option<ImePassthrough>,
+    #[cfg(target_os = "linux")]
+    led_indicator: Option<LedIndicator>,
+    /// The layer activated by pointing devices, see `linux-pointer-layer`.
+    #[cfg(target_os = "linux")]
+    pub pointer_layer: PointerLayer,
+    /// Pauses the layers while the screen is locked.
+    #[cfg(target_os = "linux")]
+    screen_lock: ScreenLockPause,
+    /// Turns layer tags on and off when input devices are plugged in or unplugged.
+    #[cfg(target_os = "linux")]
+    device_tags: DeviceTagRules,
+    /// Sets keyboard colors for layers through an OpenRGB server.
+    openrgb: Option<OpenRgb>,
+    /// Shows the active layer on a keyboard with a display.
+    layer_display: Option<LayerDisplay>,
+    input_sources: InputSources,
+    /// The other machines that the output is sent to, and the listener for theirs.
+    kvm: Kvm,
+    /// Where the output goes: this machine, the secondary device or another machine.
+    output_router: OutputRouter,
+    /// Layers that are active while the pointer is in a region of the screen.
+    pointer_regions: PointerRegions,
+    /// The helper processes of `defhelpers`.
+    helpers: Helpers,
+    /// Sends commands to the processing loop, set once it exists.
+    processing_tx: Option<Sender<ProcessingEvent>>,
+    /// Input devices with LEDs, opened by the event loop.
+    #[cfg(target_os = "linux")]
+    led_devices: Vec<evdev::Device>,
+    /// Keys matched by scancode, taken by the event loop when it starts.
+    #[cfg(target_os = "linux")]
+    scancode_map: Option<ScancodeMap>,
+    /// Set by the event loop once the devices are open.
+    #[cfg(target_os = "linux")]
+    pub device_grab_control: Option<DeviceGrabControl>,
+    /// Set if `--measure-latency` is passed.
+    #[cfg(target_os = "linux")]
+    latency_meter: Option<Arc<Mutex<LatencyMeter>>>,
 }
 
 pub struct ScrollState {
@@ -92,20 +577,27 @@ pub struct ScrollState {
     pub distance: u16,
 }
 
+impl ScrollState {
+    /// Scrolling counts down to the next scroll every tick.
+    pub fn ticks_until_due(&self) -> Option<u16> {
+        Some(1)
+    }
+}
+
 pub struct MoveMouseState {
     pub direction: MoveDirection,
     pub interval: u16,
     pub ticks_until_move: u16,
     pub distance: u16,
-    pub move_mouse_accel_state: Option<MoveMouseAccelState>,
+    pub ticks_since_press: u16,
+    pub accel: Option<MouseAccel>,
 }
 
-pub struct MoveMouseAccelState {
-    pub accel_ticks_from_min: u16,
-    pub accel_ticks_until_max: u16,
-    pub accel_increment: f64,
-    pub min_distance: u16,
-    pub max_distance: u16,
+impl MoveMouseState {
+    /// Mouse movement counts down to the next move and accelerates every tick.
+    pub fn ticks_until_due(&self) -> Option<u16> {
+        Some(1)
+    }
 }
 
 pub struct SequenceState {
@@ -113,6 +605,13 @@ pub struct SequenceState {
     pub ticks_until_timeout: u16,
 }
 
+impl SequenceState {
+    /// Sequence mode counts down its timeout every tick.
+    pub fn ticks_until_due(&self) -> Option<u16> {
+        Some(1)
+    }
+}
+
 /// This controls the behaviour of kanata when sequence mode is initiated by the sequence leader
 /// action.
 ///
@@ -152,6 +651,13 @@ pub struct DynamicMacroReplayState {
     pub macro_items: VecDeque<DynamicMacroItem>,
 }
 
+impl DynamicMacroReplayState {
+    /// The replay outputs an item every tick after the delay.
+    pub fn ticks_until_due(&self) -> Option<u16> {
+        Some(1)
+    }
+}
+
 pub struct DynamicMacroRecordState {
     pub starting_macro_id: u16,
     pub macro_items: Vec<DynamicMacroItem>,
@@ -212,17 +718,53 @@ impl Kanata {
             })
             .unwrap_or_default();
 
-        let kbd_out = match KbdOut::new(
+        #[cfg(target_os = "linux")]
+        let mut output_device_cfg = parse_output_device_cfg(&cfg.items)?;
+        #[cfg(target_os = "linux")]
+        if args.filter {
+            if output_device_cfg.split_mouse {
+                bail!("linux-output-split-mouse is not supported with --filter");
+            }
+            output_device_cfg.backend = OutputBackend::Stdout;
+        }
+
+        // The Wayland backend sends its own keymap, so the layout of the session does not matter.
+        #[cfg(all(target_os = "linux", feature = "wayland"))]
+        if !matches!(output_device_cfg.backend, OutputBackend::Wayland { .. }) {
+            check_xkb_layout(&cfg.items);
+        }
+        #[cfg(all(target_os = "linux", not(feature = "wayland")))]
+        check_xkb_layout(&cfg.items);
+
+        let mut kbd_out = match KbdOut::new(
             #[cfg(target_os = "linux")]
             &args.symlink_path,
+            #[cfg(target_os = "linux")]
+            &output_device_cfg,
         ) {
             Ok(kbd_out) => kbd_out,
             Err(err) => {
+                // On Linux, the error explains how to fix the usual problems with uinput.
+                #[cfg(target_os = "linux")]
+                error!("Failed to create the output device");
+                #[cfg(not(target_os = "linux"))]
                 error!("Failed to open the output uinput device. Make sure you've added kanata to the `uinput` group");
                 bail!(err)
             }
         };
 
+        #[cfg(target_os = "linux")]
+        let compose_on_top = !args.filter
+            && cfg
+                .items
+                .get("linux-compose-on-top")
+                .is_some_and(|s| matches!(s.to_lowercase().as_str(), "yes" | "true"));
+        #[cfg(target_os = "linux")]
+        if compose_on_top {
+            log::info!("compose-on-top mode: not grabbing the input devices");
+            kbd_out.compose_on_top();
+        }
+
         #[cfg(target_os = "linux")]
         let kbd_in_paths = cfg
             .items
@@ -262,31 +804,144 @@ impl Kanata {
             .get("log-layer-changes")
             .map(|s| !matches!(s.to_lowercase().as_str(), "no" | "false" | "0"))
             .unwrap_or(true);
+        let defer_layer_changes = cfg
+            .items
+            .get(DEFER_LAYER_CHANGES_CFG_NAME)
+            .is_some_and(|s| matches!(s.to_lowercase().as_str(), "yes" | "true"));
 
+        #[cfg(target_os = "linux")]
+        let ime_passthrough = ImePassthrough::from_cfg(&cfg.items, &cfg.layer_info)?;
+        #[cfg(target_os = "linux")]
+        let led_indicator = LedIndicator::from_cfg(&cfg.items, &cfg.layer_info)?;
+        #[cfg(target_os = "linux")]
+        let pointer_layer = PointerLayer::from_cfg(&cfg.items, &cfg.layer_info)?;
+        #[cfg(target_os = "linux")]
+        let mut screen_lock = ScreenLockPause::default();
+        #[cfg(target_os = "linux")]
+        screen_lock.update_from_cfg(&cfg.items);
+        #[cfg(not(target_os = "linux"))]
+        if !cfg.device_tags.is_empty() {
+            log::warn!("defdevicetags has no effect on this platform");
+        }
+        let openrgb = OpenRgb::from_cfg(&cfg.items, &cfg.layer_info)?;
+        let layer_display = LayerDisplay::from_cfg(&cfg.items)?;
+        let input_sources = InputSources::from_cfg(&cfg.items, &cfg.mapped_keys)?;
+        let kvm = Kvm::from_cfg(&cfg.items)?;
+        let output_router = OutputRouter::from_cfg(&cfg.items, &cfg.layer_info, &kvm)?;
+        let pointer_regions = PointerRegions::from_cfg(&cfg.items, &cfg.layer_info)?;
+        let mut game_mode = GameMode::default();
+        game_mode.update_from_cfg(&cfg.items, &cfg.layer_info)?;
+        let mut layer_tags = LayerTags::default();
+        layer_tags.update_from_cfg(&cfg.layer_info);
+        let language_keys = LanguageKeys::new(&cfg.mod_translations, &cfg.items, &cfg.layer_info)?;
+        let mut dwell_click = DwellClick::default();
+        dwell_click.update_from_cfg(&cfg.items)?;
+        let mut key_filter = KeyFilter::default();
+        key_filter.update_from_cfg(&cfg.items)?;
+        let mut key_repeat = KeyRepeat::default();
+        key_repeat.update_from_cfg(&cfg.items)?;
+        let mut event_stages = EventStages::default();
+        event_stages.update_from_cfg(&cfg.items)?;
+        let key_transforms = KeyTransforms::from_cfg(&cfg.items, &cfg.mapped_keys)?;
+        let modifier_taps = ModifierTaps::from_cfg(&cfg.items, &cfg.mapped_keys)?;
+        let guard = ChordGuard::from_cfg(&cfg.items, &cfg.layer_info)?;
+        let kiosk = Kiosk::from_cfg(&cfg.items, &cfg.layer_info)?;
+        let count_prefix = CountPrefix::from_cfg(&cfg.items, &cfg.layer_info)?;
+        #[cfg(feature = "cmd")]
+        let mut multi_press = MultiPress::default();
+        #[cfg(feature = "cmd")]
+        multi_press.update_from_cfg(&cfg.items)?;
+        let mut rate_limit = OutputRateLimit::default();
+        rate_limit.update_from_cfg(&cfg.items)?;
+        let mut output_queue = OutputQueue::default();
+        output_queue.update_from_cfg(&cfg.items)?;
+        let mut layer_stack_log = LayerStackLog::default();
+        layer_stack_log.update_from_cfg(&cfg.items);
+        let mut update_check = UpdateCheck::default();
+        update_check.update_from_cfg(&cfg.items);
+        let startup_layers = startup_layers(&cfg.items, &cfg.layer_info)?;
+        crate::logging::set_filter(cfg.items.get(LOG_FILTER_CFG_NAME).map_or("", |s| s))?;
+        #[cfg(target_os = "linux")]
+        let scancode_map = ScancodeMap::from_cfg(&cfg.items)?;
+
+        CRASH_DUMP.update_from_cfg(&cfg.items, &cfg.layer_info);
+        EXIT_COMBO.lock().update_from_cfg(&cfg.items)?;
         *MAPPED_KEYS.lock() = cfg.mapped_keys;
 
-        Ok(Self {
+        let mut kanata = Self {
             kbd_in_paths,
             kbd_out,
             cfg_paths: args.paths.clone(),
             cur_cfg_idx: 0,
+            loaded_cfg_idx: 0,
+            config_hash: config_hash(&args.paths[0]),
+            update_check,
+            reload_error: None,
             key_outputs: cfg.key_outputs,
             layout: cfg.layout,
             layer_info: cfg.layer_info,
             cur_keys: Vec::new(),
             prev_keys: Vec::new(),
+            cfg_items: cfg.items.clone(),
             prev_layer: 0,
             scroll_state: None,
             hscroll_state: None,
             move_mouse_state_vertical: None,
             move_mouse_state_horizontal: None,
+            mouse_accel_layers: cfg.mouse_accel_layers,
+            language_keys,
             sequence_timeout,
             sequence_state: None,
+            launcher_entries: cfg.launcher,
+            launcher_state: None,
+            launcher_message: None,
+            mod_chords: ModChords::new(cfg.mod_chords),
+            mouse_grid: None,
+            dragged_btn: None,
+            dwell_click,
+            key_filter,
+            key_repeat,
+            event_stages,
+            key_transforms,
+            modifier_taps,
+            guard,
+            kiosk,
+            count_prefix,
+            modal: Modal::new(cfg.modal_layers),
+            rate_limit,
+            output_queue,
+            layer_stack_log,
+            output_history: OutputHistory::from_cfg(&cfg.items),
+            shortcodes: Shortcodes::new(cfg.shortcodes),
+            snippets: cfg.snippets,
             sequences: cfg.sequences,
             sequence_input_mode,
+            clock: Clock::System,
             last_tick: time::Instant::now(),
             live_reload_requested: false,
+            passthrough: None,
+            passthrough_requested: false,
+            resync_modifiers_requested: false,
+            kvm_switch_requested: None,
+            overlay: Overlay::default(),
+            temp_layer: None,
             overrides: cfg.overrides,
+            hooks: cfg.hooks,
+            fake_key_names: fake_key_names(&cfg.fake_keys),
+            fake_keys: cfg.fake_keys,
+            runtime_vars: HashMap::default(),
+            cooldowns: Cooldowns::default(),
+            active_window: None,
+            #[cfg(feature = "cmd")]
+            multi_press,
+            #[cfg(feature = "clipboard")]
+            clipboard_paste: ClipboardPaste::default(),
+            undo_history: UndoHistory::default(),
+            latched_keys: vec![],
+            latch_audit: LatchAudit::default(),
+            state_persistence: StatePersistence::from_cfg(&cfg.items),
+            usage_stats: UsageRecorder::from_cfg(&cfg.items, time::Instant::now()),
+            key_counts: KeyCounts::from_cfg(&cfg.items, time::Instant::now()),
             override_states: OverrideStates::new(),
             #[cfg(target_os = "linux")]
             continue_if_no_devices: cfg
@@ -294,6 +949,26 @@ impl Kanata {
                 .get("linux-continue-if-no-devs-found")
                 .map(|s| matches!(s.to_lowercase().as_str(), "yes" | "true"))
                 .unwrap_or_default(),
+            #[cfg(target_os = "linux")]
+            resume_detector: ResumeDetector::default(),
+            #[cfg(target_os = "linux")]
+            session_aware: cfg
+                .items
+                .get("linux-session-aware")
+                .is_some_and(|s| matches!(s.to_lowercase().as_str(), "yes" | "true"))
+                .then(|| cfg.items.get("linux-session-id").cloned()),
+            #[cfg(target_os = "linux")]
+            filter_mode: args.filter,
+            #[cfg(target_os = "linux")]
+            compose_on_top,
+            #[cfg(target_os = "linux")]
+            output_device_cfg,
+            #[cfg(target_os = "linux")]
+            symlink_path: args.symlink_path.clone(),
+            #[cfg(target_os = "linux")]
+            force: args.force,
+            #[cfg(target_os = "linux")]
+            device_fds: args.device_fds.clone(),
             #[cfg(all(feature = "interception_driver", target_os = "windows"))]
             intercept_mouse_hwid,
             dynamic_macro_replay_state: None,
@@ -301,7 +976,69 @@ impl Kanata {
             dynamic_macros: Default::default(),
             log_layer_changes,
             caps_word: None,
-        })
+            morse: None,
+            unicode_input: None,
+            swap_hands: SwapHandsState::default(),
+            game_mode,
+            layer_tags,
+            defer_layer_changes,
+            deferred_layer_commands: vec![],
+            #[cfg(target_os = "linux")]
+            ime_passthrough,
+            #[cfg(target_os = "linux")]
+            led_indicator,
+            #[cfg(target_os = "linux")]
+            pointer_layer,
+            #[cfg(target_os = "linux")]
+            screen_lock,
+            #[cfg(target_os = "linux")]
+            device_tags: DeviceTagRules::new(cfg.device_tags),
+            openrgb,
+            layer_display,
+            input_sources,
+            kvm,
+            output_router,
+            pointer_regions,
+            helpers: Helpers::new(cfg.helpers),
+            processing_tx: None,
+            #[cfg(target_os = "linux")]
+            led_devices: vec![],
+            #[cfg(target_os = "linux")]
+            scancode_map,
+            #[cfg(target_os = "linux")]
+            device_grab_control: None,
+            #[cfg(target_os = "linux")]
+            latency_meter: args
+                .measure_latency
+                .then(|| Arc::new(Mutex::new(LatencyMeter::default()))),
+        };
+        activate_startup_layers(kanata.layout.bm(), &startup_layers);
+        kanata.restore_persisted_state();
+        if let Some(recorder) = &mut kanata.usage_stats {
+            recorder.layer_activated(&kanata.layer_info[kanata.layout.b().current_layer()].name);
+        }
+        if let Some(rgb) = &kanata.openrgb {
+            rgb.layer_changed(kanata.layout.b().current_layer());
+        }
+        if let Some(display) = &kanata.layer_display {
+            display.layer_changed(kanata.layout.b().current_layer(), &kanata.layer_info);
+        }
+        Ok(kanata)
+    }
+
+    /// Give kanata the sender of the processing loop, for the parts that send it commands, e.g.
+    /// `layer-display-sync`.
+    pub fn set_processing_tx(&mut self, tx: Sender<ProcessingEvent>) {
+        if let Some(display) = &self.layer_display {
+            display.start_sync(tx.clone(), &self.layer_info);
+        }
+        self.input_sources.start(&tx);
+        self.kvm.start(&tx);
+        self.pointer_regions.start(&tx);
+        self.helpers.start();
+        #[cfg(target_os = "linux")]
+        self.screen_lock.start_watching(&tx);
+        self.processing_tx = Some(tx);
     }
 
     /// Create a new configuration from a file, wrapped in an Arc<Mutex<_>>
@@ -310,14 +1047,20 @@ impl Kanata {
     }
 
     fn do_live_reload(&mut self) -> Result<()> {
-        let cfg = match cfg::new_from_file(&self.cfg_paths[self.cur_cfg_idx]) {
+        let cfg = match self.parse_cfg_with_overlay() {
             Ok(c) => c,
             Err(e) => {
                 log::error!("{e:?}");
-                bail!("failed to parse config file");
+                bail!("failed to parse config file: {e}");
             }
         };
         update_kbd_out(&cfg.items, &self.kbd_out)?;
+        // The output of filter mode is stdout, whatever the configuration says.
+        #[cfg(target_os = "linux")]
+        let output_device_cfg = match self.filter_mode {
+            true => self.output_device_cfg.clone(),
+            false => parse_output_device_cfg(&cfg.items)?,
+        };
         set_altgr_behaviour(&cfg).map_err(|e| anyhow!("failed to set altgr behaviour {e})"))?;
         self.sequence_timeout = cfg
             .items
@@ -337,28 +1080,163 @@ impl Kanata {
             .get("log-layer-changes")
             .map(|s| !matches!(s.to_lowercase().as_str(), "no" | "false" | "0"))
             .unwrap_or(true);
+        #[cfg(target_os = "linux")]
+        {
+            self.ime_passthrough = ImePassthrough::from_cfg(&cfg.items, &cfg.layer_info)?;
+            self.led_indicator = LedIndicator::from_cfg(&cfg.items, &cfg.layer_info)?;
+            self.pointer_layer = PointerLayer::from_cfg(&cfg.items, &cfg.layer_info)?;
+            self.screen_lock.update_from_cfg(&cfg.items);
+        }
+        self.openrgb = OpenRgb::from_cfg(&cfg.items, &cfg.layer_info)?;
+        self.layer_display = LayerDisplay::from_cfg(&cfg.items)?;
+        if let (Some(display), Some(tx)) = (&self.layer_display, &self.processing_tx) {
+            display.start_sync(tx.clone(), &cfg.layer_info);
+        }
+        let input_sources = InputSources::from_cfg(&cfg.items, &cfg.mapped_keys)?;
+        if !input_sources.same_as(&self.input_sources) {
+            self.input_sources = input_sources;
+            if let Some(tx) = &self.processing_tx {
+                self.input_sources.start(tx);
+            }
+        }
+        self.replace_kvm(Kvm::from_cfg(&cfg.items)?);
+        self.output_router
+            .update_from_cfg(&cfg.items, &cfg.layer_info, &self.kvm)?;
+        self.replace_pointer_regions(PointerRegions::from_cfg(&cfg.items, &cfg.layer_info)?);
+        self.helpers.update_from_cfg(cfg.helpers);
+        self.game_mode
+            .update_from_cfg(&cfg.items, &cfg.layer_info)?;
+        self.layer_tags.update_from_cfg(&cfg.layer_info);
+        let language_keys = LanguageKeys::new(&cfg.mod_translations, &cfg.items, &cfg.layer_info)?;
+        self.defer_layer_changes = cfg
+            .items
+            .get(DEFER_LAYER_CHANGES_CFG_NAME)
+            .is_some_and(|s| matches!(s.to_lowercase().as_str(), "yes" | "true"));
+        self.dwell_click.update_from_cfg(&cfg.items)?;
+        self.key_filter.update_from_cfg(&cfg.items)?;
+        self.key_repeat.update_from_cfg(&cfg.items)?;
+        self.event_stages.update_from_cfg(&cfg.items)?;
+        self.key_transforms = KeyTransforms::from_cfg(&cfg.items, &cfg.mapped_keys)?;
+        self.modifier_taps = ModifierTaps::from_cfg(&cfg.items, &cfg.mapped_keys)?;
+        self.guard = ChordGuard::from_cfg(&cfg.items, &cfg.layer_info)?;
+        self.kiosk = Kiosk::from_cfg(&cfg.items, &cfg.layer_info)?;
+        self.count_prefix = CountPrefix::from_cfg(&cfg.items, &cfg.layer_info)?;
+        self.modal = Modal::new(cfg.modal_layers);
+        #[cfg(feature = "cmd")]
+        self.multi_press.update_from_cfg(&cfg.items)?;
+        self.cooldowns.clear();
+        self.rate_limit.update_from_cfg(&cfg.items)?;
+        self.output_queue.update_from_cfg(&cfg.items)?;
+        self.layer_stack_log.update_from_cfg(&cfg.items);
+        self.update_check.update_from_cfg(&cfg.items);
+        let startup_layers = startup_layers(&cfg.items, &cfg.layer_info)?;
+        CRASH_DUMP.update_from_cfg(&cfg.items, &cfg.layer_info);
+        EXIT_COMBO.lock().update_from_cfg(&cfg.items)?;
+        crate::logging::set_filter(cfg.items.get(LOG_FILTER_CFG_NAME).map_or("", |s| s))?;
+        let diff = CfgDiff::new(&self.layer_info, &self.cfg_items, &cfg.layer_info, &cfg.items);
+        let kept_layers = self.kept_layers();
         self.layout = cfg.layout;
         self.key_outputs = cfg.key_outputs;
         self.layer_info = cfg.layer_info;
         self.sequences = cfg.sequences;
         self.overrides = cfg.overrides;
+        self.hooks = cfg.hooks;
+        self.fake_key_names = fake_key_names(&cfg.fake_keys);
+        self.fake_keys = cfg.fake_keys;
+        self.launcher_entries = cfg.launcher;
+        self.mod_chords = ModChords::new(cfg.mod_chords);
+        self.mouse_accel_layers = cfg.mouse_accel_layers;
+        self.language_keys = language_keys;
+        self.output_history = OutputHistory::from_cfg(&cfg.items);
+        self.shortcodes = Shortcodes::new(cfg.shortcodes);
+        self.snippets = cfg.snippets;
         self.log_layer_changes = log_layer_changes;
+        self.state_persistence = StatePersistence::from_cfg(&cfg.items);
+        if let Some(recorder) = &mut self.usage_stats {
+            recorder.save(self.clock.now());
+        }
+        self.usage_stats = UsageRecorder::from_cfg(&cfg.items, self.clock.now());
+        self.key_counts.update_from_cfg(&cfg.items, self.clock.now());
         *MAPPED_KEYS.lock() = cfg.mapped_keys;
-        log::info!("Live reload successful");
+        self.cfg_items = cfg.items;
+        self.config_hash = config_hash(&self.cfg_paths[self.cur_cfg_idx]);
+        activate_startup_layers(self.layout.bm(), &startup_layers);
+        self.restore_kept_layers(kept_layers);
+        self.restore_pointer_region_layer();
+        self.overlay.reloaded();
+        self.activate_overlay_layer();
+        self.restore_temp_layer();
+        #[cfg(target_os = "linux")]
+        {
+            self.clear_paused_layers();
+            if let Some(tx) = &self.processing_tx {
+                self.screen_lock.start_watching(tx);
+            }
+            self.reload_device_tags(cfg.device_tags);
+        }
+        if let Some(rgb) = &self.openrgb {
+            rgb.layer_changed(self.layout.b().current_layer());
+        }
+        if let Some(display) = &self.layer_display {
+            display.layer_changed(self.layout.b().current_layer(), &self.layer_info);
+        }
+        #[cfg(target_os = "linux")]
+        self.replace_output(output_device_cfg);
+        log::info!("Live reload successful, {}", diff.summary());
         Ok(())
     }
 
+    /// Replace the output if the live reload changed its settings, e.g. the backend. If the new
+    /// output cannot be created, the current one is kept. Held keys are pressed again on the new
+    /// output.
+    #[cfg(target_os = "linux")]
+    fn replace_output(&mut self, device_cfg: OutputDeviceCfg) {
+        if device_cfg == self.output_device_cfg {
+            return;
+        }
+        if let Err(e) = self.kbd_out.replace(&self.symlink_path, &device_cfg) {
+            log::warn!("keeping the current output, failed to create the new one: {e}");
+            return;
+        }
+        log::info!("output replaced");
+        self.output_device_cfg = device_cfg;
+        self.prev_keys.clear();
+    }
+
     /// Update keyberon layout state for press/release, handle repeat separately
     fn handle_key_event(&mut self, event: &KeyEvent) -> Result<()> {
+        CRASH_DUMP.record_event(event);
+        #[cfg(target_os = "linux")]
+        self.release_held_keys_after_resume();
+        if event.value == KeyValue::Press {
+            self.key_counts.press(event.code);
+        }
+        self.process_key_event(*event)?;
+        self.update_crash_dump();
+        Ok(())
+    }
+
+    /// Process a key event that passed the event stages.
+    fn handle_layout_event(&mut self, event: &KeyEvent) -> Result<()> {
         let evc: u16 = event.code.into();
+        let cur_layer = self.layout.b().current_layer();
+        self.modifier_taps.input(event);
+        if let Some(recorder) = &mut self.usage_stats {
+            recorder.key_event(event.code, event.value, self.clock.now());
+        }
         let kbrn_ev = match event.value {
             KeyValue::Press => {
+                play_sound(&self.layer_info, cur_layer, SoundEvent::Press);
+                self.record_key_usage(event.code);
+                #[cfg(feature = "cmd")]
+                self.multi_press.press(event.code, self.clock.now());
                 if let Some(state) = &mut self.dynamic_macro_record_state {
                     state.macro_items.push(DynamicMacroItem::Press(event.code));
                 }
                 Event::Press(0, evc)
             }
             KeyValue::Release => {
+                play_sound(&self.layer_info, cur_layer, SoundEvent::Release);
                 if let Some(state) = &mut self.dynamic_macro_record_state {
                     state
                         .macro_items
@@ -367,35 +1245,98 @@ impl Kanata {
                 Event::Release(0, evc)
             }
             KeyValue::Repeat => {
-                let ret = self.handle_repeat(event);
-                return ret;
+                return self.handle_repeat_policy(event);
             }
         };
         self.layout.bm().event(kbrn_ev);
+        if let Some((x, y)) = self.mod_chords.key(event) {
+            log::debug!(
+                "modifier chord pressed, tapping {}",
+                fake_key_name(&self.fake_key_names, y)
+            );
+            self.layout.bm().event(Event::Press(x, y));
+            self.layout.bm().event(Event::Release(x, y));
+        }
         Ok(())
     }
 
     /// Advance keyberon layout state and send events based on changes to its state.
     fn handle_time_ticks(&mut self, tx: &Option<Sender<ServerMessage>>) -> Result<()> {
-        let now = time::Instant::now();
+        let now = self.clock.now();
         let ms_elapsed = now.duration_since(self.last_tick).as_millis();
+        #[cfg(target_os = "linux")]
+        self.release_held_keys_after_resume();
 
         for _ in 0..ms_elapsed {
+            if let Some(code) = self.key_filter.tick() {
+                log::debug!("slow keys: accepting {code:?}");
+                self.process_slow_key_press(code)?;
+            }
+            self.rate_limit.tick();
+            self.layout.bm().sequences_paused =
+                !self.rate_limit.allows() || self.output_queue.yielding();
             self.live_reload_requested |= self.handle_keystate_changes()?;
+            if std::mem::take(&mut self.passthrough_requested) {
+                self.set_passthrough(true);
+            }
+            self.layer_tags.enforce(self.layout.bm());
+            if !self.deferred_layer_commands.is_empty() && !keys_are_held(self.layout.b()) {
+                for command in std::mem::take(&mut self.deferred_layer_commands) {
+                    self.handle_command(command, tx);
+                }
+            }
+            if let Some(tx) = tx {
+                self.send_key_output_notifications(tx);
+            }
+            self.layer_stack_log.tick(self.layout.b(), &self.layer_info);
             self.handle_scrolling()?;
             self.handle_move_mouse()?;
             self.tick_sequence_state()?;
             self.tick_dynamic_macro_state()?;
+            self.tick_counted_repeats();
+            #[cfg(feature = "clipboard")]
+            self.tick_clipboard_paste();
+            self.tick_output_queue()?;
+            self.tick_morse_state()?;
+            self.tick_launcher_state();
+            self.tick_mouse_grid_state();
+            self.tick_dwell_click()?;
+            #[cfg(target_os = "linux")]
+            self.tick_pointer_layer();
+            self.tick_temp_layer();
+            if let Some(message) = self.launcher_message.take() {
+                if let Some(tx) = tx {
+                    if let Err(e) = tx.send(message) {
+                        log::error!("could not send event notification: {e}");
+                    }
+                }
+            }
 
-            if self.live_reload_requested && self.prev_keys.is_empty() && self.cur_keys.is_empty() {
+            // Latched keys are held by kanata rather than the layout, so they stay latched across
+            // the reload.
+            let only_latched =
+                |keys: &[KeyCode]| keys.iter().all(|k| self.latched_keys.contains(k));
+            if self.live_reload_requested
+                && only_latched(&self.prev_keys)
+                && only_latched(&self.cur_keys)
+            {
                 self.live_reload_requested = false;
-                if let Err(e) = self.do_live_reload() {
-                    log::error!("live reload failed {e}");
-                }
+                self.live_reload(tx);
             }
 
             self.prev_keys.clear();
             self.prev_keys.append(&mut self.cur_keys);
+            if std::mem::take(&mut self.resync_modifiers_requested) {
+                self.resync_modifiers();
+            }
+            if let Some(switch) = self.kvm_switch_requested.take() {
+                #[cfg(target_os = "linux")]
+                self.switch_kvm_target(&switch);
+                #[cfg(not(target_os = "linux"))]
+                log::warn!("kvm-target {switch:?} is only supported on Linux");
+            }
+            #[cfg(target_os = "linux")]
+            self.route_output();
         }
 
         if ms_elapsed > 0 {
@@ -408,12 +1349,17 @@ impl Kanata {
                 // 1000 ticks in 1ms on average. In practice, there will already be fewer than 1000
                 // ticks in 1ms to the expensive operations, this just avoids having tens to
                 // thousands of ticks all happening as soon as the expensive operation ends.
-                _ => time::Instant::now(),
+                _ => self.clock.now(),
             };
 
             // Handle layer change outside the loop. I don't see any practical scenario where it
             // would make a difference, so may as well reduce the amount of processing.
             self.check_handle_layer_change(tx);
+            self.save_persisted_state();
+            if let Some(recorder) = &mut self.usage_stats {
+                recorder.save_if_due(self.clock.now());
+            }
+            self.key_counts.save_if_due(self.clock.now());
         }
 
         Ok(())
@@ -442,42 +1388,30 @@ impl Kanata {
     }
 
     fn handle_move_mouse(&mut self) -> Result<()> {
-        if let Some(mmsv) = &mut self.move_mouse_state_vertical {
-            if let Some(mmas) = &mut mmsv.move_mouse_accel_state {
-                if mmas.accel_ticks_until_max != 0 {
-                    let increment =
-                        (mmas.accel_increment * f64::from(mmas.accel_ticks_from_min)) as u16;
-                    mmsv.distance = mmas.min_distance + increment;
-                    mmas.accel_ticks_from_min += 1;
-                    mmas.accel_ticks_until_max -= 1;
-                } else {
-                    mmsv.distance = mmas.max_distance;
-                }
-            }
-            if mmsv.ticks_until_move == 0 {
-                mmsv.ticks_until_move = mmsv.interval - 1;
-                self.kbd_out.move_mouse(mmsv.direction, mmsv.distance)?;
-            } else {
-                mmsv.ticks_until_move -= 1;
-            }
-        }
-        if let Some(mmsh) = &mut self.move_mouse_state_horizontal {
-            if let Some(mmas) = &mut mmsh.move_mouse_accel_state {
-                if mmas.accel_ticks_until_max != 0 {
-                    let increment =
-                        (mmas.accel_increment * f64::from(mmas.accel_ticks_from_min)) as u16;
-                    mmsh.distance = mmas.min_distance + increment;
-                    mmas.accel_ticks_from_min += 1;
-                    mmas.accel_ticks_until_max -= 1;
-                } else {
-                    mmsh.distance = mmas.max_distance;
-                }
+        // A `defmouseaccel` of the active layer takes precedence over the action's own distance.
+        let current_layer = self.layout.b().current_layer();
+        let layer_accel = self
+            .mouse_accel_layers
+            .iter()
+            .find(|(layer, _)| *layer == current_layer)
+            .map(|(_, accel)| *accel);
+        for mms in [
+            &mut self.move_mouse_state_vertical,
+            &mut self.move_mouse_state_horizontal,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if let Some(accel) = layer_accel.or(mms.accel) {
+                mms.distance = accel.distance(mms.ticks_since_press);
             }
-            if mmsh.ticks_until_move == 0 {
-                mmsh.ticks_until_move = mmsh.interval - 1;
-                self.kbd_out.move_mouse(mmsh.direction, mmsh.distance)?;
+            mms.ticks_since_press = mms.ticks_since_press.saturating_add(1);
+            if mms.ticks_until_move == 0 {
+                mms.ticks_until_move = mms.interval - 1;
+                self.kbd_out.move_mouse(mms.direction, mms.distance)?;
+                self.dwell_click.moved();
             } else {
-                mmsh.ticks_until_move -= 1;
+                mms.ticks_until_move -= 1;
             }
         }
         Ok(())
@@ -505,9 +1439,54 @@ impl Kanata {
         Ok(())
     }
 
+    fn tick_launcher_state(&mut self) {
+        if let Some(launcher) = &mut self.launcher_state {
+            launcher.ticks_until_timeout -= 1;
+            if launcher.ticks_until_timeout == 0 {
+                log::debug!("launcher timeout; exiting launcher");
+                self.launcher_message = Some(ServerMessage::Launcher {
+                    input: std::mem::take(&mut launcher.input),
+                    best_match: None,
+                    active: false,
+                });
+                self.launcher_state = None;
+            }
+        }
+    }
+
+    fn tick_mouse_grid_state(&mut self) {
+        if let Some(grid) = &mut self.mouse_grid {
+            grid.ticks_until_timeout -= 1;
+            if grid.ticks_until_timeout == 0 {
+                log::debug!("mouse grid timeout; exiting mouse grid");
+                self.mouse_grid = None;
+            }
+        }
+    }
+
+    fn tick_dwell_click(&mut self) -> Result<()> {
+        if !self.dwell_click.tick() {
+            return Ok(());
+        }
+        // A dwell ends a drag like a click in the mouse grid does.
+        if let Some(dragged) = self.dragged_btn.take() {
+            log::debug!("dwell drop {dragged:?}");
+            self.kbd_out.release_btn(dragged)?;
+        } else {
+            log::debug!("dwell click");
+            self.kbd_out.click_btn(Btn::Left)?;
+            self.kbd_out.release_btn(Btn::Left)?;
+        }
+        self.output_history.clear();
+        Ok(())
+    }
+
     fn tick_dynamic_macro_state(&mut self) -> Result<()> {
         let mut clear_replaying_macro = false;
         if let Some(state) = &mut self.dynamic_macro_replay_state {
+            if !self.rate_limit.allows() || self.output_queue.yielding() {
+                return Ok(());
+            }
             state.delay_remaining = state.delay_remaining.saturating_sub(1);
             if state.delay_remaining == 0 {
                 match state.macro_items.pop_front() {
@@ -534,6 +1513,39 @@ impl Kanata {
         Ok(())
     }
 
+    fn tick_counted_repeats(&mut self) {
+        if let Some(event) = self.count_prefix.next_repeat_event() {
+            self.layout.bm().event(event);
+        }
+    }
+
+    /// Types the character of a finished morse sequence, or a space after a word gap.
+    fn tick_morse_state(&mut self) -> Result<()> {
+        let Some(morse) = &mut self.morse else {
+            return Ok(());
+        };
+        let (output, next_state) = morse.tick();
+        match output {
+            Some(MorseOutput::Char(c)) => {
+                log::debug!("morse typing {c}");
+                let osc = str_to_oscode(&c.to_string()).expect("morse chars are valid keys");
+                self.kbd_out.press_key(osc)?;
+                self.kbd_out.release_key(osc)?;
+            }
+            Some(MorseOutput::WordGap) => {
+                log::debug!("morse word gap");
+                self.kbd_out.press_key(OsCode::KEY_SPACE)?;
+                self.kbd_out.release_key(OsCode::KEY_SPACE)?;
+            }
+            Some(MorseOutput::Invalid) => log::debug!("morse input did not match a character"),
+            None => {}
+        }
+        if next_state == MorseNextState::End {
+            self.morse = None;
+        }
+        Ok(())
+    }
+
     /// Sends OS key events according to the change in key state between the current and the
     /// previous keyberon keystate. Also processes any custom actions.
     ///
@@ -543,11 +1555,38 @@ impl Kanata {
     fn handle_keystate_changes(&mut self) -> Result<bool> {
         let layout = self.layout.bm();
         let custom_event = layout.tick();
+        if let Some((coord, resolution)) = layout.hold_tap_resolution.take() {
+            if let (Some(recorder), HoldTapResolution::Hold, (0, j)) =
+                (&mut self.usage_stats, resolution, coord)
+            {
+                if let Some(key) = OsCode::from_u16(j) {
+                    recorder.hold_resolved(key);
+                }
+            }
+            let (event, hook) = match resolution {
+                HoldTapResolution::Tap => (SoundEvent::Tap, self.hooks.tap_hold_tap),
+                HoldTapResolution::Hold => (SoundEvent::Hold, self.hooks.tap_hold_hold),
+            };
+            play_sound(&self.layer_info, layout.current_layer(), event);
+            if let Some((x, y)) = hook {
+                log::debug!("tap-hold resolved as {resolution:?}, tapping hook");
+                layout.event(Event::Press(x, y));
+                layout.event(Event::Release(x, y));
+            }
+        }
         let mut live_reload_requested = false;
         let cur_keys = &mut self.cur_keys;
         cur_keys.extend(layout.keycodes());
+        for k in &self.latched_keys {
+            if !cur_keys.contains(k) {
+                cur_keys.push(*k);
+            }
+        }
         self.overrides
             .override_keys(cur_keys, &mut self.override_states);
+        self.language_keys
+            .translate(cur_keys, layout.current_layer());
+        self.key_transforms.transform_output(cur_keys);
         if let Some(caps_word) = &mut self.caps_word {
             if caps_word.maybe_add_lsft(cur_keys) == CapsWordNextState::End {
                 self.caps_word = None;
@@ -562,9 +1601,24 @@ impl Kanata {
                 continue;
             }
             log::debug!("key release   {:?}", k);
+            if let Some(mask) = self.modifier_taps.output_released(k.into()) {
+                log::debug!("masking the tap of {k:?} with {mask:?}");
+                self.kbd_out.press_key(mask)?;
+                self.kbd_out.release_key(mask)?;
+            }
             if let Err(e) = self.kbd_out.release_key(k.into()) {
                 bail!("failed to release key: {:?}", e);
             }
+            self.rate_limit.record();
+            #[cfg(target_os = "linux")]
+            if let Some(leds) = &mut self.led_indicator {
+                if matches!(
+                    k,
+                    KeyCode::CapsLock | KeyCode::NumLock | KeyCode::ScrollLock
+                ) {
+                    leds.mark_dirty();
+                }
+            }
         }
 
         // Press keys that exist in the current state but are missing from the previous state.
@@ -576,12 +1630,144 @@ impl Kanata {
                 continue;
             }
             LAST_PRESSED_KEY.store(OsCode::from(k).into(), SeqCst);
+            if let Some(input) = &mut self.unicode_input {
+                log::debug!("unicode input got {k:?}");
+                let next = input.key(*k);
+                end_unicode_input(&mut self.unicode_input, &mut self.kbd_out, next)?;
+                continue;
+            }
+            if let Some(launcher) = &mut self.launcher_state {
+                log::debug!("launcher got {k:?}");
+                launcher.ticks_until_timeout = self.sequence_timeout;
+                let names = self.launcher_entries.iter().map(|(name, _)| name.as_str());
+                let message = match launcher.key(*k, names.clone()) {
+                    LauncherNext::Active => launcher.message(names),
+                    LauncherNext::Run(idx) => {
+                        let (name, (x, y)) = &self.launcher_entries[idx];
+                        log::debug!("launcher running {name}");
+                        layout.event(Event::Press(*x, *y));
+                        layout.event(Event::Release(*x, *y));
+                        let input = std::mem::take(&mut launcher.input);
+                        self.launcher_state = None;
+                        ServerMessage::Launcher {
+                            input,
+                            best_match: Some(name.clone()),
+                            active: false,
+                        }
+                    }
+                    LauncherNext::Cancel => {
+                        log::debug!("launcher cancelled");
+                        let input = std::mem::take(&mut launcher.input);
+                        self.launcher_state = None;
+                        ServerMessage::Launcher {
+                            input,
+                            best_match: None,
+                            active: false,
+                        }
+                    }
+                };
+                self.launcher_message = Some(message);
+                continue;
+            }
+            if let Some(grid) = &mut self.mouse_grid {
+                log::debug!("mouse grid got {k:?}");
+                grid.ticks_until_timeout = self.sequence_timeout;
+                match grid.key(*k) {
+                    MouseGridNext::Active => {}
+                    MouseGridNext::Warp(x, y) => self.kbd_out.set_mouse(x, y)?,
+                    MouseGridNext::Click => {
+                        self.mouse_grid = None;
+                        self.dwell_click.cancel();
+                        // Clicking ends a drag instead of starting another one.
+                        if let Some(dragged) = self.dragged_btn.take() {
+                            log::debug!("mouse grid drop {dragged:?}");
+                            self.kbd_out.release_btn(dragged)?;
+                        } else {
+                            log::debug!("mouse grid click");
+                            self.kbd_out.click_btn(Btn::Left)?;
+                            self.kbd_out.release_btn(Btn::Left)?;
+                        }
+                        self.output_history.clear();
+                    }
+                    MouseGridNext::End => {
+                        log::debug!("mouse grid ended");
+                        self.mouse_grid = None;
+                    }
+                }
+                continue;
+            }
             match &mut self.sequence_state {
                 None => {
+                    let (now, layer) = (self.clock.now(), layout.current_layer());
+                    if !self.kiosk.allows_key(k.into(), layer)
+                        || !self.guard.allows(k.into(), cur_keys, layer, now)
+                    {
+                        continue;
+                    }
+                    if self.count_prefix.capture(k.into(), layer) {
+                        continue;
+                    }
+                    let count = &mut self.count_prefix.count;
+                    match self.modal.key(k.into(), layer, count) {
+                        ModalNext::Output => {}
+                        ModalNext::Captured => continue,
+                        ModalNext::Operate {
+                            motion,
+                            count,
+                            operator,
+                        } => {
+                            log::debug!("modal operator {:?} on {motion:?}", operator.key);
+                            modal_operate(&mut self.kbd_out, motion, count, &operator.chord)?;
+                            if let Some(layer) = operator.then_layer {
+                                layout.set_default_layer(layer);
+                            }
+                            self.output_history.clear();
+                            continue;
+                        }
+                    }
+                    let count = self.count_prefix.take_for_key(k.into());
+                    if count > 1 {
+                        log::debug!("repeating {k:?}, {count} times");
+                        for _ in 1..count {
+                            self.kbd_out.press_key(k.into())?;
+                            self.kbd_out.release_key(k.into())?;
+                        }
+                    }
                     log::debug!("key press     {:?}", k);
                     if let Err(e) = self.kbd_out.press_key(k.into()) {
                         bail!("failed to press key: {:?}", e);
                     }
+                    self.rate_limit.record();
+                    self.modifier_taps.output_pressed(k.into());
+                    if let Some(layer) = self.language_keys.output(k.into()) {
+                        set_language_layer(layout, layer);
+                    }
+                    // Text typed by macros is not replaced.
+                    if !layout.active_sequences.is_empty() {
+                        self.output_history.clear();
+                        continue;
+                    }
+                    let held_shifts = cur_keys
+                        .iter()
+                        .filter(|k| matches!(k, KeyCode::LShift | KeyCode::RShift))
+                        .map(OsCode::from)
+                        .collect::<Vec<_>>();
+                    if !self.output_history.key(*k, !held_shifts.is_empty()) {
+                        continue;
+                    }
+                    let history = self.output_history.as_str();
+                    let replacement = match self.shortcodes.closed_code(history) {
+                        Some((erase, text)) => Some((erase, text.to_owned(), 0)),
+                        None => find_snippet(&self.snippets, history).map(|snippet| {
+                            let (text, lefts) = expand(&snippet.expansion, snippets::now());
+                            (snippet.trigger.chars().count(), text, lefts)
+                        }),
+                    };
+                    if let Some((erase, text, lefts)) = replacement {
+                        log::debug!("replacing {erase} typed character(s) with {text}");
+                        self.output_history.clear();
+                        replace_text(&mut self.kbd_out, &held_shifts, erase, &text, lefts)?;
+                    }
                 }
                 Some(state) => {
                     state.ticks_until_timeout = self.sequence_timeout;
@@ -649,6 +1835,9 @@ impl Kanata {
                 let mut cmds = vec![];
                 let mut prev_mouse_btn = None;
                 for custact in custacts.iter() {
+                    if !self.kiosk.allows_action(custact, layout.current_layer()) {
+                        continue;
+                    }
                     match custact {
                         // For unicode, only send on the press. No repeat action is supported for this for
                         // now.
@@ -689,12 +1878,20 @@ impl Kanata {
                                 log::debug!("unclick   {:?}", pbtn);
                                 self.kbd_out.release_btn(pbtn)?;
                             }
+                            // The button is released with this key now.
+                            if self.dragged_btn == Some(*btn) {
+                                self.dragged_btn = None;
+                            }
+                            self.dwell_click.cancel();
                             self.kbd_out.click_btn(*btn)?;
+                            self.output_history.clear();
                             prev_mouse_btn = Some(*btn);
                         }
                         CustomAction::MouseTap(btn) => {
                             log::debug!("click     {:?}", btn);
+                            self.dwell_click.cancel();
                             self.kbd_out.click_btn(*btn)?;
+                            self.output_history.clear();
                             log::debug!("unclick   {:?}", btn);
                             self.kbd_out.release_btn(*btn)?;
                         }
@@ -731,7 +1928,8 @@ impl Kanata {
                                     distance: *distance,
                                     ticks_until_move: 0,
                                     interval: *interval,
-                                    move_mouse_accel_state: None,
+                                    ticks_since_press: 0,
+                                    accel: None,
                                 })
                             }
                             MoveDirection::Left | MoveDirection::Right => {
@@ -740,51 +1938,30 @@ impl Kanata {
                                     distance: *distance,
                                     ticks_until_move: 0,
                                     interval: *interval,
-                                    move_mouse_accel_state: None,
+                                    ticks_since_press: 0,
+                                    accel: None,
                                 })
                             }
                         },
                         CustomAction::MoveMouseAccel {
                             direction,
                             interval,
-                            accel_time,
-                            min_distance,
-                            max_distance,
+                            accel,
                         } => {
-                            let f_max_distance: f64 = *max_distance as f64;
-                            let f_min_distance: f64 = *min_distance as f64;
-                            let f_accel_time: f64 = *accel_time as f64;
-                            let increment = (f_max_distance - f_min_distance) / f_accel_time;
+                            let state = Some(MoveMouseState {
+                                direction: *direction,
+                                distance: accel.min_distance,
+                                ticks_until_move: 0,
+                                interval: *interval,
+                                ticks_since_press: 0,
+                                accel: Some(*accel),
+                            });
                             match direction {
                                 MoveDirection::Up | MoveDirection::Down => {
-                                    self.move_mouse_state_vertical = Some(MoveMouseState {
-                                        direction: *direction,
-                                        distance: *min_distance,
-                                        ticks_until_move: 0,
-                                        interval: *interval,
-                                        move_mouse_accel_state: Some(MoveMouseAccelState {
-                                            accel_ticks_from_min: 0,
-                                            accel_ticks_until_max: *accel_time,
-                                            accel_increment: increment,
-                                            min_distance: *min_distance,
-                                            max_distance: *max_distance,
-                                        }),
-                                    })
+                                    self.move_mouse_state_vertical = state
                                 }
                                 MoveDirection::Left | MoveDirection::Right => {
-                                    self.move_mouse_state_horizontal = Some(MoveMouseState {
-                                        direction: *direction,
-                                        distance: *min_distance,
-                                        ticks_until_move: 0,
-                                        interval: *interval,
-                                        move_mouse_accel_state: Some(MoveMouseAccelState {
-                                            accel_ticks_from_min: 0,
-                                            accel_ticks_until_max: *accel_time,
-                                            accel_increment: increment,
-                                            min_distance: *min_distance,
-                                            max_distance: *max_distance,
-                                        }),
-                                    })
+                                    self.move_mouse_state_horizontal = state
                                 }
                             }
                         }
@@ -792,10 +1969,18 @@ impl Kanata {
                             #[cfg(feature = "cmd")]
                             cmds.push(_cmd.clone());
                         }
+                        CustomAction::ClipboardPaste {
+                            max_chars: _max_chars,
+                        } => {
+                            #[cfg(feature = "clipboard")]
+                            self.clipboard_paste.start(*_max_chars);
+                        }
                         CustomAction::CmdOutputKeys(_cmd) => {
                             #[cfg(feature = "cmd")]
                             {
-                                for (key_action, osc) in keys_for_cmd_output(_cmd) {
+                                for (key_action, osc) in
+                                    keys_for_cmd_output(_cmd, &self.cmd_env())
+                                {
                                     match key_action {
                                         KeyAction::Press => self.kbd_out.press_key(osc)?,
                                         KeyAction::Release => self.kbd_out.release_key(osc)?,
@@ -806,9 +1991,9 @@ impl Kanata {
                         CustomAction::FakeKey { coord, action } => {
                             let (x, y) = (coord.x, coord.y);
                             log::debug!(
-                                "fake key on press   {action:?} {:?},{x:?},{y:?} {:?}",
-                                layout.default_layer,
-                                layout.layers[layout.default_layer][x as usize][y as usize]
+                                "fake key on press   {action:?} {} on layer {}",
+                                fake_key_name(&self.fake_key_names, y),
+                                self.layer_info[layout.default_layer].name,
                             );
                             match action {
                                 FakeKeyAction::Press => layout.event(Event::Press(x, y)),
@@ -834,6 +2019,45 @@ impl Kanata {
                                 });
                             }
                         }
+                        CustomAction::Launcher => {
+                            log::debug!("entering launcher");
+                            let launcher = LauncherState::new(self.sequence_timeout);
+                            self.launcher_message = Some(launcher.message(
+                                self.launcher_entries.iter().map(|(name, _)| name.as_str()),
+                            ));
+                            self.launcher_state = Some(launcher);
+                        }
+                        CustomAction::MouseDrag(btn) => {
+                            let dragged = self.dragged_btn.take();
+                            self.dwell_click.cancel();
+                            if let Some(dragged) = dragged {
+                                log::debug!("drop      {:?}", dragged);
+                                self.kbd_out.release_btn(dragged)?;
+                            }
+                            if dragged != Some(*btn) {
+                                log::debug!("drag      {:?}", btn);
+                                self.kbd_out.click_btn(*btn)?;
+                                self.output_history.clear();
+                                self.dragged_btn = Some(*btn);
+                            }
+                        }
+                        CustomAction::MouseDrop => {
+                            self.dwell_click.cancel();
+                            if let Some(dragged) = self.dragged_btn.take() {
+                                log::debug!("drop      {:?}", dragged);
+                                self.kbd_out.release_btn(dragged)?;
+                            }
+                        }
+                        CustomAction::DwellClick(action) => {
+                            self.dwell_click.set(*action);
+                        }
+                        CustomAction::MouseGrid => {
+                            log::debug!("entering mouse grid");
+                            let grid = MouseGridState::new(self.sequence_timeout);
+                            let (x, y) = grid.region.center();
+                            self.kbd_out.set_mouse(x, y)?;
+                            self.mouse_grid = Some(grid);
+                        }
                         CustomAction::Repeat => {
                             let key = OsCode::from(LAST_PRESSED_KEY.load(SeqCst));
                             log::debug!("repeating a keypress {key:?}");
@@ -874,8 +2098,13 @@ impl Kanata {
                                     // record" key press action which we don't want to keep.
                                     state.macro_items.remove(state.macro_items.len() - 1);
                                     state.add_release_for_all_unreleased_presses();
-                                    self.dynamic_macros
-                                        .insert(state.starting_macro_id, state.macro_items.clone());
+                                    self.dynamic_macros.insert(
+                                        state.starting_macro_id,
+                                        std::mem::take(&mut state.macro_items),
+                                    );
+                                    if let Some(p) = &mut self.state_persistence {
+                                        p.mark_macros_changed();
+                                    }
                                     if state.starting_macro_id == *macro_id {
                                         log::info!(
                                             "same macro id pressed. saving and stopping dynamic macro {} recording",
@@ -918,8 +2147,13 @@ impl Kanata {
                                         .saturating_sub(*num_actions_to_remove as usize),
                                 );
                                 state.add_release_for_all_unreleased_presses();
-                                self.dynamic_macros
-                                    .insert(state.starting_macro_id, state.macro_items.clone());
+                                self.dynamic_macros.insert(
+                                    state.starting_macro_id,
+                                    std::mem::take(&mut state.macro_items),
+                                );
+                                if let Some(p) = &mut self.state_persistence {
+                                    p.mark_macros_changed();
+                                }
                             }
                             self.dynamic_macro_record_state = None;
                         }
@@ -962,8 +2196,133 @@ impl Kanata {
                         CustomAction::CapsWord(cfg) => {
                             self.caps_word = Some(CapsWordState::new(cfg));
                         }
+                        CustomAction::UnicodeInput(radix) => {
+                            self.unicode_input = Some(UnicodeInputState::new(*radix));
+                        }
+                        CustomAction::Morse(cfg) => {
+                            match &mut self.morse {
+                                Some(morse) if morse.cfg == *cfg => {}
+                                _ => self.morse = Some(MorseState::new(*cfg)),
+                            }
+                            if let Some(morse) = &mut self.morse {
+                                morse.press();
+                            }
+                        }
                         CustomAction::SetMouse { x, y } => {
                             self.kbd_out.set_mouse(*x, *y)?;
+                            self.dwell_click.moved();
+                        }
+                        CustomAction::SwapHands(pairs) => {
+                            self.swap_hands.activate(pairs);
+                        }
+                        CustomAction::ToggleKey(kc) => {
+                            let len_before = self.latched_keys.len();
+                            self.latched_keys.retain(|k| k != kc);
+                            if self.latched_keys.len() == len_before {
+                                log::debug!("latching {kc:?}");
+                                self.latched_keys.push(*kc);
+                                let coord =
+                                    layout.states.iter().rev().find_map(|state| match state {
+                                        State::Custom { value, coord }
+                                            if std::ptr::eq(*value, custacts) =>
+                                        {
+                                            Some(*coord)
+                                        }
+                                        _ => None,
+                                    });
+                                let by = match coord {
+                                    Some(coord) => coord_name(&self.fake_key_names, coord),
+                                    None => "unknown key".to_owned(),
+                                };
+                                self.latch_audit.latched(*kc, by, self.clock.now());
+                            } else {
+                                log::debug!("unlatching {kc:?}");
+                                self.latch_audit.unlatched(*kc);
+                            }
+                        }
+                        CustomAction::Passthrough => self.passthrough_requested = true,
+                        CustomAction::ResyncModifiers => self.resync_modifiers_requested = true,
+                        CustomAction::KvmTarget(switch) => {
+                            self.kvm_switch_requested = Some(switch.clone());
+                        }
+                        CustomAction::RouteOutput(target) => {
+                            if let Err(e) = self.output_router.choose(target.clone(), &self.kvm) {
+                                log::warn!("route-output: {e}");
+                            }
+                        }
+                        CustomAction::TempLayer { layer, seconds } => {
+                            let name = self.layer_info[*layer].name.clone();
+                            log::info!("activating {name} for {seconds}s");
+                            let until =
+                                self.clock.now() + time::Duration::from_secs((*seconds).into());
+                            start_temp_layer(layout, &mut self.temp_layer, *layer, name, until);
+                        }
+                        CustomAction::LayerLock => match toggle_layer_lock(layout) {
+                            Some((layer, true)) => {
+                                log::info!("locked layer {}", self.layer_info[layer].name)
+                            }
+                            Some((layer, false)) => {
+                                log::info!("unlocked layer {}", self.layer_info[layer].name)
+                            }
+                            None => log::debug!("layer-lock: no held layer to lock"),
+                        },
+                        CustomAction::SetVar { name, value } => {
+                            log::debug!("setting variable {name} to {value}");
+                            self.runtime_vars.insert(name.clone(), value.clone());
+                        }
+                        CustomAction::SwitchVar { name, cases } => {
+                            let value = self.runtime_vars.get(name);
+                            let case = cases
+                                .iter()
+                                .find(|case| case.value.is_none() || case.value.as_ref() == value);
+                            // Activate the chosen action at the coordinate of the key that was
+                            // pressed so that it is released together with that key.
+                            let coord = layout.states.iter().rev().find_map(|state| match state {
+                                State::Custom { value, coord }
+                                    if std::ptr::eq(*value, custacts) =>
+                                {
+                                    Some(*coord)
+                                }
+                                _ => None,
+                            });
+                            if let (Some(case), Some(coord)) = (case, coord) {
+                                let _ = layout.action_queue.push_back(Some((coord, case.action.0)));
+                            }
+                        }
+                        CustomAction::Cooldown { ms, action } => {
+                            if !self.cooldowns.activate(*action, *ms, self.clock.now()) {
+                                log::debug!("cooldown: ignoring press within {ms}ms");
+                                continue;
+                            }
+                            // Like switch-var, at the coordinate of the pressed key so that the
+                            // action is released together with it.
+                            let coord = layout.states.iter().rev().find_map(|state| match state {
+                                State::Custom { value, coord }
+                                    if std::ptr::eq(*value, custacts) =>
+                                {
+                                    Some(*coord)
+                                }
+                                _ => None,
+                            });
+                            if let Some(coord) = coord {
+                                let _ = layout.action_queue.push_back(Some((coord, action.0)));
+                            }
+                        }
+                        CustomAction::Counted(action) => {
+                            // Like cooldown, at the coordinate of the pressed key.
+                            let coord = layout.states.iter().rev().find_map(|state| match state {
+                                State::Custom { value, coord }
+                                    if std::ptr::eq(*value, custacts) =>
+                                {
+                                    Some(*coord)
+                                }
+                                _ => None,
+                            });
+                            let physical_key = coord.filter(|(x, _)| *x == 0).map(|(_, y)| y);
+                            self.count_prefix.counted_press(physical_key);
+                            if let Some(coord) = coord {
+                                let _ = layout.action_queue.push_back(Some((coord, action.0)));
+                            }
                         }
                         CustomAction::FakeKeyOnRelease { .. }
                         | CustomAction::DelayOnRelease(_)
@@ -971,7 +2330,7 @@ impl Kanata {
                     }
                 }
                 #[cfg(feature = "cmd")]
-                run_multi_cmd(cmds);
+                run_multi_cmd(cmds, self.cmd_env());
             }
 
             CustomEvent::Release(custacts) => {
@@ -1052,7 +2411,10 @@ impl Kanata {
                         }
                         CustomAction::FakeKeyOnRelease { coord, action } => {
                             let (x, y) = (coord.x, coord.y);
-                            log::debug!("fake key on release {action:?} {x:?},{y:?}");
+                            log::debug!(
+                                "fake key on release {action:?} {}",
+                                fake_key_name(&self.fake_key_names, y)
+                            );
                             match action {
                                 FakeKeyAction::Press => layout.event(Event::Press(x, y)),
                                 FakeKeyAction::Release => layout.event(Event::Release(x, y)),
@@ -1063,6 +2425,33 @@ impl Kanata {
                             }
                             pbtn
                         }
+                        CustomAction::Morse(_) => {
+                            if let Some(morse) = &mut self.morse {
+                                morse.release();
+                            }
+                            pbtn
+                        }
+                        CustomAction::UnicodeInput(_) => {
+                            if let Some(input) = &mut self.unicode_input {
+                                let next = input.release();
+                                if let Err(e) = end_unicode_input(
+                                    &mut self.unicode_input,
+                                    &mut self.kbd_out,
+                                    next,
+                                ) {
+                                    log::error!("failed to type unicode input {e:?}");
+                                }
+                            }
+                            pbtn
+                        }
+                        CustomAction::SwapHands(_) => {
+                            self.swap_hands.deactivate();
+                            pbtn
+                        }
+                        CustomAction::Counted(_) => {
+                            self.count_prefix.counted_release();
+                            pbtn
+                        }
                         CustomAction::CancelMacroOnRelease => {
                             log::debug!("cancelling all macros");
                             layout.active_sequences.clear();
@@ -1116,9 +2505,35 @@ impl Kanata {
             // cancel an input sequence... I'll wait for a user created issue to deal with this.
             return Ok(());
         }
+        if !self.rate_limit.allows() {
+            log::debug!("output rate limit reached, dropping repeat");
+            return Ok(());
+        }
+        self.rate_limit.record();
         self.cur_keys.extend(self.layout.bm().keycodes());
         self.overrides
             .override_keys(&mut self.cur_keys, &mut self.override_states);
+        self.key_transforms.transform_output(&mut self.cur_keys);
+        // Prefer the key that was output when this key was pressed. The layer may have changed
+        // since then, in which case the layer lookups below would find a different key.
+        let coord = (0, u16::from(event.code));
+        let pressed_kc = self
+            .layout
+            .bm()
+            .states
+            .iter()
+            .find_map(|s| match s {
+                State::NormalKey { keycode, coord: c } if *c == coord => Some(*keycode),
+                _ => None,
+            })
+            .and_then(|kc| self.key_transforms.output_key(kc));
+        if let Some(kc) = pressed_kc.filter(|kc| self.cur_keys.contains(kc)) {
+            log::debug!("repeat    {:?}", kc);
+            if let Err(e) = self.kbd_out.write_key(kc.into(), KeyValue::Repeat) {
+                bail!("could not write key {:?}", e)
+            }
+            return Ok(());
+        }
         let current_layer = self.layout.bm().current_layer();
         if current_layer % 2 == 1 {
             // Prioritize checking the active layer in case a layer-while-held is active.
@@ -1161,12 +2576,261 @@ impl Kanata {
         Ok(())
     }
 
-    pub fn change_layer(&mut self, layer_name: String) {
+    /// Set the default layer. Returns false if the layer does not exist.
+    pub fn change_layer(&mut self, layer_name: String) -> bool {
         for (i, l) in self.layer_info.iter().enumerate() {
             if l.name == layer_name {
+                if self.layer_tags.is_disabled(i) {
+                    log::warn!("cannot change to layer {layer_name}: it is turned off by its tags");
+                    return false;
+                }
                 self.layout.bm().set_default_layer(i);
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn handle_command(&mut self, command: KanataCommand, tx: &Option<Sender<ServerMessage>>) {
+        log::debug!("processing command {command:?}");
+        match command {
+            command
+                if command.changes_layers()
+                    && self.defer_layer_changes
+                    && keys_are_held(self.layout.b()) =>
+            {
+                log::info!("deferring {command:?} until the held keys are released");
+                self.deferred_layer_commands.push(command);
+            }
+            KanataCommand::ChangeLayer { name } => {
+                let prev = self.layer_info[self.layout.b().default_layer].name.clone();
+                if self.change_layer(name) {
+                    self.undo_history.push(UndoEntry::DefaultLayer(prev));
+                }
+            }
+            KanataCommand::ActOnFakeKey { name, action } => self.act_on_fake_key(&name, action),
+            KanataCommand::SetVar { name, value } => {
+                let prev = self.runtime_vars.insert(name.clone(), value);
+                self.undo_history.push(UndoEntry::Var { name, value: prev });
+            }
+            KanataCommand::Shutdown => self.shutdown(tx),
+            KanataCommand::ChangeConfig { index } => {
+                let prev = self.cur_cfg_idx;
+                if self.change_config(index) {
+                    self.undo_history.push(UndoEntry::Config(prev));
+                }
+            }
+            KanataCommand::SetGameMode { enabled } => {
+                self.undo_history
+                    .push(UndoEntry::GameMode(self.game_mode.enabled));
+                self.set_game_mode(enabled);
+            }
+            KanataCommand::SetLayerTags { changes } => {
+                if let Some(undo) = self.set_layer_tags(&changes) {
+                    self.undo_history.push(UndoEntry::LayerTags(undo));
+                }
+            }
+            KanataCommand::Undo => match self.undo_history.pop() {
+                Some(entry) => self.undo(entry),
+                None => log::warn!("there is nothing to undo"),
+            },
+            KanataCommand::ClearOutputHistory => self.output_history.clear(),
+            KanataCommand::ForceUnlock => self.force_unlock(),
+            KanataCommand::ActiveWindowChanged { class, title } => {
+                log::debug!("active window changed to {class}: {title}");
+                // Text typed in the previous window is not replaced in the new one.
+                self.output_history.clear();
+                self.active_window = Some(ActiveWindow { class, title });
+            }
+            #[cfg(target_os = "linux")]
+            KanataCommand::PointerActivity => self.pointer_activity(),
+            #[cfg(not(target_os = "linux"))]
+            KanataCommand::PointerActivity => {}
+            KanataCommand::PointerMoved { x, y } => self.pointer_moved((x, y)),
+            #[cfg(target_os = "linux")]
+            KanataCommand::ScreenLocked { locked } => self.screen_lock_changed(locked),
+            #[cfg(not(target_os = "linux"))]
+            KanataCommand::ScreenLocked { .. } => {}
+            #[cfg(target_os = "linux")]
+            KanataCommand::DevicesChanged { names } => self.devices_changed(names),
+            #[cfg(not(target_os = "linux"))]
+            KanataCommand::DevicesChanged { .. } => {}
+            KanataCommand::LoadOverlay { path } => self.load_overlay(path),
+            KanataCommand::DropOverlay => self.drop_overlay(),
+            KanataCommand::SetPassthrough { enabled } => self.set_passthrough(enabled),
+            KanataCommand::ResyncModifiers => self.resync_modifiers(),
+            KanataCommand::OutputKeys { events } => self
+                .output_queue
+                .push_keys(OutputPriority::Background, &events),
+            KanataCommand::TypeText { text } => self
+                .output_queue
+                .push_text(OutputPriority::Background, &text),
+            KanataCommand::ActivateTempLayer { name, duration } => {
+                self.activate_temp_layer(&name, duration);
+            }
+            KanataCommand::ExtendTempLayer { by } => self.extend_temp_layer(by),
+            KanataCommand::CancelTempLayer => self.cancel_temp_layer(),
+            KanataCommand::ImportMacros { macros } => self.import_macros(macros),
+            KanataCommand::Transaction { commands } => self.apply_transaction(commands),
+        }
+    }
+
+    /// Request a live reload of the configuration file with the index. Returns false if it does
+    /// not exist.
+    fn change_config(&mut self, index: usize) -> bool {
+        if index >= self.cfg_paths.len() {
+            log::warn!("cannot change to configuration file {index}: it does not exist");
+            return false;
+        }
+        self.cur_cfg_idx = index;
+        self.live_reload_requested = true;
+        log::info!(
+            "Requested live reload of file: {}",
+            self.cfg_paths[self.cur_cfg_idx].display()
+        );
+        true
+    }
+
+    fn set_game_mode(&mut self, enabled: bool) {
+        log::info!("game mode {}", if enabled { "enabled" } else { "disabled" });
+        self.game_mode.enabled = enabled;
+        let layout = self.layout.bm();
+        layout.game_mode = self.game_mode.is_active(layout.current_layer());
+    }
+
+    /// Turn the layers with the tags off or on at once, so that no tick sees only some of the
+    /// changes. Returns the changes that undo these, or `None` if no layer has one of the tags.
+    fn set_layer_tags(&mut self, changes: &[(String, bool)]) -> Option<Vec<(String, bool)>> {
+        match self.layer_tags.set_enabled(changes, &self.layer_info) {
+            Ok(undo) => {
+                for (tag, enabled) in changes {
+                    log::info!(
+                        "layers tagged {tag} turned {}",
+                        if *enabled { "on" } else { "off" }
+                    );
+                }
+                self.layer_tags.enforce(self.layout.bm());
+                Some(undo)
+            }
+            Err(e) => {
+                log::warn!("cannot change layer tags: {e}");
+                None
+            }
+        }
+    }
+
+    /// Restore the state from before a TCP command changed it.
+    fn undo(&mut self, entry: UndoEntry) {
+        log::info!("undoing {entry:?}");
+        match entry {
+            UndoEntry::DefaultLayer(name) => {
+                if !self.change_layer(name) {
+                    log::warn!("cannot undo the layer change: the layer no longer exists");
+                }
+            }
+            UndoEntry::Var { name, value } => match value {
+                Some(value) => {
+                    self.runtime_vars.insert(name, value);
+                }
+                None => {
+                    self.runtime_vars.remove(&name);
+                }
+            },
+            UndoEntry::Config(index) => {
+                self.change_config(index);
+            }
+            UndoEntry::GameMode(enabled) => self.set_game_mode(enabled),
+            UndoEntry::LayerTags(changes) => {
+                self.set_layer_tags(&changes);
+            }
+            UndoEntry::Transaction(entries) => {
+                for entry in entries.into_iter().rev() {
+                    self.undo(entry);
+                }
+            }
+        }
+    }
+
+    pub fn config_files_message(&self) -> ServerMessage {
+        ServerMessage::ConfigFiles {
+            paths: self
+                .cfg_paths
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect(),
+            active: self.cur_cfg_idx,
+        }
+    }
+
+    /// Save the state, release the keys that kanata holds down and notify the TCP clients, then
+    /// exit. The input devices are released by exiting, after the keys were released.
+    fn shutdown(&mut self, tx: &Option<Sender<ServerMessage>>) -> ! {
+        log::info!("shutting down");
+        self.save_persisted_state();
+        if let Some(recorder) = &mut self.usage_stats {
+            recorder.save(self.clock.now());
+        }
+        self.key_counts.save(self.clock.now());
+        for k in self.prev_keys.drain(..) {
+            if let Err(e) = self.kbd_out.release_key(k.into()) {
+                log::warn!("failed to release {k:?}: {e}");
+            }
+        }
+        if let Some(btn) = self.dragged_btn.take() {
+            if let Err(e) = self.kbd_out.release_btn(btn) {
+                log::warn!("failed to release {btn:?}: {e}");
+            }
+        }
+        self.helpers.kill_all();
+        #[cfg(target_os = "linux")]
+        self.kbd_out.remove_symlink();
+        if let Some(tx) = tx {
+            // The notification loop exits once it sent this to the clients. Exit anyway if it is
+            // stuck on a client.
+            if tx.send(ServerMessage::Shutdown).is_ok() {
+                std::thread::sleep(SHUTDOWN_NOTIFICATION_TIMEOUT);
+                log::warn!("timed out notifying TCP clients of the shutdown");
+            }
+        }
+        std::process::exit(0)
+    }
+
+    /// Handle an input of the processing loop without ticking.
+    fn handle_processing_event(
+        &mut self,
+        event: ProcessingEvent,
+        tx: &Option<Sender<ServerMessage>>,
+    ) -> Result<()> {
+        match event {
+            ProcessingEvent::Key(kev) => {
+                #[cfg(target_os = "linux")]
+                self.latency_event_received();
+                self.handle_key_event(&kev)
+            }
+            ProcessingEvent::Command(command) => {
+                self.handle_command(command, tx);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn act_on_fake_key(&mut self, name: &str, action: FakeKeyAction) {
+        let (x, y) = match self.fake_keys.get(name) {
+            Some(idx) => get_fake_key_coords(*idx),
+            None => {
+                log::warn!("fake key does not exist: {name}");
                 return;
             }
+        };
+        log::debug!("fake key from TCP client {action:?} {name}");
+        let layout = self.layout.bm();
+        match action {
+            FakeKeyAction::Press => layout.event(Event::Press(x, y)),
+            FakeKeyAction::Release => layout.event(Event::Release(x, y)),
+            FakeKeyAction::Tap => {
+                layout.event(Event::Press(x, y));
+                layout.event(Event::Release(x, y));
+            }
         }
     }
 
@@ -1174,12 +2838,62 @@ impl Kanata {
     /// all connected clients.
     fn check_handle_layer_change(&mut self, tx: &Option<Sender<ServerMessage>>) {
         let cur_layer = self.layout.bm().current_layer();
+        self.layout.bm().game_mode = self.game_mode.is_active(cur_layer);
+        #[cfg(target_os = "linux")]
+        if let Some(leds) = &mut self.led_indicator {
+            leds.update(
+                &mut self.led_devices,
+                cur_layer,
+                cur_layer != self.prev_layer,
+            );
+        }
         if cur_layer != self.prev_layer {
             let new = self.layer_info[cur_layer].name.clone();
-            self.prev_layer = cur_layer;
+            // keyberon has two layers for every layer in the configuration; the odd one is used
+            // for layer-while-held.
+            if cur_layer / 2 != self.prev_layer / 2 {
+                let exit = self.hooks.layer_exit.get(&(self.prev_layer / 2));
+                let enter = self.hooks.layer_enter.get(&(cur_layer / 2));
+                for &(x, y) in exit.into_iter().chain(enter) {
+                    log::debug!(
+                        "layer changed to {new}, tapping {}",
+                        fake_key_name(&self.fake_key_names, y)
+                    );
+                    self.layout.bm().event(Event::Press(x, y));
+                    self.layout.bm().event(Event::Release(x, y));
+                }
+            }
+            let prev_layer = std::mem::replace(&mut self.prev_layer, cur_layer);
+            if let Some(recorder) = &mut self.usage_stats {
+                recorder.layer_activated(&new);
+            }
             self.print_layer(cur_layer);
+            self.update_crash_dump();
+            if let Some(rgb) = &self.openrgb {
+                rgb.layer_changed(cur_layer);
+            }
+            if let Some(display) = &self.layer_display {
+                display.layer_changed(cur_layer, &self.layer_info);
+            }
+            play_sound(&self.layer_info, cur_layer, SoundEvent::LayerChange);
 
             if let Some(tx) = tx {
+                if prev_layer / 2 != cur_layer / 2 {
+                    for (layer, active) in [(prev_layer, false), (cur_layer, true)] {
+                        let info = &self.layer_info[layer];
+                        if info.notify.is_empty() {
+                            continue;
+                        }
+                        let notification = ServerMessage::LayerNotify {
+                            layer: info.name.clone(),
+                            active,
+                            fields: info.notify.iter().cloned().collect(),
+                        };
+                        if let Err(error) = tx.send(notification) {
+                            log::error!("could not send event notification: {}", error);
+                        }
+                    }
+                }
                 match tx.send(ServerMessage::LayerChange { new }) {
                     Ok(_) => {}
                     Err(error) => {
@@ -1190,84 +2904,157 @@ impl Kanata {
         }
     }
 
+    /// Notify TCP clients of the key outputs that changed in the most recent tick.
+    fn send_key_output_notifications(&self, tx: &Sender<ServerMessage>) {
+        let released = self
+            .prev_keys
+            .iter()
+            .filter(|k| !self.cur_keys.contains(k))
+            .map(|k| (k, false));
+        let pressed = self
+            .cur_keys
+            .iter()
+            .filter(|k| !self.prev_keys.contains(k))
+            .map(|k| (k, true));
+        for (k, pressed) in released.chain(pressed) {
+            let key = format!("{k:?}");
+            if let Err(error) = tx.send(ServerMessage::KeyOutput { key, pressed }) {
+                log::error!("could not send event notification: {}", error);
+            }
+        }
+    }
+
+    /// Explain how the key is resolved with the active layers, for `ClientMessage::Explain`.
+    pub fn explain(&self, key: &str) -> String {
+        match str_to_oscode(key) {
+            Some(osc) => {
+                let layout = self.layout.b();
+                let stack = layout.active_layers().collect::<Vec<_>>();
+                crate::explain::explain_key(layout, &self.layer_info, &stack, osc)
+            }
+            None => format!("unknown key {key}"),
+        }
+    }
+
+    /// The actions that the keys trigger with the active layers, for
+    /// `ClientMessage::RequestPalette`.
+    pub fn palette(&self) -> Vec<crate::tcp_server::PaletteEntry> {
+        let layout = self.layout.b();
+        let stack = layout.active_layers().collect::<Vec<_>>();
+        crate::palette::palette_entries(layout, &self.layer_info, &stack, &MAPPED_KEYS.lock())
+    }
+
+    /// The active layers and modes, for `ClientMessage::RequestStatus`.
+    pub fn status(&self) -> crate::tcp_server::StatusInfo {
+        let layout = self.layout.b();
+        let mut active_layers = Vec::<String>::new();
+        for layer in layout.active_layers() {
+            let name = &self.layer_info[layer].name;
+            if active_layers.last() != Some(name) {
+                active_layers.push(name.clone());
+            }
+        }
+        crate::tcp_server::StatusInfo {
+            layer: self.layer_info[layout.current_layer()].name.clone(),
+            default_layer: self.layer_info[layout.default_layer].name.clone(),
+            active_layers,
+            config: self.cfg_paths[self.cur_cfg_idx].display().to_string(),
+            game_mode: self.game_mode.enabled,
+            passthrough: self.passthrough.is_some(),
+        }
+    }
+
+    /// The descriptions of the layers that have a `deflayerdoc`, for
+    /// `ClientMessage::RequestLayerDocs`.
+    pub fn layer_docs(&self) -> Vec<crate::tcp_server::LayerDocInfo> {
+        // keyberon has two layers for every layer in the configuration.
+        self.layer_info
+            .iter()
+            .step_by(2)
+            .filter(|info| info.doc.description.is_some())
+            .map(|info| crate::tcp_server::LayerDocInfo {
+                layer: info.name.clone(),
+                description: info.doc.description.clone(),
+                keys: info
+                    .doc
+                    .keys
+                    .iter()
+                    .map(|(key, description)| (crate::palette::key_name(*key), description.clone()))
+                    .collect(),
+            })
+            .collect()
+    }
+
     fn print_layer(&self, layer: usize) {
         if self.log_layer_changes {
             log::info!("Entered layer:\n\n{}", self.layer_info[layer].cfg_text);
         }
     }
 
+    /// Starts a new thread that relays the notifications of the processing loop to `sink` and
+    /// exits kanata after relaying `ServerMessage::Shutdown`. Layer changes are sent at most once
+    /// per `min_interval`, see [`relay_notifications`].
     pub fn start_notification_loop(
         rx: Receiver<ServerMessage>,
-        clients: Arc<Mutex<HashMap<String, TcpStream>>>,
+        mut sink: impl NotificationSink,
+        min_interval: time::Duration,
     ) {
         info!("listening for event notifications to relay to connected clients");
         std::thread::spawn(move || {
-            loop {
-                match rx.recv() {
-                    Err(_) => {
-                        panic!("channel disconnected")
-                    }
-                    Ok(event) => {
-                        let notification = event.as_bytes();
-                        let mut clients = clients.lock();
-                        let mut stale_clients = vec![];
-                        for (id, client) in &mut *clients {
-                            match client.write(&notification) {
-                                Ok(_) => {
-                                    log::debug!("layer change notification sent");
-                                }
-                                Err(_) => {
-                                    // the client is no longer connected, let's remove them
-                                    stale_clients.push(id.clone());
-                                }
-                            }
-                        }
-
-                        for id in &stale_clients {
-                            log::warn!("removing disconnected tcp client: {id}");
-                            clients.remove(id);
-                        }
-                    }
-                }
-            }
+            relay_notifications(&rx, &mut sink, min_interval);
+            std::process::exit(0);
         });
     }
 
     /// Starts a new thread that processes OS key events and advances the keyberon layout's state.
     pub fn start_processing_loop(
         kanata: Arc<Mutex<Self>>,
-        rx: Receiver<KeyEvent>,
+        rx: Receiver<ProcessingEvent>,
         tx: Option<Sender<ServerMessage>>,
     ) {
         info!("entering the processing loop");
         std::thread::spawn(move || {
             info!("Init: catching only releases and sending immediately");
             for _ in 0..500 {
-                if let Ok(kev) = rx.try_recv() {
-                    if kev.value == KeyValue::Release {
+                match rx.try_recv() {
+                    Ok(ProcessingEvent::Key(kev)) => {
                         let mut k = kanata.lock();
-                        info!("Init: releasing {:?}", kev.code);
-                        k.kbd_out.release_key(kev.code).expect("key released");
+                        #[cfg(target_os = "linux")]
+                        k.latency_event_received();
+                        if kev.value == KeyValue::Release {
+                            info!("Init: releasing {:?}", kev.code);
+                            k.kbd_out.release_key(kev.code).expect("key released");
+                        }
+                        #[cfg(target_os = "linux")]
+                        k.latency_event_processed();
+                    }
+                    Ok(ProcessingEvent::Command(command)) => {
+                        kanata.lock().handle_command(command, &tx)
                     }
+                    Err(_) => {}
                 }
                 std::thread::sleep(time::Duration::from_millis(1));
             }
 
             info!("Starting kanata proper");
+            // An input that arrived while sleeping until a timer expires.
+            let mut received = None;
             let err = loop {
-                if kanata.lock().can_block() {
+                if received.is_none() && kanata.lock().can_block() {
                     log::trace!("blocking on channel");
                     match rx.recv() {
-                        Ok(kev) => {
+                        Ok(event) => {
                             let mut k = kanata.lock();
-                            k.last_tick = time::Instant::now()
+                            k.last_tick = k
+                                .clock
+                                .now()
                                 .checked_sub(time::Duration::from_millis(1))
                                 .expect("subtract 1ms from current time");
 
                             #[cfg(feature = "perf_logging")]
                             let start = std::time::Instant::now();
 
-                            if let Err(e) = k.handle_key_event(&kev) {
+                            if let Err(e) = k.handle_processing_event(event, &tx) {
                                 break e;
                             }
 
@@ -1282,6 +3069,8 @@ impl Kanata {
                             if let Err(e) = k.handle_time_ticks(&tx) {
                                 break e;
                             }
+                            #[cfg(target_os = "linux")]
+                            k.latency_event_processed();
 
                             #[cfg(feature = "perf_logging")]
                             log::info!(
@@ -1296,12 +3085,12 @@ impl Kanata {
                     }
                 } else {
                     let mut k = kanata.lock();
-                    match rx.try_recv() {
-                        Ok(kev) => {
+                    match received.take().map_or_else(|| rx.try_recv(), Ok) {
+                        Ok(event) => {
                             #[cfg(feature = "perf_logging")]
                             let start = std::time::Instant::now();
 
-                            if let Err(e) = k.handle_key_event(&kev) {
+                            if let Err(e) = k.handle_processing_event(event, &tx) {
                                 break e;
                             }
 
@@ -1316,6 +3105,8 @@ impl Kanata {
                             if let Err(e) = k.handle_time_ticks(&tx) {
                                 break e;
                             }
+                            #[cfg(target_os = "linux")]
+                            k.latency_event_processed();
 
                             #[cfg(feature = "perf_logging")]
                             log::info!(
@@ -1337,8 +3128,23 @@ impl Kanata {
                                 (start.elapsed()).as_nanos()
                             );
 
+                            let ticks = k.ticks_until_due().unwrap_or(1);
                             drop(k);
-                            std::thread::sleep(time::Duration::from_millis(1));
+                            if ticks > 1 {
+                                // Only timers are running: sleep until the first one expires,
+                                // unless an input arrives before. The ticks in between are caught
+                                // up on the next tick.
+                                match rx.recv_timeout(time::Duration::from_millis(ticks.into())) {
+                                    Ok(event) => received = Some(event),
+                                    Err(RecvTimeoutError::Timeout) => {}
+                                    Err(RecvTimeoutError::Disconnected) => {
+                                        log::error!("channel disconnected");
+                                        return;
+                                    }
+                                }
+                            } else {
+                                std::thread::sleep(time::Duration::from_millis(1));
+                            }
                         }
                         Err(TryRecvError::Disconnected) => {
                             log::error!("channel disconnected");
@@ -1352,25 +3158,70 @@ impl Kanata {
     }
 
     pub fn can_block(&self) -> bool {
-        self.layout.b().queue.is_empty()
-            && self.layout.b().waiting.is_none()
-            && self.layout.b().last_press_tracker.tap_hold_timeout == 0
-            && (self.layout.b().oneshot.timeout == 0 || self.layout.b().oneshot.keys.is_empty())
-            && self.layout.b().active_sequences.is_empty()
-            && self.layout.b().tap_dance_eager.is_none()
-            && self.sequence_state.is_none()
-            && self.scroll_state.is_none()
-            && self.hscroll_state.is_none()
-            && self.move_mouse_state_vertical.is_none()
-            && self.move_mouse_state_horizontal.is_none()
-            && self.dynamic_macro_replay_state.is_none()
-            && self.caps_word.is_none()
-            && !self
-                .layout
-                .b()
-                .states
-                .iter()
-                .any(|s| matches!(s, State::SeqCustomPending(_) | State::SeqCustomActive(_)))
+        self.ticks_until_due().is_none()
+    }
+
+    /// The ticks that can pass before the processing loop must tick again, or `None` if nothing
+    /// happens until the next input. Everything that `handle_time_ticks` advances reports its own
+    /// deadline with a `ticks_until_due` method and is listed here in the same order. Timers that
+    /// do nothing until they expire, e.g. the dwell click and the pointer layer, are slept through
+    /// and everything else ticks every millisecond.
+    pub fn ticks_until_due(&self) -> Option<u16> {
+        let layout = self.layout.b();
+        #[cfg(feature = "clipboard")]
+        let clipboard_paste = self.clipboard_paste.ticks_until_due();
+        #[cfg(not(feature = "clipboard"))]
+        let clipboard_paste = None;
+        #[cfg(target_os = "linux")]
+        let pointer_layer = self.pointer_layer.ticks_until_due();
+        #[cfg(not(target_os = "linux"))]
+        let pointer_layer = None;
+        [
+            self.key_filter.ticks_until_due(),
+            self.rate_limit.ticks_until_due(),
+            layout.ticks_until_due(),
+            self.caps_word
+                .as_ref()
+                .and_then(CapsWordState::ticks_until_due),
+            (!self.deferred_layer_commands.is_empty()).then_some(1),
+            self.layer_stack_log.ticks_until_due(layout),
+            self.scroll_state
+                .as_ref()
+                .and_then(ScrollState::ticks_until_due),
+            self.hscroll_state
+                .as_ref()
+                .and_then(ScrollState::ticks_until_due),
+            self.move_mouse_state_vertical
+                .as_ref()
+                .and_then(MoveMouseState::ticks_until_due),
+            self.move_mouse_state_horizontal
+                .as_ref()
+                .and_then(MoveMouseState::ticks_until_due),
+            self.sequence_state
+                .as_ref()
+                .and_then(SequenceState::ticks_until_due),
+            self.dynamic_macro_replay_state
+                .as_ref()
+                .and_then(DynamicMacroReplayState::ticks_until_due),
+            self.count_prefix.ticks_until_due(),
+            clipboard_paste,
+            self.output_queue.ticks_until_due(),
+            self.morse.as_ref().and_then(MorseState::ticks_until_due),
+            self.launcher_state
+                .as_ref()
+                .and_then(LauncherState::ticks_until_due),
+            self.mouse_grid
+                .as_ref()
+                .and_then(MouseGridState::ticks_until_due),
+            self.dwell_click.ticks_until_due(),
+            pointer_layer,
+            self.temp_layer
+                .as_ref()
+                .map(|temp_layer| temp_layer.ticks_until_due(self.clock.now())),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
     }
 }
 
@@ -1381,48 +3232,16 @@ fn set_altgr_behaviour(_cfg: &cfg::Cfg) -> Result<()> {
 }
 
 #[cfg(feature = "cmd")]
-fn run_multi_cmd(cmds: Vec<Vec<String>>) {
+fn run_multi_cmd(cmds: Vec<Vec<String>>, env: CmdEnv) {
     std::thread::spawn(move || {
         for cmd in cmds {
-            if let Err(e) = run_cmd_in_thread(cmd).join() {
+            if let Err(e) = run_cmd_in_thread(cmd, env.clone()).join() {
                 log::error!("problem joining thread {:?}", e);
             }
         }
     });
 }
 
-/// Checks if kanata should exit based on the fixed key combination of:
-/// Lctl+Spc+Esc
-fn check_for_exit(event: &KeyEvent) {
-    static IS_LCL_PRESSED: AtomicBool = AtomicBool::new(false);
-    static IS_SPC_PRESSED: AtomicBool = AtomicBool::new(false);
-    static IS_ESC_PRESSED: AtomicBool = AtomicBool::new(false);
-    let is_pressed = match event.value {
-        KeyValue::Press => true,
-        KeyValue::Release => false,
-        _ => return,
-    };
-    match event.code {
-        OsCode::KEY_ESC => IS_ESC_PRESSED.store(is_pressed, SeqCst),
-        OsCode::KEY_SPACE => IS_SPC_PRESSED.store(is_pressed, SeqCst),
-        OsCode::KEY_LEFTCTRL => IS_LCL_PRESSED.store(is_pressed, SeqCst),
-        _ => return,
-    }
-    const EXIT_MSG: &str = "pressed LControl+Space+Escape, exiting";
-    if IS_ESC_PRESSED.load(SeqCst) && IS_SPC_PRESSED.load(SeqCst) && IS_LCL_PRESSED.load(SeqCst) {
-        #[cfg(not(target_os = "linux"))]
-        {
-            log::info!("{EXIT_MSG}");
-            panic!("{EXIT_MSG}");
-        }
-        #[cfg(target_os = "linux")]
-        {
-            log::info!("{EXIT_MSG}");
-            signal_hook::low_level::raise(signal_hook::consts::SIGTERM).expect("raise signal");
-        }
-    }
-}
-
 fn update_kbd_out(_cfg: &HashMap<String, String>, _kbd_out: &KbdOut) -> Result<()> {
     #[cfg(target_os = "linux")]
     {
@@ -1444,6 +3263,340 @@ fn update_kbd_out(_cfg: &HashMap<String, String>, _kbd_out: &KbdOut) -> Result<(
                 })
                 .unwrap_or(Ok(_kbd_out.unicode_u_code.get()))?,
         );
+        _kbd_out.update_stripped_events(
+            _cfg.get("linux-strip-events")
+                .map(|s| parse_stripped_events(s))
+                .unwrap_or(Ok(_kbd_out.stripped_events.get()))?,
+        );
     }
     Ok(())
 }
+
+/// Parse the `linux-output-*` items. A live reload that changes them replaces the output.
+#[cfg(target_os = "linux")]
+fn parse_output_device_cfg(cfg: &HashMap<String, String>) -> Result<OutputDeviceCfg> {
+    let parse_id = |name: &str, default: u16| -> Result<u16> {
+        let s = match cfg.get(name) {
+            Some(s) => s,
+            None => return Ok(default),
+        };
+        match s.strip_prefix("0x") {
+            Some(hex) => u16::from_str_radix(hex, 16),
+            None => s.parse::<u16>(),
+        }
+        .map_err(|_| anyhow!("{name} got {s}. It accepts a number from 0 to 0xffff"))
+    };
+    let mut device_cfg = OutputDeviceCfg::default();
+    if let Some(name) = cfg.get("linux-output-device-name") {
+        device_cfg.name = name.clone();
+    }
+    device_cfg.vendor_id = parse_id("linux-output-device-vendor-id", device_cfg.vendor_id)?;
+    device_cfg.product_id = parse_id("linux-output-device-product-id", device_cfg.product_id)?;
+    device_cfg.split_mouse = cfg
+        .get("linux-output-split-mouse")
+        .map(|s| matches!(s.to_lowercase().as_str(), "yes" | "true"))
+        .unwrap_or_default();
+    if let Some(caps) = cfg.get("linux-output-device-capabilities") {
+        device_cfg.rel = false;
+        device_cfg.msc = false;
+        let mut has_keys = false;
+        for cap in caps.split_whitespace() {
+            match cap {
+                "keys" => has_keys = true,
+                "rel" => device_cfg.rel = true,
+                "msc" => device_cfg.msc = true,
+                _ => bail!("linux-output-device-capabilities got {cap}. It accepts: keys|rel|msc"),
+            }
+        }
+        if !has_keys {
+            bail!("linux-output-device-capabilities must include keys");
+        }
+    }
+    match cfg.get("linux-output-backend").map(|s| s.as_str()) {
+        None | Some("uinput") => {}
+        #[cfg(feature = "wayland")]
+        Some("wayland") => {
+            device_cfg.backend = OutputBackend::Wayland {
+                xkb_layout: cfg
+                    .get("linux-output-wayland-xkb-layout")
+                    .or_else(|| cfg.get(XKB_LAYOUT_CFG_NAME))
+                    .cloned()
+                    .unwrap_or_else(|| "us".into()),
+            };
+        }
+        #[cfg(not(feature = "wayland"))]
+        Some("wayland") => {
+            bail!(
+                "linux-output-backend wayland requires kanata to be compiled with the wayland \
+                 feature"
+            )
+        }
+        #[cfg(feature = "xtest")]
+        Some("xtest") => device_cfg.backend = OutputBackend::Xtest,
+        #[cfg(not(feature = "xtest"))]
+        Some("xtest") => {
+            bail!(
+                "linux-output-backend xtest requires kanata to be compiled with the xtest feature"
+            )
+        }
+        #[cfg(feature = "ble_hid")]
+        Some("ble") => {
+            device_cfg.backend = OutputBackend::Ble {
+                adapter: cfg
+                    .get("linux-output-ble-adapter")
+                    .cloned()
+                    .unwrap_or_else(|| "hci0".into()),
+            };
+        }
+        #[cfg(not(feature = "ble_hid"))]
+        Some("ble") => {
+            bail!(
+                "linux-output-backend ble requires kanata to be compiled with the ble_hid feature"
+            )
+        }
+        Some(backend) => {
+            bail!("linux-output-backend got {backend}. It accepts: uinput|wayland|xtest|ble")
+        }
+    }
+    device_cfg.absolute_pointer = cfg
+        .get("linux-output-absolute-pointer")
+        .map(|s| matches!(s.to_lowercase().as_str(), "yes" | "true"))
+        .unwrap_or_default();
+    if device_cfg.split_mouse && device_cfg.backend != OutputBackend::Uinput {
+        bail!("linux-output-split-mouse is only supported by the uinput backend");
+    }
+    if device_cfg.absolute_pointer && device_cfg.backend != OutputBackend::Uinput {
+        bail!("linux-output-absolute-pointer is only supported by the uinput backend");
+    }
+    device_cfg.secondary_name = cfg.get("linux-output-secondary-device-name").cloned();
+    if device_cfg.secondary_name.is_some() && device_cfg.backend != OutputBackend::Uinput {
+        bail!("linux-output-secondary-device-name is only supported by the uinput backend");
+    }
+    Ok(device_cfg)
+}
+
+#[cfg(target_os = "linux")]
+const XKB_LAYOUT_CFG_NAME: &str = "linux-xkb-layout";
+
+/// Warn if the system keyboard layout differs from the layout the configuration was written for,
+/// since the output keys are then translated to other characters than intended.
+#[cfg(target_os = "linux")]
+fn check_xkb_layout(cfg: &HashMap<String, String>) {
+    let expected = cfg
+        .get(XKB_LAYOUT_CFG_NAME)
+        .map(String::as_str)
+        .and_then(first_layout)
+        .unwrap_or("us");
+    let detected = match detect_xkb_layout() {
+        Some(detected) => detected,
+        None => {
+            log::debug!("could not detect the XKB layout of the system");
+            return;
+        }
+    };
+    if detected.layout == expected {
+        return;
+    }
+    log::warn!(
+        "The keyboard layout of the system is {} according to {}, but the configuration is \
+         written for {expected}. Output keys will type the characters of the {} layout. \
+         If this is intended, set {XKB_LAYOUT_CFG_NAME} {} in defcfg to silence this warning.",
+        detected.layout,
+        detected.source,
+        detected.layout,
+        detected.layout,
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn parse_output_device_cfg_from_items() {
+    let mut items = HashMap::default();
+    assert_eq!(
+        parse_output_device_cfg(&items).unwrap(),
+        OutputDeviceCfg::default()
+    );
+    items.insert("linux-output-device-name".into(), "My Keyboard".into());
+    items.insert("linux-output-device-vendor-id".into(), "0x046d".into());
+    items.insert("linux-output-device-product-id".into(), "49948".into());
+    items.insert("linux-output-device-capabilities".into(), "keys msc".into());
+    assert_eq!(
+        parse_output_device_cfg(&items).unwrap(),
+        OutputDeviceCfg {
+            name: "My Keyboard".into(),
+            vendor_id: 0x046d,
+            product_id: 49948,
+            rel: false,
+            msc: true,
+            split_mouse: false,
+            absolute_pointer: false,
+            secondary_name: None,
+            backend: OutputBackend::Uinput,
+        }
+    );
+    items.insert("linux-output-backend".into(), "wayland".into());
+    items.insert("linux-xkb-layout".into(), "fr".into());
+    #[cfg(not(feature = "wayland"))]
+    assert!(parse_output_device_cfg(&items).is_err());
+    #[cfg(feature = "wayland")]
+    {
+        assert_eq!(
+            parse_output_device_cfg(&items).unwrap().backend,
+            OutputBackend::Wayland {
+                xkb_layout: "fr".into()
+            }
+        );
+        items.insert(
+            "linux-output-wayland-xkb-layout".into(),
+            "de(nodeadkeys)".into(),
+        );
+        assert_eq!(
+            parse_output_device_cfg(&items).unwrap().backend,
+            OutputBackend::Wayland {
+                xkb_layout: "de(nodeadkeys)".into()
+            }
+        );
+    }
+    items.insert("linux-output-split-mouse".into(), "yes".into());
+    assert!(parse_output_device_cfg(&items).is_err());
+    items.remove("linux-output-split-mouse");
+    items.insert("linux-output-absolute-pointer".into(), "yes".into());
+    assert!(parse_output_device_cfg(&items).is_err());
+    items.remove("linux-output-backend");
+    assert!(parse_output_device_cfg(&items).unwrap().absolute_pointer);
+    items.remove("linux-output-absolute-pointer");
+    items.insert(
+        "linux-output-secondary-device-name".into(),
+        "My Keyboard 2".into(),
+    );
+    assert_eq!(
+        parse_output_device_cfg(&items).unwrap().secondary_name,
+        Some("My Keyboard 2".into())
+    );
+    items.insert("linux-output-backend".into(), "wayland".into());
+    assert!(parse_output_device_cfg(&items).is_err());
+    items.remove("linux-output-backend");
+    items.remove("linux-output-secondary-device-name");
+    items.insert("linux-output-split-mouse".into(), "yes".into());
+    items.insert("linux-output-backend".into(), "x11".into());
+    assert!(parse_output_device_cfg(&items).is_err());
+    items.remove("linux-output-split-mouse");
+    items.remove("linux-output-backend");
+    items.insert("linux-output-device-capabilities".into(), "rel".into());
+    assert!(parse_output_device_cfg(&items).is_err());
+    items.insert("linux-output-device-capabilities".into(), "keys abs".into());
+    assert!(parse_output_device_cfg(&items).is_err());
+    items.insert("linux-output-device-vendor-id".into(), "0x10000".into());
+    assert!(parse_output_device_cfg(&items).is_err());
+}
+
+#[cfg(target_os = "linux")]
+fn parse_stripped_events(s: &str) -> Result<StrippedEvents> {
+    let mut stripped = StrippedEvents::default();
+    for event in s.split_whitespace() {
+        match event {
+            "msc-scan" => stripped.msc_scan = true,
+            "led" => stripped.led = true,
+            "rep" => stripped.rep = true,
+            _ => bail!("linux-strip-events got {event}. It accepts: msc-scan|led|rep"),
+        }
+    }
+    Ok(stripped)
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn parse_stripped_events_from_cfg() {
+    assert_eq!(
+        parse_stripped_events("rep msc-scan").unwrap(),
+        StrippedEvents {
+            msc_scan: true,
+            led: false,
+            rep: true,
+        }
+    );
+    assert_eq!(
+        parse_stripped_events("").unwrap(),
+        StrippedEvents::default()
+    );
+    assert!(parse_stripped_events("msc").is_err());
+}
+
+/// Reverse lookup of `fake_keys` by column.
+fn fake_key_names(fake_keys: &HashMap<String, usize>) -> Vec<String> {
+    let mut names = vec![String::new(); fake_keys.values().max().map_or(0, |max| max + 1)];
+    for (name, &idx) in fake_keys.iter() {
+        names[idx] = name.clone();
+    }
+    names
+}
+
+fn fake_key_name(names: &[String], y: u16) -> &str {
+    match names.get(usize::from(y)) {
+        Some(name) if !name.is_empty() => name,
+        _ => "unnamed fake key",
+    }
+}
+
+/// Whether a physical key is held or its action is not resolved yet. Fake keys are ignored since
+/// they can stay pressed indefinitely.
+fn keys_are_held(layout: &BorrowedKLayout) -> bool {
+    layout.waiting.is_some()
+        || !layout.queue.is_empty()
+        || layout.states.iter().any(|s| match s {
+            State::NormalKey { coord, .. }
+            | State::LayerModifier { coord, .. }
+            | State::Custom { coord, .. }
+            | State::RepeatingSequence { coord, .. }
+            | State::OnRelease { coord, .. } => coord.0 == 0,
+            _ => false,
+        })
+}
+
+#[test]
+fn keys_are_held_until_released_and_resolved() {
+    let path = std::env::temp_dir().join(format!("kanata-held-{}.kbd", std::process::id()));
+    std::fs::write(
+        &path,
+        "
+(defsrc a s)
+(deflayer base (tap-hold 200 200 a lctl) s)
+(deffakekeys fk b)
+",
+    )
+    .unwrap();
+    let cfg = cfg::new_from_file(&path);
+    std::fs::remove_file(&path).unwrap();
+    let mut cfg = cfg.unwrap();
+    let layout = cfg.layout.bm();
+    let a = OsCode::KEY_A as u16;
+    assert!(!keys_are_held(layout));
+    layout.event(Event::Press(0, a));
+    layout.tick();
+    assert!(keys_are_held(layout));
+    layout.event(Event::Release(0, a));
+    for _ in 0..5 {
+        layout.tick();
+    }
+    assert!(!keys_are_held(layout));
+
+    // A fake key that stays pressed does not count.
+    let (x, y) = get_fake_key_coords(cfg.fake_keys["fk"]);
+    let layout = cfg.layout.bm();
+    layout.event(Event::Press(x, y));
+    layout.tick();
+    assert!(!layout.states.is_empty());
+    assert!(!keys_are_held(layout));
+}
+
+#[test]
+fn fake_key_names_are_looked_up_by_column() {
+    let mut fake_keys = HashMap::default();
+    fake_keys.insert("paste".to_string(), 0);
+    fake_keys.insert("layer-enter nav hook".to_string(), 2);
+    let names = fake_key_names(&fake_keys);
+    assert_eq!(fake_key_name(&names, 0), "paste");
+    assert_eq!(fake_key_name(&names, 1), "unnamed fake key");
+    assert_eq!(fake_key_name(&names, 2), "layer-enter nav hook");
+    assert_eq!(fake_key_name(&names, 3), "unnamed fake key");
+}
@@ -0,0 +1,92 @@
+//! Modifier chords, e.g. both shifts together for caps-word.
+//!
+//! Chords of other keys wait for the chord timeout before their keys are output, which would delay
+//! every shortcut if done for modifiers. Instead, the modifiers of a modifier chord are passed to
+//! the layout as usual and the chord action is tapped on top when the last of its modifiers is
+//! pressed. Pressing any other key while a chord modifier is held means the modifiers are used
+//! normally, so the chord does not trigger until all of them have been released.
+
+use crate::cfg::ModChord;
+use crate::keys::{KeyEvent, KeyValue, OsCode};
+
+#[derive(Debug, Default)]
+pub struct ModChords {
+    chords: Vec<ModChord>,
+    held: Vec<OsCode>,
+    interrupted: bool,
+}
+
+impl ModChords {
+    pub fn new(chords: Vec<ModChord>) -> Self {
+        Self {
+            chords,
+            ..Default::default()
+        }
+    }
+
+    /// Process a key event and return the fake key to tap, if it completes a chord.
+    pub fn key(&mut self, event: &KeyEvent) -> Option<(u8, u16)> {
+        if self.chords.is_empty() {
+            return None;
+        }
+        let is_chord_key = self
+            .chords
+            .iter()
+            .any(|(mods, _)| mods.contains(&event.code));
+        match event.value {
+            KeyValue::Press if is_chord_key => {
+                if self.held.is_empty() {
+                    self.interrupted = false;
+                }
+                if !self.held.contains(&event.code) {
+                    self.held.push(event.code);
+                }
+                if self.interrupted {
+                    return None;
+                }
+                self.chords
+                    .iter()
+                    .find(|(mods, _)| {
+                        mods.contains(&event.code) && mods.iter().all(|m| self.held.contains(m))
+                    })
+                    .map(|(_, coord)| *coord)
+            }
+            KeyValue::Press => {
+                self.interrupted |= !self.held.is_empty();
+                None
+            }
+            KeyValue::Release => {
+                self.held.retain(|k| *k != event.code);
+                None
+            }
+            KeyValue::Repeat => None,
+        }
+    }
+}
+
+#[test]
+fn mod_chords_trigger_only_without_other_keys() {
+    let press = |code| KeyEvent::new(code, KeyValue::Press);
+    let release = |code| KeyEvent::new(code, KeyValue::Release);
+    let mut chords = ModChords::new(vec![(
+        vec![OsCode::KEY_LEFTSHIFT, OsCode::KEY_RIGHTSHIFT],
+        (1, 0),
+    )]);
+
+    assert_eq!(chords.key(&press(OsCode::KEY_LEFTSHIFT)), None);
+    assert_eq!(chords.key(&press(OsCode::KEY_RIGHTSHIFT)), Some((1, 0)));
+    assert_eq!(chords.key(&release(OsCode::KEY_RIGHTSHIFT)), None);
+    // The chord triggers again if the other modifier is pressed again.
+    assert_eq!(chords.key(&press(OsCode::KEY_RIGHTSHIFT)), Some((1, 0)));
+    assert_eq!(chords.key(&release(OsCode::KEY_RIGHTSHIFT)), None);
+
+    // Typing with a held shift means it is used normally.
+    assert_eq!(chords.key(&press(OsCode::KEY_A)), None);
+    assert_eq!(chords.key(&press(OsCode::KEY_RIGHTSHIFT)), None);
+    assert_eq!(chords.key(&release(OsCode::KEY_RIGHTSHIFT)), None);
+    assert_eq!(chords.key(&release(OsCode::KEY_LEFTSHIFT)), None);
+
+    // Releasing all modifiers resets that.
+    assert_eq!(chords.key(&press(OsCode::KEY_RIGHTSHIFT)), None);
+    assert_eq!(chords.key(&press(OsCode::KEY_LEFTSHIFT)), Some((1, 0)));
+}
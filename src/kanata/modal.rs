@@ -0,0 +1,208 @@
+//! Modal editing: a vim-like normal mode for every application, on top of layers.
+//!
+//! `(defmodal <layer-name> (<key> <chord> <layer-name>?)...)` makes a layer modal. The layer maps
+//! keys to motions as usual, e.g. `j` to `down`, and is entered and left with layer actions, e.g.
+//! `layer-switch`. While it is the current layer, the keys that the layout outputs are processed
+//! by a state machine before they are pressed:
+//!
+//! - Digits are captured as a count, so that `5` then `down` taps `down` five times, see
+//!   `count_prefix`.
+//! - An operator key is captured as the pending operator. The next motion is selected by tapping
+//!   it with shift held, as often as the count says, and the chord of the operator is tapped on
+//!   the selection, e.g. `C-x` to delete it. Pressing the operator key again selects whole lines
+//!   instead, like `dd`. Counts before the operator and before the motion multiply, like `2d3w`.
+//!   If the operator has a layer, it is switched to afterwards, e.g. to insert text after `c`.
+//! - Escape cancels a pending count or operator.
+//!
+//! Modifiers are output as usual, so that motions like `C-right` work. Leaving the layer drops a
+//! pending operator.
+
+use super::*;
+
+#[derive(Debug, Default)]
+pub struct Modal {
+    /// The keyberon layers that are modal and their operators.
+    layers: Vec<(usize, Vec<ModalOperator>)>,
+    /// The pending operator and the count typed before it.
+    operator: Option<(ModalOperator, u32)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModalNext {
+    /// Output the key as usual, repeated by the count if one is pending.
+    Output,
+    /// The key was captured as part of a count or an operator.
+    Captured,
+    /// Select the motion, or whole lines if `None`, this many times and apply the operator.
+    Operate {
+        motion: Option<OsCode>,
+        count: u32,
+        operator: ModalOperator,
+    },
+}
+
+impl Modal {
+    pub fn new(layers: Vec<(usize, Vec<ModalOperator>)>) -> Self {
+        Self {
+            layers,
+            ..Default::default()
+        }
+    }
+
+    /// Handle a key that the layout outputs while the keyberon layer is the current layer, with
+    /// the count typed so far.
+    pub fn key(&mut self, key: OsCode, layer: usize, count: &mut Count) -> ModalNext {
+        let Some((_, operators)) = self.layers.iter().find(|(l, _)| *l == layer) else {
+            self.operator = None;
+            return ModalNext::Output;
+        };
+        if KeyCode::from(key).is_modifier() {
+            return ModalNext::Output;
+        }
+        if count.digit(key) {
+            return ModalNext::Captured;
+        }
+        if key == OsCode::KEY_ESC && (count.is_pending() || self.operator.is_some()) {
+            log::debug!("modal count and operator cancelled");
+            count.clear();
+            self.operator = None;
+            return ModalNext::Captured;
+        }
+        if let Some(operator) = operators.iter().find(|op| op.key == key) {
+            let count = count.take();
+            match self.operator.take() {
+                Some((pending, before)) if pending.key == key => {
+                    return ModalNext::Operate {
+                        motion: None,
+                        count: (before * count).min(MAX_COUNT),
+                        operator: pending,
+                    };
+                }
+                _ => {
+                    log::debug!("modal operator {key:?} pending");
+                    self.operator = Some((operator.clone(), count));
+                    return ModalNext::Captured;
+                }
+            }
+        }
+        match self.operator.take() {
+            Some((operator, before)) => ModalNext::Operate {
+                motion: Some(key),
+                count: (before * count.take()).min(MAX_COUNT),
+                operator,
+            },
+            None => ModalNext::Output,
+        }
+    }
+}
+
+/// Select the motion, or whole lines, and tap the chord of the operator on the selection.
+pub fn modal_operate(
+    kbd_out: &mut KbdOut,
+    motion: Option<OsCode>,
+    count: u32,
+    chord: &[OsCode],
+) -> Result<()> {
+    let tap = |kbd_out: &mut KbdOut, osc: OsCode| -> Result<()> {
+        kbd_out.press_key(osc)?;
+        kbd_out.release_key(osc)?;
+        Ok(())
+    };
+    if motion.is_none() {
+        tap(kbd_out, OsCode::KEY_HOME)?;
+    }
+    kbd_out.press_key(OsCode::KEY_LEFTSHIFT)?;
+    match motion {
+        Some(motion) => {
+            for _ in 0..count {
+                tap(kbd_out, motion)?;
+            }
+        }
+        None => {
+            for _ in 1..count {
+                tap(kbd_out, OsCode::KEY_DOWN)?;
+            }
+            tap(kbd_out, OsCode::KEY_END)?;
+        }
+    }
+    kbd_out.release_key(OsCode::KEY_LEFTSHIFT)?;
+    for &osc in chord {
+        kbd_out.press_key(osc)?;
+    }
+    for &osc in chord.iter().rev() {
+        kbd_out.release_key(osc)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn modal_counts_and_operators() {
+    let delete = ModalOperator {
+        key: OsCode::KEY_D,
+        chord: vec![OsCode::KEY_LEFTCTRL, OsCode::KEY_X],
+        then_layer: None,
+    };
+    let change = ModalOperator {
+        key: OsCode::KEY_C,
+        chord: vec![OsCode::KEY_LEFTCTRL, OsCode::KEY_X],
+        then_layer: Some(0),
+    };
+    let mut modal = Modal::new(vec![
+        (2, vec![delete.clone(), change.clone()]),
+        (3, vec![delete.clone(), change.clone()]),
+    ]);
+    let mut count = Count::default();
+    let mut key = |osc: OsCode, layer: usize| modal.key(osc, layer, &mut count);
+    assert_eq!(key(OsCode::KEY_5, 0), ModalNext::Output);
+    assert_eq!(key(OsCode::KEY_DOWN, 2), ModalNext::Output);
+    assert_eq!(key(OsCode::KEY_0, 2), ModalNext::Output);
+    assert_eq!(key(OsCode::KEY_1, 2), ModalNext::Captured);
+    assert_eq!(key(OsCode::KEY_0, 3), ModalNext::Captured);
+    assert_eq!(key(OsCode::KEY_LEFTCTRL, 2), ModalNext::Output);
+    // The count is left for the key.
+    assert_eq!(key(OsCode::KEY_DOWN, 2), ModalNext::Output);
+    assert_eq!(count.take(), 10);
+
+    let mut key = |osc: OsCode, layer: usize| modal.key(osc, layer, &mut count);
+    assert_eq!(key(OsCode::KEY_2, 2), ModalNext::Captured);
+    assert_eq!(key(OsCode::KEY_D, 2), ModalNext::Captured);
+    assert_eq!(key(OsCode::KEY_3, 2), ModalNext::Captured);
+    assert_eq!(
+        key(OsCode::KEY_RIGHT, 2),
+        ModalNext::Operate {
+            motion: Some(OsCode::KEY_RIGHT),
+            count: 6,
+            operator: delete.clone(),
+        }
+    );
+    assert_eq!(key(OsCode::KEY_D, 2), ModalNext::Captured);
+    assert_eq!(
+        key(OsCode::KEY_D, 2),
+        ModalNext::Operate {
+            motion: None,
+            count: 1,
+            operator: delete.clone(),
+        }
+    );
+    // Another operator replaces the pending one.
+    assert_eq!(key(OsCode::KEY_D, 2), ModalNext::Captured);
+    assert_eq!(key(OsCode::KEY_C, 2), ModalNext::Captured);
+    assert_eq!(
+        key(OsCode::KEY_LEFT, 2),
+        ModalNext::Operate {
+            motion: Some(OsCode::KEY_LEFT),
+            count: 1,
+            operator: change,
+        }
+    );
+
+    assert_eq!(key(OsCode::KEY_4, 2), ModalNext::Captured);
+    assert_eq!(key(OsCode::KEY_D, 2), ModalNext::Captured);
+    assert_eq!(key(OsCode::KEY_ESC, 2), ModalNext::Captured);
+    assert_eq!(key(OsCode::KEY_ESC, 2), ModalNext::Output);
+    // Leaving the layer drops the operator.
+    assert_eq!(key(OsCode::KEY_D, 2), ModalNext::Captured);
+    assert_eq!(key(OsCode::KEY_A, 0), ModalNext::Output);
+    assert_eq!(key(OsCode::KEY_UP, 2), ModalNext::Output);
+    assert!(!count.is_pending());
+}
@@ -0,0 +1,142 @@
+//! Suppression of bare modifier taps, e.g. so that tapping the Super key alone does not open the
+//! GNOME overview or the Windows start menu.
+//!
+//! `suppress-modifier-taps` lists defsrc keys, e.g. `"lmet rmet"`. When the layout outputs one of
+//! them while that key is held and nothing else is pressed on the output until it is released,
+//! kanata taps the key of `modifier-tap-mask`, left control by default, just before the release.
+//! The OS then sees a shortcut instead of a bare tap. The key still works as a modifier, and
+//! output that the key is explicitly bound to, e.g. the tap of `(tap-hold 200 200 lmet lctl)`
+//! which is output after the key is released, is left alone.
+
+use super::*;
+
+pub const SUPPRESS_MODIFIER_TAPS_CFG_NAME: &str = "suppress-modifier-taps";
+pub const MODIFIER_TAP_MASK_CFG_NAME: &str = "modifier-tap-mask";
+
+#[derive(Debug)]
+pub struct ModifierTaps {
+    keys: Vec<OsCode>,
+    mask: OsCode,
+    /// The listed keys that are held on the input.
+    held: Vec<OsCode>,
+    /// The listed key that was output while it was held, with no other output press since.
+    bare: Option<OsCode>,
+}
+
+impl Default for ModifierTaps {
+    fn default() -> Self {
+        Self {
+            keys: vec![],
+            mask: OsCode::KEY_LEFTCTRL,
+            held: vec![],
+            bare: None,
+        }
+    }
+}
+
+impl ModifierTaps {
+    pub fn from_cfg(items: &HashMap<String, String>, mapped_keys: &MappedKeys) -> Result<Self> {
+        let key = |cfg_name: &str, name: &str| {
+            str_to_oscode(name).ok_or_else(|| anyhow!("{cfg_name}: unknown key {name}"))
+        };
+        let mut taps = Self::default();
+        for name in items
+            .get(SUPPRESS_MODIFIER_TAPS_CFG_NAME)
+            .map(String::as_str)
+            .unwrap_or_default()
+            .split_whitespace()
+        {
+            let osc = key(SUPPRESS_MODIFIER_TAPS_CFG_NAME, name)?;
+            if !mapped_keys.contains(&osc) {
+                bail!("{SUPPRESS_MODIFIER_TAPS_CFG_NAME}: {name} is not in defsrc");
+            }
+            taps.keys.push(osc);
+        }
+        if let Some(name) = items.get(MODIFIER_TAP_MASK_CFG_NAME) {
+            taps.mask = key(MODIFIER_TAP_MASK_CFG_NAME, name)?;
+        }
+        if taps.keys.contains(&taps.mask) {
+            bail!(
+                "{MODIFIER_TAP_MASK_CFG_NAME} cannot be a key of {SUPPRESS_MODIFIER_TAPS_CFG_NAME}"
+            );
+        }
+        Ok(taps)
+    }
+
+    /// A key event reached the layout.
+    pub fn input(&mut self, event: &KeyEvent) {
+        if !self.keys.contains(&event.code) {
+            return;
+        }
+        match event.value {
+            KeyValue::Press => {
+                if !self.held.contains(&event.code) {
+                    self.held.push(event.code);
+                }
+            }
+            KeyValue::Release => self.held.retain(|k| *k != event.code),
+            KeyValue::Repeat => {}
+        }
+    }
+
+    /// A key was pressed on the output.
+    pub fn output_pressed(&mut self, key: OsCode) {
+        self.bare = self.held.contains(&key).then_some(key);
+    }
+
+    /// A key is about to be released on the output. Returns the key to tap first if this
+    /// release ends a bare tap.
+    pub fn output_released(&mut self, key: OsCode) -> Option<OsCode> {
+        (self.bare == Some(key)).then(|| {
+            self.bare = None;
+            self.mask
+        })
+    }
+}
+
+#[test]
+fn modifier_taps_are_masked_unless_interrupted() {
+    let mapped_keys = [OsCode::KEY_LEFTMETA, OsCode::KEY_A].into_iter().collect();
+    let mut items = HashMap::default();
+    items.insert(
+        SUPPRESS_MODIFIER_TAPS_CFG_NAME.to_owned(),
+        "lmet".to_owned(),
+    );
+    let mut taps = ModifierTaps::from_cfg(&items, &mapped_keys).unwrap();
+    let input = |taps: &mut ModifierTaps, value| {
+        taps.input(&KeyEvent::new(OsCode::KEY_LEFTMETA, value));
+    };
+
+    input(&mut taps, KeyValue::Press);
+    taps.output_pressed(OsCode::KEY_LEFTMETA);
+    input(&mut taps, KeyValue::Release);
+    assert_eq!(
+        taps.output_released(OsCode::KEY_LEFTMETA),
+        Some(OsCode::KEY_LEFTCTRL)
+    );
+
+    // Super+A is a shortcut already.
+    input(&mut taps, KeyValue::Press);
+    taps.output_pressed(OsCode::KEY_LEFTMETA);
+    taps.output_pressed(OsCode::KEY_A);
+    assert_eq!(taps.output_released(OsCode::KEY_A), None);
+    input(&mut taps, KeyValue::Release);
+    assert_eq!(taps.output_released(OsCode::KEY_LEFTMETA), None);
+
+    // Output while the key is not held, e.g. the tap of a tap-hold, is explicitly bound.
+    taps.output_pressed(OsCode::KEY_LEFTMETA);
+    assert_eq!(taps.output_released(OsCode::KEY_LEFTMETA), None);
+
+    for (name, bad) in [
+        (SUPPRESS_MODIFIER_TAPS_CFG_NAME, "rmet"),
+        (SUPPRESS_MODIFIER_TAPS_CFG_NAME, "nokey"),
+        (MODIFIER_TAP_MASK_CFG_NAME, "lmet"),
+    ] {
+        let mut items = items.clone();
+        items.insert(name.to_owned(), bad.to_owned());
+        assert!(
+            ModifierTaps::from_cfg(&items, &mapped_keys).is_err(),
+            "{bad}"
+        );
+    }
+}
@@ -0,0 +1,181 @@
+//! Single-key Morse code input.
+//!
+//! Presses of a `morse` key are classified as dots or dashes by how long they are held. Once no
+//! press has happened for the letter gap, the collected symbols are decoded and the character is
+//! typed. After the word gap, a space is typed and Morse input ends until the next press.
+
+use crate::custom_action::MorseCfg;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MorseOutput {
+    Char(char),
+    /// The symbols did not match any character.
+    Invalid,
+    WordGap,
+}
+
+#[derive(Debug)]
+pub struct MorseState {
+    pub cfg: MorseCfg,
+    /// Ticks the key has been held for, if it is currently held.
+    held_ticks: Option<u16>,
+    /// Ticks since the key was last released.
+    idle_ticks: u16,
+    /// Dots (false) and dashes (true) of the letter being input.
+    symbols: Vec<bool>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum MorseNextState {
+    Active,
+    End,
+}
+
+impl MorseState {
+    pub fn new(cfg: MorseCfg) -> Self {
+        Self {
+            cfg,
+            held_ticks: None,
+            idle_ticks: 0,
+            symbols: vec![],
+        }
+    }
+
+    /// Morse input times the held key and the gaps every tick.
+    pub fn ticks_until_due(&self) -> Option<u16> {
+        Some(1)
+    }
+
+    pub fn press(&mut self) {
+        self.held_ticks = Some(0);
+    }
+
+    pub fn release(&mut self) {
+        if let Some(held) = self.held_ticks.take() {
+            self.symbols.push(held >= self.cfg.dash_threshold);
+            self.idle_ticks = 0;
+        }
+    }
+
+    /// Advance the state by 1ms. Returns the output to type, if any, and whether Morse input is
+    /// still active.
+    pub fn tick(&mut self) -> (Option<MorseOutput>, MorseNextState) {
+        if let Some(held) = &mut self.held_ticks {
+            *held = held.saturating_add(1);
+            return (None, MorseNextState::Active);
+        }
+        self.idle_ticks = self.idle_ticks.saturating_add(1);
+        if !self.symbols.is_empty() && self.idle_ticks >= self.cfg.letter_gap {
+            let out = decode(&self.symbols).map_or(MorseOutput::Invalid, MorseOutput::Char);
+            self.symbols.clear();
+            return (Some(out), MorseNextState::Active);
+        }
+        if self.idle_ticks >= self.cfg.word_gap {
+            return (Some(MorseOutput::WordGap), MorseNextState::End);
+        }
+        (None, MorseNextState::Active)
+    }
+}
+
+/// International Morse code; `.` is a dot and `-` is a dash.
+const MORSE_TABLE: &[(&str, char)] = &[
+    (".-", 'a'),
+    ("-...", 'b'),
+    ("-.-.", 'c'),
+    ("-..", 'd'),
+    (".", 'e'),
+    ("..-.", 'f'),
+    ("--.", 'g'),
+    ("....", 'h'),
+    ("..", 'i'),
+    (".---", 'j'),
+    ("-.-", 'k'),
+    (".-..", 'l'),
+    ("--", 'm'),
+    ("-.", 'n'),
+    ("---", 'o'),
+    (".--.", 'p'),
+    ("--.-", 'q'),
+    (".-.", 'r'),
+    ("...", 's'),
+    ("-", 't'),
+    ("..-", 'u'),
+    ("...-", 'v'),
+    (".--", 'w'),
+    ("-..-", 'x'),
+    ("-.--", 'y'),
+    ("--..", 'z'),
+    (".----", '1'),
+    ("..---", '2'),
+    ("...--", '3'),
+    ("....-", '4'),
+    (".....", '5'),
+    ("-....", '6'),
+    ("--...", '7'),
+    ("---..", '8'),
+    ("----.", '9'),
+    ("-----", '0'),
+    (".-.-.-", '.'),
+    ("--..--", ','),
+    ("-..-.", '/'),
+    ("-...-", '='),
+];
+
+fn decode(symbols: &[bool]) -> Option<char> {
+    MORSE_TABLE
+        .iter()
+        .find(|(code, _)| {
+            code.len() == symbols.len()
+                && code
+                    .bytes()
+                    .zip(symbols.iter())
+                    .all(|(c, is_dash)| (c == b'-') == *is_dash)
+        })
+        .map(|(_, c)| *c)
+}
+
+#[test]
+fn morse_decodes_letters_and_word_gap() {
+    let mut state = MorseState::new(MorseCfg {
+        dash_threshold: 3,
+        letter_gap: 5,
+        word_gap: 10,
+    });
+    let tap = |state: &mut MorseState, hold: u16| {
+        state.press();
+        for _ in 0..hold {
+            assert_eq!(state.tick(), (None, MorseNextState::Active));
+        }
+        state.release();
+        state.tick()
+    };
+    // dash, dot -> n
+    tap(&mut state, 4);
+    tap(&mut state, 1);
+    for _ in 0..3 {
+        assert_eq!(state.tick(), (None, MorseNextState::Active));
+    }
+    assert_eq!(
+        state.tick(),
+        (Some(MorseOutput::Char('n')), MorseNextState::Active)
+    );
+    for _ in 0..4 {
+        assert_eq!(state.tick(), (None, MorseNextState::Active));
+    }
+    assert_eq!(
+        state.tick(),
+        (Some(MorseOutput::WordGap), MorseNextState::End)
+    );
+
+    let mut state = MorseState::new(state.cfg);
+    for _ in 0..7 {
+        tap(&mut state, 1);
+    }
+    for _ in 0..3 {
+        state.tick();
+    }
+    assert_eq!(
+        state.tick(),
+        (Some(MorseOutput::Invalid), MorseNextState::Active)
+    );
+}
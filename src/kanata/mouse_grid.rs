@@ -0,0 +1,140 @@
+//! Keyboard-driven mouse warping in the style of keynav.
+//!
+//! After the `mouse-grid` action, the cursor is warped to the center of the screen and the keys
+//! output by the layout are captured. Each key narrows the region of the screen that the cursor is
+//! in and warps it to the center of the new region: the arrow keys or `h j k l` keep a half of the
+//! region and the digits `1` to `9` pick a cell of a 3x3 grid in reading order. Backspace undoes
+//! the last step. Enter or space click the left button and end the grid, while escape or the
+//! sequence timeout end it and leave the cursor where it is.
+
+use kanata_keyberon::key_code::KeyCode;
+
+/// The size of the screen in `setmouse` coordinates, which go from 0 to 65535 on both axes.
+const SCREEN_SIZE: u32 = 1 << 16;
+
+/// A rectangle of the screen in `setmouse` coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl Region {
+    pub const SCREEN: Region = Region {
+        x: 0,
+        y: 0,
+        w: SCREEN_SIZE,
+        h: SCREEN_SIZE,
+    };
+
+    /// The coordinates of the center, as accepted by `KbdOut::set_mouse`.
+    pub fn center(&self) -> (u16, u16) {
+        let coord = |start: u32, len: u32| (start + len / 2).min(u32::from(u16::MAX)) as u16;
+        (coord(self.x, self.w), coord(self.y, self.h))
+    }
+
+    /// The cell at the column and row when the region is divided into a grid.
+    fn cell(&self, col: u32, row: u32, cols: u32, rows: u32) -> Region {
+        let x = self.x + self.w * col / cols;
+        let y = self.y + self.h * row / rows;
+        Region {
+            x,
+            y,
+            w: self.x + self.w * (col + 1) / cols - x,
+            h: self.y + self.h * (row + 1) / rows - y,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MouseGridState {
+    pub region: Region,
+    /// The previous regions, for undoing steps with backspace.
+    history: Vec<Region>,
+    pub ticks_until_timeout: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseGridNext {
+    Active,
+    /// Warp the cursor to these coordinates.
+    Warp(u16, u16),
+    /// Click the left button and end the grid.
+    Click,
+    End,
+}
+
+impl MouseGridState {
+    pub fn new(timeout: u16) -> Self {
+        Self {
+            region: Region::SCREEN,
+            history: vec![],
+            ticks_until_timeout: timeout,
+        }
+    }
+
+    /// The grid counts down its timeout every tick.
+    pub fn ticks_until_due(&self) -> Option<u16> {
+        Some(1)
+    }
+
+    /// Handle a key pressed while the grid is active.
+    pub fn key(&mut self, key: KeyCode) -> MouseGridNext {
+        use KeyCode::*;
+        const DIGITS: [KeyCode; 9] = [Kb1, Kb2, Kb3, Kb4, Kb5, Kb6, Kb7, Kb8, Kb9];
+        let next = match key {
+            Left | H => self.region.cell(0, 0, 2, 1),
+            Right | L => self.region.cell(1, 0, 2, 1),
+            Up | K => self.region.cell(0, 0, 1, 2),
+            Down | J => self.region.cell(0, 1, 1, 2),
+            BSpace => match self.history.pop() {
+                Some(prev) => {
+                    self.region = prev;
+                    let (x, y) = self.region.center();
+                    return MouseGridNext::Warp(x, y);
+                }
+                None => return MouseGridNext::Active,
+            },
+            Enter | KpEnter | Space => return MouseGridNext::Click,
+            Escape => return MouseGridNext::End,
+            _ => match DIGITS.iter().position(|&d| d == key) {
+                Some(i) => self.region.cell(i as u32 % 3, i as u32 / 3, 3, 3),
+                None => return MouseGridNext::Active,
+            },
+        };
+        if next.w == 0 || next.h == 0 || next == self.region {
+            return MouseGridNext::Active;
+        }
+        self.history.push(self.region);
+        self.region = next;
+        let (x, y) = self.region.center();
+        MouseGridNext::Warp(x, y)
+    }
+}
+
+#[test]
+fn mouse_grid_bisects_the_screen() {
+    let mut grid = MouseGridState::new(1000);
+    assert_eq!(grid.region.center(), (32768, 32768));
+    assert_eq!(grid.key(KeyCode::H), MouseGridNext::Warp(16384, 32768));
+    assert_eq!(grid.key(KeyCode::Down), MouseGridNext::Warp(16384, 49152));
+    assert_eq!(grid.key(KeyCode::A), MouseGridNext::Active);
+    assert_eq!(grid.key(KeyCode::BSpace), MouseGridNext::Warp(16384, 32768));
+    assert_eq!(grid.key(KeyCode::BSpace), MouseGridNext::Warp(32768, 32768));
+    assert_eq!(grid.key(KeyCode::BSpace), MouseGridNext::Active);
+
+    // The 3x3 grid is numbered in reading order.
+    assert_eq!(grid.key(KeyCode::Kb9), MouseGridNext::Warp(54613, 54613));
+    assert_eq!(grid.key(KeyCode::Kb1), MouseGridNext::Warp(47331, 47331));
+
+    // Bisecting stops at a single coordinate and never leaves the screen.
+    let mut grid = MouseGridState::new(1000);
+    for _ in 0..20 {
+        grid.key(KeyCode::L);
+    }
+    assert_eq!(grid.region.center(), (65535, 32768));
+    assert_eq!(grid.key(KeyCode::Space), MouseGridNext::Click);
+    assert_eq!(grid.key(KeyCode::Escape), MouseGridNext::End);
+}
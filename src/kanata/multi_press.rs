@@ -0,0 +1,96 @@
+//! The presses of an input key in a row, for commands that do different things on double and
+//! triple presses.
+//!
+//! A press of the same key within `multi-press-timeout` milliseconds of the previous one continues
+//! the row, any other press starts a new row. Commands run with the row of the last pressed key in
+//! `KANATA_PRESS_COUNT` and `KANATA_PRESS_TIMES`, e.g. when a tap-dance runs a command on its last
+//! tap.
+
+use super::*;
+
+pub const MULTI_PRESS_TIMEOUT_CFG_NAME: &str = "multi-press-timeout";
+const DEFAULT_MULTI_PRESS_TIMEOUT_MS: u16 = 300;
+
+#[derive(Debug)]
+pub struct MultiPress {
+    timeout: time::Duration,
+    key: Option<OsCode>,
+    /// The times of the presses in the row, oldest first.
+    times: Vec<time::Instant>,
+}
+
+impl Default for MultiPress {
+    fn default() -> Self {
+        Self {
+            timeout: time::Duration::from_millis(DEFAULT_MULTI_PRESS_TIMEOUT_MS.into()),
+            key: None,
+            times: vec![],
+        }
+    }
+}
+
+impl MultiPress {
+    pub fn update_from_cfg(&mut self, items: &HashMap<String, String>) -> Result<()> {
+        let ms = match items.get(MULTI_PRESS_TIMEOUT_CFG_NAME) {
+            Some(s) => s.parse::<u16>().map_err(|_| {
+                anyhow!("{MULTI_PRESS_TIMEOUT_CFG_NAME} must be 0-65535, found {s}")
+            })?,
+            None => DEFAULT_MULTI_PRESS_TIMEOUT_MS,
+        };
+        self.timeout = time::Duration::from_millis(ms.into());
+        Ok(())
+    }
+
+    pub fn press(&mut self, key: OsCode, now: time::Instant) {
+        let continues = self.key == Some(key)
+            && self
+                .times
+                .last()
+                .is_some_and(|at| now.duration_since(*at) <= self.timeout);
+        if !continues {
+            self.key = Some(key);
+            self.times.clear();
+        }
+        self.times.push(now);
+    }
+
+    /// The times of the presses in the row in milliseconds since the first one.
+    pub fn times_ms(&self) -> Vec<u64> {
+        let Some(first) = self.times.first() else {
+            return vec![];
+        };
+        self.times
+            .iter()
+            .map(|at| at.duration_since(*first).as_millis() as u64)
+            .collect()
+    }
+}
+
+impl Kanata {
+    /// The environment of the commands run now.
+    pub(super) fn cmd_env(&self) -> CmdEnv {
+        CmdEnv {
+            window: self.active_window.clone(),
+            presses: self.multi_press.times_ms(),
+        }
+    }
+}
+
+#[test]
+fn presses_of_the_same_key_in_a_row_are_counted() {
+    let start = time::Instant::now();
+    let ms = |ms| start + time::Duration::from_millis(ms);
+    let mut multi_press = MultiPress::default();
+    assert!(multi_press.times_ms().is_empty());
+    multi_press.press(OsCode::KEY_A, start);
+    multi_press.press(OsCode::KEY_A, ms(180));
+    multi_press.press(OsCode::KEY_A, ms(350));
+    assert_eq!(multi_press.times_ms(), [0, 180, 350]);
+    // Too late for the row.
+    multi_press.press(OsCode::KEY_A, ms(700));
+    assert_eq!(multi_press.times_ms(), [0]);
+    // Another key starts a new row.
+    multi_press.press(OsCode::KEY_B, ms(800));
+    multi_press.press(OsCode::KEY_A, ms(900));
+    assert_eq!(multi_press.times_ms(), [0]);
+}
@@ -0,0 +1,376 @@
+//! Layer colors on RGB keyboards through an OpenRGB server.
+//!
+//! When `openrgb-layer-colors` is configured, kanata connects to the SDK server of OpenRGB and sets
+//! the colors configured for a layer whenever that layer becomes active. Layers without colors
+//! keep the colors of the previous layer. The server is talked to from a separate thread so that a
+//! slow or missing server never delays key processing. If the connection fails it is tried again
+//! on the next layer change.
+
+use super::*;
+
+use std::io::Read;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+
+pub const OPENRGB_SERVER_CFG_NAME: &str = "openrgb-server";
+pub const OPENRGB_LAYER_COLORS_CFG_NAME: &str = "openrgb-layer-colors";
+
+const DEFAULT_SERVER: &str = "127.0.0.1:6742";
+const CONNECT_TIMEOUT: time::Duration = time::Duration::from_secs(1);
+
+const PACKET_REQUEST_CONTROLLER_COUNT: u32 = 0;
+const PACKET_REQUEST_CONTROLLER_DATA: u32 = 1;
+const PACKET_SET_CLIENT_NAME: u32 = 50;
+const PACKET_UPDATE_LEDS: u32 = 1050;
+const PACKET_UPDATE_SINGLE_LED: u32 = 1052;
+const PACKET_SET_CUSTOM_MODE: u32 = 1100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb(u8, u8, u8);
+
+impl Rgb {
+    fn parse(s: &str) -> Option<Self> {
+        let s = s.strip_prefix('#').unwrap_or(s);
+        if s.len() != 6 {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(s.get(i..i + 2)?, 16).ok();
+        Some(Self(channel(0)?, channel(2)?, channel(4)?))
+    }
+
+    fn bytes(self) -> [u8; 4] {
+        [self.0, self.1, self.2, 0]
+    }
+}
+
+/// The colors of one layer: a color for all LEDs and colors for individual LEDs by index.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LayerColors {
+    all: Option<Rgb>,
+    leds: Vec<(u32, Rgb)>,
+}
+
+pub struct OpenRgb {
+    /// Keyberon layer indexes and their colors.
+    layers: Vec<(usize, LayerColors)>,
+    tx: Sender<LayerColors>,
+}
+
+impl OpenRgb {
+    /// Read the layer colors from defcfg and start the thread that talks to the server. Returns
+    /// `None` if layer colors are not configured.
+    pub fn from_cfg(
+        items: &HashMap<String, String>,
+        layer_info: &[LayerInfo],
+    ) -> Result<Option<Self>> {
+        let cfg = match items.get(OPENRGB_LAYER_COLORS_CFG_NAME) {
+            Some(cfg) => cfg,
+            None => return Ok(None),
+        };
+        let layers = parse_layer_colors(cfg, layer_info)?;
+        let server = items
+            .get(OPENRGB_SERVER_CFG_NAME)
+            .map(String::as_str)
+            .unwrap_or(DEFAULT_SERVER);
+        let server = server
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .ok_or_else(|| anyhow!("{OPENRGB_SERVER_CFG_NAME}: invalid address {server}"))?;
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || run_client(server, rx));
+        Ok(Some(Self { layers, tx }))
+    }
+
+    /// Set the colors of the layer, if it has any.
+    pub fn layer_changed(&self, layer: usize) {
+        if let Some((_, colors)) = self.layers.iter().find(|(l, _)| *l == layer) {
+            // The thread only stops if this is dropped.
+            let _ = self.tx.send(colors.clone());
+        }
+    }
+}
+
+/// Parse `<layer>:<color>` entries, which set all LEDs, and `<layer>:<led>:<color>` entries, which
+/// set a single LED by its index.
+fn parse_layer_colors(cfg: &str, layer_info: &[LayerInfo]) -> Result<Vec<(usize, LayerColors)>> {
+    let mut layers: Vec<(usize, LayerColors)> = vec![];
+    for entry in cfg.split_whitespace() {
+        let parts = entry.split(':').collect::<Vec<_>>();
+        let (layer, led, color) = match parts[..] {
+            [layer, color] => (layer, None, color),
+            [layer, led, color] => (layer, Some(led), color),
+            _ => bail!(
+                "{OPENRGB_LAYER_COLORS_CFG_NAME} expects <layer>:<color> or \
+                 <layer>:<led>:<color>, found: {entry}"
+            ),
+        };
+        let color = Rgb::parse(color).ok_or_else(|| {
+            anyhow!("{OPENRGB_LAYER_COLORS_CFG_NAME}: invalid color {color}, expected rrggbb")
+        })?;
+        let led = led
+            .map(|led| {
+                led.parse::<u32>()
+                    .map_err(|_| anyhow!("{OPENRGB_LAYER_COLORS_CFG_NAME}: invalid LED {led}"))
+            })
+            .transpose()?;
+        let indexes = layer_info
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| l.name == layer)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        if indexes.is_empty() {
+            bail!("{OPENRGB_LAYER_COLORS_CFG_NAME} contains unknown layer: {layer}");
+        }
+        for i in indexes {
+            let colors = match layers.iter().position(|(l, _)| *l == i) {
+                Some(pos) => &mut layers[pos].1,
+                None => {
+                    layers.push((i, LayerColors::default()));
+                    &mut layers.last_mut().expect("pushed").1
+                }
+            };
+            match led {
+                Some(led) => colors.leds.push((led, color)),
+                None => colors.all = Some(color),
+            }
+        }
+    }
+    Ok(layers)
+}
+
+fn run_client(server: SocketAddr, rx: Receiver<LayerColors>) {
+    let mut connection: Option<Connection> = None;
+    while let Ok(colors) = rx.recv() {
+        // Only the most recent layer matters if several changed in the meantime.
+        let colors = rx.try_iter().last().unwrap_or(colors);
+        if connection.is_none() {
+            match Connection::open(server) {
+                Ok(c) => connection = Some(c),
+                Err(e) => {
+                    log::warn!("could not connect to the OpenRGB server at {server}: {e}");
+                    continue;
+                }
+            }
+        }
+        if let Some(Err(e)) = connection.as_mut().map(|c| c.set_colors(&colors)) {
+            log::warn!("failed to set OpenRGB colors: {e}");
+            connection = None;
+        }
+    }
+}
+
+struct Connection {
+    stream: TcpStream,
+    /// The number of LEDs of every controller.
+    controllers: Vec<u16>,
+}
+
+impl Connection {
+    fn open(server: SocketAddr) -> std::io::Result<Self> {
+        let stream = TcpStream::connect_timeout(&server, CONNECT_TIMEOUT)?;
+        stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+        let mut connection = Self {
+            stream,
+            controllers: vec![],
+        };
+        connection.send(0, PACKET_SET_CLIENT_NAME, b"kanata\0")?;
+        connection.send(0, PACKET_REQUEST_CONTROLLER_COUNT, &[])?;
+        let count = read_u32(&connection.receive(PACKET_REQUEST_CONTROLLER_COUNT)?, 0)?;
+        for device in 0..count {
+            connection.send(device, PACKET_REQUEST_CONTROLLER_DATA, &[])?;
+            let data = connection.receive(PACKET_REQUEST_CONTROLLER_DATA)?;
+            connection.controllers.push(controller_led_count(&data)?);
+            connection.send(device, PACKET_SET_CUSTOM_MODE, &[])?;
+        }
+        log::info!("connected to the OpenRGB server at {server}, {count} device(s)");
+        Ok(connection)
+    }
+
+    fn set_colors(&mut self, colors: &LayerColors) -> std::io::Result<()> {
+        for (device, leds) in self.controllers.clone().into_iter().enumerate() {
+            let device = device as u32;
+            if let Some(color) = colors.all {
+                self.send(device, PACKET_UPDATE_LEDS, &update_leds_data(color, leds))?;
+            }
+            for (led, color) in colors.leds.iter().filter(|(led, _)| *led < u32::from(leds)) {
+                let mut data = led.to_le_bytes().to_vec();
+                data.extend(color.bytes());
+                self.send(device, PACKET_UPDATE_SINGLE_LED, &data)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, device: u32, packet: u32, data: &[u8]) -> std::io::Result<()> {
+        let mut buf = packet_header(device, packet, data.len() as u32).to_vec();
+        buf.extend_from_slice(data);
+        self.stream.write_all(&buf)
+    }
+
+    /// Read packets until one with the given id arrives and return its data. The server may send
+    /// other packets in the meantime, e.g. when its device list changes.
+    fn receive(&mut self, packet: u32) -> std::io::Result<Vec<u8>> {
+        loop {
+            let mut header = [0; 16];
+            self.stream.read_exact(&mut header)?;
+            if &header[..4] != b"ORGB" {
+                return Err(invalid_data("invalid packet header"));
+            }
+            let mut data = vec![0; read_u32(&header, 12)? as usize];
+            self.stream.read_exact(&mut data)?;
+            if read_u32(&header, 8)? == packet {
+                return Ok(data);
+            }
+        }
+    }
+}
+
+fn packet_header(device: u32, packet: u32, size: u32) -> [u8; 16] {
+    let mut header = [0; 16];
+    header[..4].copy_from_slice(b"ORGB");
+    header[4..8].copy_from_slice(&device.to_le_bytes());
+    header[8..12].copy_from_slice(&packet.to_le_bytes());
+    header[12..].copy_from_slice(&size.to_le_bytes());
+    header
+}
+
+fn update_leds_data(color: Rgb, leds: u16) -> Vec<u8> {
+    let size = 4 + 2 + 4 * u32::from(leds);
+    let mut data = size.to_le_bytes().to_vec();
+    data.extend(leds.to_le_bytes());
+    for _ in 0..leds {
+        data.extend(color.bytes());
+    }
+    data
+}
+
+/// Returns the number of LEDs from the controller data of protocol version 0.
+fn controller_led_count(data: &[u8]) -> std::io::Result<u16> {
+    let mut reader = DataReader { data, pos: 0 };
+    reader.skip(4 + 4)?; // data size, device type
+    for _ in 0..5 {
+        reader.skip_string()?; // name, description, version, serial, location
+    }
+    let modes = reader.u16()?;
+    reader.skip(4)?; // active mode
+    for _ in 0..modes {
+        reader.skip_string()?;
+        reader.skip(4 * 9)?; // value, flags, speed and color limits, speed, direction, color mode
+        let colors = reader.u16()?;
+        reader.skip(4 * usize::from(colors))?;
+    }
+    let zones = reader.u16()?;
+    for _ in 0..zones {
+        reader.skip_string()?;
+        reader.skip(4 * 4)?; // type, LED count limits, LED count
+        let matrix_len = reader.u16()?;
+        reader.skip(usize::from(matrix_len))?;
+    }
+    reader.u16()
+}
+
+struct DataReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl DataReader<'_> {
+    fn skip(&mut self, len: usize) -> std::io::Result<()> {
+        if self.pos + len > self.data.len() {
+            return Err(invalid_data("controller data is too short"));
+        }
+        self.pos += len;
+        Ok(())
+    }
+
+    fn u16(&mut self) -> std::io::Result<u16> {
+        let start = self.pos;
+        self.skip(2)?;
+        Ok(u16::from_le_bytes([self.data[start], self.data[start + 1]]))
+    }
+
+    fn skip_string(&mut self) -> std::io::Result<()> {
+        let len = self.u16()?;
+        self.skip(usize::from(len))
+    }
+}
+
+fn read_u32(data: &[u8], pos: usize) -> std::io::Result<u32> {
+    data.get(pos..pos + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| invalid_data("packet is too short"))
+}
+
+fn invalid_data(msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+#[test]
+fn openrgb_layer_colors_and_protocol() {
+    let layer_info = ["base", "nav"]
+        .iter()
+        .map(|name| LayerInfo {
+            name: name.to_string(),
+            cfg_text: String::new(),
+            sounds: None,
+            tags: vec![],
+            notify: vec![],
+            doc: LayerDoc::default(),
+        })
+        .collect::<Vec<_>>();
+    let layers = parse_layer_colors("base:ffffff nav:#0000ff nav:3:ff0000", &layer_info).unwrap();
+    assert_eq!(
+        layers,
+        [
+            (
+                0,
+                LayerColors {
+                    all: Some(Rgb(255, 255, 255)),
+                    leds: vec![]
+                }
+            ),
+            (
+                1,
+                LayerColors {
+                    all: Some(Rgb(0, 0, 255)),
+                    leds: vec![(3, Rgb(255, 0, 0))]
+                }
+            ),
+        ]
+    );
+    assert!(parse_layer_colors("other:ffffff", &layer_info).is_err());
+    assert!(parse_layer_colors("base:fffff", &layer_info).is_err());
+    assert!(parse_layer_colors("base:x:ffffff", &layer_info).is_err());
+
+    assert_eq!(
+        update_leds_data(Rgb(1, 2, 3), 2),
+        [14, 0, 0, 0, 2, 0, 1, 2, 3, 0, 1, 2, 3, 0]
+    );
+
+    // Controller data with one mode with one color, one zone with a matrix and 3 LEDs.
+    let string = |s: &str| {
+        let mut b = (s.len() as u16 + 1).to_le_bytes().to_vec();
+        b.extend(s.as_bytes());
+        b.push(0);
+        b
+    };
+    let mut data = vec![0; 8];
+    for s in ["Keyboard", "desc", "1.0", "serial", "usb"] {
+        data.extend(string(s));
+    }
+    data.extend(1u16.to_le_bytes());
+    data.extend(0u32.to_le_bytes());
+    data.extend(string("Direct"));
+    data.extend([0; 36]);
+    data.extend(1u16.to_le_bytes());
+    data.extend([0; 4]);
+    data.extend(1u16.to_le_bytes());
+    data.extend(string("Keys"));
+    data.extend([0; 16]);
+    data.extend(12u16.to_le_bytes());
+    data.extend([0; 12]);
+    data.extend(3u16.to_le_bytes());
+    assert_eq!(controller_led_count(&data).unwrap(), 3);
+    assert!(controller_led_count(&data[..data.len() - 1]).is_err());
+}
@@ -0,0 +1,116 @@
+//! The text that kanata recently output, for features that replace typed text such as short codes
+//! and snippets.
+//!
+//! Since this is effectively a short keylog, it is kept to a minimum: nothing is recorded unless
+//! `output-history` is enabled in defcfg, only the last few characters are kept, and they are
+//! cleared whenever the text is likely to have been submitted or to be in another place: on enter,
+//! tab, mouse clicks, keys that move the cursor and on request of a TCP client, e.g. when the
+//! focused window changes. The history is only held in memory, is never persisted or sent to TCP
+//! clients, and its contents are not printed in logs.
+
+use super::*;
+
+/// The number of characters kept in the output history.
+const OUTPUT_HISTORY_LEN: usize = 64;
+
+/// The characters of the keys that kanata recently output. Backspace removes a character and
+/// keys without a character, such as arrows, clear the history since the cursor may have moved.
+#[derive(Default)]
+pub struct OutputHistory {
+    enabled: bool,
+    chars: String,
+}
+
+impl std::fmt::Debug for OutputHistory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OutputHistory")
+            .field("enabled", &self.enabled)
+            .field("len", &self.chars.chars().count())
+            .finish()
+    }
+}
+
+impl OutputHistory {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            chars: String::new(),
+        }
+    }
+
+    /// Read `output-history` from defcfg. The history is disabled by default.
+    pub fn from_cfg(cfg: &HashMap<String, String>) -> Self {
+        Self::new(
+            cfg.get("output-history")
+                .map(|s| matches!(s.to_lowercase().as_str(), "yes" | "true"))
+                .unwrap_or_default(),
+        )
+    }
+
+    pub fn clear(&mut self) {
+        self.chars.clear();
+    }
+
+    /// Record an output key press. Returns whether a character was added.
+    pub fn key(&mut self, key: KeyCode, shifted: bool) -> bool {
+        if !self.enabled || key.is_modifier() {
+            return false;
+        }
+        if key == KeyCode::BSpace {
+            self.chars.pop();
+            return false;
+        }
+        let c = match key_char(key, shifted) {
+            Some('\n' | '\t') | None => {
+                self.chars.clear();
+                return false;
+            }
+            Some(c) => c,
+        };
+        if self.chars.chars().count() == OUTPUT_HISTORY_LEN {
+            self.chars.remove(0);
+        }
+        self.chars.push(c);
+        true
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.chars
+    }
+}
+
+#[test]
+fn output_history_is_opt_in_and_cleared() {
+    let mut history = OutputHistory::default();
+    assert!(!history.key(KeyCode::A, false));
+    assert_eq!(history.as_str(), "");
+
+    let mut history = OutputHistory::new(true);
+    for (key, shifted) in [
+        (KeyCode::H, true),
+        (KeyCode::I, false),
+        (KeyCode::LShift, true),
+        (KeyCode::Kb1, true),
+        (KeyCode::Kb2, false),
+        (KeyCode::BSpace, false),
+        (KeyCode::SColon, false),
+    ] {
+        history.key(key, shifted);
+    }
+    assert_eq!(history.as_str(), "Hi!;");
+    assert_eq!(
+        format!("{history:?}"),
+        "OutputHistory { enabled: true, len: 4 }"
+    );
+    for key in [KeyCode::Enter, KeyCode::Tab, KeyCode::Left] {
+        history.key(KeyCode::A, false);
+        history.key(key, false);
+        assert_eq!(history.as_str(), "", "{key:?}");
+    }
+    for _ in 0..OUTPUT_HISTORY_LEN + 1 {
+        history.key(KeyCode::A, false);
+    }
+    history.key(KeyCode::B, false);
+    assert!(history.as_str().ends_with("aab"));
+    assert_eq!(history.as_str().len(), OUTPUT_HISTORY_LEN);
+}
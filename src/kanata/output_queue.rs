@@ -0,0 +1,228 @@
+//! Output by priority, so that output that can wait never delays live typing.
+//!
+//! Output has three priority classes:
+//! - interactive: the output of the keys that are pressed, which is written as soon as it is
+//!   known, as well as everything else that the layers output.
+//! - macro: text that actions type, e.g. `clipboard-paste`.
+//! - background: keys and text injected by TCP clients and other machines of `kvm-listen`.
+//!
+//! Macro and background output is queued and written at the end of each tick, after the
+//! interactive output, at most `output-queue-rate` events per millisecond, macro output first.
+//! A long text thus no longer holds up the processing of the keys pressed meanwhile. With
+//! `output-interactive-yield`, the queued output, keyberon macros and dynamic macro replays also
+//! pause for that many milliseconds after every key event of the input devices.
+
+use super::*;
+
+pub const OUTPUT_QUEUE_RATE_CFG_NAME: &str = "output-queue-rate";
+pub const OUTPUT_INTERACTIVE_YIELD_CFG_NAME: &str = "output-interactive-yield";
+const DEFAULT_RATE: u16 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputPriority {
+    Macro,
+    Background,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum QueuedOutput {
+    Key(KeyEvent),
+    Unicode(char),
+}
+
+#[derive(Debug)]
+pub struct OutputQueue {
+    macro_output: VecDeque<QueuedOutput>,
+    background: VecDeque<QueuedOutput>,
+    /// Events written per millisecond.
+    rate: u16,
+    yield_ms: u16,
+    /// Milliseconds until the queued output continues after interactive output.
+    yield_remaining: u16,
+}
+
+impl Default for OutputQueue {
+    fn default() -> Self {
+        Self {
+            macro_output: VecDeque::new(),
+            background: VecDeque::new(),
+            rate: DEFAULT_RATE,
+            yield_ms: 0,
+            yield_remaining: 0,
+        }
+    }
+}
+
+impl OutputQueue {
+    /// Read the rate and the yield time from defcfg, keeping the queued output.
+    pub fn update_from_cfg(&mut self, items: &HashMap<String, String>) -> Result<()> {
+        let number = |name: &str, default: u16| -> Result<u16> {
+            items.get(name).map_or(Ok(default), |s| {
+                s.parse()
+                    .map_err(|_| anyhow!("{name} must be 0-65535, found {s}"))
+            })
+        };
+        self.rate = number(OUTPUT_QUEUE_RATE_CFG_NAME, DEFAULT_RATE)?;
+        if self.rate == 0 {
+            bail!("{OUTPUT_QUEUE_RATE_CFG_NAME} must be a positive number");
+        }
+        self.yield_ms = number(OUTPUT_INTERACTIVE_YIELD_CFG_NAME, 0)?;
+        self.yield_remaining = self.yield_remaining.min(self.yield_ms);
+        Ok(())
+    }
+
+    fn queue(&mut self, priority: OutputPriority) -> &mut VecDeque<QueuedOutput> {
+        match priority {
+            OutputPriority::Macro => &mut self.macro_output,
+            OutputPriority::Background => &mut self.background,
+        }
+    }
+
+    pub fn push_keys(&mut self, priority: OutputPriority, events: &[KeyEvent]) {
+        self.queue(priority)
+            .extend(events.iter().map(|&event| QueuedOutput::Key(event)));
+    }
+
+    /// Queue the text, pressing shift where the US layout needs it, like `type_text`.
+    pub fn push_text(&mut self, priority: OutputPriority, text: &str) {
+        let key = |code, value| QueuedOutput::Key(KeyEvent::new(code, value));
+        let queue = self.queue(priority);
+        for text_key in text_keys(text) {
+            match text_key {
+                TextKey::Key { osc, shift } => {
+                    if shift {
+                        queue.push_back(key(OsCode::KEY_LEFTSHIFT, KeyValue::Press));
+                    }
+                    queue.push_back(key(osc, KeyValue::Press));
+                    queue.push_back(key(osc, KeyValue::Release));
+                    if shift {
+                        queue.push_back(key(OsCode::KEY_LEFTSHIFT, KeyValue::Release));
+                    }
+                }
+                TextKey::Unicode(c) => queue.push_back(QueuedOutput::Unicode(c)),
+            }
+        }
+    }
+
+    /// A key event of the input devices was processed.
+    pub fn interactive_input(&mut self) {
+        self.yield_remaining = self.yield_ms;
+    }
+
+    /// Whether output that is not interactive should wait.
+    pub fn yielding(&self) -> bool {
+        self.yield_remaining > 0
+    }
+
+    /// Advance by a millisecond. Returns how many queued events may be written now.
+    pub fn tick(&mut self) -> usize {
+        if self.yielding() {
+            self.yield_remaining -= 1;
+            return 0;
+        }
+        self.rate.into()
+    }
+
+    /// Tick every millisecond while output is queued, otherwise until the yield is over.
+    pub fn ticks_until_due(&self) -> Option<u16> {
+        match (self.is_empty(), self.yield_remaining) {
+            (false, _) => Some(1),
+            (true, 0) => None,
+            (true, ms) => Some(ms),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.macro_output.is_empty() && self.background.is_empty()
+    }
+
+    /// The next queued event, macro output first.
+    pub fn pop(&mut self) -> Option<QueuedOutput> {
+        self.macro_output
+            .pop_front()
+            .or_else(|| self.background.pop_front())
+    }
+
+    /// Drop the queued output. Returns the keys that were pressed by the output that was
+    /// already written and are still held.
+    pub fn clear(&mut self) -> Vec<OsCode> {
+        let mut pressed = vec![];
+        let mut held = vec![];
+        for output in self.macro_output.drain(..).chain(self.background.drain(..)) {
+            let QueuedOutput::Key(event) = output else {
+                continue;
+            };
+            match event.value {
+                KeyValue::Release if !pressed.contains(&event.code) => {
+                    if !held.contains(&event.code) {
+                        held.push(event.code);
+                    }
+                }
+                KeyValue::Release => pressed.retain(|k| *k != event.code),
+                _ => pressed.push(event.code),
+            }
+        }
+        held
+    }
+}
+
+impl Kanata {
+    /// Write the queued output that is due in this millisecond.
+    pub(super) fn tick_output_queue(&mut self) -> Result<()> {
+        for _ in 0..self.output_queue.tick() {
+            if !self.rate_limit.allows() {
+                break;
+            }
+            let Some(output) = self.output_queue.pop() else {
+                break;
+            };
+            self.rate_limit.record();
+            match output {
+                QueuedOutput::Key(event) => match event.value {
+                    KeyValue::Release => self.kbd_out.release_key(event.code)?,
+                    _ => self.kbd_out.press_key(event.code)?,
+                },
+                QueuedOutput::Unicode(c) => self.kbd_out.send_unicode(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn queued_output_waits_for_interactive_output() {
+    let mut queue = OutputQueue::default();
+    let items = [(OUTPUT_INTERACTIVE_YIELD_CFG_NAME.to_owned(), "2".to_owned())]
+        .into_iter()
+        .collect();
+    queue.update_from_cfg(&items).unwrap();
+    queue.push_keys(
+        OutputPriority::Background,
+        &[KeyEvent::new(OsCode::KEY_B, KeyValue::Press)],
+    );
+    queue.push_text(OutputPriority::Macro, "A");
+    let next = |queue: &mut OutputQueue| match queue.pop() {
+        Some(QueuedOutput::Key(event)) => Some((event.code, event.value)),
+        _ => None,
+    };
+    assert_eq!(
+        next(&mut queue),
+        Some((OsCode::KEY_LEFTSHIFT, KeyValue::Press))
+    );
+
+    queue.interactive_input();
+    assert!(queue.yielding());
+    assert_eq!(queue.tick(), 0);
+    assert_eq!(queue.tick(), 0);
+    assert_eq!(queue.tick(), usize::from(DEFAULT_RATE));
+
+    assert_eq!(next(&mut queue), Some((OsCode::KEY_A, KeyValue::Press)));
+    // The release of shift is still queued, so shift is held.
+    assert_eq!(queue.clear(), [OsCode::KEY_A, OsCode::KEY_LEFTSHIFT]);
+    assert!(queue.pop().is_none());
+
+    let items = [(OUTPUT_QUEUE_RATE_CFG_NAME.to_owned(), "0".to_owned())]
+        .into_iter()
+        .collect();
+    assert!(queue.update_from_cfg(&items).is_err());
+}
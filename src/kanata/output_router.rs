@@ -0,0 +1,238 @@
+//! Routing of the output to this machine, a second virtual keyboard or another machine.
+//!
+//! The destinations of the output are called targets: `local` is the output device,
+//! `secondary` is the second virtual keyboard of `linux-output-secondary-device-name`, and the
+//! names of `kvm-targets` are other machines. `output-layer-targets` gives layers a default
+//! target as pairs of a layer and a target, e.g. `output-layer-targets "gaming secondary remote
+//! laptop"`, so that the output goes to `laptop` while the `remote` layer is active. Layers that
+//! are not listed output locally.
+//!
+//! `(route-output laptop)` and `(kvm-target laptop)` send the output to a target whichever layer
+//! is active, until `(route-output auto)` goes back to the targets of the layers. Keys held when
+//! the target changes are released on the old target and pressed again on the new one. Routing
+//! the output is only supported on Linux.
+
+use super::*;
+
+pub const OUTPUT_LAYER_TARGETS_CFG_NAME: &str = "output-layer-targets";
+
+/// Target names that `kvm-targets` cannot use.
+pub const RESERVED_TARGET_NAMES: &[&str] = &["local", "secondary", "auto"];
+
+#[derive(Debug)]
+pub struct OutputRouter {
+    /// The keyberon layers and their default targets.
+    layer_targets: Vec<(usize, String)>,
+    /// The target of `route-output` or `kvm-target`, which overrides the targets of the layers.
+    chosen: Option<String>,
+    /// The target that receives the output.
+    active: String,
+    /// Whether the route must be made again even if the target stays the same, e.g. after the
+    /// targets were reloaded.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    stale: bool,
+    has_secondary: bool,
+}
+
+impl OutputRouter {
+    pub fn from_cfg(
+        items: &HashMap<String, String>,
+        layer_info: &[LayerInfo],
+        kvm: &Kvm,
+    ) -> Result<Self> {
+        let has_secondary = items.contains_key("linux-output-secondary-device-name");
+        let pairs = items
+            .get(OUTPUT_LAYER_TARGETS_CFG_NAME)
+            .map(|s| s.split_whitespace().collect::<Vec<_>>())
+            .unwrap_or_default();
+        if pairs.len() % 2 != 0 {
+            bail!("{OUTPUT_LAYER_TARGETS_CFG_NAME} expects pairs of a layer and a target");
+        }
+        #[cfg(not(target_os = "linux"))]
+        if !pairs.is_empty() {
+            bail!("{OUTPUT_LAYER_TARGETS_CFG_NAME} is only supported on Linux");
+        }
+        let mut layer_targets = vec![];
+        for pair in pairs.chunks(2) {
+            let (layer, target) = (pair[0], pair[1]);
+            if !is_target(target, has_secondary, kvm) {
+                bail!("{OUTPUT_LAYER_TARGETS_CFG_NAME}: unknown target {target}");
+            }
+            let layers = layer_info
+                .iter()
+                .enumerate()
+                .filter(|(_, l)| l.name == layer)
+                .map(|(i, _)| (i, target.to_owned()))
+                .collect::<Vec<_>>();
+            if layers.is_empty() {
+                bail!("{OUTPUT_LAYER_TARGETS_CFG_NAME} contains unknown layer: {layer}");
+            }
+            layer_targets.extend(layers);
+        }
+        Ok(Self {
+            layer_targets,
+            chosen: None,
+            active: "local".into(),
+            stale: false,
+            has_secondary,
+        })
+    }
+
+    /// Read the configuration again on a live reload. The chosen target is kept if it still
+    /// exists.
+    pub fn update_from_cfg(
+        &mut self,
+        items: &HashMap<String, String>,
+        layer_info: &[LayerInfo],
+        kvm: &Kvm,
+    ) -> Result<()> {
+        let new = Self::from_cfg(items, layer_info, kvm)?;
+        self.chosen = self
+            .chosen
+            .take()
+            .filter(|target| is_target(target, new.has_secondary, kvm));
+        self.layer_targets = new.layer_targets;
+        self.has_secondary = new.has_secondary;
+        self.stale = true;
+        Ok(())
+    }
+
+    /// Send the output to the target whichever layer is active, or to the targets of the layers
+    /// again with `None`.
+    pub fn choose(&mut self, target: Option<String>, kvm: &Kvm) -> Result<()> {
+        if let Some(target) = &target {
+            if !is_target(target, self.has_secondary, kvm) {
+                bail!("unknown output target {target}");
+            }
+        }
+        self.chosen = target;
+        Ok(())
+    }
+
+    /// The target for the output while the layer is active.
+    pub fn target(&self, layer: usize) -> &str {
+        if let Some(chosen) = &self.chosen {
+            return chosen;
+        }
+        self.layer_targets
+            .iter()
+            .find(|(l, _)| *l == layer)
+            .map_or("local", |(_, target)| target)
+    }
+
+    /// The target that receives the output.
+    pub fn active(&self) -> &str {
+        &self.active
+    }
+
+    /// Make the route again on the next tick, e.g. because the targets changed.
+    pub fn reroute(&mut self) {
+        self.stale = true;
+    }
+}
+
+fn is_target(target: &str, has_secondary: bool, kvm: &Kvm) -> bool {
+    match target {
+        "local" => true,
+        "secondary" => has_secondary,
+        _ => kvm.has_target(target),
+    }
+}
+
+impl Kanata {
+    /// Send the output to the target of the active layer or the chosen target, if it changed.
+    #[cfg(target_os = "linux")]
+    pub(super) fn route_output(&mut self) {
+        let layer = self.layout.b().current_layer();
+        let router = &mut self.output_router;
+        if router.target(layer) == router.active && !std::mem::take(&mut router.stale) {
+            return;
+        }
+        let target = router.target(layer).to_owned();
+        if target != router.active {
+            self.release_routed_keys();
+        }
+        let route = match target.as_str() {
+            "local" => OutputRoute::Device,
+            "secondary" => OutputRoute::Secondary,
+            name => match self.kvm.sink(name) {
+                Some(sink) => OutputRoute::Remote(sink),
+                None => {
+                    log::warn!("output target {name} is not in {KVM_TARGETS_CFG_NAME}");
+                    OutputRoute::Device
+                }
+            },
+        };
+        self.kbd_out.set_route(route);
+        log::info!("sending the output to {target}");
+        self.output_router.active = target;
+    }
+
+    /// Release the held keys on the current target. The keys still held by the layout are
+    /// pressed again on the next tick, on the new target.
+    #[cfg(target_os = "linux")]
+    pub(super) fn release_routed_keys(&mut self) {
+        for osc in self.kbd_out.modifiers.held().to_vec() {
+            if let Err(e) = self.kbd_out.release_key(osc) {
+                log::warn!("failed to release {osc:?} on the old output target: {e:?}");
+            }
+        }
+        for k in std::mem::take(&mut self.prev_keys) {
+            if let Err(e) = self.kbd_out.release_key(k.into()) {
+                log::warn!("failed to release {k:?} on the old output target: {e:?}");
+            }
+        }
+    }
+}
+
+#[test]
+fn output_targets_follow_the_layers_unless_chosen() {
+    let layer_info = ["base", "base", "gaming", "gaming"]
+        .iter()
+        .map(|name| LayerInfo {
+            name: name.to_string(),
+            cfg_text: String::new(),
+            sounds: None,
+            tags: vec![],
+            notify: vec![],
+            doc: LayerDoc::default(),
+        })
+        .collect::<Vec<_>>();
+    let kvm = Kvm::from_cfg(&HashMap::default()).unwrap();
+    let mut items = HashMap::default();
+    items.insert(
+        OUTPUT_LAYER_TARGETS_CFG_NAME.to_owned(),
+        "gaming secondary".to_owned(),
+    );
+    assert!(OutputRouter::from_cfg(&items, &layer_info, &kvm).is_err());
+    items.insert(
+        "linux-output-secondary-device-name".to_owned(),
+        "kanata 2".to_owned(),
+    );
+    #[cfg(target_os = "linux")]
+    {
+        let mut router = OutputRouter::from_cfg(&items, &layer_info, &kvm).unwrap();
+        assert_eq!(router.target(0), "local");
+        assert_eq!(router.target(3), "secondary");
+        router.choose(Some("local".into()), &kvm).unwrap();
+        assert_eq!(router.target(3), "local");
+        router.choose(None, &kvm).unwrap();
+        assert_eq!(router.target(3), "secondary");
+        assert!(router.choose(Some("auto".into()), &kvm).is_err());
+
+        router.choose(Some("secondary".into()), &kvm).unwrap();
+        items.remove(OUTPUT_LAYER_TARGETS_CFG_NAME);
+        items.remove("linux-output-secondary-device-name");
+        router.update_from_cfg(&items, &layer_info, &kvm).unwrap();
+        assert_eq!(router.target(3), "local");
+        assert!(router.choose(Some("secondary".into()), &kvm).is_err());
+    }
+
+    for bad in ["gaming", "gaming laptop", "typing local"] {
+        items.insert(OUTPUT_LAYER_TARGETS_CFG_NAME.to_owned(), bad.to_owned());
+        assert!(
+            OutputRouter::from_cfg(&items, &layer_info, &kvm).is_err(),
+            "{bad}"
+        );
+    }
+}
@@ -0,0 +1,127 @@
+//! A configuration file that is loaded on top of the active one for trying out layers.
+//!
+//! `LoadOverlay` live reloads the active configuration file with the items of the overlay file
+//! appended, then activates the first layer of the overlay above the default layer, as if a key
+//! held it with `layer-while-held`. Its transparent keys act as the keys of the layers below.
+//! The overlay is kept across live reloads until `DropOverlay` reloads the configuration without
+//! it. If the overlay does not parse, the configuration stays as it was.
+
+use super::*;
+
+/// The coordinate of the layer activated by the overlay. No key is at this coordinate, so only
+/// dropping the overlay or a force unlock releases the layer.
+pub const OVERLAY_LAYER_COORD: (u8, u16) = (u8::MAX, u16::MAX - 2);
+
+#[derive(Debug, Default)]
+pub struct Overlay {
+    /// The overlay that the layout was built with.
+    pub path: Option<PathBuf>,
+    /// The overlay to use on the next live reload, `Some(None)` to drop it.
+    requested: Option<Option<PathBuf>>,
+}
+
+impl Overlay {
+    /// The overlay to build the next layout with.
+    pub fn next_path(&self) -> Option<&PathBuf> {
+        match &self.requested {
+            Some(requested) => requested.as_ref(),
+            None => self.path.as_ref(),
+        }
+    }
+
+    /// The live reload built the layout with [`Self::next_path`].
+    pub fn reloaded(&mut self) {
+        if let Some(requested) = self.requested.take() {
+            self.path = requested;
+        }
+    }
+
+    /// The live reload failed, so the layout is still built with [`Self::path`].
+    pub fn reload_failed(&mut self) {
+        if let Some(Some(path)) = self.requested.take() {
+            log::warn!("overlay {} was not loaded", path.display());
+        }
+    }
+}
+
+impl Kanata {
+    /// Request a live reload with the overlay file.
+    pub(super) fn load_overlay(&mut self, path: PathBuf) {
+        log::info!("Requested live reload with overlay: {}", path.display());
+        self.overlay.requested = Some(Some(path));
+        self.live_reload_requested = true;
+    }
+
+    /// Request a live reload without the overlay file.
+    pub(super) fn drop_overlay(&mut self) {
+        if self.overlay.next_path().is_none() {
+            log::warn!("there is no overlay to drop");
+            return;
+        }
+        log::info!("Requested live reload without the overlay");
+        self.overlay.requested = Some(None);
+        self.live_reload_requested = true;
+    }
+
+    /// Parse the active configuration file, with the overlay if there is one.
+    pub(super) fn parse_cfg_with_overlay(&self) -> miette::Result<cfg::Cfg> {
+        let path = &self.cfg_paths[self.cur_cfg_idx];
+        match self.overlay.next_path() {
+            Some(overlay) => cfg::new_from_file_with_overlay(path, overlay),
+            None => cfg::new_from_file(path),
+        }
+    }
+
+    /// Activate the first layer of the overlay in the new layout.
+    pub(super) fn activate_overlay_layer(&mut self) {
+        let Some(path) = &self.overlay.path else {
+            return;
+        };
+        let Some(name) = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|text| cfg::first_layer_name(&text))
+        else {
+            log::warn!("overlay {} defines no layer", path.display());
+            return;
+        };
+        // The second version of each layer is the one activated by layer-while-held.
+        let Some(layer) = self
+            .layer_info
+            .iter()
+            .enumerate()
+            .position(|(i, l)| i % 2 == 1 && l.name == name)
+        else {
+            return;
+        };
+        log::info!("overlay {} active with layer {name}", path.display());
+        let _ = self.layout.bm().states.push(State::LayerModifier {
+            value: layer,
+            coord: OVERLAY_LAYER_COORD,
+        });
+    }
+}
+
+#[test]
+fn overlay_is_kept_until_dropped() {
+    let mut overlay = Overlay::default();
+    assert_eq!(overlay.next_path(), None);
+
+    overlay.requested = Some(Some("symbols.kbd".into()));
+    assert_eq!(overlay.next_path(), Some(&PathBuf::from("symbols.kbd")));
+    overlay.reload_failed();
+    assert_eq!(overlay.next_path(), None);
+
+    overlay.requested = Some(Some("symbols.kbd".into()));
+    overlay.reloaded();
+    assert_eq!(overlay.path, Some("symbols.kbd".into()));
+    // Live reloads that were not requested for the overlay keep it.
+    overlay.reloaded();
+    assert_eq!(overlay.next_path(), Some(&PathBuf::from("symbols.kbd")));
+
+    overlay.requested = Some(None);
+    overlay.reload_failed();
+    assert_eq!(overlay.path, Some("symbols.kbd".into()));
+    overlay.requested = Some(None);
+    overlay.reloaded();
+    assert_eq!(overlay.next_path(), None);
+}
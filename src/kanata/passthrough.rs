@@ -0,0 +1,44 @@
+//! Emergency passthrough, for when an experimental configuration makes the keyboard unusable.
+//!
+//! The `passthrough` action and the `SetPassthrough` TCP message make kanata output every key as
+//! it is pressed, bypassing the layout. Everything that the layout held is released first. A TCP
+//! client turns passthrough off with `SetPassthrough`; a successful live reload also turns it off,
+//! so that a fixed configuration can be reloaded, e.g. with `ChangeConfig`.
+
+use super::*;
+
+impl Kanata {
+    pub(super) fn set_passthrough(&mut self, enabled: bool) {
+        match (enabled, self.passthrough.take()) {
+            (true, None) => {
+                log::warn!("passthrough: keys are output unchanged until it is turned off");
+                self.force_unlock();
+                self.passthrough = Some(vec![]);
+            }
+            (false, Some(held)) => {
+                log::info!("passthrough turned off");
+                // The keys held now were pressed on the output; their releases go to the layout.
+                for osc in held {
+                    if let Err(e) = self.kbd_out.release_key(osc) {
+                        log::warn!("failed to release {osc:?}: {e:?}");
+                    }
+                }
+            }
+            (_, held) => self.passthrough = held,
+        }
+    }
+
+    /// Output the key event unchanged if passthrough is on. Returns whether it was output.
+    pub(super) fn pass_through(&mut self, event: &KeyEvent) -> Result<bool> {
+        let Some(held) = &mut self.passthrough else {
+            return Ok(false);
+        };
+        match event.value {
+            KeyValue::Press => held.push(event.code),
+            KeyValue::Release => held.retain(|osc| *osc != event.code),
+            KeyValue::Repeat => {}
+        }
+        self.kbd_out.write_key(event.code, event.value)?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,230 @@
+//! Persistence of runtime state across restarts.
+//!
+//! When `persist-state-file` is configured, the default layer, the keys latched by `toggle-key`
+//! and the recorded dynamic macros are written to the file whenever they change and restored when
+//! kanata starts.
+
+use super::*;
+
+use serde::{Deserialize, Serialize};
+
+pub const PERSIST_STATE_FILE_CFG_NAME: &str = "persist-state-file";
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersistedState {
+    /// Name of the default layer, which is changed by `layer-switch`.
+    #[serde(default)]
+    pub default_layer: Option<String>,
+    /// Key codes of the keys latched by `toggle-key`.
+    #[serde(default)]
+    pub latched_keys: Vec<u16>,
+    /// Recorded dynamic macros by macro ID.
+    #[serde(default)]
+    pub dynamic_macros: Vec<(u16, Vec<PersistedMacroItem>)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PersistedMacroItem {
+    Press(u16),
+    Release(u16),
+    EndMacro(u16),
+}
+
+impl From<&DynamicMacroItem> for PersistedMacroItem {
+    fn from(item: &DynamicMacroItem) -> Self {
+        match item {
+            DynamicMacroItem::Press(k) => Self::Press(*k as u16),
+            DynamicMacroItem::Release(k) => Self::Release(*k as u16),
+            DynamicMacroItem::EndMacro(id) => Self::EndMacro(*id),
+        }
+    }
+}
+
+impl PersistedMacroItem {
+    fn to_dynamic_macro_item(self) -> Option<DynamicMacroItem> {
+        Some(match self {
+            Self::Press(k) => DynamicMacroItem::Press(OsCode::from_u16(k)?),
+            Self::Release(k) => DynamicMacroItem::Release(OsCode::from_u16(k)?),
+            Self::EndMacro(id) => DynamicMacroItem::EndMacro(id),
+        })
+    }
+}
+
+pub struct StatePersistence {
+    path: PathBuf,
+    /// The state as last written to or read from the file.
+    saved: PersistedState,
+    /// Whether the dynamic macros changed since the state was saved. These are not compared
+    /// every tick because they can be large.
+    macros_changed: bool,
+}
+
+impl StatePersistence {
+    /// Returns `None` if persistence is not configured.
+    pub fn from_cfg(items: &HashMap<String, String>) -> Option<Self> {
+        items.get(PERSIST_STATE_FILE_CFG_NAME).map(|path| Self {
+            path: PathBuf::from(path),
+            saved: PersistedState::default(),
+            macros_changed: false,
+        })
+    }
+
+    /// Read the state file. A missing file is not an error since it is created on the first
+    /// change; other errors are logged and the state is not restored.
+    pub fn load(&mut self) -> Option<PersistedState> {
+        let text = match std::fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                log::warn!("failed to read state file {}: {e}", self.path.display());
+                return None;
+            }
+        };
+        match serde_json::from_str::<PersistedState>(&text) {
+            Ok(state) => {
+                self.saved = state.clone();
+                Some(state)
+            }
+            Err(e) => {
+                log::warn!("ignoring invalid state file {}: {e}", self.path.display());
+                None
+            }
+        }
+    }
+
+    pub fn mark_macros_changed(&mut self) {
+        self.macros_changed = true;
+    }
+
+    /// Write the state file if the state differs from what was last saved.
+    pub fn save_if_changed(
+        &mut self,
+        default_layer: &str,
+        latched_keys: &[KeyCode],
+        dynamic_macros: &HashMap<u16, Vec<DynamicMacroItem>>,
+    ) {
+        let latched_keys_unchanged = latched_keys.iter().map(|k| OsCode::from(*k) as u16).eq(self
+            .saved
+            .latched_keys
+            .iter()
+            .copied());
+        if !self.macros_changed
+            && latched_keys_unchanged
+            && self.saved.default_layer.as_deref() == Some(default_layer)
+        {
+            return;
+        }
+        self.macros_changed = false;
+        let mut macros = dynamic_macros
+            .iter()
+            .map(|(id, items)| (*id, items.iter().map(PersistedMacroItem::from).collect()))
+            .collect::<Vec<_>>();
+        macros.sort_by_key(|(id, _)| *id);
+        self.saved = PersistedState {
+            default_layer: Some(default_layer.to_owned()),
+            latched_keys: latched_keys
+                .iter()
+                .map(|k| OsCode::from(*k) as u16)
+                .collect(),
+            dynamic_macros: macros,
+        };
+        if let Err(e) = self.write() {
+            log::warn!("failed to write state file {}: {e}", self.path.display());
+        }
+    }
+
+    /// Write to a temporary file first so that the state file is never left half written.
+    fn write(&self) -> std::io::Result<()> {
+        let text =
+            serde_json::to_string(&self.saved).map_err(|e| std::io::Error::other(e.to_string()))?;
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, text)?;
+        std::fs::rename(&tmp, &self.path)
+    }
+}
+
+impl Kanata {
+    /// Restore the state saved in the state file, if persistence is configured.
+    pub fn restore_persisted_state(&mut self) {
+        let state = match self.state_persistence.as_mut().and_then(|p| p.load()) {
+            Some(state) => state,
+            None => return,
+        };
+        if let Some(layer) = state.default_layer {
+            if self.layer_info.iter().any(|l| l.name == layer) {
+                log::info!("restoring default layer {layer}");
+                self.change_layer(layer);
+            } else {
+                log::warn!("not restoring default layer {layer} which no longer exists");
+            }
+        }
+        self.latched_keys = state
+            .latched_keys
+            .into_iter()
+            .filter_map(OsCode::from_u16)
+            .map(KeyCode::from)
+            .collect();
+        self.dynamic_macros = state
+            .dynamic_macros
+            .into_iter()
+            .map(|(id, items)| {
+                let items = items
+                    .into_iter()
+                    .filter_map(PersistedMacroItem::to_dynamic_macro_item)
+                    .collect();
+                (id, items)
+            })
+            .collect();
+    }
+
+    /// Save the state if it changed, if persistence is configured.
+    pub fn save_persisted_state(&mut self) {
+        if let Some(persistence) = &mut self.state_persistence {
+            let default_layer = &self.layer_info[self.layout.b().default_layer].name;
+            persistence.save_if_changed(default_layer, &self.latched_keys, &self.dynamic_macros);
+        }
+    }
+}
+
+#[test]
+fn state_persistence_round_trip() {
+    let path = std::env::temp_dir().join(format!("kanata-state-{}.json", std::process::id()));
+    let mut items = HashMap::default();
+    items.insert(
+        PERSIST_STATE_FILE_CFG_NAME.to_owned(),
+        path.to_string_lossy().to_string(),
+    );
+    let mut macros = HashMap::default();
+    macros.insert(
+        3,
+        vec![
+            DynamicMacroItem::Press(OsCode::KEY_A),
+            DynamicMacroItem::Release(OsCode::KEY_A),
+        ],
+    );
+
+    let mut persistence = StatePersistence::from_cfg(&items).unwrap();
+    assert_eq!(persistence.load(), None);
+    persistence.save_if_changed("qwerty", &[KeyCode::LShift], &macros);
+
+    let mut persistence = StatePersistence::from_cfg(&items).unwrap();
+    let state = persistence.load().unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(state.default_layer.as_deref(), Some("qwerty"));
+    assert_eq!(state.latched_keys, [OsCode::KEY_LEFTSHIFT as u16]);
+    assert_eq!(
+        state.dynamic_macros,
+        [(
+            3,
+            vec![
+                PersistedMacroItem::Press(OsCode::KEY_A as u16),
+                PersistedMacroItem::Release(OsCode::KEY_A as u16)
+            ]
+        )]
+    );
+
+    // Unchanged state is not written again.
+    persistence.save_if_changed("qwerty", &[KeyCode::LShift], &macros);
+    assert!(!path.exists());
+}
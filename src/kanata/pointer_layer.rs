@@ -0,0 +1,178 @@
+//! A layer that is active while a pointing device is in use.
+//!
+//! When a device named in `linux-pointer-layer-devices`, e.g. a trackpoint or a trackball, sends
+//! events, the `linux-pointer-layer` layer is activated as if a key held it with
+//! `layer-while-held`. It is deactivated once the devices were idle for
+//! `linux-pointer-layer-linger` milliseconds, so keys next to the pointer can act as mouse
+//! buttons or scroll keys while the pointer is used. Kanata only sees the events of devices that
+//! it grabs.
+
+use super::*;
+
+pub const POINTER_LAYER_DEVICES_CFG_NAME: &str = "linux-pointer-layer-devices";
+pub const POINTER_LAYER_CFG_NAME: &str = "linux-pointer-layer";
+pub const POINTER_LAYER_LINGER_CFG_NAME: &str = "linux-pointer-layer-linger";
+const DEFAULT_POINTER_LAYER_LINGER: u16 = 500;
+
+/// The coordinate of the layer activated by the pointing devices. No key is at this coordinate,
+/// so only idleness or a force unlock releases the layer.
+pub const POINTER_LAYER_COORD: (u8, u16) = (u8::MAX, u16::MAX - 1);
+
+#[derive(Debug)]
+pub struct PointerLayer {
+    /// The names of the devices whose events activate the layer.
+    pub devices: Vec<String>,
+    /// The keyberon layer index, `None` if the pointer layer is not configured.
+    layer: Option<usize>,
+    linger: u16,
+    ticks_until_idle: Option<u16>,
+}
+
+impl Default for PointerLayer {
+    fn default() -> Self {
+        Self {
+            devices: vec![],
+            layer: None,
+            linger: DEFAULT_POINTER_LAYER_LINGER,
+            ticks_until_idle: None,
+        }
+    }
+}
+
+impl PointerLayer {
+    pub fn from_cfg(items: &HashMap<String, String>, layer_info: &[LayerInfo]) -> Result<Self> {
+        let devices = items
+            .get(POINTER_LAYER_DEVICES_CFG_NAME)
+            .map(|names| parse_dev_paths(names))
+            .unwrap_or_default();
+        let layer = match items.get(POINTER_LAYER_CFG_NAME) {
+            // The second version of each layer is the one activated by layer-while-held.
+            Some(name) => Some(
+                layer_info
+                    .iter()
+                    .enumerate()
+                    .position(|(i, l)| i % 2 == 1 && l.name == *name)
+                    .ok_or_else(|| {
+                        anyhow!("{POINTER_LAYER_CFG_NAME} is an unknown layer: {name}")
+                    })?,
+            ),
+            None => None,
+        };
+        if devices.is_empty() != layer.is_none() {
+            bail!(
+                "{POINTER_LAYER_DEVICES_CFG_NAME} and {POINTER_LAYER_CFG_NAME} must be used together"
+            );
+        }
+        let linger = match items.get(POINTER_LAYER_LINGER_CFG_NAME) {
+            Some(s) => match s.parse::<u16>() {
+                Ok(linger @ 1..) => linger,
+                _ => bail!("{POINTER_LAYER_LINGER_CFG_NAME} must be 1-65535, found {s}"),
+            },
+            None => DEFAULT_POINTER_LAYER_LINGER,
+        };
+        Ok(Self {
+            devices,
+            layer,
+            linger,
+            ticks_until_idle: None,
+        })
+    }
+
+    /// A pointing device was used. Returns the layer to keep active for the linger time.
+    pub fn activity(&mut self) -> Option<usize> {
+        let layer = self.layer?;
+        self.ticks_until_idle = Some(self.linger);
+        Some(layer)
+    }
+
+    /// Returns whether the devices just became idle.
+    pub fn tick(&mut self) -> bool {
+        match &mut self.ticks_until_idle {
+            Some(1) => {
+                self.ticks_until_idle = None;
+                true
+            }
+            Some(ticks) => {
+                *ticks -= 1;
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// The ticks until the layer is deactivated, if it is active.
+    pub fn ticks_until_due(&self) -> Option<u16> {
+        self.ticks_until_idle
+    }
+}
+
+impl Kanata {
+    /// Activate the pointer layer, or keep it active for longer if it already is.
+    pub(super) fn pointer_activity(&mut self) {
+        let Some(layer) = self.pointer_layer.activity() else {
+            return;
+        };
+        let layout = self.layout.bm();
+        let active = layout.states.iter().any(
+            |s| matches!(s, State::LayerModifier { coord, .. } if *coord == POINTER_LAYER_COORD),
+        );
+        if !active {
+            log::debug!("pointer in use, activating {}", self.layer_info[layer].name);
+            let _ = layout.states.push(State::LayerModifier {
+                value: layer,
+                coord: POINTER_LAYER_COORD,
+            });
+        }
+    }
+
+    pub(super) fn tick_pointer_layer(&mut self) {
+        if !self.pointer_layer.tick() {
+            return;
+        }
+        log::debug!("pointer idle, deactivating the pointer layer");
+        self.layout.bm().states.retain(
+            |s| !matches!(s, State::LayerModifier { coord, .. } if *coord == POINTER_LAYER_COORD),
+        );
+    }
+}
+
+#[test]
+fn pointer_layer_lingers_after_activity() {
+    let layer_info = ["base", "base", "mouse", "mouse"]
+        .iter()
+        .map(|name| LayerInfo {
+            name: name.to_string(),
+            cfg_text: String::new(),
+            sounds: None,
+            tags: vec![],
+            notify: vec![],
+            doc: LayerDoc::default(),
+        })
+        .collect::<Vec<_>>();
+    let mut items = HashMap::default();
+    let mut pointer = PointerLayer::from_cfg(&items, &layer_info).unwrap();
+    assert_eq!(pointer.activity(), None);
+    assert_eq!(pointer.ticks_until_due(), None);
+
+    items.insert(
+        POINTER_LAYER_DEVICES_CFG_NAME.into(),
+        "TPPS/2 IBM TrackPoint:Logitech Trackball".into(),
+    );
+    items.insert(POINTER_LAYER_CFG_NAME.into(), "mouse".into());
+    items.insert(POINTER_LAYER_LINGER_CFG_NAME.into(), "3".into());
+    let mut pointer = PointerLayer::from_cfg(&items, &layer_info).unwrap();
+    assert_eq!(pointer.devices, ["TPPS/2 IBM TrackPoint", "Logitech Trackball"]);
+    assert_eq!(pointer.activity(), Some(3));
+    assert!(!pointer.tick());
+    assert_eq!(pointer.activity(), Some(3));
+    assert_eq!(
+        [pointer.tick(), pointer.tick(), pointer.tick()],
+        [false, false, true]
+    );
+    assert_eq!(pointer.ticks_until_due(), None);
+
+    items.insert(POINTER_LAYER_CFG_NAME.into(), "nav".into());
+    assert!(PointerLayer::from_cfg(&items, &layer_info).is_err());
+    items.remove(POINTER_LAYER_CFG_NAME);
+    assert!(PointerLayer::from_cfg(&items, &layer_info).is_err());
+}
@@ -0,0 +1,327 @@
+//! Layers that are active while the pointer is in a region of the screen, e.g. on a monitor.
+//!
+//! `pointer-regions` names rectangles of the screen in the geometry format of `xrandr`, e.g.
+//! `pointer-regions "tv=1920x1080+3840+0"` for a monitor at x 3840, and `pointer-region-layers`
+//! lists pairs of a region and a layer, e.g. `"tv media"`. While the pointer is in the region, the
+//! layer is active as if a key held it with `layer-while-held`. If regions overlap, the first one
+//! that contains the pointer counts.
+//!
+//! Kanata reads the pointer position every `pointer-region-poll` milliseconds where it can: on
+//! Windows, and on Linux in an X11 session if it is compiled with the `xtest` feature. Elsewhere,
+//! e.g. on Wayland where only the compositor knows the pointer position, a TCP client can report
+//! it with `PointerMoved`.
+
+use super::*;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub const POINTER_REGIONS_CFG_NAME: &str = "pointer-regions";
+pub const POINTER_REGION_LAYERS_CFG_NAME: &str = "pointer-region-layers";
+pub const POINTER_REGION_POLL_CFG_NAME: &str = "pointer-region-poll";
+const DEFAULT_POLL_MS: u16 = 100;
+
+/// The coordinate of the layer of the region with the pointer. No key is at this coordinate, so
+/// only the pointer or a force unlock releases the layer.
+pub const POINTER_REGION_LAYER_COORD: (u8, u16) = (u8::MAX, u16::MAX - 6);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Region {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+impl Region {
+    /// Parse the `xrandr` geometry `<width>x<height>+<x>+<y>`, where the offsets may be negative.
+    fn parse(s: &str) -> Option<Self> {
+        let x_at = s.find(['+', '-'])?;
+        let y_at = x_at + 1 + s[x_at + 1..].find(['+', '-'])?;
+        let (width, height) = s[..x_at].split_once('x')?;
+        let region = Self {
+            x: s[x_at..y_at].parse().ok()?,
+            y: s[y_at..].parse().ok()?,
+            width: width.parse().ok()?,
+            height: height.parse().ok()?,
+        };
+        (region.width > 0 && region.height > 0).then_some(region)
+    }
+
+    fn contains(&self, (x, y): (i32, i32)) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
+#[derive(Debug)]
+pub struct PointerRegions {
+    /// The regions and the keyberon layers active while the pointer is in them.
+    regions: Vec<(String, Region, usize)>,
+    poll: time::Duration,
+    /// The last reported pointer position.
+    position: Option<(i32, i32)>,
+    started: bool,
+    stop: Arc<AtomicBool>,
+}
+
+impl Drop for PointerRegions {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+impl PointerRegions {
+    pub fn from_cfg(items: &HashMap<String, String>, layer_info: &[LayerInfo]) -> Result<Self> {
+        let mut named = vec![];
+        for item in items
+            .get(POINTER_REGIONS_CFG_NAME)
+            .map(String::as_str)
+            .unwrap_or_default()
+            .split_whitespace()
+        {
+            let Some((name, region)) = item
+                .split_once('=')
+                .and_then(|(name, geometry)| Some((name, Region::parse(geometry)?)))
+            else {
+                bail!(
+                    "{POINTER_REGIONS_CFG_NAME} expects items like tv=1920x1080+3840+0, \
+                     found {item}"
+                );
+            };
+            named.push((name, region));
+        }
+        let pairs = items
+            .get(POINTER_REGION_LAYERS_CFG_NAME)
+            .map(|s| s.split_whitespace().collect::<Vec<_>>())
+            .unwrap_or_default();
+        if pairs.len() % 2 != 0 {
+            bail!("{POINTER_REGION_LAYERS_CFG_NAME} expects pairs of a region and a layer");
+        }
+        let mut regions = vec![];
+        for pair in pairs.chunks(2) {
+            let Some((name, region)) = named.iter().find(|(name, _)| *name == pair[0]) else {
+                bail!(
+                    "{POINTER_REGION_LAYERS_CFG_NAME}: {} is not in {POINTER_REGIONS_CFG_NAME}",
+                    pair[0]
+                );
+            };
+            // The second version of each layer is the one activated by layer-while-held.
+            let layer = layer_info
+                .iter()
+                .enumerate()
+                .position(|(i, l)| i % 2 == 1 && l.name == pair[1])
+                .ok_or_else(|| {
+                    anyhow!(
+                        "{POINTER_REGION_LAYERS_CFG_NAME} contains unknown layer: {}",
+                        pair[1]
+                    )
+                })?;
+            regions.push((name.to_string(), *region, layer));
+        }
+        let poll = match items.get(POINTER_REGION_POLL_CFG_NAME) {
+            Some(s) => match s.parse::<u16>() {
+                Ok(ms @ 1..) => ms,
+                _ => bail!("{POINTER_REGION_POLL_CFG_NAME} must be 1-65535, found {s}"),
+            },
+            None => DEFAULT_POLL_MS,
+        };
+        Ok(Self {
+            regions,
+            poll: time::Duration::from_millis(poll.into()),
+            position: None,
+            started: false,
+            stop: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Whether the new configuration reads the pointer in the same way, so that this can be kept
+    /// on a live reload along with its thread.
+    pub fn same_as(&self, other: &Self) -> bool {
+        self.regions.is_empty() == other.regions.is_empty() && self.poll == other.poll
+    }
+
+    /// Take the regions of the new configuration, keeping the thread and the pointer position.
+    pub fn update(&mut self, mut other: Self) {
+        self.regions = std::mem::take(&mut other.regions);
+    }
+
+    /// Start reading the pointer position if a region has a layer and kanata can read it.
+    pub fn start(&mut self, tx: &Sender<ProcessingEvent>) {
+        if self.regions.is_empty() || std::mem::replace(&mut self.started, true) {
+            return;
+        }
+        let (tx, stop, poll) = (tx.clone(), self.stop.clone(), self.poll);
+        std::thread::spawn(move || {
+            if let Err(e) = poll_pointer(poll, &tx, &stop) {
+                log::info!("{e}; TCP clients can report the pointer with PointerMoved");
+            }
+        });
+    }
+
+    /// The layer for the pointer position.
+    fn layer_at(&self, position: (i32, i32)) -> Option<usize> {
+        self.regions
+            .iter()
+            .find(|(_, region, _)| region.contains(position))
+            .map(|(_, _, layer)| *layer)
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "xtest"))]
+fn poll_pointer(
+    poll: time::Duration,
+    tx: &Sender<ProcessingEvent>,
+    stop: &AtomicBool,
+) -> Result<()> {
+    let pointer = X11Pointer::connect()?;
+    send_pointer_positions(|| pointer.position(), poll, tx, stop);
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+fn poll_pointer(
+    poll: time::Duration,
+    tx: &Sender<ProcessingEvent>,
+    stop: &AtomicBool,
+) -> Result<()> {
+    send_pointer_positions(pointer_position, poll, tx, stop);
+    Ok(())
+}
+
+#[cfg(not(any(all(target_os = "linux", feature = "xtest"), target_os = "windows")))]
+fn poll_pointer(_: time::Duration, _: &Sender<ProcessingEvent>, _: &AtomicBool) -> Result<()> {
+    bail!("kanata cannot read the pointer position in this build")
+}
+
+/// Send the pointer position to the processing loop whenever it changed.
+#[cfg(any(all(target_os = "linux", feature = "xtest"), target_os = "windows"))]
+fn send_pointer_positions(
+    position: impl Fn() -> Option<(i32, i32)>,
+    poll: time::Duration,
+    tx: &Sender<ProcessingEvent>,
+    stop: &AtomicBool,
+) {
+    let mut last = None;
+    while !stop.load(Ordering::Relaxed) {
+        let current = position();
+        if current.is_some() && current != last {
+            last = current;
+            let (x, y) = current.expect("checked above");
+            let command = KanataCommand::PointerMoved { x, y };
+            if tx.send(ProcessingEvent::Command(command)).is_err() {
+                return;
+            }
+        }
+        std::thread::sleep(poll);
+    }
+}
+
+impl Kanata {
+    /// Activate the layer of the region with the pointer, deactivating the previous one.
+    pub(super) fn pointer_moved(&mut self, position: (i32, i32)) {
+        self.pointer_regions.position = Some(position);
+        let layer = self.pointer_regions.layer_at(position);
+        let layout = self.layout.bm();
+        let active = layout.states.iter().find_map(|s| match s {
+            State::LayerModifier { value, coord } if *coord == POINTER_REGION_LAYER_COORD => {
+                Some(*value)
+            }
+            _ => None,
+        });
+        if layer == active {
+            return;
+        }
+        layout.states.retain(
+            |s| !matches!(s, State::LayerModifier { coord, .. } if *coord == POINTER_REGION_LAYER_COORD),
+        );
+        if let Some(layer) = layer {
+            log::debug!(
+                "pointer at {position:?}, activating {}",
+                self.layer_info[layer].name
+            );
+            let _ = layout.states.push(State::LayerModifier {
+                value: layer,
+                coord: POINTER_REGION_LAYER_COORD,
+            });
+        }
+    }
+
+    /// Replace the regions on a live reload.
+    pub(super) fn replace_pointer_regions(&mut self, regions: PointerRegions) {
+        if regions.same_as(&self.pointer_regions) {
+            self.pointer_regions.update(regions);
+        } else {
+            let position = self.pointer_regions.position;
+            self.pointer_regions = regions;
+            self.pointer_regions.position = position;
+            if let Some(tx) = &self.processing_tx {
+                self.pointer_regions.start(tx);
+            }
+        }
+    }
+
+    /// Activate the layer of the region with the pointer in a new layout.
+    pub(super) fn restore_pointer_region_layer(&mut self) {
+        if let Some(position) = self.pointer_regions.position {
+            self.pointer_moved(position);
+        }
+    }
+}
+
+#[test]
+fn pointer_regions_map_positions_to_layers() {
+    assert_eq!(
+        Region::parse("1920x1080+3840+0"),
+        Some(Region {
+            x: 3840,
+            y: 0,
+            width: 1920,
+            height: 1080
+        })
+    );
+    assert_eq!(
+        Region::parse("1280x1024-1280-56"),
+        Some(Region {
+            x: -1280,
+            y: -56,
+            width: 1280,
+            height: 1024
+        })
+    );
+    for bad in ["1920x1080", "1920+0+0", "0x1080+0+0", "axb+0+0"] {
+        assert_eq!(Region::parse(bad), None, "{bad}");
+    }
+
+    let layer_info = ["base", "base", "media", "media"]
+        .iter()
+        .map(|name| LayerInfo {
+            name: name.to_string(),
+            cfg_text: String::new(),
+            sounds: None,
+            tags: vec![],
+            notify: vec![],
+            doc: LayerDoc::default(),
+        })
+        .collect::<Vec<_>>();
+    let mut items = HashMap::default();
+    items.insert(
+        POINTER_REGIONS_CFG_NAME.to_owned(),
+        "laptop=1920x1080+0+0 tv=1920x1080+1920+0".to_owned(),
+    );
+    items.insert(
+        POINTER_REGION_LAYERS_CFG_NAME.to_owned(),
+        "tv media".to_owned(),
+    );
+    let regions = PointerRegions::from_cfg(&items, &layer_info).unwrap();
+    assert_eq!(regions.layer_at((100, 100)), None);
+    assert_eq!(regions.layer_at((1920, 0)), Some(3));
+    assert_eq!(regions.layer_at((3839, 1079)), Some(3));
+    assert_eq!(regions.layer_at((3840, 0)), None);
+
+    for bad in ["tv", "desk media", "tv nav"] {
+        items.insert(POINTER_REGION_LAYERS_CFG_NAME.to_owned(), bad.to_owned());
+        assert!(
+            PointerRegions::from_cfg(&items, &layer_info).is_err(),
+            "{bad}"
+        );
+    }
+}
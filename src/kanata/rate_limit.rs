@@ -0,0 +1,115 @@
+//! A limit on the rate of output key events, for remote desktop sessions and runaway macros.
+//!
+//! This is a token bucket: `output-rate-limit` events per second refill it, up to
+//! `output-rate-burst` events. Every output key event takes an event from the bucket. Key presses
+//! and releases of the user are always output, but while the bucket is empty, macros and dynamic
+//! macro replays are paused and key repeats are dropped.
+
+use super::*;
+
+pub const OUTPUT_RATE_LIMIT_CFG_NAME: &str = "output-rate-limit";
+pub const OUTPUT_RATE_BURST_CFG_NAME: &str = "output-rate-burst";
+const DEFAULT_BURST: u32 = 10;
+
+#[derive(Debug, Default)]
+pub struct OutputRateLimit {
+    /// Events per second, or 0 if there is no limit.
+    rate: u32,
+    burst: u32,
+    /// The events in the bucket, in thousandths of an event.
+    milli_events: u64,
+}
+
+impl OutputRateLimit {
+    /// Read the limit from defcfg. The bucket starts out full.
+    pub fn update_from_cfg(&mut self, items: &HashMap<String, String>) -> Result<()> {
+        let number = |name: &str| -> Result<Option<u32>> {
+            items
+                .get(name)
+                .map(|s| match s.parse::<u32>() {
+                    Ok(n) if n > 0 => Ok(n),
+                    _ => Err(anyhow!("{name} must be a positive number, found {s}")),
+                })
+                .transpose()
+        };
+        self.rate = number(OUTPUT_RATE_LIMIT_CFG_NAME)?.unwrap_or(0);
+        self.burst = number(OUTPUT_RATE_BURST_CFG_NAME)?.unwrap_or(DEFAULT_BURST);
+        self.milli_events = u64::from(self.burst) * 1000;
+        Ok(())
+    }
+
+    /// Refill the bucket for a millisecond.
+    pub fn tick(&mut self) {
+        if self.rate > 0 {
+            self.milli_events =
+                (self.milli_events + u64::from(self.rate)).min(u64::from(self.burst) * 1000);
+        }
+    }
+
+    /// The ticks until the bucket is full again. Refills are caught up after sleeping, but not
+    /// after blocking on input, so the bucket would stay empty until the next input otherwise.
+    pub fn ticks_until_due(&self) -> Option<u16> {
+        let missing = (u64::from(self.burst) * 1000).saturating_sub(self.milli_events);
+        if self.rate == 0 || missing == 0 {
+            return None;
+        }
+        let ticks = missing.div_ceil(u64::from(self.rate));
+        Some(ticks.clamp(1, u16::MAX.into()) as u16)
+    }
+
+    /// Whether an event that can be delayed or dropped may be output now.
+    pub fn allows(&self) -> bool {
+        self.rate == 0 || self.milli_events >= 1000
+    }
+
+    /// Take an output event from the bucket.
+    pub fn record(&mut self) {
+        self.milli_events = self.milli_events.saturating_sub(1000);
+    }
+}
+
+#[test]
+fn output_rate_limit_refills_at_the_rate() {
+    let mut limit = OutputRateLimit::default();
+    limit.update_from_cfg(&HashMap::default()).unwrap();
+    for _ in 0..100 {
+        limit.record();
+    }
+    assert!(limit.allows(), "no limit by default");
+    assert_eq!(limit.ticks_until_due(), None);
+
+    let items = [
+        (OUTPUT_RATE_LIMIT_CFG_NAME.to_owned(), "100".to_owned()),
+        (OUTPUT_RATE_BURST_CFG_NAME.to_owned(), "2".to_owned()),
+    ]
+    .into_iter()
+    .collect();
+    limit.update_from_cfg(&items).unwrap();
+    assert_eq!(limit.ticks_until_due(), None, "the bucket is full");
+    limit.record();
+    assert!(limit.allows());
+    limit.record();
+    assert!(!limit.allows());
+    // The processing loop must not block on input until the bucket is full again.
+    assert_eq!(limit.ticks_until_due(), Some(20));
+    // 100 events per second is an event every 10ms.
+    for _ in 0..9 {
+        limit.tick();
+    }
+    assert!(!limit.allows());
+    limit.tick();
+    assert!(limit.allows());
+    assert_eq!(limit.ticks_until_due(), Some(10));
+    // The bucket holds at most the burst.
+    for _ in 0..1000 {
+        limit.tick();
+    }
+    limit.record();
+    limit.record();
+    assert!(!limit.allows());
+
+    let items = [(OUTPUT_RATE_LIMIT_CFG_NAME.to_owned(), "0".to_owned())]
+        .into_iter()
+        .collect();
+    assert!(limit.update_from_cfg(&items).is_err());
+}
@@ -0,0 +1,201 @@
+//! What a live reload changes, and the runtime state that it keeps.
+//!
+//! A live reload replaces the whole keyberon layout, since the actions of a configuration are
+//! allocated together. The layers and defcfg items of the old and new configuration are compared
+//! so that the log says what changed. The default layer and the layers locked by `layer-lock` are
+//! carried over to the new layout by name. Keys latched by `toggle-key` are kept by kanata outside
+//! the layout and stay latched.
+//!
+//! If the new configuration does not load, kanata keeps running with the old one and TCP clients
+//! are sent the error, also when they connect later, until a live reload succeeds.
+
+use super::*;
+
+/// The differences between two configurations that are summarized after a live reload.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CfgDiff {
+    pub added_layers: Vec<String>,
+    pub removed_layers: Vec<String>,
+    pub changed_layers: Vec<String>,
+    /// The defcfg items that were added, removed or changed.
+    pub changed_items: Vec<String>,
+}
+
+impl CfgDiff {
+    pub fn new(
+        old_layers: &[LayerInfo],
+        old_items: &HashMap<String, String>,
+        new_layers: &[LayerInfo],
+        new_items: &HashMap<String, String>,
+    ) -> Self {
+        // Each layer is duplicated in the keyberon layout.
+        let old_layers = old_layers.iter().step_by(2).collect::<Vec<_>>();
+        let new_layers = new_layers.iter().step_by(2).collect::<Vec<_>>();
+        let mut diff = Self::default();
+        for new in new_layers.iter() {
+            match old_layers.iter().find(|old| old.name == new.name) {
+                None => diff.added_layers.push(new.name.clone()),
+                Some(old) if old.cfg_text != new.cfg_text => {
+                    diff.changed_layers.push(new.name.clone())
+                }
+                Some(_) => {}
+            }
+        }
+        diff.removed_layers = old_layers
+            .iter()
+            .filter(|old| !new_layers.iter().any(|new| new.name == old.name))
+            .map(|old| old.name.clone())
+            .collect();
+        diff.changed_items = old_items
+            .keys()
+            .chain(new_items.keys())
+            .filter(|name| old_items.get(*name) != new_items.get(*name))
+            .cloned()
+            .collect();
+        diff.changed_items.sort();
+        diff.changed_items.dedup();
+        diff
+    }
+
+    pub fn summary(&self) -> String {
+        let parts = [
+            ("layers added", &self.added_layers),
+            ("layers removed", &self.removed_layers),
+            ("layers changed", &self.changed_layers),
+            ("defcfg items changed", &self.changed_items),
+        ]
+        .into_iter()
+        .filter(|(_, names)| !names.is_empty())
+        .map(|(what, names)| format!("{what}: {}", names.join(" ")))
+        .collect::<Vec<_>>();
+        match parts.is_empty() {
+            true => "no layers or defcfg items changed".to_owned(),
+            false => parts.join("; "),
+        }
+    }
+}
+
+/// The layers that are active without a held key, by name.
+pub struct KeptLayers {
+    default_layer: String,
+    locked: Vec<String>,
+}
+
+impl Kanata {
+    /// Live reload the active configuration file and notify the TCP clients of the result.
+    pub(super) fn live_reload(&mut self, tx: &Option<Sender<ServerMessage>>) {
+        let message = match self.do_live_reload() {
+            Ok(()) => {
+                self.loaded_cfg_idx = self.cur_cfg_idx;
+                self.reload_error = None;
+                self.set_passthrough(false);
+                self.config_files_message()
+            }
+            Err(e) => {
+                log::error!("live reload failed, keeping the previous configuration: {e}");
+                self.overlay.reload_failed();
+                let path = self.cfg_paths[self.cur_cfg_idx].display().to_string();
+                self.reload_error = Some((path, e.to_string()));
+                self.cur_cfg_idx = self.loaded_cfg_idx;
+                match self.reload_error_message() {
+                    Some(message) => message,
+                    None => return,
+                }
+            }
+        };
+        if let Some(tx) = tx {
+            if let Err(e) = tx.send(message) {
+                log::error!("could not send event notification: {e}");
+            }
+        }
+    }
+
+    /// The error of the last live reload if it failed, for TCP clients.
+    pub fn reload_error_message(&self) -> Option<ServerMessage> {
+        let (path, error) = self.reload_error.clone()?;
+        Some(ServerMessage::LiveReloadFailed { path, error })
+    }
+
+    pub(super) fn kept_layers(&self) -> KeptLayers {
+        let layout = self.layout.b();
+        KeptLayers {
+            default_layer: self.layer_info[layout.default_layer].name.clone(),
+            locked: layout
+                .states
+                .iter()
+                .filter_map(|state| match state {
+                    State::LayerModifier { value, coord } if *coord == LAYER_LOCK_COORD => {
+                        Some(self.layer_info[*value].name.clone())
+                    }
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Activate the kept layers in the new layout if they still exist.
+    pub(super) fn restore_kept_layers(&mut self, kept: KeptLayers) {
+        if kept.default_layer != self.layer_info[self.layout.b().default_layer].name
+            && !self.change_layer(kept.default_layer.clone())
+        {
+            log::info!(
+                "cannot keep the default layer {}, using the first layer",
+                kept.default_layer
+            );
+        }
+        for name in kept.locked {
+            // The second version of each layer is the one activated by layer-while-held.
+            let Some(layer) = self
+                .layer_info
+                .iter()
+                .enumerate()
+                .position(|(i, l)| i % 2 == 1 && l.name == name)
+            else {
+                log::info!("locked layer {name} no longer exists, releasing it");
+                continue;
+            };
+            let layout = self.layout.bm();
+            let _ = layout.states.push(State::LayerModifier {
+                value: layer,
+                coord: LAYER_LOCK_COORD,
+            });
+        }
+    }
+}
+
+#[test]
+fn cfg_diff_summarizes_layers_and_items() {
+    let layers = |layers: &[(&str, &str)]| {
+        layers
+            .iter()
+            .flat_map(|(name, text)| [(name, text), (name, text)])
+            .map(|(name, text)| LayerInfo {
+                name: name.to_string(),
+                cfg_text: text.to_string(),
+                sounds: None,
+                tags: vec![],
+                notify: vec![],
+                doc: LayerDoc::default(),
+            })
+            .collect::<Vec<_>>()
+    };
+    let items = |items: &[(&str, &str)]| {
+        items
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>()
+    };
+    let old_layers = layers(&[("base", "a b"), ("nav", "left _"), ("num", "1 2")]);
+    let old_items = items(&[("sequence-timeout", "1000"), ("log-layer-changes", "yes")]);
+    let diff = CfgDiff::new(&old_layers, &old_items, &old_layers, &old_items);
+    assert_eq!(diff.summary(), "no layers or defcfg items changed");
+
+    let new_layers = layers(&[("base", "a b"), ("nav", "left down"), ("sym", "! @")]);
+    let new_items = items(&[("sequence-timeout", "500"), ("game-mode-layers", "sym")]);
+    let diff = CfgDiff::new(&old_layers, &old_items, &new_layers, &new_items);
+    assert_eq!(
+        diff.summary(),
+        "layers added: sym; layers removed: num; layers changed: nav; \
+         defcfg items changed: game-mode-layers log-layer-changes sequence-timeout"
+    );
+}
@@ -0,0 +1,135 @@
+//! Matching of keys by their hardware scancode.
+//!
+//! Some keyboards report several of their extra keys with the same generic key code, so kanata
+//! cannot tell them apart by key code alone. The kernel reports the scancode of a key in an
+//! `MSC_SCAN` event right before the key event, within the same report. When
+//! `linux-scancode-keys` is configured, key events whose scancode is listed are changed to the key
+//! that the scancode is paired with before they are looked up in defsrc.
+
+use super::*;
+
+use evdev::{EventType, InputEvent, MiscType};
+
+pub const SCANCODE_KEYS_CFG_NAME: &str = "linux-scancode-keys";
+
+pub struct ScancodeMap {
+    /// Scancodes and the keys they are changed to.
+    keys: Vec<(u32, OsCode)>,
+    /// The scancode reported in the current report, if any.
+    scancode: Option<u32>,
+    /// Key codes that were changed on press and the keys they were changed to. Repeats do not
+    /// come with a scancode and releases may not either, so these follow the press.
+    held: Vec<(u16, OsCode)>,
+}
+
+impl ScancodeMap {
+    /// Read the scancode keys from defcfg. Returns `None` if they are not configured.
+    pub fn from_cfg(items: &HashMap<String, String>) -> Result<Option<Self>> {
+        let cfg = match items.get(SCANCODE_KEYS_CFG_NAME) {
+            Some(cfg) => cfg,
+            None => return Ok(None),
+        };
+        let mut keys = vec![];
+        for pair in cfg.split_whitespace() {
+            let (scancode, key) = pair.split_once(':').ok_or_else(|| {
+                anyhow!("{SCANCODE_KEYS_CFG_NAME} expects <scancode>:<key> pairs, found: {pair}")
+            })?;
+            let scancode = match scancode.strip_prefix("0x") {
+                Some(hex) => u32::from_str_radix(hex, 16),
+                None => scancode.parse(),
+            }
+            .map_err(|_| anyhow!("{SCANCODE_KEYS_CFG_NAME}: invalid scancode {scancode}"))?;
+            let key = str_to_oscode(key)
+                .ok_or_else(|| anyhow!("{SCANCODE_KEYS_CFG_NAME}: unknown key {key}"))?;
+            if keys.iter().any(|(s, _)| *s == scancode) {
+                bail!("{SCANCODE_KEYS_CFG_NAME}: scancode {scancode:#x} is listed twice");
+            }
+            keys.push((scancode, key));
+        }
+        Ok(Some(Self {
+            keys,
+            scancode: None,
+            held: vec![],
+        }))
+    }
+
+    /// Change the key code of a key event to the key paired with its scancode. Other events are
+    /// returned unchanged and are used to track the scancode of the current report.
+    pub fn remap(&mut self, event: InputEvent) -> InputEvent {
+        match event.event_type() {
+            EventType::MISC if event.code() == MiscType::MSC_SCAN.0 => {
+                self.scancode = Some(event.value() as u32);
+                event
+            }
+            EventType::SYNCHRONIZATION => {
+                self.scancode = None;
+                event
+            }
+            EventType::KEY => {
+                let held = self.held.iter().position(|(code, _)| *code == event.code());
+                let key = match (event.value(), held) {
+                    (_, Some(i)) => {
+                        let key = self.held[i].1;
+                        if event.value() == 0 {
+                            self.held.remove(i);
+                        }
+                        key
+                    }
+                    (1, None) => {
+                        let scancode = self.scancode.take();
+                        match self.keys.iter().find(|(s, _)| Some(*s) == scancode) {
+                            Some((_, key)) => {
+                                self.held.push((event.code(), *key));
+                                *key
+                            }
+                            None => return event,
+                        }
+                    }
+                    _ => return event,
+                };
+                InputEvent::new(EventType::KEY, key as u16, event.value())
+            }
+            _ => event,
+        }
+    }
+}
+
+#[test]
+fn scancode_keys_change_key_events() {
+    let mut items = HashMap::default();
+    items.insert(
+        SCANCODE_KEYS_CFG_NAME.into(),
+        "0xc0221:f21 786979:f22".into(),
+    );
+    let mut map = ScancodeMap::from_cfg(&items).unwrap().unwrap();
+    let scan = |s| InputEvent::new(EventType::MISC, MiscType::MSC_SCAN.0, s);
+    let key = |value| InputEvent::new(EventType::KEY, OsCode::KEY_SEARCH as u16, value);
+    let syn = InputEvent::new(EventType::SYNCHRONIZATION, 0, 0);
+    let code = |ev: InputEvent| ev.code();
+
+    // The listed scancodes change the key, including the repeats and the release.
+    map.remap(scan(0xc0221));
+    assert_eq!(code(map.remap(key(1))), OsCode::KEY_F21 as u16);
+    map.remap(syn);
+    assert_eq!(code(map.remap(key(2))), OsCode::KEY_F21 as u16);
+    assert_eq!(code(map.remap(key(0))), OsCode::KEY_F21 as u16);
+    map.remap(syn);
+    map.remap(scan(0xc0223));
+    assert_eq!(code(map.remap(key(1))), OsCode::KEY_F22 as u16);
+    map.remap(syn);
+    assert_eq!(code(map.remap(key(0))), OsCode::KEY_F22 as u16);
+
+    // Other scancodes and keys without a scancode are not changed.
+    map.remap(scan(0xc0224));
+    assert_eq!(code(map.remap(key(1))), OsCode::KEY_SEARCH as u16);
+    map.remap(syn);
+    assert_eq!(code(map.remap(key(0))), OsCode::KEY_SEARCH as u16);
+    assert_eq!(code(map.remap(key(1))), OsCode::KEY_SEARCH as u16);
+
+    items.insert(SCANCODE_KEYS_CFG_NAME.into(), "0xc0221".into());
+    assert!(ScancodeMap::from_cfg(&items).is_err());
+    items.insert(SCANCODE_KEYS_CFG_NAME.into(), "0xzz:f21".into());
+    assert!(ScancodeMap::from_cfg(&items).is_err());
+    items.insert(SCANCODE_KEYS_CFG_NAME.into(), "1:f21 1:f22".into());
+    assert!(ScancodeMap::from_cfg(&items).is_err());
+}
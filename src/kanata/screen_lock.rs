@@ -0,0 +1,122 @@
+//! Pausing the layers while the screen is locked.
+//!
+//! With `linux-pause-layers-on-lock`, only the first layer is active while the screen of the
+//! logind session is locked, so that the password can always be typed with the plain keymap.
+//! The default layer and the layers activated without a held key, e.g. by `layer-lock`, are
+//! restored by name when the screen is unlocked. Layers of held keys are not restored since the
+//! keys are released while the screen is locked.
+
+use super::*;
+
+pub const PAUSE_LAYERS_ON_LOCK_CFG_NAME: &str = "linux-pause-layers-on-lock";
+
+#[derive(Debug, Default)]
+pub struct ScreenLockPause {
+    enabled: bool,
+    /// The session to watch, from `linux-session-id`, or the one kanata runs in.
+    session_id: Option<String>,
+    /// The watch is started once and runs until kanata exits.
+    watching: bool,
+    paused: Option<PausedLayers>,
+}
+
+/// The layers that were active when the screen was locked, by name.
+#[derive(Debug)]
+struct PausedLayers {
+    default_layer: String,
+    layers: Vec<(String, (u8, u16))>,
+}
+
+impl ScreenLockPause {
+    pub fn update_from_cfg(&mut self, items: &HashMap<String, String>) {
+        self.enabled = items
+            .get(PAUSE_LAYERS_ON_LOCK_CFG_NAME)
+            .is_some_and(|s| matches!(s.to_lowercase().as_str(), "yes" | "true"));
+        self.session_id = items.get("linux-session-id").cloned();
+    }
+
+    /// Start watching the screen lock if pausing is enabled and it is not watched yet. Changes
+    /// are sent to the processing loop through `tx`.
+    pub fn start_watching(&mut self, tx: &Sender<ProcessingEvent>) {
+        if !self.enabled || self.watching {
+            return;
+        }
+        let tx = tx.clone();
+        let result = watch_screen_lock(self.session_id.as_deref(), move |locked| {
+            let _ = tx.send(ProcessingEvent::Command(KanataCommand::ScreenLocked {
+                locked,
+            }));
+        });
+        match result {
+            Ok(()) => self.watching = true,
+            Err(e) => log::warn!("{PAUSE_LAYERS_ON_LOCK_CFG_NAME} has no effect: {e}"),
+        }
+    }
+}
+
+impl Kanata {
+    /// Pause the layers when the screen is locked and restore them when it is unlocked.
+    pub(super) fn screen_lock_changed(&mut self, locked: bool) {
+        if !locked {
+            let Some(paused) = self.screen_lock.paused.take() else {
+                return;
+            };
+            log::info!("screen unlocked, restoring the layers");
+            if !self.change_layer(paused.default_layer.clone()) {
+                log::info!("default layer {} no longer exists", paused.default_layer);
+            }
+            for (name, coord) in paused.layers {
+                // The second version of each layer is the one activated by layer-while-held.
+                let Some(layer) = self
+                    .layer_info
+                    .iter()
+                    .enumerate()
+                    .position(|(i, l)| i % 2 == 1 && l.name == name)
+                else {
+                    continue;
+                };
+                let _ = self.layout.bm().states.push(State::LayerModifier {
+                    value: layer,
+                    coord,
+                });
+            }
+            return;
+        }
+        if !self.screen_lock.enabled || self.screen_lock.paused.is_some() {
+            return;
+        }
+        let layout = self.layout.b();
+        let paused = PausedLayers {
+            default_layer: self.layer_info[layout.default_layer].name.clone(),
+            layers: layout
+                .states
+                .iter()
+                .filter_map(|state| match state {
+                    State::LayerModifier { value, coord } if coord.0 == u8::MAX => {
+                        Some((self.layer_info[*value].name.clone(), *coord))
+                    }
+                    _ => None,
+                })
+                .collect(),
+        };
+        log::info!(
+            "screen locked, pausing the layers above {}",
+            self.layer_info[0].name
+        );
+        self.screen_lock.paused = Some(paused);
+        self.clear_paused_layers();
+    }
+
+    /// Leave only the first layer active while the layers are paused, e.g. again after a live
+    /// reload restored the kept layers.
+    pub(super) fn clear_paused_layers(&mut self) {
+        if self.screen_lock.paused.is_none() {
+            return;
+        }
+        let layout = self.layout.bm();
+        layout
+            .states
+            .retain(|state| !matches!(state, State::LayerModifier { .. }));
+        layout.set_default_layer(0);
+    }
+}
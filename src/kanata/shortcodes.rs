@@ -0,0 +1,41 @@
+//! Emoji input by short codes such as `:shrug:`.
+//!
+//! When the output history ends with a short code of `defshortcodes` between colons, the code is
+//! erased with backspaces and its text is typed instead.
+
+use rustc_hash::FxHashMap as HashMap;
+
+#[derive(Debug, Default)]
+pub struct Shortcodes {
+    table: HashMap<String, String>,
+}
+
+impl Shortcodes {
+    pub fn new(table: HashMap<String, String>) -> Self {
+        Self { table }
+    }
+
+    /// If the output history ends with a short code, returns the number of characters of the code
+    /// including the colons and its text.
+    pub fn closed_code(&self, history: &str) -> Option<(usize, &str)> {
+        let inner = history.strip_suffix(':')?;
+        let code = &inner[inner.rfind(':')? + 1..];
+        self.table
+            .get(code)
+            .map(|text| (code.chars().count() + 2, text.as_str()))
+    }
+}
+
+#[test]
+fn shortcodes_are_found_when_closed() {
+    let mut table = HashMap::default();
+    table.insert("shrug".to_string(), "🤷".to_string());
+    table.insert("+1".to_string(), "👍".to_string());
+    let codes = Shortcodes::new(table);
+    assert_eq!(codes.closed_code("hi :shrug:"), Some((7, "🤷")));
+    // A colon that does not close a code starts the next one.
+    assert_eq!(codes.closed_code(":a:+1:"), Some((4, "👍")));
+    assert_eq!(codes.closed_code(":shrug"), None);
+    assert_eq!(codes.closed_code("shrug:"), None);
+    assert_eq!(codes.closed_code(":sh rug:"), None);
+}
@@ -0,0 +1,82 @@
+//! Text expansion of the triggers of `defsnippets`.
+//!
+//! When the output history ends with a trigger, the trigger is erased with backspaces and the
+//! expansion is typed instead. Expansions can contain the date and time, and the position where
+//! the cursor is left, which is reached by pressing left after typing.
+
+use crate::cfg::{Snippet, SnippetPart};
+
+/// Returns the snippet with the longest trigger that ends the output history.
+pub fn find_snippet<'a>(snippets: &'a [Snippet], history: &str) -> Option<&'a Snippet> {
+    snippets
+        .iter()
+        .filter(|s| history.ends_with(&s.trigger))
+        .max_by_key(|s| s.trigger.len())
+}
+
+/// Returns the text of the expansion and how many characters the cursor should move left after
+/// typing it.
+pub fn expand(expansion: &[SnippetPart], now: time::OffsetDateTime) -> (String, usize) {
+    let mut text = String::new();
+    let mut cursor = None;
+    for part in expansion {
+        match part {
+            SnippetPart::Text(t) => text.push_str(t),
+            SnippetPart::Cursor => cursor = Some(text.chars().count()),
+            SnippetPart::Date => text.push_str(&format!(
+                "{}-{:02}-{:02}",
+                now.year(),
+                u8::from(now.month()),
+                now.day()
+            )),
+            SnippetPart::Time => text.push_str(&format!("{:02}:{:02}", now.hour(), now.minute())),
+        }
+    }
+    let lefts = cursor.map_or(0, |c| text.chars().count() - c);
+    (text, lefts)
+}
+
+/// The local time, or UTC if the local offset cannot be determined.
+pub fn now() -> time::OffsetDateTime {
+    time::OffsetDateTime::now_local().unwrap_or_else(|_| time::OffsetDateTime::now_utc())
+}
+
+#[test]
+fn snippets_are_found_and_expanded() {
+    let snippet = |trigger: &str, expansion| Snippet {
+        trigger: trigger.into(),
+        expansion,
+    };
+    let snippets = [
+        snippet(";d", vec![SnippetPart::Date]),
+        snippet("d;d", vec![SnippetPart::Text("x".into())]),
+        snippet(
+            ";fn",
+            vec![
+                SnippetPart::Text("fn ".into()),
+                SnippetPart::Cursor,
+                SnippetPart::Text("() {}".into()),
+            ],
+        ),
+    ];
+    assert_eq!(find_snippet(&snippets, "add;d"), Some(&snippets[1]));
+    assert_eq!(find_snippet(&snippets, "a;d"), Some(&snippets[0]));
+    assert_eq!(find_snippet(&snippets, ";f"), None);
+
+    let now = time::OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+    assert_eq!(
+        expand(
+            &[
+                SnippetPart::Date,
+                SnippetPart::Text(" ".into()),
+                SnippetPart::Time
+            ],
+            now
+        ),
+        ("2023-11-14 22:13".to_string(), 0)
+    );
+    assert_eq!(
+        expand(&snippets[2].expansion, now),
+        ("fn () {}".to_string(), 5)
+    );
+}
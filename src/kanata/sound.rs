@@ -0,0 +1,83 @@
+//! Audio feedback for key events.
+//!
+//! Sounds are configured per layer with `defsounds` and looked up in the profile of the layer that
+//! is active when the event happens. Playback never blocks the processing loop: on Linux a player
+//! process (`aplay`) is spawned, and on Windows the sound is played asynchronously with
+//! `PlaySoundW`. Without the `sound` feature, nothing is played.
+
+use crate::cfg::{LayerInfo, SoundProfile};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundEvent {
+    Press,
+    Release,
+    LayerChange,
+    Tap,
+    Hold,
+}
+
+impl SoundEvent {
+    fn file(self, profile: &SoundProfile) -> Option<&str> {
+        match self {
+            SoundEvent::Press => profile.press.as_deref(),
+            SoundEvent::Release => profile.release.as_deref(),
+            SoundEvent::LayerChange => profile.layer_change.as_deref(),
+            SoundEvent::Tap => profile.tap.as_deref(),
+            SoundEvent::Hold => profile.hold.as_deref(),
+        }
+    }
+}
+
+/// Play the sound configured for the event in the profile of the given layer, if there is one.
+pub fn play_sound(layer_info: &[LayerInfo], layer: usize, event: SoundEvent) {
+    if let Some(file) = layer_info
+        .get(layer)
+        .and_then(|l| l.sounds.as_ref())
+        .and_then(|profile| event.file(profile))
+    {
+        log::trace!("playing {event:?} sound {file}");
+        play_file(file);
+    }
+}
+
+#[cfg(all(feature = "sound", target_os = "linux"))]
+fn play_file(file: &str) {
+    let file = file.to_owned();
+    // Wait for the player in a separate thread so that it does not linger as a zombie process.
+    std::thread::spawn(move || {
+        match std::process::Command::new("aplay")
+            .arg("-q")
+            .arg(&file)
+            .status()
+        {
+            Ok(status) if !status.success() => log::warn!("aplay failed to play {file}: {status}"),
+            Ok(_) => {}
+            Err(e) => log::error!("could not run aplay to play {file}: {e}"),
+        }
+    });
+}
+
+#[cfg(all(feature = "sound", target_os = "windows"))]
+fn play_file(file: &str) {
+    use std::os::windows::ffi::OsStrExt;
+    use winapi::um::playsoundapi::{PlaySoundW, SND_ASYNC, SND_FILENAME, SND_NODEFAULT};
+
+    let file: Vec<u16> = std::ffi::OsStr::new(file)
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect();
+    // SND_ASYNC returns immediately; a sound that is still playing is stopped.
+    let ok = unsafe {
+        PlaySoundW(
+            file.as_ptr(),
+            std::ptr::null_mut(),
+            SND_ASYNC | SND_FILENAME | SND_NODEFAULT,
+        )
+    };
+    if ok == 0 {
+        log::warn!("failed to play sound");
+    }
+}
+
+#[cfg(not(feature = "sound"))]
+fn play_file(_file: &str) {}
@@ -0,0 +1,65 @@
+//! Layers that are active from the start.
+//!
+//! `startup-layers` lists layers that are activated above the default layer when kanata starts, as
+//! if keys held them with `layer-while-held`, e.g. a layer of tweaks that should always be on. The
+//! last one listed is the current layer. They are activated again with the layers of the new
+//! configuration on every live reload. No key is at their coordinate, so only a force unlock
+//! releases them until the next live reload.
+
+use super::*;
+
+pub const STARTUP_LAYERS_CFG_NAME: &str = "startup-layers";
+
+/// The coordinate of the startup layers.
+pub const STARTUP_LAYER_COORD: (u8, u16) = (u8::MAX, u16::MAX - 5);
+
+/// The keyberon layers of `startup-layers`, in the order they are activated.
+pub fn startup_layers(
+    items: &HashMap<String, String>,
+    layer_info: &[LayerInfo],
+) -> Result<Vec<usize>> {
+    let Some(names) = items.get(STARTUP_LAYERS_CFG_NAME) else {
+        return Ok(vec![]);
+    };
+    names
+        .split_whitespace()
+        .map(|name| {
+            // The second version of each layer is the one activated by layer-while-held.
+            layer_info
+                .iter()
+                .enumerate()
+                .position(|(i, l)| i % 2 == 1 && l.name == name)
+                .ok_or_else(|| anyhow!("{STARTUP_LAYERS_CFG_NAME}: unknown layer {name}"))
+        })
+        .collect()
+}
+
+pub fn activate_startup_layers(layout: &mut BorrowedKLayout, layers: &[usize]) {
+    for layer in layers.iter().copied() {
+        let _ = layout.states.push(State::LayerModifier {
+            value: layer,
+            coord: STARTUP_LAYER_COORD,
+        });
+    }
+}
+
+#[test]
+fn startup_layers_are_found_by_name() {
+    let layer_info = ["base", "base", "tweaks", "tweaks", "nav", "nav"]
+        .into_iter()
+        .map(|name| LayerInfo {
+            name: name.into(),
+            cfg_text: String::new(),
+            sounds: None,
+            tags: vec![],
+            notify: vec![],
+            doc: LayerDoc::default(),
+        })
+        .collect::<Vec<_>>();
+    let mut items = HashMap::default();
+    assert!(startup_layers(&items, &layer_info).unwrap().is_empty());
+    items.insert(STARTUP_LAYERS_CFG_NAME.to_owned(), "tweaks nav".to_owned());
+    assert_eq!(startup_layers(&items, &layer_info).unwrap(), [3, 5]);
+    items.insert(STARTUP_LAYERS_CFG_NAME.to_owned(), "tweaks gaming".to_owned());
+    assert!(startup_layers(&items, &layer_info).is_err());
+}
@@ -0,0 +1,98 @@
+//! Swapping of key positions while a `swap-hands` key is held.
+//!
+//! Unlike a layer, the swap is applied to the physical key events before they reach the layout, so
+//! it composes with every layer: pressing `f` while swapping behaves exactly like pressing `j` on
+//! whichever layer is active.
+
+use crate::keys::{KeyEvent, KeyValue, OsCode};
+
+#[derive(Debug, Default)]
+pub struct SwapHandsState {
+    /// The active key pairs, set while a swap-hands key is held.
+    active: Option<&'static [(OsCode, OsCode)]>,
+    /// Keys pressed while swapping was active along with the key they were swapped to, so that
+    /// their repeats and releases are swapped even if swapping ends while they are held.
+    swapped_keys: Vec<(OsCode, OsCode)>,
+}
+
+impl SwapHandsState {
+    pub fn activate(&mut self, pairs: &'static [(OsCode, OsCode)]) {
+        self.active = Some(pairs);
+    }
+
+    pub fn deactivate(&mut self) {
+        self.active = None;
+    }
+
+    /// Returns the event with its key swapped, if applicable.
+    pub fn transform(&mut self, event: &KeyEvent) -> KeyEvent {
+        let swapped = match event.value {
+            KeyValue::Press => {
+                let swapped = self.active.and_then(|pairs| {
+                    pairs
+                        .iter()
+                        .find(|(from, _)| *from == event.code)
+                        .map(|(_, to)| *to)
+                });
+                if let Some(to) = swapped {
+                    self.swapped_keys.push((event.code, to));
+                }
+                swapped
+            }
+            KeyValue::Repeat => self
+                .swapped_keys
+                .iter()
+                .find(|(from, _)| *from == event.code)
+                .map(|(_, to)| *to),
+            KeyValue::Release => {
+                let pos = self
+                    .swapped_keys
+                    .iter()
+                    .position(|(from, _)| *from == event.code);
+                pos.map(|pos| self.swapped_keys.remove(pos).1)
+            }
+        };
+        KeyEvent {
+            code: swapped.unwrap_or(event.code),
+            value: event.value,
+        }
+    }
+}
+
+#[test]
+fn swap_hands_releases_follow_presses() {
+    let mut state = SwapHandsState::default();
+    let ev = |code, value| KeyEvent { code, value };
+    let pairs: &'static [_] = &[
+        (OsCode::KEY_F, OsCode::KEY_J),
+        (OsCode::KEY_J, OsCode::KEY_F),
+    ];
+
+    assert_eq!(
+        state.transform(&ev(OsCode::KEY_F, KeyValue::Press)).code,
+        OsCode::KEY_F
+    );
+    state.activate(pairs);
+    assert_eq!(
+        state.transform(&ev(OsCode::KEY_J, KeyValue::Press)).code,
+        OsCode::KEY_F
+    );
+    // Pressed before swapping started, so it is not swapped.
+    assert_eq!(
+        state.transform(&ev(OsCode::KEY_F, KeyValue::Release)).code,
+        OsCode::KEY_F
+    );
+    state.deactivate();
+    assert_eq!(
+        state.transform(&ev(OsCode::KEY_J, KeyValue::Repeat)).code,
+        OsCode::KEY_F
+    );
+    assert_eq!(
+        state.transform(&ev(OsCode::KEY_J, KeyValue::Release)).code,
+        OsCode::KEY_F
+    );
+    assert_eq!(
+        state.transform(&ev(OsCode::KEY_J, KeyValue::Press)).code,
+        OsCode::KEY_J
+    );
+}
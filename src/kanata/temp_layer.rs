@@ -0,0 +1,151 @@
+//! A layer that is active for a fixed time.
+//!
+//! `(temp-layer <layer> <seconds>)` and the `ActivateTempLayer` TCP message activate a layer as
+//! if a key held it with `layer-while-held`, and release it once the time is over, e.g. a
+//! presentation layer for the length of a talk. `ExtendTempLayer` adds time and
+//! `CancelTempLayer` releases the layer early. There is one temporary layer at a time; activating
+//! another one replaces it. It stays active across live reloads if the layer still exists.
+
+use super::*;
+
+/// The coordinate of the temporary layer. No key is at this coordinate, so only the end of its
+/// time, `CancelTempLayer` or a force unlock releases the layer.
+pub const TEMP_LAYER_COORD: (u8, u16) = (u8::MAX, u16::MAX - 4);
+
+#[derive(Debug)]
+pub struct TempLayer {
+    /// The layer by name, since a live reload can change its index.
+    name: String,
+    until: time::Instant,
+}
+
+impl TempLayer {
+    /// The ticks until the layer is released.
+    pub fn ticks_until_due(&self, now: time::Instant) -> u16 {
+        let ms = self.until.saturating_duration_since(now).as_millis();
+        ms.clamp(1, u16::MAX.into()) as u16
+    }
+}
+
+/// Activate the keyberon layer until `until`, replacing the temporary layer if there is one.
+pub fn start_temp_layer(
+    layout: &mut BorrowedKLayout,
+    temp_layer: &mut Option<TempLayer>,
+    layer: usize,
+    name: String,
+    until: time::Instant,
+) {
+    release_temp_layer(layout);
+    let _ = layout.states.push(State::LayerModifier {
+        value: layer,
+        coord: TEMP_LAYER_COORD,
+    });
+    *temp_layer = Some(TempLayer { name, until });
+}
+
+fn release_temp_layer(layout: &mut BorrowedKLayout) {
+    layout.states.retain(
+        |s| !matches!(s, State::LayerModifier { coord, .. } if *coord == TEMP_LAYER_COORD),
+    );
+}
+
+impl Kanata {
+    /// Activate the layer by name for the duration. Returns false if it does not exist.
+    pub(super) fn activate_temp_layer(&mut self, name: &str, duration: time::Duration) -> bool {
+        // The second version of each layer is the one activated by layer-while-held.
+        let Some(layer) = self
+            .layer_info
+            .iter()
+            .enumerate()
+            .position(|(i, l)| i % 2 == 1 && l.name == name)
+        else {
+            log::warn!("cannot activate temporary layer {name}: it does not exist");
+            return false;
+        };
+        log::info!("activating {name} for {}s", duration.as_secs());
+        let until = self.clock.now() + duration;
+        start_temp_layer(
+            self.layout.bm(),
+            &mut self.temp_layer,
+            layer,
+            name.to_owned(),
+            until,
+        );
+        true
+    }
+
+    pub(super) fn extend_temp_layer(&mut self, by: time::Duration) {
+        match &mut self.temp_layer {
+            Some(temp_layer) => {
+                log::info!(
+                    "temporary layer {} extended by {}s",
+                    temp_layer.name,
+                    by.as_secs()
+                );
+                temp_layer.until += by;
+            }
+            None => log::warn!("there is no temporary layer to extend"),
+        }
+    }
+
+    pub(super) fn cancel_temp_layer(&mut self) {
+        match self.temp_layer.take() {
+            Some(temp_layer) => log::info!("temporary layer {} cancelled", temp_layer.name),
+            None => log::warn!("there is no temporary layer to cancel"),
+        }
+        release_temp_layer(self.layout.bm());
+    }
+
+    /// Release the temporary layer once its time is over. Forget it if something else, e.g. a
+    /// force unlock, released it.
+    pub(super) fn tick_temp_layer(&mut self) {
+        let Some(temp_layer) = &self.temp_layer else {
+            return;
+        };
+        let active = self.layout.b().states.iter().any(
+            |s| matches!(s, State::LayerModifier { coord, .. } if *coord == TEMP_LAYER_COORD),
+        );
+        if !active {
+            self.temp_layer = None;
+        } else if self.clock.now() >= temp_layer.until {
+            log::info!("temporary layer {} is over", temp_layer.name);
+            self.temp_layer = None;
+            release_temp_layer(self.layout.bm());
+        }
+    }
+
+    /// Activate the temporary layer in the new layout after a live reload.
+    pub(super) fn restore_temp_layer(&mut self) {
+        let Some(TempLayer { name, until }) = self.temp_layer.take() else {
+            return;
+        };
+        let Some(layer) = self
+            .layer_info
+            .iter()
+            .enumerate()
+            .position(|(i, l)| i % 2 == 1 && l.name == name)
+        else {
+            log::info!("temporary layer {name} no longer exists, releasing it");
+            return;
+        };
+        start_temp_layer(self.layout.bm(), &mut self.temp_layer, layer, name, until);
+    }
+}
+
+#[test]
+fn temp_layer_is_due_when_its_time_is_over() {
+    let now = time::Instant::now();
+    let temp_layer = TempLayer {
+        name: "presentation".into(),
+        until: now + time::Duration::from_secs(45 * 60),
+    };
+    assert_eq!(temp_layer.ticks_until_due(now), u16::MAX);
+    assert_eq!(
+        temp_layer.ticks_until_due(now + time::Duration::from_secs(45 * 60 - 2)),
+        2000
+    );
+    assert_eq!(
+        temp_layer.ticks_until_due(now + time::Duration::from_secs(45 * 60 + 1)),
+        1
+    );
+}
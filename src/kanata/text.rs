@@ -0,0 +1,186 @@
+//! Text typed by kanata and the characters of the keys it outputs.
+//!
+//! Both directions use a US layout: characters on it are typed as key presses, since that is what
+//! most places understand best, and other characters use the unicode output.
+
+use super::*;
+
+/// A key to type for a character.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextKey {
+    Key { osc: OsCode, shift: bool },
+    Unicode(char),
+}
+
+pub fn text_keys(text: &str) -> Vec<TextKey> {
+    text.chars()
+        .map(|c| match char_key(c) {
+            Some((osc, shift)) => TextKey::Key { osc, shift },
+            None => TextKey::Unicode(c),
+        })
+        .collect()
+}
+
+/// Type the text, pressing shift where the US layout needs it.
+pub fn type_text(kbd_out: &mut KbdOut, text: &str) -> Result<()> {
+    for key in text_keys(text) {
+        match key {
+            TextKey::Key { osc, shift } => {
+                if shift {
+                    kbd_out.press_key(OsCode::KEY_LEFTSHIFT)?;
+                }
+                kbd_out.press_key(osc)?;
+                kbd_out.release_key(osc)?;
+                if shift {
+                    kbd_out.release_key(OsCode::KEY_LEFTSHIFT)?;
+                }
+            }
+            TextKey::Unicode(c) => kbd_out.send_unicode(c)?,
+        }
+    }
+    Ok(())
+}
+
+/// Erase characters before the cursor with backspaces and type the text instead, then move the
+/// cursor left. Held shift keys are released meanwhile so that they do not change the text.
+pub fn replace_text(
+    kbd_out: &mut KbdOut,
+    held_shifts: &[OsCode],
+    erase: usize,
+    text: &str,
+    lefts: usize,
+) -> Result<()> {
+    for &osc in held_shifts {
+        kbd_out.release_key(osc)?;
+    }
+    for _ in 0..erase {
+        kbd_out.press_key(OsCode::KEY_BACKSPACE)?;
+        kbd_out.release_key(OsCode::KEY_BACKSPACE)?;
+    }
+    type_text(kbd_out, text)?;
+    for _ in 0..lefts {
+        kbd_out.press_key(OsCode::KEY_LEFT)?;
+        kbd_out.release_key(OsCode::KEY_LEFT)?;
+    }
+    for &osc in held_shifts {
+        kbd_out.press_key(osc)?;
+    }
+    Ok(())
+}
+
+/// Characters typed with shift and the character of the same key without it.
+const SHIFTED: &[(char, char)] = &[
+    ('~', '`'),
+    ('!', '1'),
+    ('@', '2'),
+    ('#', '3'),
+    ('$', '4'),
+    ('%', '5'),
+    ('^', '6'),
+    ('&', '7'),
+    ('*', '8'),
+    ('(', '9'),
+    (')', '0'),
+    ('_', '-'),
+    ('+', '='),
+    ('{', '['),
+    ('}', ']'),
+    ('|', '\\'),
+    (':', ';'),
+    ('"', '\''),
+    ('<', ','),
+    ('>', '.'),
+    ('?', '/'),
+];
+
+/// Keys whose character is not their name.
+const PUNCTUATION: &[(char, OsCode)] = &[
+    (' ', OsCode::KEY_SPACE),
+    ('\n', OsCode::KEY_ENTER),
+    ('\t', OsCode::KEY_TAB),
+    ('`', OsCode::KEY_GRAVE),
+    ('-', OsCode::KEY_MINUS),
+    ('=', OsCode::KEY_EQUAL),
+    ('[', OsCode::KEY_LEFTBRACE),
+    (']', OsCode::KEY_RIGHTBRACE),
+    ('\\', OsCode::KEY_BACKSLASH),
+    (';', OsCode::KEY_SEMICOLON),
+    ('\'', OsCode::KEY_APOSTROPHE),
+    (',', OsCode::KEY_COMMA),
+    ('.', OsCode::KEY_DOT),
+    ('/', OsCode::KEY_SLASH),
+];
+
+/// The key and whether shift is needed to type the character on a US layout.
+pub fn char_key(c: char) -> Option<(OsCode, bool)> {
+    if c.is_ascii_uppercase() {
+        return char_key(c.to_ascii_lowercase()).map(|(osc, _)| (osc, true));
+    }
+    if let Some((_, unshifted)) = SHIFTED.iter().find(|(shifted, _)| *shifted == c) {
+        return char_key(*unshifted).map(|(osc, _)| (osc, true));
+    }
+    if let Some((_, osc)) = PUNCTUATION.iter().find(|(p, _)| *p == c) {
+        return Some((*osc, false));
+    }
+    match c {
+        'a'..='z' | '0'..='9' => str_to_oscode(&c.to_string()).map(|osc| (osc, false)),
+        _ => None,
+    }
+}
+
+/// The character that the key types on a US layout.
+pub fn key_char(key: KeyCode, shifted: bool) -> Option<char> {
+    let osc = OsCode::from(key);
+    let c = match PUNCTUATION.iter().find(|(_, o)| *o == osc) {
+        Some((c, _)) => *c,
+        None => {
+            let name = format!("{key:?}");
+            match name.as_bytes() {
+                [c] if c.is_ascii_uppercase() => c.to_ascii_lowercase() as char,
+                [b'K', b'b', c] if c.is_ascii_digit() => *c as char,
+                _ => return None,
+            }
+        }
+    };
+    if !shifted {
+        return Some(c);
+    }
+    if c.is_ascii_lowercase() {
+        return Some(c.to_ascii_uppercase());
+    }
+    Some(
+        SHIFTED
+            .iter()
+            .find(|(_, unshifted)| *unshifted == c)
+            .map_or(c, |(shifted, _)| *shifted),
+    )
+}
+
+#[test]
+fn text_is_typed_with_a_us_layout() {
+    assert_eq!(
+        text_keys("aZ?\né"),
+        [
+            TextKey::Key {
+                osc: OsCode::KEY_A,
+                shift: false
+            },
+            TextKey::Key {
+                osc: OsCode::KEY_Z,
+                shift: true
+            },
+            TextKey::Key {
+                osc: OsCode::KEY_SLASH,
+                shift: true
+            },
+            TextKey::Key {
+                osc: OsCode::KEY_ENTER,
+                shift: false
+            },
+            TextKey::Unicode('é'),
+        ]
+    );
+    assert_eq!(key_char(KeyCode::Kb1, true), Some('!'));
+    assert_eq!(key_char(KeyCode::SColon, false), Some(';'));
+    assert_eq!(key_char(KeyCode::Left, false), None);
+}
@@ -0,0 +1,79 @@
+//! State changes of a TCP client that are applied together or not at all.
+//!
+//! After `BeginTransaction`, the `ChangeLayer`, `SetVar`, `SetGameMode`, `SetLayerTag` and
+//! `SetLayerTags` messages of a client are collected instead of applied. `CommitTransaction`
+//! checks them against the active configuration, e.g. that the layers and tags exist, and applies
+//! all of them between two ticks, or none if one of them cannot be applied; the client is told
+//! which with `TransactionResult`. `RollbackTransaction` drops the collected changes. `Undo`
+//! reverts a committed transaction as a whole.
+
+use super::*;
+
+impl Kanata {
+    /// Check that every command of a transaction can be applied to the active configuration.
+    pub fn check_transaction(&self, commands: &[KanataCommand]) -> Result<()> {
+        for command in commands {
+            match command {
+                KanataCommand::ChangeLayer { name } => {
+                    if !self.layer_info.iter().any(|l| l.name == *name) {
+                        bail!("layer {name} does not exist");
+                    }
+                }
+                KanataCommand::SetLayerTags { changes } => {
+                    for (tag, _) in changes {
+                        if !self.layer_info.iter().any(|l| l.tags.contains(tag)) {
+                            bail!("no layer has the tag {tag}");
+                        }
+                    }
+                }
+                KanataCommand::SetVar { .. } | KanataCommand::SetGameMode { .. } => {}
+                command => bail!("{command:?} cannot be part of a transaction"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the commands in order. If one of them fails, e.g. a layer change to a layer that an
+    /// earlier command of the transaction turned off, the ones before it are undone.
+    pub(super) fn apply_transaction(&mut self, commands: Vec<KanataCommand>) {
+        if let Err(e) = self.check_transaction(&commands) {
+            log::warn!("transaction not applied: {e}");
+            return;
+        }
+        let mut applied = vec![];
+        for command in commands {
+            let undo = match command {
+                KanataCommand::ChangeLayer { name } => {
+                    let prev = self.layer_info[self.layout.b().default_layer].name.clone();
+                    self.change_layer(name)
+                        .then_some(UndoEntry::DefaultLayer(prev))
+                }
+                KanataCommand::SetVar { name, value } => {
+                    let prev = self.runtime_vars.insert(name.clone(), value);
+                    Some(UndoEntry::Var { name, value: prev })
+                }
+                KanataCommand::SetGameMode { enabled } => {
+                    let prev = UndoEntry::GameMode(self.game_mode.enabled);
+                    self.set_game_mode(enabled);
+                    Some(prev)
+                }
+                KanataCommand::SetLayerTags { changes } => {
+                    self.set_layer_tags(&changes).map(UndoEntry::LayerTags)
+                }
+                _ => unreachable!("checked by check_transaction"),
+            };
+            match undo {
+                Some(undo) => applied.push(undo),
+                None => {
+                    log::warn!("transaction failed, undoing its {} changes", applied.len());
+                    for undo in applied.into_iter().rev() {
+                        self.undo(undo);
+                    }
+                    return;
+                }
+            }
+        }
+        log::info!("applied a transaction of {} changes", applied.len());
+        self.undo_history.push(UndoEntry::Transaction(applied));
+    }
+}
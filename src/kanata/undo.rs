@@ -0,0 +1,57 @@
+//! History of the state changes made by TCP clients, so that they can be undone.
+//!
+//! Only the state that a command changed is recorded, e.g. the previous default layer for
+//! `ChangeLayer`. Layers are recorded by name since a live reload can change their indexes.
+
+use std::collections::VecDeque;
+
+/// The number of changes that can be undone.
+const UNDO_HISTORY_LEN: usize = 16;
+
+/// The state from before a command changed it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UndoEntry {
+    DefaultLayer(String),
+    Var {
+        name: String,
+        value: Option<String>,
+    },
+    Config(usize),
+    GameMode(bool),
+    /// The changes of layer tags that restore their state, in order.
+    LayerTags(Vec<(String, bool)>),
+    /// The changes of a transaction, which are undone in reverse order.
+    Transaction(Vec<UndoEntry>),
+}
+
+#[derive(Debug, Default)]
+pub struct UndoHistory {
+    entries: VecDeque<UndoEntry>,
+}
+
+impl UndoHistory {
+    /// Record a change, dropping the oldest one if the history is full.
+    pub fn push(&mut self, entry: UndoEntry) {
+        if self.entries.len() == UNDO_HISTORY_LEN {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Take the most recent change.
+    pub fn pop(&mut self) -> Option<UndoEntry> {
+        self.entries.pop_back()
+    }
+}
+
+#[test]
+fn undo_history_keeps_the_most_recent_changes() {
+    let mut history = UndoHistory::default();
+    for i in 0..UNDO_HISTORY_LEN + 2 {
+        history.push(UndoEntry::Config(i));
+    }
+    for i in (2..UNDO_HISTORY_LEN + 2).rev() {
+        assert_eq!(history.pop(), Some(UndoEntry::Config(i)));
+    }
+    assert_eq!(history.pop(), None);
+}
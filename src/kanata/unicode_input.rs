@@ -0,0 +1,131 @@
+//! Unicode input by code point, like Alt codes on Windows.
+//!
+//! While input is active, the keys output by the layout are captured instead of typed. Digits of
+//! the input's radix are collected and the character with that code point is typed when input is
+//! committed: by enter or space, or by releasing the `unicode-input` key if digits were typed
+//! while it was held. Backspace removes a digit and escape cancels. Other keys are ignored so
+//! that a stray press does not end up in the focused application.
+
+use crate::oskbd::KbdOut;
+use anyhow::Result;
+use kanata_keyberon::key_code::KeyCode;
+
+/// The most digits of a code point; `10FFFF` is the largest code point.
+const MAX_DIGITS: usize = 8;
+
+#[derive(Debug)]
+pub struct UnicodeInputState {
+    radix: u32,
+    digits: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnicodeInputNext {
+    Active,
+    /// Input ended, typing the character if the digits were a valid code point.
+    Commit(Option<char>),
+    Cancel,
+}
+
+impl UnicodeInputState {
+    pub fn new(radix: u32) -> Self {
+        Self {
+            radix,
+            digits: String::new(),
+        }
+    }
+
+    /// Handle a key pressed while input is active.
+    pub fn key(&mut self, key: KeyCode) -> UnicodeInputNext {
+        use KeyCode::*;
+        let digit = match key {
+            Enter | KpEnter | Space => return self.commit(),
+            Escape => return UnicodeInputNext::Cancel,
+            BSpace => {
+                self.digits.pop();
+                return UnicodeInputNext::Active;
+            }
+            Kb0 | Kp0 => '0',
+            Kb1 | Kp1 => '1',
+            Kb2 | Kp2 => '2',
+            Kb3 | Kp3 => '3',
+            Kb4 | Kp4 => '4',
+            Kb5 | Kp5 => '5',
+            Kb6 | Kp6 => '6',
+            Kb7 | Kp7 => '7',
+            Kb8 | Kp8 => '8',
+            Kb9 | Kp9 => '9',
+            A => 'a',
+            B => 'b',
+            C => 'c',
+            D => 'd',
+            E => 'e',
+            F => 'f',
+            _ => return UnicodeInputNext::Active,
+        };
+        if digit.is_digit(self.radix) && self.digits.len() < MAX_DIGITS {
+            self.digits.push(digit);
+        }
+        UnicodeInputNext::Active
+    }
+
+    /// Handle the release of the `unicode-input` key.
+    pub fn release(&mut self) -> UnicodeInputNext {
+        if self.digits.is_empty() {
+            UnicodeInputNext::Active
+        } else {
+            self.commit()
+        }
+    }
+
+    fn commit(&self) -> UnicodeInputNext {
+        UnicodeInputNext::Commit(
+            u32::from_str_radix(&self.digits, self.radix)
+                .ok()
+                .and_then(char::from_u32),
+        )
+    }
+}
+
+/// Type the character of committed input and end input if it is no longer active.
+pub fn end_unicode_input(
+    input: &mut Option<UnicodeInputState>,
+    kbd_out: &mut KbdOut,
+    next: UnicodeInputNext,
+) -> Result<()> {
+    match next {
+        UnicodeInputNext::Active => return Ok(()),
+        UnicodeInputNext::Commit(Some(c)) => {
+            log::debug!("unicode input typing {c}");
+            kbd_out.send_unicode(c)?;
+        }
+        UnicodeInputNext::Commit(None) => log::warn!("unicode input is not a valid character"),
+        UnicodeInputNext::Cancel => log::debug!("unicode input cancelled"),
+    }
+    *input = None;
+    Ok(())
+}
+
+#[test]
+fn unicode_input_collects_digits_until_commit() {
+    use KeyCode::*;
+    let mut input = UnicodeInputState::new(16);
+    for key in [Kb1, F, Kb6, Kb0, Kb0, Z] {
+        assert_eq!(input.key(key), UnicodeInputNext::Active);
+    }
+    assert_eq!(input.key(BSpace), UnicodeInputNext::Active);
+    assert_eq!(input.key(Kb2), UnicodeInputNext::Active);
+    assert_eq!(input.key(Enter), UnicodeInputNext::Commit(Some('😂')));
+
+    // Alt code style: the digits are committed by releasing the key.
+    let mut input = UnicodeInputState::new(10);
+    for key in [Kp2, Kp3, A, Kp3] {
+        input.key(key);
+    }
+    assert_eq!(input.release(), UnicodeInputNext::Commit(Some('é')));
+
+    let mut input = UnicodeInputState::new(10);
+    assert_eq!(input.release(), UnicodeInputNext::Active);
+    assert_eq!(input.key(Escape), UnicodeInputNext::Cancel);
+    assert_eq!(input.key(Space), UnicodeInputNext::Commit(None));
+}
@@ -0,0 +1,319 @@
+//! Statistics of which bindings and layers are used, for `kanata report`.
+//!
+//! When `usage-stats-file` is configured, every key press is counted for the layer whose action
+//! it triggers, and every activation of a layer is counted. The counts are kept across restarts
+//! in the file, which is written at most once a minute and on shutdown. Nothing is recorded about
+//! the order or timing of the keys.
+//!
+//! Probable misfires of tap-hold keys are counted per key too: a key that resolved as hold and
+//! was released without another key pressed while it was held, and a key that resolved as hold
+//! followed right after its release by backspace, which is how an unwanted hold is usually undone.
+
+use super::*;
+
+use kanata_keyberon::action::Action;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const USAGE_STATS_FILE_CFG_NAME: &str = "usage-stats-file";
+const SAVE_INTERVAL: time::Duration = time::Duration::from_secs(60);
+/// How soon after the release of a held tap-hold key a backspace counts as undoing the hold.
+const MISFIRE_UNDO_WINDOW: time::Duration = time::Duration::from_secs(1);
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageStats {
+    /// When recording started, in seconds since the Unix epoch.
+    #[serde(default)]
+    pub since: u64,
+    /// Key presses by layer name and key name.
+    #[serde(default)]
+    pub presses: BTreeMap<String, BTreeMap<String, u64>>,
+    /// Activations by layer name.
+    #[serde(default)]
+    pub layers: BTreeMap<String, u64>,
+    /// Probable tap-hold misfires by key name.
+    #[serde(default)]
+    pub misfires: BTreeMap<String, Misfires>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Misfires {
+    /// Resolved as hold and released without another key pressed.
+    #[serde(default)]
+    pub hold_alone: u64,
+    /// Resolved as hold and followed by backspace right after the release.
+    #[serde(default)]
+    pub hold_undone: u64,
+}
+
+impl UsageStats {
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("failed to read {}: {e}", path.display()))?;
+        serde_json::from_str(&text)
+            .map_err(|e| anyhow!("{} is not a usage statistics file: {e}", path.display()))
+    }
+
+    pub fn presses_of(&self, layer: &str, key: OsCode) -> u64 {
+        self.presses
+            .get(layer)
+            .and_then(|keys| keys.get(&format!("{key:?}")))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+pub struct UsageRecorder {
+    path: PathBuf,
+    stats: UsageStats,
+    changed: bool,
+    saved_at: time::Instant,
+    /// The held keys and whether another key was pressed after them.
+    held: Vec<(OsCode, bool)>,
+    /// The held tap-hold keys that resolved as hold.
+    held_as_hold: Vec<OsCode>,
+    /// The tap-hold key that was last released after resolving as hold, and when.
+    released_hold: Option<(OsCode, time::Instant)>,
+}
+
+impl UsageRecorder {
+    /// Returns `None` if usage statistics are not configured. Counts recorded before are read
+    /// from the file; an unreadable file is logged and recording starts over.
+    pub fn from_cfg(items: &HashMap<String, String>, now: time::Instant) -> Option<Self> {
+        let path = PathBuf::from(items.get(USAGE_STATS_FILE_CFG_NAME)?);
+        let stats = match UsageStats::read(&path) {
+            Ok(stats) => stats,
+            Err(e) => {
+                if path.exists() {
+                    log::warn!("starting new usage statistics: {e}");
+                }
+                UsageStats {
+                    since: SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |d| d.as_secs()),
+                    ..Default::default()
+                }
+            }
+        };
+        Some(Self {
+            path,
+            stats,
+            changed: false,
+            saved_at: now,
+            held: vec![],
+            held_as_hold: vec![],
+            released_hold: None,
+        })
+    }
+
+    pub fn press(&mut self, layer: &str, key: OsCode) {
+        *self
+            .stats
+            .presses
+            .entry(layer.to_owned())
+            .or_default()
+            .entry(format!("{key:?}"))
+            .or_default() += 1;
+        self.changed = true;
+    }
+
+    pub fn layer_activated(&mut self, layer: &str) {
+        *self.stats.layers.entry(layer.to_owned()).or_default() += 1;
+        self.changed = true;
+    }
+
+    /// The tap-hold action of the held key resolved as hold.
+    pub fn hold_resolved(&mut self, key: OsCode) {
+        if !self.held_as_hold.contains(&key) {
+            self.held_as_hold.push(key);
+        }
+    }
+
+    /// Look for probable misfires of the tap-hold keys in the input key events.
+    pub fn key_event(&mut self, key: OsCode, value: KeyValue, now: time::Instant) {
+        match value {
+            KeyValue::Press => {
+                if let Some((hold_key, released_at)) = self.released_hold.take() {
+                    if key == OsCode::KEY_BACKSPACE
+                        && now.duration_since(released_at) <= MISFIRE_UNDO_WINDOW
+                    {
+                        log::debug!("{hold_key:?} held, then undone with backspace");
+                        self.misfire(hold_key).hold_undone += 1;
+                    }
+                }
+                for (_, companion) in &mut self.held {
+                    *companion = true;
+                }
+                self.held.retain(|(k, _)| *k != key);
+                self.held.push((key, false));
+            }
+            KeyValue::Release => {
+                let companion = self
+                    .held
+                    .iter()
+                    .find(|(k, _)| *k == key)
+                    .is_some_and(|(_, companion)| *companion);
+                self.held.retain(|(k, _)| *k != key);
+                let Some(idx) = self.held_as_hold.iter().position(|k| *k == key) else {
+                    return;
+                };
+                self.held_as_hold.remove(idx);
+                if !companion {
+                    log::debug!("{key:?} held without another key");
+                    self.misfire(key).hold_alone += 1;
+                }
+                self.released_hold = Some((key, now));
+            }
+            KeyValue::Repeat => {}
+        }
+    }
+
+    fn misfire(&mut self, key: OsCode) -> &mut Misfires {
+        self.changed = true;
+        self.stats.misfires.entry(format!("{key:?}")).or_default()
+    }
+
+    /// Write the file if the counts changed and it was last written long enough ago.
+    pub fn save_if_due(&mut self, now: time::Instant) {
+        if now.duration_since(self.saved_at) >= SAVE_INTERVAL {
+            self.save(now);
+        }
+    }
+
+    /// Write the file if the counts changed. Written to a temporary file first so that the file
+    /// is never left half written.
+    pub fn save(&mut self, now: time::Instant) {
+        if !self.changed {
+            return;
+        }
+        self.changed = false;
+        self.saved_at = now;
+        let result = serde_json::to_string(&self.stats)
+            .map_err(|e| std::io::Error::other(e.to_string()))
+            .and_then(|text| {
+                let mut tmp = self.path.clone().into_os_string();
+                tmp.push(".tmp");
+                std::fs::write(&tmp, text)?;
+                std::fs::rename(&tmp, &self.path)
+            });
+        if let Err(e) = result {
+            log::warn!(
+                "failed to write usage statistics {}: {e}",
+                self.path.display()
+            );
+        }
+    }
+}
+
+impl Kanata {
+    /// Count a press of the key for the layer whose action it triggers. A transparent key
+    /// triggers the action of the default layer.
+    pub(super) fn record_key_usage(&mut self, key: OsCode) {
+        let Some(recorder) = &mut self.usage_stats else {
+            return;
+        };
+        let layout = self.layout.b();
+        let mut layer = layout.current_layer();
+        if matches!(layout.layers[layer][0][usize::from(key as u16)], Action::Trans) {
+            layer = layout.default_layer;
+        }
+        recorder.press(&self.layer_info[layer].name, key);
+    }
+}
+
+#[test]
+fn usage_stats_count_tap_hold_misfires() {
+    let path = std::env::temp_dir().join(format!("kanata-misfires-{}.json", std::process::id()));
+    let mut items = HashMap::default();
+    items.insert(
+        USAGE_STATS_FILE_CFG_NAME.to_owned(),
+        path.to_string_lossy().to_string(),
+    );
+    let start = time::Instant::now();
+    let ms = |ms| start + time::Duration::from_millis(ms);
+    let mut recorder = UsageRecorder::from_cfg(&items, start).unwrap();
+    let events = |recorder: &mut UsageRecorder, list: &[(OsCode, KeyValue, u64)]| {
+        for (key, value, at) in list {
+            recorder.key_event(*key, *value, ms(*at));
+        }
+    };
+    use KeyValue::*;
+    use OsCode::*;
+
+    // Held alone past the tapping term.
+    events(&mut recorder, &[(KEY_F, Press, 0)]);
+    recorder.hold_resolved(KEY_F);
+    events(&mut recorder, &[(KEY_F, Release, 300)]);
+    // Held with another key, which resolved the hold, then undone.
+    events(&mut recorder, &[(KEY_F, Press, 1000), (KEY_J, Press, 1050)]);
+    recorder.hold_resolved(KEY_F);
+    events(
+        &mut recorder,
+        &[
+            (KEY_J, Release, 1100),
+            (KEY_F, Release, 1120),
+            (KEY_BACKSPACE, Press, 1300),
+            (KEY_BACKSPACE, Release, 1350),
+        ],
+    );
+    // A hold used as intended, and a backspace long after another one.
+    events(&mut recorder, &[(KEY_D, Press, 2000)]);
+    recorder.hold_resolved(KEY_D);
+    events(
+        &mut recorder,
+        &[
+            (KEY_K, Press, 2200),
+            (KEY_K, Release, 2250),
+            (KEY_D, Release, 2300),
+            (KEY_BACKSPACE, Press, 4000),
+        ],
+    );
+
+    assert_eq!(
+        recorder.stats.misfires.get("KEY_F"),
+        Some(&Misfires {
+            hold_alone: 1,
+            hold_undone: 1,
+        })
+    );
+    assert_eq!(recorder.stats.misfires.get("KEY_D"), None);
+    assert!(!path.exists());
+}
+
+#[test]
+fn usage_stats_round_trip() {
+    let path = std::env::temp_dir().join(format!("kanata-usage-{}.json", std::process::id()));
+    let mut items = HashMap::default();
+    items.insert(
+        USAGE_STATS_FILE_CFG_NAME.to_owned(),
+        path.to_string_lossy().to_string(),
+    );
+    let start = time::Instant::now();
+    let mut recorder = UsageRecorder::from_cfg(&items, start).unwrap();
+    recorder.layer_activated("base");
+    recorder.press("base", OsCode::KEY_A);
+    recorder.press("base", OsCode::KEY_A);
+    recorder.press("nav", OsCode::KEY_H);
+    recorder.save_if_due(start + time::Duration::from_secs(1));
+    assert!(!path.exists());
+    recorder.save_if_due(start + SAVE_INTERVAL);
+
+    let stats = UsageStats::read(&path).unwrap();
+    assert_eq!(stats.presses_of("base", OsCode::KEY_A), 2);
+    assert_eq!(stats.presses_of("nav", OsCode::KEY_H), 1);
+    assert_eq!(stats.presses_of("nav", OsCode::KEY_A), 0);
+    assert_eq!(stats.layers["base"], 1);
+
+    // The counts continue after a restart.
+    let mut recorder = UsageRecorder::from_cfg(&items, start).unwrap();
+    recorder.press("nav", OsCode::KEY_H);
+    recorder.save(start);
+    assert_eq!(
+        UsageStats::read(&path).unwrap().presses_of("nav", OsCode::KEY_H),
+        2
+    );
+    assert_eq!(UsageStats::read(&path).unwrap().since, stats.since);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -11,7 +11,7 @@ use crate::keys::{KeyValue, OsCode};
 const HWID_ARR_SZ: usize = 128;
 
 impl Kanata {
-    pub fn event_loop(kanata: Arc<Mutex<Self>>, tx: Sender<KeyEvent>) -> Result<()> {
+    pub fn event_loop(kanata: Arc<Mutex<Self>>, tx: Sender<ProcessingEvent>) -> Result<()> {
         let intrcptn = ic::Interception::new().ok_or_else(|| anyhow!("interception driver should init: have you completed the interception driver installation?"))?;
         intrcptn.set_filter(ic::is_keyboard, ic::Filter::KeyFilter(ic::KeyFilter::all()));
         let mut strokes = [ic::Stroke::Keyboard {
@@ -111,7 +111,7 @@ impl Kanata {
                         }
                         _ => {}
                     }
-                    tx.send(key_event)?;
+                    tx.send(key_event.into())?;
                 }
             }
         }
@@ -10,7 +10,7 @@ use crate::kanata::*;
 impl Kanata {
     /// Initialize the callback that is passed to the Windows low level hook to receive key events
     /// and run the native_windows_gui event loop.
-    pub fn event_loop(_kanata: Arc<Mutex<Self>>, tx: Sender<KeyEvent>) -> Result<()> {
+    pub fn event_loop(_kanata: Arc<Mutex<Self>>, tx: Sender<ProcessingEvent>) -> Result<()> {
         // Display debug and panic output when launched from a terminal.
         unsafe {
             use winapi::um::wincon::*;
@@ -72,13 +72,16 @@ impl Kanata {
     }
 }
 
-fn try_send_panic(tx: &Sender<KeyEvent>, kev: KeyEvent) {
-    if let Err(e) = tx.send(kev) {
+fn try_send_panic<T>(tx: &Sender<T>, kev: impl Into<T>) {
+    if let Err(e) = tx.send(kev.into()) {
         panic!("failed to send on channel: {e:?}")
     }
 }
 
-fn start_event_preprocessor(preprocess_rx: Receiver<KeyEvent>, process_tx: Sender<KeyEvent>) {
+fn start_event_preprocessor(
+    preprocess_rx: Receiver<KeyEvent>,
+    process_tx: Sender<ProcessingEvent>,
+) {
     #[derive(Debug, Clone, Copy, PartialEq)]
     enum LctlState {
         Pressed,
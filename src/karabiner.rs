@@ -0,0 +1,343 @@
+//! `kanata export-karabiner`: translate a subset of a kanata configuration into a
+//! Karabiner-Elements complex modification.
+//!
+//! Each kanata layer becomes a rule. The active layer is tracked in a Karabiner variable; the
+//! manipulators of every layer except the base layer are conditioned on it. Karabiner uses the
+//! first manipulator that matches, so the rules of the other layers come before the base layer
+//! rule and transparent keys fall through to the base layer.
+//!
+//! Supported actions are keys, keys with modifiers, `XX`, `layer-while-held`, `layer-switch` and
+//! tap-hold actions made of these. Everything else is skipped with a warning.
+
+use crate::cfg::{self, LayerInfo};
+use crate::custom_action::CustomAction;
+use crate::keys::OsCode;
+
+use anyhow::{anyhow, Result};
+use kanata_keyberon::action::Action;
+use kanata_keyberon::key_code::KeyCode;
+use serde_json::{json, Value};
+
+use std::path::Path;
+
+/// Name of the Karabiner variable that holds the active layer.
+const LAYER_VAR: &str = "kanata_layer";
+
+type LayoutAction<'a> = Action<'a, &'a &'a [&'a CustomAction]>;
+
+/// Parse the configuration at the given path and print the Karabiner JSON to stdout.
+pub fn run(cfg_path: &Path) -> Result<()> {
+    let cfg = cfg::new_from_file(cfg_path).map_err(|e| anyhow!("{e:?}"))?;
+    let mut mapped_keys = cfg.mapped_keys.iter().copied().collect::<Vec<_>>();
+    mapped_keys.sort_by_key(|k| *k as u16);
+    let title = format!(
+        "kanata: {}",
+        cfg_path.file_name().unwrap_or_default().to_string_lossy()
+    );
+    let (json, warnings) = export(&title, cfg.layout.b().layers, &cfg.layer_info, &mapped_keys);
+    for warning in warnings {
+        eprintln!("warning: {warning}");
+    }
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&json)
+            .map_err(|e| anyhow!("failed to serialize JSON: {e}"))?
+    );
+    Ok(())
+}
+
+/// Returns the complex modification and warnings about the actions that could not be
+/// translated.
+fn export<const C: usize, const R: usize, const L: usize>(
+    title: &str,
+    layers: &[[[LayoutAction; C]; R]; L],
+    layer_info: &[LayerInfo],
+    mapped_keys: &[OsCode],
+) -> (Value, Vec<String>) {
+    let mut warnings = vec![];
+    let mut rules = vec![];
+    let base_layer = &layer_info[0].name;
+    // Each layer is duplicated in the keyberon layout, the first copy is used.
+    for layer_idx in (0..layer_info.len()).step_by(2).rev() {
+        let layer_name = &layer_info[layer_idx].name;
+        let is_base = layer_idx == 0;
+        let mut manipulators = vec![];
+        for &key in mapped_keys {
+            let src = match karabiner_key(key) {
+                Some(src) => src,
+                None => {
+                    if layer_idx == 0 {
+                        warnings.push(format!("{key:?} has no Karabiner equivalent"));
+                    }
+                    continue;
+                }
+            };
+            let action = &layers[layer_idx][0][usize::from(key as u16)];
+            if is_base && matches!(action, Action::KeyCode(k) if OsCode::from(*k) == key) {
+                continue;
+            }
+            let mut manipulator = match manipulator(action, layer_info, base_layer) {
+                Ok(Some(m)) => m,
+                Ok(None) => continue,
+                Err(e) => {
+                    warnings.push(format!("layer {layer_name}, key {src}: {e}"));
+                    continue;
+                }
+            };
+            manipulator["type"] = json!("basic");
+            manipulator["from"] = json!({
+                "key_code": src,
+                "modifiers": { "optional": ["any"] },
+            });
+            if !is_base {
+                manipulator["conditions"] = json!([{
+                    "type": "variable_if",
+                    "name": LAYER_VAR,
+                    "value": layer_name,
+                }]);
+            }
+            manipulators.push(manipulator);
+        }
+        rules.push(json!({
+            "description": format!("kanata layer {layer_name}"),
+            "manipulators": manipulators,
+        }));
+    }
+    (json!({ "title": title, "rules": rules }), warnings)
+}
+
+/// Returns the manipulator for the action without the `from` part, `None` if the key should be
+/// left alone, or a description of why the action is not supported.
+fn manipulator(
+    action: &LayoutAction,
+    layer_info: &[LayerInfo],
+    base_layer: &str,
+) -> Result<Option<Value>, String> {
+    let set_layer = |name: &str| json!({ "set_variable": { "name": LAYER_VAR, "value": name } });
+    Ok(Some(match action {
+        Action::Trans => return Ok(None),
+        Action::Layer(idx) => json!({
+            "to": [set_layer(&layer_info[*idx].name)],
+            "to_after_key_up": [set_layer(base_layer)],
+        }),
+        Action::HoldTap(ht) => {
+            let tap = to_events(&ht.tap, layer_info)?;
+            let mut m = match &ht.hold {
+                Action::Layer(idx) => json!({
+                    "to": [set_layer(&layer_info[*idx].name)],
+                    "to_after_key_up": [set_layer(base_layer)],
+                }),
+                hold => json!({ "to": to_events(hold, layer_info)? }),
+            };
+            m["to_if_alone"] = json!(tap);
+            m["parameters"] = json!({
+                "basic.to_if_alone_timeout_milliseconds": ht.timeout,
+            });
+            m
+        }
+        action => json!({ "to": to_events(action, layer_info)? }),
+    }))
+}
+
+/// Translate an action that outputs events immediately.
+fn to_events(action: &LayoutAction, layer_info: &[LayerInfo]) -> Result<Vec<Value>, String> {
+    let key_name = |k: &KeyCode| {
+        karabiner_key(OsCode::from(*k)).ok_or_else(|| format!("{k:?} has no Karabiner equivalent"))
+    };
+    Ok(match action {
+        Action::NoOp => vec![json!({ "key_code": "vk_none" })],
+        Action::KeyCode(k) => vec![json!({ "key_code": key_name(k)? })],
+        Action::MultipleKeyCodes(keys) => {
+            let (mods, keys): (Vec<_>, Vec<_>) = keys.iter().partition(|k| k.is_modifier());
+            let (key, mods) = match keys.as_slice() {
+                [key] => (*key, &mods[..]),
+                [] if !mods.is_empty() => (mods[0], &mods[1..]),
+                _ => return Err("only one non-modifier key can be pressed at once".into()),
+            };
+            vec![json!({
+                "key_code": key_name(key)?,
+                "modifiers": mods.iter().map(|k| key_name(k)).collect::<Result<Vec<_>, _>>()?,
+            })]
+        }
+        Action::DefaultLayer(idx) => vec![json!({
+            "set_variable": { "name": LAYER_VAR, "value": layer_info[*idx].name },
+        })],
+        _ => return Err("the action is not supported".into()),
+    })
+}
+
+/// Returns the Karabiner `key_code` of the key.
+fn karabiner_key(key: OsCode) -> Option<&'static str> {
+    use OsCode::*;
+    Some(match key {
+        KEY_A => "a",
+        KEY_B => "b",
+        KEY_C => "c",
+        KEY_D => "d",
+        KEY_E => "e",
+        KEY_F => "f",
+        KEY_G => "g",
+        KEY_H => "h",
+        KEY_I => "i",
+        KEY_J => "j",
+        KEY_K => "k",
+        KEY_L => "l",
+        KEY_M => "m",
+        KEY_N => "n",
+        KEY_O => "o",
+        KEY_P => "p",
+        KEY_Q => "q",
+        KEY_R => "r",
+        KEY_S => "s",
+        KEY_T => "t",
+        KEY_U => "u",
+        KEY_V => "v",
+        KEY_W => "w",
+        KEY_X => "x",
+        KEY_Y => "y",
+        KEY_Z => "z",
+        KEY_1 => "1",
+        KEY_2 => "2",
+        KEY_3 => "3",
+        KEY_4 => "4",
+        KEY_5 => "5",
+        KEY_6 => "6",
+        KEY_7 => "7",
+        KEY_8 => "8",
+        KEY_9 => "9",
+        KEY_0 => "0",
+        KEY_ENTER => "return_or_enter",
+        KEY_ESC => "escape",
+        KEY_BACKSPACE => "delete_or_backspace",
+        KEY_DELETE => "delete_forward",
+        KEY_TAB => "tab",
+        KEY_SPACE => "spacebar",
+        KEY_MINUS => "hyphen",
+        KEY_EQUAL => "equal_sign",
+        KEY_LEFTBRACE => "open_bracket",
+        KEY_RIGHTBRACE => "close_bracket",
+        KEY_BACKSLASH => "backslash",
+        KEY_SEMICOLON => "semicolon",
+        KEY_APOSTROPHE => "quote",
+        KEY_GRAVE => "grave_accent_and_tilde",
+        KEY_COMMA => "comma",
+        KEY_DOT => "period",
+        KEY_SLASH => "slash",
+        KEY_102ND => "non_us_backslash",
+        KEY_CAPSLOCK => "caps_lock",
+        KEY_F1 => "f1",
+        KEY_F2 => "f2",
+        KEY_F3 => "f3",
+        KEY_F4 => "f4",
+        KEY_F5 => "f5",
+        KEY_F6 => "f6",
+        KEY_F7 => "f7",
+        KEY_F8 => "f8",
+        KEY_F9 => "f9",
+        KEY_F10 => "f10",
+        KEY_F11 => "f11",
+        KEY_F12 => "f12",
+        KEY_F13 => "f13",
+        KEY_F14 => "f14",
+        KEY_F15 => "f15",
+        KEY_F16 => "f16",
+        KEY_F17 => "f17",
+        KEY_F18 => "f18",
+        KEY_F19 => "f19",
+        KEY_F20 => "f20",
+        KEY_LEFT => "left_arrow",
+        KEY_RIGHT => "right_arrow",
+        KEY_UP => "up_arrow",
+        KEY_DOWN => "down_arrow",
+        KEY_HOME => "home",
+        KEY_END => "end",
+        KEY_PAGEUP => "page_up",
+        KEY_PAGEDOWN => "page_down",
+        KEY_INSERT => "insert",
+        KEY_SYSRQ => "print_screen",
+        KEY_SCROLLLOCK => "scroll_lock",
+        KEY_PAUSE => "pause",
+        KEY_COMPOSE => "application",
+        KEY_LEFTCTRL => "left_control",
+        KEY_LEFTSHIFT => "left_shift",
+        KEY_LEFTALT => "left_option",
+        KEY_LEFTMETA => "left_command",
+        KEY_RIGHTCTRL => "right_control",
+        KEY_RIGHTSHIFT => "right_shift",
+        KEY_RIGHTALT => "right_option",
+        KEY_RIGHTMETA => "right_command",
+        KEY_NUMLOCK => "keypad_num_lock",
+        KEY_KPSLASH => "keypad_slash",
+        KEY_KPASTERISK => "keypad_asterisk",
+        KEY_KPMINUS => "keypad_hyphen",
+        KEY_KPPLUS => "keypad_plus",
+        KEY_KPENTER => "keypad_enter",
+        KEY_KPDOT => "keypad_period",
+        KEY_KPEQUAL => "keypad_equal_sign",
+        KEY_KP1 => "keypad_1",
+        KEY_KP2 => "keypad_2",
+        KEY_KP3 => "keypad_3",
+        KEY_KP4 => "keypad_4",
+        KEY_KP5 => "keypad_5",
+        KEY_KP6 => "keypad_6",
+        KEY_KP7 => "keypad_7",
+        KEY_KP8 => "keypad_8",
+        KEY_KP9 => "keypad_9",
+        KEY_KP0 => "keypad_0",
+        KEY_MUTE => "mute",
+        KEY_VOLUMEDOWN => "volume_decrement",
+        KEY_VOLUMEUP => "volume_increment",
+        _ => return None,
+    })
+}
+
+#[test]
+fn export_karabiner_layers() {
+    let path = std::env::temp_dir().join(format!("kanata-karabiner-{}.kbd", std::process::id()));
+    std::fs::write(
+        &path,
+        "
+(defsrc caps a s d)
+(deflayer base (tap-hold 200 200 esc lctl) _ (layer-while-held nav) C-S-d)
+(deflayer nav XX left (layer-switch base) sldr)
+",
+    )
+    .unwrap();
+    let cfg = cfg::new_from_file(&path);
+    std::fs::remove_file(&path).unwrap();
+    let cfg = cfg.unwrap();
+    let keys = [
+        OsCode::KEY_CAPSLOCK,
+        OsCode::KEY_A,
+        OsCode::KEY_S,
+        OsCode::KEY_D,
+    ];
+    let (json, warnings) = export("test", cfg.layout.b().layers, &cfg.layer_info, &keys);
+    assert_eq!(warnings, ["layer nav, key d: the action is not supported"]);
+    assert_eq!(json["rules"][0]["description"], "kanata layer nav");
+    let nav = &json["rules"][0]["manipulators"];
+    assert_eq!(nav[0]["to"][0]["key_code"], "vk_none");
+    assert_eq!(nav[0]["conditions"][0]["value"], "nav");
+    assert_eq!(nav[1]["from"]["key_code"], "a");
+    assert_eq!(nav[1]["to"][0]["key_code"], "left_arrow");
+    assert_eq!(nav[2]["to"][0]["set_variable"]["value"], "base");
+    assert_eq!(nav.as_array().unwrap().len(), 3);
+
+    let base = &json["rules"][1]["manipulators"];
+    assert_eq!(base[0]["from"]["key_code"], "caps_lock");
+    assert_eq!(base[0]["to_if_alone"][0]["key_code"], "escape");
+    assert_eq!(base[0]["to"][0]["key_code"], "left_control");
+    assert!(base[0].get("conditions").is_none());
+    // `a` is unchanged in the base layer.
+    assert_eq!(base[1]["from"]["key_code"], "s");
+    assert_eq!(base[1]["to"][0]["set_variable"]["value"], "nav");
+    assert_eq!(
+        base[1]["to_after_key_up"][0]["set_variable"]["value"],
+        "base"
+    );
+    assert_eq!(base[2]["to"][0]["key_code"], "d");
+    assert_eq!(
+        base[2]["to"][0]["modifiers"],
+        json!(["left_control", "left_shift"])
+    );
+}
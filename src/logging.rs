@@ -0,0 +1,174 @@
+//! The logger of kanata: simplelog's terminal logger or JSON lines, with a level filter per module
+//! that can be changed while kanata runs.
+//!
+//! Filters are written like `info kanata::oskbd=debug kanata::tcp_server=trace`: a bare level
+//! sets the level of every module, `module=level` sets the level of a module and its submodules.
+//! The most specific module wins.
+
+use anyhow::{anyhow, Result};
+use log::{LevelFilter, Log, Metadata, Record};
+use once_cell::sync::OnceCell;
+use parking_lot::RwLock;
+use simplelog::*;
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line, for journald and other log collectors.
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFilter {
+    default: LevelFilter,
+    /// Module path prefixes and their levels.
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl LogFilter {
+    /// Parse directives on top of the default level.
+    pub fn parse(default: LevelFilter, directives: &str) -> Result<Self> {
+        let mut filter = Self {
+            default,
+            modules: vec![],
+        };
+        for directive in directives
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|d| !d.is_empty())
+        {
+            let parse_level = |s: &str| {
+                s.parse::<LevelFilter>()
+                    .map_err(|_| anyhow!("invalid log level {s:?} in {directive:?}"))
+            };
+            match directive.split_once('=') {
+                Some((module, level)) => filter.modules.push((module.into(), parse_level(level)?)),
+                None => filter.default = parse_level(directive)?,
+            }
+        }
+        Ok(filter)
+    }
+
+    fn level(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .filter(|(module, _)| {
+                target
+                    .strip_prefix(module.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .max_by_key(|(module, _)| module.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.modules
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, Ord::max)
+    }
+}
+
+struct KanataLogger {
+    /// The level set by the command line, which filters start from.
+    cli_level: LevelFilter,
+    filter: RwLock<LogFilter>,
+    format: LogFormat,
+    term: Box<TermLogger>,
+}
+
+static LOGGER: OnceCell<KanataLogger> = OnceCell::new();
+
+impl Log for KanataLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.filter.read().level(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match self.format {
+            LogFormat::Text => self.term.log(record),
+            LogFormat::Json => {
+                let _ = writeln!(std::io::stderr().lock(), "{}", json_line(record));
+            }
+        }
+    }
+
+    fn flush(&self) {
+        self.term.flush();
+    }
+}
+
+fn json_line(record: &Record) -> String {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    serde_json::json!({
+        "timestamp_ms": timestamp as u64,
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+    })
+    .to_string()
+}
+
+/// Install the logger. The terminal mode only applies to the text format; JSON is written to
+/// stderr.
+pub fn init(level: LevelFilter, format: LogFormat, terminal_mode: TerminalMode) {
+    let mut log_cfg = ConfigBuilder::new();
+    if let Err(e) = log_cfg.set_time_offset_to_local() {
+        eprintln!("WARNING: could not set log TZ to local: {e:?}");
+    };
+    let logger = LOGGER.get_or_init(|| KanataLogger {
+        cli_level: level,
+        filter: RwLock::new(LogFilter {
+            default: level,
+            modules: vec![],
+        }),
+        format,
+        term: TermLogger::new(
+            LevelFilter::Trace,
+            log_cfg.build(),
+            terminal_mode,
+            ColorChoice::AlwaysAnsi,
+        ),
+    });
+    log::set_logger(logger).expect("logger can init");
+    log::set_max_level(level);
+}
+
+/// Replace the module filters with these directives, on top of the level from the command line.
+/// Does nothing if the logger is not installed, e.g. in tests.
+pub fn set_filter(directives: &str) -> Result<()> {
+    let Some(logger) = LOGGER.get() else {
+        return Ok(());
+    };
+    let filter = LogFilter::parse(logger.cli_level, directives)?;
+    log::set_max_level(filter.max_level());
+    *logger.filter.write() = filter;
+    Ok(())
+}
+
+#[test]
+fn log_filter_picks_most_specific_module() {
+    let filter = LogFilter::parse(
+        LevelFilter::Info,
+        "kanata::oskbd=debug, kanata::oskbd::linux=trace kanata::tcp_server=off",
+    )
+    .unwrap();
+    assert_eq!(filter.level("kanata::kanata"), LevelFilter::Info);
+    assert_eq!(filter.level("kanata::oskbd"), LevelFilter::Debug);
+    assert_eq!(filter.level("kanata::oskbd::windows"), LevelFilter::Debug);
+    assert_eq!(filter.level("kanata::oskbd::linux"), LevelFilter::Trace);
+    assert_eq!(filter.level("kanata::oskbdx"), LevelFilter::Info);
+    assert_eq!(filter.level("kanata::tcp_server"), LevelFilter::Off);
+    assert_eq!(filter.max_level(), LevelFilter::Trace);
+
+    let filter = LogFilter::parse(LevelFilter::Info, "warn").unwrap();
+    assert_eq!(filter.level("kanata::kanata"), LevelFilter::Warn);
+    assert!(LogFilter::parse(LevelFilter::Info, "kanata=loud").is_err());
+}
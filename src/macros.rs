@@ -0,0 +1,245 @@
+//! `kanata export-macros` and `kanata import-macros`: move recorded dynamic macros and snippets
+//! between machines, or into the configuration, with the portable format of
+//! [`macro_export`](crate::kanata::macro_export).
+//!
+//! `export-macros` prints the dynamic macros recorded by a running kanata instance and the
+//! snippets of its configuration as JSON. `import-macros` sends the dynamic macros of such a file
+//! to a running instance, or without `--port`, prints them as `macro` aliases and the snippets as
+//! `defsnippets`, to be pasted into a configuration:
+//!
+//! ```sh
+//! kanata export-macros -p 5829 > macros.json
+//! kanata import-macros macros.json -p 5829
+//! kanata import-macros macros.json >> kanata.kbd
+//! ```
+
+use crate::kanata::{parse_macro_keys, DynamicMacroItem, MACRO_EXPORT_VERSION};
+use crate::keys::OsCode;
+use crate::palette::key_name;
+use crate::tcp_client::KanataClient;
+use crate::tcp_server::{ClientMessage, MacroExport, ServerMessage};
+
+use anyhow::{anyhow, bail, Result};
+use kanata_keyberon::key_code::KeyCode;
+
+use std::path::Path;
+
+/// Print the dynamic macros and snippets of the kanata instance on the port as JSON.
+pub fn export(port: u16) -> Result<()> {
+    let macros =
+        KanataClient::connect(port)?.request(&ClientMessage::ExportMacros, "macros", |msg| {
+            match msg {
+                ServerMessage::Macros { macros } => Some(macros),
+                _ => None,
+            }
+        })?;
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&macros)
+            .map_err(|e| anyhow!("failed to serialize the macros: {e}"))?
+    );
+    Ok(())
+}
+
+/// Send the dynamic macros of the exported file to the kanata instance on the port, or print the
+/// file as configuration if there is no port.
+pub fn import(path: &Path, port: Option<u16>) -> Result<()> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("failed to read {}: {e}", path.display()))?;
+    let export: MacroExport = serde_json::from_str(&text)
+        .map_err(|e| anyhow!("{} is not a macro export: {e}", path.display()))?;
+    if export.version > MACRO_EXPORT_VERSION {
+        bail!(
+            "{} has export version {}, this kanata imports up to version {MACRO_EXPORT_VERSION}",
+            path.display(),
+            export.version
+        );
+    }
+    for exported in &export.dynamic_macros {
+        parse_macro_keys(&exported.keys)
+            .map_err(|e| anyhow!("dynamic macro {}: {e}", exported.id))?;
+    }
+    match port {
+        Some(port) => {
+            if !export.snippets.is_empty() {
+                eprintln!(
+                    "warning: snippets are not imported into a running kanata, \
+                    run without --port to print them as defsnippets"
+                );
+            }
+            let count = export.dynamic_macros.len();
+            let mut client = KanataClient::connect(port)?;
+            client.send(&ClientMessage::ImportMacros { macros: export })?;
+            client.confirm()?;
+            println!("sent {count} dynamic macro(s) to kanata");
+        }
+        None => {
+            let (text, warnings) = config_text(&export);
+            for warning in warnings {
+                eprintln!("warning: {warning}");
+            }
+            print!("{text}");
+        }
+    }
+    Ok(())
+}
+
+/// The export as configuration: the dynamic macros as `macro` aliases named `dmacro-<id>`, and
+/// the snippets as `defsnippets`. Returns warnings about what could not be written.
+fn config_text(export: &MacroExport) -> (String, Vec<String>) {
+    let mut warnings = vec![];
+    let mut aliases = vec![];
+    let mut digits = vec![];
+    for exported in &export.dynamic_macros {
+        let items = parse_macro_keys(&exported.keys).unwrap_or_default();
+        match macro_text(&items, &mut digits) {
+            Ok(text) => aliases.push(format!("  dmacro-{} (macro {text})", exported.id)),
+            Err(e) => warnings.push(format!("skipping dynamic macro {}: {e}", exported.id)),
+        }
+    }
+    // Number keys are delays in `macro`, so they are typed through aliases.
+    digits.sort();
+    digits.dedup();
+    for digit in digits.into_iter().rev() {
+        aliases.insert(0, format!("  {DIGIT_ALIAS_PREFIX}{digit} {digit}"));
+    }
+    let mut snippets = vec![];
+    for snippet in &export.snippets {
+        if snippet.trigger.contains('"') || snippet.expansion.contains('"') {
+            warnings.push(format!(
+                "skipping snippet {}: quotes cannot be written in a configuration string",
+                snippet.trigger
+            ));
+            continue;
+        }
+        let (trigger, expansion) = (&snippet.trigger, &snippet.expansion);
+        snippets.push(format!("  \"{trigger}\" \"{expansion}\""));
+    }
+    let mut text = String::new();
+    if !aliases.is_empty() {
+        text.push_str(&format!("(defalias\n{}\n)\n", aliases.join("\n")));
+    }
+    if !snippets.is_empty() {
+        text.push_str(&format!("(defsnippets\n{}\n)\n", snippets.join("\n")));
+    }
+    (text, warnings)
+}
+
+const DIGIT_ALIAS_PREFIX: &str = "dmacro-digit-";
+
+/// The parameters of a `macro` that types the dynamic macro: taps of keys, with the held
+/// modifiers as prefixes like `C-S-`. Dynamic macros that press several keys other than
+/// modifiers at once cannot be written as a `macro`. Number keys that are typed without
+/// modifiers are added to `digits`.
+fn macro_text(items: &[DynamicMacroItem], digits: &mut Vec<String>) -> Result<String, String> {
+    // The held modifiers and whether a key was tapped while they were held.
+    let mut held: Vec<(OsCode, bool)> = vec![];
+    let mut pressed = None;
+    let mut taps = vec![];
+    let mut tap = |osc: OsCode, held: &[(OsCode, bool)]| {
+        let prefix = held
+            .iter()
+            .map(|(m, _)| match KeyCode::from(*m) {
+                KeyCode::LCtrl | KeyCode::RCtrl => "C-",
+                KeyCode::LShift | KeyCode::RShift => "S-",
+                KeyCode::RAlt => "RA-",
+                KeyCode::LAlt => "A-",
+                _ => "M-",
+            })
+            .collect::<String>();
+        let name = key_name(osc);
+        if prefix.is_empty() && name.parse::<u16>().is_ok() {
+            taps.push(format!("@{DIGIT_ALIAS_PREFIX}{name}"));
+            digits.push(name);
+        } else {
+            taps.push(format!("{prefix}{name}"));
+        }
+    };
+    for item in items {
+        match *item {
+            DynamicMacroItem::Press(osc) if KeyCode::from(osc).is_modifier() => {
+                held.push((osc, false));
+            }
+            DynamicMacroItem::Release(osc) if KeyCode::from(osc).is_modifier() => {
+                let Some(i) = held.iter().position(|(m, _)| *m == osc) else {
+                    continue;
+                };
+                let (_, used) = held.remove(i);
+                if !used {
+                    tap(osc, &held);
+                }
+            }
+            DynamicMacroItem::Press(osc) => {
+                if let Some(other) = pressed {
+                    return Err(format!(
+                        "{} is pressed while {} is held",
+                        key_name(osc),
+                        key_name(other)
+                    ));
+                }
+                pressed = Some(osc);
+                for (_, used) in held.iter_mut() {
+                    *used = true;
+                }
+            }
+            DynamicMacroItem::Release(osc) => {
+                if pressed == Some(osc) {
+                    pressed = None;
+                    tap(osc, &held);
+                }
+            }
+            DynamicMacroItem::EndMacro(_) => {}
+        }
+    }
+    if taps.is_empty() {
+        return Err("it types no keys".into());
+    }
+    Ok(taps.join(" "))
+}
+
+#[test]
+fn exports_are_written_as_configuration() {
+    use crate::tcp_server::{ExportedMacro, ExportedSnippet};
+
+    let export = MacroExport {
+        version: MACRO_EXPORT_VERSION,
+        dynamic_macros: vec![
+            ExportedMacro {
+                id: 1,
+                keys: "+lctl +lsft t -lsft -lctl h i 1 lsft".into(),
+            },
+            ExportedMacro {
+                id: 2,
+                keys: "+a +b -a -b".into(),
+            },
+            ExportedMacro {
+                id: 3,
+                keys: "".into(),
+            },
+        ],
+        snippets: vec![
+            ExportedSnippet {
+                trigger: ";d".into(),
+                expansion: "{date}{enter}".into(),
+            },
+            ExportedSnippet {
+                trigger: ";q".into(),
+                expansion: "\"quoted\"".into(),
+            },
+        ],
+    };
+    let (text, warnings) = config_text(&export);
+    assert_eq!(
+        text,
+        r#"(defalias
+  dmacro-digit-1 1
+  dmacro-1 (macro C-S-t h i @dmacro-digit-1 lshift)
+)
+(defsnippets
+  ";d" "{date}{enter}"
+)
+"#
+    );
+    assert_eq!(warnings.len(), 3, "{warnings:?}");
+    assert!(warnings[0].contains("b is pressed while a is held"));
+}
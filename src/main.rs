@@ -3,25 +3,50 @@ use log::info;
 use simplelog::*;
 use std::path::PathBuf;
 
+mod bench;
+mod build_info;
 mod cfg;
 mod custom_action;
+mod diff;
+mod explain;
 mod kanata;
+mod karabiner;
 mod keys;
 mod layers;
+mod logging;
+mod macros;
 mod oskbd;
+mod palette;
+mod report;
+mod status;
+mod tcp_client;
 mod tcp_server;
+mod top;
+mod train;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use kanata::Kanata;
-use tcp_server::TcpServer;
+use tcp_server::{Acl, TcpServer};
 
 type CfgPath = PathBuf;
 
 pub struct ValidatedArgs {
     paths: Vec<CfgPath>,
+    check: bool,
+    bench_self: bool,
     port: Option<i32>,
+    tcp_acl: Option<Acl>,
+    notify_interval: u64,
     #[cfg(target_os = "linux")]
     symlink_path: Option<String>,
+    #[cfg(target_os = "linux")]
+    filter: bool,
+    #[cfg(target_os = "linux")]
+    force: bool,
+    #[cfg(target_os = "linux")]
+    measure_latency: bool,
+    #[cfg(target_os = "linux")]
+    device_fds: Vec<i32>,
 }
 
 #[derive(Parser, Debug)]
@@ -39,9 +64,10 @@ pub struct ValidatedArgs {
 ///
 ///     https://github.com/jtroo/kanata
 struct Args {
-    /// Configuration file(s) to use with kanata. If not specified, defaults to
-    /// kanata.kbd in the current working directory.
-    #[arg(short, long, default_value = "kanata.kbd", verbatim_doc_comment)]
+    /// Configuration file(s) to use with kanata. If not specified, the first
+    /// kanata.kbd found in the current working directory, the user's config
+    /// directory, and the system config directories is used.
+    #[arg(short, long, verbatim_doc_comment)]
     cfg: Vec<String>,
 
     /// Port to run the optional TCP server on. If blank, no TCP port will be
@@ -49,12 +75,70 @@ struct Args {
     #[arg(short, long, verbatim_doc_comment)]
     port: Option<i32>,
 
+    /// File of tokens and the TCP messages that clients presenting them may
+    /// send. Clients that do not authenticate may only send the messages
+    /// that read state. If blank, every client may send every message.
+    /// Subcommands such as `kanata status` present the token in the
+    /// KANATA_TCP_TOKEN environment variable.
+    #[arg(long, verbatim_doc_comment)]
+    tcp_acl: Option<PathBuf>,
+
+    /// Minimum time in milliseconds between two layer change notifications
+    /// to TCP clients. The layer changes in between are coalesced into the
+    /// latest one.
+    #[arg(long, default_value_t = 0, verbatim_doc_comment)]
+    notify_interval: u64,
+
     /// Path for the symlink pointing to the newly-created device. If blank, no
     /// symlink will be created.
     #[cfg(target_os = "linux")]
     #[arg(short, long, verbatim_doc_comment)]
     symlink_path: Option<String>,
 
+    /// Read input events from stdin and write output events to stdout instead
+    /// of grabbing devices, for use in an Interception Tools pipeline, e.g.:
+    ///
+    ///     intercept -g $DEVNODE | kanata --filter | uinput -d $DEVNODE
+    #[cfg(target_os = "linux")]
+    #[arg(long, verbatim_doc_comment)]
+    filter: bool,
+
+    /// Parse the configuration, print warnings about suspicious patterns in
+    /// it and exit.
+    #[arg(long, verbatim_doc_comment)]
+    check: bool,
+
+    /// Measure how long the stages of key processing take with the
+    /// configuration on this machine, print the timings and exit, e.g. to
+    /// include them in a bug report about lag.
+    #[arg(long, verbatim_doc_comment)]
+    bench_self: bool,
+
+    /// Grab devices that have a touchpad or touchscreen. Kanata does not pass
+    /// through touch events, so these stop working while they are grabbed.
+    #[cfg(target_os = "linux")]
+    #[arg(long, verbatim_doc_comment)]
+    force: bool,
+
+    /// Measure the latency that kanata adds to key events and periodically
+    /// log its percentiles.
+    #[cfg(target_os = "linux")]
+    #[arg(long, verbatim_doc_comment)]
+    measure_latency: bool,
+
+    /// List the input devices and whether kanata grabs them, then exit.
+    #[cfg(target_os = "linux")]
+    #[arg(long, verbatim_doc_comment)]
+    list_devices: bool,
+
+    /// File descriptor of an input device that is already open, e.g. one
+    /// passed by a container runtime. Can be given multiple times. File
+    /// descriptors passed by systemd (LISTEN_FDS) are used as well. Kanata
+    /// then only uses these devices and does not need access to /dev/input.
+    #[cfg(target_os = "linux")]
+    #[arg(long, verbatim_doc_comment)]
+    device_fd: Vec<i32>,
+
     /// Enable debug logging.
     #[arg(short, long)]
     debug: bool,
@@ -62,16 +146,181 @@ struct Args {
     /// Enable trace logging; implies --debug as well.
     #[arg(short, long)]
     trace: bool,
+
+    /// Format of the log: text for terminals, or json for one JSON object
+    /// per line, e.g. for journald.
+    #[arg(long, value_enum, default_value_t = logging::LogFormat::Text, verbatim_doc_comment)]
+    log_format: logging::LogFormat,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-/// Parse CLI arguments and initialize logging.
-fn cli_init() -> Result<ValidatedArgs> {
-    let args = Args::parse();
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Grab the input devices and create the output device with the
+    /// privileges of this command, usually root, then run kanata in filter
+    /// mode as an unprivileged user and forward the events between them over
+    /// a socket pair. Arguments after `--` are passed to kanata, e.g.
+    /// `kanata helper --uid 1000 --gid 1000 -- --cfg /etc/kanata.kbd`.
+    #[cfg(target_os = "linux")]
+    #[command(verbatim_doc_comment)]
+    Helper {
+        /// User ID to run kanata as.
+        #[arg(long)]
+        uid: u32,
+        /// Group ID to run kanata as.
+        #[arg(long)]
+        gid: u32,
+        /// Input device to grab instead of detecting keyboards. Can be given
+        /// multiple times.
+        #[arg(long, verbatim_doc_comment)]
+        device: Vec<String>,
+        /// Grab devices that have a touchpad or touchscreen.
+        #[arg(long)]
+        force: bool,
+        /// Arguments for kanata.
+        #[arg(last = true)]
+        kanata_args: Vec<String>,
+    },
+    /// Show a live dashboard of a running kanata instance. The instance must
+    /// have been started with the TCP server enabled.
+    #[command(verbatim_doc_comment)]
+    Top {
+        /// Port of the TCP server of the running kanata instance.
+        #[arg(short, long)]
+        port: u16,
+    },
+    /// Show how a key is resolved: the layer whose action wins, the action
+    /// and what it expands to, and the state machines it involves. Uses the
+    /// active layers of a running kanata instance if --port is given, or
+    /// else the given layers of the configuration file.
+    #[command(verbatim_doc_comment)]
+    Explain {
+        /// Name of the key, e.g. a or caps.
+        key: String,
+        /// Configuration file to explain the key for.
+        #[arg(short, long, default_value = "kanata.kbd")]
+        cfg: PathBuf,
+        /// Active layers, starting with the default layer. Defaults to the
+        /// first layer.
+        #[arg(short, long, value_delimiter = ',', verbatim_doc_comment)]
+        layers: Vec<String>,
+        /// Port of the TCP server of a running kanata instance to ask
+        /// instead.
+        #[arg(short, long, verbatim_doc_comment)]
+        port: Option<u16>,
+    },
+    /// Print the configuration as a Karabiner-Elements complex modification
+    /// in JSON. Only a subset of the actions is supported; warnings about the
+    /// skipped actions are printed to stderr.
+    #[command(verbatim_doc_comment)]
+    ExportKarabiner {
+        /// Configuration file to export.
+        #[arg(short, long, default_value = "kanata.kbd")]
+        cfg: PathBuf,
+    },
+    /// Show the keys whose action differs between two configuration files,
+    /// per layer. Aliases, templates and variables are resolved first, so
+    /// only changes in behaviour are listed.
+    #[command(verbatim_doc_comment)]
+    Diff {
+        /// Previous version of the configuration file.
+        old: PathBuf,
+        /// New version of the configuration file.
+        new: PathBuf,
+    },
+    /// List the layers that were never activated and the bindings that were
+    /// never used, according to the statistics that kanata records in the
+    /// `usage-stats-file` of the configuration.
+    #[command(verbatim_doc_comment)]
+    Report {
+        /// Configuration file to report on.
+        #[arg(short, long, default_value = "kanata.kbd")]
+        cfg: PathBuf,
+        /// Usage statistics file written by kanata.
+        #[arg(short, long)]
+        stats: PathBuf,
+    },
+    /// Practice the keys whose output changed since a previous version of
+    /// the configuration. The answers are read from the key outputs of a
+    /// running kanata instance, which must have been started with the new
+    /// configuration and the TCP server enabled.
+    #[command(verbatim_doc_comment)]
+    Train {
+        /// Configuration file that kanata is running with.
+        #[arg(short, long, default_value = "kanata.kbd")]
+        cfg: PathBuf,
+        /// Previous version of the configuration file.
+        #[arg(long)]
+        previous: PathBuf,
+        /// Port of the TCP server of the running kanata instance.
+        #[arg(short, long)]
+        port: u16,
+        /// Number of times each changed key is practiced.
+        #[arg(short, long, default_value_t = 3)]
+        rounds: usize,
+    },
+    /// List the actions that the keys of a running kanata instance trigger
+    /// with its active layers, one per line for rofi or dmenu. With --run,
+    /// read the chosen line from stdin and tap its key, e.g.
+    /// `kanata palette -p 5829 | rofi -dmenu | kanata palette -p 5829 --run`.
+    #[command(verbatim_doc_comment)]
+    Palette {
+        /// Port of the TCP server of the running kanata instance.
+        #[arg(short, long)]
+        port: u16,
+        /// Tap the key of the palette line read from stdin.
+        #[arg(long)]
+        run: bool,
+    },
+    /// Print the active layers and modes of a running kanata instance, as
+    /// JSON or as shell variables for prompts and scripts, e.g.
+    /// `eval "$(kanata status -p 5829 --format env)"`.
+    #[command(verbatim_doc_comment)]
+    Status {
+        /// Port of the TCP server of the running kanata instance.
+        #[arg(short, long)]
+        port: u16,
+        /// Output format.
+        #[arg(long, value_enum, default_value_t = status::StatusFormat::Json)]
+        format: status::StatusFormat,
+    },
+    /// Print the dynamic macros recorded by a running kanata instance and
+    /// the snippets of its configuration as portable JSON, e.g.
+    /// `kanata export-macros -p 5829 > macros.json`.
+    #[command(verbatim_doc_comment)]
+    ExportMacros {
+        /// Port of the TCP server of the running kanata instance.
+        #[arg(short, long)]
+        port: u16,
+    },
+    /// Import a file written by export-macros. With --port, its dynamic
+    /// macros are sent to a running kanata instance. Without it, the dynamic
+    /// macros and snippets are printed as configuration, as `macro` aliases
+    /// and `defsnippets`.
+    #[command(verbatim_doc_comment)]
+    ImportMacros {
+        /// File written by export-macros.
+        file: PathBuf,
+        /// Port of the TCP server of a running kanata instance to send the
+        /// dynamic macros to.
+        #[arg(short, long, verbatim_doc_comment)]
+        port: Option<u16>,
+    },
+    /// Print the version, features and backend of a running kanata instance
+    /// and the hash of its configuration file as JSON, for bug reports.
+    #[command(verbatim_doc_comment)]
+    BuildInfo {
+        /// Port of the TCP server of the running kanata instance.
+        #[arg(short, long)]
+        port: u16,
+    },
+}
 
+/// Validate CLI arguments and initialize logging.
+fn cli_init(args: Args) -> Result<ValidatedArgs> {
     let mut cfg_paths = args.cfg.iter().map(PathBuf::from).collect::<Vec<_>>();
-    if cfg_paths.is_empty() {
-        cfg_paths.push(PathBuf::from("kanata.kbd"));
-    }
 
     let log_lvl = match (args.debug, args.trace) {
         (_, true) => LevelFilter::Trace,
@@ -79,23 +328,39 @@ fn cli_init() -> Result<ValidatedArgs> {
         (false, false) => LevelFilter::Info,
     };
 
-    let mut log_cfg = ConfigBuilder::new();
-    if let Err(e) = log_cfg.set_time_offset_to_local() {
-        eprintln!("WARNING: could not set log TZ to local: {e:?}");
+    // In filter mode, stdout carries the output events.
+    #[cfg(target_os = "linux")]
+    let terminal_mode = match args.filter {
+        true => TerminalMode::Stderr,
+        false => TerminalMode::Mixed,
     };
-    CombinedLogger::init(vec![TermLogger::new(
-        log_lvl,
-        log_cfg.build(),
-        TerminalMode::Mixed,
-        ColorChoice::AlwaysAnsi,
-    )])
-    .expect("logger can init");
+    #[cfg(not(target_os = "linux"))]
+    let terminal_mode = TerminalMode::Mixed;
+    logging::init(log_lvl, args.log_format, terminal_mode);
     log::info!("kanata v{} starting", env!("CARGO_PKG_VERSION"));
     #[cfg(all(not(feature = "interception_driver"), target_os = "windows"))]
     log::info!("using LLHOOK+SendInput for keyboard IO");
     #[cfg(all(feature = "interception_driver", target_os = "windows"))]
     log::info!("using the Interception driver for keyboard IO");
 
+    if cfg_paths.is_empty() {
+        let candidates = cfg::default_cfg_paths();
+        match candidates.iter().find(|p| p.exists()) {
+            Some(p) => {
+                log::info!("using the config file {}", p.display());
+                cfg_paths.push(p.clone());
+            }
+            None => bail!(
+                "Could not find a config file, looked for:\n{}\nFor more info, pass the `-h` or `--help` flags.",
+                candidates
+                    .iter()
+                    .map(|p| format!("  {}", p.display()))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ),
+        }
+    }
+
     if !cfg_paths[0].exists() {
         bail!(
             "Could not find the config file ({})\nFor more info, pass the `-h` or `--help` flags.",
@@ -103,17 +368,51 @@ fn cli_init() -> Result<ValidatedArgs> {
         )
     }
 
+    #[cfg(target_os = "linux")]
+    let device_fds = {
+        let mut fds = args.device_fd;
+        fds.extend(sd_notify::listen_fds()?);
+        fds
+    };
+
     Ok(ValidatedArgs {
         paths: cfg_paths,
+        check: args.check,
+        bench_self: args.bench_self,
         port: args.port,
+        tcp_acl: args
+            .tcp_acl
+            .as_deref()
+            .map(Acl::from_file)
+            .transpose()?,
+        notify_interval: args.notify_interval,
         #[cfg(target_os = "linux")]
         symlink_path: args.symlink_path,
+        #[cfg(target_os = "linux")]
+        filter: args.filter,
+        #[cfg(target_os = "linux")]
+        force: args.force,
+        #[cfg(target_os = "linux")]
+        measure_latency: args.measure_latency,
+        #[cfg(target_os = "linux")]
+        device_fds,
     })
 }
 
-fn main_impl() -> Result<()> {
-    let args = cli_init()?;
+fn main_impl(args: Args) -> Result<()> {
+    let args = cli_init(args)?;
+    if args.check {
+        return check(&args.paths[0]);
+    }
+    if args.bench_self {
+        return bench::run(&args.paths[0]);
+    }
     let kanata_arc = Kanata::new_arc(&args)?;
+    kanata::install_crash_dump_hook();
+    let (tx, rx) = std::sync::mpsc::channel();
+    kanata_arc.lock().set_processing_tx(tx.clone());
+    #[cfg(target_os = "linux")]
+    Kanata::start_signal_handler(tx.clone())?;
 
     info!("Sleeping for 2s. Please release all keys and don't press additional ones.");
     std::thread::sleep(std::time::Duration::from_secs(2));
@@ -125,19 +424,22 @@ fn main_impl() -> Result<()> {
     // while also maintaining `tick()` calls to keyberon.
 
     let (server, ntx, nrx) = if let Some(port) = args.port {
-        let mut server = TcpServer::new(port);
-        server.start(kanata_arc.clone());
+        let mut server = TcpServer::new(port, args.tcp_acl.clone());
         let (ntx, nrx) = std::sync::mpsc::channel();
+        server.start(kanata_arc.clone(), tx.clone(), ntx.clone());
         (Some(server), Some(ntx), Some(nrx))
     } else {
         (None, None, None)
     };
 
-    let (tx, rx) = std::sync::mpsc::channel();
     Kanata::start_processing_loop(kanata_arc.clone(), rx, ntx);
 
     if let (Some(server), Some(nrx)) = (server, nrx) {
-        Kanata::start_notification_loop(nrx, server.connections);
+        Kanata::start_notification_loop(
+            nrx,
+            server,
+            std::time::Duration::from_millis(args.notify_interval),
+        );
     }
 
     #[cfg(target_os = "linux")]
@@ -148,12 +450,98 @@ fn main_impl() -> Result<()> {
     Ok(())
 }
 
+/// Parse the configuration, print the lint warnings and run the tests defined in `deftest`.
+fn check(path: &std::path::Path) -> Result<()> {
+    let cfg = cfg::CfgBuilder::from_file(path)
+        .and_then(|b| b.parse())
+        .and_then(|p| p.resolve())
+        .map_err(|e| anyhow::anyhow!("{e:?}"))?
+        .validate();
+    let warnings = cfg.warnings();
+    for warning in warnings.iter() {
+        println!("{warning}");
+    }
+    info!(
+        "config {} is valid, {} warning(s)",
+        path.display(),
+        warnings.len()
+    );
+    let cfg = cfg.freeze();
+    let mut failed = 0;
+    for test in cfg.tests.iter() {
+        match test.run(&cfg) {
+            Ok(()) => println!("test {} ... ok", test.name),
+            Err(e) => {
+                println!("test {} ... FAILED: {e}", test.name);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("{failed} of {} test(s) failed", cfg.tests.len());
+    }
+    if !cfg.tests.is_empty() {
+        info!("{} test(s) passed", cfg.tests.len());
+    }
+    Ok(())
+}
+
 fn main() -> Result<()> {
-    let ret = main_impl();
+    let args = Args::parse();
+    match args.command {
+        Some(Command::Top { port }) => return top::run(port),
+        Some(Command::ExportKarabiner { cfg }) => return karabiner::run(&cfg),
+        Some(Command::Report { cfg, stats }) => return report::run(&cfg, &stats),
+        Some(Command::Diff { old, new }) => return diff::run(&old, &new),
+        Some(Command::Explain {
+            key,
+            cfg,
+            layers,
+            port,
+        }) => return explain::run(&key, &cfg, &layers, port),
+        Some(Command::Train {
+            cfg,
+            previous,
+            port,
+            rounds,
+        }) => return train::run(&cfg, &previous, port, rounds),
+        Some(Command::Palette { port, run }) => return palette::run(port, run),
+        Some(Command::Status { port, format }) => return status::run(port, format),
+        Some(Command::ExportMacros { port }) => return macros::export(port),
+        Some(Command::ImportMacros { file, port }) => return macros::import(&file, port),
+        Some(Command::BuildInfo { port }) => return build_info::run(port),
+        #[cfg(target_os = "linux")]
+        Some(Command::Helper {
+            uid,
+            gid,
+            device,
+            force,
+            kanata_args,
+        }) => {
+            CombinedLogger::init(vec![TermLogger::new(
+                LevelFilter::Info,
+                Config::default(),
+                TerminalMode::Mixed,
+                ColorChoice::AlwaysAnsi,
+            )])
+            .expect("logger can init");
+            return Ok(oskbd::run_helper(&device, force, uid, gid, &kanata_args)?);
+        }
+        None => {}
+    }
+    #[cfg(target_os = "linux")]
+    if args.list_devices {
+        return Ok(oskbd::list_devices(args.force)?);
+    }
+    let check = args.check;
+    let ret = main_impl(args);
     if let Err(ref e) = ret {
         log::error!("{e}\n");
     }
-    eprintln!("\nPress enter to exit");
-    let _ = std::io::stdin().read_line(&mut String::new());
+    // Checking is meant to be scriptable, so it should not wait for input.
+    if !check {
+        eprintln!("\nPress enter to exit");
+        let _ = std::io::stdin().read_line(&mut String::new());
+    }
     ret
 }
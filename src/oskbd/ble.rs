@@ -0,0 +1,689 @@
+//! Output of key events as a Bluetooth LE keyboard, through BlueZ.
+//!
+//! Kanata registers a GATT application with the HID over GATT service and an advertisement with
+//! BlueZ, so that another machine, e.g. a tablet or a second laptop, can pair with this host like
+//! with a Bluetooth keyboard. The output key events are then sent to it as boot keyboard reports.
+//! The objects are served on the system bus with `zbus`, whose object manager answers BlueZ's
+//! `GetManagedObjects` from their properties. Pairing is confirmed by the Bluetooth agent of the
+//! host, e.g. the one of the desktop or `bluetoothctl`.
+//!
+//! Only key events are output, and only the keys that have a HID usage. A report has room for six
+//! keys besides the modifiers; while six are held, further presses are dropped. The receiving
+//! machine repeats held keys itself, so repeat events are not sent.
+
+use evdev::{EventType, InputEvent, Key};
+use parking_lot::Mutex;
+use zbus::blocking::{connection, Connection};
+use zbus::zvariant::{ObjectPath, OwnedValue, Value};
+use zbus::{fdo, interface, DBusError};
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+
+use super::{OutputCapabilities, OutputSink};
+
+const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+const BLUEZ_NAME: &str = "org.bluez";
+const CHARACTERISTIC_INTERFACE: &str = "org.bluez.GattCharacteristic1";
+
+const APP_PATH: &str = "/org/kanata/ble";
+const ADVERTISEMENT_PATH: &str = "/org/kanata/ble/advertisement";
+const HID_SERVICE_PATH: &str = "/org/kanata/ble/hid";
+const HID_INFORMATION_PATH: &str = "/org/kanata/ble/hid/information";
+const REPORT_MAP_PATH: &str = "/org/kanata/ble/hid/report_map";
+const CONTROL_POINT_PATH: &str = "/org/kanata/ble/hid/control_point";
+const PROTOCOL_MODE_PATH: &str = "/org/kanata/ble/hid/protocol_mode";
+const REPORT_PATH: &str = "/org/kanata/ble/hid/report";
+const REPORT_REFERENCE_PATH: &str = "/org/kanata/ble/hid/report/reference";
+const DEVICE_INFORMATION_PATH: &str = "/org/kanata/ble/device_information";
+const PNP_ID_PATH: &str = "/org/kanata/ble/device_information/pnp_id";
+
+const HID_SERVICE_UUID: u16 = 0x1812;
+/// The GAP appearance of a keyboard.
+const KEYBOARD_APPEARANCE: u16 = 0x03C1;
+
+/// A boot keyboard with report ID 1: a byte of modifiers, a reserved byte and six keys.
+const REPORT_MAP: &[u8] = &[
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x06, // Usage (Keyboard)
+    0xA1, 0x01, // Collection (Application)
+    0x85, 0x01, //   Report ID (1)
+    0x05, 0x07, //   Usage Page (Keyboard)
+    0x19, 0xE0, //   Usage Minimum (Left Control)
+    0x29, 0xE7, //   Usage Maximum (Right GUI)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x01, //   Logical Maximum (1)
+    0x75, 0x01, //   Report Size (1)
+    0x95, 0x08, //   Report Count (8)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x01, //   Report Count (1)
+    0x81, 0x01, //   Input (Constant)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x06, //   Report Count (6)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x26, 0xFF, 0x00, //   Logical Maximum (255)
+    0x19, 0x00, //   Usage Minimum (0)
+    0x2A, 0xFF, 0x00, //   Usage Maximum (255)
+    0x81, 0x00, //   Input (Data, Array)
+    0xC0, // End Collection
+];
+/// HID version 1.11, no country code, normally connectable.
+const HID_INFORMATION: &[u8] = &[0x11, 0x01, 0x00, 0x02];
+const REPORT_ID: u8 = 1;
+const INPUT_REPORT: u8 = 1;
+const REPORT_PROTOCOL_MODE: u8 = 1;
+
+/// An object of the GATT application.
+struct GattObject {
+    path: &'static str,
+    uuid: u16,
+    kind: GattKind,
+}
+
+enum GattKind {
+    Service,
+    Characteristic {
+        service: &'static str,
+        flags: &'static [&'static str],
+    },
+    Descriptor {
+        characteristic: &'static str,
+        flags: &'static [&'static str],
+    },
+}
+
+const GATT_OBJECTS: &[GattObject] = &[
+    GattObject {
+        path: HID_SERVICE_PATH,
+        uuid: HID_SERVICE_UUID,
+        kind: GattKind::Service,
+    },
+    GattObject {
+        path: HID_INFORMATION_PATH,
+        uuid: 0x2A4A,
+        kind: GattKind::Characteristic {
+            service: HID_SERVICE_PATH,
+            flags: &["read"],
+        },
+    },
+    GattObject {
+        path: REPORT_MAP_PATH,
+        uuid: 0x2A4B,
+        kind: GattKind::Characteristic {
+            service: HID_SERVICE_PATH,
+            flags: &["read", "encrypt-read"],
+        },
+    },
+    GattObject {
+        path: CONTROL_POINT_PATH,
+        uuid: 0x2A4C,
+        kind: GattKind::Characteristic {
+            service: HID_SERVICE_PATH,
+            flags: &["write-without-response"],
+        },
+    },
+    GattObject {
+        path: PROTOCOL_MODE_PATH,
+        uuid: 0x2A4E,
+        kind: GattKind::Characteristic {
+            service: HID_SERVICE_PATH,
+            flags: &["read", "write-without-response"],
+        },
+    },
+    GattObject {
+        path: REPORT_PATH,
+        uuid: 0x2A4D,
+        kind: GattKind::Characteristic {
+            service: HID_SERVICE_PATH,
+            flags: &["read", "notify", "encrypt-read"],
+        },
+    },
+    GattObject {
+        path: REPORT_REFERENCE_PATH,
+        uuid: 0x2908,
+        kind: GattKind::Descriptor {
+            characteristic: REPORT_PATH,
+            flags: &["read"],
+        },
+    },
+    GattObject {
+        path: DEVICE_INFORMATION_PATH,
+        uuid: 0x180A,
+        kind: GattKind::Service,
+    },
+    GattObject {
+        path: PNP_ID_PATH,
+        uuid: 0x2A50,
+        kind: GattKind::Characteristic {
+            service: DEVICE_INFORMATION_PATH,
+            flags: &["read"],
+        },
+    },
+];
+
+/// The identity of the keyboard that other machines see.
+#[derive(Debug, Clone)]
+struct Identity {
+    name: String,
+    vendor_id: u16,
+    product_id: u16,
+}
+
+/// What the output and the method calls of BlueZ share.
+#[derive(Debug, Default)]
+struct KeyboardState {
+    report: [u8; 8],
+    /// Whether a connected machine subscribed to the reports.
+    notifying: bool,
+}
+
+/// A keyboard that other machines connect to over Bluetooth LE. BlueZ removes the service and
+/// the advertisement when the connection to the bus is dropped.
+pub struct BleKeyboard {
+    conn: Connection,
+    state: Arc<Mutex<KeyboardState>>,
+}
+
+impl BleKeyboard {
+    /// Register the keyboard with BlueZ on the adapter, e.g. `hci0`, and start advertising it.
+    pub fn connect(
+        adapter: &str,
+        name: &str,
+        vendor_id: u16,
+        product_id: u16,
+    ) -> Result<Self, io::Error> {
+        let identity = Arc::new(Identity {
+            name: name.to_owned(),
+            vendor_id,
+            product_id,
+        });
+        let state = Arc::new(Mutex::new(KeyboardState::default()));
+        let mut builder = connection::Builder::system()
+            .and_then(|b| b.serve_at(APP_PATH, fdo::ObjectManager))
+            .and_then(|b| {
+                b.serve_at(
+                    ADVERTISEMENT_PATH,
+                    Advertisement {
+                        identity: identity.clone(),
+                    },
+                )
+            })
+            .map_err(io::Error::other)?;
+        for object in GATT_OBJECTS {
+            let value = |parent: &'static str, flags: &'static [&'static str]| GattValue {
+                path: object.path,
+                uuid: object.uuid,
+                parent,
+                flags,
+                state: state.clone(),
+                identity: identity.clone(),
+            };
+            builder = match object.kind {
+                GattKind::Service => builder.serve_at(object.path, Service { uuid: object.uuid }),
+                GattKind::Characteristic { service, flags } => {
+                    builder.serve_at(object.path, Characteristic(value(service, flags)))
+                }
+                GattKind::Descriptor {
+                    characteristic,
+                    flags,
+                } => builder.serve_at(object.path, Descriptor(value(characteristic, flags))),
+            }
+            .map_err(io::Error::other)?;
+        }
+        let conn = builder
+            .build()
+            .map_err(|e| io::Error::other(format!("failed to connect to the system bus: {e}")))?;
+
+        let adapter_path = format!("/org/bluez/{adapter}");
+        register(
+            &conn,
+            &adapter_path,
+            "org.bluez.GattManager1.RegisterApplication",
+            APP_PATH,
+        )
+        .map_err(|e| io::Error::other(format!("failed to register the HID service: {e}")))?;
+        register(
+            &conn,
+            &adapter_path,
+            "org.bluez.LEAdvertisingManager1.RegisterAdvertisement",
+            ADVERTISEMENT_PATH,
+        )
+        .map_err(|e| io::Error::other(format!("failed to advertise the keyboard: {e}")))?;
+        log::info!("Advertising the Bluetooth LE keyboard {name} on {adapter}");
+        Ok(Self { conn, state })
+    }
+}
+
+impl OutputSink for BleKeyboard {
+    fn emit(&mut self, events: &[InputEvent]) -> Result<(), io::Error> {
+        let mut state = self.state.lock();
+        let mut changed = false;
+        for event in events {
+            if event.event_type() == EventType::KEY && event.value() <= 1 {
+                changed |= update_report(&mut state.report, event.code(), event.value() == 1);
+            }
+        }
+        if changed && state.notifying {
+            // The report reaches the subscribed machine as a change of the value of the report
+            // characteristic.
+            let changed = HashMap::from([("Value", Value::from(state.report.to_vec()))]);
+            self.conn
+                .emit_signal(
+                    None::<&str>,
+                    REPORT_PATH,
+                    PROPERTIES_INTERFACE,
+                    "PropertiesChanged",
+                    &(CHARACTERISTIC_INTERFACE, changed, Vec::<&str>::new()),
+                )
+                .map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+
+    fn capabilities(&self) -> OutputCapabilities {
+        // A boot keyboard only has keys.
+        OutputCapabilities::default()
+    }
+}
+
+/// Call a registration method of the adapter, e.g. `org.bluez.GattManager1.RegisterApplication`,
+/// with the object at `path` and no options.
+fn register(conn: &Connection, adapter_path: &str, method: &str, path: &str) -> zbus::Result<()> {
+    let (interface, member) = method.rsplit_once('.').expect("interface.member");
+    let options = HashMap::<&str, Value>::new();
+    conn.call_method(
+        Some(BLUEZ_NAME),
+        adapter_path,
+        Some(interface),
+        member,
+        &(ObjectPath::try_from(path)?, options),
+    )?;
+    Ok(())
+}
+
+/// The errors of the method calls of BlueZ.
+#[derive(Debug, DBusError)]
+#[zbus(prefix = "org.bluez.Error")]
+enum BluezError {
+    #[zbus(error)]
+    ZBus(zbus::Error),
+    NotSupported(String),
+}
+
+struct Service {
+    uuid: u16,
+}
+
+#[interface(name = "org.bluez.GattService1")]
+impl Service {
+    #[zbus(property, name = "UUID")]
+    fn uuid(&self) -> String {
+        full_uuid(self.uuid)
+    }
+
+    #[zbus(property)]
+    fn primary(&self) -> bool {
+        true
+    }
+}
+
+/// A characteristic or a descriptor, whose value BlueZ reads.
+struct GattValue {
+    path: &'static str,
+    uuid: u16,
+    /// The service of a characteristic or the characteristic of a descriptor.
+    parent: &'static str,
+    flags: &'static [&'static str],
+    state: Arc<Mutex<KeyboardState>>,
+    identity: Arc<Identity>,
+}
+
+impl GattValue {
+    fn read(&self) -> Result<Vec<u8>, BluezError> {
+        let report = self.state.lock().report;
+        read_value(self.path, report, &self.identity)
+            .ok_or_else(|| BluezError::NotSupported("not readable".into()))
+    }
+
+    fn flags(&self) -> Vec<String> {
+        self.flags.iter().map(|flag| (*flag).to_owned()).collect()
+    }
+
+    /// Subscribe to the reports or unsubscribe from them.
+    fn notify(&self, notifying: bool) -> Result<(), BluezError> {
+        if self.path != REPORT_PATH {
+            return Err(BluezError::NotSupported("no notifications".into()));
+        }
+        match notifying {
+            true => log::info!("Bluetooth LE keyboard: a machine subscribed to the key reports"),
+            false => {
+                log::info!("Bluetooth LE keyboard: a machine unsubscribed from the key reports")
+            }
+        }
+        self.state.lock().notifying = notifying;
+        Ok(())
+    }
+}
+
+struct Characteristic(GattValue);
+
+#[interface(name = "org.bluez.GattCharacteristic1")]
+impl Characteristic {
+    fn read_value(&self, _options: HashMap<String, OwnedValue>) -> Result<Vec<u8>, BluezError> {
+        self.0.read()
+    }
+
+    /// Only the protocol mode and the control point are writable, and the keyboard works the
+    /// same in every mode.
+    fn write_value(&self, _value: Vec<u8>, _options: HashMap<String, OwnedValue>) {}
+
+    fn start_notify(&self) -> Result<(), BluezError> {
+        self.0.notify(true)
+    }
+
+    fn stop_notify(&self) -> Result<(), BluezError> {
+        self.0.notify(false)
+    }
+
+    #[zbus(property, name = "UUID")]
+    fn uuid(&self) -> String {
+        full_uuid(self.0.uuid)
+    }
+
+    #[zbus(property)]
+    fn service(&self) -> ObjectPath<'static> {
+        ObjectPath::from_static_str_unchecked(self.0.parent)
+    }
+
+    #[zbus(property)]
+    fn flags(&self) -> Vec<String> {
+        self.0.flags()
+    }
+}
+
+struct Descriptor(GattValue);
+
+#[interface(name = "org.bluez.GattDescriptor1")]
+impl Descriptor {
+    fn read_value(&self, _options: HashMap<String, OwnedValue>) -> Result<Vec<u8>, BluezError> {
+        self.0.read()
+    }
+
+    #[zbus(property, name = "UUID")]
+    fn uuid(&self) -> String {
+        full_uuid(self.0.uuid)
+    }
+
+    #[zbus(property)]
+    fn characteristic(&self) -> ObjectPath<'static> {
+        ObjectPath::from_static_str_unchecked(self.0.parent)
+    }
+
+    #[zbus(property)]
+    fn flags(&self) -> Vec<String> {
+        self.0.flags()
+    }
+}
+
+struct Advertisement {
+    identity: Arc<Identity>,
+}
+
+#[interface(name = "org.bluez.LEAdvertisement1")]
+impl Advertisement {
+    fn release(&self) {
+        log::warn!("Bluetooth LE keyboard: BlueZ stopped the advertisement");
+    }
+
+    #[zbus(property, name = "Type")]
+    fn kind(&self) -> String {
+        "peripheral".into()
+    }
+
+    #[zbus(property, name = "ServiceUUIDs")]
+    fn service_uuids(&self) -> Vec<String> {
+        vec![full_uuid(HID_SERVICE_UUID)]
+    }
+
+    #[zbus(property)]
+    fn local_name(&self) -> String {
+        self.identity.name.clone()
+    }
+
+    #[zbus(property)]
+    fn appearance(&self) -> u16 {
+        KEYBOARD_APPEARANCE
+    }
+
+    #[zbus(property)]
+    fn discoverable(&self) -> bool {
+        true
+    }
+}
+
+fn read_value(path: &str, report: [u8; 8], identity: &Identity) -> Option<Vec<u8>> {
+    Some(match path {
+        HID_INFORMATION_PATH => HID_INFORMATION.to_vec(),
+        REPORT_MAP_PATH => REPORT_MAP.to_vec(),
+        PROTOCOL_MODE_PATH => vec![REPORT_PROTOCOL_MODE],
+        REPORT_PATH => report.to_vec(),
+        REPORT_REFERENCE_PATH => vec![REPORT_ID, INPUT_REPORT],
+        PNP_ID_PATH => {
+            // The IDs are USB IDs, and the product version is 1.0.
+            let [vendor_lo, vendor_hi] = identity.vendor_id.to_le_bytes();
+            let [product_lo, product_hi] = identity.product_id.to_le_bytes();
+            vec![
+                0x02, vendor_lo, vendor_hi, product_lo, product_hi, 0x00, 0x01,
+            ]
+        }
+        _ => return None,
+    })
+}
+
+/// The 128-bit form of a 16-bit Bluetooth SIG UUID.
+fn full_uuid(uuid: u16) -> String {
+    format!("0000{uuid:04x}-0000-1000-8000-00805f9b34fb")
+}
+
+/// Press or release the key in the report. Returns whether the report changed.
+fn update_report(report: &mut [u8; 8], code: u16, pressed: bool) -> bool {
+    let Some(usage) = hid_usage(code) else {
+        return false;
+    };
+    let before = *report;
+    if (0xE0..=0xE7).contains(&usage) {
+        let bit = 1 << (usage - 0xE0);
+        match pressed {
+            true => report[0] |= bit,
+            false => report[0] &= !bit,
+        }
+    } else if pressed {
+        if !report[2..].contains(&usage) {
+            if let Some(slot) = report[2..].iter_mut().find(|k| **k == 0) {
+                *slot = usage;
+            }
+        }
+    } else if let Some(slot) = report[2..].iter_mut().find(|k| **k == usage) {
+        *slot = 0;
+    }
+    *report != before
+}
+
+/// Returns the usage on the HID keyboard page for an evdev key code.
+fn hid_usage(code: u16) -> Option<u8> {
+    Some(match Key(code) {
+        Key::KEY_A => 0x04,
+        Key::KEY_B => 0x05,
+        Key::KEY_C => 0x06,
+        Key::KEY_D => 0x07,
+        Key::KEY_E => 0x08,
+        Key::KEY_F => 0x09,
+        Key::KEY_G => 0x0A,
+        Key::KEY_H => 0x0B,
+        Key::KEY_I => 0x0C,
+        Key::KEY_J => 0x0D,
+        Key::KEY_K => 0x0E,
+        Key::KEY_L => 0x0F,
+        Key::KEY_M => 0x10,
+        Key::KEY_N => 0x11,
+        Key::KEY_O => 0x12,
+        Key::KEY_P => 0x13,
+        Key::KEY_Q => 0x14,
+        Key::KEY_R => 0x15,
+        Key::KEY_S => 0x16,
+        Key::KEY_T => 0x17,
+        Key::KEY_U => 0x18,
+        Key::KEY_V => 0x19,
+        Key::KEY_W => 0x1A,
+        Key::KEY_X => 0x1B,
+        Key::KEY_Y => 0x1C,
+        Key::KEY_Z => 0x1D,
+        Key::KEY_1 => 0x1E,
+        Key::KEY_2 => 0x1F,
+        Key::KEY_3 => 0x20,
+        Key::KEY_4 => 0x21,
+        Key::KEY_5 => 0x22,
+        Key::KEY_6 => 0x23,
+        Key::KEY_7 => 0x24,
+        Key::KEY_8 => 0x25,
+        Key::KEY_9 => 0x26,
+        Key::KEY_0 => 0x27,
+        Key::KEY_ENTER => 0x28,
+        Key::KEY_ESC => 0x29,
+        Key::KEY_BACKSPACE => 0x2A,
+        Key::KEY_TAB => 0x2B,
+        Key::KEY_SPACE => 0x2C,
+        Key::KEY_MINUS => 0x2D,
+        Key::KEY_EQUAL => 0x2E,
+        Key::KEY_LEFTBRACE => 0x2F,
+        Key::KEY_RIGHTBRACE => 0x30,
+        Key::KEY_BACKSLASH => 0x31,
+        Key::KEY_SEMICOLON => 0x33,
+        Key::KEY_APOSTROPHE => 0x34,
+        Key::KEY_GRAVE => 0x35,
+        Key::KEY_COMMA => 0x36,
+        Key::KEY_DOT => 0x37,
+        Key::KEY_SLASH => 0x38,
+        Key::KEY_CAPSLOCK => 0x39,
+        Key::KEY_F1 => 0x3A,
+        Key::KEY_F2 => 0x3B,
+        Key::KEY_F3 => 0x3C,
+        Key::KEY_F4 => 0x3D,
+        Key::KEY_F5 => 0x3E,
+        Key::KEY_F6 => 0x3F,
+        Key::KEY_F7 => 0x40,
+        Key::KEY_F8 => 0x41,
+        Key::KEY_F9 => 0x42,
+        Key::KEY_F10 => 0x43,
+        Key::KEY_F11 => 0x44,
+        Key::KEY_F12 => 0x45,
+        Key::KEY_SYSRQ => 0x46,
+        Key::KEY_SCROLLLOCK => 0x47,
+        Key::KEY_PAUSE => 0x48,
+        Key::KEY_INSERT => 0x49,
+        Key::KEY_HOME => 0x4A,
+        Key::KEY_PAGEUP => 0x4B,
+        Key::KEY_DELETE => 0x4C,
+        Key::KEY_END => 0x4D,
+        Key::KEY_PAGEDOWN => 0x4E,
+        Key::KEY_RIGHT => 0x4F,
+        Key::KEY_LEFT => 0x50,
+        Key::KEY_DOWN => 0x51,
+        Key::KEY_UP => 0x52,
+        Key::KEY_NUMLOCK => 0x53,
+        Key::KEY_KPSLASH => 0x54,
+        Key::KEY_KPASTERISK => 0x55,
+        Key::KEY_KPMINUS => 0x56,
+        Key::KEY_KPPLUS => 0x57,
+        Key::KEY_KPENTER => 0x58,
+        Key::KEY_KP1 => 0x59,
+        Key::KEY_KP2 => 0x5A,
+        Key::KEY_KP3 => 0x5B,
+        Key::KEY_KP4 => 0x5C,
+        Key::KEY_KP5 => 0x5D,
+        Key::KEY_KP6 => 0x5E,
+        Key::KEY_KP7 => 0x5F,
+        Key::KEY_KP8 => 0x60,
+        Key::KEY_KP9 => 0x61,
+        Key::KEY_KP0 => 0x62,
+        Key::KEY_KPDOT => 0x63,
+        Key::KEY_102ND => 0x64,
+        Key::KEY_COMPOSE => 0x65,
+        Key::KEY_POWER => 0x66,
+        Key::KEY_KPEQUAL => 0x67,
+        Key::KEY_F13 => 0x68,
+        Key::KEY_F14 => 0x69,
+        Key::KEY_F15 => 0x6A,
+        Key::KEY_F16 => 0x6B,
+        Key::KEY_F17 => 0x6C,
+        Key::KEY_F18 => 0x6D,
+        Key::KEY_F19 => 0x6E,
+        Key::KEY_F20 => 0x6F,
+        Key::KEY_F21 => 0x70,
+        Key::KEY_F22 => 0x71,
+        Key::KEY_F23 => 0x72,
+        Key::KEY_F24 => 0x73,
+        Key::KEY_KPCOMMA => 0x85,
+        Key::KEY_RO => 0x87,
+        Key::KEY_KATAKANAHIRAGANA => 0x88,
+        Key::KEY_YEN => 0x89,
+        Key::KEY_HENKAN => 0x8A,
+        Key::KEY_MUHENKAN => 0x8B,
+        Key::KEY_HANGEUL => 0x90,
+        Key::KEY_HANJA => 0x91,
+        Key::KEY_LEFTCTRL => 0xE0,
+        Key::KEY_LEFTSHIFT => 0xE1,
+        Key::KEY_LEFTALT => 0xE2,
+        Key::KEY_LEFTMETA => 0xE3,
+        Key::KEY_RIGHTCTRL => 0xE4,
+        Key::KEY_RIGHTSHIFT => 0xE5,
+        Key::KEY_RIGHTALT => 0xE6,
+        Key::KEY_RIGHTMETA => 0xE7,
+        _ => return None,
+    })
+}
+
+#[test]
+fn ble_gatt_values_are_readable() {
+    let identity = Identity {
+        name: "kanata".into(),
+        vendor_id: 0x1234,
+        product_id: 0xABCD,
+    };
+    for object in GATT_OBJECTS {
+        let flags = match object.kind {
+            GattKind::Service => continue,
+            GattKind::Characteristic { flags, .. } | GattKind::Descriptor { flags, .. } => flags,
+        };
+        assert_eq!(
+            read_value(object.path, [0; 8], &identity).is_some(),
+            flags.contains(&"read"),
+            "{}",
+            object.path
+        );
+    }
+    assert_eq!(
+        read_value(PNP_ID_PATH, [0; 8], &identity),
+        Some(vec![0x02, 0x34, 0x12, 0xCD, 0xAB, 0x00, 0x01])
+    );
+}
+
+#[test]
+fn key_reports_hold_modifiers_and_six_keys() {
+    let mut report = [0; 8];
+    assert!(update_report(&mut report, Key::KEY_LEFTSHIFT.code(), true));
+    assert!(update_report(&mut report, Key::KEY_A.code(), true));
+    assert_eq!(report, [0x02, 0, 0x04, 0, 0, 0, 0, 0]);
+    // Unchanged by a second press and keys without a usage.
+    assert!(!update_report(&mut report, Key::KEY_A.code(), true));
+    assert!(!update_report(&mut report, Key::KEY_PROG1.code(), true));
+    for key in [Key::KEY_B, Key::KEY_C, Key::KEY_D, Key::KEY_E, Key::KEY_F] {
+        update_report(&mut report, key.code(), true);
+    }
+    assert!(!update_report(&mut report, Key::KEY_G.code(), true));
+    assert!(update_report(&mut report, Key::KEY_A.code(), false));
+    assert!(update_report(&mut report, Key::KEY_LEFTSHIFT.code(), false));
+    assert_eq!(report, [0, 0, 0, 0x05, 0x06, 0x07, 0x08, 0x09]);
+}
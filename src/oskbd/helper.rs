@@ -0,0 +1,124 @@
+//! `kanata helper`: a privileged helper that only does device IO.
+//!
+//! Grabbing input devices and creating the uinput device usually requires root, but parsing the
+//! configuration and running actions does not. The helper grabs the devices and creates the
+//! output device, then starts kanata in filter mode as an unprivileged user with one end of a
+//! socket pair as its stdin and stdout. Both directions use the framing of filter mode: a stream
+//! of raw `input_event` structs. Input events are forwarded to kanata as they are read, and the
+//! events kanata outputs are emitted in batches that end with `SYN_REPORT`.
+
+use evdev::{EventType, InputEvent, Synchronization};
+
+use std::io::{self, Read, Write};
+use std::os::fd::OwnedFd;
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+
+use super::linux::{create_uinput_device, input_event_bytes, take_input_events};
+use super::{KbdIn, OutputDeviceCfg};
+
+/// Grab the devices, run kanata with `kanata_args` as `uid` and `gid`, and forward events until
+/// kanata exits.
+pub fn run_helper(
+    dev_paths: &[String],
+    force: bool,
+    uid: u32,
+    gid: u32,
+    kanata_args: &[String],
+) -> Result<(), io::Error> {
+    if uid == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the helper must run kanata as an unprivileged user, not root",
+        ));
+    }
+    let mut kbd_in = KbdIn::new(dev_paths, &[], false, false, force, true)?;
+    let (mut output, _) = create_uinput_device(&OutputDeviceCfg::default())?;
+
+    let (mut helper_end, kanata_end) = UnixStream::pair()?;
+    log::info!("starting kanata as uid {uid}, gid {gid}");
+    // Dropping to the user also drops the supplementary groups of root.
+    let mut kanata = Command::new(std::env::current_exe()?)
+        .arg("--filter")
+        .args(kanata_args)
+        .stdin(Stdio::from(OwnedFd::from(kanata_end.try_clone()?)))
+        .stdout(Stdio::from(OwnedFd::from(kanata_end)))
+        .uid(uid)
+        .gid(gid)
+        .spawn()?;
+
+    let mut reader = helper_end.try_clone()?;
+    std::thread::spawn(move || {
+        let mut buf = vec![];
+        let mut chunk = [0u8; 1024];
+        let mut events = vec![];
+        let mut batch = OutputBatch::default();
+        loop {
+            match reader.read(&mut chunk) {
+                Ok(0) | Err(_) => return,
+                Ok(n) => buf.extend_from_slice(&chunk[..n]),
+            }
+            take_input_events(&mut buf, &mut events);
+            for event in events.drain(..) {
+                if let Some(events) = batch.push(event) {
+                    if let Err(e) = output.emit(&events) {
+                        log::error!("failed to emit events from kanata: {e}");
+                    }
+                }
+            }
+        }
+    });
+
+    let mut events = vec![];
+    loop {
+        kbd_in.read(&mut events)?;
+        let bytes = events
+            .iter()
+            .flat_map(|event| input_event_bytes(event).iter().copied())
+            .collect::<Vec<_>>();
+        if let Err(e) = helper_end.write_all(&bytes) {
+            log::info!("kanata stopped reading events: {e}");
+            break;
+        }
+    }
+    let status = kanata.wait()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(format!("kanata exited with {status}"))),
+    }
+}
+
+/// Collects the events output by kanata until a `SYN_REPORT`, since the uinput device adds its
+/// own `SYN_REPORT` to every emitted batch.
+#[derive(Default)]
+struct OutputBatch {
+    events: Vec<InputEvent>,
+}
+
+impl OutputBatch {
+    fn push(&mut self, event: InputEvent) -> Option<Vec<InputEvent>> {
+        if event.event_type() == EventType::SYNCHRONIZATION
+            && event.code() == Synchronization::SYN_REPORT.0
+        {
+            return Some(std::mem::take(&mut self.events));
+        }
+        self.events.push(event);
+        None
+    }
+}
+
+#[test]
+fn output_batches_end_at_syn_report() {
+    let key = |value| InputEvent::new(EventType::KEY, 30, value);
+    let syn = InputEvent::new(EventType::SYNCHRONIZATION, 0, 0);
+    let mut batch = OutputBatch::default();
+    assert!(batch.push(key(1)).is_none());
+    assert!(batch.push(key(0)).is_none());
+    let events = batch.push(syn).expect("batch ends");
+    assert_eq!(
+        events.iter().map(|ev| ev.value()).collect::<Vec<_>>(),
+        [1, 0]
+    );
+    assert_eq!(batch.push(syn).expect("empty batch").len(), 0);
+}
@@ -1,52 +1,136 @@
 //! Contains the input/output code for keyboards on Linux.
 
-use evdev::{uinput, Device, EventType, InputEvent, RelativeAxisType};
+use evdev::{uinput, AbsoluteAxisType, Device, EventType, InputEvent, MiscType, RelativeAxisType};
 use inotify::{Inotify, WatchMask};
-use mio::{unix::SourceFd, Events, Interest, Poll, Token};
+use mio::{unix::SourceFd, Events, Interest, Poll, Token, Waker};
 use nix::ioctl_read_buf;
 use rustc_hash::FxHashMap as HashMap;
-use signal_hook::{
-    consts::{SIGINT, SIGTERM},
-    iterator::Signals,
-};
 
 use std::fs;
-use std::io;
-use std::os::unix::io::AsRawFd;
-use std::path::PathBuf;
-use std::thread;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
 
+use super::{OutputModifiers, ResumeDetector, SessionWatcher};
 use crate::custom_action::*;
 use crate::keys::KeyEvent;
 use crate::keys::*;
 
 pub struct KbdIn {
     devices: HashMap<Token, (Device, String)>,
+    /// Whether devices that only have media keys, e.g. consumer control devices, are grabbed.
+    include_media_devices: bool,
+    /// Whether devices with a touch surface are grabbed, see [`has_touch_surface`].
+    force: bool,
+    /// Whether the devices are grabbed at all. If not, their events also reach the system.
+    grab: bool,
     /// Some(_) if devices are explicitly listed, otherwise None.
     missing_device_paths: Option<Vec<String>>,
     poll: Poll,
     events: Events,
     token_counter: usize,
-    /// stored to prevent dropping
-    _inotify: Inotify,
+    /// stored to prevent dropping, None if the devices were passed as file descriptors
+    _inotify: Option<Inotify>,
+    /// The logind session whose devices are released while it is inactive.
+    session: Option<SessionWatcher>,
+    resume: ResumeDetector,
+    /// Requests to release or grab individual devices, see [`DeviceGrabControl`].
+    grab_requests: Receiver<DeviceGrabRequest>,
+    grab_control: DeviceGrabControl,
+    /// Paths of the devices that were released on request. These stay released until they are
+    /// requested to be grabbed again, including when they are unplugged and plugged in again.
+    released_paths: Vec<String>,
+    /// Names of the devices whose events are reported by [`KbdIn::take_activity`].
+    activity_devices: Vec<String>,
+    activity: bool,
+    /// Whether devices were registered or removed since the last [`KbdIn::take_devices_changed`].
+    devices_changed: bool,
 }
 
 const INOTIFY_TOKEN_VALUE: usize = 0;
 const INOTIFY_TOKEN: Token = Token(INOTIFY_TOKEN_VALUE);
+const SESSION_TOKEN_VALUE: usize = 1;
+const SESSION_TOKEN: Token = Token(SESSION_TOKEN_VALUE);
+const GRAB_TOKEN_VALUE: usize = 2;
+const GRAB_TOKEN: Token = Token(GRAB_TOKEN_VALUE);
+
+/// A request to release or grab the devices with the given name or path.
+struct DeviceGrabRequest {
+    device: String,
+    grab: bool,
+    reply: Sender<Result<Vec<String>, String>>,
+}
+
+/// Releases and grabs individual devices from other threads while the event loop is running.
+#[derive(Clone)]
+pub struct DeviceGrabControl {
+    requests: Sender<DeviceGrabRequest>,
+    waker: Arc<Waker>,
+}
+
+impl DeviceGrabControl {
+    /// Release or grab the devices whose name or path is `device`. Blocks until the event loop
+    /// has handled the request and returns the paths of the devices whose grab changed.
+    pub fn set_grabbed(&self, device: &str, grab: bool) -> Result<Vec<String>, String> {
+        let (reply, reply_rx) = std::sync::mpsc::channel();
+        self.requests
+            .send(DeviceGrabRequest {
+                device: device.to_owned(),
+                grab,
+                reply,
+            })
+            .map_err(|_| "the event loop has stopped".to_owned())?;
+        self.waker.wake().map_err(|e| e.to_string())?;
+        reply_rx
+            .recv()
+            .map_err(|_| "the event loop has stopped".to_owned())?
+    }
+}
 
 impl KbdIn {
-    pub fn new(dev_paths: &[String], continue_if_no_devices: bool) -> Result<Self, io::Error> {
+    /// Open the devices of `device_fds` if there are any, otherwise those of `dev_paths`, or
+    /// discover the keyboards in `/dev/input` if both are empty. Devices passed as file
+    /// descriptors are never rediscovered, since `/dev/input` may not be accessible.
+    pub fn new(
+        dev_paths: &[String],
+        device_fds: &[RawFd],
+        continue_if_no_devices: bool,
+        include_media_devices: bool,
+        force: bool,
+        grab: bool,
+    ) -> Result<Self, io::Error> {
         let poll = Poll::new()?;
+        // Touchpads and touchscreens keep working if their device is not grabbed.
+        let force = force || !grab;
 
         let mut missing_device_paths = None;
-        let devices = if !dev_paths.is_empty() {
+        let devices = if !dev_paths.is_empty() || !device_fds.is_empty() {
             missing_device_paths = Some(vec![]);
-            devices_from_input_paths(
-                dev_paths,
-                missing_device_paths.as_mut().expect("initialized"),
-            )
+            let devices = match device_fds {
+                [] => devices_from_input_paths(
+                    dev_paths,
+                    missing_device_paths.as_mut().expect("initialized"),
+                ),
+                fds => devices_from_fds(fds)?,
+            };
+            if let Some((device, path)) = devices
+                .iter()
+                .find(|(device, _)| !force && has_touch_surface(device))
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{path} ({}) has a touchpad or touchscreen, which stops working while \
+                         kanata grabs the device. Pass --force to grab it anyway.",
+                        device.name().unwrap_or("unknown device name")
+                    ),
+                ));
+            }
+            devices
         } else {
-            discover_devices()?
+            discover_devices(include_media_devices, force)?
         };
         if devices.is_empty() {
             if continue_if_no_devices {
@@ -58,15 +142,27 @@ impl KbdIn {
                 ));
             }
         }
-        let _inotify = watch_devinput().map_err(|e| {
-            log::error!("failed to watch files: {e:?}");
-            e
-        })?;
-        poll.registry().register(
-            &mut SourceFd(&_inotify.as_raw_fd()),
-            INOTIFY_TOKEN,
-            Interest::READABLE,
-        )?;
+        let _inotify = match device_fds {
+            [] => {
+                let inotify = watch_devinput().map_err(|e| {
+                    log::error!("failed to watch files: {e:?}");
+                    e
+                })?;
+                poll.registry().register(
+                    &mut SourceFd(&inotify.as_raw_fd()),
+                    INOTIFY_TOKEN,
+                    Interest::READABLE,
+                )?;
+                Some(inotify)
+            }
+            _ => None,
+        };
+
+        let (requests, grab_requests) = std::sync::mpsc::channel();
+        let grab_control = DeviceGrabControl {
+            requests,
+            waker: Arc::new(Waker::new(poll.registry(), GRAB_TOKEN)?),
+        };
 
         let mut kbdin = Self {
             poll,
@@ -74,7 +170,18 @@ impl KbdIn {
             _inotify,
             events: Events::with_capacity(32),
             devices: HashMap::default(),
-            token_counter: INOTIFY_TOKEN_VALUE + 1,
+            include_media_devices,
+            force,
+            grab,
+            token_counter: GRAB_TOKEN_VALUE + 1,
+            session: None,
+            resume: ResumeDetector::default(),
+            grab_requests,
+            grab_control,
+            activity_devices: vec![],
+            activity: false,
+            devices_changed: false,
+            released_paths: vec![],
         };
 
         for (device, dev_path) in devices.into_iter() {
@@ -90,13 +197,15 @@ impl KbdIn {
     }
 
     fn register_device(&mut self, mut dev: Device, path: String) -> Result<(), io::Error> {
-        log::info!("registering {path}");
-        wait_for_all_keys_unpressed(&dev)?;
-        // NOTE: This grab-ungrab-grab sequence magically fixes an issue with a Lenovo Yoga
-        // trackpad not working. No idea why this works.
-        dev.grab()?;
-        dev.ungrab()?;
-        dev.grab()?;
+        log::info!("registering {path}: {}", dev.name().unwrap_or("unnamed"));
+        if self.grab && !self.is_paused() && !self.released_paths.contains(&path) {
+            wait_for_all_keys_unpressed(&dev)?;
+            // NOTE: This grab-ungrab-grab sequence magically fixes an issue with a Lenovo Yoga
+            // trackpad not working. No idea why this works.
+            dev.grab()?;
+            dev.ungrab()?;
+            dev.grab()?;
+        }
 
         let tok = Token(self.token_counter);
         self.token_counter += 1;
@@ -105,26 +214,195 @@ impl KbdIn {
             .registry()
             .register(&mut SourceFd(&fd), tok, Interest::READABLE)?;
         self.devices.insert(tok, (dev, path));
+        self.devices_changed = true;
+        Ok(())
+    }
+
+    /// Release the devices while the logind session is inactive and grab them again once it is
+    /// active, see [`SessionWatcher`].
+    pub fn watch_session(&mut self, session: SessionWatcher) -> Result<(), io::Error> {
+        self.poll.registry().register(
+            &mut SourceFd(&session.as_raw_fd()),
+            SESSION_TOKEN,
+            Interest::READABLE,
+        )?;
+        let active = session.is_active();
+        self.session = Some(session);
+        if !active {
+            self.set_grabbed(false);
+        }
         Ok(())
     }
 
-    pub fn read(&mut self) -> Result<Vec<InputEvent>, io::Error> {
-        let mut input_events = vec![];
+    /// Whether the devices are released because the session is inactive. Only key releases are
+    /// read while paused, so that keys held when the session became inactive are not stuck.
+    fn is_paused(&self) -> bool {
+        self.session.as_ref().is_some_and(|s| !s.is_active())
+    }
+
+    fn set_grabbed(&mut self, grab: bool) {
+        if !self.grab {
+            return;
+        }
+        log::info!(
+            "logind session became {}, {} devices",
+            if grab { "active" } else { "inactive" },
+            if grab { "grabbing" } else { "releasing" },
+        );
+        for (dev, path) in self.devices.values_mut() {
+            if self.released_paths.contains(path) {
+                continue;
+            }
+            let result = match grab {
+                true => wait_for_all_keys_unpressed(dev).and_then(|_| dev.grab()),
+                false => dev.ungrab(),
+            };
+            if let Err(e) = result {
+                log::warn!("failed to change grab of {path}: {e}");
+            }
+        }
+    }
+
+    /// Report whether the devices with these names sent events, see [`KbdIn::take_activity`].
+    pub fn watch_activity(&mut self, device_names: Vec<String>) {
+        self.activity_devices = device_names;
+    }
+
+    /// Returns whether a watched device sent events since the last call.
+    pub fn take_activity(&mut self) -> bool {
+        std::mem::take(&mut self.activity)
+    }
+
+    /// Returns whether devices were plugged in or unplugged since the last call. Reads return
+    /// early when they are, even without events.
+    pub fn take_devices_changed(&mut self) -> bool {
+        std::mem::take(&mut self.devices_changed)
+    }
+
+    /// Names of the devices that are currently registered.
+    pub fn device_names(&self) -> Vec<String> {
+        self.devices
+            .values()
+            .filter_map(|(dev, _)| dev.name().map(str::to_owned))
+            .collect()
+    }
+
+    pub fn grab_control(&self) -> DeviceGrabControl {
+        self.grab_control.clone()
+    }
+
+    fn handle_grab_requests(&mut self) {
+        while let Ok(request) = self.grab_requests.try_recv() {
+            if !self.grab {
+                let _ = request.reply.send(Err(
+                    "the devices are not grabbed in compose-on-top mode".to_owned(),
+                ));
+                continue;
+            }
+            let paused = self.is_paused();
+            let mut found = false;
+            let mut changed = vec![];
+            for (dev, path) in self.devices.values_mut() {
+                if dev.name() != Some(request.device.as_str()) && *path != request.device {
+                    continue;
+                }
+                found = true;
+                let released = self.released_paths.contains(path);
+                // Already in the requested state.
+                if request.grab != released {
+                    continue;
+                }
+                // While the session is inactive all devices are released already; they are
+                // grabbed once it becomes active.
+                let result = match (request.grab, paused) {
+                    (_, true) => Ok(()),
+                    (true, false) => wait_for_all_keys_unpressed(dev).and_then(|_| dev.grab()),
+                    (false, false) => dev.ungrab(),
+                };
+                match result {
+                    Ok(()) => {
+                        log::info!(
+                            "{} {path} on request",
+                            if request.grab { "grabbed" } else { "released" }
+                        );
+                        if request.grab {
+                            self.released_paths.retain(|p| p != path);
+                        } else {
+                            self.released_paths.push(path.clone());
+                        }
+                        changed.push(path.clone());
+                    }
+                    Err(e) => log::warn!("failed to change grab of {path}: {e}"),
+                }
+            }
+            let reply = match found {
+                true => Ok(changed),
+                false => Err(format!("no device is named {}", request.device)),
+            };
+            let _ = request.reply.send(reply);
+        }
+    }
+
+    /// Paths of the devices that are currently grabbed.
+    pub fn device_paths(&self) -> Vec<String> {
+        self.devices
+            .values()
+            .map(|(_, path)| path.clone())
+            .collect()
+    }
+
+    /// Wait for input events and replace the contents of `input_events` with them. The buffer is
+    /// passed in so that it is reused for every read.
+    pub fn read(&mut self, input_events: &mut Vec<InputEvent>) -> Result<(), io::Error> {
+        input_events.clear();
         loop {
             log::trace!("polling");
 
             if let Err(e) = self.poll.poll(&mut self.events, None) {
                 log::error!("failed poll: {:?}", e);
-                return Ok(vec![]);
+                return Ok(());
             }
 
             let mut do_rediscover = false;
+            if self.resume.check() {
+                // Devices may have been reset during suspend, which can drop the grab or replace
+                // the device node.
+                log::info!("system resumed from suspend, grabbing devices again");
+                if self.grab && !self.is_paused() {
+                    for (dev, path) in self.devices.values_mut() {
+                        if self.released_paths.contains(path) {
+                            continue;
+                        }
+                        if let Err(e) = dev.ungrab().and_then(|_| dev.grab()) {
+                            log::warn!("failed to grab {path} after resume: {e}");
+                        }
+                    }
+                }
+                do_rediscover = true;
+            }
+            let mut session_changed = None;
+            let mut do_grab_requests = false;
+            let paused = self.is_paused();
             for event in &self.events {
-                if let Some((device, _)) = self.devices.get_mut(&event.token()) {
-                    if let Err(e) = device
-                        .fetch_events()
-                        .map(|evs| evs.into_iter().for_each(|ev| input_events.push(ev)))
-                    {
+                if let Some((device, path)) = self.devices.get_mut(&event.token()) {
+                    // The OS receives the events of released devices, so they must not be
+                    // processed, but they are still read to not be queued up.
+                    let released = self.released_paths.contains(path);
+                    let watched = device
+                        .name()
+                        .is_some_and(|name| self.activity_devices.iter().any(|d| d == name));
+                    if let Err(e) = device.fetch_events().map(|evs| {
+                        evs.into_iter()
+                            .filter(|ev| {
+                                !released
+                                    && (!paused
+                                        || (ev.event_type() == EventType::KEY && ev.value() == 0))
+                            })
+                            .for_each(|ev| {
+                                self.activity |= watched && !paused;
+                                input_events.push(ev)
+                            })
+                    }) {
                         // Currently the kind() is uncategorized... not helpful, need to match
                         // on os error (19)
                         match e.raw_os_error() {
@@ -134,6 +412,7 @@ impl KbdIn {
                                     .deregister(&mut SourceFd(&device.as_raw_fd()))?;
                                 if let Some((_, path)) = self.devices.remove(&event.token()) {
                                     log::warn!("removing kbd device: {path}");
+                                    self.devices_changed = true;
                                     if let Some(ref mut missing) = self.missing_device_paths {
                                         missing.push(path);
                                     }
@@ -147,16 +426,26 @@ impl KbdIn {
                     }
                 } else if event.token() == INOTIFY_TOKEN {
                     do_rediscover = true;
+                } else if event.token() == SESSION_TOKEN {
+                    session_changed = self.session.as_mut().and_then(|s| s.update());
+                } else if event.token() == GRAB_TOKEN {
+                    do_grab_requests = true;
                 } else {
                     panic!("encountered unexpected epoll event {event:?}");
                 }
             }
+            if let Some(active) = session_changed {
+                self.set_grabbed(active);
+            }
+            if do_grab_requests {
+                self.handle_grab_requests();
+            }
             if do_rediscover {
                 log::info!("watch found file changes, looking for new devices");
                 self.rediscover_devices()?;
             }
-            if !input_events.is_empty() {
-                return Ok(input_events);
+            if !input_events.is_empty() || self.devices_changed {
+                return Ok(());
             }
         }
     }
@@ -170,6 +459,7 @@ impl KbdIn {
                 return Ok(());
             }
             log::info!("checking for {missing:?}");
+            let force = self.force;
             let discovered_devices = missing
                 .iter()
                 .filter_map(|dev_path| {
@@ -182,6 +472,7 @@ impl KbdIn {
                     }
                     None
                 })
+                .filter(|(device, dev_path)| !refuses_touch_device(device, dev_path, force))
                 .collect::<Vec<(_, _)>>();
             for (device, dev_path) in discovered_devices {
                 if let Err(e) = self.register_device(device, dev_path.clone()) {
@@ -194,7 +485,7 @@ impl KbdIn {
         if let Some(ref mut missing) = self.missing_device_paths {
             missing.retain(|path| !paths_registered.contains(path));
         } else {
-            discover_devices()?
+            discover_devices(self.include_media_devices, self.force)?
                 .into_iter()
                 .try_for_each(|(dev, path)| {
                     if !self
@@ -212,16 +503,41 @@ impl KbdIn {
     }
 }
 
-pub fn is_input_device(device: &Device) -> bool {
+/// Names of the virtual devices created by kanata, which must never be grabbed.
+static VIRTUAL_DEVICE_NAMES: parking_lot::Mutex<Vec<String>> = parking_lot::Mutex::new(vec![]);
+
+/// Keys that are commonly on a separate input device from the rest of the keyboard, e.g. one
+/// named "Consumer Control".
+pub const MEDIA_KEYS: &[OsCode] = &[
+    OsCode::KEY_MUTE,
+    OsCode::KEY_VOLUMEDOWN,
+    OsCode::KEY_VOLUMEUP,
+    OsCode::KEY_NEXTSONG,
+    OsCode::KEY_PLAYPAUSE,
+    OsCode::KEY_PREVIOUSSONG,
+    OsCode::KEY_STOPCD,
+    OsCode::KEY_REWIND,
+    OsCode::KEY_FASTFORWARD,
+    OsCode::KEY_BRIGHTNESSDOWN,
+    OsCode::KEY_BRIGHTNESSUP,
+];
+
+pub fn is_input_device(device: &Device, include_media_devices: bool) -> bool {
     use evdev::Key;
-    let is_keyboard = device
-        .supported_keys()
-        .map_or(false, |keys| keys.contains(Key::KEY_ENTER));
-    let is_mouse = device
-        .supported_relative_axes()
-        .map_or(false, |axes| axes.contains(RelativeAxisType::REL_X));
+    let is_keyboard = device.supported_keys().map_or(false, |keys| {
+        keys.contains(Key::KEY_ENTER)
+            || (include_media_devices && MEDIA_KEYS.iter().any(|k| keys.contains(Key(*k as u16))))
+    });
+    // Knobs that report REL_DIAL are often a device of their own.
+    let is_mouse = device.supported_relative_axes().map_or(false, |axes| {
+        axes.contains(RelativeAxisType::REL_X) || axes.contains(RelativeAxisType::REL_DIAL)
+    });
     if is_keyboard || is_mouse {
-        if device.name() == Some("kanata") {
+        let is_virtual = match device.name() {
+            Some(name) => name == "kanata" || VIRTUAL_DEVICE_NAMES.lock().iter().any(|n| n == name),
+            None => false,
+        };
+        if is_virtual {
             return false;
         }
         log::debug!(
@@ -246,6 +562,84 @@ pub fn is_input_device(device: &Device) -> bool {
     }
 }
 
+/// Returns true for devices with a touchpad or touchscreen. Some laptops expose the internal
+/// keyboard and touchpad as one device. Kanata only passes through the events of a grabbed device
+/// that its virtual device supports, so grabbing such a device stops the touchpad from working.
+pub fn has_touch_surface(device: &Device) -> bool {
+    let multitouch = device
+        .supported_absolute_axes()
+        .is_some_and(|axes| axes.contains(AbsoluteAxisType::ABS_MT_POSITION_X));
+    let finger = device
+        .supported_keys()
+        .is_some_and(|keys| keys.contains(evdev::Key::BTN_TOOL_FINGER));
+    multitouch || finger
+}
+
+/// Returns true and logs a warning if a device is not grabbed because it has a touch surface.
+fn refuses_touch_device(device: &Device, path: &str, force: bool) -> bool {
+    if force || !has_touch_surface(device) {
+        return false;
+    }
+    log::warn!(
+        "not grabbing {path} ({}) because it has a touchpad or touchscreen, \
+         pass --force to grab it anyway",
+        device.name().unwrap_or("unknown device name")
+    );
+    true
+}
+
+/// Print the input devices, what they are, and whether kanata grabs them when no devices are
+/// configured with linux-dev.
+pub fn list_devices(force: bool) -> Result<(), io::Error> {
+    let mut devices = evdev::enumerate()
+        .map(|(path, device)| (path.to_string_lossy().to_string(), device))
+        .collect::<Vec<_>>();
+    devices.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (path, device) in devices.iter() {
+        let keys = device.supported_keys();
+        let has_key = |key: evdev::Key| keys.is_some_and(|keys| keys.contains(key));
+        let mut kinds = vec![];
+        if has_key(evdev::Key::KEY_ENTER) {
+            kinds.push("keyboard");
+        } else if MEDIA_KEYS.iter().any(|k| has_key(evdev::Key(*k as u16))) {
+            kinds.push("media keys");
+        }
+        let axes = device.supported_relative_axes();
+        if axes.is_some_and(|axes| axes.contains(RelativeAxisType::REL_X)) {
+            kinds.push("mouse");
+        }
+        if axes.is_some_and(|axes| axes.contains(RelativeAxisType::REL_DIAL)) {
+            kinds.push("dial");
+        }
+        let touch = has_touch_surface(device);
+        if touch {
+            kinds.push("touch");
+        }
+        let grab = if !is_input_device(device, true) {
+            "not grabbed"
+        } else if touch && !force {
+            "not grabbed: has a touch surface, use --force to grab"
+        } else if !is_input_device(device, false) {
+            "grabbed if media keys are in defsrc"
+        } else {
+            "grabbed"
+        };
+        println!(
+            "{path}: {}\n    {}\n    {grab}",
+            device.name().unwrap_or("unknown device name"),
+            if kinds.is_empty() {
+                "other".to_owned()
+            } else {
+                kinds.join(", ")
+            },
+        );
+    }
+    if devices.is_empty() {
+        println!("no input devices found, reading them may require root or the input group");
+    }
+    Ok(())
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum UnicodeTermination {
     Enter,
@@ -254,10 +648,249 @@ pub enum UnicodeTermination {
     EnterSpace,
 }
 
+/// Non-key events that are not passed through from the input devices to the virtual device.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct StrippedEvents {
+    pub msc_scan: bool,
+    pub led: bool,
+    pub rep: bool,
+}
+
+impl StrippedEvents {
+    fn strips(&self, event: &InputEvent) -> bool {
+        match event.event_type() {
+            EventType::MISC => self.msc_scan && event.code() == MiscType::MSC_SCAN.0,
+            EventType::LED => self.led,
+            EventType::REPEAT => self.rep,
+            _ => false,
+        }
+    }
+}
+
+/// Identity and capabilities of the virtual output device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputDeviceCfg {
+    pub name: String,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    /// Whether relative axes, i.e. mouse movement and scrolling, are declared.
+    pub rel: bool,
+    /// Whether MSC_SCAN is declared.
+    pub msc: bool,
+    /// Whether mouse buttons and relative axes are output by a separate mouse device.
+    pub split_mouse: bool,
+    /// Whether an absolute pointer device is created for `setmouse`.
+    pub absolute_pointer: bool,
+    /// The name of a second virtual keyboard that the output can be routed to.
+    pub secondary_name: Option<String>,
+    pub backend: OutputBackend,
+}
+
+/// How the output events are delivered to the OS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputBackend {
+    /// A virtual device created through `/dev/uinput`.
+    Uinput,
+    /// The Wayland virtual keyboard protocol. Only key events are output. The compositor
+    /// interprets the keycodes with the XKB layout.
+    #[cfg(feature = "wayland")]
+    Wayland { xkb_layout: String },
+    /// The X11 XTEST extension. The X server interprets the keycodes with its own keymap.
+    #[cfg(feature = "xtest")]
+    Xtest,
+    /// A Bluetooth LE keyboard registered with BlueZ on the adapter, e.g. `hci0`, for a second
+    /// machine. Only key events are output.
+    #[cfg(feature = "ble_hid")]
+    Ble { adapter: String },
+    /// Raw `input_event` structs written to stdout, for use in an Interception Tools pipeline.
+    Stdout,
+}
+
+/// What an output can deliver besides keys. Events that need a missing capability are dropped
+/// with a warning instead of failing, so that one configuration works with every backend.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OutputCapabilities {
+    pub mouse_buttons: bool,
+    /// Mouse movement and scrolling.
+    pub mouse_movement: bool,
+    /// Positioning the cursor for `setmouse`.
+    pub absolute_pointer: bool,
+}
+
+impl OutputCapabilities {
+    /// Returns the name of the capability that the event needs if it is missing.
+    fn missing_for(&self, event: &InputEvent) -> Option<&'static str> {
+        match event.event_type() {
+            EventType::RELATIVE if !self.mouse_movement => Some("mouse movement and scrolling"),
+            EventType::KEY if MOUSE_BTNS.contains(&event.code()) && !self.mouse_buttons => {
+                Some("mouse buttons")
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A destination for the events that kanata outputs.
+pub trait OutputSink: Send {
+    fn emit(&mut self, events: &[InputEvent]) -> Result<(), io::Error>;
+
+    /// The events that the destination delivers besides keys.
+    fn capabilities(&self) -> OutputCapabilities;
+}
+
+impl OutputSink for uinput::VirtualDevice {
+    fn emit(&mut self, events: &[InputEvent]) -> Result<(), io::Error> {
+        uinput::VirtualDevice::emit(self, events)
+    }
+
+    fn capabilities(&self) -> OutputCapabilities {
+        OutputCapabilities {
+            mouse_buttons: true,
+            mouse_movement: true,
+            absolute_pointer: false,
+        }
+    }
+}
+
+/// Writes raw `input_event` structs to stdout, as expected by Interception Tools' `uinput`.
+struct StdoutOut;
+
+impl OutputSink for StdoutOut {
+    fn emit(&mut self, events: &[InputEvent]) -> Result<(), io::Error> {
+        // Match the uinput device, which terminates every batch of events with SYN_REPORT.
+        let syn = InputEvent::new(EventType::SYNCHRONIZATION, 0, 0);
+        let mut stdout = io::stdout().lock();
+        for event in events.iter().chain(std::iter::once(&syn)) {
+            stdout.write_all(input_event_bytes(event))?;
+        }
+        stdout.flush()
+    }
+
+    fn capabilities(&self) -> OutputCapabilities {
+        OutputCapabilities {
+            mouse_buttons: true,
+            mouse_movement: true,
+            absolute_pointer: false,
+        }
+    }
+}
+
+pub(super) fn input_event_bytes(event: &InputEvent) -> &[u8] {
+    let raw: &libc::input_event = event.as_ref();
+    // SAFETY: input_event is a plain C struct without padding.
+    unsafe {
+        std::slice::from_raw_parts(
+            (raw as *const libc::input_event).cast::<u8>(),
+            std::mem::size_of::<libc::input_event>(),
+        )
+    }
+}
+
+/// Reads raw `input_event` structs from stdin, as written by Interception Tools' `intercept`.
+#[derive(Default)]
+pub struct StdinIn {
+    buf: Vec<u8>,
+}
+
+impl StdinIn {
+    /// Read the next events. Returns an empty list when stdin is closed.
+    /// Replace the contents of `events` with the next events. No events are read when stdin is
+    /// closed.
+    pub fn read(&mut self, events: &mut Vec<InputEvent>) -> Result<(), io::Error> {
+        events.clear();
+        let mut chunk = [0u8; 1024];
+        loop {
+            let n = io::stdin().lock().read(&mut chunk)?;
+            if n == 0 {
+                return Ok(());
+            }
+            self.buf.extend_from_slice(&chunk[..n]);
+            take_input_events(&mut self.buf, events);
+            if !events.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Move the complete `input_event` structs out of the buffer into `events`.
+pub(super) fn take_input_events(buf: &mut Vec<u8>, events: &mut Vec<InputEvent>) {
+    let event_size = std::mem::size_of::<libc::input_event>();
+    let complete_len = buf.len() - buf.len() % event_size;
+    events.extend(buf[..complete_len].chunks_exact(event_size).map(|bytes| {
+        // SAFETY: the chunk is exactly the size of input_event, which is valid for any bit
+        // pattern.
+        let raw = unsafe { std::ptr::read_unaligned(bytes.as_ptr().cast::<libc::input_event>()) };
+        InputEvent::from(raw)
+    }));
+    buf.drain(..complete_len);
+}
+
+#[test]
+fn input_events_round_trip_through_bytes() {
+    let events = [
+        InputEvent::new(EventType::KEY, OsCode::KEY_A as u16, 1),
+        InputEvent::new(EventType::SYNCHRONIZATION, 0, 0),
+    ];
+    let mut buf = events
+        .iter()
+        .flat_map(|ev| input_event_bytes(ev).to_vec())
+        .collect::<Vec<_>>();
+    // An incomplete event stays in the buffer until the rest arrives.
+    let rest = buf.split_off(buf.len() - 3);
+    let mut parsed = vec![];
+    take_input_events(&mut buf, &mut parsed);
+    assert_eq!(parsed.len(), 1);
+    assert_eq!(parsed[0].event_type(), EventType::KEY);
+    assert_eq!(parsed[0].code(), OsCode::KEY_A as u16);
+    assert_eq!(parsed[0].value(), 1);
+    assert!(!buf.is_empty());
+
+    buf.extend_from_slice(&rest);
+    parsed.clear();
+    take_input_events(&mut buf, &mut parsed);
+    assert_eq!(parsed.len(), 1);
+    assert_eq!(parsed[0].event_type(), EventType::SYNCHRONIZATION);
+    assert!(buf.is_empty());
+}
+
+impl Default for OutputDeviceCfg {
+    fn default() -> Self {
+        Self {
+            name: "kanata".into(),
+            vendor_id: 1,
+            product_id: 1,
+            rel: true,
+            msc: true,
+            split_mouse: false,
+            absolute_pointer: false,
+            secondary_name: None,
+            backend: OutputBackend::Uinput,
+        }
+    }
+}
+
+/// Mouse buttons, BTN_LEFT to BTN_TASK.
+const MOUSE_BTNS: std::ops::Range<u16> = 0x110..0x118;
+
+/// Returns true if the event belongs to the mouse device when the mouse device is split from the
+/// keyboard device.
+fn is_mouse_event(event: &InputEvent) -> bool {
+    match event.event_type() {
+        EventType::RELATIVE => true,
+        EventType::KEY => MOUSE_BTNS.contains(&event.code()),
+        _ => false,
+    }
+}
+
 use std::cell::Cell;
 
 pub struct KbdOut {
-    device: uinput::VirtualDevice,
+    device: Box<dyn OutputSink>,
+    /// Separate device for mouse events, if configured.
+    mouse_device: Option<uinput::VirtualDevice>,
+    /// Device with absolute axes that positions the cursor for `setmouse`, if configured.
+    pointer_device: Option<uinput::VirtualDevice>,
     accumulated_scroll: u16,
     accumulated_hscroll: u16,
     #[allow(dead_code)] // stored here for persistence+cleanup on exit
@@ -265,54 +898,178 @@ pub struct KbdOut {
     raw_buf: Vec<InputEvent>,
     pub unicode_termination: Cell<UnicodeTermination>,
     pub unicode_u_code: Cell<OsCode>,
+    pub stripped_events: Cell<StrippedEvents>,
+    /// Some(_) if the input devices are not grabbed, see [`KbdOut::compose_on_top`].
+    compose_on_top: Option<ComposeOnTop>,
+    capabilities: OutputCapabilities,
+    /// The missing capabilities that were warned about, so that each is only logged once.
+    warned_missing: Vec<&'static str>,
+    /// The modifiers written to the device that are still pressed.
+    pub modifiers: OutputModifiers,
+    /// The second virtual keyboard of `linux-output-secondary-device-name`, if configured.
+    secondary: Option<uinput::VirtualDevice>,
+    route: OutputRoute,
+}
+
+/// Where the output of [`KbdOut`] goes.
+pub enum OutputRoute {
+    /// The output device.
+    Device,
+    /// The second virtual keyboard, or the output device if there is none.
+    Secondary,
+    /// Another destination, e.g. a machine of `kvm-targets`.
+    Remote(Box<dyn OutputSink>),
+}
+
+/// The keys held on the input devices when they are not grabbed. Their events reach the system
+/// without kanata, so kanata does not write them again.
+#[derive(Debug, Default)]
+struct ComposeOnTop {
+    held: Vec<OsCode>,
+    /// Keys whose press was not written because they were held, so their release is not either.
+    skipped: Vec<OsCode>,
+}
+
+impl ComposeOnTop {
+    fn input_key(&mut self, key: OsCode, value: KeyValue) {
+        match value {
+            KeyValue::Press if !self.held.contains(&key) => self.held.push(key),
+            KeyValue::Release => self.held.retain(|k| *k != key),
+            _ => {}
+        }
+    }
+
+    /// Returns whether writing the key event is skipped.
+    fn skips(&mut self, key: OsCode, value: KeyValue) -> bool {
+        match value {
+            KeyValue::Press if self.held.contains(&key) => {
+                if !self.skipped.contains(&key) {
+                    self.skipped.push(key);
+                }
+                true
+            }
+            KeyValue::Press => false,
+            KeyValue::Release => {
+                let skipped = self.skipped.contains(&key);
+                self.skipped.retain(|k| *k != key);
+                skipped
+            }
+            _ => self.skipped.contains(&key),
+        }
+    }
+}
+
+#[test]
+fn compose_on_top_skips_held_keys() {
+    use KeyValue::*;
+    let mut c = ComposeOnTop::default();
+    c.input_key(OsCode::KEY_A, Press);
+    // The held key is not written again, other keys are.
+    assert!(c.skips(OsCode::KEY_A, Press));
+    assert!(!c.skips(OsCode::KEY_B, Press));
+    assert!(c.skips(OsCode::KEY_A, Repeat));
+    // The release of a skipped press is skipped even after the key was released on the device.
+    c.input_key(OsCode::KEY_A, Release);
+    assert!(c.skips(OsCode::KEY_A, Release));
+    assert!(!c.skips(OsCode::KEY_B, Release));
+    // Kanata typing the key later, e.g. in a macro, is written.
+    assert!(!c.skips(OsCode::KEY_A, Press));
+    assert!(!c.skips(OsCode::KEY_A, Release));
 }
 
 pub const HI_RES_SCROLL_UNITS_IN_LO_RES: u16 = 120;
 
 impl KbdOut {
-    pub fn new(symlink_path: &Option<String>) -> Result<Self, io::Error> {
-        // Support pretty much every feature of a Keyboard or a Mouse in a VirtualDevice so that no event from the original input devices gets lost
-        // TODO investigate the rare possibility that a device is e.g. a Joystick and a Keyboard or a Mouse at the same time, which could lead to lost events
-
-        // For some reason 0..0x300 (max value for a key) doesn't work, the closest that I've got to work is 560
-        let keys = evdev::AttributeSet::from_iter((0..560).map(evdev::Key));
-        let relative_axes = evdev::AttributeSet::from_iter([
-            RelativeAxisType::REL_WHEEL,
-            RelativeAxisType::REL_HWHEEL,
-            RelativeAxisType::REL_X,
-            RelativeAxisType::REL_Y,
-            RelativeAxisType::REL_Z,
-            RelativeAxisType::REL_RX,
-            RelativeAxisType::REL_RY,
-            RelativeAxisType::REL_RZ,
-            RelativeAxisType::REL_DIAL,
-            RelativeAxisType::REL_MISC,
-            RelativeAxisType::REL_WHEEL_HI_RES,
-            RelativeAxisType::REL_HWHEEL_HI_RES,
-        ]);
-
-        let mut device = uinput::VirtualDeviceBuilder::new()?
-            .name("kanata")
-            .input_id(evdev::InputId::new(evdev::BusType::BUS_USB, 1, 1, 1))
-            .with_keys(&keys)?
-            .with_relative_axes(&relative_axes)?
-            .build()?;
-        let devnode = device
-            .enumerate_dev_nodes_blocking()?
-            .next() // Expect only one. Using fold or calling next again blocks indefinitely
-            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "devnode is not found"))??;
-        log::info!("Created device {:#?}", devnode);
-        let symlink = if let Some(symlink_path) = symlink_path {
+    pub fn new(
+        symlink_path: &Option<String>,
+        device_cfg: &OutputDeviceCfg,
+    ) -> Result<Self, io::Error> {
+        let (device, devnode): (Box<dyn OutputSink>, _) = match &device_cfg.backend {
+            OutputBackend::Uinput => {
+                let (mut device, devnode) = create_uinput_device(device_cfg)?;
+                self_test_uinput_device(&mut device, &devnode, device_cfg)?;
+                (Box::new(device), Some(devnode))
+            }
+            #[cfg(feature = "wayland")]
+            OutputBackend::Wayland { xkb_layout } => {
+                (Box::new(super::WaylandKeyboard::connect(xkb_layout)?), None)
+            }
+            #[cfg(feature = "xtest")]
+            OutputBackend::Xtest => (Box::new(super::XtestOutput::connect()?), None),
+            #[cfg(feature = "ble_hid")]
+            OutputBackend::Ble { adapter } => {
+                let keyboard = super::BleKeyboard::connect(
+                    adapter,
+                    &device_cfg.name,
+                    device_cfg.vendor_id,
+                    device_cfg.product_id,
+                )?;
+                (Box::new(keyboard), None)
+            }
+            OutputBackend::Stdout => (Box::new(StdoutOut), None),
+        };
+        let mouse_device = if device_cfg.split_mouse {
+            let mut mouse_device = uinput::VirtualDeviceBuilder::new().map_err(uinput_open_error)?
+                .name(&format!("{} mouse", device_cfg.name))
+                .input_id(evdev::InputId::new(
+                    evdev::BusType::BUS_USB,
+                    device_cfg.vendor_id,
+                    device_cfg.product_id,
+                    1,
+                ))
+                .with_keys(&evdev::AttributeSet::from_iter(MOUSE_BTNS.map(evdev::Key)))?
+                .with_relative_axes(&evdev::AttributeSet::from_iter(
+                    RELATIVE_AXES.iter().copied(),
+                ))?
+                .build()?;
+            VIRTUAL_DEVICE_NAMES
+                .lock()
+                .push(format!("{} mouse", device_cfg.name));
+            if let Some(devnode) = mouse_device.enumerate_dev_nodes_blocking()?.next() {
+                log::info!("Created mouse device {:#?}", devnode?);
+            }
+            Some(mouse_device)
+        } else {
+            None
+        };
+        let pointer_device = if device_cfg.absolute_pointer {
+            Some(create_pointer_device(device_cfg)?)
+        } else {
+            None
+        };
+        let mut capabilities = device.capabilities();
+        capabilities.mouse_movement &= device_cfg.rel;
+        if mouse_device.is_some() {
+            capabilities.mouse_buttons = true;
+            capabilities.mouse_movement = true;
+        }
+        capabilities.absolute_pointer = pointer_device.is_some();
+        let secondary = match &device_cfg.secondary_name {
+            Some(name) => {
+                let secondary_cfg = OutputDeviceCfg {
+                    name: name.clone(),
+                    split_mouse: false,
+                    ..device_cfg.clone()
+                };
+                Some(create_uinput_device(&secondary_cfg)?.0)
+            }
+            None => None,
+        };
+        log::info!("output {:?}, {capabilities:?}", device_cfg.backend);
+        if symlink_path.is_some() && devnode.is_none() {
+            log::warn!("The output backend has no device node, the symlink is not created");
+        }
+        let symlink = if let (Some(symlink_path), Some(devnode)) = (symlink_path, devnode) {
             let dest = PathBuf::from(symlink_path);
-            let symlink = Symlink::new(devnode, dest)?;
-            Symlink::clean_when_killed(symlink.clone());
-            Some(symlink)
+            Some(Symlink::new(devnode, dest)?)
         } else {
             None
         };
 
         Ok(KbdOut {
             device,
+            mouse_device,
+            pointer_device,
             accumulated_scroll: 0,
             accumulated_hscroll: 0,
             symlink,
@@ -323,9 +1080,68 @@ impl KbdOut {
 
             // historically was the only option, so make KEY_U the default
             unicode_u_code: Cell::new(OsCode::KEY_U),
+
+            stripped_events: Cell::new(StrippedEvents::default()),
+            compose_on_top: None,
+            capabilities,
+            warned_missing: vec![],
+            modifiers: OutputModifiers::default(),
+            secondary,
+            route: OutputRoute::Device,
         })
     }
 
+    /// What the output delivers besides keys.
+    pub fn capabilities(&self) -> OutputCapabilities {
+        match &self.route {
+            OutputRoute::Remote(remote) => remote.capabilities(),
+            _ => self.capabilities,
+        }
+    }
+
+    /// Send the events to another destination than the output device, or to the device again.
+    /// Keys that are held should be released before.
+    pub fn set_route(&mut self, route: OutputRoute) {
+        self.route = route;
+        self.warned_missing.clear();
+    }
+
+    /// Replace the output with a new one made from `device_cfg`, e.g. another backend. The
+    /// settings changed at runtime are kept. The old output is only replaced if the new one can
+    /// be created.
+    pub fn replace(
+        &mut self,
+        symlink_path: &Option<String>,
+        device_cfg: &OutputDeviceCfg,
+    ) -> Result<(), io::Error> {
+        let mut new = KbdOut::new(symlink_path, device_cfg)?;
+        new.unicode_termination = self.unicode_termination.clone();
+        new.unicode_u_code = self.unicode_u_code.clone();
+        new.stripped_events = self.stripped_events.clone();
+        new.compose_on_top = self.compose_on_top.take();
+        new.route = std::mem::replace(&mut self.route, OutputRoute::Device);
+        if new.symlink.is_some() {
+            // The new symlink replaced the old one at the same path, which dropping the old one
+            // would remove.
+            std::mem::forget(self.symlink.take());
+        }
+        *self = new;
+        Ok(())
+    }
+
+    fn warn_missing(&mut self, capability: &'static str) {
+        if !self.warned_missing.contains(&capability) {
+            log::warn!("the output does not support {capability}, these events are dropped");
+            self.warned_missing.push(capability);
+        }
+    }
+
+    /// Delete the symlink to the output device. `std::process::exit` does not run destructors,
+    /// so this is called before exiting.
+    pub fn remove_symlink(&mut self) {
+        self.symlink = None;
+    }
+
     pub fn update_unicode_termination(&self, t: UnicodeTermination) {
         self.unicode_termination.replace(t);
     }
@@ -334,6 +1150,10 @@ impl KbdOut {
         self.unicode_u_code.replace(u);
     }
 
+    pub fn update_stripped_events(&self, s: StrippedEvents) {
+        self.stripped_events.replace(s);
+    }
+
     pub fn write_raw(&mut self, event: InputEvent) -> Result<(), io::Error> {
         if event.event_type() == EventType::SYNCHRONIZATION {
             // Possible codes are:
@@ -346,9 +1166,11 @@ impl KbdOut {
             //     this correctly.
             //
             // With this knowledge, seems fine to not bother checking.
-            self.device.emit(&self.raw_buf)?;
+            let raw_buf = std::mem::take(&mut self.raw_buf);
+            self.emit(&raw_buf)?;
+            self.raw_buf = raw_buf;
             self.raw_buf.clear();
-        } else {
+        } else if !self.stripped_events.get().strips(&event) {
             self.raw_buf.push(event);
         }
         Ok(())
@@ -356,25 +1178,101 @@ impl KbdOut {
 
     pub fn write(&mut self, event: InputEvent) -> Result<(), io::Error> {
         if !self.raw_buf.is_empty() {
-            self.device.emit(&self.raw_buf)?;
+            let raw_buf = std::mem::take(&mut self.raw_buf);
+            self.emit(&raw_buf)?;
+            self.raw_buf = raw_buf;
             self.raw_buf.clear();
         }
-        self.device.emit(&[event])?;
+        self.emit(&[event])
+    }
+
+    /// Emit the events to the device they belong to. Mouse events go to the mouse device if it
+    /// exists and everything else goes to the keyboard device. Events that the output does not
+    /// support are dropped.
+    fn emit(&mut self, events: &[InputEvent]) -> Result<(), io::Error> {
+        for ev in events {
+            if let Ok(KeyEvent { code, value }) = KeyEvent::try_from(*ev) {
+                self.modifiers.record(code, value);
+            }
+        }
+        let capabilities = self.capabilities();
+        if let Some(missing) = events.iter().find_map(|ev| capabilities.missing_for(ev)) {
+            self.warn_missing(missing);
+            let supported = events
+                .iter()
+                .filter(|ev| capabilities.missing_for(ev).is_none())
+                .copied()
+                .collect::<Vec<_>>();
+            if supported.is_empty() {
+                return Ok(());
+            }
+            return self.emit_supported(&supported);
+        }
+        self.emit_supported(events)
+    }
+
+    fn emit_supported(&mut self, events: &[InputEvent]) -> Result<(), io::Error> {
+        match (&mut self.route, &mut self.secondary) {
+            (OutputRoute::Remote(remote), _) => return remote.emit(events),
+            (OutputRoute::Secondary, Some(secondary)) => return secondary.emit(events),
+            _ => {}
+        }
+        let mouse_device = match &mut self.mouse_device {
+            Some(d) => d,
+            None => return self.device.emit(events),
+        };
+        let (mouse_events, kbd_events): (Vec<_>, Vec<_>) =
+            events.iter().partition(|ev| is_mouse_event(ev));
+        if !kbd_events.is_empty() {
+            self.device.emit(&kbd_events)?;
+        }
+        if !mouse_events.is_empty() {
+            mouse_device.emit(&mouse_events)?;
+        }
         Ok(())
     }
 
     pub fn write_key(&mut self, key: OsCode, value: KeyValue) -> Result<(), io::Error> {
+        if let Some(direction) = wheel_key_direction(key) {
+            // The wheel keys scroll by one detent, so that a layer can keep the wheel scrolling.
+            return match (value, direction) {
+                (KeyValue::Press, MWheelDirection::Up | MWheelDirection::Down) => {
+                    self.do_scroll(direction, 1)
+                }
+                (KeyValue::Press, _) => self.do_hscroll(direction, 1),
+                _ => Ok(()),
+            };
+        }
+        if is_extra_key(key)
+            || self
+                .compose_on_top
+                .as_mut()
+                .is_some_and(|c| c.skips(key, value))
+        {
+            return Ok(());
+        }
         let key_ev = KeyEvent::new(key, value);
         let input_ev = key_ev.into();
-        log::debug!("input ev: {:?}", input_ev);
-        self.device.emit(&[input_ev])?;
-        Ok(())
+        log::debug!("output {key:?} {value:?}");
+        self.emit(&[input_ev])
+    }
+
+    /// Stop writing the keys held on the input devices, for when the devices are not grabbed.
+    /// Kanata then only adds events, e.g. the output of sequences and macros.
+    pub fn compose_on_top(&mut self) {
+        self.compose_on_top = Some(ComposeOnTop::default());
+    }
+
+    /// Record a key event of the input devices, in compose-on-top mode.
+    pub fn input_key(&mut self, key: OsCode, value: KeyValue) {
+        if let Some(c) = &mut self.compose_on_top {
+            c.input_key(key, value);
+        }
     }
 
     pub fn write_code(&mut self, code: u32, value: KeyValue) -> Result<(), io::Error> {
         let event = InputEvent::new(EventType::KEY, code as u16, value as i32);
-        self.device.emit(&[event])?;
-        Ok(())
+        self.emit(&[event])
     }
 
     pub fn press_key(&mut self, key: OsCode) -> Result<(), io::Error> {
@@ -481,9 +1379,19 @@ impl KbdOut {
         self.write(InputEvent::new(EventType::RELATIVE, axis.0, distance))
     }
 
-    pub fn set_mouse(&mut self, _x: u16, _y: u16) -> Result<(), io::Error> {
-        log::warn!("setmouse does not work in Linux yet. Maybe try out warpd:\n\thttps://github.com/rvaiya/warpd");
-        Ok(())
+    pub fn set_mouse(&mut self, x: u16, y: u16) -> Result<(), io::Error> {
+        if matches!(self.route, OutputRoute::Remote(_)) {
+            self.warn_missing("setmouse on another machine");
+            return Ok(());
+        }
+        let Some(pointer_device) = &mut self.pointer_device else {
+            self.warn_missing("setmouse without linux-output-absolute-pointer yes in defcfg");
+            return Ok(());
+        };
+        pointer_device.emit(&[
+            InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_X.0, i32::from(x)),
+            InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_Y.0, i32::from(y)),
+        ])
     }
 
     fn do_scroll(
@@ -521,6 +1429,214 @@ impl KbdOut {
     }
 }
 
+/// The scroll direction of the keys that kanata makes from scroll wheel rotation.
+fn wheel_key_direction(key: OsCode) -> Option<MWheelDirection> {
+    match key {
+        OsCode::WHEEL_UP => Some(MWheelDirection::Up),
+        OsCode::WHEEL_DOWN => Some(MWheelDirection::Down),
+        OsCode::WHEEL_LEFT => Some(MWheelDirection::Left),
+        OsCode::WHEEL_RIGHT => Some(MWheelDirection::Right),
+        _ => None,
+    }
+}
+
+const RELATIVE_AXES: &[RelativeAxisType] = &[
+    RelativeAxisType::REL_WHEEL,
+    RelativeAxisType::REL_HWHEEL,
+    RelativeAxisType::REL_X,
+    RelativeAxisType::REL_Y,
+    RelativeAxisType::REL_Z,
+    RelativeAxisType::REL_RX,
+    RelativeAxisType::REL_RY,
+    RelativeAxisType::REL_RZ,
+    RelativeAxisType::REL_DIAL,
+    RelativeAxisType::REL_MISC,
+    RelativeAxisType::REL_WHEEL_HI_RES,
+    RelativeAxisType::REL_HWHEEL_HI_RES,
+];
+
+pub(super) fn create_uinput_device(
+    device_cfg: &OutputDeviceCfg,
+) -> Result<(uinput::VirtualDevice, PathBuf), io::Error> {
+    // Support pretty much every feature of a Keyboard or a Mouse in a VirtualDevice so that no event from the original input devices gets lost
+    // TODO investigate the rare possibility that a device is e.g. a Joystick and a Keyboard or a Mouse at the same time, which could lead to lost events
+
+    // For some reason 0..0x300 (max value for a key) doesn't work, the closest that I've got to work is 560
+    let keys = evdev::AttributeSet::from_iter(
+        (0..560)
+            .filter(|code| !device_cfg.split_mouse || !MOUSE_BTNS.contains(code))
+            .map(evdev::Key),
+    );
+    let relative_axes = evdev::AttributeSet::from_iter(RELATIVE_AXES.iter().copied());
+
+    let mut builder = uinput::VirtualDeviceBuilder::new().map_err(uinput_open_error)?
+        .name(&device_cfg.name)
+        .input_id(evdev::InputId::new(
+            evdev::BusType::BUS_USB,
+            device_cfg.vendor_id,
+            device_cfg.product_id,
+            1,
+        ))
+        .with_keys(&keys)?;
+    if device_cfg.rel && !device_cfg.split_mouse {
+        builder = builder.with_relative_axes(&relative_axes)?;
+    }
+    if device_cfg.msc {
+        // Some applications bind to scancodes, which keyboards report with MSC_SCAN.
+        builder = builder.with_msc(&evdev::AttributeSet::from_iter([MiscType::MSC_SCAN]))?;
+    }
+    let mut device = builder.build()?;
+    VIRTUAL_DEVICE_NAMES.lock().push(device_cfg.name.clone());
+    let devnode = device
+        .enumerate_dev_nodes_blocking()?
+        .next() // Expect only one. Using fold or calling next again blocks indefinitely
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "devnode is not found"))??;
+    log::info!("Created device {:#?}", devnode);
+    Ok((device, devnode))
+}
+
+/// Explain how to fix the usual reasons why `/dev/uinput` cannot be opened.
+fn uinput_open_error(e: io::Error) -> io::Error {
+    let hint = match e.kind() {
+        io::ErrorKind::NotFound => "the uinput kernel module is not loaded, load it with `sudo \
+                                    modprobe uinput`",
+        io::ErrorKind::PermissionDenied => "the user may not open it, add the user to the \
+                                            uinput group and install the udev rule in \
+                                            docs/avoid-sudo-linux.md",
+        _ => return e,
+    };
+    io::Error::new(e.kind(), format!("failed to open /dev/uinput: {e}; {hint}"))
+}
+
+/// Any value of MSC_SCAN that no key has, so that the self-test event does not look like a key.
+const SELF_TEST_SCANCODE: i32 = 0x70000;
+/// How long the self-test waits to read its event back from the output device.
+const SELF_TEST_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Check that the output device declares the events that kanata outputs and that events written
+/// to it are delivered, so that a broken setup is reported on startup rather than on the first
+/// key press. The device is read back like an input device; if it cannot be opened, the check is
+/// skipped with a warning.
+fn self_test_uinput_device(
+    device: &mut uinput::VirtualDevice,
+    devnode: &Path,
+    device_cfg: &OutputDeviceCfg,
+) -> Result<(), io::Error> {
+    let mut reader = match Device::open(devnode) {
+        Ok(reader) => reader,
+        Err(e) => {
+            log::warn!(
+                "self-test skipped, cannot read the output device {}: {e}",
+                devnode.display()
+            );
+            return Ok(());
+        }
+    };
+    let missing = |what: &str| {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("self-test: the output device does not declare {what}"),
+        )
+    };
+    if !reader
+        .supported_keys()
+        .is_some_and(|keys| keys.contains(evdev::Key::KEY_A))
+    {
+        return Err(missing("keys"));
+    }
+    if device_cfg.rel
+        && !device_cfg.split_mouse
+        && !reader
+            .supported_relative_axes()
+            .is_some_and(|axes| axes.contains(RelativeAxisType::REL_X))
+    {
+        return Err(missing("mouse movement"));
+    }
+    // Only MSC_SCAN can be written without any effect on the system.
+    if !device_cfg.msc {
+        log::info!("self-test: the output device declares its capabilities");
+        return Ok(());
+    }
+    let mut poll = Poll::new()?;
+    poll.registry().register(
+        &mut SourceFd(&reader.as_raw_fd()),
+        Token(0),
+        Interest::READABLE,
+    )?;
+    device.emit(&[InputEvent::new(
+        EventType::MISC,
+        MiscType::MSC_SCAN.0,
+        SELF_TEST_SCANCODE,
+    )])?;
+    let deadline = std::time::Instant::now() + SELF_TEST_TIMEOUT;
+    let mut events = Events::with_capacity(1);
+    loop {
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        if remaining.is_zero() {
+            log::warn!(
+                "self-test: the test event written to the output device was not read back \
+                 within {}ms, key events may not reach the system",
+                SELF_TEST_TIMEOUT.as_millis()
+            );
+            return Ok(());
+        }
+        match poll.poll(&mut events, Some(remaining)) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            result => result?,
+        }
+        if events.is_empty() {
+            continue;
+        }
+        if reader.fetch_events()?.any(|ev| {
+            ev.event_type() == EventType::MISC && ev.value() == SELF_TEST_SCANCODE
+        }) {
+            log::info!("self-test: the output device delivers events");
+            return Ok(());
+        }
+    }
+}
+
+#[test]
+fn uinput_open_errors_explain_the_fix() {
+    let e = uinput_open_error(io::Error::from(io::ErrorKind::NotFound));
+    assert!(e.to_string().contains("modprobe uinput"), "{e}");
+    let e = uinput_open_error(io::Error::from(io::ErrorKind::PermissionDenied));
+    assert!(e.to_string().contains("uinput group"), "{e}");
+    let e = uinput_open_error(io::Error::from(io::ErrorKind::Other));
+    assert_eq!(e.kind(), io::ErrorKind::Other);
+}
+
+/// Create a device that reports absolute coordinates from 0 to 65535 over the whole screen, like
+/// the tablets of virtual machines.
+fn create_pointer_device(device_cfg: &OutputDeviceCfg) -> Result<uinput::VirtualDevice, io::Error> {
+    let axis = |code| {
+        evdev::UinputAbsSetup::new(
+            code,
+            evdev::AbsInfo::new(0, 0, i32::from(u16::MAX), 0, 0, 0),
+        )
+    };
+    let name = format!("{} pointer", device_cfg.name);
+    let mut device = uinput::VirtualDeviceBuilder::new().map_err(uinput_open_error)?
+        .name(&name)
+        .input_id(evdev::InputId::new(
+            evdev::BusType::BUS_USB,
+            device_cfg.vendor_id,
+            device_cfg.product_id,
+            1,
+        ))
+        // Without a button, absolute devices are treated as touchscreens or joysticks rather
+        // than as pointers.
+        .with_keys(&evdev::AttributeSet::from_iter([evdev::Key::BTN_LEFT]))?
+        .with_absolute_axis(&axis(AbsoluteAxisType::ABS_X))?
+        .with_absolute_axis(&axis(AbsoluteAxisType::ABS_Y))?
+        .build()?;
+    VIRTUAL_DEVICE_NAMES.lock().push(name);
+    if let Some(devnode) = device.enumerate_dev_nodes_blocking()?.next() {
+        log::info!("Created pointer device {:#?}", devnode?);
+    }
+    Ok(device)
+}
+
 fn devices_from_input_paths(
     dev_paths: &[String],
     missing_device_paths: &mut Vec<String>,
@@ -539,7 +1655,44 @@ fn devices_from_input_paths(
         .collect()
 }
 
-fn discover_devices() -> Result<Vec<(Device, String)>, io::Error> {
+/// Open the devices of file descriptors that were passed to kanata, e.g. by a container runtime
+/// or by systemd, so that kanata does not need access to `/dev/input`.
+fn devices_from_fds(fds: &[RawFd]) -> Result<Vec<(Device, String)>, io::Error> {
+    fds.iter()
+        .map(|fd| {
+            // evdev only opens devices by path. The link in /proc opens the device of the file
+            // descriptor, even if its device node is not visible to kanata.
+            let path = format!("/proc/self/fd/{fd}");
+            match Device::open(&path) {
+                Ok(device) => Ok((device, path)),
+                Err(e) => Err(io::Error::new(
+                    e.kind(),
+                    format!("failed to open the device of file descriptor {fd}: {e}"),
+                )),
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn devices_from_fds_reports_the_fd() {
+    // A file descriptor that is not an input device, or not open at all.
+    let file = fs::File::open("/dev/null").expect("/dev/null opens");
+    for fd in [file.as_raw_fd(), RawFd::MAX] {
+        let Err(err) = devices_from_fds(&[fd]) else {
+            panic!("{fd} is not an input device");
+        };
+        assert!(
+            err.to_string().contains(&format!("file descriptor {fd}")),
+            "{err}"
+        );
+    }
+}
+
+fn discover_devices(
+    include_media_devices: bool,
+    force: bool,
+) -> Result<Vec<(Device, String)>, io::Error> {
     log::info!("looking for devices in /dev/input");
     let devices: Vec<_> = evdev::enumerate()
         .map(|(path, device)| {
@@ -550,7 +1703,8 @@ fn discover_devices() -> Result<Vec<(Device, String)>, io::Error> {
                     .to_owned(),
             )
         })
-        .filter(|pd| is_input_device(&pd.0))
+        .filter(|pd| is_input_device(&pd.0, include_media_devices))
+        .filter(|(device, path)| !refuses_touch_device(device, path, force))
         .collect();
     if devices.is_empty() {
         return Err(io::Error::new(
@@ -579,7 +1733,6 @@ impl From<Btn> for OsCode {
     }
 }
 
-#[derive(Clone)]
 struct Symlink {
     dest: PathBuf,
 }
@@ -603,23 +1756,6 @@ impl Symlink {
         log::info!("Created symlink {:#?} -> {:#?}", dest, source);
         Ok(Self { dest })
     }
-
-    fn clean_when_killed(symlink: Self) {
-        thread::spawn(|| {
-            let mut signals = Signals::new([SIGINT, SIGTERM]).expect("signals register");
-            for signal in &mut signals {
-                match signal {
-                    SIGINT | SIGTERM => {
-                        drop(symlink);
-                        signal_hook::low_level::emulate_default_handler(signal)
-                            .expect("run original sighandlers");
-                        unreachable!();
-                    }
-                    _ => unreachable!(),
-                }
-            }
-        });
-    }
 }
 
 pub fn parse_dev_paths(paths: &str) -> Vec<String> {
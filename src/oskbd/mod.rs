@@ -1,9 +1,40 @@
 //! Platform specific code for low level keyboard read/write.
 
+mod output_mods;
+pub use output_mods::*;
+
 #[cfg(target_os = "linux")]
 mod linux;
 #[cfg(target_os = "linux")]
 pub use linux::*;
+#[cfg(target_os = "linux")]
+mod helper;
+#[cfg(target_os = "linux")]
+pub use helper::*;
+#[cfg(all(target_os = "linux", feature = "ble_hid"))]
+mod ble;
+#[cfg(all(target_os = "linux", feature = "ble_hid"))]
+pub use ble::*;
+#[cfg(target_os = "linux")]
+mod session;
+#[cfg(target_os = "linux")]
+pub use session::*;
+#[cfg(target_os = "linux")]
+mod suspend;
+#[cfg(target_os = "linux")]
+pub use suspend::*;
+#[cfg(all(target_os = "linux", feature = "wayland"))]
+mod wayland;
+#[cfg(all(target_os = "linux", feature = "wayland"))]
+pub use wayland::*;
+#[cfg(target_os = "linux")]
+mod xkb;
+#[cfg(target_os = "linux")]
+pub use xkb::*;
+#[cfg(all(target_os = "linux", feature = "xtest"))]
+mod xtest;
+#[cfg(all(target_os = "linux", feature = "xtest"))]
+pub use xtest::*;
 
 #[cfg(target_os = "windows")]
 mod windows;
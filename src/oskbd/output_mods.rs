@@ -0,0 +1,57 @@
+//! The modifiers that kanata holds down on the output, as far as it knows.
+//!
+//! Kanata cannot read the modifier state of the OS, so it tracks the modifier presses and releases
+//! that it writes. The state of the OS can still differ, e.g. when a virtual machine grabs the
+//! keyboard while a modifier is held or the X server restarts. `resync-modifiers` then releases
+//! every modifier on the output.
+
+use crate::keys::*;
+
+/// The modifiers that are tracked and released by a resync.
+pub const OUTPUT_MODIFIERS: [OsCode; 8] = [
+    OsCode::KEY_LEFTSHIFT,
+    OsCode::KEY_RIGHTSHIFT,
+    OsCode::KEY_LEFTCTRL,
+    OsCode::KEY_RIGHTCTRL,
+    OsCode::KEY_LEFTALT,
+    OsCode::KEY_RIGHTALT,
+    OsCode::KEY_LEFTMETA,
+    OsCode::KEY_RIGHTMETA,
+];
+
+#[derive(Debug, Default)]
+pub struct OutputModifiers {
+    held: Vec<OsCode>,
+}
+
+impl OutputModifiers {
+    /// Record a key event that was written to the output.
+    pub fn record(&mut self, key: OsCode, value: KeyValue) {
+        if !OUTPUT_MODIFIERS.contains(&key) {
+            return;
+        }
+        match value {
+            KeyValue::Press if !self.held.contains(&key) => self.held.push(key),
+            KeyValue::Release => self.held.retain(|k| *k != key),
+            _ => {}
+        }
+    }
+
+    /// The modifiers that kanata pressed and did not release, in the order they were pressed.
+    pub fn held(&self) -> &[OsCode] {
+        &self.held
+    }
+}
+
+#[test]
+fn output_modifiers_track_presses_and_releases() {
+    let mut mods = OutputModifiers::default();
+    mods.record(OsCode::KEY_LEFTSHIFT, KeyValue::Press);
+    mods.record(OsCode::KEY_A, KeyValue::Press);
+    mods.record(OsCode::KEY_RIGHTALT, KeyValue::Press);
+    mods.record(OsCode::KEY_LEFTSHIFT, KeyValue::Repeat);
+    assert_eq!(mods.held(), [OsCode::KEY_LEFTSHIFT, OsCode::KEY_RIGHTALT]);
+    mods.record(OsCode::KEY_LEFTSHIFT, KeyValue::Release);
+    mods.record(OsCode::KEY_LEFTCTRL, KeyValue::Release);
+    assert_eq!(mods.held(), [OsCode::KEY_RIGHTALT]);
+}
@@ -0,0 +1,231 @@
+//! Tracking of whether the logind session that kanata belongs to is the active session of its
+//! seat.
+//!
+//! When another session becomes active, e.g. after switching VTs or on a login screen of another
+//! seat user, kanata releases its grabbed devices so that it does not interfere with the other
+//! session. The devices are grabbed again when the session becomes active again.
+//!
+//! The session state is read from the session files that logind maintains in
+//! `/run/systemd/sessions`. logind officially exposes this state over D-Bus, but the files avoid a
+//! D-Bus dependency and can be watched with inotify like `/dev/input` already is.
+//!
+//! Whether the screen of the session is locked is not in the session files, so it is followed
+//! with `gdbus monitor` instead, see [`watch_screen_lock`].
+
+use inotify::{Inotify, WatchMask};
+
+use std::io::{self, BufRead, BufReader};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+const SESSIONS_DIR: &str = "/run/systemd/sessions";
+/// Value of `/proc/self/sessionid` for processes that do not belong to a session.
+const NO_AUDIT_SESSION: &str = "4294967295";
+
+pub struct SessionWatcher {
+    session_file: PathBuf,
+    inotify: Inotify,
+    active: bool,
+}
+
+impl SessionWatcher {
+    /// Watch the session with the given ID. Without an ID, the session kanata was started in is
+    /// used.
+    pub fn new(session_id: Option<&str>) -> Result<Self, io::Error> {
+        let session_id = session_id_or_own(session_id)?;
+        let session_file = PathBuf::from(SESSIONS_DIR).join(&session_id);
+        let text = std::fs::read_to_string(&session_file).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("failed to read logind session {session_id}: {e}"),
+            )
+        })?;
+        let mut inotify = Inotify::init()?;
+        // logind replaces the session files by renaming a temporary file.
+        inotify.add_watch(
+            SESSIONS_DIR,
+            WatchMask::MOVED_TO | WatchMask::CLOSE_WRITE | WatchMask::DELETE,
+        )?;
+        let active = session_is_active(&text);
+        log::info!(
+            "watching logind session {session_id}, which is {}",
+            if active { "active" } else { "inactive" }
+        );
+        Ok(Self {
+            session_file,
+            inotify,
+            active,
+        })
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Read the session state after the inotify file descriptor became readable. Returns whether
+    /// the session became active or inactive.
+    pub fn update(&mut self) -> Option<bool> {
+        let mut buf = [0; 1024];
+        // Drain the events; which file changed does not matter since reading the session file is
+        // cheap.
+        while let Ok(events) = self.inotify.read_events(&mut buf) {
+            if events.count() == 0 {
+                break;
+            }
+        }
+        // A missing session file means the session ended, which is treated as inactive.
+        let active = std::fs::read_to_string(&self.session_file)
+            .map(|text| session_is_active(&text))
+            .unwrap_or(false);
+        if active == self.active {
+            return None;
+        }
+        self.active = active;
+        Some(active)
+    }
+}
+
+impl AsRawFd for SessionWatcher {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inotify.as_raw_fd()
+    }
+}
+
+/// Watch whether the screen of the session with the given ID, or of the session kanata was
+/// started in, is locked. `changed` is called from another thread with the new state.
+///
+/// logind reports the lock with the `Lock` and `Unlock` signals, sent by e.g. `loginctl
+/// lock-session`, and with the `LockedHint` property that screen lockers set. Both are read from
+/// the output of `gdbus monitor`, which only needs the permissions of a normal user.
+pub fn watch_screen_lock(
+    session_id: Option<&str>,
+    mut changed: impl FnMut(bool) + Send + 'static,
+) -> Result<(), io::Error> {
+    let session_id = session_id_or_own(session_id)?;
+    let mut child = Command::new("gdbus")
+        .args(["monitor", "--system", "--dest", "org.freedesktop.login1"])
+        .arg("--object-path")
+        .arg(session_object_path(&session_id))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("failed to run gdbus monitor: {e}")))?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    log::info!("watching the screen lock of logind session {session_id}");
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if let Some(locked) = screen_lock_change(&line) {
+                changed(locked);
+            }
+        }
+        let _ = child.wait();
+        log::warn!("gdbus monitor exited, the screen lock is no longer followed");
+    });
+    Ok(())
+}
+
+/// Returns whether the line of `gdbus monitor` reports that the screen was locked or unlocked.
+fn screen_lock_change(line: &str) -> Option<bool> {
+    let (_, signal) = line.split_once(": ")?;
+    if signal.starts_with("org.freedesktop.login1.Session.Lock ") {
+        Some(true)
+    } else if signal.starts_with("org.freedesktop.login1.Session.Unlock ") {
+        Some(false)
+    } else if signal.starts_with("org.freedesktop.DBus.Properties.PropertiesChanged ") {
+        match signal.split_once("'LockedHint': <")?.1 {
+            hint if hint.starts_with("true>") => Some(true),
+            hint if hint.starts_with("false>") => Some(false),
+            _ => None,
+        }
+    } else {
+        None
+    }
+}
+
+/// The D-Bus object path of the session, with the ID escaped like logind does: characters other
+/// than letters, and digits at the start, are replaced with `_` and their hexadecimal value.
+fn session_object_path(session_id: &str) -> String {
+    let mut path = String::from("/org/freedesktop/login1/session/");
+    for (i, b) in session_id.bytes().enumerate() {
+        if b.is_ascii_alphabetic() || (i > 0 && b.is_ascii_digit()) {
+            path.push(char::from(b));
+        } else {
+            path.push_str(&format!("_{b:02x}"));
+        }
+    }
+    if session_id.is_empty() {
+        path.push('_');
+    }
+    path
+}
+
+fn session_id_or_own(session_id: Option<&str>) -> Result<String, io::Error> {
+    match session_id {
+        Some(id) => Ok(id.to_owned()),
+        None => own_session_id().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "kanata is not running in a logind session, set linux-session-id",
+            )
+        }),
+    }
+}
+
+fn own_session_id() -> Option<String> {
+    if let Ok(id) = std::env::var("XDG_SESSION_ID") {
+        return Some(id);
+    }
+    let id = std::fs::read_to_string("/proc/self/sessionid").ok()?;
+    let id = id.trim();
+    (id != NO_AUDIT_SESSION).then(|| id.to_owned())
+}
+
+fn session_is_active(session_file: &str) -> bool {
+    session_file.lines().any(|line| line == "ACTIVE=1")
+}
+
+#[test]
+fn session_file_active_state() {
+    let session = "# This is private data. Do not parse.\nUID=1000\nUSER=user\nACTIVE=1\n\
+                   IS_DISPLAY=1\nSTATE=active\nSEAT=seat0\nVTNR=2\n";
+    assert!(session_is_active(session));
+    let session = session.replace("ACTIVE=1", "ACTIVE=0");
+    assert!(!session_is_active(&session));
+}
+
+#[test]
+fn screen_lock_from_gdbus_monitor() {
+    assert_eq!(session_object_path("2"), "/org/freedesktop/login1/session/_32");
+    assert_eq!(session_object_path("c12"), "/org/freedesktop/login1/session/c12");
+    let path = "/org/freedesktop/login1/session/_32";
+    assert_eq!(
+        screen_lock_change(&format!("{path}: org.freedesktop.login1.Session.Lock ()")),
+        Some(true)
+    );
+    assert_eq!(
+        screen_lock_change(&format!("{path}: org.freedesktop.login1.Session.Unlock ()")),
+        Some(false)
+    );
+    assert_eq!(
+        screen_lock_change(&format!(
+            "{path}: org.freedesktop.DBus.Properties.PropertiesChanged \
+             ('org.freedesktop.login1.Session', {{'LockedHint': <true>}}, @as [])"
+        )),
+        Some(true)
+    );
+    assert_eq!(
+        screen_lock_change(&format!(
+            "{path}: org.freedesktop.DBus.Properties.PropertiesChanged \
+             ('org.freedesktop.login1.Session', {{'IdleHint': <true>}}, @as [])"
+        )),
+        None
+    );
+    assert_eq!(
+        screen_lock_change("Monitoring signals on object /org/freedesktop/login1/session/_32"),
+        None
+    );
+}
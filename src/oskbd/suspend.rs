@@ -0,0 +1,62 @@
+//! Detection of the system resuming from suspend.
+//!
+//! `CLOCK_BOOTTIME` keeps advancing while the system is suspended and `CLOCK_MONOTONIC` does not,
+//! so the difference between them grows by the duration of every suspend. Comparing the
+//! difference between checks tells whether the system was suspended in between, without having
+//! to listen to logind over D-Bus.
+
+use std::time::Duration;
+
+/// Suspends shorter than this are not detected. Reading the two clocks is not atomic, so the
+/// difference jitters slightly between checks.
+const MIN_SUSPEND: Duration = Duration::from_secs(1);
+
+pub struct ResumeDetector {
+    suspended: Duration,
+}
+
+impl Default for ResumeDetector {
+    fn default() -> Self {
+        Self {
+            suspended: suspended_time(),
+        }
+    }
+}
+
+impl ResumeDetector {
+    /// Returns true if the system was suspended since the previous check.
+    pub fn check(&mut self) -> bool {
+        self.update(suspended_time())
+    }
+
+    fn update(&mut self, suspended: Duration) -> bool {
+        let resumed = suspended.saturating_sub(self.suspended) >= MIN_SUSPEND;
+        self.suspended = suspended;
+        resumed
+    }
+}
+
+/// Total time the system has spent suspended since boot.
+fn suspended_time() -> Duration {
+    clock(libc::CLOCK_BOOTTIME).saturating_sub(clock(libc::CLOCK_MONOTONIC))
+}
+
+fn clock(id: libc::clockid_t) -> Duration {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // Both clocks are always available on Linux, so this cannot fail.
+    unsafe { libc::clock_gettime(id, &mut ts) };
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+#[test]
+fn resume_detected_from_suspended_time() {
+    let mut detector = ResumeDetector {
+        suspended: Duration::from_secs(10),
+    };
+    assert!(!detector.update(Duration::from_millis(10_001)));
+    assert!(detector.update(Duration::from_secs(70)));
+    assert!(!detector.update(Duration::from_secs(70)));
+}
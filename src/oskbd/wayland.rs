@@ -0,0 +1,174 @@
+//! The `wayland` output backend: key events go to the compositor through the virtual keyboard
+//! protocol, `zwp_virtual_keyboard_v1`, instead of a uinput device.
+//!
+//! This needs no access to `/dev/uinput`, but kanata must run inside the Wayland session and the
+//! compositor must offer the protocol, e.g. wlroots-based compositors do. The connection is made
+//! with `wayland-client`, and the protocol comes from `wayland-protocols-misc`, so the backend
+//! needs the `wayland` cargo feature.
+//!
+//! The compositor interprets the keycodes with the XKB keymap that is sent on creation and it
+//! tracks the modifier state from the key events itself. Key repeat is done by the clients that
+//! receive the events, so repeat events are not sent.
+
+use evdev::{EventType, InputEvent};
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
+use wayland_client::protocol::{wl_keyboard, wl_registry, wl_seat};
+use wayland_client::{delegate_noop, Connection, Dispatch, EventQueue, QueueHandle, WaylandError};
+use wayland_protocols_misc::zwp_virtual_keyboard_v1::client::{
+    zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1,
+    zwp_virtual_keyboard_v1::ZwpVirtualKeyboardV1,
+};
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::unix::io::{AsFd, FromRawFd};
+use std::time::Instant;
+
+use super::{OutputCapabilities, OutputSink};
+
+/// A virtual keyboard created through the Wayland virtual keyboard protocol.
+pub struct WaylandKeyboard {
+    conn: Connection,
+    queue: EventQueue<State>,
+    keyboard: ZwpVirtualKeyboardV1,
+    start: Instant,
+}
+
+/// The objects of kanata get no events that need handling.
+struct State;
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &wl_registry::WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+delegate_noop!(State: ignore wl_seat::WlSeat);
+delegate_noop!(State: ZwpVirtualKeyboardManagerV1);
+delegate_noop!(State: ZwpVirtualKeyboardV1);
+
+impl WaylandKeyboard {
+    /// Connect to the compositor of the current session and create a virtual keyboard that uses
+    /// the given XKB layout, e.g. `us` or `de(nodeadkeys)`.
+    pub fn connect(xkb_layout: &str) -> Result<Self, io::Error> {
+        let conn = Connection::connect_to_env().map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("cannot connect to the Wayland compositor: {e}"),
+            )
+        })?;
+        let (globals, mut queue) = registry_queue_init::<State>(&conn).map_err(io::Error::other)?;
+        let qh = queue.handle();
+        let seat: wl_seat::WlSeat = globals.bind(&qh, 1..=1, ()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "the Wayland compositor has no seat",
+            )
+        })?;
+        let manager: ZwpVirtualKeyboardManagerV1 = globals.bind(&qh, 1..=1, ()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the Wayland compositor does not support zwp_virtual_keyboard_manager_v1",
+            )
+        })?;
+        let keyboard = manager.create_virtual_keyboard(&seat, &qh, ());
+        let (keymap, keymap_size) = keymap_file(xkb_layout)?;
+        keyboard.keymap(
+            wl_keyboard::KeymapFormat::XkbV1.into(),
+            keymap.as_fd(),
+            keymap_size,
+        );
+        // The compositor reports e.g. an unauthorized client with an error, so wait until the
+        // requests above have been processed.
+        queue.roundtrip(&mut State).map_err(io::Error::other)?;
+        log::info!("Created Wayland virtual keyboard with layout {xkb_layout}");
+        Ok(Self {
+            conn,
+            queue,
+            keyboard,
+            start: Instant::now(),
+        })
+    }
+
+    /// Read the events that are available without blocking. The compositor does not send
+    /// anything relevant after the setup except for errors, but the socket buffer must not fill
+    /// up.
+    fn dispatch_pending(&mut self) -> Result<(), io::Error> {
+        if let Some(guard) = self.queue.prepare_read() {
+            match guard.read() {
+                Ok(_) => {}
+                Err(WaylandError::Io(e)) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(io::Error::other(e)),
+            }
+        }
+        self.queue
+            .dispatch_pending(&mut State)
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+}
+
+impl OutputSink for WaylandKeyboard {
+    fn emit(&mut self, events: &[InputEvent]) -> Result<(), io::Error> {
+        self.dispatch_pending()?;
+        let time = self.start.elapsed().as_millis() as u32;
+        for event in events {
+            if event.event_type() != EventType::KEY || event.value() > 1 {
+                continue;
+            }
+            self.keyboard
+                .key(time, u32::from(event.code()), event.value() as u32);
+        }
+        self.conn.flush().map_err(io::Error::other)
+    }
+
+    fn capabilities(&self) -> OutputCapabilities {
+        // The virtual keyboard protocol only has keys.
+        OutputCapabilities::default()
+    }
+}
+
+/// Returns an XKB keymap that uses the given layout, relying on the compositor's XKB data for
+/// everything else.
+fn keymap(xkb_layout: &str) -> String {
+    format!(
+        "xkb_keymap {{
+    xkb_keycodes {{ include \"evdev+aliases(qwerty)\" }};
+    xkb_types {{ include \"complete\" }};
+    xkb_compat {{ include \"complete\" }};
+    xkb_symbols {{ include \"pc+{xkb_layout}+inet(evdev)\" }};
+}};
+"
+    )
+}
+
+/// Write the keymap to a memory file that can be passed to the compositor. The size includes the
+/// terminating NUL byte that the compositor expects.
+fn keymap_file(xkb_layout: &str) -> Result<(File, u32), io::Error> {
+    let fd = unsafe { libc::memfd_create(c"kanata-keymap".as_ptr(), libc::MFD_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    let mut keymap = keymap(xkb_layout).into_bytes();
+    keymap.push(0);
+    file.write_all(&keymap)?;
+    Ok((file, keymap.len() as u32))
+}
+
+#[test]
+fn wayland_keymap_uses_the_layout() {
+    let (mut file, size) = keymap_file("de(nodeadkeys)").unwrap();
+    let mut keymap = String::new();
+    io::Seek::rewind(&mut file).unwrap();
+    io::Read::read_to_string(&mut file, &mut keymap).unwrap();
+    assert_eq!(keymap.len(), size as usize);
+    assert!(keymap.ends_with('\0'));
+    assert!(keymap.contains("\"pc+de(nodeadkeys)+inet(evdev)\""));
+}
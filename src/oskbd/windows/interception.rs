@@ -6,6 +6,7 @@ use kanata_interception::{Interception, KeyState, MouseFlags, MouseState, Stroke
 
 use crate::custom_action::*;
 use crate::keys::*;
+use crate::oskbd::OutputModifiers;
 
 /// Key event received by the low level keyboard hook.
 #[derive(Debug, Clone, Copy)]
@@ -108,7 +109,10 @@ thread_local! {
 }
 
 /// Handle for writing keys to the OS.
-pub struct KbdOut {}
+pub struct KbdOut {
+    /// The modifiers written to the OS that are still pressed.
+    pub modifiers: OutputModifiers,
+}
 
 fn write_interception(event: InputEvent) {
     let strokes = [event.0];
@@ -130,7 +134,9 @@ fn write_interception(event: InputEvent) {
 
 impl KbdOut {
     pub fn new() -> Result<Self, io::Error> {
-        Ok(Self {})
+        Ok(Self {
+            modifiers: OutputModifiers::default(),
+        })
     }
 
     pub fn write(&mut self, event: InputEvent) -> Result<(), io::Error> {
@@ -143,6 +149,10 @@ impl KbdOut {
     }
 
     pub fn write_key(&mut self, key: OsCode, value: KeyValue) -> Result<(), io::Error> {
+        if is_extra_key(key) {
+            return Ok(());
+        }
+        self.modifiers.record(key, value);
         self.write(InputEvent::from_oscode(key, value))
     }
 
@@ -14,6 +14,7 @@ use winapi::um::winuser::*;
 
 use crate::custom_action::*;
 use crate::keys::*;
+use crate::oskbd::OutputModifiers;
 
 type HookFn = dyn FnMut(InputEvent) -> bool;
 
@@ -124,11 +125,16 @@ unsafe extern "system" fn hook_proc(code: c_int, wparam: WPARAM, lparam: LPARAM)
 }
 
 /// Handle for writing keys to the OS.
-pub struct KbdOut {}
+pub struct KbdOut {
+    /// The modifiers written to the OS that are still pressed.
+    pub modifiers: OutputModifiers,
+}
 
 impl KbdOut {
     pub fn new() -> Result<Self, io::Error> {
-        Ok(Self {})
+        Ok(Self {
+            modifiers: OutputModifiers::default(),
+        })
     }
 
     pub fn write(&mut self, event: InputEvent) -> Result<(), io::Error> {
@@ -137,6 +143,10 @@ impl KbdOut {
     }
 
     pub fn write_key(&mut self, key: OsCode, value: KeyValue) -> Result<(), io::Error> {
+        if is_extra_key(key) {
+            return Ok(());
+        }
+        self.modifiers.record(key, value);
         let event = InputEvent::from_oscode(key, value);
         self.write(event)
     }
@@ -47,6 +47,13 @@ fn send_uc(c: char, up: bool) {
     }
 }
 
+/// The position of the pointer on the virtual screen, which spans all monitors.
+pub fn pointer_position() -> Option<(i32, i32)> {
+    let mut point = winapi::shared::windef::POINT { x: 0, y: 0 };
+    let ok = unsafe { GetCursorPos(&mut point) };
+    (ok != 0).then_some((point.x, point.y))
+}
+
 fn write_code(code: u16, value: KeyValue) -> Result<(), std::io::Error> {
     send_key_sendinput(
         code,
@@ -0,0 +1,93 @@
+//! Detection of the XKB keyboard layout of the system.
+//!
+//! Kanata outputs key codes, which the desktop translates to characters with its own keyboard
+//! layout. A configuration that is written for one layout outputs different characters with
+//! another layout, e.g. a symbols layer written for US QWERTY types other symbols on AZERTY. The
+//! layout of the running session can only be queried through the display server, so the
+//! configured layout is read from the places where the system and compositors keep it instead.
+
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DetectedXkbLayout {
+    /// The first layout of the configured list, which is the active one by default.
+    pub layout: String,
+    /// Where the layout was read from, for logging.
+    pub source: &'static str,
+}
+
+/// Detect the configured XKB layout, checked in the order the desktop would apply them.
+pub fn detect_xkb_layout() -> Option<DetectedXkbLayout> {
+    let detected = |layout: &str, source| {
+        first_layout(layout).map(|layout| DetectedXkbLayout {
+            layout: layout.to_owned(),
+            source,
+        })
+    };
+    if let Ok(layout) = std::env::var("XKB_DEFAULT_LAYOUT") {
+        return detected(&layout, "XKB_DEFAULT_LAYOUT");
+    }
+    let read = |path: &str| std::fs::read_to_string(Path::new(path)).ok();
+    if let Some(layout) = read("/etc/X11/xorg.conf.d/00-keyboard.conf")
+        .as_deref()
+        .and_then(xorg_conf_layout)
+    {
+        return detected(layout, "/etc/X11/xorg.conf.d/00-keyboard.conf");
+    }
+    if let Some(layout) = read("/etc/default/keyboard")
+        .as_deref()
+        .and_then(|text| shell_var(text, "XKBLAYOUT"))
+    {
+        return detected(layout, "/etc/default/keyboard");
+    }
+    if let Some(layout) = read("/etc/vconsole.conf")
+        .as_deref()
+        .and_then(|text| shell_var(text, "XKBLAYOUT"))
+    {
+        return detected(layout, "/etc/vconsole.conf");
+    }
+    None
+}
+
+/// Returns the layout name of the first layout in an XKB layout list, without the variant, e.g.
+/// `de` for `de(nodeadkeys),us`.
+pub fn first_layout(layouts: &str) -> Option<&str> {
+    let layout = layouts.split(',').next()?.trim();
+    let layout = layout.split('(').next()?.trim();
+    (!layout.is_empty()).then_some(layout)
+}
+
+/// Reads `Option "XkbLayout" "<layout>"` from an X11 config as written by localectl.
+fn xorg_conf_layout(text: &str) -> Option<&str> {
+    text.lines().find_map(|line| {
+        let mut parts = line.split('"').map(str::trim).filter(|p| !p.is_empty());
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some("Option"), Some("XkbLayout"), Some(layout)) => Some(layout),
+            _ => None,
+        }
+    })
+}
+
+/// Reads `NAME=value` or `NAME="value"` from a shell variable file.
+fn shell_var<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    text.lines().find_map(|line| {
+        let value = line.trim().strip_prefix(name)?.strip_prefix('=')?;
+        Some(value.trim_matches('"'))
+    })
+}
+
+#[test]
+fn xkb_layout_from_config_files() {
+    let xorg = r#"Section "InputClass"
+        Identifier "system-keyboard"
+        MatchIsKeyboard "on"
+        Option "XkbLayout" "fr,us"
+        Option "XkbVariant" "azerty,"
+EndSection"#;
+    assert_eq!(xorg_conf_layout(xorg).and_then(first_layout), Some("fr"));
+    let debian = "XKBMODEL=\"pc105\"\nXKBLAYOUT=\"de\"\nXKBVARIANT=\"\"\n";
+    assert_eq!(shell_var(debian, "XKBLAYOUT"), Some("de"));
+    assert_eq!(shell_var(debian, "XKBVARIANT"), Some(""));
+    assert_eq!(first_layout("de(nodeadkeys),us"), Some("de"));
+    assert_eq!(first_layout(""), None);
+}
@@ -0,0 +1,251 @@
+//! Output of events through the X11 XTEST extension.
+//!
+//! This lets kanata output events without access to `/dev/uinput` when it runs inside an X11
+//! session. The events are injected into the X server, so they only reach X11 clients, and the X
+//! server interprets the keycodes with its own keymap. X11 keycodes are the evdev keycodes offset
+//! by 8, which is the case for every X server that uses the evdev or libinput drivers.
+//!
+//! The same feature reads the position of the X11 pointer, see [`X11Pointer`].
+
+use evdev::{EventType, InputEvent, RelativeAxisType};
+
+use std::io;
+use std::os::raw::{c_char, c_int, c_uint, c_ulong};
+
+use super::{OutputCapabilities, OutputSink};
+
+#[repr(C)]
+struct Display {
+    _private: [u8; 0],
+}
+
+const CURRENT_TIME: c_ulong = 0;
+const X_TRUE: c_int = 1;
+const X_FALSE: c_int = 0;
+const EVDEV_TO_X11_KEYCODE_OFFSET: u32 = 8;
+
+#[link(name = "X11")]
+extern "C" {
+    fn XOpenDisplay(name: *const c_char) -> *mut Display;
+    fn XCloseDisplay(display: *mut Display) -> c_int;
+    fn XFlush(display: *mut Display) -> c_int;
+    fn XDefaultRootWindow(display: *mut Display) -> c_ulong;
+    #[allow(clippy::too_many_arguments)]
+    fn XQueryPointer(
+        display: *mut Display,
+        window: c_ulong,
+        root_return: *mut c_ulong,
+        child_return: *mut c_ulong,
+        root_x_return: *mut c_int,
+        root_y_return: *mut c_int,
+        win_x_return: *mut c_int,
+        win_y_return: *mut c_int,
+        mask_return: *mut c_uint,
+    ) -> c_int;
+}
+
+#[link(name = "Xtst")]
+extern "C" {
+    fn XTestQueryExtension(
+        display: *mut Display,
+        event_base: *mut c_int,
+        error_base: *mut c_int,
+        major: *mut c_int,
+        minor: *mut c_int,
+    ) -> c_int;
+    fn XTestFakeKeyEvent(
+        display: *mut Display,
+        keycode: c_uint,
+        is_press: c_int,
+        delay: c_ulong,
+    ) -> c_int;
+    fn XTestFakeButtonEvent(
+        display: *mut Display,
+        button: c_uint,
+        is_press: c_int,
+        delay: c_ulong,
+    ) -> c_int;
+    fn XTestFakeRelativeMotionEvent(
+        display: *mut Display,
+        x: c_int,
+        y: c_int,
+        delay: c_ulong,
+    ) -> c_int;
+}
+
+/// A connection to the X server of the current session that injects events with XTEST.
+pub struct XtestOutput {
+    display: *mut Display,
+}
+
+// The display connection is only used by the owner of the output, which is behind a mutex.
+unsafe impl Send for XtestOutput {}
+
+impl XtestOutput {
+    /// Connect to the X server named by the `DISPLAY` environment variable.
+    pub fn connect() -> Result<Self, io::Error> {
+        let display = unsafe { XOpenDisplay(std::ptr::null()) };
+        if display.is_null() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "failed to connect to the X server, check that DISPLAY is set",
+            ));
+        }
+        let output = Self { display };
+        let (mut event_base, mut error_base, mut major, mut minor) = (0, 0, 0, 0);
+        let has_xtest = unsafe {
+            XTestQueryExtension(
+                display,
+                &mut event_base,
+                &mut error_base,
+                &mut major,
+                &mut minor,
+            )
+        };
+        if has_xtest == X_FALSE {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the X server does not support the XTEST extension",
+            ));
+        }
+        log::info!("Connected to the X server, XTEST version {major}.{minor}");
+        Ok(output)
+    }
+
+    fn output(&mut self, event: &InputEvent) {
+        let value = event.value();
+        match event.event_type() {
+            EventType::KEY => {
+                let is_press = match value {
+                    0 => X_FALSE,
+                    1 => X_TRUE,
+                    // The X server does key repeat itself.
+                    _ => return,
+                };
+                match x11_button(event.code()) {
+                    Some(button) => unsafe {
+                        XTestFakeButtonEvent(self.display, button, is_press, CURRENT_TIME);
+                    },
+                    None => unsafe {
+                        XTestFakeKeyEvent(
+                            self.display,
+                            u32::from(event.code()) + EVDEV_TO_X11_KEYCODE_OFFSET,
+                            is_press,
+                            CURRENT_TIME,
+                        );
+                    },
+                }
+            }
+            EventType::RELATIVE => match RelativeAxisType(event.code()) {
+                RelativeAxisType::REL_X => unsafe {
+                    XTestFakeRelativeMotionEvent(self.display, value, 0, CURRENT_TIME);
+                },
+                RelativeAxisType::REL_Y => unsafe {
+                    XTestFakeRelativeMotionEvent(self.display, 0, value, CURRENT_TIME);
+                },
+                RelativeAxisType::REL_WHEEL => self.scroll(value, 4, 5),
+                RelativeAxisType::REL_HWHEEL => self.scroll(value, 7, 6),
+                // X11 only knows about scroll clicks, which are also output as REL_WHEEL and
+                // REL_HWHEEL.
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    /// Scrolling in X11 is done by clicking the scroll buttons once per notch.
+    fn scroll(&mut self, notches: i32, positive_button: c_uint, negative_button: c_uint) {
+        let button = if notches > 0 {
+            positive_button
+        } else {
+            negative_button
+        };
+        for _ in 0..notches.unsigned_abs() {
+            unsafe {
+                XTestFakeButtonEvent(self.display, button, X_TRUE, CURRENT_TIME);
+                XTestFakeButtonEvent(self.display, button, X_FALSE, CURRENT_TIME);
+            }
+        }
+    }
+}
+
+impl OutputSink for XtestOutput {
+    fn emit(&mut self, events: &[InputEvent]) -> Result<(), io::Error> {
+        for event in events {
+            self.output(event);
+        }
+        unsafe { XFlush(self.display) };
+        Ok(())
+    }
+
+    fn capabilities(&self) -> OutputCapabilities {
+        OutputCapabilities {
+            mouse_buttons: true,
+            mouse_movement: true,
+            absolute_pointer: false,
+        }
+    }
+}
+
+impl Drop for XtestOutput {
+    fn drop(&mut self) {
+        unsafe { XCloseDisplay(self.display) };
+    }
+}
+
+/// A connection to the X server of the current session that reads the pointer position.
+pub struct X11Pointer {
+    display: *mut Display,
+}
+
+impl X11Pointer {
+    /// Connect to the X server named by the `DISPLAY` environment variable.
+    pub fn connect() -> Result<Self, io::Error> {
+        let display = unsafe { XOpenDisplay(std::ptr::null()) };
+        if display.is_null() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "failed to connect to the X server, check that DISPLAY is set",
+            ));
+        }
+        Ok(Self { display })
+    }
+
+    /// The position of the pointer on the root window, which spans all monitors.
+    pub fn position(&self) -> Option<(i32, i32)> {
+        let (mut root, mut child) = (0, 0);
+        let (mut x, mut y, mut win_x, mut win_y, mut mask) = (0, 0, 0, 0, 0);
+        let on_screen = unsafe {
+            XQueryPointer(
+                self.display,
+                XDefaultRootWindow(self.display),
+                &mut root,
+                &mut child,
+                &mut x,
+                &mut y,
+                &mut win_x,
+                &mut win_y,
+                &mut mask,
+            )
+        };
+        (on_screen == X_TRUE).then_some((x, y))
+    }
+}
+
+impl Drop for X11Pointer {
+    fn drop(&mut self) {
+        unsafe { XCloseDisplay(self.display) };
+    }
+}
+
+/// Returns the X11 pointer button for an evdev mouse button code.
+fn x11_button(code: u16) -> Option<c_uint> {
+    Some(match code {
+        0x110 => 1, // BTN_LEFT
+        0x111 => 3, // BTN_RIGHT
+        0x112 => 2, // BTN_MIDDLE
+        0x113 => 8, // BTN_SIDE
+        0x114 => 9, // BTN_EXTRA
+        _ => return None,
+    })
+}
@@ -0,0 +1,140 @@
+//! `kanata palette`: the actions that the keys of a running kanata instance trigger right now, as
+//! a menu for rofi or dmenu.
+//!
+//! Every mapped key whose action on the active layers does something other than output the key
+//! itself is printed as one line, `<layer> | <key> | <action>`, where the layer is the one whose
+//! action wins and the action is its description from `deflayerdoc` if it has one. With `--run`, the line that the menu printed is read from stdin and its key is
+//! tapped through the TCP server with `InjectKeys`, so the action runs as if the key was pressed:
+//!
+//! ```sh
+//! kanata palette -p 5829 | rofi -dmenu | kanata palette -p 5829 --run
+//! ```
+
+use crate::cfg::{BorrowedKLayout, LayerInfo, MappedKeys};
+use crate::explain::describe_action;
+use crate::keys::{str_to_oscode, OsCode, KEY_NAMES};
+use crate::tcp_client::KanataClient;
+use crate::tcp_server::{ClientMessage, PaletteEntry, ServerMessage};
+
+use anyhow::Result;
+use kanata_keyberon::action::Action;
+use kanata_keyberon::key_code::KeyCode;
+
+const SEPARATOR: &str = " | ";
+
+/// Print the palette of the kanata instance on the port, or with `run`, tap the key of the
+/// palette line read from stdin.
+pub fn run(port: u16, run: bool) -> Result<()> {
+    let mut client = KanataClient::connect(port)?;
+    if run {
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line)?;
+        let Some(key) = line.split(SEPARATOR).nth(1) else {
+            // The menu was closed without a choice.
+            return Ok(());
+        };
+        let request = ClientMessage::InjectKeys {
+            keys: key.trim().to_owned(),
+            output: false,
+        };
+        client.send(&request)?;
+        return client.confirm();
+    }
+    let entries = client.request(&ClientMessage::RequestPalette, "palette", |msg| match msg {
+        ServerMessage::Palette { entries } => Some(entries),
+        _ => None,
+    })?;
+    for entry in entries {
+        println!(
+            "{}{SEPARATOR}{}{SEPARATOR}{}",
+            entry.layer,
+            entry.key,
+            entry.description.unwrap_or(entry.action)
+        );
+    }
+    Ok(())
+}
+
+/// The winning actions of the mapped keys with the given keyberon layers active, bottom first,
+/// leaving out the keys that are transparent on every layer or output themselves.
+pub fn palette_entries(
+    layout: &BorrowedKLayout,
+    layer_info: &[LayerInfo],
+    stack: &[usize],
+    mapped_keys: &MappedKeys,
+) -> Vec<PaletteEntry> {
+    let mut keys = mapped_keys.iter().copied().collect::<Vec<_>>();
+    keys.sort_by_key(|k| u16::from(*k));
+    keys.into_iter()
+        .filter_map(|key| {
+            let (layer, action) = stack.iter().rev().find_map(|&idx| {
+                let action = &layout.layers[idx][0][usize::from(u16::from(key))];
+                (!matches!(action, Action::Trans)).then_some((idx, action))
+            })?;
+            match action {
+                Action::NoOp => return None,
+                Action::KeyCode(kc) if *kc == KeyCode::from(key) => return None,
+                _ => {}
+            }
+            let action = describe_action(action, layer_info)
+                .lines()
+                .map(str::trim)
+                .collect::<Vec<_>>()
+                .join(", ");
+            Some(PaletteEntry {
+                layer: layer_info[layer].name.clone(),
+                key: key_name(key),
+                action,
+                description: layer_info[layer].doc.key(key).map(str::to_owned),
+            })
+        })
+        .collect()
+}
+
+/// The first name of the key in the configuration language, which `InjectKeys` accepts.
+pub fn key_name(key: OsCode) -> String {
+    KEY_NAMES
+        .iter()
+        .find(|name| str_to_oscode(name) == Some(key))
+        .map_or_else(|| format!("{key:?}"), |name| (*name).to_owned())
+}
+
+#[test]
+fn palette_lists_the_winning_actions() {
+    let path = std::env::temp_dir().join(format!("kanata-palette-{}.kbd", std::process::id()));
+    std::fs::write(
+        &path,
+        "
+(defsrc a s d f)
+(deflayer base a (layer-while-held nav) XX C-c)
+(deflayer nav left _ _ C-v)
+(deflayerdoc nav \"Navigation\" f \"Paste\")
+",
+    )
+    .unwrap();
+    let cfg = crate::cfg::new_from_file(&path);
+    std::fs::remove_file(&path).unwrap();
+    let cfg = cfg.unwrap();
+    let line = |e: &PaletteEntry| format!("{}{SEPARATOR}{}{SEPARATOR}{}", e.layer, e.key, e.action);
+
+    let entries = palette_entries(cfg.layout.b(), &cfg.layer_info, &[0], &cfg.mapped_keys);
+    assert_eq!(
+        entries.iter().map(line).collect::<Vec<_>>(),
+        [
+            "base | s | layer-while-held nav",
+            "base | f | keys KEY_LEFTCTRL + KEY_C",
+        ]
+    );
+    // With nav held, as the second keyberon copy of it.
+    let entries = palette_entries(cfg.layout.b(), &cfg.layer_info, &[0, 3], &cfg.mapped_keys);
+    assert_eq!(
+        entries.iter().map(line).collect::<Vec<_>>(),
+        [
+            "nav | a | key KEY_LEFT",
+            "base | s | layer-while-held nav",
+            "nav | f | keys KEY_LEFTCTRL + KEY_V",
+        ]
+    );
+    assert_eq!(entries[0].description, None);
+    assert_eq!(entries[2].description.as_deref(), Some("Paste"));
+}
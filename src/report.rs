@@ -0,0 +1,142 @@
+//! `kanata report`: the bindings and layers of a configuration that were never used, and the
+//! tap-hold keys that probably misfired.
+//!
+//! The usage is read from the statistics that kanata records with `usage-stats-file`. A binding
+//! is a key of a layer that is not transparent; keys that output themselves are left out since
+//! there is nothing to prune about them.
+
+use crate::cfg::{self, Cfg};
+use crate::kanata::UsageStats;
+use crate::keys::OsCode;
+
+use anyhow::{anyhow, Result};
+use kanata_keyberon::action::Action;
+use kanata_keyberon::key_code::KeyCode;
+
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The unused parts of a configuration.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Report {
+    layers: Vec<String>,
+    /// Layer names and their keys that were never pressed.
+    bindings: Vec<(String, Vec<OsCode>)>,
+}
+
+/// Print what of the configuration was not used according to the statistics file.
+pub fn run(cfg_path: &Path, stats_path: &Path) -> Result<()> {
+    let cfg = cfg::new_from_file(cfg_path).map_err(|e| anyhow!("{e:?}"))?;
+    let stats = UsageStats::read(stats_path)?;
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+        .saturating_sub(stats.since)
+        / (24 * 60 * 60);
+    println!("usage recorded over {days} day(s)\n");
+    let report = unused(&cfg, &stats);
+    if report.layers.is_empty() && report.bindings.is_empty() {
+        println!("every layer and binding was used");
+    }
+    if !report.layers.is_empty() {
+        println!("layers never activated:");
+        for layer in &report.layers {
+            println!("  {layer}");
+        }
+        println!();
+    }
+    if !report.bindings.is_empty() {
+        println!("bindings never used:");
+        for (layer, keys) in &report.bindings {
+            let keys = keys.iter().map(|k| key_name(*k)).collect::<Vec<_>>();
+            println!("  {layer}: {}", keys.join(" "));
+        }
+    }
+    if !stats.misfires.is_empty() {
+        println!("\nprobable tap-hold misfires:");
+        for (key, misfires) in &stats.misfires {
+            let key = key.trim_start_matches("KEY_").to_lowercase();
+            println!(
+                "  {key}: held alone {} time(s), held then backspaced {} time(s)",
+                misfires.hold_alone, misfires.hold_undone
+            );
+        }
+        println!("keys often held alone may need a longer hold timeout; keys often backspaced");
+        println!("may need a longer timeout or a tap-hold variant that decides on release");
+    }
+    Ok(())
+}
+
+fn unused(cfg: &Cfg, stats: &UsageStats) -> Report {
+    let mut report = Report::default();
+    let layout = cfg.layout.b();
+    let mut keys = cfg.mapped_keys.iter().copied().collect::<Vec<_>>();
+    keys.sort_by_key(|k| *k as u16);
+    // Each layer is duplicated in the keyberon layout; the second copy keeps `_` transparent.
+    for layer_idx in (0..cfg.layer_info.len()).step_by(2) {
+        let layer = &cfg.layer_info[layer_idx].name;
+        if !stats.layers.contains_key(layer) {
+            report.layers.push(layer.clone());
+        }
+        let unused_keys = keys
+            .iter()
+            .copied()
+            .filter(|key| {
+                match &layout.layers[layer_idx + 1][0][usize::from(*key as u16)] {
+                    Action::Trans => false,
+                    Action::KeyCode(kc) => *kc != KeyCode::from(*key),
+                    _ => true,
+                }
+            })
+            .filter(|key| stats.presses_of(layer, *key) == 0)
+            .collect::<Vec<_>>();
+        if !unused_keys.is_empty() {
+            report.bindings.push((layer.clone(), unused_keys));
+        }
+    }
+    report
+}
+
+fn key_name(key: OsCode) -> String {
+    format!("{key:?}").trim_start_matches("KEY_").to_lowercase()
+}
+
+#[test]
+fn report_lists_unused_layers_and_bindings() {
+    let path = std::env::temp_dir().join(format!("kanata-report-{}.kbd", std::process::id()));
+    std::fs::write(
+        &path,
+        "
+(defsrc a s d f)
+(deflayer base a s C-d (layer-while-held nav))
+(deflayer nav left down up _)
+(deflayer num 1 2 _ _)
+",
+    )
+    .unwrap();
+    let cfg = cfg::new_from_file(&path);
+    std::fs::remove_file(&path).unwrap();
+    let cfg = cfg.unwrap();
+
+    let mut stats = UsageStats::default();
+    stats.layers.insert("base".into(), 1);
+    stats.layers.insert("nav".into(), 4);
+    for (layer, key) in [("base", "KEY_F"), ("nav", "KEY_A"), ("nav", "KEY_D")] {
+        stats
+            .presses
+            .entry(layer.into())
+            .or_default()
+            .insert(key.into(), 1);
+    }
+    assert_eq!(
+        unused(&cfg, &stats),
+        Report {
+            layers: vec!["num".into()],
+            bindings: vec![
+                ("base".into(), vec![OsCode::KEY_D]),
+                ("nav".into(), vec![OsCode::KEY_S]),
+                ("num".into(), vec![OsCode::KEY_A, OsCode::KEY_S]),
+            ],
+        }
+    );
+}
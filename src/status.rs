@@ -0,0 +1,85 @@
+//! `kanata status`: the active layers and modes of a running kanata instance, for shell prompts
+//! and scripts.
+//!
+//! The status is printed as JSON, or with `--format env` as shell variable assignments that a
+//! script can `eval` without parsing JSON:
+//!
+//! ```sh
+//! eval "$(kanata status -p 5829 --format env)"
+//! echo "$KANATA_LAYER"
+//! ```
+
+use crate::tcp_client::KanataClient;
+use crate::tcp_server::{ClientMessage, ServerMessage, StatusInfo};
+
+use anyhow::{anyhow, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum StatusFormat {
+    Json,
+    /// `KANATA_LAYER=...` lines that a POSIX shell can source or eval.
+    Env,
+}
+
+/// Print the status of the kanata instance on the port.
+pub fn run(port: u16, format: StatusFormat) -> Result<()> {
+    let status =
+        KanataClient::connect(port)?.request(&ClientMessage::RequestStatus, "status", |msg| {
+            match msg {
+                ServerMessage::Status { status } => Some(status),
+                _ => None,
+            }
+        })?;
+    match format {
+        StatusFormat::Json => println!(
+            "{}",
+            serde_json::to_string(&status)
+                .map_err(|e| anyhow!("failed to serialize status: {e}"))?
+        ),
+        StatusFormat::Env => print!("{}", env_lines(&status)),
+    }
+    Ok(())
+}
+
+/// The status as `KANATA_*` variable assignments, one per line. Lists are separated by spaces and
+/// booleans are `1` or `0`.
+fn env_lines(status: &StatusInfo) -> String {
+    let flag = |b: bool| if b { "1" } else { "0" };
+    [
+        ("KANATA_LAYER", status.layer.clone()),
+        ("KANATA_DEFAULT_LAYER", status.default_layer.clone()),
+        ("KANATA_ACTIVE_LAYERS", status.active_layers.join(" ")),
+        ("KANATA_CONFIG", status.config.clone()),
+        ("KANATA_GAME_MODE", flag(status.game_mode).to_owned()),
+        ("KANATA_PASSTHROUGH", flag(status.passthrough).to_owned()),
+    ]
+    .iter()
+    .map(|(name, value)| format!("{name}={}\n", shell_quote(value)))
+    .collect()
+}
+
+/// Quote the value for a POSIX shell: in single quotes, with each single quote written as `'\''`.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[test]
+fn status_env_lines_are_quoted_for_the_shell() {
+    let status = StatusInfo {
+        layer: "nav".into(),
+        default_layer: "base".into(),
+        active_layers: vec!["base".into(), "nav".into()],
+        config: "/home/me/it's kanata.kbd".into(),
+        game_mode: false,
+        passthrough: true,
+    };
+    assert_eq!(
+        env_lines(&status),
+        "KANATA_LAYER='nav'\n\
+         KANATA_DEFAULT_LAYER='base'\n\
+         KANATA_ACTIVE_LAYERS='base nav'\n\
+         KANATA_CONFIG='/home/me/it'\\''s kanata.kbd'\n\
+         KANATA_GAME_MODE='0'\n\
+         KANATA_PASSTHROUGH='1'\n"
+    );
+}
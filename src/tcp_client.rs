@@ -0,0 +1,187 @@
+//! The client side of the TCP server, for the subcommands that talk to a running kanata instance,
+//! such as `kanata status` and `kanata palette`.
+//!
+//! If kanata runs with `--tcp-acl`, the token in the `KANATA_TCP_TOKEN` environment variable is
+//! presented with `ClientMessage::Authenticate` right after connecting. The variable is used
+//! instead of an argument so that the token does not show up in the process list.
+
+use crate::tcp_server::{ClientMessage, ServerMessage};
+
+use anyhow::{anyhow, bail, Result};
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+/// The environment variable with the token that clients authenticate with.
+pub const TOKEN_ENV_VAR: &str = "KANATA_TCP_TOKEN";
+
+/// How long to wait for kanata to accept the connection or to reply.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A connection to the TCP server of kanata on this machine.
+pub struct KanataClient {
+    stream: TcpStream,
+    /// Bytes read after the last complete message.
+    pending: Vec<u8>,
+    authenticated: bool,
+}
+
+impl KanataClient {
+    /// Connect to kanata on the port, and authenticate if `KANATA_TCP_TOKEN` is set.
+    pub fn connect(port: u16) -> Result<Self> {
+        let stream = TcpStream::connect_timeout(&SocketAddr::from(([127, 0, 0, 1], port)), TIMEOUT)
+            .map_err(|e| anyhow!("could not connect to kanata on port {port}: {e}"))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        let mut client = Self {
+            stream,
+            pending: vec![],
+            authenticated: false,
+        };
+        if let Some(token) = std::env::var(TOKEN_ENV_VAR).ok().filter(|t| !t.is_empty()) {
+            client.send(&ClientMessage::Authenticate { token })?;
+            client.receive("authentication result", |msg| match msg {
+                ServerMessage::Authenticated { .. } => Some(()),
+                _ => None,
+            })?;
+            client.authenticated = true;
+        }
+        Ok(client)
+    }
+
+    /// Send the message, terminated by a newline.
+    pub fn send(&mut self, msg: &ClientMessage) -> Result<()> {
+        let mut request =
+            serde_json::to_string(msg).map_err(|e| anyhow!("failed to serialize message: {e}"))?;
+        request.push('\n');
+        self.stream.write_all(request.as_bytes())?;
+        Ok(())
+    }
+
+    /// Wait for the reply that `reply` picks, named `what` in errors. Other messages, such as the
+    /// notifications that kanata greets new clients with, are skipped. Fails if kanata did not
+    /// allow a message that was sent.
+    pub fn receive<T>(
+        &mut self,
+        what: &str,
+        mut reply: impl FnMut(ServerMessage) -> Option<T>,
+    ) -> Result<T> {
+        loop {
+            match self.next_message() {
+                Ok(ServerMessage::PermissionDenied { command }) => {
+                    match (command.as_str(), self.authenticated) {
+                        ("Authenticate", _) => {
+                            bail!("kanata does not know the token in {TOKEN_ENV_VAR}")
+                        }
+                        (_, true) => bail!("the token in {TOKEN_ENV_VAR} does not allow {command}"),
+                        (_, false) => bail!(
+                            "kanata only allows {command} after authenticating, \
+                            set {TOKEN_ENV_VAR} to a token of its --tcp-acl file"
+                        ),
+                    }
+                }
+                Ok(msg) => {
+                    if let Some(reply) = reply(msg) {
+                        return Ok(reply);
+                    }
+                }
+                Err(e) => bail!("no {what} received from kanata: {e}"),
+            }
+        }
+    }
+
+    /// Send the message and wait for the reply that `reply` picks, see [`Self::receive`].
+    pub fn request<T>(
+        &mut self,
+        msg: &ClientMessage,
+        what: &str,
+        reply: impl FnMut(ServerMessage) -> Option<T>,
+    ) -> Result<T> {
+        self.send(msg)?;
+        self.receive(what, reply)
+    }
+
+    /// Wait until kanata handled the messages sent so far, for messages without a reply. Fails if
+    /// kanata did not allow one of them.
+    pub fn confirm(&mut self) -> Result<()> {
+        self.request(&ClientMessage::Hello, "confirmation", |msg| match msg {
+            ServerMessage::Hello { .. } => Some(()),
+            _ => None,
+        })
+    }
+
+    /// Receive every following message on the returned channel, which disconnects when the
+    /// connection is closed.
+    pub fn into_messages(mut self) -> Result<Receiver<ServerMessage>> {
+        self.stream.set_read_timeout(None)?;
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            while let Ok(msg) = self.next_message() {
+                if tx.send(msg).is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(rx)
+    }
+
+    /// The next message from kanata. Messages are written back-to-back without a delimiter and a
+    /// read may end in the middle of one, which is completed by the next reads. Messages that
+    /// this version does not know are skipped.
+    fn next_message(&mut self) -> Result<ServerMessage> {
+        loop {
+            let mut values = serde_json::Deserializer::from_slice(&self.pending)
+                .into_iter::<serde_json::Value>();
+            match values.next() {
+                Some(Ok(value)) => {
+                    let end = values.byte_offset();
+                    self.pending.drain(..end);
+                    match serde_json::from_value(value) {
+                        Ok(msg) => return Ok(msg),
+                        Err(_) => continue,
+                    }
+                }
+                Some(Err(e)) if !e.is_eof() => bail!("kanata sent an invalid message: {e}"),
+                _ => {}
+            }
+            let mut buf = [0; 4096];
+            match self.stream.read(&mut buf)? {
+                0 => bail!("kanata closed the connection"),
+                size => self.pending.extend_from_slice(&buf[..size]),
+            }
+        }
+    }
+}
+
+#[test]
+fn messages_are_split_and_skipped() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let greeting = br#"{"LayerChange":{"new":"base"}}{"NoSuchMessage":{}}{"Explan"#;
+        stream.write_all(greeting).unwrap();
+        stream.flush().unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        stream.write_all(br#"ation":{"text":"a"}}"#).unwrap();
+        stream
+            .write_all(br#"{"PermissionDenied":{"command":"ChangeLayer"}}"#)
+            .unwrap();
+    });
+    std::env::remove_var(TOKEN_ENV_VAR);
+    let mut client = KanataClient::connect(port).unwrap();
+    let text = client
+        .receive("explanation", |msg| match msg {
+            ServerMessage::Explanation { text } => Some(text),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(text, "a");
+    let e = client.receive("status", |_| Some(())).unwrap_err();
+    assert!(
+        e.to_string().contains("ChangeLayer after authenticating"),
+        "{e}"
+    );
+    server.join().unwrap();
+}
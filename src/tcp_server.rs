@@ -1,16 +1,194 @@
+use crate::custom_action::FakeKeyAction;
+use crate::kanata::{
+    parse_injected_keys, parse_macro_keys, KanataCommand, ProcessingEvent, MACRO_EXPORT_VERSION,
+};
+use crate::keys::str_to_oscode;
 use crate::Kanata;
+use kanata_keyberon::key_code::KeyCode;
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
-use std::io::{Read, Write};
+use std::io::{BufReader, Read, Write};
 use std::net::{TcpListener, TcpStream};
+use std::collections::BTreeMap;
 use std::str::FromStr;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 type HashMap<K, V> = rustc_hash::FxHashMap<K, V>;
+type HashSet<T> = rustc_hash::FxHashSet<T>;
+
+/// The version of the messages in this file. It is increased when a change would break existing
+/// clients, e.g. a message is removed or a field changes meaning. New messages and fields do not
+/// change it; clients learn about those from `ServerMessage::Hello`.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// The `ClientMessage`s that kanata handles on every platform.
+const CLIENT_MESSAGES: &[&str] = &[
+    "Hello",
+    "Authenticate",
+    "ChangeLayer",
+    "SubscribeKeyOutputs",
+    "Subscribe",
+    "ActOnFakeKey",
+    "SetVar",
+    "Shutdown",
+    "ChangeConfig",
+    "SetGameMode",
+    "SetLayerTag",
+    "SetLayerTags",
+    "Undo",
+    "ClearOutputHistory",
+    "ActiveWindowChanged",
+    "PointerMoved",
+    "Explain",
+    "RequestLocks",
+    "ForceUnlock",
+    "SetLogFilter",
+    "LoadOverlay",
+    "DropOverlay",
+    "InjectKeys",
+    "InjectText",
+    "SetPassthrough",
+    "ResyncModifiers",
+    "ActivateTempLayer",
+    "ExtendTempLayer",
+    "CancelTempLayer",
+    "RequestKeyCounts",
+    "RequestPalette",
+    "RequestStatus",
+    "RequestLayerDocs",
+    "ExportMacros",
+    "ImportMacros",
+    "RequestBuildInfo",
+    "BeginTransaction",
+    "CommitTransaction",
+    "RollbackTransaction",
+];
+
+/// The `ClientMessage`s that kanata only handles on Linux.
+const LINUX_CLIENT_MESSAGES: &[&str] = &["ReleaseDevice", "GrabDevice"];
+
+/// The names of the `ClientMessage`s this build of kanata handles.
+pub fn supported_commands() -> Vec<String> {
+    let mut commands = CLIENT_MESSAGES.to_vec();
+    if cfg!(target_os = "linux") {
+        commands.extend(LINUX_CLIENT_MESSAGES);
+    }
+    commands.into_iter().map(str::to_owned).collect()
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum ServerMessage {
-    LayerChange { new: String },
+    LayerChange {
+        new: String,
+    },
+    /// A layer with `deflayernotify` fields was activated or deactivated. Unlike `LayerChange`,
+    /// these are never coalesced.
+    LayerNotify {
+        layer: String,
+        active: bool,
+        fields: BTreeMap<String, String>,
+    },
+    /// A key press or release that kanata sent to the OS. Only sent to clients that have sent
+    /// `ClientMessage::SubscribeKeyOutputs`, or `ClientMessage::Subscribe` with keys.
+    KeyOutput {
+        key: String,
+        pressed: bool,
+    },
+    /// An input device was released or grabbed on the request of a client.
+    DeviceGrabChanged {
+        device: String,
+        grabbed: bool,
+    },
+    /// Kanata is exiting. This is the last message sent to clients.
+    Shutdown,
+    /// The configuration files kanata was started with and the index of the active one. Sent to
+    /// new clients and after every live reload.
+    ConfigFiles {
+        paths: Vec<String>,
+        active: usize,
+    },
+    /// A live reload of the configuration file failed and kanata still runs with the previous
+    /// configuration. Also sent to new clients until a live reload succeeds.
+    LiveReloadFailed {
+        path: String,
+        error: String,
+    },
+    /// The reply to `ClientMessage::RequestLocks`, sent only to the client that asked.
+    Locks {
+        locks: Vec<LockInfo>,
+    },
+    /// The reply to `ClientMessage::Explain`, sent only to the client that asked.
+    Explanation {
+        text: String,
+    },
+    /// The reply to `ClientMessage::RequestKeyCounts`, sent only to the client that asked.
+    KeyCounts {
+        keys: Vec<KeyCount>,
+    },
+    /// The reply to `ClientMessage::RequestPalette`, sent only to the client that asked.
+    Palette {
+        entries: Vec<PaletteEntry>,
+    },
+    /// The reply to `ClientMessage::RequestStatus`, sent only to the client that asked.
+    Status {
+        status: StatusInfo,
+    },
+    /// The reply to `ClientMessage::RequestLayerDocs`, sent only to the client that asked.
+    LayerDocs {
+        layers: Vec<LayerDocInfo>,
+    },
+    /// The reply to `ClientMessage::ExportMacros`, sent only to the client that asked.
+    Macros {
+        macros: MacroExport,
+    },
+    /// The reply to `ClientMessage::RequestBuildInfo`, sent only to the client that asked.
+    BuildInfo {
+        info: BuildInfo,
+    },
+    /// The reply to `ClientMessage::CommitTransaction`, sent only to the client that committed.
+    /// If `committed` is false, none of the changes were applied and `error` says why.
+    TransactionResult {
+        committed: bool,
+        error: Option<String>,
+    },
+    /// The input of the launcher and the `deflauncher` entry it matches best, sent when they
+    /// change. When the launcher closes, `active` is false and `best_match` is the entry that was
+    /// run, if any.
+    Launcher {
+        input: String,
+        best_match: Option<String>,
+        active: bool,
+    },
+    /// The reply to `ClientMessage::Hello`, sent only to the client that asked. `protocol` is
+    /// [`PROTOCOL_VERSION`], `version` the version of kanata and `commands` the names of the
+    /// `ClientMessage`s it handles.
+    Hello {
+        protocol: u32,
+        version: String,
+        commands: Vec<String>,
+    },
+    /// The reply to a `ClientMessage::Authenticate` with a known token, sent only to the client
+    /// that asked. `commands` are the names of the `ClientMessage`s the client may now send.
+    Authenticated {
+        commands: Vec<String>,
+    },
+    /// The reply to a `ClientMessage` that the client may not send, see [`Acl`]. The message is
+    /// ignored and the client stays connected.
+    PermissionDenied {
+        command: String,
+    },
+}
+
+impl ServerMessage {
+    pub fn hello() -> Self {
+        ServerMessage::Hello {
+            protocol: PROTOCOL_VERSION,
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            commands: supported_commands(),
+        }
+    }
 }
 
 #[test]
@@ -23,10 +201,647 @@ fn layer_change_serializes() {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum ClientMessage {
-    ChangeLayer { new: String },
+    /// Ask for the protocol version and the messages kanata handles, so that a client can avoid
+    /// sending messages an older kanata does not know, which disconnects the client. Kanata
+    /// replies with `ServerMessage::Hello`.
+    Hello,
+    /// Present a token of the `--tcp-acl` file to be allowed the commands listed for it. Kanata
+    /// replies with `ServerMessage::Authenticated`, or `ServerMessage::PermissionDenied` if the
+    /// token is unknown.
+    Authenticate {
+        token: String,
+    },
+    ChangeLayer {
+        new: String,
+    },
+    SubscribeKeyOutputs,
+    /// Receive only the notifications that match one of the filters instead of all of them.
+    /// `Shutdown` is always sent. Replies to other messages are not affected. An empty list of
+    /// filters receives all notifications again.
+    Subscribe {
+        filters: Vec<EventFilter>,
+    },
+    /// Press, release or tap a fake key defined in `deffakekeys`.
+    ActOnFakeKey {
+        name: String,
+        action: FakeKeyActionMessage,
+    },
+    /// Set a variable that is read by `switch-var` actions.
+    SetVar {
+        name: String,
+        value: String,
+    },
+    /// Release the input devices with this name or path so that the OS or another program
+    /// receives their events directly. Linux only.
+    ReleaseDevice {
+        device: String,
+    },
+    /// Grab the input devices with this name or path again after `ReleaseDevice`. Linux only.
+    GrabDevice {
+        device: String,
+    },
+    /// Release the held keys, save the state and exit.
+    Shutdown,
+    /// Switch to the configuration file with this index in `ConfigFiles` and live reload it.
+    ChangeConfig {
+        index: usize,
+    },
+    /// Enable or disable game mode, in addition to the `game-mode-layers`.
+    SetGameMode {
+        enabled: bool,
+    },
+    /// Turn the layers with a `deflayertags` tag off or on. Layers that are turned off cannot be
+    /// activated.
+    SetLayerTag {
+        tag: String,
+        enabled: bool,
+    },
+    /// Apply several `SetLayerTag` changes in order as one update, so that there is no moment in
+    /// which only some of them apply. If a tag is unknown, none of them are applied.
+    SetLayerTags {
+        changes: Vec<LayerTagChange>,
+    },
+    /// Revert the most recent `ChangeLayer`, `SetVar`, `ChangeConfig`, `SetGameMode`,
+    /// `SetLayerTag` or `SetLayerTags`.
+    Undo,
+    /// Forget the text that kanata recently output, which short codes and snippets match against.
+    /// Clients that watch the focused window can send this when it changes.
+    ClearOutputHistory,
+    /// Report the focused window. Its class and title are passed to `cmd` actions in the
+    /// environment variables `KANATA_WINDOW_CLASS` and `KANATA_WINDOW_TITLE`.
+    ActiveWindowChanged {
+        class: String,
+        title: String,
+    },
+    /// Report the position of the pointer on the screen, for `pointer-regions` where kanata
+    /// cannot read it itself, e.g. on Wayland.
+    PointerMoved {
+        x: i32,
+        y: i32,
+    },
+    /// Explain how the key is resolved with the active layers. Kanata replies with
+    /// `ServerMessage::Explanation`.
+    Explain {
+        key: String,
+    },
+    /// Ask for the latched keys, held layers and active modes. Kanata replies with
+    /// `ServerMessage::Locks`.
+    RequestLocks,
+    /// Release everything in `RequestLocks`, for recovery when keys or layers are stuck.
+    ForceUnlock,
+    /// Replace the log filter of `log-filter` until the next live reload, e.g. with
+    /// `"kanata::oskbd=trace"`.
+    SetLogFilter {
+        filter: String,
+    },
+    /// Live reload with the layers of this configuration file on top of the active one and
+    /// activate its first layer, until `DropOverlay`. The file must not have a `defsrc` or
+    /// `defcfg`.
+    LoadOverlay {
+        path: String,
+    },
+    /// Live reload the active configuration file without the overlay of `LoadOverlay`.
+    DropOverlay,
+    /// Inject key events: key names separated by spaces, where a name taps the key, `+name`
+    /// presses it and `-name` releases it. The events are processed like those of the input
+    /// devices, or sent to the output as they are if `output` is true. Only allowed from this
+    /// machine, or after authenticating.
+    InjectKeys {
+        keys: String,
+        #[serde(default)]
+        output: bool,
+    },
+    /// Type the text on the output. Only allowed from this machine, or after authenticating.
+    InjectText {
+        text: String,
+    },
+    /// Output every key unchanged instead of processing it, for when the configuration makes the
+    /// keyboard unusable. A successful live reload also turns it off.
+    SetPassthrough {
+        enabled: bool,
+    },
+    /// Release every modifier on the output, for when the OS has modifiers stuck that kanata
+    /// does not hold, e.g. after a virtual machine grabbed the keyboard.
+    ResyncModifiers,
+    /// Activate the layer as if a key held it, and release it after the seconds. Replaces the
+    /// previous temporary layer.
+    ActivateTempLayer {
+        name: String,
+        seconds: u64,
+    },
+    /// Keep the temporary layer active for this many more seconds.
+    ExtendTempLayer {
+        seconds: u64,
+    },
+    /// Release the temporary layer now.
+    CancelTempLayer,
+    /// Ask for the press counts of the physical keys. Kanata replies with
+    /// `ServerMessage::KeyCounts`.
+    RequestKeyCounts,
+    /// Ask for the actions that the keys trigger with the active layers. Kanata replies with
+    /// `ServerMessage::Palette`.
+    RequestPalette,
+    /// Ask for the active layers and modes. Kanata replies with `ServerMessage::Status`.
+    RequestStatus,
+    /// Ask for the descriptions of the layers and their keys. Kanata replies with
+    /// `ServerMessage::LayerDocs`.
+    RequestLayerDocs,
+    /// Ask for the recorded dynamic macros and the snippets of `defsnippets`. Kanata replies with
+    /// `ServerMessage::Macros`. With `--tcp-acl`, only clients whose token allows it may send it.
+    ExportMacros,
+    /// Replace the recorded dynamic macros that have the IDs of the macros in the export. The
+    /// snippets of the export are ignored since they belong to the configuration.
+    ImportMacros {
+        macros: MacroExport,
+    },
+    /// Ask for the version and build of kanata and the hash of its configuration, e.g. for a bug
+    /// report. Kanata replies with `ServerMessage::BuildInfo`.
+    RequestBuildInfo,
+    /// Collect the following `ChangeLayer`, `SetVar`, `SetGameMode`, `SetLayerTag` and
+    /// `SetLayerTags` messages instead of applying them.
+    BeginTransaction,
+    /// Apply the collected changes together if all of them can be applied, or else none of them.
+    /// Kanata replies with `ServerMessage::TransactionResult`.
+    CommitTransaction,
+    /// Drop the collected changes.
+    RollbackTransaction,
+}
+
+/// Something that keeps keys or layers active, with the key that activated it and for how long
+/// if known.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockInfo {
+    pub lock: String,
+    pub taken_by: Option<String>,
+    pub held_ms: Option<u64>,
+}
+
+/// The presses of a physical key, by key name. `presses` counts every press, including the ones
+/// in `bounces` that bounce keys ignored. Counts are since kanata started or, with
+/// `key-counts-file`, since the file was created; `bounces` is always since kanata started.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyCount {
+    pub key: String,
+    pub presses: u64,
+    pub bounces: u64,
+}
+
+/// A key whose action on the active layers does something other than output the key, with the
+/// layer whose action wins. `key` is a key name of the configuration language and `description`
+/// the description of the key on the layer from `deflayerdoc`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaletteEntry {
+    pub layer: String,
+    pub key: String,
+    pub action: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// The descriptions of a layer and of its keys from `deflayerdoc`, with the keys by key names of
+/// the configuration language.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayerDocInfo {
+    pub layer: String,
+    pub description: Option<String>,
+    pub keys: BTreeMap<String, String>,
+}
+
+/// Recorded dynamic macros and snippets in a format that does not depend on the platform, see
+/// [`macro_export`](crate::kanata::macro_export). `keys` are in the syntax of `InjectKeys` and
+/// `expansion` as written in `defsnippets`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MacroExport {
+    pub version: u32,
+    #[serde(default)]
+    pub dynamic_macros: Vec<ExportedMacro>,
+    #[serde(default)]
+    pub snippets: Vec<ExportedSnippet>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedMacro {
+    pub id: u16,
+    pub keys: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedSnippet {
+    pub trigger: String,
+    pub expansion: String,
+}
+
+/// The version and build of kanata. `target` is the CPU architecture and OS, `backend` how keys
+/// are read and output, and `config_hash` the SHA-256 of the configuration file that is in use.
+/// `latest_release` is the tag of the latest release if `check-for-updates` is enabled and the
+/// check finished.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuildInfo {
+    pub version: String,
+    pub target: String,
+    pub features: Vec<String>,
+    pub backend: String,
+    pub config: String,
+    pub config_hash: String,
+    pub latest_release: Option<String>,
+}
+
+/// The state of kanata for scripts and status bars. `active_layers` starts with the default layer,
+/// followed by the held layers in the order they were activated, and `config` is the path of the
+/// active configuration file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusInfo {
+    pub layer: String,
+    pub default_layer: String,
+    pub active_layers: Vec<String>,
+    pub config: String,
+    pub game_mode: bool,
+    pub passthrough: bool,
+}
+
+/// A kind of notification for `ClientMessage::Subscribe`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum EventFilter {
+    /// `LayerChange` and `LayerNotify`.
+    Layers,
+    /// `KeyOutput` of these keys, by key name, or of every key if the list is empty.
+    Keys { keys: Vec<String> },
+    /// `LiveReloadFailed`.
+    Errors,
+}
+
+/// The notifications that a client asked for with `ClientMessage::Subscribe`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Subscription {
+    layers: bool,
+    errors: bool,
+    all_keys: bool,
+    /// The keys as named in `KeyOutput`.
+    keys: Vec<String>,
+}
+
+impl Subscription {
+    pub fn new(filters: &[EventFilter]) -> Result<Self, String> {
+        let mut subscription = Self::default();
+        for filter in filters {
+            match filter {
+                EventFilter::Layers => subscription.layers = true,
+                EventFilter::Errors => subscription.errors = true,
+                EventFilter::Keys { keys } if keys.is_empty() => subscription.all_keys = true,
+                EventFilter::Keys { keys } => {
+                    for key in keys {
+                        let osc = str_to_oscode(key).ok_or_else(|| format!("unknown key {key}"))?;
+                        subscription.keys.push(format!("{:?}", KeyCode::from(osc)));
+                    }
+                }
+            }
+        }
+        Ok(subscription)
+    }
+
+    pub fn wants(&self, event: &ServerMessage) -> bool {
+        match event {
+            ServerMessage::Shutdown => true,
+            ServerMessage::LayerChange { .. } | ServerMessage::LayerNotify { .. } => self.layers,
+            ServerMessage::LiveReloadFailed { .. } => self.errors,
+            ServerMessage::KeyOutput { key, .. } => self.all_keys || self.keys.contains(key),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayerTagChange {
+    pub tag: String,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum FakeKeyActionMessage {
+    Press,
+    Release,
+    Tap,
+}
+
+impl From<FakeKeyActionMessage> for FakeKeyAction {
+    fn from(action: FakeKeyActionMessage) -> Self {
+        match action {
+            FakeKeyActionMessage::Press => FakeKeyAction::Press,
+            FakeKeyActionMessage::Release => FakeKeyAction::Release,
+            FakeKeyActionMessage::Tap => FakeKeyAction::Tap,
+        }
+    }
+}
+
+#[test]
+fn act_on_fake_key_deserializes() {
+    let msg: ClientMessage = r#"{"ActOnFakeKey":{"name":"screenshot","action":"Tap"}}"#
+        .parse()
+        .unwrap();
+    assert!(matches!(
+        msg,
+        ClientMessage::ActOnFakeKey {
+            action: FakeKeyActionMessage::Tap,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn inject_keys_are_processed_by_default() {
+    let msg: ClientMessage = r#"{"InjectKeys":{"keys":"+lctl c -lctl"}}"#.parse().unwrap();
+    assert!(matches!(msg, ClientMessage::InjectKeys { output: false, .. }));
+    let msg: ClientMessage = r#"{"InjectKeys":{"keys":"a","output":true}}"#.parse().unwrap();
+    assert!(matches!(msg, ClientMessage::InjectKeys { output: true, .. }));
+    assert!(types_on_host(&msg.name()));
+    assert!(types_on_host("InjectText"));
+    assert!(!types_on_host("RequestStatus"));
+}
+
+#[test]
+fn subscriptions_filter_notifications() {
+    let msg: ClientMessage =
+        r#"{"Subscribe":{"filters":["Errors",{"Keys":{"keys":["a","lsft"]}}]}}"#
+            .parse()
+            .unwrap();
+    let ClientMessage::Subscribe { filters } = msg else {
+        panic!("expected Subscribe");
+    };
+    let subscription = Subscription::new(&filters).unwrap();
+    let key = |key: &str| ServerMessage::KeyOutput {
+        key: key.into(),
+        pressed: true,
+    };
+    assert!(subscription.wants(&key("A")));
+    assert!(subscription.wants(&key("LShift")));
+    assert!(!subscription.wants(&key("B")));
+    assert!(!subscription.wants(&ServerMessage::LayerChange { new: "nav".into() }));
+    assert!(subscription.wants(&ServerMessage::LiveReloadFailed {
+        path: "main.kbd".into(),
+        error: "Unknown key".into(),
+    }));
+    assert!(subscription.wants(&ServerMessage::Shutdown));
+
+    let subscription =
+        Subscription::new(&[EventFilter::Keys { keys: vec![] }, EventFilter::Layers]).unwrap();
+    assert!(subscription.wants(&key("B")));
+    assert!(subscription.wants(&ServerMessage::LayerChange { new: "nav".into() }));
+    assert!(Subscription::new(&[EventFilter::Keys {
+        keys: vec!["nosuchkey".into()]
+    }])
+    .is_err());
+}
+
+#[test]
+fn layer_notify_serializes() {
+    let notification = ServerMessage::LayerNotify {
+        layer: "nav".into(),
+        active: true,
+        fields: [("label", "Navigation"), ("icon", "compass")]
+            .into_iter()
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect(),
+    };
+    assert_eq!(
+        String::from_utf8(notification.as_bytes()).unwrap(),
+        r#"{"LayerNotify":{"layer":"nav","active":true,"fields":{"icon":"compass","label":"Navigation"}}}"#
+    );
+}
+
+#[test]
+fn device_grab_messages_round_trip() {
+    let msg: ClientMessage = r#"{"ReleaseDevice":{"device":"USB Keyboard"}}"#.parse().unwrap();
+    assert!(matches!(msg, ClientMessage::ReleaseDevice { device } if device == "USB Keyboard"));
+    let notification = ServerMessage::DeviceGrabChanged {
+        device: "/dev/input/event3".into(),
+        grabbed: false,
+    };
+    assert_eq!(
+        String::from_utf8(notification.as_bytes()).unwrap(),
+        r#"{"DeviceGrabChanged":{"device":"/dev/input/event3","grabbed":false}}"#
+    );
+}
+
+#[test]
+fn set_layer_tags_deserializes() {
+    let msg: ClientMessage = r#"{"SetLayerTags":{"changes":[
+        {"tag":"experimental","enabled":false},{"tag":"nav","enabled":true}]}}"#
+        .parse()
+        .unwrap();
+    let ClientMessage::SetLayerTags { changes } = msg else {
+        panic!("expected SetLayerTags");
+    };
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes[0].tag, "experimental");
+    assert!(!changes[0].enabled);
+}
+
+#[test]
+fn lock_messages_round_trip() {
+    let msg: ClientMessage = r#""ForceUnlock""#.parse().unwrap();
+    assert!(matches!(msg, ClientMessage::ForceUnlock));
+    let reply = ServerMessage::Locks {
+        locks: vec![LockInfo {
+            lock: "toggle-key KEY_LEFTSHIFT".into(),
+            taken_by: Some("KEY_CAPSLOCK".into()),
+            held_ms: Some(1500),
+        }],
+    };
+    assert_eq!(
+        String::from_utf8(reply.as_bytes()).unwrap(),
+        r#"{"Locks":{"locks":[{"lock":"toggle-key KEY_LEFTSHIFT","taken_by":"KEY_CAPSLOCK","held_ms":1500}]}}"#
+    );
+}
+
+#[test]
+fn key_counts_serialize() {
+    let msg: ClientMessage = r#""RequestKeyCounts""#.parse().unwrap();
+    assert!(matches!(msg, ClientMessage::RequestKeyCounts));
+    let reply = ServerMessage::KeyCounts {
+        keys: vec![KeyCount {
+            key: "KEY_E".into(),
+            presses: 120345,
+            bounces: 17,
+        }],
+    };
+    assert_eq!(
+        String::from_utf8(reply.as_bytes()).unwrap(),
+        r#"{"KeyCounts":{"keys":[{"key":"KEY_E","presses":120345,"bounces":17}]}}"#
+    );
+}
+
+#[test]
+fn hello_lists_every_client_message() {
+    let msg: ClientMessage = r#""Hello""#.parse().unwrap();
+    assert!(matches!(msg, ClientMessage::Hello));
+    // The error for an unknown message lists every variant of ClientMessage.
+    let err = r#""NoSuchMessage""#.parse::<ClientMessage>().unwrap_err();
+    let err = err.to_string();
+    let mut variants = err
+        .split('`')
+        .skip(3)
+        .step_by(2)
+        .map(str::to_owned)
+        .collect::<Vec<_>>();
+    let mut listed = [CLIENT_MESSAGES, LINUX_CLIENT_MESSAGES]
+        .concat()
+        .into_iter()
+        .map(str::to_owned)
+        .collect::<Vec<_>>();
+    variants.sort();
+    listed.sort();
+    assert_eq!(variants, listed, "{err}");
+
+    let reply = String::from_utf8(ServerMessage::hello().as_bytes()).unwrap();
+    assert!(
+        reply.starts_with(r#"{"Hello":{"protocol":1,"version":""#),
+        "{reply}"
+    );
+    assert!(reply.contains(r#""SetVar""#), "{reply}");
+    assert_eq!(
+        reply.contains(r#""GrabDevice""#),
+        cfg!(target_os = "linux"),
+        "{reply}"
+    );
+}
+
+#[test]
+fn transaction_messages_round_trip() {
+    let msg: ClientMessage = r#""BeginTransaction""#.parse().unwrap();
+    assert!(matches!(msg, ClientMessage::BeginTransaction));
+    let msg: ClientMessage = r#""CommitTransaction""#.parse().unwrap();
+    assert!(matches!(msg, ClientMessage::CommitTransaction));
+    let reply = ServerMessage::TransactionResult {
+        committed: false,
+        error: Some("layer gaming does not exist".into()),
+    };
+    assert_eq!(
+        String::from_utf8(reply.as_bytes()).unwrap(),
+        r#"{"TransactionResult":{"committed":false,"error":"layer gaming does not exist"}}"#
+    );
+}
+
+#[test]
+fn macro_messages_round_trip() {
+    let msg: ClientMessage =
+        r#"{"ImportMacros":{"macros":{"version":1,"dynamic_macros":[{"id":2,"keys":"+lsft a -lsft"}]}}}"#
+            .parse()
+            .unwrap();
+    let ClientMessage::ImportMacros { macros } = msg else {
+        panic!("expected ImportMacros");
+    };
+    assert_eq!(macros.dynamic_macros[0].id, 2);
+    assert!(macros.snippets.is_empty());
+    let reply = ServerMessage::Macros {
+        macros: MacroExport {
+            version: 1,
+            dynamic_macros: vec![],
+            snippets: vec![ExportedSnippet {
+                trigger: ";d".into(),
+                expansion: "{date}".into(),
+            }],
+        },
+    };
+    assert_eq!(
+        String::from_utf8(reply.as_bytes()).unwrap(),
+        r#"{"Macros":{"macros":{"version":1,"dynamic_macros":[],"snippets":[{"trigger":";d","expansion":"{date}"}]}}}"#
+    );
+
+    // Imports are longer than a single read of the server.
+    let import = ClientMessage::ImportMacros {
+        macros: MacroExport {
+            version: 1,
+            dynamic_macros: (0..100)
+                .map(|id| ExportedMacro {
+                    id,
+                    keys: "+lsft a -lsft b c".into(),
+                })
+                .collect(),
+            snippets: vec![],
+        },
+    };
+    let import = serde_json::to_string(&import).unwrap();
+    assert!(import.len() > 1024);
+    let stream = format!("{import}\n\"Hello\"\n\"ExportMacros\"");
+    let mut messages = client_messages(stream.as_bytes());
+    assert!(matches!(
+        messages.next(),
+        Some(Ok(ClientMessage::ImportMacros { macros })) if macros.dynamic_macros.len() == 100
+    ));
+    assert!(matches!(messages.next(), Some(Ok(ClientMessage::Hello))));
+    assert!(matches!(
+        messages.next(),
+        Some(Ok(ClientMessage::ExportMacros))
+    ));
+    assert!(messages.next().is_none());
+    assert!(client_messages(&b"{\"NoSuchMessage\":1}\n"[..])
+        .next()
+        .unwrap()
+        .is_err());
+
+    let acl = Acl::parse("admin-token *").unwrap();
+    assert!(!acl.commands(None).unwrap().allows("ExportMacros"));
+}
+
+#[test]
+fn build_info_messages_round_trip() {
+    let msg: ClientMessage = r#""RequestBuildInfo""#.parse().unwrap();
+    assert!(matches!(msg, ClientMessage::RequestBuildInfo));
+    let reply = ServerMessage::BuildInfo {
+        info: BuildInfo {
+            version: "1.4.0".into(),
+            target: "x86_64-linux".into(),
+            features: vec!["cmd".into()],
+            backend: "linux evdev input, uinput output".into(),
+            config: "kanata.kbd".into(),
+            config_hash: "ab12".into(),
+            latest_release: None,
+        },
+    };
+    let reply = String::from_utf8(reply.as_bytes()).unwrap();
+    assert!(
+        reply.starts_with(r#"{"BuildInfo":{"info":{"version":"1.4.0","target":"x86_64-linux","#),
+        "{reply}"
+    );
+    assert!(reply.ends_with(r#""latest_release":null}}}"#), "{reply}");
+}
+
+#[test]
+fn shutdown_messages_serialize() {
+    let msg: ClientMessage = r#""Shutdown""#.parse().unwrap();
+    assert!(matches!(msg, ClientMessage::Shutdown));
+    assert_eq!(ServerMessage::Shutdown.as_bytes(), br#""Shutdown""#);
+}
+
+#[test]
+fn config_messages_round_trip() {
+    let msg: ClientMessage = r#"{"ChangeConfig":{"index":1}}"#.parse().unwrap();
+    assert!(matches!(msg, ClientMessage::ChangeConfig { index: 1 }));
+    let notification = ServerMessage::ConfigFiles {
+        paths: vec!["main.kbd".into(), "gaming.kbd".into()],
+        active: 0,
+    };
+    assert_eq!(
+        String::from_utf8(notification.as_bytes()).unwrap(),
+        r#"{"ConfigFiles":{"paths":["main.kbd","gaming.kbd"],"active":0}}"#
+    );
+    let notification = ServerMessage::LiveReloadFailed {
+        path: "main.kbd".into(),
+        error: "Unknown key".into(),
+    };
+    assert_eq!(
+        String::from_utf8(notification.as_bytes()).unwrap(),
+        r#"{"LiveReloadFailed":{"path":"main.kbd","error":"Unknown key"}}"#
+    );
 }
 
 impl ServerMessage {
+    /// Returns true if the message should only be sent to clients that subscribed to key
+    /// outputs.
+    pub fn is_key_output(&self) -> bool {
+        matches!(self, ServerMessage::KeyOutput { .. })
+    }
+
     pub fn as_bytes(&self) -> Vec<u8> {
         serde_json::to_string(self)
             .expect("ServerMessage should serialize")
@@ -35,6 +850,17 @@ impl ServerMessage {
     }
 }
 
+impl ClientMessage {
+    /// The name of the message, as listed in `ServerMessage::Hello`.
+    pub fn name(&self) -> String {
+        match serde_json::to_value(self).expect("ClientMessage serializes") {
+            serde_json::Value::String(name) => name,
+            serde_json::Value::Object(fields) => fields.keys().next().cloned().unwrap_or_default(),
+            value => unreachable!("ClientMessage serialized to {value}"),
+        }
+    }
+}
+
 impl FromStr for ClientMessage {
     type Err = serde_json::Error;
 
@@ -43,49 +869,274 @@ impl FromStr for ClientMessage {
     }
 }
 
+/// The `ClientMessage`s that type on the machine that kanata runs on. Clients on other machines
+/// may only send them after authenticating with `ClientMessage::Authenticate`, which needs
+/// `--tcp-acl`.
+const TYPING_CLIENT_MESSAGES: &[&str] = &["InjectKeys", "InjectText"];
+
+fn types_on_host(command: &str) -> bool {
+    TYPING_CLIENT_MESSAGES.contains(&command)
+}
+
+/// The `ClientMessage`s that clients may send without authenticating when there is an [`Acl`]
+/// without an `anonymous` line: the ones that only read state. `ExportMacros` is not one of them
+/// since recorded macros and expanded snippets may contain passwords.
+const READ_ONLY_CLIENT_MESSAGES: &[&str] = &[
+    "SubscribeKeyOutputs",
+    "Subscribe",
+    "Explain",
+    "RequestLocks",
+    "RequestKeyCounts",
+    "RequestPalette",
+    "RequestStatus",
+    "RequestLayerDocs",
+    "RequestBuildInfo",
+];
+
+/// Which `ClientMessage`s each client may send, read from the file given with `--tcp-acl`.
+///
+/// Every line of the file is a token followed by the names of the messages that a client who
+/// presents the token with `ClientMessage::Authenticate` may send, or `*` for all of them. The
+/// line of the token `anonymous` lists the messages of clients that did not authenticate; without
+/// it, they may only send [`READ_ONLY_CLIENT_MESSAGES`]. `Hello` and `Authenticate` are always
+/// allowed. Empty lines and lines starting with `#` are skipped, e.g.:
+///
+/// ```text
+/// # status bars may follow the layer
+/// anonymous SubscribeKeyOutputs RequestLocks
+/// 6f1e0b8c2d admin *
+/// 93ab11e7f0 ChangeLayer SetVar
+/// ```
+#[derive(Debug, Clone)]
+pub struct Acl {
+    tokens: HashMap<String, AllowedCommands>,
+    anonymous: AllowedCommands,
+}
+
+#[derive(Debug, Clone)]
+enum AllowedCommands {
+    All,
+    Some(HashSet<String>),
+}
+
+impl AllowedCommands {
+    fn allows(&self, command: &str) -> bool {
+        matches!(command, "Hello" | "Authenticate")
+            || match self {
+                AllowedCommands::All => true,
+                AllowedCommands::Some(commands) => commands.contains(command),
+            }
+    }
+
+    fn names(&self) -> Vec<String> {
+        let mut names = supported_commands();
+        names.retain(|name| self.allows(name));
+        names
+    }
+}
+
+impl Acl {
+    pub fn from_file(path: &std::path::Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {e}", path.display()))?;
+        Self::parse(&text).map_err(|e| anyhow::anyhow!("Invalid {}: {e}", path.display()))
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let known = [CLIENT_MESSAGES, LINUX_CLIENT_MESSAGES].concat();
+        let mut tokens = HashMap::default();
+        let mut anonymous = None;
+        for (i, line) in text.lines().enumerate() {
+            let mut words = line.split_whitespace();
+            let token = match words.next() {
+                Some(token) if !token.starts_with('#') => token,
+                _ => continue,
+            };
+            let mut commands = HashSet::default();
+            let mut all = false;
+            for word in words {
+                match word {
+                    "*" => all = true,
+                    _ if known.contains(&word) => {
+                        commands.insert(word.to_owned());
+                    }
+                    _ => return Err(format!("line {}: unknown message {word}", i + 1)),
+                }
+            }
+            let commands = match all {
+                true => AllowedCommands::All,
+                false => AllowedCommands::Some(commands),
+            };
+            let duplicate = match token {
+                "anonymous" => anonymous.replace(commands).is_some(),
+                _ => tokens.insert(token.to_owned(), commands).is_some(),
+            };
+            if duplicate {
+                return Err(format!("line {}: {token} is listed twice", i + 1));
+            }
+        }
+        Ok(Self {
+            tokens,
+            anonymous: anonymous.unwrap_or_else(|| {
+                AllowedCommands::Some(
+                    READ_ONLY_CLIENT_MESSAGES
+                        .iter()
+                        .map(|name| (*name).to_owned())
+                        .collect(),
+                )
+            }),
+        })
+    }
+
+    /// The messages a client with this token may send, or the anonymous ones if `token` is None.
+    /// Returns None for an unknown token.
+    fn commands(&self, token: Option<&str>) -> Option<&AllowedCommands> {
+        match token {
+            Some(token) => self.tokens.get(token),
+            None => Some(&self.anonymous),
+        }
+    }
+}
+
+#[test]
+fn acl_limits_commands() {
+    let acl = Acl::parse(
+        "# comment\n\nanonymous RequestLocks\nadmin-token *\nbar-token ChangeLayer SetVar\n",
+    )
+    .unwrap();
+    let anonymous = acl.commands(None).unwrap();
+    assert!(anonymous.allows("RequestLocks"));
+    assert!(anonymous.allows("Hello"));
+    assert!(anonymous.allows("Authenticate"));
+    assert!(!anonymous.allows("ChangeConfig"));
+    assert!(acl.commands(Some("admin-token")).unwrap().allows("Shutdown"));
+    let bar = acl.commands(Some("bar-token")).unwrap();
+    assert!(bar.allows("SetVar"));
+    assert!(!bar.allows("Shutdown"));
+    assert!(acl.commands(Some("unknown")).is_none());
+    assert!(bar.names().contains(&"ChangeLayer".to_owned()));
+
+    let acl = Acl::parse("admin-token *").unwrap();
+    let anonymous = acl.commands(None).unwrap();
+    assert!(anonymous.allows("Explain"));
+    assert!(!anonymous.allows("SetVar"));
+
+    assert!(Acl::parse("token NoSuchMessage").is_err());
+    assert!(Acl::parse("token *\ntoken SetVar").is_err());
+
+    let msg: ClientMessage = r#"{"SetVar":{"name":"a","value":"b"}}"#.parse().unwrap();
+    assert_eq!(msg.name(), "SetVar");
+    assert_eq!(ClientMessage::Undo.name(), "Undo");
+}
+
+/// Delivers the notifications of the processing loop to clients, see
+/// [`Kanata::start_notification_loop`]. [`TcpServer`] writes them to its connected clients;
+/// [`NotificationLog`] keeps them in memory.
+pub trait NotificationSink: Send + 'static {
+    /// Deliver `event` to the clients that should receive it.
+    fn notify(&mut self, event: &ServerMessage);
+}
+
+/// Keeps the notifications in memory instead of sending them, e.g. for tests. Clones share the
+/// same notifications.
+#[derive(Clone, Default)]
+pub struct NotificationLog {
+    events: Arc<Mutex<Vec<ServerMessage>>>,
+}
+
+#[allow(dead_code)] // used by tests, kanata itself only sends to TCP clients
+impl NotificationLog {
+    /// Returns the notifications received since the last call.
+    pub fn take(&self) -> Vec<ServerMessage> {
+        std::mem::take(&mut *self.events.lock())
+    }
+}
+
+impl NotificationSink for NotificationLog {
+    fn notify(&mut self, event: &ServerMessage) {
+        // Round trip through JSON since ServerMessage is not Clone. This also checks that
+        // everything sent to clients serializes.
+        let event = serde_json::from_slice(&event.as_bytes()).expect("ServerMessage deserializes");
+        self.events.lock().push(event);
+    }
+}
+
 pub struct TcpServer {
     pub port: i32,
     pub connections: Arc<Mutex<HashMap<String, TcpStream>>>,
+    pub key_output_subscribers: Arc<Mutex<HashSet<String>>>,
+    /// The clients that filter their notifications with `ClientMessage::Subscribe`.
+    pub subscriptions: Arc<Mutex<HashMap<String, Subscription>>>,
+    /// The messages clients may send. Without it, every client may send every message.
+    pub acl: Option<Arc<Acl>>,
+    /// The tokens that clients have authenticated with.
+    pub authenticated: Arc<Mutex<HashMap<String, String>>>,
+    /// The commands that clients collected since `ClientMessage::BeginTransaction`.
+    pub transactions: Arc<Mutex<HashMap<String, Vec<KanataCommand>>>>,
 }
 
 impl TcpServer {
-    pub fn new(port: i32) -> Self {
+    pub fn new(port: i32, acl: Option<Acl>) -> Self {
         Self {
             port,
             connections: Arc::new(Mutex::new(HashMap::default())),
+            key_output_subscribers: Arc::new(Mutex::new(HashSet::default())),
+            subscriptions: Arc::new(Mutex::new(HashMap::default())),
+            acl: acl.map(Arc::new),
+            authenticated: Arc::new(Mutex::new(HashMap::default())),
+            transactions: Arc::new(Mutex::new(HashMap::default())),
         }
     }
 
-    pub fn start(&mut self, kanata: Arc<Mutex<Kanata>>) {
+    /// Start accepting clients. Commands of clients are sent to the processing loop through
+    /// `processing_tx`; the kanata lock is only taken briefly to read state.
+    pub fn start(
+        &mut self,
+        kanata: Arc<Mutex<Kanata>>,
+        processing_tx: Sender<ProcessingEvent>,
+        notify_tx: Sender<ServerMessage>,
+    ) {
         let listener =
             TcpListener::bind(format!("0.0.0.0:{}", self.port)).expect("TCP server starts");
 
         let connections = self.connections.clone();
+        let key_output_subscribers = self.key_output_subscribers.clone();
+        let subscriptions = self.subscriptions.clone();
+        let acl = self.acl.clone();
+        let authenticated = self.authenticated.clone();
+        let transactions = self.transactions.clone();
 
         std::thread::spawn(move || {
             for stream in listener.incoming() {
                 match stream {
                     Ok(mut stream) => {
-                        {
+                        log::info!(
+                            "new client connection, sending initial LayerChange event to inform them of current layer"
+                        );
+                        let (new, config_files, reload_error) = {
                             let k = kanata.lock();
-                            log::info!(
-                                "new client connection, sending initial LayerChange event to inform them of current layer"
-                            );
-                            if let Err(e) = stream.write(
-                                &ServerMessage::LayerChange {
-                                    new: k.layer_info[k.layout.b().current_layer()].name.clone(),
-                                }
-                                .as_bytes(),
-                            ) {
-                                log::warn!("failed to write to stream, dropping it: {e:?}");
-                                continue;
-                            }
+                            (
+                                k.layer_info[k.layout.b().current_layer()].name.clone(),
+                                k.config_files_message(),
+                                k.reload_error_message(),
+                            )
+                        };
+                        // Writing may block, so it is done without holding the lock.
+                        if let Err(e) = stream
+                            .write_all(&ServerMessage::LayerChange { new }.as_bytes())
+                            .and_then(|_| stream.write_all(&config_files.as_bytes()))
+                            .and_then(|_| match reload_error {
+                                Some(error) => stream.write_all(&error.as_bytes()),
+                                None => Ok(()),
+                            })
+                        {
+                            log::warn!("failed to write to stream, dropping it: {e:?}");
+                            continue;
                         }
 
-                        let addr = stream
-                            .peer_addr()
-                            .expect("incoming conn has known address")
-                            .to_string();
+                        let peer = stream.peer_addr().expect("incoming conn has known address");
+                        let addr = peer.to_string();
+                        let loopback = peer.ip().is_loopback();
 
                         connections.lock().insert(
                             addr.clone(),
@@ -95,22 +1146,441 @@ impl TcpServer {
                         log::info!("listening for incoming messages {}", &addr);
 
                         let connections = connections.clone();
+                        let key_output_subscribers = key_output_subscribers.clone();
+                        let subscriptions = subscriptions.clone();
+                        let acl = acl.clone();
+                        let authenticated = authenticated.clone();
+                        let transactions = transactions.clone();
                         let kanata = kanata.clone();
-                        std::thread::spawn(move || loop {
-                            let mut buf = vec![0; 1024];
-                            match stream.read(&mut buf) {
-                                Ok(size) => {
-                                    if let Ok(event) = ClientMessage::from_str(
-                                        &String::from_utf8_lossy(&buf[..size]),
-                                    ) {
+                        let notify_tx = notify_tx.clone();
+                        let processing_tx = processing_tx.clone();
+                        std::thread::spawn(move || {
+                            let reader = stream.try_clone().expect("stream is clonable");
+                            for message in client_messages(reader) {
+                                match message {
+                                    Ok(event) => {
+                                        let command = event.name();
+                                        let allowed = match &acl {
+                                            Some(acl) => acl
+                                                .commands(
+                                                    authenticated
+                                                        .lock()
+                                                        .get(&addr)
+                                                        .map(String::as_str),
+                                                )
+                                                .map_or(false, |c| c.allows(&command)),
+                                            None => true,
+                                        };
+                                        let allowed = allowed
+                                            && (loopback
+                                                || !types_on_host(&command)
+                                                || authenticated.lock().contains_key(&addr));
+                                        if !allowed {
+                                            log::warn!("{addr} may not send {command}, ignoring it");
+                                            let reply =
+                                                ServerMessage::PermissionDenied { command };
+                                            if let Err(e) = stream.write_all(&reply.as_bytes()) {
+                                                log::warn!(
+                                                    "could not send a permission error to {addr}: {e}"
+                                                );
+                                            }
+                                            continue;
+                                        }
                                         match event {
                                             ClientMessage::ChangeLayer { new } => {
-                                                kanata.lock().change_layer(new);
+                                                send_or_queue(
+                                                    &processing_tx,
+                                                    &transactions,
+                                                    &addr,
+                                                    KanataCommand::ChangeLayer { name: new },
+                                                );
+                                            }
+                                            ClientMessage::ActOnFakeKey { name, action } => {
+                                                send_command(
+                                                    &processing_tx,
+                                                    KanataCommand::ActOnFakeKey {
+                                                        name,
+                                                        action: action.into(),
+                                                    },
+                                                );
+                                            }
+                                            ClientMessage::SetVar { name, value } => {
+                                                log::debug!(
+                                                    "{addr} set variable {name} to {value}"
+                                                );
+                                                send_or_queue(
+                                                    &processing_tx,
+                                                    &transactions,
+                                                    &addr,
+                                                    KanataCommand::SetVar { name, value },
+                                                );
+                                            }
+                                            ClientMessage::ChangeConfig { index } => {
+                                                send_command(
+                                                    &processing_tx,
+                                                    KanataCommand::ChangeConfig { index },
+                                                );
+                                            }
+                                            ClientMessage::SetGameMode { enabled } => {
+                                                send_or_queue(
+                                                    &processing_tx,
+                                                    &transactions,
+                                                    &addr,
+                                                    KanataCommand::SetGameMode { enabled },
+                                                );
+                                            }
+                                            ClientMessage::SetLayerTag { tag, enabled } => {
+                                                send_or_queue(
+                                                    &processing_tx,
+                                                    &transactions,
+                                                    &addr,
+                                                    KanataCommand::SetLayerTags {
+                                                        changes: vec![(tag, enabled)],
+                                                    },
+                                                );
+                                            }
+                                            ClientMessage::SetLayerTags { changes } => {
+                                                send_or_queue(
+                                                    &processing_tx,
+                                                    &transactions,
+                                                    &addr,
+                                                    KanataCommand::SetLayerTags {
+                                                        changes: changes
+                                                            .into_iter()
+                                                            .map(|c| (c.tag, c.enabled))
+                                                            .collect(),
+                                                    },
+                                                );
+                                            }
+                                            ClientMessage::Undo => {
+                                                send_command(&processing_tx, KanataCommand::Undo);
+                                            }
+                                            ClientMessage::BeginTransaction => {
+                                                let mut transactions = transactions.lock();
+                                                if transactions.contains_key(&addr) {
+                                                    log::warn!(
+                                                        "{addr} already started a transaction"
+                                                    );
+                                                } else {
+                                                    transactions.insert(addr.clone(), vec![]);
+                                                }
+                                            }
+                                            ClientMessage::RollbackTransaction => {
+                                                if transactions.lock().remove(&addr).is_none() {
+                                                    log::warn!(
+                                                        "{addr} rolled back without a transaction"
+                                                    );
+                                                }
+                                            }
+                                            ClientMessage::CommitTransaction => {
+                                                let commands = transactions.lock().remove(&addr);
+                                                let result = match commands {
+                                                    Some(commands) => kanata
+                                                        .lock()
+                                                        .check_transaction(&commands)
+                                                        .map(|()| commands)
+                                                        .map_err(|e| e.to_string()),
+                                                    None => {
+                                                        Err("no transaction was started".into())
+                                                    }
+                                                };
+                                                let reply = match result {
+                                                    Ok(commands) => {
+                                                        send_command(
+                                                            &processing_tx,
+                                                            KanataCommand::Transaction { commands },
+                                                        );
+                                                        ServerMessage::TransactionResult {
+                                                            committed: true,
+                                                            error: None,
+                                                        }
+                                                    }
+                                                    Err(error) => {
+                                                        log::warn!(
+                                                            "{addr} transaction not committed: {error}"
+                                                        );
+                                                        ServerMessage::TransactionResult {
+                                                            committed: false,
+                                                            error: Some(error),
+                                                        }
+                                                    }
+                                                };
+                                                if let Err(e) = stream.write_all(&reply.as_bytes())
+                                                {
+                                                    log::warn!(
+                                                        "could not send the transaction result to {addr}: {e}"
+                                                    );
+                                                }
+                                            }
+                                            ClientMessage::ClearOutputHistory => {
+                                                send_command(
+                                                    &processing_tx,
+                                                    KanataCommand::ClearOutputHistory,
+                                                );
+                                            }
+                                            ClientMessage::ActiveWindowChanged { class, title } => {
+                                                send_command(
+                                                    &processing_tx,
+                                                    KanataCommand::ActiveWindowChanged {
+                                                        class,
+                                                        title,
+                                                    },
+                                                );
+                                            }
+                                            ClientMessage::PointerMoved { x, y } => {
+                                                send_command(
+                                                    &processing_tx,
+                                                    KanataCommand::PointerMoved { x, y },
+                                                );
+                                            }
+                                            ClientMessage::Hello => {
+                                                let reply = ServerMessage::hello().as_bytes();
+                                                if let Err(e) = stream.write_all(&reply) {
+                                                    log::warn!(
+                                                        "could not send the protocol version to {addr}: {e}"
+                                                    );
+                                                }
+                                            }
+                                            ClientMessage::Authenticate { token } => {
+                                                let reply = match &acl {
+                                                    None => ServerMessage::Authenticated {
+                                                        commands: supported_commands(),
+                                                    },
+                                                    Some(acl) => match acl.commands(Some(&token)) {
+                                                        Some(commands) => {
+                                                            log::info!("{addr} authenticated");
+                                                            let commands = commands.names();
+                                                            authenticated
+                                                                .lock()
+                                                                .insert(addr.clone(), token);
+                                                            ServerMessage::Authenticated {
+                                                                commands,
+                                                            }
+                                                        }
+                                                        None => {
+                                                            log::warn!(
+                                                                "{addr} sent an unknown token"
+                                                            );
+                                                            ServerMessage::PermissionDenied {
+                                                                command,
+                                                            }
+                                                        }
+                                                    },
+                                                };
+                                                if let Err(e) = stream.write_all(&reply.as_bytes())
+                                                {
+                                                    log::warn!(
+                                                        "could not send the authentication result to {addr}: {e}"
+                                                    );
+                                                }
+                                            }
+                                            ClientMessage::RequestLocks => {
+                                                let locks = kanata.lock().lock_report();
+                                                let reply =
+                                                    ServerMessage::Locks { locks }.as_bytes();
+                                                if let Err(e) = stream.write_all(&reply) {
+                                                    log::warn!(
+                                                        "could not send locks to {addr}: {e}"
+                                                    );
+                                                }
+                                            }
+                                            ClientMessage::RequestKeyCounts => {
+                                                let keys = kanata.lock().key_count_report();
+                                                let reply =
+                                                    ServerMessage::KeyCounts { keys }.as_bytes();
+                                                if let Err(e) = stream.write_all(&reply) {
+                                                    log::warn!(
+                                                        "could not send key counts to {addr}: {e}"
+                                                    );
+                                                }
+                                            }
+                                            ClientMessage::RequestPalette => {
+                                                let entries = kanata.lock().palette();
+                                                let reply =
+                                                    ServerMessage::Palette { entries }.as_bytes();
+                                                if let Err(e) = stream.write_all(&reply) {
+                                                    log::warn!(
+                                                        "could not send the palette to {addr}: {e}"
+                                                    );
+                                                }
+                                            }
+                                            ClientMessage::RequestStatus => {
+                                                let status = kanata.lock().status();
+                                                let reply =
+                                                    ServerMessage::Status { status }.as_bytes();
+                                                if let Err(e) = stream.write_all(&reply) {
+                                                    log::warn!(
+                                                        "could not send the status to {addr}: {e}"
+                                                    );
+                                                }
+                                            }
+                                            ClientMessage::RequestLayerDocs => {
+                                                let layers = kanata.lock().layer_docs();
+                                                let reply =
+                                                    ServerMessage::LayerDocs { layers }.as_bytes();
+                                                if let Err(e) = stream.write_all(&reply) {
+                                                    log::warn!(
+                                                        "could not send the layer docs to {addr}: {e}"
+                                                    );
+                                                }
+                                            }
+                                            ClientMessage::ExportMacros => {
+                                                let macros = kanata.lock().macro_export();
+                                                let reply =
+                                                    ServerMessage::Macros { macros }.as_bytes();
+                                                if let Err(e) = stream.write_all(&reply) {
+                                                    log::warn!(
+                                                        "could not send the macros to {addr}: {e}"
+                                                    );
+                                                }
+                                            }
+                                            ClientMessage::RequestBuildInfo => {
+                                                let info = kanata.lock().build_info();
+                                                let reply =
+                                                    ServerMessage::BuildInfo { info }.as_bytes();
+                                                if let Err(e) = stream.write_all(&reply) {
+                                                    log::warn!(
+                                                        "could not send the build info to {addr}: {e}"
+                                                    );
+                                                }
+                                            }
+                                            ClientMessage::ImportMacros { macros } => {
+                                                import_macros(&processing_tx, &addr, macros);
+                                            }
+                                            ClientMessage::ForceUnlock => {
+                                                log::info!("{addr} requested a force unlock");
+                                                send_command(
+                                                    &processing_tx,
+                                                    KanataCommand::ForceUnlock,
+                                                );
+                                            }
+                                            ClientMessage::Explain { key } => {
+                                                let text = kanata.lock().explain(&key);
+                                                let reply =
+                                                    ServerMessage::Explanation { text }.as_bytes();
+                                                if let Err(e) = stream.write_all(&reply) {
+                                                    log::warn!(
+                                                        "could not send an explanation to {addr}: {e}"
+                                                    );
+                                                }
+                                            }
+                                            ClientMessage::SetLogFilter { filter } => {
+                                                match crate::logging::set_filter(&filter) {
+                                                    Ok(()) => log::info!(
+                                                        "{addr} set the log filter to {filter:?}"
+                                                    ),
+                                                    Err(e) => log::warn!(
+                                                        "{addr} sent an invalid log filter: {e}"
+                                                    ),
+                                                }
+                                            }
+                                            ClientMessage::Shutdown => {
+                                                log::info!("{addr} requested shutdown");
+                                                send_command(
+                                                    &processing_tx,
+                                                    KanataCommand::Shutdown,
+                                                );
+                                            }
+                                            ClientMessage::LoadOverlay { path } => {
+                                                send_command(
+                                                    &processing_tx,
+                                                    KanataCommand::LoadOverlay {
+                                                        path: path.into(),
+                                                    },
+                                                );
+                                            }
+                                            ClientMessage::DropOverlay => {
+                                                send_command(
+                                                    &processing_tx,
+                                                    KanataCommand::DropOverlay,
+                                                );
+                                            }
+                                            ClientMessage::InjectKeys { keys, output } => {
+                                                inject_keys(&processing_tx, &addr, &keys, output);
+                                            }
+                                            ClientMessage::SetPassthrough { enabled } => {
+                                                log::info!("{addr} set passthrough to {enabled}");
+                                                send_command(
+                                                    &processing_tx,
+                                                    KanataCommand::SetPassthrough { enabled },
+                                                );
+                                            }
+                                            ClientMessage::ResyncModifiers => {
+                                                log::info!("{addr} requested a modifier resync");
+                                                send_command(
+                                                    &processing_tx,
+                                                    KanataCommand::ResyncModifiers,
+                                                );
+                                            }
+                                            ClientMessage::ActivateTempLayer { name, seconds } => {
+                                                send_command(
+                                                    &processing_tx,
+                                                    KanataCommand::ActivateTempLayer {
+                                                        name,
+                                                        duration: Duration::from_secs(seconds),
+                                                    },
+                                                );
+                                            }
+                                            ClientMessage::ExtendTempLayer { seconds } => {
+                                                send_command(
+                                                    &processing_tx,
+                                                    KanataCommand::ExtendTempLayer {
+                                                        by: Duration::from_secs(seconds),
+                                                    },
+                                                );
+                                            }
+                                            ClientMessage::CancelTempLayer => {
+                                                send_command(
+                                                    &processing_tx,
+                                                    KanataCommand::CancelTempLayer,
+                                                );
+                                            }
+                                            ClientMessage::InjectText { text } => {
+                                                send_command(
+                                                    &processing_tx,
+                                                    KanataCommand::TypeText { text },
+                                                );
+                                            }
+                                            ClientMessage::SubscribeKeyOutputs => {
+                                                log::info!("{addr} subscribed to key outputs");
+                                                key_output_subscribers.lock().insert(addr.clone());
+                                            }
+                                            ClientMessage::Subscribe { filters } => {
+                                                if filters.is_empty() {
+                                                    log::info!("{addr} unsubscribed");
+                                                    subscriptions.lock().remove(&addr);
+                                                    continue;
+                                                }
+                                                match Subscription::new(&filters) {
+                                                    Ok(subscription) => {
+                                                        log::info!(
+                                                            "{addr} subscribed to {filters:?}"
+                                                        );
+                                                        subscriptions
+                                                            .lock()
+                                                            .insert(addr.clone(), subscription);
+                                                    }
+                                                    Err(e) => log::warn!(
+                                                        "{addr} sent an invalid subscription: {e}"
+                                                    ),
+                                                }
+                                            }
+                                            ClientMessage::ReleaseDevice { device } => {
+                                                set_device_grabbed(
+                                                    &kanata, &notify_tx, &device, false,
+                                                );
+                                            }
+                                            ClientMessage::GrabDevice { device } => {
+                                                set_device_grabbed(
+                                                    &kanata, &notify_tx, &device, true,
+                                                );
                                             }
                                         }
-                                    } else {
+                                    }
+                                    Err(e) if e.is_io() || e.is_eof() => break,
+                                    Err(e) => {
                                         log::warn!(
-                                            "client sent an invalid message of size {size}, disconnecting them"
+                                            "client sent an invalid message, disconnecting them: {e}"
                                         );
                                         // Ignore write result because we're about to disconnect
                                         // the client anyway.
@@ -118,16 +1588,16 @@ impl TcpServer {
                                             "you sent an invalid message; disconnecting you"
                                                 .as_bytes(),
                                         );
-                                        connections.lock().remove(&addr);
                                         break;
                                     }
                                 }
-                                Err(_) => {
-                                    log::warn!("removing disconnected tcp client: {addr}");
-                                    connections.lock().remove(&addr);
-                                    break;
-                                }
                             }
+                            log::warn!("removing disconnected tcp client: {addr}");
+                            connections.lock().remove(&addr);
+                            key_output_subscribers.lock().remove(&addr);
+                            subscriptions.lock().remove(&addr);
+                            authenticated.lock().remove(&addr);
+                            transactions.lock().remove(&addr);
                         });
                     }
                     Err(_) => log::error!("not able to accept client connection"),
@@ -136,3 +1606,303 @@ impl TcpServer {
         });
     }
 }
+
+/// The messages that a client sends. Clients end each message with a newline, but messages are
+/// parsed as they arrive, so one may span several reads or share one with others, and clients
+/// without the newline are read the same way.
+fn client_messages(reader: impl Read) -> impl Iterator<Item = serde_json::Result<ClientMessage>> {
+    serde_json::Deserializer::from_reader(BufReader::new(reader)).into_iter()
+}
+
+fn send_command(processing_tx: &Sender<ProcessingEvent>, command: KanataCommand) {
+    if let Err(e) = processing_tx.send(ProcessingEvent::Command(command)) {
+        log::error!("could not send command to the processing loop: {e}");
+    }
+}
+
+/// Send the command to the processing loop, or collect it if the client started a transaction.
+fn send_or_queue(
+    processing_tx: &Sender<ProcessingEvent>,
+    transactions: &Mutex<HashMap<String, Vec<KanataCommand>>>,
+    addr: &str,
+    command: KanataCommand,
+) {
+    match transactions.lock().get_mut(addr) {
+        Some(commands) => commands.push(command),
+        None => send_command(processing_tx, command),
+    }
+}
+
+/// Send the keys of `ClientMessage::InjectKeys` to the processing loop. Events to process are sent
+/// one by one like those of the input devices, so that the layout is ticked after each of them.
+fn inject_keys(processing_tx: &Sender<ProcessingEvent>, addr: &str, keys: &str, output: bool) {
+    let events = match parse_injected_keys(keys) {
+        Ok(events) => events,
+        Err(e) => {
+            log::warn!("{addr} sent keys that cannot be injected: {e}");
+            return;
+        }
+    };
+    if output {
+        send_command(processing_tx, KanataCommand::OutputKeys { events });
+        return;
+    }
+    for event in events {
+        if let Err(e) = processing_tx.send(ProcessingEvent::Key(event)) {
+            log::error!("could not send an injected key to the processing loop: {e}");
+            return;
+        }
+    }
+}
+
+fn import_macros(processing_tx: &Sender<ProcessingEvent>, addr: &str, export: MacroExport) {
+    if export.version > MACRO_EXPORT_VERSION {
+        log::warn!(
+            "{addr} sent macros of export version {}, which this kanata cannot import",
+            export.version
+        );
+        return;
+    }
+    let mut macros = vec![];
+    for exported in export.dynamic_macros {
+        match parse_macro_keys(&exported.keys) {
+            Ok(items) => macros.push((exported.id, items)),
+            Err(e) => {
+                let id = exported.id;
+                log::warn!("{addr} sent dynamic macro {id} that cannot be imported: {e}");
+                return;
+            }
+        }
+    }
+    send_command(processing_tx, KanataCommand::ImportMacros { macros });
+}
+
+/// Delivers the notifications of `rx` to `sink` until `ServerMessage::Shutdown` is delivered.
+///
+/// Layer changes are sent at most once per `min_interval`. The changes in between are coalesced:
+/// only the latest is sent once the interval has passed, and it is not sent at all if it is the
+/// layer that was sent last. Other notifications are not delayed, so a coalesced layer change can
+/// arrive after them, except for `ServerMessage::Shutdown`.
+pub fn relay_notifications(
+    rx: &Receiver<ServerMessage>,
+    sink: &mut impl NotificationSink,
+    min_interval: Duration,
+) {
+    let mut layer_changes = LayerChanges::new(min_interval);
+    loop {
+        let received = match layer_changes.deadline() {
+            Some(deadline) => rx.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            None => rx.recv().map_err(RecvTimeoutError::from),
+        };
+        match received {
+            Err(RecvTimeoutError::Disconnected) => {
+                panic!("channel disconnected")
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                notify_layer(sink, layer_changes.flush(Instant::now()));
+            }
+            Ok(ServerMessage::LayerChange { new }) => {
+                notify_layer(sink, layer_changes.change(new, Instant::now()));
+            }
+            Ok(event) => {
+                let shutdown = matches!(event, ServerMessage::Shutdown);
+                if shutdown {
+                    notify_layer(sink, layer_changes.flush(Instant::now()));
+                }
+                sink.notify(&event);
+                if shutdown {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+fn notify_layer(sink: &mut impl NotificationSink, new: Option<String>) {
+    if let Some(new) = new {
+        sink.notify(&ServerMessage::LayerChange { new });
+    }
+}
+
+/// Coalesces layer changes that are closer together than the minimum interval, e.g. momentary
+/// layers during fast typing, see [`relay_notifications`].
+struct LayerChanges {
+    min_interval: Duration,
+    /// The layer that was sent last and when.
+    sent: Option<(String, Instant)>,
+    /// The latest layer change that is waiting for the interval to pass.
+    pending: Option<String>,
+}
+
+impl LayerChanges {
+    fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval,
+            sent: None,
+            pending: None,
+        }
+    }
+
+    /// When the pending layer change can be sent, if there is one.
+    fn deadline(&self) -> Option<Instant> {
+        self.pending.as_ref()?;
+        self.sent.as_ref().map(|(_, at)| *at + self.min_interval)
+    }
+
+    /// Returns the layer to notify now, if any.
+    fn change(&mut self, new: String, now: Instant) -> Option<String> {
+        match &self.sent {
+            Some((_, at)) if now < *at + self.min_interval => {
+                self.pending = Some(new);
+                None
+            }
+            _ => {
+                self.pending = None;
+                self.send(new, now)
+            }
+        }
+    }
+
+    /// Returns the pending layer change to notify now, if any.
+    fn flush(&mut self, now: Instant) -> Option<String> {
+        let new = self.pending.take()?;
+        self.send(new, now)
+    }
+
+    fn send(&mut self, new: String, now: Instant) -> Option<String> {
+        if matches!(&self.sent, Some((layer, _)) if *layer == new) {
+            return None;
+        }
+        self.sent = Some((new.clone(), now));
+        Some(new)
+    }
+}
+
+#[test]
+fn layer_changes_are_coalesced() {
+    let start = Instant::now();
+    let ms = |n| start + Duration::from_millis(n);
+    let mut changes = LayerChanges::new(Duration::from_millis(100));
+    assert_eq!(changes.change("nav".into(), ms(0)), Some("nav".into()));
+    assert_eq!(changes.deadline(), None);
+    // Flapping within the interval sends nothing until the interval has passed.
+    assert_eq!(changes.change("base".into(), ms(10)), None);
+    assert_eq!(changes.change("sym".into(), ms(20)), None);
+    assert_eq!(changes.deadline(), Some(ms(100)));
+    assert_eq!(changes.flush(ms(100)), Some("sym".into()));
+    assert_eq!(changes.flush(ms(100)), None);
+    // Ending up on the layer that was sent last sends nothing.
+    assert_eq!(changes.change("base".into(), ms(150)), None);
+    assert_eq!(changes.change("sym".into(), ms(160)), None);
+    assert_eq!(changes.flush(ms(200)), None);
+    // After the interval, changes are sent right away, except repeats of the last layer.
+    assert_eq!(changes.change("sym".into(), ms(300)), None);
+    assert_eq!(changes.change("nav".into(), ms(400)), Some("nav".into()));
+
+    let mut changes = LayerChanges::new(Duration::ZERO);
+    assert_eq!(changes.change("nav".into(), ms(0)), Some("nav".into()));
+    assert_eq!(changes.change("nav".into(), ms(0)), None);
+    assert_eq!(changes.change("base".into(), ms(0)), Some("base".into()));
+}
+
+#[test]
+fn notifications_are_relayed_until_shutdown() {
+    let (tx, rx) = std::sync::mpsc::channel();
+    for event in [
+        ServerMessage::LayerChange { new: "nav".into() },
+        ServerMessage::KeyOutput {
+            key: "a".into(),
+            pressed: true,
+        },
+        ServerMessage::Shutdown,
+        ServerMessage::LayerChange { new: "base".into() },
+    ] {
+        tx.send(event).unwrap();
+    }
+    let log = NotificationLog::default();
+    relay_notifications(&rx, &mut log.clone(), Duration::ZERO);
+    let events = log.take();
+    assert!(matches!(
+        events.as_slice(),
+        [
+            ServerMessage::LayerChange { new },
+            ServerMessage::KeyOutput { key, pressed: true },
+            ServerMessage::Shutdown,
+        ] if new == "nav" && key == "a"
+    ));
+    assert!(log.take().is_empty());
+    // Notifications after the shutdown are not relayed.
+    assert!(rx.try_recv().is_ok());
+}
+
+impl NotificationSink for TcpServer {
+    fn notify(&mut self, event: &ServerMessage) {
+        let notification = event.as_bytes();
+        let mut clients = self.connections.lock();
+        let mut stale_clients = vec![];
+        for (id, client) in &mut *clients {
+            let wanted = match self.subscriptions.lock().get(id) {
+                Some(subscription) => subscription.wants(event),
+                None => !event.is_key_output() || self.key_output_subscribers.lock().contains(id),
+            };
+            if !wanted {
+                continue;
+            }
+            match client.write_all(&notification) {
+                Ok(_) => {
+                    log::debug!("notification sent: {event:?}");
+                }
+                Err(_) => {
+                    // the client is no longer connected, let's remove them
+                    stale_clients.push(id.clone());
+                }
+            }
+        }
+
+        for id in &stale_clients {
+            log::warn!("removing disconnected tcp client: {id}");
+            clients.remove(id);
+        }
+    }
+}
+
+/// Release or grab a device and notify the clients of the devices whose grab changed.
+#[cfg(target_os = "linux")]
+fn set_device_grabbed(
+    kanata: &Mutex<Kanata>,
+    notify_tx: &Sender<ServerMessage>,
+    device: &str,
+    grab: bool,
+) {
+    // The event loop handles the request, and it may need the kanata lock in the meantime.
+    let control = match kanata.lock().device_grab_control.clone() {
+        Some(control) => control,
+        None => {
+            log::warn!("cannot change the grab of {device}: devices are not open yet");
+            return;
+        }
+    };
+    match control.set_grabbed(device, grab) {
+        Ok(paths) => {
+            for device in paths {
+                if let Err(e) = notify_tx.send(ServerMessage::DeviceGrabChanged {
+                    device,
+                    grabbed: grab,
+                }) {
+                    log::error!("could not send event notification: {e}");
+                }
+            }
+        }
+        Err(e) => log::warn!("cannot change the grab of {device}: {e}"),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_device_grabbed(
+    _kanata: &Mutex<Kanata>,
+    _notify_tx: &Sender<ServerMessage>,
+    device: &str,
+    _grab: bool,
+) {
+    log::warn!("cannot change the grab of {device}: only supported on Linux");
+}
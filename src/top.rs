@@ -0,0 +1,165 @@
+//! `kanata top`: a terminal dashboard for a running kanata instance.
+//!
+//! This connects to the TCP server of a running kanata process, subscribes to key output
+//! notifications, and periodically redraws the terminal with the active layer, the currently held
+//! outputs, the most recent outputs and the output event rate. It is intended for interactively
+//! debugging things like tap-hold timings.
+
+use crate::tcp_client::KanataClient;
+use crate::tcp_server::{ClientMessage, ServerMessage};
+
+use anyhow::Result;
+
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+
+const HISTORY_LEN: usize = 20;
+const LAYER_HISTORY_LEN: usize = 5;
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Connect to kanata on the given port and run the dashboard until the connection is closed.
+pub fn run(port: u16) -> Result<()> {
+    let rx = subscribe_key_outputs(port)?;
+    let mut state = TopState::default();
+    let mut stdout = std::io::stdout();
+    loop {
+        loop {
+            match rx.try_recv() {
+                Ok(msg) => state.handle_message(msg, Instant::now()),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    println!("\nconnection to kanata closed");
+                    return Ok(());
+                }
+            }
+        }
+        // Clear the screen and move the cursor to the top-left before redrawing.
+        write!(stdout, "\x1b[2J\x1b[H{}", state.render(Instant::now()))?;
+        stdout.flush()?;
+        std::thread::sleep(REDRAW_INTERVAL);
+    }
+}
+
+/// Connect to kanata on the given port and subscribe to key outputs. The messages from kanata are
+/// received on the returned channel, which disconnects when the connection is closed.
+pub fn subscribe_key_outputs(port: u16) -> Result<Receiver<ServerMessage>> {
+    let mut client = KanataClient::connect(port)?;
+    client.send(&ClientMessage::SubscribeKeyOutputs)?;
+    client.into_messages()
+}
+
+#[derive(Default)]
+struct TopState {
+    layer: String,
+    layer_history: VecDeque<String>,
+    held: Vec<String>,
+    history: VecDeque<(Instant, String, bool)>,
+    event_times: VecDeque<Instant>,
+}
+
+impl TopState {
+    fn handle_message(&mut self, msg: ServerMessage, now: Instant) {
+        match msg {
+            ServerMessage::LayerChange { new } => {
+                if !self.layer.is_empty() {
+                    self.layer_history
+                        .push_front(std::mem::take(&mut self.layer));
+                    self.layer_history.truncate(LAYER_HISTORY_LEN);
+                }
+                self.layer = new;
+            }
+            ServerMessage::KeyOutput { key, pressed } => {
+                if pressed {
+                    if !self.held.contains(&key) {
+                        self.held.push(key.clone());
+                    }
+                } else {
+                    self.held.retain(|k| k != &key);
+                }
+                self.history.push_front((now, key, pressed));
+                self.history.truncate(HISTORY_LEN);
+                self.event_times.push_back(now);
+            }
+            ServerMessage::Authenticated { .. } | ServerMessage::PermissionDenied { .. } => {}
+            ServerMessage::DeviceGrabChanged { .. }
+            | ServerMessage::LayerNotify { .. }
+            | ServerMessage::Shutdown
+            | ServerMessage::ConfigFiles { .. }
+            | ServerMessage::LiveReloadFailed { .. }
+            | ServerMessage::Launcher { .. }
+            | ServerMessage::Locks { .. }
+            | ServerMessage::KeyCounts { .. }
+            | ServerMessage::Palette { .. }
+            | ServerMessage::Status { .. }
+            | ServerMessage::LayerDocs { .. }
+            | ServerMessage::Macros { .. }
+            | ServerMessage::BuildInfo { .. }
+            | ServerMessage::TransactionResult { .. }
+            | ServerMessage::Explanation { .. }
+            | ServerMessage::Hello { .. } => {}
+        }
+    }
+
+    fn render(&mut self, now: Instant) -> String {
+        while matches!(self.event_times.front(), Some(t) if now.duration_since(*t) > RATE_WINDOW) {
+            self.event_times.pop_front();
+        }
+        let mut out = String::new();
+        out.push_str("kanata top (Ctrl+C to quit)\n\n");
+        out.push_str(&format!("layer      : {}\n", self.layer));
+        if !self.layer_history.is_empty() {
+            let prev: Vec<_> = self.layer_history.iter().map(String::as_str).collect();
+            out.push_str(&format!("previous   : {}\n", prev.join(" <- ")));
+        }
+        out.push_str(&format!("held       : {}\n", self.held.join(" ")));
+        out.push_str(&format!("events/sec : {}\n\n", self.event_times.len()));
+        out.push_str("recent outputs:\n");
+        for (t, key, pressed) in &self.history {
+            let ms_ago = now.duration_since(*t).as_millis();
+            let action = if *pressed { "press  " } else { "release" };
+            out.push_str(&format!("  {ms_ago:>6}ms ago  {action} {key}\n"));
+        }
+        out
+    }
+}
+
+#[test]
+fn top_state_tracks_layers_and_outputs() {
+    let now = Instant::now();
+    let mut state = TopState::default();
+    state.handle_message(ServerMessage::LayerChange { new: "base".into() }, now);
+    state.handle_message(ServerMessage::LayerChange { new: "nav".into() }, now);
+    state.handle_message(
+        ServerMessage::KeyOutput {
+            key: "A".into(),
+            pressed: true,
+        },
+        now,
+    );
+    state.handle_message(
+        ServerMessage::KeyOutput {
+            key: "B".into(),
+            pressed: true,
+        },
+        now,
+    );
+    state.handle_message(
+        ServerMessage::KeyOutput {
+            key: "A".into(),
+            pressed: false,
+        },
+        now,
+    );
+    assert_eq!(state.layer, "nav");
+    assert_eq!(state.layer_history, ["base"]);
+    assert_eq!(state.held, ["B"]);
+    let rendered = state.render(now);
+    assert!(rendered.contains("events/sec : 3"));
+    assert!(rendered.contains("release A"));
+    assert!(state
+        .render(now + RATE_WINDOW * 2)
+        .contains("events/sec : 0"));
+}
@@ -0,0 +1,241 @@
+//! `kanata train`: typing drills for recently remapped keys.
+//!
+//! The configuration is compared with a previous version of it to find the keys whose output
+//! changed. The drills ask for the new outputs of these keys in random order and check the
+//! answers against the key outputs of a running kanata instance, so they exercise the actual
+//! layout including layer switching. Only keys that output a single key are drilled.
+
+use crate::cfg::{self, Cfg};
+use crate::keys::OsCode;
+use crate::tcp_server::ServerMessage;
+use crate::top::subscribe_key_outputs;
+
+use anyhow::{anyhow, bail, Result};
+use kanata_keyberon::key_code::KeyCode;
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// A key whose output changed and the layer on which it changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Drill {
+    layer: String,
+    key: OsCode,
+    output: KeyCode,
+}
+
+/// Compare the configurations, then run `rounds` drills for every changed key against kanata on
+/// the given port.
+pub fn run(cfg_path: &Path, previous_path: &Path, port: u16, rounds: usize) -> Result<()> {
+    let cfg = cfg::new_from_file(cfg_path).map_err(|e| anyhow!("{e:?}"))?;
+    let previous = cfg::new_from_file(previous_path).map_err(|e| anyhow!("{e:?}"))?;
+    let drills = changed_outputs(&cfg, &previous);
+    if drills.is_empty() {
+        bail!("no key outputs changed, there is nothing to practice");
+    }
+    let rx = subscribe_key_outputs(port)?;
+
+    let mut queue = (0..rounds).flat_map(|_| drills.iter()).collect::<Vec<_>>();
+    shuffle(&mut queue);
+    println!(
+        "{} changed key(s), {} drill(s). Type the requested key, Ctrl+C to quit.\n",
+        drills.len(),
+        queue.len()
+    );
+    let mut results = TrainingResults::default();
+    for (i, drill) in queue.iter().enumerate() {
+        println!(
+            "[{}/{}] layer {}: type {:?}",
+            i + 1,
+            queue.len(),
+            drill.layer,
+            drill.output
+        );
+        let start = Instant::now();
+        let typed = loop {
+            match rx.recv() {
+                Ok(ServerMessage::KeyOutput { key, pressed: true })
+                    if !is_modifier(&key) || is_modifier(&format!("{:?}", drill.output)) =>
+                {
+                    break key
+                }
+                Ok(_) => {}
+                Err(_) => bail!("connection to kanata closed"),
+            }
+        };
+        let correct = typed == format!("{:?}", drill.output);
+        results.record(drill, correct, start.elapsed());
+        if correct {
+            println!("  correct");
+        } else {
+            println!(
+                "  got {typed}, {:?} is on the {} key",
+                drill.output,
+                key_name(drill.key)
+            );
+        }
+    }
+    println!("\n{}", results.summary());
+    Ok(())
+}
+
+/// Returns the keys that output a single key that differs from their output in the previous
+/// configuration, on the layers with the same name or the base layer for new layers.
+fn changed_outputs(cfg: &Cfg, previous: &Cfg) -> Vec<Drill> {
+    let mut drills = vec![];
+    // Each layer is duplicated in the keyberon layout, the first copy is used.
+    for layer_idx in (0..cfg.layer_info.len()).step_by(2) {
+        let layer = &cfg.layer_info[layer_idx].name;
+        // Keys on new layers are compared with what they did on the previous base layer.
+        let previous_outputs = &previous.key_outputs[previous
+            .layer_info
+            .iter()
+            .position(|l| &l.name == layer)
+            .unwrap_or(0)];
+        for (key, outputs) in cfg.key_outputs[layer_idx].iter() {
+            // There is nothing to learn about keys that output themselves.
+            let output = match outputs {
+                [output] if *output != key => *output,
+                _ => continue,
+            };
+            if previous_outputs.get(&key).map(Vec::as_slice) != Some(outputs) {
+                drills.push(Drill {
+                    layer: layer.clone(),
+                    key,
+                    output: output.into(),
+                });
+            }
+        }
+    }
+    drills
+}
+
+#[derive(Default)]
+struct TrainingResults {
+    attempts: usize,
+    correct: usize,
+    total_time: Duration,
+    /// The drills that were answered wrong and how often.
+    mistakes: Vec<(Drill, usize)>,
+}
+
+impl TrainingResults {
+    fn record(&mut self, drill: &Drill, correct: bool, time: Duration) {
+        self.attempts += 1;
+        self.total_time += time;
+        if correct {
+            self.correct += 1;
+            return;
+        }
+        match self.mistakes.iter_mut().find(|(d, _)| d == drill) {
+            Some((_, count)) => *count += 1,
+            None => self.mistakes.push((drill.clone(), 1)),
+        }
+    }
+
+    fn summary(&self) -> String {
+        let mut out = format!(
+            "accuracy: {}/{} ({}%), average time: {}ms\n",
+            self.correct,
+            self.attempts,
+            self.correct * 100 / self.attempts.max(1),
+            self.total_time.as_millis() / self.attempts.max(1) as u128,
+        );
+        let mut mistakes = self.mistakes.iter().collect::<Vec<_>>();
+        mistakes.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        for (drill, count) in mistakes {
+            out.push_str(&format!(
+                "  {count} mistake(s): {:?} on layer {} ({} key)\n",
+                drill.output,
+                drill.layer,
+                key_name(drill.key)
+            ));
+        }
+        out
+    }
+}
+
+fn key_name(key: OsCode) -> String {
+    format!("{key:?}").trim_start_matches("KEY_").to_lowercase()
+}
+
+fn is_modifier(key: &str) -> bool {
+    matches!(
+        key,
+        "LShift" | "RShift" | "LCtrl" | "RCtrl" | "LAlt" | "RAlt" | "LGui" | "RGui"
+    )
+}
+
+/// Shuffle in place with a xorshift generator seeded from the clock; the drill order does not
+/// need good randomness.
+fn shuffle<T>(items: &mut [T]) {
+    let mut state = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+        | 1;
+    for i in (1..items.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        items.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+}
+
+#[test]
+fn train_finds_changed_outputs() {
+    let parse = |name: &str, text: &str| {
+        let path =
+            std::env::temp_dir().join(format!("kanata-train-{name}-{}.kbd", std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        let cfg = cfg::new_from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        cfg.unwrap()
+    };
+    let previous = parse(
+        "previous",
+        "
+(defsrc a s d f)
+(deflayer base a s d (layer-while-held nav))
+(deflayer nav left down up _)
+",
+    );
+    let cfg = parse(
+        "new",
+        "
+(defsrc a s d f)
+(deflayer base a s C-d (layer-while-held nav))
+(deflayer nav left up down _)
+(deflayer num 1 2 3 _)
+",
+    );
+    let drills = changed_outputs(&cfg, &previous);
+    let drill = |layer: &str, key, output| Drill {
+        layer: layer.into(),
+        key,
+        output,
+    };
+    assert_eq!(
+        drills,
+        [
+            drill("nav", OsCode::KEY_S, KeyCode::Up),
+            drill("nav", OsCode::KEY_D, KeyCode::Down),
+            drill("num", OsCode::KEY_A, KeyCode::Kb1),
+            drill("num", OsCode::KEY_S, KeyCode::Kb2),
+            drill("num", OsCode::KEY_D, KeyCode::Kb3),
+        ]
+    );
+
+    let mut results = TrainingResults::default();
+    results.record(&drills[0], true, Duration::from_millis(300));
+    results.record(&drills[1], false, Duration::from_millis(500));
+    results.record(&drills[1], false, Duration::from_millis(700));
+    let summary = results.summary();
+    assert!(summary.contains("accuracy: 1/3 (33%), average time: 500ms"));
+    assert!(summary.contains("2 mistake(s): Down on layer nav (d key)"));
+
+    let mut items = (0..10).collect::<Vec<_>>();
+    shuffle(&mut items);
+    items.sort();
+    assert_eq!(items, (0..10).collect::<Vec<_>>());
+}
@@ -0,0 +1,237 @@
+//! End-to-end tests of a real kanata process, without a physical keyboard.
+//!
+//! Each test creates a virtual keyboard with uinput and starts kanata with a configuration that
+//! only grabs that keyboard. The test types on the virtual keyboard and reads what kanata writes
+//! to its output device. The test grabs the output device, so nothing reaches the desktop.
+//!
+//! The tests need write access to `/dev/uinput` and read access to `/dev/input`, usually root, so
+//! they are ignored by default. Run them with:
+//!
+//!     cargo build && sudo -E cargo test --test uinput_loopback -- --ignored --test-threads 1
+#![cfg(target_os = "linux")]
+
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+use evdev::{AttributeSet, Device, EventType, InputEvent, Key};
+use std::fs::File;
+use std::path::PathBuf;
+use std::process::{Child, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// How long kanata may take to create its output device and grab the test keyboard. Kanata waits
+/// 2s before it grabs keyboards.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long to wait for an expected output event.
+const OUTPUT_TIMEOUT: Duration = Duration::from_secs(2);
+/// Configurations refer to the test keyboard with `linux-dev ${KANATA_LOOPBACK_SOURCE}`.
+const SOURCE_ENV_VAR: &str = "KANATA_LOOPBACK_SOURCE";
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A kanata process between a virtual input keyboard and the output device it creates.
+struct Loopback {
+    kanata: Child,
+    source: VirtualDevice,
+    output: Receiver<(Key, i32)>,
+    dir: PathBuf,
+}
+
+impl Loopback {
+    /// Starts kanata with `cfg` and waits until it has grabbed the test keyboard.
+    fn start(cfg: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "kanata-loopback-{}-{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::SeqCst)
+        ));
+        std::fs::create_dir_all(&dir).expect("create the test directory");
+        let mut source = VirtualDeviceBuilder::new()
+            .expect("open /dev/uinput")
+            .name("kanata loopback source")
+            .with_keys(&AttributeSet::from_iter((1..=248).map(Key)))
+            .expect("declare keys")
+            .build()
+            .expect("create the test keyboard");
+        let source_path = source
+            .enumerate_dev_nodes_blocking()
+            .expect("list the device nodes")
+            .next()
+            .expect("the test keyboard has a device node")
+            .expect("read the device node");
+
+        let cfg_path = dir.join("test.kbd");
+        std::fs::write(&cfg_path, cfg).expect("write the configuration");
+        let log_path = dir.join("kanata.log");
+        let log = File::create(&log_path).expect("create the log file");
+        let output_path = dir.join("output");
+        let kanata = Command::new(env!("CARGO_BIN_EXE_kanata"))
+            .arg("--cfg")
+            .arg(&cfg_path)
+            .arg("--symlink-path")
+            .arg(&output_path)
+            .env(SOURCE_ENV_VAR, &source_path)
+            .stdout(log.try_clone().expect("clone the log file"))
+            .stderr(log)
+            .spawn()
+            .expect("start kanata");
+        let mut loopback = Self {
+            kanata,
+            source,
+            // Replaced once the output device exists.
+            output: std::sync::mpsc::channel().1,
+            dir,
+        };
+
+        loopback.wait_until("kanata creates its output device", || output_path.exists());
+        let mut output = Device::open(&output_path).expect("open the output device");
+        output.grab().expect("grab the output device");
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || loop {
+            let Ok(events) = output.fetch_events() else {
+                return;
+            };
+            for event in events {
+                // Autorepeat of the output device depends on timing, so it is left out.
+                if event.event_type() != EventType::KEY || event.value() == 2 {
+                    continue;
+                }
+                if tx.send((Key(event.code()), event.value())).is_err() {
+                    return;
+                }
+            }
+        });
+        loopback.output = rx;
+
+        // Kanata grabs the keyboard right after logging this, since no key is pressed.
+        let registered = format!("registering {}", source_path.display());
+        loopback.wait_until("kanata grabs the test keyboard", || {
+            std::fs::read_to_string(&log_path).map_or(false, |log| log.contains(&registered))
+        });
+        std::thread::sleep(Duration::from_millis(500));
+        loopback
+    }
+
+    fn wait_until(&mut self, what: &str, mut done: impl FnMut() -> bool) {
+        let start = Instant::now();
+        while !done() {
+            if let Ok(Some(status)) = self.kanata.try_wait() {
+                panic!("kanata exited with {status} before {what}\n{}", self.log());
+            }
+            if start.elapsed() > STARTUP_TIMEOUT {
+                panic!("timed out waiting until {what}\n{}", self.log());
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    fn log(&self) -> String {
+        std::fs::read_to_string(self.dir.join("kanata.log")).unwrap_or_default()
+    }
+
+    fn press(&mut self, key: Key) {
+        self.emit(key, 1);
+    }
+
+    fn release(&mut self, key: Key) {
+        self.emit(key, 0);
+    }
+
+    fn tap(&mut self, key: Key) {
+        self.press(key);
+        std::thread::sleep(Duration::from_millis(20));
+        self.release(key);
+    }
+
+    fn emit(&mut self, key: Key, value: i32) {
+        // The virtual device adds the SYN_REPORT.
+        self.source
+            .emit(&[InputEvent::new(EventType::KEY, key.code(), value)])
+            .expect("write to the test keyboard");
+    }
+
+    /// Asserts that the next key events of the output device are `expected`.
+    fn expect(&self, expected: &[(Key, i32)]) {
+        let mut received = vec![];
+        let deadline = Instant::now() + OUTPUT_TIMEOUT;
+        while received.len() < expected.len() {
+            match self
+                .output
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            {
+                Ok(event) => received.push(event),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    panic!("the output device went away\n{}", self.log())
+                }
+            }
+        }
+        assert_eq!(received, expected, "\n{}", self.log());
+    }
+
+    /// Asserts that the output device writes no key event for a while.
+    fn expect_nothing(&self, wait: Duration) {
+        if let Ok(event) = self.output.recv_timeout(wait) {
+            panic!("unexpected output {event:?}\n{}", self.log());
+        }
+    }
+}
+
+impl Drop for Loopback {
+    fn drop(&mut self) {
+        let _ = self.kanata.kill();
+        let _ = self.kanata.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+#[ignore = "needs access to /dev/uinput and /dev/input"]
+fn remaps_keys_and_passes_through_unmapped_keys() {
+    let mut k = Loopback::start(
+        "(defcfg linux-dev ${KANATA_LOOPBACK_SOURCE})
+         (defsrc a b)
+         (deflayer base b a)",
+    );
+    k.tap(Key::KEY_A);
+    k.expect(&[(Key::KEY_B, 1), (Key::KEY_B, 0)]);
+    k.tap(Key::KEY_B);
+    k.expect(&[(Key::KEY_A, 1), (Key::KEY_A, 0)]);
+    k.tap(Key::KEY_C);
+    k.expect(&[(Key::KEY_C, 1), (Key::KEY_C, 0)]);
+    k.expect_nothing(Duration::from_millis(200));
+}
+
+#[test]
+#[ignore = "needs access to /dev/uinput and /dev/input"]
+fn tap_hold_uses_real_time() {
+    let mut k = Loopback::start(
+        "(defcfg linux-dev ${KANATA_LOOPBACK_SOURCE})
+         (defsrc a)
+         (deflayer base (tap-hold 150 150 a lctl))",
+    );
+    k.tap(Key::KEY_A);
+    k.expect(&[(Key::KEY_A, 1), (Key::KEY_A, 0)]);
+    k.press(Key::KEY_A);
+    std::thread::sleep(Duration::from_millis(400));
+    k.expect(&[(Key::KEY_LEFTCTRL, 1)]);
+    k.release(Key::KEY_A);
+    k.expect(&[(Key::KEY_LEFTCTRL, 0)]);
+}
+
+#[test]
+#[ignore = "needs access to /dev/uinput and /dev/input"]
+fn layer_while_held() {
+    let mut k = Loopback::start(
+        "(defcfg linux-dev ${KANATA_LOOPBACK_SOURCE})
+         (defsrc a b)
+         (deflayer base (layer-while-held nav) b)
+         (deflayer nav _ left)",
+    );
+    k.press(Key::KEY_A);
+    k.tap(Key::KEY_B);
+    k.expect(&[(Key::KEY_LEFT, 1), (Key::KEY_LEFT, 0)]);
+    k.release(Key::KEY_A);
+    k.tap(Key::KEY_B);
+    k.expect(&[(Key::KEY_B, 1), (Key::KEY_B, 0)]);
+}
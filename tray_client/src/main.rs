@@ -0,0 +1,211 @@
+//! System tray icon for kanata.
+//!
+//! Connects to the TCP server of a running kanata process, shows the active layer in the tray
+//! and offers a menu to switch between the configuration files kanata was started with. See the
+//! README for the messages that are used.
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use simplelog::*;
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Port that kanata's TCP server is listening on
+    #[arg(short, long)]
+    port: u16,
+
+    /// Enable debug logging
+    #[arg(short, long)]
+    debug: bool,
+}
+
+/// The messages of kanata that the tray uses. Other messages are ignored.
+#[derive(Debug, Deserialize)]
+enum ServerMessage {
+    LayerChange { new: String },
+    ConfigFiles { paths: Vec<String>, active: usize },
+    Shutdown,
+}
+
+#[derive(Debug, Serialize)]
+enum ClientMessage {
+    ChangeConfig { index: usize },
+}
+
+struct KanataTray {
+    layer: String,
+    configs: Vec<String>,
+    active_config: usize,
+    kanata: TcpStream,
+}
+
+impl KanataTray {
+    fn send(&mut self, msg: &ClientMessage) {
+        let msg = serde_json::to_string(msg).expect("ClientMessage serializes");
+        if let Err(e) = self.kanata.write_all(format!("{msg}\n").as_bytes()) {
+            log::error!("could not send {msg} to kanata: {e}");
+        }
+    }
+}
+
+impl ksni::Tray for KanataTray {
+    fn id(&self) -> String {
+        "kanata".into()
+    }
+
+    fn title(&self) -> String {
+        format!("kanata: {}", self.layer)
+    }
+
+    fn icon_name(&self) -> String {
+        "input-keyboard".into()
+    }
+
+    fn tool_tip(&self) -> ksni::ToolTip {
+        ksni::ToolTip {
+            title: self.title(),
+            ..Default::default()
+        }
+    }
+
+    fn menu(&self) -> Vec<ksni::MenuItem<Self>> {
+        use ksni::menu::*;
+        let mut menu = vec![
+            StandardItem {
+                label: format!("Layer: {}", self.layer),
+                enabled: false,
+                ..Default::default()
+            }
+            .into(),
+            MenuItem::Separator,
+        ];
+        if self.configs.len() > 1 {
+            menu.push(
+                RadioGroup {
+                    selected: self.active_config,
+                    select: Box::new(|tray: &mut Self, index| {
+                        log::info!("switching to {}", tray.configs[index]);
+                        tray.send(&ClientMessage::ChangeConfig { index });
+                    }),
+                    options: self
+                        .configs
+                        .iter()
+                        .map(|path| RadioItem {
+                            label: config_label(path),
+                            ..Default::default()
+                        })
+                        .collect(),
+                }
+                .into(),
+            );
+            menu.push(MenuItem::Separator);
+        }
+        menu.push(
+            StandardItem {
+                label: "Close tray icon".into(),
+                icon_name: "window-close".into(),
+                activate: Box::new(|_| std::process::exit(0)),
+                ..Default::default()
+            }
+            .into(),
+        );
+        menu
+    }
+}
+
+/// The file name of a configuration, which is shorter than its path.
+fn config_label(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_owned())
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    init_logger(&args);
+    let kanata = TcpStream::connect_timeout(
+        &SocketAddr::from(([127, 0, 0, 1], args.port)),
+        Duration::from_secs(5),
+    )
+    .map_err(|e| anyhow!("could not connect to kanata on port {}: {e}", args.port))?;
+    let reader = kanata.try_clone()?;
+    let service = ksni::TrayService::new(KanataTray {
+        layer: String::new(),
+        configs: vec![],
+        active_config: 0,
+        kanata,
+    });
+    let handle = service.handle();
+    service.spawn();
+
+    read_messages(reader, |msg| match msg {
+        ServerMessage::LayerChange { new } => handle.update(|tray| tray.layer = new),
+        ServerMessage::ConfigFiles { paths, active } => handle.update(|tray| {
+            tray.configs = paths;
+            tray.active_config = active;
+        }),
+        ServerMessage::Shutdown => {
+            log::info!("kanata is exiting");
+            std::process::exit(0);
+        }
+    })?;
+    log::info!("kanata closed the connection");
+    Ok(())
+}
+
+/// Call `on_message` for every message from kanata until the connection is closed.
+fn read_messages(mut stream: TcpStream, mut on_message: impl FnMut(ServerMessage)) -> Result<()> {
+    let mut pending = vec![];
+    let mut buf = vec![0; 1024];
+    loop {
+        match stream.read(&mut buf)? {
+            0 => return Ok(()),
+            size => pending.extend_from_slice(&buf[..size]),
+        }
+        // Messages are written back-to-back without a delimiter and a read may end in the middle
+        // of a message. Handle all complete messages and keep the remainder.
+        let mut values =
+            serde_json::Deserializer::from_slice(&pending).into_iter::<serde_json::Value>();
+        let mut consumed = 0;
+        loop {
+            match values.next() {
+                Some(Ok(value)) => {
+                    consumed = values.byte_offset();
+                    match serde_json::from_value::<ServerMessage>(value) {
+                        Ok(msg) => on_message(msg),
+                        Err(e) => log::debug!("ignoring message: {e}"),
+                    }
+                }
+                Some(Err(e)) if !e.is_eof() => return Err(anyhow!("invalid message: {e}")),
+                _ => break,
+            }
+        }
+        pending.drain(..consumed);
+    }
+}
+
+fn init_logger(args: &Args) {
+    let log_lvl = match args.debug {
+        true => LevelFilter::Debug,
+        false => LevelFilter::Info,
+    };
+    let mut log_cfg = ConfigBuilder::new();
+    if let Err(e) = log_cfg.set_time_offset_to_local() {
+        eprintln!("WARNING: could not set log TZ to local: {e:?}");
+    };
+    CombinedLogger::init(vec![TermLogger::new(
+        log_lvl,
+        log_cfg.build(),
+        TerminalMode::Mixed,
+        ColorChoice::Auto,
+    )])
+    .expect("init logger");
+}